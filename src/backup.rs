@@ -0,0 +1,594 @@
+use {
+    crate::passphrase_key::{derive_cipher, NONCE_LEN, PBKDF2_ITERATIONS, SALT_LEN},
+    aes_gcm_siv::aead::{generic_array::GenericArray, Aead},
+    chrono::prelude::*,
+    flate2::{read::GzDecoder, write::GzEncoder, Compression},
+    hmac::{Hmac, Mac},
+    rand::RngCore,
+    reqwest::{Client, Method},
+    sha2::{Digest, Sha256},
+    std::{
+        env, fmt, fs,
+        io::{self, Read},
+        path::{Path, PathBuf},
+        str::FromStr,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    sys::http_client::{self, TracedSend},
+};
+
+const BACKUP_MAGIC: &[u8] = b"sys-backup-v1\0";
+const BACKUP_SUFFIX: &str = ".json.enc";
+const SNAPSHOTS_DIRNAME: &str = "snapshots";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Provider {
+    S3,
+    Gcs,
+}
+
+// A cloud storage location of the form `s3://bucket/path` or `gs://bucket/path`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BackupLocation {
+    provider: Provider,
+    bucket: String,
+    path: String,
+}
+
+impl FromStr for BackupLocation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (provider, rest) = if let Some(rest) = s.strip_prefix("s3://") {
+            (Provider::S3, rest)
+        } else if let Some(rest) = s.strip_prefix("gs://") {
+            (Provider::Gcs, rest)
+        } else {
+            return Err(format!("Unsupported backup location: {s}"));
+        };
+
+        let (bucket, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("Backup location is missing a bucket: {s}"));
+        }
+        Ok(Self {
+            provider,
+            bucket: bucket.into(),
+            path: path.trim_matches('/').into(),
+        })
+    }
+}
+
+impl fmt::Display for BackupLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.url(&self.path))
+    }
+}
+
+impl BackupLocation {
+    fn url(&self, key: &str) -> String {
+        let scheme = match self.provider {
+            Provider::S3 => "s3",
+            Provider::Gcs => "gs",
+        };
+        format!("{scheme}://{}/{key}", self.bucket)
+    }
+
+    fn is_backup(&self) -> bool {
+        self.path.ends_with(BACKUP_SUFFIX)
+    }
+
+    // Backup keys sort in the order the backups were taken
+    fn backup_prefix(&self) -> String {
+        if self.path.is_empty() {
+            "sys-".into()
+        } else {
+            format!("{}/sys-", self.path)
+        }
+    }
+
+    fn backup_key(&self, when: DateTime<Utc>) -> String {
+        format!(
+            "{}{}{BACKUP_SUFFIX}",
+            self.backup_prefix(),
+            when.format("%Y%m%dT%H%M%SZ")
+        )
+    }
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    region: String,
+}
+
+impl Credentials {
+    // S3 uses the standard AWS environment variables. GCS is accessed through its S3-compatible
+    // XML API, which requires an HMAC key for a service account
+    fn from_env(provider: Provider) -> Result<Self, String> {
+        let var = |name: &str| env::var(name).map_err(|_| format!("{name} is not set"));
+        Ok(match provider {
+            Provider::S3 => Self {
+                access_key_id: var("AWS_ACCESS_KEY_ID")?,
+                secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+                region: var("AWS_REGION").unwrap_or_else(|_| "us-east-1".into()),
+            },
+            Provider::Gcs => Self {
+                access_key_id: var("GCS_HMAC_ACCESS_KEY_ID")?,
+                secret_access_key: var("GCS_HMAC_SECRET")?,
+                region: "auto".into(),
+            },
+        })
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn hmac_sha256(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+fn xml_values<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    let mut values = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        match rest.find(&close) {
+            Some(end) => {
+                values.push(&rest[..end]);
+                rest = &rest[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+    values
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+struct Bucket {
+    client: Client,
+    provider: Provider,
+    name: String,
+    credentials: Credentials,
+}
+
+impl Bucket {
+    fn new(location: &BackupLocation) -> Result<Self, String> {
+        Ok(Self {
+            client: http_client::client(),
+            provider: location.provider,
+            name: location.bucket.clone(),
+            credentials: Credentials::from_env(location.provider)?,
+        })
+    }
+
+    fn host_and_path(&self, key: &str) -> (String, String) {
+        match self.provider {
+            Provider::S3 => (
+                format!("{}.s3.{}.amazonaws.com", self.name, self.credentials.region),
+                format!("/{}", uri_encode(key, false)),
+            ),
+            Provider::Gcs if key.is_empty() => (
+                "storage.googleapis.com".into(),
+                format!("/{}", uri_encode(&self.name, true)),
+            ),
+            Provider::Gcs => (
+                "storage.googleapis.com".into(),
+                format!(
+                    "/{}/{}",
+                    uri_encode(&self.name, true),
+                    uri_encode(key, false)
+                ),
+            ),
+        }
+    }
+
+    // Sends a request signed with AWS Signature Version 4
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        let (host, path) = self.host_and_path(key);
+
+        let mut query = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let query = query
+            .into_iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = sha256_hex(&body);
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\n\
+             x-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.credentials.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let signing_key = [self.credentials.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(
+                hmac_sha256(
+                    format!("AWS4{}", self.credentials.secret_access_key).as_bytes(),
+                    &date,
+                ),
+                |key, message| hmac_sha256(&key, message),
+            );
+        let signature = hex::encode(hmac_sha256(&signing_key, &string_to_sign));
+
+        let url = if query.is_empty() {
+            format!("https://{host}{path}")
+        } else {
+            format!("https://{host}{path}?{query}")
+        };
+        let response = self
+            .client
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+                     Signature={signature}",
+                    self.credentials.access_key_id
+                ),
+            )
+            .body(body)
+            .traced_send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "{} request for {key} failed with {}: {}",
+                self.name,
+                response.status(),
+                response.text().await.unwrap_or_default()
+            )
+            .into());
+        }
+        Ok(response)
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+        self.send(Method::PUT, key, &[], body).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(self
+            .send(Method::GET, key, &[], vec![])
+            .await?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.send(Method::DELETE, key, &[], vec![]).await?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut keys = vec![];
+        let mut continuation_token = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(continuation_token) = &continuation_token {
+                query.push(("continuation-token", continuation_token));
+            }
+            let xml = self
+                .send(Method::GET, "", &query, vec![])
+                .await?
+                .text()
+                .await?;
+
+            keys.extend(xml_values(&xml, "Key").into_iter().map(xml_unescape));
+            continuation_token = xml_values(&xml, "NextContinuationToken")
+                .first()
+                .map(|token| xml_unescape(token));
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+        Ok(keys)
+    }
+}
+
+fn passphrase() -> Result<String, String> {
+    env::var("SYS_BACKUP_PASSPHRASE")
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+        .ok_or_else(|| "SYS_BACKUP_PASSPHRASE is not set".into())
+}
+
+// Encrypted backups are laid out as: magic | salt | nonce | AES-256-GCM-SIV ciphertext
+fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = derive_cipher(passphrase, &salt, PBKDF2_ITERATIONS)
+        .encrypt(GenericArray::from_slice(&nonce), plaintext)
+        .map_err(|err| format!("Encryption failed: {err}"))?;
+
+    Ok([BACKUP_MAGIC, &salt, &nonce, &ciphertext].concat())
+}
+
+fn decrypt(passphrase: &str, backup: &[u8]) -> Result<Vec<u8>, String> {
+    let backup = backup
+        .strip_prefix(BACKUP_MAGIC)
+        .ok_or("Not a sys backup")?;
+    if backup.len() < SALT_LEN + NONCE_LEN {
+        return Err("Backup is truncated".into());
+    }
+    let (salt, backup) = backup.split_at(SALT_LEN);
+    let (nonce, ciphertext) = backup.split_at(NONCE_LEN);
+
+    derive_cipher(passphrase, salt, PBKDF2_ITERATIONS)
+        .decrypt(GenericArray::from_slice(nonce), ciphertext)
+        .map_err(|_| "Decryption failed. Incorrect passphrase or corrupt backup".into())
+}
+
+// Encrypts `data` with the SYS_BACKUP_PASSPHRASE and uploads it under `location`. When `keep` is
+// provided, all but the most recent `keep` backups at `location` are then deleted
+pub async fn backup(
+    location: &BackupLocation,
+    data: &[u8],
+    keep: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if location.is_backup() {
+        return Err(format!("Backup location must be a directory: {location}").into());
+    }
+    let bucket = Bucket::new(location)?;
+
+    let key = location.backup_key(Utc::now());
+    bucket.put(&key, encrypt(&passphrase()?, data)?).await?;
+    println!("Backed up to {}", location.url(&key));
+
+    if let Some(keep) = keep {
+        let mut backups = backups(&bucket, location).await?;
+        if backups.len() > keep {
+            backups.truncate(backups.len() - keep);
+            for key in backups {
+                bucket.delete(&key).await?;
+                println!("Removed expired backup {}", location.url(&key));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Returns the keys of all backups at `location`, oldest first
+async fn backups(
+    bucket: &Bucket,
+    location: &BackupLocation,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut backups = bucket
+        .list(&location.backup_prefix())
+        .await?
+        .into_iter()
+        .filter(|key| key.ends_with(BACKUP_SUFFIX))
+        .collect::<Vec<_>>();
+    backups.sort();
+    Ok(backups)
+}
+
+// Downloads and decrypts a backup. `location` may name a specific backup, otherwise the most
+// recent backup at `location` is used
+pub async fn restore(location: &BackupLocation) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let bucket = Bucket::new(location)?;
+
+    let key = if location.is_backup() {
+        location.path.clone()
+    } else {
+        backups(&bucket, location)
+            .await?
+            .pop()
+            .ok_or_else(|| format!("No backups found at {location}"))?
+    };
+
+    println!("Restoring from {}", location.url(&key));
+    Ok(decrypt(&passphrase()?, &bucket.get(&key).await?)?)
+}
+
+// The files of the database directory, leaving out temporary files and the copies set aside by
+// restores and backend migrations
+fn database_files(db_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(db_path)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file()
+            && ![".temp.", ".pre-restore.", ".pre-migrate."]
+                .iter()
+                .any(|infix| filename.contains(infix))
+        {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Copies the database files of `db_path` into a new directory at `path`, gzipping each of them
+// when `gzip`. The directory is assembled under a temporary name, so that `path` only appears
+// once the snapshot is complete
+pub fn snapshot(db_path: &Path, path: &Path, gzip: bool) -> io::Result<()> {
+    if path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    let temp_path = PathBuf::from(format!(
+        "{}.temp.{}",
+        path.display(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    ));
+
+    let result = fs::create_dir_all(&temp_path)
+        .and_then(|()| {
+            for filename in database_files(db_path)? {
+                let mut snapshot_filename = filename.file_name().unwrap().to_os_string();
+                if gzip {
+                    snapshot_filename.push(".gz");
+                    let mut encoder = GzEncoder::new(
+                        fs::File::create(temp_path.join(snapshot_filename))?,
+                        Compression::default(),
+                    );
+                    io::copy(&mut fs::File::open(&filename)?, &mut encoder)?;
+                    encoder.finish()?;
+                } else {
+                    fs::copy(&filename, temp_path.join(snapshot_filename))?;
+                }
+            }
+            Ok(())
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&temp_path);
+    }
+    result
+}
+
+// Replaces the database files of `db_path` with those of the snapshot at `path`. The current
+// files are first snapshotted into the snapshots directory, which is returned, so that an
+// unwanted restore can be undone
+pub fn restore_snapshot(db_path: &Path, path: &Path) -> io::Result<PathBuf> {
+    let mut files = vec![];
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let filename = PathBuf::from(entry.file_name());
+        files.push(if filename.extension() == Some("gz".as_ref()) {
+            let mut bytes = vec![];
+            GzDecoder::new(fs::File::open(entry.path())?).read_to_end(&mut bytes)?;
+            (filename.with_extension(""), bytes)
+        } else {
+            (filename, fs::read(entry.path())?)
+        });
+    }
+    if !files.iter().any(|(filename, _)| {
+        filename == Path::new("data.json") || filename == Path::new("data.sqlite")
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Not a database snapshot: {}", path.display()),
+        ));
+    }
+
+    let previous_path = snapshots_path(db_path).join(snapshot_name());
+    fs::create_dir_all(snapshots_path(db_path))?;
+    snapshot(db_path, &previous_path, false)?;
+
+    let previous_files = database_files(db_path)?;
+    for (filename, bytes) in &files {
+        let filename = db_path.join(filename);
+        let temp_filename = format!("{}.temp.restore", filename.display());
+        fs::write(&temp_filename, bytes)?;
+        fs::rename(temp_filename, filename)?;
+    }
+    for filename in previous_files {
+        if !files.iter().any(|(restored_filename, _)| {
+            filename.file_name() == Some(restored_filename.as_os_str())
+        }) {
+            fs::remove_file(filename)?;
+        }
+    }
+    Ok(previous_path)
+}
+
+fn snapshots_path(db_path: &Path) -> PathBuf {
+    db_path.join(SNAPSHOTS_DIRNAME)
+}
+
+// Snapshot names sort in the order the snapshots were taken
+fn snapshot_name() -> String {
+    format!("sys-{}", Utc::now().format("%Y%m%dT%H%M%S%.3fZ"))
+}
+
+// The automatic snapshots of the database at `db_path`, oldest first
+pub fn snapshots(db_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let snapshots_path = snapshots_path(db_path);
+    if !snapshots_path.exists() {
+        return Ok(vec![]);
+    }
+    let mut snapshots = vec![];
+    for entry in fs::read_dir(snapshots_path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && name.starts_with("sys-") && !name.contains(".temp.") {
+            snapshots.push(entry.path());
+        }
+    }
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+// Snapshots the database at `db_path` before a command modifies it, then deletes all but the
+// most recent `keep` snapshots
+pub fn auto_snapshot(db_path: &Path, keep: usize) -> io::Result<()> {
+    fs::create_dir_all(snapshots_path(db_path))?;
+    snapshot(
+        db_path,
+        &snapshots_path(db_path).join(snapshot_name()),
+        false,
+    )?;
+
+    let mut snapshots = snapshots(db_path)?;
+    if snapshots.len() > keep {
+        snapshots.truncate(snapshots.len() - keep);
+        for snapshot in snapshots {
+            fs::remove_dir_all(snapshot)?;
+        }
+    }
+    Ok(())
+}
@@ -26,7 +26,7 @@ use {
     },
     std::collections::{BTreeMap, HashMap, HashSet},
     sys::{
-        metrics,
+        http_client, metrics,
         notifier::*,
         priority_fee::{apply_priority_fee, PriorityFee},
         send_transaction_until_expired,
@@ -35,7 +35,7 @@ use {
             kamino, marginfi_v2,
             solend::{self, math::TryMul},
         },
-        *,
+        SendTransactionStatus, *,
     },
 };
 
@@ -572,14 +572,14 @@ async fn send_instructions_for_ops<T: solana_sdk::signers::Signers + ?Sized>(
     (
         Signature,
         /*priority_fee_lamports: */ u64,
-        /*transaction_confirmed:*/ Option<bool>,
+        /*send_status:*/ Option<SendTransactionStatus>,
     ),
     Box<dyn std::error::Error>,
 > {
     let rpc_client = rpc_clients.default();
 
     let (recent_blockhash, last_valid_block_height) =
-        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+        rpc_clients.get_latest_blockhash_with_commitment()?;
 
     let (transaction, priority_fee) = {
         let mut instructions = instructions_for_ops.instructions;
@@ -605,14 +605,18 @@ async fn send_instructions_for_ops<T: solana_sdk::signers::Signers + ?Sized>(
 
     let signature = transaction.signatures[0];
 
-    let transaction_confirmed = if dry_run {
+    let send_status = if dry_run {
         println!("Dry run. Will not send transaction");
         None
     } else {
-        send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
+        Some(send_transaction_until_expired(
+            rpc_clients,
+            &transaction,
+            last_valid_block_height,
+        ))
     };
 
-    Ok((signature, priority_fee, transaction_confirmed))
+    Ok((signature, priority_fee, send_status))
 }
 
 #[tokio::main]
@@ -635,9 +639,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .long("url")
                 .value_name("URL")
                 .takes_value(true)
-                .validator(is_url_or_moniker)
+                .validator(is_comma_separated_url_or_moniker_list)
                 .default_value(default_json_rpc_url)
-                .help("JSON RPC URL for the cluster"),
+                .help("JSON RPC URL for the cluster. Several URLs, separated by commas, \
+                       are failed over between in order, skipping unhealthy ones"),
         )
         .arg(
             Arg::with_name("send_json_rpc_urls")
@@ -999,6 +1004,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let app_matches = app.get_matches();
 
+    http_client::configure(
+        std::env::var("SYS_PROXY").ok(),
+        std::env::var("SYS_CA_BUNDLE").ok(),
+        false,
+    )?;
+
     let rpc_clients = RpcClients::new(
         value_t_or_exit!(app_matches, "json_rpc_url", String),
         value_t!(app_matches, "send_json_rpc_urls", String).ok(),
@@ -1054,7 +1065,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     )
                 };
                 if !raw {
-                    notifier.send(&msg).await;
+                    notifier
+                        .send(NotificationKind::Balance, Severity::Info, &msg)
+                        .await;
                 }
                 metrics::push(dp::supply_apy(&pool, maybe_token, apy_as_bps)).await;
                 println!("{msg}");
@@ -1093,7 +1106,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         "".into()
                     }
                 );
-                notifier.send(&msg).await;
+                notifier
+                    .send(NotificationKind::Balance, Severity::Info, &msg)
+                    .await;
                 metrics::push(dp::supply_balance(
                     pool,
                     &address,
@@ -1561,22 +1576,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             println!("{}", operation_info.op_msg);
 
-            let (signature, priority_fee_lamports, transaction_confirmed) =
-                send_instructions_for_ops(
-                    &rpc_clients,
-                    address,
-                    operation_info.instructions_for_ops,
-                    priority_fee,
-                    dry_run,
-                    &vec![signer],
-                )
-                .await?;
+            let (signature, priority_fee_lamports, send_status) = send_instructions_for_ops(
+                &rpc_clients,
+                address,
+                operation_info.instructions_for_ops,
+                priority_fee,
+                dry_run,
+                &vec![signer],
+            )
+            .await?;
 
             if dry_run {
                 return Ok(());
             }
 
-            if transaction_confirmed.is_some() {
+            // The priority fee was only paid if the transaction was processed
+            if matches!(
+                send_status,
+                Some(SendTransactionStatus::Confirmed | SendTransactionStatus::Failed)
+            ) {
                 metrics::push(dp::priority_fee(
                     &format!("{cmd:?}").to_lowercase(),
                     &address,
@@ -1586,9 +1604,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .await;
             }
 
-            if !transaction_confirmed.unwrap_or_default() {
+            if !send_status.is_some_and(SendTransactionStatus::confirmed) {
                 let failure_msg = format!("Transaction failed: {signature}");
-                notifier.send(&failure_msg).await;
+                notifier
+                    .send(NotificationKind::Transfer, Severity::Alert, &failure_msg)
+                    .await;
                 return Err(failure_msg.into());
             }
             println!("Transaction confirmed: {signature}");
@@ -1,13 +1,24 @@
 use {
-    crate::{exchange::*, token::MaybeToken, token::Token},
+    crate::{
+        exchange::*,
+        http_client::{self, TracedSend},
+        token::MaybeToken,
+        token::Token,
+    },
     async_trait::async_trait,
     chrono::{Local, TimeZone},
+    futures::StreamExt,
+    hmac::{Hmac, Mac},
+    serde::{de::DeserializeOwned, Deserialize},
+    sha2::Sha256,
     solana_sdk::pubkey::Pubkey,
     std::{
         collections::HashMap,
         str::FromStr,
-        time::{SystemTime, UNIX_EPOCH},
+        time::{Duration, SystemTime, UNIX_EPOCH},
     },
+    tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    tokio_tungstenite::tungstenite::Message,
 };
 
 pub struct BinanceExchangeClient {
@@ -15,6 +26,229 @@ pub struct BinanceExchangeClient {
     market: binance::market::Market,
     wallet: binance::wallet::Wallet,
     preferred_solusd_pair: &'static str,
+    binance_us: bool,
+    api_key: String,
+    secret: String,
+    rest_api_endpoint: &'static str,
+    websocket_endpoint: &'static str,
+}
+
+// Simple Earn flexible savings responses, which `binance-rs-async` does not cover
+#[derive(Debug, Deserialize)]
+struct Rows<T> {
+    rows: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FlexiblePosition {
+    asset: String,
+    total_amount: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlexibleReward {
+    asset: String,
+    rewards: String,
+    #[serde(rename = "type")]
+    kind: String,
+    time: u64,
+}
+
+// Reward records of each type are listed separately
+const FLEXIBLE_REWARD_TYPES: &[&str] = &["BONUS", "REALTIME", "REWARDS"];
+
+// Small balances are converted into BNB
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DustAsset {
+    asset: String,
+    amount_free: String,
+    #[serde(rename = "toBNB")]
+    to_bnb: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DustAssets {
+    details: Vec<DustAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DustTransfer {
+    amount: String,
+    from_asset: String,
+    service_charge_amount: String,
+    tran_id: u64,
+    transfered_amount: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DustTransferResult {
+    transfer_result: Vec<DustTransfer>,
+}
+
+// Cross margin account, which `binance-rs-async` does not cover
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MarginUserAsset {
+    asset: String,
+    borrowed: String,
+    interest: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CrossMarginAccount {
+    margin_level: String,
+    user_assets: Vec<MarginUserAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawQuota {
+    wd_quota: String,
+    used_wd_quota: String,
+}
+
+// Binance expires a user data stream listen key unless it's kept alive at least once an hour
+const LISTEN_KEY_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+async fn stream_order_updates(
+    rest_api_endpoint: &'static str,
+    websocket_endpoint: &'static str,
+    api_key: String,
+    listen_key: String,
+    sender: UnboundedSender<OrderId>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = http_client::client();
+    let (mut websocket, _) =
+        tokio_tungstenite::connect_async(format!("{websocket_endpoint}/ws/{listen_key}")).await?;
+
+    let mut keepalive = tokio::time::interval(LISTEN_KEY_KEEPALIVE_INTERVAL);
+    keepalive.tick().await; // The first tick completes immediately
+
+    loop {
+        tokio::select! {
+            _ = keepalive.tick() => {
+                client
+                    .put(format!("{rest_api_endpoint}/api/v3/userDataStream"))
+                    .header("X-MBX-APIKEY", &api_key)
+                    .query(&[("listenKey", &listen_key)])
+                    .traced_send()
+                    .await?
+                    .error_for_status()?;
+            }
+            message = websocket.next() => {
+                let text = match message {
+                    None => return Ok(()),
+                    Some(message) => match message? {
+                        Message::Text(text) => text,
+                        Message::Close(_) => return Ok(()),
+                        _ => continue,
+                    },
+                };
+
+                let event = serde_json::from_str::<serde_json::Value>(&text)?;
+                if event["e"] != "executionReport"
+                    || !matches!(
+                        event["X"].as_str(),
+                        Some("PARTIALLY_FILLED" | "FILLED" | "CANCELED" | "EXPIRED" | "REJECTED")
+                    )
+                {
+                    continue;
+                }
+
+                // Cancellations report the cancelled order's client order id in "C"
+                let order_id = match event["C"].as_str() {
+                    Some(order_id) if !order_id.is_empty() => order_id,
+                    _ => event["c"].as_str().unwrap_or_default(),
+                };
+                if sender.send(order_id.to_string()).is_err() {
+                    return Ok(()); // Receiver dropped
+                }
+            }
+        }
+    }
+}
+
+impl BinanceExchangeClient {
+    async fn signed_request<T: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let query = query
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .chain(std::iter::once(format!("timestamp={timestamp}")))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(query.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        Ok(http_client::client()
+            .request(
+                method,
+                format!(
+                    "{}{path}?{query}&signature={signature}",
+                    self.rest_api_endpoint
+                ),
+            )
+            .header("X-MBX-APIKEY", &self.api_key)
+            .traced_send()
+            .await?
+            .error_for_status()?
+            .json::<T>()
+            .await?)
+    }
+
+    async fn signed_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        self.signed_request(reqwest::Method::GET, path, query).await
+    }
+
+    async fn signed_post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        self.signed_request(reqwest::Method::POST, path, query)
+            .await
+    }
+
+    // Flexible savings balances by coin. Simple Earn is not offered by Binance US
+    async fn earn_balances(&self) -> Result<HashMap<String, f64>, Box<dyn std::error::Error>> {
+        if self.binance_us {
+            return Ok(HashMap::new());
+        }
+
+        let mut earn_balances = HashMap::new();
+        for position in self
+            .signed_get::<Rows<FlexiblePosition>>(
+                "/sapi/v1/simple-earn/flexible/position",
+                &[("size", "100")],
+            )
+            .await?
+            .rows
+        {
+            let total_amount = parse_f64("totalAmount", &position.total_amount)?;
+            *earn_balances.entry(position.asset).or_default() += total_amount;
+        }
+        Ok(earn_balances)
+    }
 }
 
 #[async_trait]
@@ -42,6 +276,13 @@ impl ExchangeClient for BinanceExchangeClient {
             .parse::<Pubkey>()?)
     }
 
+    async fn deposit_memo(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
     async fn recent_deposits(
         &self,
     ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
@@ -55,7 +296,10 @@ impl ExchangeClient for BinanceExchangeClient {
                     if dr.status == 1 {
                         Some(DepositInfo {
                             tx_id: dr.tx_id,
+                            coin: dr.coin,
                             amount: dr.amount,
+                            memo: None,
+                            when: None,
                         })
                     } else {
                         None
@@ -98,6 +342,66 @@ impl ExchangeClient for BinanceExchangeClient {
             .collect())
     }
 
+    // Flexible savings interest from the last 30 days
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        if self.binance_us {
+            return Ok(vec![]);
+        }
+
+        let mut rewards = vec![];
+        for reward_type in FLEXIBLE_REWARD_TYPES {
+            for reward in self
+                .signed_get::<Rows<FlexibleReward>>(
+                    "/sapi/v1/simple-earn/flexible/history/rewardsRecord",
+                    &[("type", reward_type), ("size", "100")],
+                )
+                .await?
+                .rows
+            {
+                let token = match reward.asset.as_str() {
+                    "SOL" => MaybeToken::SOL(),
+                    asset => match Token::from_str(asset) {
+                        Ok(token) => token.into(),
+                        Err(_) => continue,
+                    },
+                };
+                let amount = parse_f64("rewards", &reward.rewards)?;
+
+                rewards.push(RewardInfo {
+                    id: format!("{}:{}:{}", reward.kind, reward.asset, reward.time),
+                    token,
+                    amount,
+                    when: Local
+                        .timestamp_opt((reward.time / 1000) as i64, 0)
+                        .unwrap()
+                        .date_naive(),
+                });
+            }
+        }
+        Ok(rewards)
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        if self.binance_us {
+            return Ok(None);
+        }
+
+        // Binance limits the USD value withdrawn across all assets
+        let withdraw_quota = self
+            .signed_get::<WithdrawQuota>("/sapi/v1/capital/withdraw/quota", &[])
+            .await?;
+        let daily_limit_usd = parse_f64("wdQuota", &withdraw_quota.wd_quota)?;
+        let used_usd = parse_f64("usedWdQuota", &withdraw_quota.used_wd_quota)?;
+        Ok(Some(WithdrawalLimit {
+            daily_limit_usd: Some(daily_limit_usd),
+            remaining_usd: Some(daily_limit_usd - used_usd),
+            remaining: None,
+        }))
+    }
+
     async fn request_withdraw(
         &self,
         address: Pubkey,
@@ -178,15 +482,29 @@ impl ExchangeClient for BinanceExchangeClient {
         &self,
     ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
         let account = self.account.get_account().await?;
+        let earn_balances = self.earn_balances().await?;
 
         let mut balances = HashMap::new();
         for coin in ["SOL"].iter().chain(USD_COINS) {
-            if let Some(balance) = account.balances.iter().find(|b| b.asset == *coin) {
-                let available = balance.free;
-                let total = available + balance.locked;
-
-                balances.insert(coin.to_string(), ExchangeBalance { available, total });
+            let balance = account.balances.iter().find(|b| b.asset == *coin);
+            let earn = earn_balances.get(*coin).copied();
+            if balance.is_none() && earn.is_none() {
+                continue;
             }
+
+            let available = balance.map(|balance| balance.free).unwrap_or_default();
+            let earn = earn.unwrap_or_default();
+            let total =
+                available + balance.map(|balance| balance.locked).unwrap_or_default() + earn;
+
+            balances.insert(
+                coin.to_string(),
+                ExchangeBalance {
+                    available,
+                    total,
+                    earn,
+                },
+            );
         }
 
         Ok(balances)
@@ -251,13 +569,27 @@ impl ExchangeClient for BinanceExchangeClient {
         })
     }
 
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        let binance::rest_model::OrderBook { bids, asks, .. } = self.market.get_depth(pair).await?;
+
+        Ok(OrderBook {
+            bids: bids.into_iter().map(|bid| (bid.price, bid.qty)).collect(),
+            asks: asks.into_iter().map(|ask| (ask.price, ask.qty)).collect(),
+        })
+    }
+
     async fn place_order(
         &self,
         pair: &str,
         side: OrderSide,
         price: f64,
         amount: f64,
+        options: OrderOptions,
     ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if options.post_only && options.time_in_force != TimeInForce::GoodTilCancelled {
+            return Err("Post-only orders must be GTC".into());
+        }
+
         // Minimum notional value for orders is $10 USD
         if price * amount < 10. {
             return Err("Total order amount must be 10 or greater".into());
@@ -271,7 +603,21 @@ impl ExchangeClient for BinanceExchangeClient {
                     OrderSide::Buy => binance::rest_model::OrderSide::Buy,
                     OrderSide::Sell => binance::rest_model::OrderSide::Sell,
                 },
-                order_type: binance::rest_model::OrderType::LimitMaker,
+                // LIMIT_MAKER orders are post-only and always GTC
+                order_type: if options.post_only {
+                    binance::rest_model::OrderType::LimitMaker
+                } else {
+                    binance::rest_model::OrderType::Limit
+                },
+                time_in_force: if options.post_only {
+                    None
+                } else {
+                    Some(match options.time_in_force {
+                        TimeInForce::GoodTilCancelled => binance::rest_model::TimeInForce::GTC,
+                        TimeInForce::ImmediateOrCancel => binance::rest_model::TimeInForce::IOC,
+                        TimeInForce::FillOrKill => binance::rest_model::TimeInForce::FOK,
+                    })
+                },
                 price: Some(price),
                 quantity: Some(amount),
                 new_order_resp_type: Some(binance::rest_model::OrderResponse::Full),
@@ -281,6 +627,29 @@ impl ExchangeClient for BinanceExchangeClient {
             .client_order_id)
     }
 
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        Ok(self
+            .account
+            .place_order(binance::account::OrderRequest {
+                symbol: pair.into(),
+                side: match side {
+                    OrderSide::Buy => binance::rest_model::OrderSide::Buy,
+                    OrderSide::Sell => binance::rest_model::OrderSide::Sell,
+                },
+                order_type: binance::rest_model::OrderType::Market,
+                quantity: Some(amount),
+                new_order_resp_type: Some(binance::rest_model::OrderResponse::Full),
+                ..binance::account::OrderRequest::default()
+            })
+            .await?
+            .client_order_id)
+    }
+
     async fn cancel_order(
         &self,
         pair: &str,
@@ -313,8 +682,15 @@ impl ExchangeClient for BinanceExchangeClient {
             })
             .await?;
 
-        assert_eq!(order.order_type, binance::rest_model::OrderType::LimitMaker);
-        assert_eq!(order.time_in_force, binance::rest_model::TimeInForce::GTC);
+        let market_order = matches!(order.order_type, binance::rest_model::OrderType::Market);
+        assert!(
+            market_order
+                || matches!(
+                    order.order_type,
+                    binance::rest_model::OrderType::LimitMaker
+                        | binance::rest_model::OrderType::Limit
+                )
+        );
         assert_eq!(&order.symbol, pair);
         assert_eq!(order.client_order_id, *order_id);
 
@@ -332,7 +708,12 @@ impl ExchangeClient for BinanceExchangeClient {
 
         let fee = trade_fees.first().map(|trade_fee| {
             assert_eq!(&trade_fee.symbol, pair);
-            (trade_fee.maker_commission * order.executed_qty, {
+            let commission = if market_order {
+                trade_fee.taker_commission
+            } else {
+                trade_fee.maker_commission
+            };
+            (commission * order.executed_qty, {
                 // TODO: Avoid hard code and support pairs generically...
                 assert!(matches!(trade_fee.symbol.as_str(), "SOLUSD" | "SOLBUSD"));
                 if side == OrderSide::Sell {
@@ -350,7 +731,12 @@ impl ExchangeClient for BinanceExchangeClient {
                     | binance::rest_model::OrderStatus::PartiallyFilled
             ),
             side,
-            price: order.price,
+            // Market orders have no price of their own, report the average fill price instead
+            price: if market_order && order.executed_qty > 0. {
+                order.cummulative_quote_qty / order.executed_qty
+            } else {
+                order.price
+            },
             amount: order.orig_qty,
             filled_amount: order.executed_qty,
             last_update,
@@ -358,6 +744,74 @@ impl ExchangeClient for BinanceExchangeClient {
         })
     }
 
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        let orders = self.account.get_open_orders(pair).await?;
+
+        Ok(orders
+            .into_iter()
+            .map(|order| {
+                let last_update = Local
+                    .timestamp_opt((order.update_time / 1000) as i64, 0)
+                    .unwrap()
+                    .date_naive();
+
+                (
+                    order.client_order_id,
+                    OrderStatus {
+                        open: true,
+                        side: match order.side {
+                            binance::rest_model::OrderSide::Sell => OrderSide::Sell,
+                            binance::rest_model::OrderSide::Buy => OrderSide::Buy,
+                        },
+                        price: order.price,
+                        amount: order.orig_qty,
+                        filled_amount: order.executed_qty,
+                        last_update,
+                        fee: None,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        let listen_key = http_client::client()
+            .post(format!("{}/api/v3/userDataStream", self.rest_api_endpoint))
+            .header("X-MBX-APIKEY", &self.api_key)
+            .traced_send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?["listenKey"]
+            .as_str()
+            .ok_or("Invalid user data stream response")?
+            .to_string();
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let rest_api_endpoint = self.rest_api_endpoint;
+        let websocket_endpoint = self.websocket_endpoint;
+        let api_key = self.api_key.clone();
+        tokio::spawn(async move {
+            if let Err(err) = stream_order_updates(
+                rest_api_endpoint,
+                websocket_endpoint,
+                api_key,
+                listen_key,
+                sender,
+            )
+            .await
+            {
+                eprintln!("Binance user data stream failed: {err}");
+            }
+        });
+        Ok(Some(receiver))
+    }
+
     async fn get_lending_info(
         &self,
         _coin: &str,
@@ -368,7 +822,7 @@ impl ExchangeClient for BinanceExchangeClient {
     async fn get_lending_history(
         &self,
         _lending_history: LendingHistory,
-    ) -> Result<HashMap<String, f64>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
         Err("Lending not currently supported for Binance".into())
     }
 
@@ -380,6 +834,89 @@ impl ExchangeClient for BinanceExchangeClient {
         Err("Lending not currently supported for Binance".into())
     }
 
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        if self.binance_us {
+            return Err("Margin not currently supported for Binance US".into());
+        }
+
+        let account = self
+            .signed_get::<CrossMarginAccount>("/sapi/v1/margin/account", &[])
+            .await?;
+
+        let mut borrows = vec![];
+        for user_asset in account.user_assets {
+            let borrowed = parse_f64("borrowed", &user_asset.borrowed)?;
+            let interest = parse_f64("interest", &user_asset.interest)?;
+            if borrowed > 0. || interest > 0. {
+                borrows.push(MarginBorrow {
+                    coin: user_asset.asset,
+                    borrowed,
+                    interest,
+                });
+            }
+        }
+
+        // Binance reports a margin level of 999 when nothing is borrowed
+        let margin_level = if borrows.is_empty() {
+            None
+        } else {
+            Some(parse_f64("marginLevel", &account.margin_level)?)
+        };
+        Ok(MarginAccount {
+            margin_level,
+            borrows,
+        })
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        if self.binance_us {
+            return Err("Dust conversion not currently supported for Binance US".into());
+        }
+
+        self.signed_post::<DustAssets>("/sapi/v1/asset/dust-btc", &[])
+            .await?
+            .details
+            .into_iter()
+            .map(|dust_asset| {
+                Ok(DustBalance {
+                    amount: parse_f64("amountFree", &dust_asset.amount_free)?,
+                    converted_amount: parse_f64("toBNB", &dust_asset.to_bnb)?,
+                    coin: dust_asset.asset,
+                    converted_coin: "BNB".into(),
+                })
+            })
+            .collect()
+    }
+
+    async fn convert_dust(
+        &self,
+        coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        if self.binance_us {
+            return Err("Dust conversion not currently supported for Binance US".into());
+        }
+
+        let query = coins
+            .iter()
+            .map(|coin| ("asset", coin.as_str()))
+            .collect::<Vec<_>>();
+        self.signed_post::<DustTransferResult>("/sapi/v1/asset/dust", &query)
+            .await?
+            .transfer_result
+            .into_iter()
+            .map(|transfer| {
+                Ok(DustConversion {
+                    id: transfer.tran_id.to_string(),
+                    amount: parse_f64("amount", &transfer.amount)?,
+                    converted_amount: parse_f64("transferedAmount", &transfer.transfered_amount)?,
+                    fee: parse_f64("serviceChargeAmount", &transfer.service_charge_amount)?,
+                    coin: transfer.from_asset,
+                    converted_coin: "BNB".into(),
+                })
+            })
+            .collect()
+    }
+
     fn preferred_solusd_pair(&self) -> &'static str {
         self.preferred_solusd_pair
     }
@@ -390,6 +927,7 @@ fn _new(
         api_key,
         secret,
         subaccount,
+        ..
     }: ExchangeCredentials,
     binance_us: bool,
 ) -> Result<BinanceExchangeClient, Box<dyn std::error::Error>> {
@@ -397,13 +935,19 @@ fn _new(
         return Err("subaccounts not supported".into());
     }
 
+    let rest_api_endpoint = if binance_us {
+        "https://api.binance.us"
+    } else {
+        "https://api.binance.com"
+    };
+    let websocket_endpoint = if binance_us {
+        "wss://stream.binance.us:9443"
+    } else {
+        "wss://stream.binance.com:9443"
+    };
+
     let config = binance::config::Config {
-        rest_api_endpoint: if binance_us {
-            "https://api.binance.us"
-        } else {
-            "https://api.binance.com"
-        }
-        .into(),
+        rest_api_endpoint: rest_api_endpoint.into(),
         binance_us_api: binance_us,
         ..binance::config::Config::default()
     };
@@ -419,14 +963,22 @@ fn _new(
         Some(secret.clone()),
         &config,
     );
-    let wallet: binance::wallet::Wallet =
-        binance::api::Binance::new_with_config(Some(api_key), Some(secret), &config);
+    let wallet: binance::wallet::Wallet = binance::api::Binance::new_with_config(
+        Some(api_key.clone()),
+        Some(secret.clone()),
+        &config,
+    );
 
     Ok(BinanceExchangeClient {
         account,
         market,
         wallet,
         preferred_solusd_pair: if binance_us { "SOLUSD" } else { "SOLBUSD" },
+        binance_us,
+        api_key,
+        secret,
+        rest_api_endpoint,
+        websocket_endpoint,
     })
 }
 
@@ -0,0 +1,770 @@
+use {
+    crate::{
+        exchange::*,
+        http_client::{self, TracedSend},
+        token::MaybeToken,
+    },
+    async_trait::async_trait,
+    chrono::prelude::*,
+    hmac::{Hmac, Mac},
+    rand::RngCore,
+    serde::{de::DeserializeOwned, Deserialize},
+    sha2::Sha256,
+    solana_sdk::pubkey::Pubkey,
+    std::collections::HashMap,
+    tokio::sync::mpsc::UnboundedReceiver,
+};
+
+const API_HOST: &str = "www.bitstamp.net";
+
+const SUPPORTED_COINS: &[&str] = &["SOL"];
+const SUPPORTED_PAIRS: &[&str] = &["SOLUSD", "SOLEUR"];
+
+pub struct BitstampExchangeClient {
+    client: reqwest::Client,
+    api_key: String,
+    secret: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    status: Option<String>,
+    reason: Option<serde_json::Value>,
+    code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DepositAddress {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct CryptoTransaction {
+    currency: String,
+    amount: f64,
+    txid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CryptoTransactions {
+    deposits: Vec<CryptoTransaction>,
+}
+
+#[derive(Deserialize)]
+struct WithdrawalRequest {
+    id: u64,
+    currency: String,
+    amount: String,
+    // 0 (open), 1 (in process), 2 (finished), 3 (canceled) or 4 (failed)
+    status: u8,
+    address: Option<String>,
+    transaction_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Withdrawal {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct WithdrawalFee {
+    fee: String,
+}
+
+#[derive(Deserialize)]
+struct Balance {
+    currency: String,
+    total: String,
+    available: String,
+}
+
+#[derive(Deserialize)]
+struct Ticker {
+    bid: String,
+    ask: String,
+    high: String,
+    low: String,
+    vwap: String,
+}
+
+#[derive(Deserialize)]
+struct Candle {
+    timestamp: String,
+    high: String,
+    low: String,
+    volume: String,
+}
+
+#[derive(Deserialize)]
+struct OhlcData {
+    ohlc: Vec<Candle>,
+}
+
+#[derive(Deserialize)]
+struct Ohlc {
+    data: OhlcData,
+}
+
+#[derive(Deserialize)]
+struct Book {
+    bids: Vec<[String; 2]>,
+    asks: Vec<[String; 2]>,
+}
+
+#[derive(Deserialize)]
+struct PlacedOrder {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct OpenOrder {
+    id: String,
+    datetime: String,
+    #[serde(rename = "type")]
+    kind: String,
+    price: String,
+    amount: String,
+    amount_at_create: String,
+}
+
+#[derive(Deserialize)]
+struct OrderTransaction {
+    price: serde_json::Value,
+    fee: serde_json::Value,
+    datetime: String,
+    // The traded amounts, keyed by lowercase currency name
+    #[serde(flatten)]
+    amounts: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct OrderStatusResponse {
+    status: String,
+    amount_remaining: String,
+    datetime: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    transactions: Vec<OrderTransaction>,
+}
+
+// Bitstamp returns some numeric fields as JSON numbers and others as strings
+fn value_f64(field: &str, value: &serde_json::Value) -> Result<f64, Box<dyn std::error::Error>> {
+    match value {
+        serde_json::Value::Number(number) => number
+            .as_f64()
+            .ok_or_else(|| format!("Invalid `{field}` field: {number}").into()),
+        serde_json::Value::String(value) => parse_f64(field, value),
+        value => Err(format!("Invalid `{field}` field: {value}").into()),
+    }
+}
+
+fn parse_date(field: &str, value: &str) -> Result<NaiveDate, Box<dyn std::error::Error>> {
+    Ok(NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .map_err(|err| format!("Invalid `{field}` field: {value}: {err}"))?
+        .date())
+}
+
+fn parse_side(kind: &str) -> Result<OrderSide, Box<dyn std::error::Error>> {
+    match kind {
+        "0" => Ok(OrderSide::Buy),
+        "1" => Ok(OrderSide::Sell),
+        kind => Err(format!("Invalid order type: {kind}").into()),
+    }
+}
+
+// Bitstamp market symbols are lowercase
+fn market_symbol(pair: &str) -> String {
+    pair.to_lowercase()
+}
+
+// The quote currency of a supported pair, in which order fees are charged
+fn quote_currency(pair: &str) -> &str {
+    &pair[pair.len() - 3..]
+}
+
+impl BitstampExchangeClient {
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let response = request.traced_send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        // Errors are reported either with an HTTP error status or as a `"status": "error"` body
+        if let Ok(ErrorResponse {
+            status: error_status,
+            reason,
+            code,
+        }) = serde_json::from_str::<ErrorResponse>(&body)
+        {
+            if !status.is_success() || error_status.as_deref() == Some("error") {
+                return Err(format!(
+                    "Bitstamp API error {}: {}",
+                    code.unwrap_or_else(|| status.to_string()),
+                    reason.map(|reason| reason.to_string()).unwrap_or_default()
+                )
+                .into());
+            }
+        }
+        if !status.is_success() {
+            return Err(format!("Bitstamp API error: {status}").into());
+        }
+        Ok(serde_json::from_str::<T>(&body)?)
+    }
+
+    async fn public_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        self.send(self.client.get(format!("https://{API_HOST}{path}")))
+            .await
+    }
+
+    async fn private_post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        // Parameter values are numbers, ids and base58 addresses, none of which need escaping
+        let body = params
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let content_type = if body.is_empty() {
+            ""
+        } else {
+            "application/x-www-form-urlencoded"
+        };
+
+        let mut nonce = [0u8; 18];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let nonce = hex::encode(nonce);
+        let timestamp = timestamp_millis().to_string();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(
+            format!(
+                "BITSTAMP {}POST{API_HOST}{path}{content_type}{nonce}{timestamp}v2{body}",
+                self.api_key
+            )
+            .as_bytes(),
+        );
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let mut request = self
+            .client
+            .post(format!("https://{API_HOST}{path}"))
+            .header("X-Auth", format!("BITSTAMP {}", self.api_key))
+            .header("X-Auth-Signature", signature)
+            .header("X-Auth-Nonce", nonce)
+            .header("X-Auth-Timestamp", timestamp)
+            .header("X-Auth-Version", "v2");
+        if !body.is_empty() {
+            request = request.header("Content-Type", content_type).body(body);
+        }
+        self.send(request).await
+    }
+
+    async fn open_orders_for_pair(
+        &self,
+        pair: &str,
+    ) -> Result<Vec<OpenOrder>, Box<dyn std::error::Error>> {
+        self.private_post(
+            &format!("/api/v2/open_orders/{}/", market_symbol(pair)),
+            &[],
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl ExchangeClient for BitstampExchangeClient {
+    async fn deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Pubkey, Box<dyn std::error::Error>> {
+        if !SUPPORTED_COINS.contains(&token.name()) {
+            return Err(format!("{token} deposits are not supported").into());
+        }
+
+        let deposit_address = self
+            .private_post::<DepositAddress>(
+                &format!("/api/v2/{}_address/", token.name().to_lowercase()),
+                &[],
+            )
+            .await?;
+        Ok(deposit_address.address.parse::<Pubkey>()?)
+    }
+
+    async fn deposit_memo(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn recent_deposits(
+        &self,
+    ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
+        // Only credited deposits are listed
+        let crypto_transactions = self
+            .private_post::<CryptoTransactions>(
+                "/api/v2/crypto-transactions/",
+                &[("limit", "100".into())],
+            )
+            .await?;
+
+        Ok(Some(
+            crypto_transactions
+                .deposits
+                .into_iter()
+                .filter(|deposit| SUPPORTED_COINS.contains(&deposit.currency.as_str()))
+                .filter_map(|deposit| {
+                    Some(DepositInfo {
+                        tx_id: deposit.txid?,
+                        coin: deposit.currency,
+                        amount: deposit.amount,
+                        memo: None,
+                        when: None,
+                    })
+                })
+                .collect(),
+        ))
+    }
+
+    async fn recent_withdrawals(&self) -> Result<Vec<WithdrawalInfo>, Box<dyn std::error::Error>> {
+        let withdrawal_requests = self
+            .private_post::<Vec<WithdrawalRequest>>(
+                "/api/v2/withdrawal-requests/",
+                &[("timedelta", (60 * 60 * 24 * 30).to_string())],
+            )
+            .await?;
+
+        let mut withdrawals = vec![];
+        for withdrawal_request in withdrawal_requests {
+            if !SUPPORTED_COINS.contains(&withdrawal_request.currency.as_str()) {
+                continue;
+            }
+
+            let (completed, tx_id) = match withdrawal_request.status {
+                2 => (true, withdrawal_request.transaction_id),
+                3 | 4 => (true, None),
+                _ => (false, None),
+            };
+            withdrawals.push(WithdrawalInfo {
+                address: withdrawal_request
+                    .address
+                    .and_then(|address| address.parse::<Pubkey>().ok())
+                    .unwrap_or_default(),
+                token: MaybeToken::SOL(),
+                amount: parse_f64("amount", &withdrawal_request.amount)?,
+                tag: withdrawal_request.id.to_string(),
+                completed,
+                tx_id,
+            });
+        }
+        Ok(withdrawals)
+    }
+
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn request_withdraw(
+        &self,
+        address: Pubkey,
+        token: MaybeToken,
+        amount: f64,
+        _withdrawal_password: Option<String>,
+        _withdrawal_code: Option<String>,
+    ) -> Result<(/* withdraw_id: */ String, /*withdraw_fee: */ f64), Box<dyn std::error::Error>>
+    {
+        let coin = token.name().to_lowercase();
+        if !SUPPORTED_COINS.contains(&token.name()) {
+            return Err(format!("{token} withdrawals are not supported").into());
+        }
+
+        let withdrawal_fee = self
+            .private_post::<WithdrawalFee>(&format!("/api/v2/fees/withdrawal/{coin}/"), &[])
+            .await?;
+        let withdrawal = self
+            .private_post::<Withdrawal>(
+                &format!("/api/v2/{coin}_withdrawal/"),
+                &[
+                    ("amount", amount.to_string()),
+                    ("address", address.to_string()),
+                ],
+            )
+            .await?;
+
+        Ok((
+            withdrawal.id.to_string(),
+            parse_f64("fee", &withdrawal_fee.fee)?,
+        ))
+    }
+
+    async fn balances(
+        &self,
+    ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
+        let mut balances = HashMap::new();
+        for balance in self
+            .private_post::<Vec<Balance>>("/api/v2/account_balances/", &[])
+            .await?
+        {
+            let currency = balance.currency.to_uppercase();
+            if currency == "SOL" || currency == "EUR" || USD_COINS.contains(&currency.as_str()) {
+                balances.insert(
+                    currency,
+                    ExchangeBalance {
+                        available: parse_f64("available", &balance.available)?,
+                        total: parse_f64("total", &balance.total)?,
+                        earn: 0.,
+                    },
+                );
+            }
+        }
+        Ok(balances)
+    }
+
+    async fn print_market_info(
+        &self,
+        pair: &str,
+        format: MarketInfoFormat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let ticker = self
+            .public_get::<Ticker>(&format!("/api/v2/ticker/{}/", market_symbol(pair)))
+            .await?;
+        let weighted_24h_avg_price = parse_f64("vwap", &ticker.vwap)?;
+
+        match format {
+            MarketInfoFormat::All => {
+                println!("Pair: {pair}");
+                println!(
+                    "Ask: {}, Bid: {}, High: {}, Low: {}, ",
+                    ticker.ask, ticker.bid, ticker.high, ticker.low
+                );
+                println!("Weighted 24h average price: {weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Ask => {
+                println!("{}", ticker.ask);
+            }
+            MarketInfoFormat::Weighted24hAveragePrice => {
+                println!("{weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Hourly => {
+                let ohlc = self
+                    .public_get::<Ohlc>(&format!(
+                        "/api/v2/ohlc/{}/?step=3600&limit=24",
+                        market_symbol(pair)
+                    ))
+                    .await?;
+
+                println!("hour,low,high,average,volume");
+                for candle in ohlc.data.ohlc {
+                    let time = Local
+                        .timestamp_opt(parse_f64("timestamp", &candle.timestamp)? as i64, 0)
+                        .single()
+                        .ok_or("Invalid API response")?;
+                    let high = parse_f64("high", &candle.high)?;
+                    let low = parse_f64("low", &candle.low)?;
+                    println!(
+                        "{},{},{},{},{}",
+                        time,
+                        low,
+                        high,
+                        (low + high) / 2.,
+                        candle.volume
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn bid_ask(&self, pair: &str) -> Result<BidAsk, Box<dyn std::error::Error>> {
+        let ticker = self
+            .public_get::<Ticker>(&format!("/api/v2/ticker/{}/", market_symbol(pair)))
+            .await?;
+
+        Ok(BidAsk {
+            bid_price: parse_f64("bid", &ticker.bid)?,
+            ask_price: parse_f64("ask", &ticker.ask)?,
+        })
+    }
+
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        let book = self
+            .public_get::<Book>(&format!("/api/v2/order_book/{}/", market_symbol(pair)))
+            .await?;
+
+        let parse_levels = |levels: Vec<[String; 2]>| -> Vec<(f64, f64)> {
+            levels
+                .into_iter()
+                .filter_map(|[price, amount]| {
+                    Some((price.parse::<f64>().ok()?, amount.parse::<f64>().ok()?))
+                })
+                .collect()
+        };
+        Ok(OrderBook {
+            bids: parse_levels(book.bids),
+            asks: parse_levels(book.asks),
+        })
+    }
+
+    async fn place_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        price: f64,
+        amount: f64,
+        options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if !SUPPORTED_PAIRS.contains(&pair) {
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        let mut params = vec![("amount", amount.to_string()), ("price", price.to_string())];
+        match (options.post_only, options.time_in_force) {
+            (true, TimeInForce::GoodTilCancelled) => params.push(("moc_order", "True".into())),
+            (true, time_in_force) => {
+                return Err(format!("Unsupported post-only time in force: {time_in_force}").into())
+            }
+            (false, TimeInForce::GoodTilCancelled) => {}
+            (false, TimeInForce::ImmediateOrCancel) => params.push(("ioc_order", "True".into())),
+            (false, TimeInForce::FillOrKill) => params.push(("fok_order", "True".into())),
+        }
+
+        let order = self
+            .private_post::<PlacedOrder>(
+                &format!(
+                    "/api/v2/{}/{}/",
+                    side.to_string().to_lowercase(),
+                    market_symbol(pair)
+                ),
+                &params,
+            )
+            .await?;
+        Ok(order.id)
+    }
+
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if !SUPPORTED_PAIRS.contains(&pair) {
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        let order = self
+            .private_post::<PlacedOrder>(
+                &format!(
+                    "/api/v2/{}/market/{}/",
+                    side.to_string().to_lowercase(),
+                    market_symbol(pair)
+                ),
+                &[("amount", amount.to_string())],
+            )
+            .await?;
+        Ok(order.id)
+    }
+
+    async fn cancel_order(
+        &self,
+        _pair: &str,
+        order_id: &OrderId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.private_post::<serde_json::Value>(
+            "/api/v2/cancel_order/",
+            &[("id", order_id.clone())],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn order_status(
+        &self,
+        pair: &str,
+        order_id: &OrderId,
+    ) -> Result<OrderStatus, Box<dyn std::error::Error>> {
+        let order = self
+            .private_post::<OrderStatusResponse>(
+                "/api/v2/order_status/",
+                &[("id", order_id.clone())],
+            )
+            .await?;
+
+        let open = order.status == "Open";
+        let amount_remaining = if open {
+            parse_f64("amount_remaining", &order.amount_remaining)?
+        } else {
+            0.
+        };
+
+        let coin = pair[..pair.len() - 3].to_lowercase();
+        let mut filled_amount = 0.;
+        let mut filled_value = 0.;
+        let mut fee = 0.;
+        let mut last_update = None;
+        for transaction in &order.transactions {
+            let amount = value_f64(
+                &coin,
+                transaction
+                    .amounts
+                    .get(&coin)
+                    .ok_or_else(|| format!("Order transaction has no `{coin}` field"))?,
+            )?;
+            filled_amount += amount;
+            filled_value += amount * value_f64("price", &transaction.price)?;
+            fee += value_f64("fee", &transaction.fee)?;
+            last_update = Some(parse_date("datetime", &transaction.datetime)?);
+        }
+
+        // The order status doesn't include the limit price or side, which are only available
+        // from the open orders
+        let open_order = if open {
+            self.open_orders_for_pair(pair)
+                .await?
+                .into_iter()
+                .find(|open_order| open_order.id == *order_id)
+        } else {
+            None
+        };
+
+        let (side, price) = match open_order {
+            Some(ref open_order) => (
+                parse_side(&open_order.kind)?,
+                parse_f64("price", &open_order.price)?,
+            ),
+            None => (
+                parse_side(
+                    order
+                        .kind
+                        .as_deref()
+                        .ok_or("Order status has no `type` field")?,
+                )?,
+                if filled_amount > 0. {
+                    filled_value / filled_amount
+                } else {
+                    0.
+                },
+            ),
+        };
+
+        let last_update = match (last_update, order.datetime) {
+            (Some(last_update), _) => last_update,
+            (None, Some(datetime)) => parse_date("datetime", &datetime)?,
+            (None, None) => Local::now().date_naive(),
+        };
+
+        Ok(OrderStatus {
+            open,
+            side,
+            price,
+            amount: filled_amount + amount_remaining,
+            filled_amount,
+            last_update,
+            fee: (fee > f64::EPSILON).then(|| (fee, quote_currency(pair).to_string())),
+        })
+    }
+
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        let mut open_orders = HashMap::new();
+        for open_order in self.open_orders_for_pair(pair).await? {
+            let amount = parse_f64("amount_at_create", &open_order.amount_at_create)?;
+            open_orders.insert(
+                open_order.id,
+                OrderStatus {
+                    open: true,
+                    side: parse_side(&open_order.kind)?,
+                    price: parse_f64("price", &open_order.price)?,
+                    amount,
+                    filled_amount: amount - parse_f64("amount", &open_order.amount)?,
+                    last_update: parse_date("datetime", &open_order.datetime)?,
+                    fee: None,
+                },
+            );
+        }
+        Ok(open_orders)
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn get_lending_info(
+        &self,
+        _coin: &str,
+    ) -> Result<Option<LendingInfo>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Bitstamp".into())
+    }
+
+    async fn get_lending_history(
+        &self,
+        _lending_history: LendingHistory,
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Bitstamp".into())
+    }
+
+    async fn submit_lending_offer(
+        &self,
+        _coin: &str,
+        _size: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Bitstamp".into())
+    }
+
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        Err("Margin not currently supported for Bitstamp".into())
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Bitstamp".into())
+    }
+
+    async fn convert_dust(
+        &self,
+        _coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Bitstamp".into())
+    }
+
+    fn preferred_solusd_pair(&self) -> &'static str {
+        "SOLUSD"
+    }
+}
+
+pub fn new(
+    ExchangeCredentials {
+        api_key,
+        secret,
+        subaccount,
+        ..
+    }: ExchangeCredentials,
+) -> Result<BitstampExchangeClient, Box<dyn std::error::Error>> {
+    if subaccount.is_some() {
+        return Err("subaccounts not supported".into());
+    }
+
+    Ok(BitstampExchangeClient {
+        client: http_client::client(),
+        api_key,
+        secret,
+    })
+}
@@ -0,0 +1,870 @@
+use {
+    crate::{
+        exchange::*,
+        http_client::{self, TracedSend},
+        token::{MaybeToken, Token},
+    },
+    async_trait::async_trait,
+    chrono::prelude::*,
+    hmac::{Hmac, Mac},
+    serde::{de::DeserializeOwned, Deserialize},
+    sha2::Sha256,
+    solana_sdk::pubkey::Pubkey,
+    std::{collections::HashMap, str::FromStr},
+    tokio::sync::mpsc::UnboundedReceiver,
+};
+
+const API_ENDPOINT: &str = "https://api.bybit.com";
+const RECV_WINDOW: &str = "5000";
+
+// Bybit names the Solana network "SOL" for both SOL and SPL token transfers
+const SOLANA_CHAIN: &str = "SOL";
+
+const SUPPORTED_COINS: &[&str] = &["SOL", "USDC"];
+const SUPPORTED_PAIRS: &[&str] = &["SOLUSDT", "SOLUSDC"];
+
+pub struct BybitExchangeClient {
+    client: reqwest::Client,
+    api_key: String,
+    secret: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    ret_code: i64,
+    ret_msg: String,
+    result: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DepositAddressChain {
+    chain: String,
+    address_deposit: String,
+    #[serde(default)]
+    tag_deposit: String, // memo required by a deposit address shared between accounts
+}
+
+#[derive(Deserialize)]
+struct DepositAddress {
+    chains: Vec<DepositAddressChain>,
+}
+
+#[derive(Deserialize)]
+struct DepositRecord {
+    chain: String,
+    amount: String,
+    #[serde(rename = "txID")]
+    tx_id: String,
+    #[serde(default)]
+    tag: String,
+    status: i64,
+    #[serde(rename = "successAt", default)]
+    success_at: String, // milliseconds since the unix epoch
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawRecord {
+    withdraw_id: String,
+    coin: String,
+    chain: String,
+    amount: String,
+    #[serde(rename = "txID")]
+    tx_id: String,
+    status: String,
+    to_address: String,
+}
+
+#[derive(Deserialize)]
+struct Rows<T> {
+    rows: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CoinChainInfo {
+    chain: String,
+    withdraw_fee: String,
+    withdraw_min: String,
+    chain_withdraw: String,
+}
+
+#[derive(Deserialize)]
+struct CoinInfo {
+    coin: String,
+    chains: Vec<CoinChainInfo>,
+}
+
+#[derive(Deserialize)]
+struct Withdraw {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawableAmount {
+    withdrawable_amount: String,
+}
+
+// `withdrawable_amount` is keyed by account type
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Withdrawable {
+    limit_amount_usd: String,
+    withdrawable_amount: HashMap<String, WithdrawableAmount>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WalletCoin {
+    coin: String,
+    wallet_balance: String,
+    locked: String,
+}
+
+#[derive(Deserialize)]
+struct WalletAccount {
+    coin: Vec<WalletCoin>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ticker {
+    bid1_price: String,
+    ask1_price: String,
+    high_price24h: String,
+    low_price24h: String,
+    volume24h: String,
+    turnover24h: String,
+}
+
+#[derive(Deserialize)]
+struct List<T> {
+    list: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct Orderbook {
+    b: Vec<(String, String)>,
+    a: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderCreated {
+    order_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Order {
+    order_id: String,
+    symbol: String,
+    side: String,
+    order_type: String,
+    price: String,
+    avg_price: String,
+    qty: String,
+    cum_exec_qty: String,
+    cum_exec_fee: String,
+    order_status: String,
+    updated_time: String,
+}
+
+impl Order {
+    fn into_order_status(self) -> Result<(OrderId, OrderStatus), Box<dyn std::error::Error>> {
+        let side = match self.side.as_str() {
+            "Buy" => OrderSide::Buy,
+            "Sell" => OrderSide::Sell,
+            side => return Err(format!("Invalid order side: {side}").into()),
+        };
+
+        // Spot trading fees are charged in the coin received: the base coin of a buy and the
+        // quote coin of a sell
+        let fee = {
+            let fee = parse_f64("cumExecFee", &self.cum_exec_fee)?;
+            let coin = match side {
+                OrderSide::Buy => "SOL",
+                OrderSide::Sell => self.symbol.trim_start_matches("SOL"),
+            };
+            (fee > f64::EPSILON).then(|| (fee, coin.to_string()))
+        };
+
+        let last_update = Local
+            .timestamp_millis_opt(
+                self.updated_time
+                    .parse::<i64>()
+                    .map_err(|err| format!("Invalid `updatedTime` field: {err}"))?,
+            )
+            .single()
+            .ok_or("Invalid `updatedTime` field")?
+            .date_naive();
+
+        Ok((
+            self.order_id,
+            OrderStatus {
+                open: matches!(
+                    self.order_status.as_str(),
+                    "New" | "PartiallyFilled" | "Untriggered"
+                ),
+                side,
+                // Market orders have no price of their own, report the average fill price instead
+                price: if self.order_type == "Market" {
+                    parse_f64("avgPrice", &self.avg_price).unwrap_or_default()
+                } else {
+                    parse_f64("price", &self.price)?
+                },
+                amount: parse_f64("qty", &self.qty)?,
+                filled_amount: parse_f64("cumExecQty", &self.cum_exec_qty)?,
+                last_update,
+                fee,
+            },
+        ))
+    }
+}
+
+impl BybitExchangeClient {
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let response = request
+            .traced_send()
+            .await?
+            .error_for_status()?
+            .json::<Response>()
+            .await?;
+
+        if response.ret_code != 0 {
+            return Err(format!(
+                "Bybit API error {}: {}",
+                response.ret_code, response.ret_msg
+            )
+            .into());
+        }
+        Ok(serde_json::from_value(response.result)?)
+    }
+
+    // Authenticates a request whose query string or JSON body is `payload`
+    fn sign(&self, request: reqwest::RequestBuilder, payload: &str) -> reqwest::RequestBuilder {
+        let timestamp = timestamp_millis().to_string();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(format!("{timestamp}{}{RECV_WINDOW}{payload}", self.api_key).as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        request
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-RECV-WINDOW", RECV_WINDOW)
+            .header("X-BAPI-SIGN", signature)
+    }
+
+    async fn public_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        self.send(
+            self.client
+                .get(format!("{API_ENDPOINT}{path}"))
+                .query(query),
+        )
+        .await
+    }
+
+    async fn private_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        // All query values used are URL-safe, so the signed query string matches the one sent
+        let query_string = query
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let request = self
+            .client
+            .get(format!("{API_ENDPOINT}{path}?{query_string}"));
+        self.send(self.sign(request, &query_string)).await
+    }
+
+    async fn private_post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: serde_json::Value,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let body = body.to_string();
+        let request = self
+            .client
+            .post(format!("{API_ENDPOINT}{path}"))
+            .header("Content-Type", "application/json");
+        let request = self.sign(request, &body);
+        self.send(request.body(body)).await
+    }
+
+    async fn get_orders(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        self.private_get::<List<Order>>(path, query)
+            .await?
+            .list
+            .into_iter()
+            .map(Order::into_order_status)
+            .collect()
+    }
+
+    async fn solana_deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<DepositAddressChain, Box<dyn std::error::Error>> {
+        if !SUPPORTED_COINS.contains(&token.name()) {
+            return Err(format!("{token} deposits are not supported").into());
+        }
+
+        Ok(self
+            .private_get::<DepositAddress>(
+                "/v5/asset/deposit/query-address",
+                &[("coin", token.name()), ("chainType", SOLANA_CHAIN)],
+            )
+            .await?
+            .chains
+            .into_iter()
+            .find(|chain| chain.chain == SOLANA_CHAIN)
+            .ok_or_else(|| format!("No Solana deposit address for {token}"))?)
+    }
+}
+
+#[async_trait]
+impl ExchangeClient for BybitExchangeClient {
+    async fn deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Pubkey, Box<dyn std::error::Error>> {
+        Ok(self
+            .solana_deposit_address(token)
+            .await?
+            .address_deposit
+            .parse::<Pubkey>()?)
+    }
+
+    async fn deposit_memo(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let tag_deposit = self.solana_deposit_address(token).await?.tag_deposit;
+        Ok((!tag_deposit.is_empty()).then_some(tag_deposit))
+    }
+
+    async fn recent_deposits(
+        &self,
+    ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
+        let mut successful_deposits = vec![];
+
+        for coin in SUPPORTED_COINS {
+            for deposit_record in self
+                .private_get::<Rows<DepositRecord>>(
+                    "/v5/asset/deposit/query-record",
+                    &[("coin", coin)],
+                )
+                .await?
+                .rows
+            {
+                /* status codes: 0 = unknown, 1 = to be confirmed, 2 = processing, 3 = success,
+                4 = deposit failed */
+                if deposit_record.chain == SOLANA_CHAIN && deposit_record.status == 3 {
+                    successful_deposits.push(DepositInfo {
+                        tx_id: deposit_record.tx_id,
+                        coin: coin.to_string(),
+                        amount: parse_f64("amount", &deposit_record.amount)?,
+                        memo: (!deposit_record.tag.is_empty()).then_some(deposit_record.tag),
+                        when: deposit_record
+                            .success_at
+                            .parse::<i64>()
+                            .ok()
+                            .map(|success_at| success_at / 1_000),
+                    });
+                }
+            }
+        }
+        Ok(Some(successful_deposits))
+    }
+
+    async fn recent_withdrawals(&self) -> Result<Vec<WithdrawalInfo>, Box<dyn std::error::Error>> {
+        let mut withdrawals = vec![];
+
+        for coin in SUPPORTED_COINS {
+            for withdraw_record in self
+                .private_get::<Rows<WithdrawRecord>>(
+                    "/v5/asset/withdraw/query-record",
+                    &[("coin", coin)],
+                )
+                .await?
+                .rows
+            {
+                if withdraw_record.chain != SOLANA_CHAIN {
+                    continue;
+                }
+
+                let (completed, tx_id) = match withdraw_record.status.as_str() {
+                    "success" => (true, Some(withdraw_record.tx_id)),
+                    "CancelByUser" | "Reject" | "Fail" => (true, None),
+                    _ => (false, None),
+                };
+
+                let token = if withdraw_record.coin == "SOL" {
+                    None
+                } else {
+                    Token::from_str(&withdraw_record.coin).ok()
+                };
+                withdrawals.push(WithdrawalInfo {
+                    address: withdraw_record
+                        .to_address
+                        .parse::<Pubkey>()
+                        .unwrap_or_default(),
+                    token: token.into(),
+                    amount: parse_f64("amount", &withdraw_record.amount)?,
+                    tag: withdraw_record.withdraw_id,
+                    completed,
+                    tx_id,
+                });
+            }
+        }
+        Ok(withdrawals)
+    }
+
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        let coin = token.name();
+        if !SUPPORTED_COINS.contains(&coin) {
+            return Ok(None);
+        }
+
+        let withdrawable = self
+            .private_get::<Withdrawable>(
+                "/v5/asset/withdraw/withdrawable-amount",
+                &[("coin", coin)],
+            )
+            .await?;
+        let remaining = match withdrawable.withdrawable_amount.get("UTA") {
+            Some(amount) => Some(parse_f64(
+                "withdrawableAmount",
+                &amount.withdrawable_amount,
+            )?),
+            None => None,
+        };
+
+        Ok(Some(WithdrawalLimit {
+            daily_limit_usd: None,
+            remaining_usd: Some(parse_f64("limitAmountUsd", &withdrawable.limit_amount_usd)?),
+            remaining,
+        }))
+    }
+
+    async fn request_withdraw(
+        &self,
+        address: Pubkey,
+        token: MaybeToken,
+        amount: f64,
+        _withdrawal_password: Option<String>,
+        _withdrawal_code: Option<String>,
+    ) -> Result<(/* withdraw_id: */ String, /*withdraw_fee: */ f64), Box<dyn std::error::Error>>
+    {
+        let coin = token.name();
+        if !SUPPORTED_COINS.contains(&coin) {
+            return Err(format!("{token} withdrawals are not supported").into());
+        }
+
+        let chain_info = self
+            .private_get::<Rows<CoinInfo>>("/v5/asset/coin/query-info", &[("coin", coin)])
+            .await?
+            .rows
+            .into_iter()
+            .find(|coin_info| coin_info.coin == coin)
+            .and_then(|coin_info| {
+                coin_info
+                    .chains
+                    .into_iter()
+                    .find(|chain_info| chain_info.chain == SOLANA_CHAIN)
+            })
+            .ok_or_else(|| format!("{coin} on Solana not found in Bybit coin list"))?;
+
+        if chain_info.chain_withdraw != "1" {
+            return Err(format!("Bybit {coin} withdrawals disabled").into());
+        }
+
+        let withdraw_min = parse_f64("withdrawMin", &chain_info.withdraw_min)?;
+        if amount < withdraw_min {
+            return Err(format!(
+                "Withdrawal request is below the minimum of {withdraw_min} {coin}"
+            )
+            .into());
+        }
+        let withdraw_fee = parse_f64("withdrawFee", &chain_info.withdraw_fee)?;
+
+        let withdraw = self
+            .private_post::<Withdraw>(
+                "/v5/asset/withdraw/create",
+                serde_json::json!({
+                    "coin": coin,
+                    "chain": SOLANA_CHAIN,
+                    "address": address.to_string(),
+                    "amount": amount.to_string(),
+                    "timestamp": timestamp_millis() as u64,
+                    "forceChain": 1,
+                    // Trading balances are held in the unified trading account
+                    "accountType": "UTA",
+                }),
+            )
+            .await?;
+
+        Ok((withdraw.id, withdraw_fee))
+    }
+
+    async fn balances(
+        &self,
+    ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
+        let wallet_accounts = self
+            .private_get::<List<WalletAccount>>(
+                "/v5/account/wallet-balance",
+                &[("accountType", "UNIFIED")],
+            )
+            .await?;
+
+        let mut balances = HashMap::new();
+        for wallet_coin in wallet_accounts
+            .list
+            .into_iter()
+            .flat_map(|wallet_account| wallet_account.coin)
+        {
+            if wallet_coin.coin == "SOL" || USD_COINS.contains(&wallet_coin.coin.as_str()) {
+                let total = parse_f64("walletBalance", &wallet_coin.wallet_balance)?;
+                let locked = parse_f64("locked", &wallet_coin.locked).unwrap_or_default();
+
+                balances.insert(
+                    wallet_coin.coin,
+                    ExchangeBalance {
+                        available: total - locked,
+                        total,
+                        earn: 0.,
+                    },
+                );
+            }
+        }
+
+        Ok(balances)
+    }
+
+    async fn print_market_info(
+        &self,
+        pair: &str,
+        format: MarketInfoFormat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let ticker = self
+            .public_get::<List<Ticker>>(
+                "/v5/market/tickers",
+                &[("category", "spot"), ("symbol", pair)],
+            )
+            .await?
+            .list
+            .pop()
+            .ok_or_else(|| format!("Unknown pair: {pair}"))?;
+
+        let weighted_24h_avg_price = parse_f64("turnover24h", &ticker.turnover24h)?
+            / parse_f64("volume24h", &ticker.volume24h)?;
+
+        match format {
+            MarketInfoFormat::All => {
+                println!("Pair: {pair}");
+                println!(
+                    "Ask: ${}, Bid: ${}, High: ${}, Low: ${}, ",
+                    ticker.ask1_price, ticker.bid1_price, ticker.high_price24h, ticker.low_price24h
+                );
+                println!("Weighted 24h average price: ${weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Ask => {
+                println!("{}", ticker.ask1_price);
+            }
+            MarketInfoFormat::Weighted24hAveragePrice => {
+                println!("{weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Hourly => {
+                // Each kline is [start time, open, high, low, close, volume, turnover], newest
+                // first
+                let klines = self
+                    .public_get::<List<Vec<String>>>(
+                        "/v5/market/kline",
+                        &[
+                            ("category", "spot"),
+                            ("symbol", pair),
+                            ("interval", "60"),
+                            ("limit", "24"),
+                        ],
+                    )
+                    .await?
+                    .list;
+
+                println!("hour,low,high,average,volume");
+                for kline in klines.iter().rev() {
+                    if kline.len() < 6 {
+                        return Err("Invalid API response".into());
+                    }
+                    let time = Local
+                        .timestamp_millis_opt(kline[0].parse::<i64>()?)
+                        .single()
+                        .ok_or("Invalid API response")?;
+                    let high = parse_f64("high", &kline[2])?;
+                    let low = parse_f64("low", &kline[3])?;
+                    println!(
+                        "{},{},{},{},{}",
+                        time,
+                        low,
+                        high,
+                        (low + high) / 2.,
+                        kline[5]
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn bid_ask(&self, pair: &str) -> Result<BidAsk, Box<dyn std::error::Error>> {
+        let ticker = self
+            .public_get::<List<Ticker>>(
+                "/v5/market/tickers",
+                &[("category", "spot"), ("symbol", pair)],
+            )
+            .await?
+            .list
+            .pop()
+            .ok_or_else(|| format!("Unknown pair: {pair}"))?;
+
+        Ok(BidAsk {
+            bid_price: parse_f64("bid1Price", &ticker.bid1_price)?,
+            ask_price: parse_f64("ask1Price", &ticker.ask1_price)?,
+        })
+    }
+
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        let orderbook = self
+            .public_get::<Orderbook>(
+                "/v5/market/orderbook",
+                &[("category", "spot"), ("symbol", pair), ("limit", "200")],
+            )
+            .await?;
+
+        let parse_levels = |levels: Vec<(String, String)>| -> Vec<(f64, f64)> {
+            levels
+                .into_iter()
+                .filter_map(|(price, amount)| {
+                    Some((price.parse::<f64>().ok()?, amount.parse::<f64>().ok()?))
+                })
+                .collect()
+        };
+        Ok(OrderBook {
+            bids: parse_levels(orderbook.b),
+            asks: parse_levels(orderbook.a),
+        })
+    }
+
+    async fn place_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        price: f64,
+        amount: f64,
+        options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if !SUPPORTED_PAIRS.contains(&pair) {
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        // Bybit expresses post-only as a time in force of its own
+        let time_in_force = match (options.post_only, options.time_in_force) {
+            (true, TimeInForce::GoodTilCancelled) => "PostOnly",
+            (true, time_in_force) => {
+                return Err(format!("Unsupported post-only time in force: {time_in_force}").into())
+            }
+            (false, time_in_force) => match time_in_force {
+                TimeInForce::GoodTilCancelled => "GTC",
+                TimeInForce::ImmediateOrCancel => "IOC",
+                TimeInForce::FillOrKill => "FOK",
+            },
+        };
+
+        let order = self
+            .private_post::<OrderCreated>(
+                "/v5/order/create",
+                serde_json::json!({
+                    "category": "spot",
+                    "symbol": pair,
+                    "side": side.to_string(),
+                    "orderType": "Limit",
+                    "qty": amount.to_string(),
+                    "price": price.to_string(),
+                    "timeInForce": time_in_force,
+                }),
+            )
+            .await?;
+
+        Ok(order.order_id)
+    }
+
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if !SUPPORTED_PAIRS.contains(&pair) {
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        let order = self
+            .private_post::<OrderCreated>(
+                "/v5/order/create",
+                serde_json::json!({
+                    "category": "spot",
+                    "symbol": pair,
+                    "side": side.to_string(),
+                    "orderType": "Market",
+                    "qty": amount.to_string(),
+                    // Spot market buys are otherwise sized in the quote coin
+                    "marketUnit": "baseCoin",
+                }),
+            )
+            .await?;
+
+        Ok(order.order_id)
+    }
+
+    async fn cancel_order(
+        &self,
+        pair: &str,
+        order_id: &OrderId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.private_post::<serde_json::Value>(
+            "/v5/order/cancel",
+            serde_json::json!({
+                "category": "spot",
+                "symbol": pair,
+                "orderId": order_id,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn order_status(
+        &self,
+        pair: &str,
+        order_id: &OrderId,
+    ) -> Result<OrderStatus, Box<dyn std::error::Error>> {
+        let query = [
+            ("category", "spot"),
+            ("symbol", pair),
+            ("orderId", order_id.as_str()),
+        ];
+
+        // Open orders are only reported by the realtime endpoint, closed orders by the history
+        // endpoint
+        let mut orders = self.get_orders("/v5/order/realtime", &query).await?;
+        if !orders.contains_key(order_id) {
+            orders = self.get_orders("/v5/order/history", &query).await?;
+        }
+
+        orders
+            .remove(order_id)
+            .ok_or_else(|| format!("Unknown order id: {order_id}").into())
+    }
+
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        self.get_orders(
+            "/v5/order/realtime",
+            &[("category", "spot"), ("symbol", pair), ("limit", "50")],
+        )
+        .await
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn get_lending_info(
+        &self,
+        _coin: &str,
+    ) -> Result<Option<LendingInfo>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Bybit".into())
+    }
+
+    async fn get_lending_history(
+        &self,
+        _lending_history: LendingHistory,
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Bybit".into())
+    }
+
+    async fn submit_lending_offer(
+        &self,
+        _coin: &str,
+        _size: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Bybit".into())
+    }
+
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        Err("Margin not currently supported for Bybit".into())
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Bybit".into())
+    }
+
+    async fn convert_dust(
+        &self,
+        _coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Bybit".into())
+    }
+
+    fn preferred_solusd_pair(&self) -> &'static str {
+        "SOLUSDT"
+    }
+}
+
+pub fn new(
+    ExchangeCredentials {
+        api_key,
+        secret,
+        subaccount,
+        ..
+    }: ExchangeCredentials,
+) -> Result<BybitExchangeClient, Box<dyn std::error::Error>> {
+    if subaccount.is_some() {
+        return Err("subaccounts not supported".into());
+    }
+
+    Ok(BybitExchangeClient {
+        client: http_client::client(),
+        api_key,
+        secret,
+    })
+}
@@ -0,0 +1,190 @@
+use {
+    solana_account_decoder::parse_token::UiTokenAmount,
+    solana_client::{
+        client_error::Result as ClientResult,
+        rpc_client::RpcClient,
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_response::{RpcInflationReward, RpcPrioritizationFee, RpcResult, RpcStakeActivation},
+    },
+    solana_sdk::{
+        account::Account,
+        clock::{Epoch, Slot, UnixTimestamp},
+        commitment_config::CommitmentConfig,
+        epoch_info::EpochInfo,
+        hash::Hash,
+        message::VersionedMessage,
+        pubkey::Pubkey,
+        signature::Signature,
+        transaction::VersionedTransaction,
+    },
+    solana_transaction_status::{
+        EncodedConfirmedTransactionWithStatusMeta, TransactionStatus, UiTransactionEncoding,
+    },
+};
+
+// The subset of the Solana JSON RPC API used by sys, so that functions taking a
+// `&dyn ChainClient` can run against a backend other than a live `RpcClient`, such as the local
+// ledger of `testkit`.
+//
+// Methods mirror those of `RpcClient`, which implements the trait by delegation, so an
+// `&RpcClient` may be passed wherever a `&dyn ChainClient` is expected
+pub trait ChainClient: Sync {
+    fn commitment(&self) -> CommitmentConfig;
+
+    fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account>;
+
+    fn get_account_with_commitment(
+        &self,
+        pubkey: &Pubkey,
+        commitment_config: CommitmentConfig,
+    ) -> RpcResult<Option<Account>>;
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> ClientResult<Vec<Option<Account>>>;
+
+    fn get_token_account_balance(&self, pubkey: &Pubkey) -> ClientResult<UiTokenAmount>;
+
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>>;
+
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64>;
+
+    fn get_stake_activation(
+        &self,
+        stake_account: Pubkey,
+        epoch: Option<Epoch>,
+    ) -> ClientResult<RpcStakeActivation>;
+
+    fn get_epoch_info(&self) -> ClientResult<EpochInfo>;
+
+    fn get_inflation_reward(
+        &self,
+        addresses: &[Pubkey],
+        epoch: Option<Epoch>,
+    ) -> ClientResult<Vec<Option<RpcInflationReward>>>;
+
+    fn get_block_time(&self, slot: Slot) -> ClientResult<UnixTimestamp>;
+
+    fn get_latest_blockhash(&self) -> ClientResult<Hash>;
+
+    fn get_fee_for_message(&self, message: &VersionedMessage) -> ClientResult<u64>;
+
+    fn get_recent_prioritization_fees(
+        &self,
+        addresses: &[Pubkey],
+    ) -> ClientResult<Vec<RpcPrioritizationFee>>;
+
+    fn get_signature_statuses_with_history(
+        &self,
+        signatures: &[Signature],
+    ) -> RpcResult<Vec<Option<TransactionStatus>>>;
+
+    fn get_transaction(
+        &self,
+        signature: &Signature,
+        encoding: UiTransactionEncoding,
+    ) -> ClientResult<EncodedConfirmedTransactionWithStatusMeta>;
+
+    fn send_transaction(&self, transaction: &VersionedTransaction) -> ClientResult<Signature>;
+}
+
+impl ChainClient for RpcClient {
+    fn commitment(&self) -> CommitmentConfig {
+        RpcClient::commitment(self)
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        RpcClient::get_account(self, pubkey)
+    }
+
+    fn get_account_with_commitment(
+        &self,
+        pubkey: &Pubkey,
+        commitment_config: CommitmentConfig,
+    ) -> RpcResult<Option<Account>> {
+        RpcClient::get_account_with_commitment(self, pubkey, commitment_config)
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> ClientResult<Vec<Option<Account>>> {
+        RpcClient::get_multiple_accounts(self, pubkeys)
+    }
+
+    fn get_token_account_balance(&self, pubkey: &Pubkey) -> ClientResult<UiTokenAmount> {
+        RpcClient::get_token_account_balance(self, pubkey)
+    }
+
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        RpcClient::get_program_accounts_with_config(self, program_id, config)
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64> {
+        RpcClient::get_minimum_balance_for_rent_exemption(self, data_len)
+    }
+
+    fn get_stake_activation(
+        &self,
+        stake_account: Pubkey,
+        epoch: Option<Epoch>,
+    ) -> ClientResult<RpcStakeActivation> {
+        RpcClient::get_stake_activation(self, stake_account, epoch)
+    }
+
+    fn get_epoch_info(&self) -> ClientResult<EpochInfo> {
+        RpcClient::get_epoch_info(self)
+    }
+
+    fn get_inflation_reward(
+        &self,
+        addresses: &[Pubkey],
+        epoch: Option<Epoch>,
+    ) -> ClientResult<Vec<Option<RpcInflationReward>>> {
+        RpcClient::get_inflation_reward(self, addresses, epoch)
+    }
+
+    fn get_block_time(&self, slot: Slot) -> ClientResult<UnixTimestamp> {
+        RpcClient::get_block_time(self, slot)
+    }
+
+    fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        RpcClient::get_latest_blockhash(self)
+    }
+
+    fn get_fee_for_message(&self, message: &VersionedMessage) -> ClientResult<u64> {
+        match message {
+            VersionedMessage::Legacy(message) => RpcClient::get_fee_for_message(self, message),
+            VersionedMessage::V0(message) => RpcClient::get_fee_for_message(self, message),
+        }
+    }
+
+    fn get_recent_prioritization_fees(
+        &self,
+        addresses: &[Pubkey],
+    ) -> ClientResult<Vec<RpcPrioritizationFee>> {
+        RpcClient::get_recent_prioritization_fees(self, addresses)
+    }
+
+    fn get_signature_statuses_with_history(
+        &self,
+        signatures: &[Signature],
+    ) -> RpcResult<Vec<Option<TransactionStatus>>> {
+        RpcClient::get_signature_statuses_with_history(self, signatures)
+    }
+
+    fn get_transaction(
+        &self,
+        signature: &Signature,
+        encoding: UiTransactionEncoding,
+    ) -> ClientResult<EncodedConfirmedTransactionWithStatusMeta> {
+        RpcClient::get_transaction(self, signature, encoding)
+    }
+
+    fn send_transaction(&self, transaction: &VersionedTransaction) -> ClientResult<Signature> {
+        RpcClient::send_transaction(self, transaction)
+    }
+}
@@ -1,12 +1,75 @@
 use {
-    crate::token::{MaybeToken, Token},
+    crate::{
+        http_client::{self, TracedSend},
+        token::{MaybeToken, Token},
+    },
     chrono::prelude::*,
+    rand::Rng,
+    reqwest::{header::RETRY_AFTER, Response, StatusCode},
     rust_decimal::prelude::*,
-    serde::{Deserialize, Serialize},
-    std::{collections::HashMap, env, sync::Arc},
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap},
+        env, mem,
+        sync::Arc,
+        time::Duration,
+    },
+    thiserror::Error,
     tokio::sync::RwLock,
 };
 
+const MAX_ATTEMPTS: u32 = 8;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug)]
+pub enum CoinGeckoError {
+    // Still throttled after `MAX_ATTEMPTS` requests. `retry_after` is the delay CoinGecko asked
+    // for in its last response, or the next backoff if it did not ask for one
+    #[error("CoinGecko rate limit exceeded, retry after {}s", .retry_after.as_secs())]
+    RateLimited { retry_after: Duration },
+}
+
+// The `Retry-After` header is either a number of seconds or an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let when = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    Some((when - Utc::now()).to_std().unwrap_or_default())
+}
+
+// Sends a GET request, retrying when CoinGecko is throttling or unavailable. The delay CoinGecko
+// asks for with `Retry-After` is respected, otherwise the delay doubles with each attempt. Jitter
+// keeps concurrent callers from retrying in lockstep
+async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, Box<dyn std::error::Error>> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let response = http_client::client().get(url).traced_send().await?;
+        let status = response.status();
+        if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+            return Ok(response.json::<T>().await?);
+        }
+
+        let retry_after = retry_after(&response).unwrap_or(backoff);
+        if attempt == MAX_ATTEMPTS {
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                return Err(CoinGeckoError::RateLimited { retry_after }.into());
+            }
+            return Err(format!("CoinGecko request failed: {status}").into());
+        }
+
+        let jitter = backoff.mul_f64(rand::thread_rng().gen_range(0.0..0.5));
+        tokio::time::sleep(retry_after + jitter).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct CurrencyList {
     usd: f64,
@@ -49,6 +112,10 @@ fn token_to_coin(token: &MaybeToken) -> Result<&'static str, Box<dyn std::error:
             Token::PYTH => "pyth-network",
             Token::WEN => "wen-4",
             Token::WIF => "dogwifcoin",
+            Token::PYUSD => "paypal-usd",
+            Token::Custom(mint) => token
+                .coingecko_id()
+                .ok_or_else(|| format!("Unknown custom token: {mint}"))?,
             unsupported_token => {
                 return Err(format!(
                     "Coin Gecko price data not available for {}",
@@ -121,7 +188,7 @@ pub async fn get_current_price(token: &MaybeToken) -> Result<Decimal, Box<dyn st
                 wif: Option<CurrencyList>,
             }
 
-            let coins = reqwest::get(url).await?.json::<Coins>().await?;
+            let coins = get_json::<Coins>(&url).await?;
 
             coins
                 .solana
@@ -150,19 +217,94 @@ pub async fn get_current_price(token: &MaybeToken) -> Result<Decimal, Box<dyn st
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct MarketChartResponse {
+    prices: Vec<(/* timestamp_millis: */ i64, /* price: */ f64)>,
+}
+
+#[derive(Default)]
+struct HistoricalPriceCache {
+    prices: HashMap<(NaiveDate, MaybeToken), Decimal>,
+    fetched: Vec<(NaiveDate, MaybeToken, Decimal)>, // not yet taken by `take_fetched_historical_prices`
+}
+
+impl HistoricalPriceCache {
+    fn insert_fetched(&mut self, when: NaiveDate, token: MaybeToken, price: Decimal) {
+        self.prices.insert((when, token), price);
+        self.fetched.push((when, token, price));
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref HISTORICAL_PRICE_CACHE: Arc<RwLock<HistoricalPriceCache>> = Arc::new(RwLock::new(HistoricalPriceCache::default()));
+}
+
+// Historical prices never change, so they may be kept across runs. Seeded prices are returned by
+// `get_historical_price` without a request to CoinGecko
+pub async fn seed_historical_prices(
+    prices: impl IntoIterator<Item = (NaiveDate, MaybeToken, Decimal)>,
+) {
+    let mut historical_price_cache = HISTORICAL_PRICE_CACHE.write().await;
+    for (when, token, price) in prices {
+        historical_price_cache.prices.insert((when, token), price);
+    }
+}
+
+// Historical prices fetched from CoinGecko since the last call, to be kept for later runs
+pub async fn take_fetched_historical_prices() -> Vec<(NaiveDate, MaybeToken, Decimal)> {
+    mem::take(&mut HISTORICAL_PRICE_CACHE.write().await.fetched)
+}
+
+// Fetches the daily prices of `token` from `from` through `to` in one request
+pub async fn get_historical_prices(
+    from: NaiveDate,
+    to: NaiveDate,
+    token: &MaybeToken,
+) -> Result<Vec<(NaiveDate, Decimal)>, Box<dyn std::error::Error>> {
+    let coin = token_to_coin(token)?;
+
+    let (maybe_pro, x_cg_pro_api_key) = get_cg_pro_api_key();
+    let url = format!(
+        "https://{maybe_pro}api.coingecko.com/api/v3/coins/{}/market_chart/range?vs_currency=usd&from={}&to={}{x_cg_pro_api_key}",
+        coin,
+        from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp(),
+        to.and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp(),
+    );
+
+    let market_chart = get_json::<MarketChartResponse>(&url).await?;
+
+    // Ranges shorter than 90 days are returned hourly. The first price of each day matches the
+    // 00:00 UTC price returned for that date by `get_historical_price`
+    let mut prices = BTreeMap::new();
+    for (timestamp_millis, price) in market_chart.prices {
+        let when = DateTime::from_timestamp_millis(timestamp_millis)
+            .ok_or_else(|| format!("Invalid market chart timestamp: {timestamp_millis}"))?
+            .date_naive();
+        if (from..=to).contains(&when) {
+            prices
+                .entry(when)
+                .or_insert_with(|| Decimal::from_f64(price).unwrap());
+        }
+    }
+
+    let mut historical_price_cache = HISTORICAL_PRICE_CACHE.write().await;
+    for (when, price) in &prices {
+        if !historical_price_cache.prices.contains_key(&(*when, *token)) {
+            historical_price_cache.insert_fetched(*when, *token, *price);
+        }
+    }
+    Ok(prices.into_iter().collect())
+}
+
 pub async fn get_historical_price(
     when: NaiveDate,
     token: &MaybeToken,
 ) -> Result<Decimal, Box<dyn std::error::Error>> {
-    type HistoricalPriceCache = HashMap<(NaiveDate, MaybeToken), Decimal>;
-    lazy_static::lazy_static! {
-        static ref HISTORICAL_PRICE_CACHE: Arc<RwLock<HistoricalPriceCache>> = Arc::new(RwLock::new(HashMap::new()));
-    }
     let mut historical_price_cache = HISTORICAL_PRICE_CACHE.write().await;
 
     let price_cache_key = (when, *token);
 
-    match historical_price_cache.get(&price_cache_key) {
+    match historical_price_cache.prices.get(&price_cache_key) {
         Some(price) => Ok(*price),
         None => {
             let coin = token_to_coin(token)?;
@@ -176,15 +318,13 @@ pub async fn get_historical_price(
                 when.year()
             );
 
-            reqwest::get(url)
-                .await?
-                .json::<HistoryResponse>()
+            get_json::<HistoryResponse>(&url)
                 .await?
                 .market_data
                 .ok_or_else(|| format!("Market data not available for {coin} on {when}").into())
                 .map(|market_data| {
                     let price = Decimal::from_f64(market_data.current_price.usd).unwrap();
-                    historical_price_cache.insert(price_cache_key, price);
+                    historical_price_cache.insert_fetched(when, *token, price);
                     price
                 })
         }
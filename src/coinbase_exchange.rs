@@ -1,14 +1,117 @@
 use {
-    crate::{exchange::*, token::MaybeToken},
+    crate::{
+        exchange::*,
+        http_client::{self, TracedSend},
+        token::{MaybeToken, Token},
+    },
     async_trait::async_trait,
+    chrono::{DateTime, Local},
     futures::{pin_mut, stream::StreamExt},
+    hmac::{Hmac, Mac},
     rust_decimal::prelude::*,
+    serde::{de::DeserializeOwned, Deserialize},
+    sha2::Sha256,
     solana_sdk::pubkey::Pubkey,
-    std::collections::HashMap,
+    std::{
+        collections::HashMap,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tokio::sync::mpsc::UnboundedReceiver,
 };
 
+const API_ENDPOINT: &str = "https://api.coinbase.com";
+const API_VERSION: &str = "2024-01-01";
+
+// Transaction types that credit the rewards of staked assets
+const REWARD_TRANSACTION_TYPES: &[&str] = &["staking_reward", "inflation_reward"];
+
 pub struct CoinbaseExchangeClient {
     client: coinbase_rs::Private,
+    http_client: reqwest::Client,
+    api_key: String,
+    secret: String,
+}
+
+// Coinbase v2 API responses not covered by `coinbase-rs`
+#[derive(Deserialize)]
+struct Pagination {
+    next_uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    data: Vec<T>,
+    pagination: Pagination,
+}
+
+#[derive(Deserialize)]
+struct Money {
+    amount: String,
+}
+
+#[derive(Deserialize)]
+struct Currency {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    id: String,
+    primary: bool,
+    currency: Currency,
+    balance: Money,
+}
+
+#[derive(Deserialize)]
+struct Transaction {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    amount: Money,
+    created_at: String,
+}
+
+impl CoinbaseExchangeClient {
+    // `path` includes the query string, which is covered by the signature
+    async fn private_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(format!("{timestamp}GET{path}").as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        Ok(self
+            .http_client
+            .get(format!("{API_ENDPOINT}{path}"))
+            .header("CB-ACCESS-KEY", &self.api_key)
+            .header("CB-ACCESS-SIGN", signature)
+            .header("CB-ACCESS-TIMESTAMP", timestamp)
+            .header("CB-VERSION", API_VERSION)
+            .traced_send()
+            .await?
+            .error_for_status()?
+            .json::<T>()
+            .await?)
+    }
+
+    async fn all_accounts(&self) -> Result<Vec<Account>, Box<dyn std::error::Error>> {
+        let mut accounts = vec![];
+        let mut path = Some("/v2/accounts?limit=100".to_string());
+        while let Some(next_path) = path {
+            let page = self.private_get::<Page<Account>>(&next_path).await?;
+            accounts.extend(page.data);
+            path = page.pagination.next_uri;
+        }
+        Ok(accounts)
+    }
 }
 
 #[async_trait]
@@ -55,10 +158,34 @@ impl ExchangeClient for CoinbaseExchangeClient {
         Err(format!("Unsupported deposit token: {}", token.name()).into())
     }
 
+    async fn deposit_memo(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
     async fn balances(
         &self,
     ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
-        Err("Balances not supported".into())
+        let mut balances = HashMap::<String, ExchangeBalance>::new();
+        for account in self.all_accounts().await? {
+            let coin = account.currency.code;
+            if coin != "SOL" && !USD_COINS.contains(&coin.as_str()) {
+                continue;
+            }
+
+            let amount = parse_f64("balance", &account.balance.amount)?;
+            let balance = balances.entry(coin).or_default();
+            balance.total += amount;
+            // Staked funds are held outside of the primary wallet of the coin
+            if account.primary {
+                balance.available += amount;
+            } else {
+                balance.earn += amount;
+            }
+        }
+        Ok(balances)
     }
 
     async fn recent_deposits(
@@ -72,6 +199,57 @@ impl ExchangeClient for CoinbaseExchangeClient {
         Ok(vec![])
     }
 
+    // Staking rewards from the most recent page of transactions of each account
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        let mut rewards = vec![];
+        for account in self.all_accounts().await? {
+            let token = match account.currency.code.as_str() {
+                "SOL" => MaybeToken::SOL(),
+                code => match Token::from_str(code) {
+                    Ok(token) => token.into(),
+                    Err(_) => continue,
+                },
+            };
+
+            for transaction in self
+                .private_get::<Page<Transaction>>(&format!(
+                    "/v2/accounts/{}/transactions?limit=100",
+                    account.id
+                ))
+                .await?
+                .data
+            {
+                if !REWARD_TRANSACTION_TYPES.contains(&transaction.kind.as_str()) {
+                    continue;
+                }
+
+                let when = DateTime::parse_from_rfc3339(&transaction.created_at)
+                    .map_err(|err| {
+                        format!(
+                            "Invalid `created_at` field: {}: {err}",
+                            transaction.created_at
+                        )
+                    })?
+                    .with_timezone(&Local)
+                    .date_naive();
+                rewards.push(RewardInfo {
+                    id: transaction.id,
+                    token,
+                    amount: parse_f64("amount", &transaction.amount.amount)?,
+                    when,
+                });
+            }
+        }
+        Ok(rewards)
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
     async fn request_withdraw(
         &self,
         _address: Pubkey,
@@ -96,12 +274,26 @@ impl ExchangeClient for CoinbaseExchangeClient {
         Err("Trading not supported".into())
     }
 
+    async fn order_book(&self, _pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        Err("Trading not supported".into())
+    }
+
     async fn place_order(
         &self,
         _pair: &str,
         _side: OrderSide,
         _price: f64,
         _amount: f64,
+        _options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        Err("Trading not supported".into())
+    }
+
+    async fn place_market_order(
+        &self,
+        _pair: &str,
+        _side: OrderSide,
+        _amount: f64,
     ) -> Result<OrderId, Box<dyn std::error::Error>> {
         Err("Trading not supported".into())
     }
@@ -122,6 +314,20 @@ impl ExchangeClient for CoinbaseExchangeClient {
         Err("Trading not supported".into())
     }
 
+    async fn open_orders(
+        &self,
+        _pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        // Trading is not supported so there are never any open orders to report
+        Ok(HashMap::default())
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
     async fn get_lending_info(
         &self,
         _coin: &str,
@@ -132,7 +338,7 @@ impl ExchangeClient for CoinbaseExchangeClient {
     async fn get_lending_history(
         &self,
         _lending_history: LendingHistory,
-    ) -> Result<HashMap<String, f64>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
         Err("Lending not supported".into())
     }
 
@@ -144,6 +350,21 @@ impl ExchangeClient for CoinbaseExchangeClient {
         Err("Lending not supported".into())
     }
 
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        Err("Margin not currently supported for Coinbase".into())
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported".into())
+    }
+
+    async fn convert_dust(
+        &self,
+        _coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported".into())
+    }
+
     fn preferred_solusd_pair(&self) -> &'static str {
         "SOLUSD"
     }
@@ -154,10 +375,14 @@ pub fn new(
         api_key,
         secret,
         subaccount,
+        ..
     }: ExchangeCredentials,
 ) -> Result<CoinbaseExchangeClient, Box<dyn std::error::Error>> {
     assert!(subaccount.is_none());
     Ok(CoinbaseExchangeClient {
         client: coinbase_rs::Private::new(coinbase_rs::MAIN_URL, &api_key, &secret),
+        http_client: http_client::client(),
+        api_key,
+        secret,
     })
 }
@@ -0,0 +1,96 @@
+use {
+    crate::passphrase_key::{derive_cipher, NONCE_LEN, PBKDF2_ITERATIONS, SALT_LEN},
+    aes_gcm_siv::{
+        aead::{generic_array::GenericArray, Aead},
+        Aes256GcmSiv,
+    },
+    rand::RngCore,
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+};
+
+// Decrypted to verify a passphrase before any record is decrypted with it
+const CHECK_PLAINTEXT: &[u8] = b"sys credentials";
+
+// An encrypted record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sealed {
+    nonce: String,      // hex
+    ciphertext: String, // hex
+}
+
+// Stored alongside the encrypted records, to derive the key from the passphrase again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionHeader {
+    salt: String, // hex
+    iterations: u32,
+    check: Sealed,
+}
+
+pub struct CredentialsCipher {
+    cipher: Aes256GcmSiv,
+}
+
+impl CredentialsCipher {
+    fn derive(passphrase: &str, salt: &[u8], iterations: u32) -> Self {
+        Self {
+            cipher: derive_cipher(passphrase, salt, iterations),
+        }
+    }
+
+    // A cipher with a key derived from `passphrase` and a new random salt
+    pub fn new(passphrase: &str) -> (Self, EncryptionHeader) {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let credentials_cipher = Self::derive(passphrase, &salt, PBKDF2_ITERATIONS);
+        let check = credentials_cipher.seal_bytes(CHECK_PLAINTEXT);
+        (
+            credentials_cipher,
+            EncryptionHeader {
+                salt: hex::encode(salt),
+                iterations: PBKDF2_ITERATIONS,
+                check,
+            },
+        )
+    }
+
+    // `None` if `passphrase` is not the one the records were encrypted with
+    pub fn unlock(passphrase: &str, header: &EncryptionHeader) -> Option<Self> {
+        let salt = hex::decode(&header.salt).ok()?;
+        let credentials_cipher = Self::derive(passphrase, &salt, header.iterations);
+        (credentials_cipher.open_bytes(&header.check)? == CHECK_PLAINTEXT)
+            .then_some(credentials_cipher)
+    }
+
+    fn seal_bytes(&self, plaintext: &[u8]) -> Sealed {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(GenericArray::from_slice(&nonce), plaintext)
+            .expect("encryption of an in-memory buffer");
+        Sealed {
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        }
+    }
+
+    fn open_bytes(&self, sealed: &Sealed) -> Option<Vec<u8>> {
+        let nonce = hex::decode(&sealed.nonce).ok()?;
+        if nonce.len() != NONCE_LEN {
+            return None;
+        }
+        let ciphertext = hex::decode(&sealed.ciphertext).ok()?;
+        self.cipher
+            .decrypt(GenericArray::from_slice(&nonce), ciphertext.as_slice())
+            .ok()
+    }
+
+    pub fn seal<T: Serialize>(&self, value: &T) -> Sealed {
+        self.seal_bytes(&serde_json::to_vec(value).expect("credentials serialize to JSON"))
+    }
+
+    // `None` if the record was tampered with or is not a `T`
+    pub fn open<T: DeserializeOwned>(&self, sealed: &Sealed) -> Option<T> {
+        serde_json::from_slice(&self.open_bytes(sealed)?).ok()
+    }
+}
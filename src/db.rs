@@ -1,23 +1,32 @@
 use {
-    crate::{field_as_string, metrics::MetricsConfig},
+    crate::{
+        credentials_cipher::{CredentialsCipher, EncryptionHeader, Sealed},
+        db_storage::{self, DbBackend, Storage},
+        field_as_string,
+        metrics::MetricsConfig,
+    },
     chrono::{prelude::*, NaiveDate},
     pickledb::{PickleDb, PickleDbDumpPolicy},
     rust_decimal::prelude::*,
-    separator::FixedPlaceSeparatable,
-    serde::{Deserialize, Serialize},
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
     solana_sdk::{
         clock::{Epoch, Slot},
         pubkey::Pubkey,
         signature::Signature,
     },
     std::{
-        collections::{HashMap, HashSet},
-        fmt, fs, io,
+        collections::{BTreeMap, HashMap, HashSet},
+        fmt, fs, io, mem,
         path::{Path, PathBuf},
         time::{SystemTime, UNIX_EPOCH},
     },
     strum::{EnumString, IntoStaticStr},
-    sys::{exchange::*, token::*},
+    sys::{
+        exchange::*,
+        notifier::NotificationRoute,
+        number_format::{LocalizedFixedPlace, NumberFormat},
+        token::*,
+    },
     thiserror::Error,
 };
 
@@ -56,13 +65,78 @@ pub enum DbError {
     #[error("Lot delete failed: {0}")]
     LotDeleteFailed(String),
 
+    #[error("Lot edit failed: {0}")]
+    LotEditFailed(String),
+
     #[error("Import failed: {0}")]
     ImportFailed(String),
+
+    #[error("Restore failed: {0}")]
+    RestoreFailed(String),
+
+    #[error("Database is open read-only")]
+    ReadOnly,
+
+    #[error("Database uses the {0} backend, run `sys db migrate {1}` to convert it")]
+    BackendMismatch(&'static str, &'static str),
+
+    #[error("Profile does not exist: {0}")]
+    ProfileDoesNotExist(String),
+
+    #[error("Profile already exists: {0}")]
+    ProfileAlreadyExists(String),
+
+    #[error("Invalid profile name: {0}")]
+    InvalidProfileName(String),
+
+    #[error("Cancelled record with signature does not exist: {0}")]
+    CancelledRecordDoesNotExist(Signature),
+
+    #[error("Cancelled record restore failed: {0}")]
+    CancelledRecordRestoreFailed(String),
+
+    #[error("Records dated in {0} are locked, run `sys db reopen-period {0}` to change them")]
+    PeriodClosed(String),
+
+    #[error("Period is already closed: {0}")]
+    PeriodAlreadyClosed(String),
+
+    #[error("Period is not closed: {0}")]
+    PeriodNotClosed(String),
+
+    #[error("Workflow does not exist: {0}")]
+    WorkflowDoesNotExist(usize),
+
+    #[error("Workflow is already finished: {0}")]
+    WorkflowFinished(usize),
+
+    #[error("Credentials are encrypted, set SYS_CREDENTIALS_PASSPHRASE to unlock them")]
+    CredentialsLocked,
+
+    #[error("Credentials are already encrypted")]
+    CredentialsAlreadyEncrypted,
+
+    #[error("Credentials are not encrypted")]
+    CredentialsNotEncrypted,
+
+    #[error("Incorrect credentials passphrase")]
+    IncorrectPassphrase,
+
+    #[error("Unable to decrypt credentials record: {0}")]
+    CredentialsDecryptFailed(String),
+
+    #[error("Token already exists: {0}")]
+    TokenAlreadyExists(String),
+
+    #[error("Undo failed: {0}")]
+    UndoFailed(String),
 }
 
 pub type DbResult<T> = std::result::Result<T, DbError>;
 
-pub fn new<P: AsRef<Path>>(db_path: P) -> DbResult<Db> {
+// Opens the database at `db_path`. The backend of existing records is detected, `backend` selects
+// the backend of a new database
+pub fn new<P: AsRef<Path>>(db_path: P, backend: Option<DbBackend>) -> DbResult<Db> {
     let db_path = db_path.as_ref();
     if !db_path.exists() {
         fs::create_dir_all(db_path)?;
@@ -70,7 +144,6 @@ pub fn new<P: AsRef<Path>>(db_path: P) -> DbResult<Db> {
 
     let legacy_db_filename = db_path.join("◎.db");
     let credentials_db_filename = db_path.join("🤐.db");
-    let data_filename = db_path.join("data.json");
 
     let credentials_db = if credentials_db_filename.exists() {
         PickleDb::load_json(credentials_db_filename, PickleDbDumpPolicy::DumpUponRequest)?
@@ -78,8 +151,17 @@ pub fn new<P: AsRef<Path>>(db_path: P) -> DbResult<Db> {
         PickleDb::new_json(credentials_db_filename, PickleDbDumpPolicy::DumpUponRequest)
     };
 
-    let data = if data_filename.exists() {
-        DbData::load(&data_filename)?
+    let storage_backend = match (db_storage::detect(db_path), backend) {
+        (Some(detected), Some(backend)) if detected != backend => {
+            return Err(DbError::BackendMismatch(detected.into(), backend.into()));
+        }
+        (Some(detected), _) => detected,
+        (None, backend) => backend.unwrap_or(DbBackend::Json),
+    };
+    let mut storage = db_storage::open(db_path, storage_backend)?;
+
+    let data = if let Some(data) = storage.load()? {
+        DbData::from_storage(data)?
     } else if legacy_db_filename.exists() {
         let db = PickleDb::load_json(&legacy_db_filename, PickleDbDumpPolicy::NeverDump)?;
         DbData::import_legacy_db(&db)
@@ -89,24 +171,76 @@ pub fn new<P: AsRef<Path>>(db_path: P) -> DbResult<Db> {
 
     Ok(Db {
         data,
-        data_filename,
+        db_path: db_path.to_path_buf(),
+        storage,
         credentials_db,
         auto_save: true,
+        read_only: false,
+        dry_run: false,
+        profile: None,
+        default_profile_data: None,
+        credentials_cipher: None,
+        journal_entry: None,
     })
 }
 
+// The custom tokens of the database at `db_path`, read without opening the database so they may
+// be registered before the command line is parsed
+pub fn custom_tokens<P: AsRef<Path>>(db_path: P) -> DbResult<Vec<CustomToken>> {
+    let db_path = db_path.as_ref();
+    let Some(backend) = db_storage::detect(db_path) else {
+        return Ok(vec![]);
+    };
+    let custom_tokens = db_storage::open(db_path, backend)?
+        .load()?
+        .and_then(|mut data| data.remove("custom_tokens"))
+        .unwrap_or_default();
+    let custom_tokens: Option<Vec<CustomToken>> =
+        serde_json::from_value(custom_tokens).map_err(io::Error::from)?;
+    Ok(custom_tokens.unwrap_or_default())
+}
+
+// Opens a snapshot of the database as of its last save. Any attempt to modify it fails, so it may
+// be safely used while another instance holds the database write lock
+pub fn new_read_only<P: AsRef<Path>>(db_path: P, backend: Option<DbBackend>) -> DbResult<Db> {
+    let mut db = new(db_path, backend)?;
+    db.read_only = true;
+    Ok(db)
+}
+
+// Opens the database such that modifications are kept in memory only and never saved, to
+// preview the effect of a command
+pub fn new_dry_run<P: AsRef<Path>>(db_path: P, backend: Option<DbBackend>) -> DbResult<Db> {
+    let mut db = new(db_path, backend)?;
+    db.dry_run = true;
+    Ok(db)
+}
+
 pub struct Db {
     credentials_db: PickleDb,
     data: DbData,
-    data_filename: PathBuf,
+    db_path: PathBuf,
+    storage: Box<dyn Storage>,
     auto_save: bool,
+    read_only: bool,
+    dry_run: bool,
+    profile: Option<String>,
+    default_profile_data: Option<DbData>, // set while `data` holds the records of `profile`
+    credentials_cipher: Option<CredentialsCipher>, // set once encrypted credentials are unlocked
+    journal_entry: Option<(JournalEntry, JournalSnapshot)>, // set while a command is journaled
 }
 
+// Key of the `EncryptionHeader` in the credentials database, present while credentials are
+// encrypted
+const ENCRYPTION_HEADER_KEY: &str = "encryption";
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct PendingDeposit {
     pub exchange: Exchange,
     pub amount: u64, // lamports/tokens
     pub transfer: PendingTransfer,
+    #[serde(default)]
+    pub memo: Option<String>, // Attributes the deposit to an account at a shared deposit address
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -126,6 +260,367 @@ pub struct PendingWithdrawal {
     pub lots: Vec<Lot>,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ExchangeTransferKind {
+    Deposit,
+    Withdrawal,
+}
+
+// A completed deposit to or withdrawal from an exchange
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ExchangeTransfer {
+    pub exchange: Exchange,
+    pub kind: ExchangeTransferKind,
+    pub when: NaiveDate,
+    pub token: MaybeToken,
+    pub amount: u64,       // lamports/tokens
+    pub fee: u64,          // in same lamports/tokens as `amount`
+    pub reference: String, // deposit transaction signature or withdrawal tag
+}
+
+// Interest or staking rewards credited by an exchange, recorded so that each reward is only
+// acquired once
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ExchangeReward {
+    pub exchange: Exchange,
+    pub when: NaiveDate,
+    pub token: MaybeToken,
+    pub amount: u64,       // lamports/tokens
+    pub reference: String, // exchange reward id
+}
+
+// A large withdrawal split into chunks that `sync` withdraws one interval apart
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WithdrawalSchedule {
+    pub exchange: Exchange,
+    pub exchange_account: String,
+    pub token: MaybeToken,
+    #[serde(with = "field_as_string")]
+    pub to_address: Pubkey,
+    pub remaining_amount: u64, // lamports/tokens
+    pub chunk_amount: u64,     // lamports/tokens
+    pub interval_hours: u32,
+    pub next_withdrawal: DateTime<Utc>,
+    pub lot_selection_method: LotSelectionMethod,
+}
+
+// A borrowed asset of a tracked margin account, a liability of `value` as of the last sync
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BorrowPosition {
+    pub coin: String,
+    pub borrowed: f64,
+    pub interest: f64,
+    pub value: Option<Decimal>, // `None` if `coin` could not be priced
+}
+
+// An exchange margin account whose borrows `sync` tracks as negative positions
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MarginTracking {
+    pub exchange: Exchange,
+    pub exchange_account: String,
+    pub warn_margin_level: Option<f64>,
+    pub alert_margin_level: Option<f64>,
+    pub margin_level: Option<f64>,
+    pub positions: Vec<BorrowPosition>,
+    pub interest_expense: Decimal, // USD value of the interest charged since tracking started
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+impl MarginTracking {
+    pub fn liabilities(&self) -> Decimal {
+        self.positions
+            .iter()
+            .filter_map(|position| position.value)
+            .sum()
+    }
+}
+
+// Tokens granted to a tracked account that unlock over time. Nothing unlocks before `cliff`,
+// then the amount vested since `start` unlocks every `interval_months` until all of it has by
+// `end`. Unlocked tranches are acquired as income when they arrive in the account, the rest is
+// locked and left out of net worth
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct VestingGrant {
+    pub name: String,
+    #[serde(with = "field_as_string")]
+    pub address: Pubkey,
+    pub token: MaybeToken,
+    pub total_amount: u64, // lamports/tokens
+    pub start: NaiveDate,
+    pub cliff: NaiveDate,
+    pub end: NaiveDate,
+    pub interval_months: u32,
+    pub received_amount: u64, // lamports/tokens acquired as income so far
+}
+
+impl VestingGrant {
+    // The amount unlocked by the schedule as of `when`
+    pub fn unlocked_amount(&self, when: NaiveDate) -> u64 {
+        if when < self.cliff {
+            return 0;
+        }
+        if when >= self.end {
+            return self.total_amount;
+        }
+
+        let months = (when.year() - self.start.year()) * 12 + when.month() as i32
+            - self.start.month() as i32
+            - i32::from(when.day() < self.start.day());
+        let months = months.max(0) as u32 / self.interval_months * self.interval_months;
+        let last_unlock = self
+            .start
+            .checked_add_months(chrono::Months::new(months))
+            .unwrap_or(self.end);
+
+        let vested_days = (last_unlock - self.start).num_days().max(0) as u128;
+        let total_days = (self.end - self.start).num_days().max(1) as u128;
+        (self.total_amount as u128 * vested_days / total_days) as u64
+    }
+
+    // The date of the first unlock after `when`, if any remain
+    pub fn next_unlock(&self, when: NaiveDate) -> Option<NaiveDate> {
+        if when >= self.end {
+            return None;
+        }
+        (1..)
+            .map_while(|i| {
+                self.start
+                    .checked_add_months(chrono::Months::new(i * self.interval_months))
+            })
+            .map(|unlock| unlock.min(self.end))
+            .find(|unlock| *unlock > when && *unlock >= self.cliff)
+    }
+
+    pub fn locked_amount(&self, when: NaiveDate) -> u64 {
+        self.total_amount - self.unlocked_amount(when)
+    }
+
+    // Unlocked by the schedule but not yet received into the account
+    pub fn unreceived_amount(&self, when: NaiveDate) -> u64 {
+        self.unlocked_amount(when)
+            .saturating_sub(self.received_amount)
+    }
+}
+
+// An incoming token stream to a tracked account. Withdrawals from the stream are acquired as
+// income when they arrive in the account
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TokenStream {
+    #[serde(with = "field_as_string")]
+    pub address: Pubkey, // stream metadata account
+    pub name: String,
+    #[serde(with = "field_as_string")]
+    pub recipient: Pubkey,
+    pub token: MaybeToken,
+    pub received_amount: u64, // lamports/tokens withdrawn and acquired as income so far
+}
+
+// The least SOL an account keeps to pay rent and fees. Deposits, sweeps, delegations and swaps
+// out of the account refuse to take its balance below this
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SolReserve {
+    #[serde(with = "field_as_string")]
+    pub address: Pubkey,
+    pub lamports: u64,
+}
+
+// The kind of operation that a priority fee was paid for
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    EnumString,
+    IntoStaticStr,
+)]
+pub enum PriorityFeeCategory {
+    #[strum(serialize = "deposit")]
+    Deposit,
+    #[strum(serialize = "sweep")]
+    Sweep,
+    #[strum(serialize = "swap")]
+    Swap,
+    #[strum(serialize = "stake")]
+    Stake, // Splits, merges, delegations and withdrawals of stake accounts
+    #[strum(serialize = "wrap")]
+    Wrap,
+    #[strum(serialize = "other")]
+    Other,
+}
+
+// The priority fee paid by a transaction that landed, whether or not it succeeded
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PriorityFeePayment {
+    pub when: NaiveDate,
+    pub category: PriorityFeeCategory,
+    #[serde(with = "field_as_string")]
+    pub signature: Signature,
+    pub lamports: u64,
+}
+
+impl WithdrawalSchedule {
+    fn is_same_schedule(&self, other: &Self) -> bool {
+        self.exchange == other.exchange
+            && self.exchange_account == other.exchange_account
+            && self.token == other.token
+            && self.to_address == other.to_address
+    }
+}
+
+// One step of a workflow. Amounts left as `None` are the amount moved by the previous step, or
+// the whole balance for the first step
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum WorkflowStep {
+    Sweep {
+        #[serde(with = "field_as_string")]
+        from_address: Pubkey,
+        token: MaybeToken,
+        retain_amount: u64, // lamports/tokens
+        #[serde(with = "field_as_string")]
+        to_address: Pubkey,
+        authority: String, // signer path or URL
+    },
+    ExchangeDeposit {
+        exchange: Exchange,
+        exchange_account: String,
+        token: MaybeToken,
+        #[serde(with = "field_as_string")]
+        from_address: Pubkey,
+        amount: Option<u64>, // lamports/tokens
+        authority: String,   // signer path or URL
+    },
+    // Sells SOL at `price`, or at market when `None`
+    ExchangeSell {
+        exchange: Exchange,
+        exchange_account: String,
+        pair: Option<String>,
+        amount: Option<u64>, // lamports
+        price: Option<f64>,
+    },
+    // Deactivates a stake account, done once the stake is inactive
+    Deactivate {
+        #[serde(with = "field_as_string")]
+        stake_address: Pubkey,
+        authority: String, // signer path or URL
+    },
+    // Withdraws all of a deactivated stake account
+    Withdraw {
+        #[serde(with = "field_as_string")]
+        stake_address: Pubkey,
+        #[serde(with = "field_as_string")]
+        to_address: Pubkey,
+        authority: String, // signer path or URL
+    },
+    // Swaps `from_token` for `to_token` through Jupiter
+    Swap {
+        #[serde(with = "field_as_string")]
+        address: Pubkey,
+        from_token: MaybeToken,
+        to_token: MaybeToken,
+        amount: Option<u64>, // `from_token` lamports/tokens
+        slippage_bps: u64,
+        max_coingecko_value_percentage_loss: f64,
+        authority: String, // signer path or URL
+    },
+}
+
+impl fmt::Display for WorkflowStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sweep {
+                from_address,
+                token,
+                to_address,
+                ..
+            } => write!(f, "sweep {token} from {from_address} to {to_address}"),
+            Self::ExchangeDeposit {
+                exchange,
+                token,
+                from_address,
+                ..
+            } => write!(f, "deposit {token} from {from_address} into {exchange:?}"),
+            Self::ExchangeSell {
+                exchange, price, ..
+            } => match price {
+                Some(price) => write!(f, "sell SOL on {exchange:?} at ${price}"),
+                None => write!(f, "sell SOL on {exchange:?} at market"),
+            },
+            Self::Deactivate { stake_address, .. } => write!(f, "deactivate {stake_address}"),
+            Self::Withdraw {
+                stake_address,
+                to_address,
+                ..
+            } => write!(f, "withdraw {stake_address} into {to_address}"),
+            Self::Swap {
+                address,
+                from_token,
+                to_token,
+                ..
+            } => write!(f, "swap {from_token} for {to_token} in {address}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum WorkflowState {
+    // The current step is ready to be submitted
+    Ready,
+    // The current step was being submitted when the process stopped, and may have gone through
+    Submitting,
+    // The current step submitted the transaction or order `reference`, moving `amount` once it
+    // settles
+    Submitted {
+        reference: String,
+        amount: Option<u64>, // lamports/tokens
+    },
+    Failed {
+        error: String,
+    },
+    Completed,
+    Cancelled,
+}
+
+impl fmt::Display for WorkflowState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ready => write!(f, "ready"),
+            Self::Submitting => write!(f, "interrupted while submitting"),
+            Self::Submitted { reference, .. } => write!(f, "waiting for {reference}"),
+            Self::Failed { error } => write!(f, "failed: {error}"),
+            Self::Completed => write!(f, "completed"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+// A sequence of transactions and orders that is resumed where it left off after a crash or error
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub id: usize,
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub steps: Vec<WorkflowStep>,
+    pub current_step: usize,
+    pub state: WorkflowState,
+    pub attempts: usize,     // failed attempts of the current step
+    pub amount: Option<u64>, // lamports/tokens moved by the last completed step
+}
+
+impl Workflow {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            WorkflowState::Completed | WorkflowState::Cancelled
+        )
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct PendingTransfer {
     #[serde(with = "field_as_string")]
@@ -158,6 +653,66 @@ pub struct PendingSwap {
     pub to_token_price: Decimal,
 
     pub lot_selection_method: LotSelectionMethod,
+
+    pub route: Option<Vec<SwapRouteStep>>, // None for swaps not routed by Jupiter
+}
+
+// One step of the Jupiter route plan a swap was quoted with
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SwapRouteStep {
+    pub venue: String,
+    pub percent: u8, // of the swapped amount
+    pub fee_amount: u64,
+    #[serde(with = "field_as_string")]
+    pub fee_mint: Pubkey,
+}
+
+// A completed swap, kept for `jup history`. The prices are those the swap was quoted at
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SwapRecord {
+    #[serde(with = "field_as_string")]
+    pub signature: Signature,
+    pub when: NaiveDate,
+
+    #[serde(with = "field_as_string")]
+    pub address: Pubkey,
+
+    pub from_token: MaybeToken,
+    pub from_amount: u64,
+    pub from_token_price: Decimal,
+
+    pub to_token: MaybeToken,
+    pub to_amount: u64,
+    pub to_token_price: Decimal,
+
+    pub fee: u64, // lamports
+
+    pub disposed_lots: Vec<usize>, // lot numbers of the `from_token` lots swapped
+    pub acquired_lot: usize,       // lot number of the `to_token` lot
+
+    pub route: Option<Vec<SwapRouteStep>>,
+}
+
+// Number of cancelled pending records kept for `db repair-pending`
+const MAX_CANCELLED_RECORDS: usize = 100;
+
+// A pending record as it was when it was cancelled, kept so that a transaction that landed after
+// all can be restored and confirmed
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum CancelledRecord {
+    Deposit(PendingDeposit),
+    Transfer(PendingTransfer),
+    Swap(PendingSwap),
+}
+
+impl CancelledRecord {
+    pub fn signature(&self) -> Signature {
+        match self {
+            Self::Deposit(pending_deposit) => pending_deposit.transfer.signature,
+            Self::Transfer(pending_transfer) => pending_transfer.signature,
+            Self::Swap(pending_swap) => pending_swap.signature,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -176,6 +731,12 @@ pub struct OpenOrder {
 
     #[serde(default = "MaybeToken::SOL")]
     pub token: MaybeToken,
+
+    #[serde(default)]
+    pub group: Option<String>, // Name of the group of related orders, such as a sell ladder
+
+    #[serde(default)]
+    pub options: OrderOptions,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -202,6 +763,20 @@ pub enum LotAcquistionKind {
         token: MaybeToken,
         amount: Option<u64>,
     },
+    ExchangeReward {
+        exchange: Exchange,
+        reference: String,
+    },
+    MevReward {
+        epoch: Epoch, // epoch whose Jito tips were distributed
+    },
+    Vesting {
+        grant: String, // name of the `VestingGrant` that unlocked
+    },
+    StreamPayment {
+        #[serde(with = "field_as_string")]
+        stream: Pubkey,
+    },
 }
 
 impl fmt::Display for LotAcquistionKind {
@@ -222,6 +797,13 @@ impl fmt::Display for LotAcquistionKind {
             LotAcquistionKind::NotAvailable => {
                 write!(f, "other income")
             }
+            LotAcquistionKind::ExchangeReward {
+                exchange,
+                reference,
+            } => write!(f, "{exchange:?} reward {reference}"),
+            LotAcquistionKind::MevReward { epoch } => write!(f, "epoch {epoch} MEV reward"),
+            LotAcquistionKind::Vesting { grant } => write!(f, "{grant} vesting unlock"),
+            LotAcquistionKind::StreamPayment { stream } => write!(f, "stream {stream} payment"),
             LotAcquistionKind::Swap {
                 token,
                 signature,
@@ -236,7 +818,7 @@ impl fmt::Display for LotAcquistionKind {
                         token.symbol(),
                         token
                             .ui_amount(*amount)
-                            .separated_string_with_fixed_place(2),
+                            .localized_string_with_fixed_place(2),
                         signature
                     )
                 } else {
@@ -271,6 +853,60 @@ impl LotAcquistion {
         self.decimal_price
             .unwrap_or_else(|| Decimal::from_f64(self.price.unwrap_or_default()).unwrap())
     }
+
+    // Returns true if both acquisitions record the same on-chain or exchange event. Generic
+    // acquisitions never match, as they may legitimately repeat
+    pub fn is_same_event(&self, other: &Self) -> bool {
+        match (&self.kind, &other.kind) {
+            (
+                LotAcquistionKind::EpochReward { epoch, .. },
+                LotAcquistionKind::EpochReward {
+                    epoch: other_epoch, ..
+                },
+            ) => epoch == other_epoch,
+            // The slot of the claim isn't recorded, so its date and price tell claims of the same
+            // epoch apart
+            (
+                LotAcquistionKind::MevReward { epoch },
+                LotAcquistionKind::MevReward { epoch: other_epoch },
+            ) => epoch == other_epoch && self.when == other.when && self.price() == other.price(),
+            (
+                LotAcquistionKind::Transaction { signature, .. },
+                LotAcquistionKind::Transaction {
+                    signature: other_signature,
+                    ..
+                },
+            )
+            | (
+                LotAcquistionKind::Swap { signature, .. },
+                LotAcquistionKind::Swap {
+                    signature: other_signature,
+                    ..
+                },
+            ) => signature == other_signature,
+            (
+                LotAcquistionKind::Exchange {
+                    exchange, order_id, ..
+                },
+                LotAcquistionKind::Exchange {
+                    exchange: other_exchange,
+                    order_id: other_order_id,
+                    ..
+                },
+            ) => exchange == other_exchange && order_id == other_order_id,
+            (
+                LotAcquistionKind::ExchangeReward {
+                    exchange,
+                    reference,
+                },
+                LotAcquistionKind::ExchangeReward {
+                    exchange: other_exchange,
+                    reference: other_reference,
+                },
+            ) => exchange == other_exchange && reference == other_reference,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumString, IntoStaticStr)]
@@ -294,6 +930,62 @@ impl Default for LotSelectionMethod {
     }
 }
 
+// How the transaction fees paid to acquire a lot are accounted for
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumString, IntoStaticStr)]
+pub enum FeeTreatment {
+    #[strum(serialize = "expense")]
+    Expense,
+    #[strum(serialize = "capitalize")]
+    Capitalize, // Added to the basis of the acquired lot
+}
+
+pub const POSSIBLE_FEE_TREATMENT_VALUES: &[&str] = &["expense", "capitalize"];
+
+// A column of the lot listings of `account ls`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, EnumString, IntoStaticStr)]
+pub enum LotColumn {
+    #[strum(serialize = "lot")]
+    Lot,
+    #[strum(serialize = "date")]
+    Date,
+    #[strum(serialize = "amount")]
+    Amount,
+    #[strum(serialize = "price")]
+    Price, // basis per unit
+    #[strum(serialize = "basis")]
+    Basis,
+    #[strum(serialize = "value")]
+    Value,
+    #[strum(serialize = "income")]
+    Income,
+    #[strum(serialize = "gain")]
+    Gain,
+    #[strum(serialize = "days")]
+    Days, // holding period
+    #[strum(serialize = "kind")]
+    Kind, // how the lot was acquired
+}
+
+pub const POSSIBLE_LOT_COLUMN_VALUES: &[&str] = &[
+    "lot", "date", "amount", "price", "basis", "value", "income", "gain", "days", "kind",
+];
+
+pub const DEFAULT_LOT_COLUMNS: &[LotColumn] = &[
+    LotColumn::Lot,
+    LotColumn::Date,
+    LotColumn::Amount,
+    LotColumn::Price,
+    LotColumn::Value,
+    LotColumn::Income,
+    LotColumn::Gain,
+];
+
+impl Default for FeeTreatment {
+    fn default() -> Self {
+        Self::Expense
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Lot {
     pub lot_number: usize,
@@ -302,32 +994,30 @@ pub struct Lot {
 }
 
 impl Lot {
-    pub fn basis(&self, token: MaybeToken) -> f64 {
-        (self.acquisition.price() * Decimal::from_f64(token.ui_amount(self.amount)).unwrap())
-            .try_into()
-            .unwrap()
+    pub fn basis(&self, token: MaybeToken) -> Decimal {
+        self.acquisition.price() * token.ui_amount_decimal(self.amount)
     }
 
     // Figure the amount of income that the Lot incurred
-    pub fn income(&self, token: MaybeToken) -> f64 {
+    pub fn income(&self, token: MaybeToken) -> Decimal {
         match self.acquisition.kind {
             // These lots were acquired pre-tax
-            LotAcquistionKind::EpochReward { .. } | LotAcquistionKind::NotAvailable => {
-                self.basis(token)
-            }
+            LotAcquistionKind::EpochReward { .. }
+            | LotAcquistionKind::ExchangeReward { .. }
+            | LotAcquistionKind::MevReward { .. }
+            | LotAcquistionKind::Vesting { .. }
+            | LotAcquistionKind::StreamPayment { .. }
+            | LotAcquistionKind::NotAvailable => self.basis(token),
             // Assume these kinds of lots are acquired post-tax
             LotAcquistionKind::Exchange { .. }
             | LotAcquistionKind::Fiat
             | LotAcquistionKind::Swap { .. }
-            | LotAcquistionKind::Transaction { .. } => 0.,
+            | LotAcquistionKind::Transaction { .. } => Decimal::ZERO,
         }
     }
     // Figure the current cap gain/loss for the Lot
-    pub fn cap_gain(&self, token: MaybeToken, current_price: Decimal) -> f64 {
-        ((current_price - self.acquisition.price())
-            * Decimal::from_f64(token.ui_amount(self.amount)).unwrap())
-        .try_into()
-        .unwrap()
+    pub fn cap_gain(&self, token: MaybeToken, current_price: Decimal) -> Decimal {
+        (current_price - self.acquisition.price()) * token.ui_amount_decimal(self.amount)
     }
 }
 
@@ -427,7 +1117,7 @@ impl fmt::Display for LotDisposalKind {
                         token.symbol(),
                         token
                             .ui_amount(*amount)
-                            .separated_string_with_fixed_place(2),
+                            .localized_string_with_fixed_place(2),
                         signature
                     )
                 } else {
@@ -468,6 +1158,12 @@ pub struct TrackedAccount {
     pub last_update_balance: u64, // lamports/tokens
     pub lots: Vec<Lot>,
     pub no_sync: Option<bool>,
+    pub last_activity: Option<NaiveDate>, // Last balance change or sync of this account
+}
+
+// First day of the month of `when`, which identifies the month as a closable period
+pub fn period_start(when: NaiveDate) -> NaiveDate {
+    when.with_day(1).unwrap()
 }
 
 fn split_lots(
@@ -582,14 +1278,17 @@ impl TrackedAccount {
         self.assert_lot_balance();
     }
 
-    fn merge_or_add_lot(&mut self, new_lot: Lot) {
+    // Returns the lot number of the lot `new_lot` ended up in
+    fn merge_or_add_lot(&mut self, new_lot: Lot) -> usize {
         for lot in self.lots.iter_mut() {
             if lot.acquisition == new_lot.acquisition {
                 lot.amount += new_lot.amount;
-                return;
+                return lot.lot_number;
             }
         }
+        let lot_number = new_lot.lot_number;
         self.lots.push(new_lot);
+        lot_number
     }
 }
 
@@ -613,6 +1312,22 @@ pub struct TaxRate {
     pub long_term_gain: f64,
 }
 
+// An estimated tax payment that satisfies part of the income tax reserve
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TaxReservePayment {
+    pub when: NaiveDate,
+    pub amount: f64, // USD
+    pub description: String,
+}
+
+// A daily USD price fetched from CoinGecko, kept so that later runs need not fetch it again
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct HistoricalPrice {
+    pub token: MaybeToken,
+    pub when: NaiveDate,
+    pub price: Decimal,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct ValidatorCreditScore {
     #[serde(with = "field_as_string")]
@@ -620,20 +1335,452 @@ pub struct ValidatorCreditScore {
     pub credits: u64,
 }
 
-#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
-pub struct DbData {
-    next_lot_number: usize,
-    accounts: Vec<TrackedAccount>,
-    open_orders: Vec<OpenOrder>,
-    disposed_lots: Vec<DisposedLot>,
-    pending_deposits: Vec<PendingDeposit>,
-    pending_withdrawals: Vec<PendingWithdrawal>,
-    pending_transfers: Vec<PendingTransfer>,
-    pending_swaps: Vec<PendingSwap>,
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub when: NaiveDateTime,
+    pub message: String,
+}
+
+// Journal entries older than the most recent this many are dropped
+const MAX_JOURNAL_ENTRIES: usize = 50;
+
+// A record as it was before and after a journaled command, `None` where it did not exist
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RecordChange<T> {
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+impl<T: Clone + PartialEq> RecordChange<T> {
+    fn record(&self) -> &T {
+        self.before
+            .as_ref()
+            .or(self.after.as_ref())
+            .expect("record before or after the change")
+    }
+
+    fn verb(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, _) => "Added",
+            (_, None) => "Removed",
+            _ => "Changed",
+        }
+    }
+
+    // Whether the record is still as the command left it
+    fn is_current<K: PartialEq>(&self, records: &[T], record_key: impl Fn(&T) -> K) -> bool {
+        let key = record_key(self.record());
+        records.iter().find(|record| record_key(record) == key) == self.after.as_ref()
+    }
+
+    fn revert<K: PartialEq>(&self, records: &mut Vec<T>, record_key: impl Fn(&T) -> K) {
+        let key = record_key(self.record());
+        let position = records.iter().position(|record| record_key(record) == key);
+        match (position, self.before.clone()) {
+            (Some(position), Some(before)) => records[position] = before,
+            (Some(position), None) => {
+                records.remove(position);
+            }
+            (None, Some(before)) => records.push(before),
+            (None, None) => {}
+        }
+    }
+}
+
+// The changes between `before` and `after`, matching records by `record_key`
+fn record_changes<T: Clone + PartialEq, K: Eq + std::hash::Hash>(
+    before: &[T],
+    after: &[T],
+    record_key: impl Fn(&T) -> K,
+) -> Vec<RecordChange<T>> {
+    let after_by_key = after
+        .iter()
+        .map(|record| (record_key(record), record))
+        .collect::<HashMap<_, _>>();
+    let before_keys = before.iter().map(&record_key).collect::<HashSet<_>>();
+
+    let mut changes = vec![];
+    for record in before {
+        let after_record = after_by_key.get(&record_key(record)).copied();
+        if after_record != Some(record) {
+            changes.push(RecordChange {
+                before: Some(record.clone()),
+                after: after_record.cloned(),
+            });
+        }
+    }
+    for record in after {
+        if !before_keys.contains(&record_key(record)) {
+            changes.push(RecordChange {
+                before: None,
+                after: Some(record.clone()),
+            });
+        }
+    }
+    changes
+}
+
+fn account_key(account: &TrackedAccount) -> (Pubkey, MaybeToken) {
+    (account.address, account.token)
+}
+
+fn open_order_key(open_order: &OpenOrder) -> (Exchange, String) {
+    (open_order.exchange, open_order.order_id.clone())
+}
+
+fn disposed_lot_key(disposed_lot: &DisposedLot) -> usize {
+    disposed_lot.lot.lot_number
+}
+
+// A batched transaction records one pending transfer per account it moves tokens out of
+fn pending_transfer_key(pending_transfer: &PendingTransfer) -> (Signature, Pubkey, MaybeToken) {
+    (
+        pending_transfer.signature,
+        pending_transfer.from_address,
+        pending_transfer.from_token,
+    )
+}
+
+fn pending_deposit_key(pending_deposit: &PendingDeposit) -> (Signature, Pubkey, MaybeToken) {
+    pending_transfer_key(&pending_deposit.transfer)
+}
+
+fn pending_swap_key(pending_swap: &PendingSwap) -> Signature {
+    pending_swap.signature
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum JournalChange {
+    Account(RecordChange<TrackedAccount>),
+    ArchivedAccount(RecordChange<TrackedAccount>),
+    OpenOrder(RecordChange<OpenOrder>),
+    DisposedLot(RecordChange<DisposedLot>),
+    PendingTransfer(RecordChange<PendingTransfer>),
+    PendingDeposit(RecordChange<PendingDeposit>),
+    PendingSwap(RecordChange<PendingSwap>),
+}
+
+impl fmt::Display for JournalChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = match self {
+            Self::Account(change) | Self::ArchivedAccount(change) => change.verb(),
+            Self::OpenOrder(change) => change.verb(),
+            Self::DisposedLot(change) => change.verb(),
+            Self::PendingTransfer(change) => change.verb(),
+            Self::PendingDeposit(change) => change.verb(),
+            Self::PendingSwap(change) => change.verb(),
+        };
+        write!(f, "{verb} {}", self.record_name())
+    }
+}
+
+impl JournalChange {
+    fn record_name(&self) -> String {
+        match self {
+            Self::Account(change) => {
+                let account = change.record();
+                format!("account {} ({})", account.address, account.token)
+            }
+            Self::ArchivedAccount(change) => {
+                let account = change.record();
+                format!("archived account {} ({})", account.address, account.token)
+            }
+            Self::OpenOrder(change) => {
+                let open_order = change.record();
+                format!("{:?} order {}", open_order.exchange, open_order.order_id)
+            }
+            Self::DisposedLot(change) => {
+                format!("disposed lot {}", change.record().lot.lot_number)
+            }
+            Self::PendingTransfer(change) => {
+                format!("pending transfer {}", change.record().signature)
+            }
+            Self::PendingDeposit(change) => {
+                format!("pending deposit {}", change.record().transfer.signature)
+            }
+            Self::PendingSwap(change) => format!("pending swap {}", change.record().signature),
+        }
+    }
+
+    fn is_current(&self, data: &DbData) -> bool {
+        match self {
+            Self::Account(change) => change.is_current(&data.accounts, account_key),
+            Self::ArchivedAccount(change) => change.is_current(
+                data.archived_accounts.as_deref().unwrap_or_default(),
+                account_key,
+            ),
+            Self::OpenOrder(change) => change.is_current(&data.open_orders, open_order_key),
+            Self::DisposedLot(change) => change.is_current(&data.disposed_lots, disposed_lot_key),
+            Self::PendingTransfer(change) => {
+                change.is_current(&data.pending_transfers, pending_transfer_key)
+            }
+            Self::PendingDeposit(change) => {
+                change.is_current(&data.pending_deposits, pending_deposit_key)
+            }
+            Self::PendingSwap(change) => change.is_current(&data.pending_swaps, pending_swap_key),
+        }
+    }
+
+    fn revert(&self, data: &mut DbData) {
+        match self {
+            Self::Account(change) => change.revert(&mut data.accounts, account_key),
+            Self::ArchivedAccount(change) => change.revert(
+                data.archived_accounts.get_or_insert_with(Vec::new),
+                account_key,
+            ),
+            Self::OpenOrder(change) => change.revert(&mut data.open_orders, open_order_key),
+            Self::DisposedLot(change) => change.revert(&mut data.disposed_lots, disposed_lot_key),
+            Self::PendingTransfer(change) => {
+                change.revert(&mut data.pending_transfers, pending_transfer_key)
+            }
+            Self::PendingDeposit(change) => {
+                change.revert(&mut data.pending_deposits, pending_deposit_key)
+            }
+            Self::PendingSwap(change) => change.revert(&mut data.pending_swaps, pending_swap_key),
+        }
+    }
+
+    // Whether the change is an order placed or cancelled on an exchange, which reverting the
+    // record would not take back
+    pub fn is_exchange_order(&self) -> bool {
+        matches!(self, Self::OpenOrder(change) if change.before.is_none() || change.after.is_none())
+    }
+
+    // Dates of the lots and disposals that reverting the change would alter
+    fn dates(&self) -> Vec<NaiveDate> {
+        // The acquisition dates of the lots in only one of `before` and `after`
+        fn changed_lot_dates(before: &[Lot], after: &[Lot]) -> Vec<NaiveDate> {
+            before
+                .iter()
+                .filter(|lot| !after.contains(lot))
+                .chain(after.iter().filter(|lot| !before.contains(lot)))
+                .map(|lot| lot.acquisition.when)
+                .collect()
+        }
+
+        match self {
+            Self::Account(change) | Self::ArchivedAccount(change) => {
+                let lots = |account: &Option<TrackedAccount>| {
+                    account
+                        .as_ref()
+                        .map(|account| account.lots.clone())
+                        .unwrap_or_default()
+                };
+                changed_lot_dates(&lots(&change.before), &lots(&change.after))
+            }
+            Self::OpenOrder(change) => {
+                let lots = |open_order: &Option<OpenOrder>| {
+                    open_order
+                        .as_ref()
+                        .map(|open_order| open_order.lots.clone())
+                        .unwrap_or_default()
+                };
+                changed_lot_dates(&lots(&change.before), &lots(&change.after))
+            }
+            Self::DisposedLot(change) => change
+                .before
+                .iter()
+                .chain(&change.after)
+                .map(|disposed_lot| disposed_lot.when)
+                .collect(),
+            Self::PendingTransfer(_) | Self::PendingDeposit(_) | Self::PendingSwap(_) => vec![],
+        }
+    }
+}
+
+// Serializes signatures as strings, as `field_as_string` does a single signature
+mod signatures_as_strings {
+    use {
+        serde::{de, Deserialize, Deserializer, Serialize, Serializer},
+        solana_sdk::signature::Signature,
+    };
+
+    pub fn serialize<S: Serializer>(
+        signatures: &[Signature],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        signatures
+            .iter()
+            .map(|signature| signature.to_string())
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Signature>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|signature| {
+                signature
+                    .parse()
+                    .map_err(|err| de::Error::custom(format!("Parse error: {err:?}")))
+            })
+            .collect()
+    }
+}
+
+// The records changed by a command, which `Db::undo_journal_entry` reverts
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: usize,
+    pub when: NaiveDateTime,
+    pub command: String,
+    pub changes: Vec<JournalChange>,
+    // Transactions the command sent or recorded, whether or not they are still pending
+    #[serde(with = "signatures_as_strings")]
+    pub signatures: Vec<Signature>,
+    pub undone: bool,
+    // Whether the command confirmed a pending transfer, deposit or swap, whose funds have moved
+    // on chain whatever the records say
+    #[serde(default)]
+    pub confirmed: bool,
+}
+
+// The records tracked by the journal as of the start of a journaled command
+struct JournalSnapshot {
+    accounts: Vec<TrackedAccount>,
+    archived_accounts: Vec<TrackedAccount>,
+    open_orders: Vec<OpenOrder>,
+    disposed_lots: Vec<DisposedLot>,
+    pending_transfers: Vec<PendingTransfer>,
+    pending_deposits: Vec<PendingDeposit>,
+    pending_swaps: Vec<PendingSwap>,
+    pending_signatures: HashSet<Signature>,
+}
+
+impl JournalSnapshot {
+    fn new(data: &DbData) -> Self {
+        Self {
+            pending_signatures: data.pending_signatures().collect(),
+            accounts: data.accounts.clone(),
+            archived_accounts: data.archived_accounts.clone().unwrap_or_default(),
+            open_orders: data.open_orders.clone(),
+            disposed_lots: data.disposed_lots.clone(),
+            pending_transfers: data.pending_transfers.clone(),
+            pending_deposits: data.pending_deposits.clone(),
+            pending_swaps: data.pending_swaps.clone(),
+        }
+    }
+
+    fn changes(&self, data: &DbData) -> Vec<JournalChange> {
+        let mut changes = vec![];
+        changes.extend(
+            record_changes(&self.accounts, &data.accounts, account_key)
+                .into_iter()
+                .map(JournalChange::Account),
+        );
+        changes.extend(
+            record_changes(
+                &self.archived_accounts,
+                data.archived_accounts.as_deref().unwrap_or_default(),
+                account_key,
+            )
+            .into_iter()
+            .map(JournalChange::ArchivedAccount),
+        );
+        changes.extend(
+            record_changes(&self.open_orders, &data.open_orders, open_order_key)
+                .into_iter()
+                .map(JournalChange::OpenOrder),
+        );
+        changes.extend(
+            record_changes(&self.disposed_lots, &data.disposed_lots, disposed_lot_key)
+                .into_iter()
+                .map(JournalChange::DisposedLot),
+        );
+        changes.extend(
+            record_changes(
+                &self.pending_transfers,
+                &data.pending_transfers,
+                pending_transfer_key,
+            )
+            .into_iter()
+            .map(JournalChange::PendingTransfer),
+        );
+        changes.extend(
+            record_changes(
+                &self.pending_deposits,
+                &data.pending_deposits,
+                pending_deposit_key,
+            )
+            .into_iter()
+            .map(JournalChange::PendingDeposit),
+        );
+        changes.extend(
+            record_changes(&self.pending_swaps, &data.pending_swaps, pending_swap_key)
+                .into_iter()
+                .map(JournalChange::PendingSwap),
+        );
+        changes
+    }
+}
+
+// Version of the `sys db export --format json` document, bumped whenever a change to `DbData`
+// would keep older versions of `sys` from importing it
+pub const DB_EXPORT_VERSION: u32 = 1;
+
+// A `sys db export --format json` document:
+//   {
+//     "version": DB_EXPORT_VERSION,
+//     "data": {
+//       "accounts": [...],          // tracked accounts and their lots
+//       "disposed_lots": [...],
+//       "open_orders": [...],
+//       "pending_deposits": [...],  // also pending_withdrawals, pending_transfers, pending_swaps
+//       "profiles": {"<name>": {...}, ...}, // the "data" of each other profile
+//       ...                         // every other field of `DbData`, missing ones are `null`
+//     }
+//   }
+// The amounts of lots are in lamports or token base units and dates are `YYYY-MM-DD`. Exchange
+// credentials are kept apart from the records, so are not exported
+#[derive(Serialize, Deserialize)]
+struct DbExport {
+    version: u32,
+    data: serde_json::Value,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DbData {
+    next_lot_number: usize,
+    accounts: Vec<TrackedAccount>,
+    open_orders: Vec<OpenOrder>,
+    disposed_lots: Vec<DisposedLot>,
+    pending_deposits: Vec<PendingDeposit>,
+    pending_withdrawals: Vec<PendingWithdrawal>,
+    pending_transfers: Vec<PendingTransfer>,
+    pending_swaps: Vec<PendingSwap>,
     sweep_stake_account: Option<SweepStakeAccount>,
     transitory_sweep_stake_accounts: Vec<TransitorySweepStake>,
     tax_rate: Option<TaxRate>,
     validator_credit_scores: Option<HashMap<Epoch, Vec<ValidatorCreditScore>>>,
+    archived_accounts: Option<Vec<TrackedAccount>>,
+    audit_log: Option<Vec<AuditLogEntry>>,
+    income_withholding_rate: Option<f64>,
+    tax_reserve_payments: Option<Vec<TaxReservePayment>>,
+    fee_treatment: Option<FeeTreatment>,
+    exchange_transfers: Option<Vec<ExchangeTransfer>>,
+    exchange_rewards: Option<Vec<ExchangeReward>>,
+    withdrawal_schedules: Option<Vec<WithdrawalSchedule>>,
+    profiles: Option<BTreeMap<String, serde_json::Value>>, // serialized `DbData` of each profile
+    historical_prices: Option<Vec<HistoricalPrice>>,
+    cancelled_records: Option<Vec<CancelledRecord>>,
+    closed_periods: Option<Vec<NaiveDate>>, // first day of each closed month
+    workflows: Option<Vec<Workflow>>,
+    lot_columns: Option<Vec<LotColumn>>,
+    number_format: Option<NumberFormat>,
+    notification_routes: Option<Vec<NotificationRoute>>,
+    margin_tracking: Option<Vec<MarginTracking>>,
+    custom_tokens: Option<Vec<CustomToken>>,
+    token_symbols: Option<BTreeMap<String, String>>, // display symbols by token name
+    auto_snapshots: Option<usize>,
+    swap_records: Option<Vec<SwapRecord>>,
+    vesting_grants: Option<Vec<VestingGrant>>,
+    token_streams: Option<Vec<TokenStream>>,
+    sol_reserves: Option<Vec<SolReserve>>,
+    ata_rent_funder: Option<PathBuf>,
+    priority_fee_payments: Option<Vec<PriorityFeePayment>>,
+    journal: Option<Vec<JournalEntry>>,
 }
 
 impl DbData {
@@ -684,33 +1831,64 @@ impl DbData {
                 .unwrap_or_default(),
             tax_rate: None,
             validator_credit_scores: None,
+            archived_accounts: None,
+            audit_log: None,
+            income_withholding_rate: None,
+            tax_reserve_payments: None,
+            fee_treatment: None,
+            exchange_transfers: None,
+            exchange_rewards: None,
+            withdrawal_schedules: None,
+            profiles: None,
+            historical_prices: None,
+            cancelled_records: None,
+            closed_periods: None,
+            workflows: None,
+            lot_columns: None,
+            number_format: None,
+            notification_routes: None,
+            margin_tracking: None,
+            custom_tokens: None,
+            token_symbols: None,
+            auto_snapshots: None,
+            swap_records: None,
+            vesting_grants: None,
+            token_streams: None,
+            sol_reserves: None,
+            ata_rent_funder: None,
+            priority_fee_payments: None,
+            journal: None,
         }
     }
 
-    fn load(filename: &Path) -> io::Result<Self> {
-        let bytes = fs::read(filename)?;
+    // Signatures of the pending deposits, transfers and swaps
+    fn pending_signatures(&self) -> impl Iterator<Item = Signature> + '_ {
+        self.pending_transfers
+            .iter()
+            .map(|pending_transfer| pending_transfer.signature)
+            .chain(
+                self.pending_deposits
+                    .iter()
+                    .map(|pending_deposit| pending_deposit.transfer.signature),
+            )
+            .chain(
+                self.pending_swaps
+                    .iter()
+                    .map(|pending_swap| pending_swap.signature),
+            )
+    }
 
-        serde_json::from_str(std::str::from_utf8(&bytes).expect("invalid utf8")).map_err(|err| {
+    fn from_storage(data: serde_json::Map<String, serde_json::Value>) -> io::Result<Self> {
+        serde_json::from_value(serde_json::Value::Object(data)).map_err(|err| {
             io::Error::new(io::ErrorKind::Other, format!("JSON parse failed: {err:?}"))
         })
     }
 
-    fn save(&self, filename: &Path) -> io::Result<()> {
-        let bytes = serde_json::to_string_pretty(self)?.into_bytes();
-
-        let temp_filename = format!(
-            "{}.temp.{}",
-            filename.display(),
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        );
-
-        fs::write(&temp_filename, bytes)?;
-        fs::rename(temp_filename, filename)?;
-
-        Ok(())
+    fn to_storage(&self) -> io::Result<serde_json::Map<String, serde_json::Value>> {
+        match serde_json::to_value(self)? {
+            serde_json::Value::Object(data) => Ok(data),
+            _ => unreachable!(),
+        }
     }
 }
 
@@ -721,16 +1899,10 @@ impl Db {
         exchange_account: &str,
         exchange_credentials: ExchangeCredentials,
     ) -> DbResult<()> {
-        self.clear_exchange_credentials(exchange, exchange_account)?;
-
-        self.credentials_db
-            .set(
-                &format!("{exchange:?}{exchange_account}"),
-                &exchange_credentials,
-            )
-            .unwrap();
-
-        Ok(self.credentials_db.dump()?)
+        self.set_credentials_record(
+            &format!("{exchange:?}{exchange_account}"),
+            &exchange_credentials,
+        )
     }
 
     pub fn get_exchange_credentials(
@@ -738,8 +1910,7 @@ impl Db {
         exchange: Exchange,
         exchange_account: &str,
     ) -> Option<ExchangeCredentials> {
-        self.credentials_db
-            .get(&format!("{exchange:?}{exchange_account}"))
+        self.get_credentials_record(&format!("{exchange:?}{exchange_account}"))
     }
 
     pub fn clear_exchange_credentials(
@@ -747,65 +1918,306 @@ impl Db {
         exchange: Exchange,
         exchange_account: &str,
     ) -> DbResult<()> {
-        if self
-            .get_exchange_credentials(exchange, exchange_account)
-            .is_some()
-        {
-            self.credentials_db
-                .rem(&format!("{exchange:?}{exchange_account}"))
-                .ok();
-            self.credentials_db.dump()?;
-        }
-        Ok(())
+        self.clear_credentials_record(&format!("{exchange:?}{exchange_account}"))
     }
 
-    pub fn get_default_accounts_from_configured_exchanges(
+    // All configured exchange accounts, including those with a user-defined account name
+    pub fn get_accounts_from_configured_exchanges(
         &self,
     ) -> Vec<(Exchange, ExchangeCredentials, String)> {
+        const EXCHANGES: &[Exchange] = &[
+            Exchange::Binance,
+            Exchange::BinanceUs,
+            Exchange::Bitstamp,
+            Exchange::Bybit,
+            Exchange::Coinbase,
+            Exchange::Ftx,
+            Exchange::FtxUs,
+            Exchange::Gemini,
+            Exchange::Kraken,
+            Exchange::KuCoin,
+        ];
+
         self.credentials_db
             .get_all()
             .into_iter()
             .filter_map(|key| {
-                if let Ok(exchange) = key.parse() {
-                    self.get_exchange_credentials(exchange, "")
-                        .map(|exchange_credentials| (exchange, exchange_credentials, "".into()))
-                } else {
-                    None
-                }
+                let key = match self.profile {
+                    Some(ref profile) => key.strip_prefix(&format!("profile/{profile}/"))?,
+                    None if key.starts_with("profile/") => return None,
+                    None => key.as_str(),
+                };
+                // Credentials are keyed by the exchange name followed by the account name. Prefer
+                // the longest exchange name, so "BinanceUs" isn't read as Binance account "Us"
+                let (exchange, exchange_account) = EXCHANGES
+                    .iter()
+                    .filter_map(|exchange| {
+                        key.strip_prefix(&format!("{exchange:?}"))
+                            .map(|exchange_account| (*exchange, exchange_account))
+                    })
+                    .min_by_key(|(_, exchange_account)| exchange_account.len())?;
+                self.get_exchange_credentials(exchange, exchange_account)
+                    .map(|exchange_credentials| {
+                        (exchange, exchange_credentials, exchange_account.to_string())
+                    })
             })
             .collect()
     }
 
     pub fn set_metrics_config(&mut self, metrics_config: MetricsConfig) -> DbResult<()> {
-        self.clear_metrics_config()?;
+        self.set_credentials_record("influxdb", &metrics_config)
+    }
+
+    pub fn get_metrics_config(&self) -> Option<MetricsConfig> {
+        self.get_credentials_record("influxdb")
+    }
+
+    pub fn clear_metrics_config(&mut self) -> DbResult<()> {
+        self.clear_credentials_record("influxdb")
+    }
+
+    // `None` if there is no such record, or if it is encrypted and credentials are locked
+    fn get_credentials_record<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let key = self.credentials_key(key);
+        if self.credentials_encrypted() {
+            let sealed = self.credentials_db.get::<Sealed>(&key)?;
+            self.credentials_cipher.as_ref()?.open(&sealed)
+        } else {
+            self.credentials_db.get(&key)
+        }
+    }
+
+    fn set_credentials_record<T: Serialize>(&mut self, key: &str, value: &T) -> DbResult<()> {
+        let key = self.credentials_key(key);
+        if self.credentials_encrypted() {
+            let credentials_cipher = self
+                .credentials_cipher
+                .as_ref()
+                .ok_or(DbError::CredentialsLocked)?;
+            self.credentials_db
+                .set(&key, &credentials_cipher.seal(value))
+                .unwrap();
+        } else {
+            self.credentials_db.set(&key, value).unwrap();
+        }
+        Ok(self.credentials_db.dump()?)
+    }
+
+    fn clear_credentials_record(&mut self, key: &str) -> DbResult<()> {
+        let key = self.credentials_key(key);
+        if self.credentials_db.exists(&key) {
+            self.credentials_db.rem(&key).ok();
+            self.credentials_db.dump()?;
+        }
+        Ok(())
+    }
 
+    pub fn credentials_encrypted(&self) -> bool {
+        self.credentials_db.exists(ENCRYPTION_HEADER_KEY)
+    }
+
+    pub fn credentials_locked(&self) -> bool {
+        self.credentials_encrypted() && self.credentials_cipher.is_none()
+    }
+
+    pub fn unlock_credentials(&mut self, passphrase: &str) -> DbResult<()> {
+        let header = self
+            .credentials_db
+            .get::<EncryptionHeader>(ENCRYPTION_HEADER_KEY)
+            .ok_or(DbError::CredentialsNotEncrypted)?;
+        self.credentials_cipher = Some(
+            CredentialsCipher::unlock(passphrase, &header).ok_or(DbError::IncorrectPassphrase)?,
+        );
+        Ok(())
+    }
+
+    // Encrypts the exchange credentials and metrics configs of all profiles with a key derived
+    // from `passphrase`. They are then unavailable until `unlock_credentials()`
+    pub fn encrypt_credentials(&mut self, passphrase: &str) -> DbResult<()> {
+        if self.credentials_encrypted() {
+            return Err(DbError::CredentialsAlreadyEncrypted);
+        }
+
+        let (credentials_cipher, header) = CredentialsCipher::new(passphrase);
+        for key in self.credentials_db.get_all() {
+            if let Some(value) = self.credentials_db.get::<serde_json::Value>(&key) {
+                self.credentials_db
+                    .set(&key, &credentials_cipher.seal(&value))
+                    .unwrap();
+            }
+        }
         self.credentials_db
-            .set("influxdb", &metrics_config)
+            .set(ENCRYPTION_HEADER_KEY, &header)
             .unwrap();
+        self.credentials_cipher = Some(credentials_cipher);
+        Ok(self.credentials_db.dump()?)
+    }
+
+    // Stores the credentials in plaintext again, they must be unlocked
+    pub fn decrypt_credentials(&mut self) -> DbResult<()> {
+        if !self.credentials_encrypted() {
+            return Err(DbError::CredentialsNotEncrypted);
+        }
+        let credentials_cipher = self
+            .credentials_cipher
+            .as_ref()
+            .ok_or(DbError::CredentialsLocked)?;
+
+        // Decrypt every record before writing any, so a damaged record leaves all encrypted
+        let mut records = vec![];
+        for key in self.credentials_db.get_all() {
+            if key == ENCRYPTION_HEADER_KEY {
+                continue;
+            }
+            let value = self
+                .credentials_db
+                .get::<Sealed>(&key)
+                .and_then(|sealed| credentials_cipher.open::<serde_json::Value>(&sealed))
+                .ok_or_else(|| DbError::CredentialsDecryptFailed(key.clone()))?;
+            records.push((key, value));
+        }
 
+        for (key, value) in records {
+            self.credentials_db.set(&key, &value).unwrap();
+        }
+        self.credentials_db.rem(ENCRYPTION_HEADER_KEY).ok();
+        self.credentials_cipher = None;
         Ok(self.credentials_db.dump()?)
     }
 
-    pub fn get_metrics_config(&self) -> Option<MetricsConfig> {
-        self.credentials_db.get("influxdb")
+    // Exchange credentials and the metrics config of a profile are stored under a key prefixed
+    // with the profile name
+    fn credentials_key(&self, key: &str) -> String {
+        match self.profile {
+            Some(ref profile) => format!("profile/{profile}/{key}"),
+            None => key.to_string(),
+        }
     }
 
-    pub fn clear_metrics_config(&mut self) -> DbResult<()> {
-        if self.get_metrics_config().is_some() {
-            self.credentials_db.rem("influxdb").ok();
-            self.credentials_db.dump()?;
+    // Profiles keep separate records, such as those of a business alongside personal holdings, in
+    // one database. The records of each profile are stored within those of the default profile
+    pub fn profiles(&self) -> Vec<String> {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .profiles
+            .iter()
+            .flat_map(|profiles| profiles.keys().cloned())
+            .collect()
+    }
+
+    // `None` for the default profile
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn add_profile(&mut self, profile: &str) -> DbResult<()> {
+        if profile.is_empty() || profile.contains('/') {
+            return Err(DbError::InvalidProfileName(profile.into()));
+        }
+        if self.profiles().iter().any(|p| p == profile) {
+            return Err(DbError::ProfileAlreadyExists(profile.into()));
         }
+
+        let default_profile_data = self.default_profile_data.as_mut().unwrap_or(&mut self.data);
+        default_profile_data
+            .profiles
+            .get_or_insert_with(BTreeMap::default)
+            .insert(
+                profile.into(),
+                serde_json::to_value(DbData::default()).map_err(io::Error::from)?,
+            );
+        self.save()
+    }
+
+    // Switches to the records of `profile`, before any are read or modified
+    pub fn select_profile(&mut self, profile: &str) -> DbResult<()> {
+        assert!(self.profile.is_none(), "profile already selected");
+
+        let profile_data = self
+            .data
+            .profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(profile))
+            .cloned()
+            .ok_or_else(|| DbError::ProfileDoesNotExist(profile.into()))?;
+        let profile_data: DbData = serde_json::from_value(profile_data).map_err(io::Error::from)?;
+
+        // Profiles created before lot numbers were shared kept a counter of their own
+        self.data.next_lot_number = self.data.next_lot_number.max(profile_data.next_lot_number);
+        self.default_profile_data = Some(mem::replace(&mut self.data, profile_data));
+        self.profile = Some(profile.into());
         Ok(())
     }
 
+    // Adds the accounts, open orders and disposed lots of every profile to those of the default
+    // profile, for reports across all profiles. The combined records are read-only
+    pub fn combine_profiles(&mut self) -> DbResult<()> {
+        assert!(self.profile.is_none(), "profile selected");
+
+        for profile_data in self
+            .data
+            .profiles
+            .iter()
+            .flat_map(|profiles| profiles.values())
+        {
+            let profile_data: DbData =
+                serde_json::from_value(profile_data.clone()).map_err(io::Error::from)?;
+            self.data.accounts.extend(profile_data.accounts);
+            self.data.open_orders.extend(profile_data.open_orders);
+            self.data.disposed_lots.extend(profile_data.disposed_lots);
+        }
+        self.read_only = true;
+        Ok(())
+    }
+
+    // The records to store, those of the default profile with the selected profile's records
+    // written back into them
+    fn storage_data(&mut self) -> io::Result<serde_json::Map<String, serde_json::Value>> {
+        match (self.default_profile_data.as_mut(), self.profile.as_ref()) {
+            (Some(default_profile_data), Some(profile)) => {
+                default_profile_data
+                    .profiles
+                    .get_or_insert_with(BTreeMap::default)
+                    .insert(profile.clone(), serde_json::to_value(&self.data)?);
+                default_profile_data.to_storage()
+            }
+            _ => self.data.to_storage(),
+        }
+    }
+
     fn auto_save(&mut self, auto_save: bool) -> DbResult<()> {
         self.auto_save = auto_save;
         self.save()
     }
 
+    // Runs `f` with saving deferred to a single save once it succeeds. If `f` fails the records
+    // are restored as they were, so a failure part way through leaves the database untouched.
+    // `f` must not itself toggle auto-saving
+    pub fn with_single_save<T>(&mut self, f: impl FnOnce(&mut Self) -> DbResult<T>) -> DbResult<T> {
+        let data = self.data.clone();
+        self.auto_save(false)?;
+        match f(self) {
+            Ok(value) => {
+                self.auto_save(true)?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.data = data;
+                self.auto_save = true;
+                Err(err)
+            }
+        }
+    }
+
     fn save(&mut self) -> DbResult<()> {
-        if self.auto_save {
-            self.data.save(&self.data_filename)?;
+        if self.read_only {
+            return Err(DbError::ReadOnly);
+        }
+        if self.auto_save && !self.dry_run {
+            self.update_journal_entry();
+            let data = self.storage_data()?;
+            self.storage.save(data)?;
         }
         Ok(())
     }
@@ -819,6 +2231,7 @@ impl Db {
         amount: u64,
         exchange: Exchange,
         deposit_address: Pubkey,
+        memo: Option<String>,
         token: MaybeToken,
         lot_selection_method: LotSelectionMethod,
         lot_numbers: Option<HashSet<usize>>,
@@ -839,49 +2252,306 @@ impl Db {
                 to_token: token,
                 lots: from_account.extract_lots(self, amount, lot_selection_method, lot_numbers)?,
             },
+            memo,
         };
         self.data.pending_deposits.push(deposit);
         self.update_account(from_account) // `update_account` calls `save`...
     }
 
+    // A batched transaction may carry deposits of several tokens, each credited by the exchange
+    // on its own, so deposits are completed by signature and token
     fn complete_deposit(
         &mut self,
         signature: Signature,
+        token: MaybeToken,
         success: Option<NaiveDate>,
     ) -> DbResult<()> {
-        let PendingDeposit { transfer, .. } = self
+        let pending_deposit = self
             .data
             .pending_deposits
             .iter()
-            .find(|pd| pd.transfer.signature == signature)
+            .find(|pd| pd.transfer.signature == signature && pd.transfer.from_token == token)
             .ok_or(DbError::PendingDepositDoesNotExist(signature))?
             .clone();
 
+        if success.is_none() {
+            self.record_cancelled(CancelledRecord::Deposit(pending_deposit.clone()));
+        }
+        let PendingDeposit {
+            exchange,
+            amount,
+            transfer,
+            ..
+        } = pending_deposit;
+
+        if let Some(when) = success {
+            self.record_exchange_transfer(ExchangeTransfer {
+                exchange,
+                kind: ExchangeTransferKind::Deposit,
+                when,
+                token: transfer.to_token,
+                amount,
+                fee: 0,
+                reference: signature.to_string(),
+            });
+        }
+
         self.data
             .pending_deposits
-            .retain(|pd| pd.transfer.signature != signature);
+            .retain(|pd| pd.transfer.signature != signature || pd.transfer.from_token != token);
         self.complete_transfer_or_deposit(transfer, success, false) // `complete_transfer_or_deposit` calls `save`...
     }
 
-    pub fn cancel_deposit(&mut self, signature: Signature) -> DbResult<()> {
-        self.complete_deposit(signature, None)
+    pub fn cancel_deposit(&mut self, signature: Signature, token: MaybeToken) -> DbResult<()> {
+        self.complete_deposit(signature, token, None)
     }
 
-    pub fn confirm_deposit(&mut self, signature: Signature, when: NaiveDate) -> DbResult<()> {
-        self.complete_deposit(signature, Some(when))
+    pub fn confirm_deposit(
+        &mut self,
+        signature: Signature,
+        token: MaybeToken,
+        when: NaiveDate,
+    ) -> DbResult<()> {
+        self.journal_confirmation(signature);
+        self.complete_deposit(signature, token, Some(when))
     }
 
     // Careful!
-    pub fn drop_deposit(&mut self, signature: Signature) -> DbResult<()> {
+    pub fn drop_deposit(&mut self, signature: Signature, token: MaybeToken) -> DbResult<()> {
         let _ = self
             .data
             .pending_deposits
             .iter()
-            .find(|pd| pd.transfer.signature == signature)
+            .find(|pd| pd.transfer.signature == signature && pd.transfer.from_token == token)
             .ok_or(DbError::PendingDepositDoesNotExist(signature))?;
         self.data
             .pending_deposits
-            .retain(|pd| pd.transfer.signature != signature);
+            .retain(|pd| pd.transfer.signature != signature || pd.transfer.from_token != token);
+        self.save()
+    }
+
+    // The caller must call `save()`...
+    fn record_exchange_transfer(&mut self, exchange_transfer: ExchangeTransfer) {
+        self.data
+            .exchange_transfers
+            .get_or_insert_with(Vec::new)
+            .push(exchange_transfer);
+    }
+
+    pub fn exchange_transfers(&self, exchange: Exchange) -> Vec<ExchangeTransfer> {
+        self.data
+            .exchange_transfers
+            .iter()
+            .flatten()
+            .filter(|exchange_transfer| exchange_transfer.exchange == exchange)
+            .cloned()
+            .collect()
+    }
+
+    pub fn exchange_rewards(&self, exchange: Exchange) -> Vec<ExchangeReward> {
+        self.data
+            .exchange_rewards
+            .iter()
+            .flatten()
+            .filter(|exchange_reward| exchange_reward.exchange == exchange)
+            .cloned()
+            .collect()
+    }
+
+    // Acquires an exchange reward as an income lot of the exchange deposit account
+    pub fn add_exchange_reward(
+        &mut self,
+        exchange_reward: ExchangeReward,
+        deposit_address: Pubkey,
+        decimal_price: Decimal,
+    ) -> DbResult<Lot> {
+        let token = exchange_reward.token;
+        let mut deposit_account = self
+            .get_account(deposit_address, token)
+            .ok_or(DbError::AccountDoesNotExist(deposit_address, token))?;
+
+        let lot = Lot {
+            lot_number: self.next_lot_number(),
+            acquisition: LotAcquistion::new(
+                exchange_reward.when,
+                decimal_price,
+                LotAcquistionKind::ExchangeReward {
+                    exchange: exchange_reward.exchange,
+                    reference: exchange_reward.reference.clone(),
+                },
+            ),
+            amount: exchange_reward.amount,
+        };
+        deposit_account.merge_lots(vec![lot.clone()]);
+
+        self.auto_save(false)?;
+        self.data
+            .exchange_rewards
+            .get_or_insert_with(Vec::new)
+            .push(exchange_reward);
+        self.update_account(deposit_account)?;
+        self.auto_save(true)?;
+        Ok(lot)
+    }
+
+    pub fn withdrawal_schedules(&self, exchange: Exchange) -> Vec<WithdrawalSchedule> {
+        self.data
+            .withdrawal_schedules
+            .iter()
+            .flatten()
+            .filter(|withdrawal_schedule| withdrawal_schedule.exchange == exchange)
+            .cloned()
+            .collect()
+    }
+
+    // Adds or replaces the schedule of withdrawals of the same token to the same address. A
+    // schedule with no remaining amount is removed
+    pub fn set_withdrawal_schedule(
+        &mut self,
+        withdrawal_schedule: WithdrawalSchedule,
+    ) -> DbResult<()> {
+        let withdrawal_schedules = self.data.withdrawal_schedules.get_or_insert_with(Vec::new);
+        withdrawal_schedules.retain(|existing| !existing.is_same_schedule(&withdrawal_schedule));
+        if withdrawal_schedule.remaining_amount > 0 {
+            withdrawal_schedules.push(withdrawal_schedule);
+        }
+        self.save()
+    }
+
+    pub fn margin_tracking(&self) -> Vec<MarginTracking> {
+        self.data.margin_tracking.clone().unwrap_or_default()
+    }
+
+    pub fn get_margin_tracking(
+        &self,
+        exchange: Exchange,
+        exchange_account: &str,
+    ) -> Option<MarginTracking> {
+        self.data
+            .margin_tracking
+            .iter()
+            .flatten()
+            .find(|margin_tracking| {
+                margin_tracking.exchange == exchange
+                    && margin_tracking.exchange_account == exchange_account
+            })
+            .cloned()
+    }
+
+    // Adds or replaces the tracking of the margin account of the same exchange account
+    pub fn set_margin_tracking(&mut self, margin_tracking: MarginTracking) -> DbResult<()> {
+        let tracked = self.data.margin_tracking.get_or_insert_with(Vec::new);
+        tracked.retain(|existing| {
+            existing.exchange != margin_tracking.exchange
+                || existing.exchange_account != margin_tracking.exchange_account
+        });
+        tracked.push(margin_tracking);
+        self.save()
+    }
+
+    pub fn remove_margin_tracking(
+        &mut self,
+        exchange: Exchange,
+        exchange_account: &str,
+    ) -> DbResult<()> {
+        if let Some(tracked) = self.data.margin_tracking.as_mut() {
+            tracked.retain(|existing| {
+                existing.exchange != exchange || existing.exchange_account != exchange_account
+            });
+        }
+        self.save()
+    }
+
+    pub fn vesting_grants(&self) -> Vec<VestingGrant> {
+        self.data.vesting_grants.clone().unwrap_or_default()
+    }
+
+    // Adds or replaces the grant of the same name to the same account
+    pub fn set_vesting_grant(&mut self, vesting_grant: VestingGrant) -> DbResult<()> {
+        let vesting_grants = self.data.vesting_grants.get_or_insert_with(Vec::new);
+        vesting_grants.retain(|existing| {
+            existing.name != vesting_grant.name
+                || existing.address != vesting_grant.address
+                || existing.token != vesting_grant.token
+        });
+        vesting_grants.push(vesting_grant);
+        self.save()
+    }
+
+    pub fn remove_vesting_grant(
+        &mut self,
+        name: &str,
+        address: Pubkey,
+        token: MaybeToken,
+    ) -> DbResult<()> {
+        if let Some(vesting_grants) = self.data.vesting_grants.as_mut() {
+            vesting_grants.retain(|existing| {
+                existing.name != name || existing.address != address || existing.token != token
+            });
+        }
+        self.save()
+    }
+
+    pub fn token_streams(&self) -> Vec<TokenStream> {
+        self.data.token_streams.clone().unwrap_or_default()
+    }
+
+    // Adds or replaces the stream of the same address
+    pub fn set_token_stream(&mut self, token_stream: TokenStream) -> DbResult<()> {
+        let token_streams = self.data.token_streams.get_or_insert_with(Vec::new);
+        token_streams.retain(|existing| existing.address != token_stream.address);
+        token_streams.push(token_stream);
+        self.save()
+    }
+
+    pub fn sol_reserves(&self) -> Vec<SolReserve> {
+        self.data.sol_reserves.clone().unwrap_or_default()
+    }
+
+    // The minimum SOL reserve of `address`, 0 if it has none
+    pub fn sol_reserve(&self, address: Pubkey) -> u64 {
+        self.data
+            .sol_reserves
+            .iter()
+            .flatten()
+            .find(|sol_reserve| sol_reserve.address == address)
+            .map(|sol_reserve| sol_reserve.lamports)
+            .unwrap_or_default()
+    }
+
+    // Sets the minimum SOL reserve of `address`, removing it when `lamports` is 0
+    pub fn set_sol_reserve(&mut self, address: Pubkey, lamports: u64) -> DbResult<()> {
+        let sol_reserves = self.data.sol_reserves.get_or_insert_with(Vec::new);
+        sol_reserves.retain(|sol_reserve| sol_reserve.address != address);
+        if lamports > 0 {
+            sol_reserves.push(SolReserve { address, lamports });
+        }
+        self.save()
+    }
+
+    pub fn priority_fee_payments(&self) -> Vec<PriorityFeePayment> {
+        self.data.priority_fee_payments.clone().unwrap_or_default()
+    }
+
+    pub fn record_priority_fee_payment(
+        &mut self,
+        category: PriorityFeeCategory,
+        signature: Signature,
+        lamports: u64,
+    ) -> DbResult<()> {
+        let priority_fee_payments = self.data.priority_fee_payments.get_or_insert_with(Vec::new);
+        if priority_fee_payments
+            .iter()
+            .any(|payment| payment.signature == signature)
+        {
+            return Ok(());
+        }
+        priority_fee_payments.push(PriorityFeePayment {
+            when: Local::now().date_naive(),
+            category,
+            signature,
+            lamports,
+        });
         self.save()
     }
 
@@ -911,6 +2581,7 @@ impl Db {
         to_token: MaybeToken,
         to_token_price: Decimal,
         lot_selection_method: LotSelectionMethod,
+        route: Option<Vec<SwapRouteStep>>,
     ) -> DbResult<()> {
         let _ = self
             .get_account(address, from_token)
@@ -925,6 +2596,7 @@ impl Db {
             to_token,
             to_token_price,
             lot_selection_method,
+            route,
         });
         self.save()
     }
@@ -932,8 +2604,19 @@ impl Db {
     fn complete_swap(
         &mut self,
         signature: Signature,
-        success: Option<(NaiveDate, u64, u64)>,
+        success: Option<(NaiveDate, u64, u64, Decimal, u64)>,
     ) -> DbResult<()> {
+        let pending_swap = self
+            .data
+            .pending_swaps
+            .iter()
+            .find(|pd| pd.signature == signature)
+            .ok_or(DbError::PendingDepositDoesNotExist(signature))?
+            .clone();
+
+        if success.is_none() {
+            self.record_cancelled(CancelledRecord::Swap(pending_swap.clone()));
+        }
         let PendingSwap {
             signature,
             address,
@@ -942,14 +2625,9 @@ impl Db {
             to_token,
             to_token_price,
             lot_selection_method,
+            route,
             ..
-        } = self
-            .data
-            .pending_swaps
-            .iter()
-            .find(|pd| pd.signature == signature)
-            .ok_or(DbError::PendingDepositDoesNotExist(signature))?
-            .clone();
+        } = pending_swap;
 
         self.data
             .pending_swaps
@@ -963,8 +2641,9 @@ impl Db {
             .ok_or(DbError::AccountDoesNotExist(address, to_token))?;
 
         self.auto_save(false)?;
-        if let Some((when, from_amount, to_amount)) = success {
+        if let Some((when, from_amount, to_amount, capitalized_fee, fee)) = success {
             let lots = from_account.extract_lots(self, from_amount, lot_selection_method, None)?;
+            let disposed_lots = lots.iter().map(|lot| lot.lot_number).collect();
 
             let to_amount_over_from_amount = to_amount as f64 / from_amount as f64;
             for lot in lots {
@@ -985,11 +2664,18 @@ impl Db {
                 });
             }
 
-            to_account.merge_or_add_lot(Lot {
+            // A swap that yielded nothing has no acquired amount to capitalize the fee into
+            let to_token_price_with_fee = if capitalized_fee > Decimal::ZERO && to_amount > 0 {
+                to_token_price + capitalized_fee / to_token.ui_amount_decimal(to_amount)
+            } else {
+                to_token_price
+            };
+
+            let acquired_lot = to_account.merge_or_add_lot(Lot {
                 lot_number: self.next_lot_number(),
                 acquisition: LotAcquistion {
                     price: None,
-                    decimal_price: Some(to_token_price),
+                    decimal_price: Some(to_token_price_with_fee),
                     when,
                     kind: LotAcquistionKind::Swap {
                         signature,
@@ -1000,6 +2686,24 @@ impl Db {
                 amount: to_amount,
             });
             to_account.last_update_balance += to_amount;
+            self.data
+                .swap_records
+                .get_or_insert_with(Vec::new)
+                .push(SwapRecord {
+                    signature,
+                    when,
+                    address,
+                    from_token,
+                    from_amount,
+                    from_token_price,
+                    to_token,
+                    to_amount,
+                    to_token_price,
+                    fee,
+                    disposed_lots,
+                    acquired_lot,
+                    route,
+                });
             self.update_account(from_account)?;
             self.update_account(to_account)?;
         }
@@ -1010,20 +2714,32 @@ impl Db {
         self.complete_swap(signature, None)
     }
 
+    // `capitalized_fee` is the USD value of the transaction fee to add to the basis of the
+    // acquired lot, `fee` the transaction fee in lamports
     pub fn confirm_swap(
         &mut self,
         signature: Signature,
         when: NaiveDate,
         from_amount: u64,
         to_amount: u64,
+        capitalized_fee: Decimal,
+        fee: u64,
     ) -> DbResult<()> {
-        self.complete_swap(signature, Some((when, from_amount, to_amount)))
+        self.journal_confirmation(signature);
+        self.complete_swap(
+            signature,
+            Some((when, from_amount, to_amount, capitalized_fee, fee)),
+        )
     }
 
     pub fn pending_swaps(&self) -> Vec<PendingSwap> {
         self.data.pending_swaps.clone()
     }
 
+    pub fn swap_records(&self) -> Vec<SwapRecord> {
+        self.data.swap_records.clone().unwrap_or_default()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn record_withdrawal(
         &mut self,
@@ -1118,11 +2834,21 @@ impl Db {
             token,
             mut lots,
             fee,
+            amount,
             ..
         }: PendingWithdrawal,
         when: NaiveDate,
     ) -> DbResult<()> {
         self.remove_pending_withdrawal(&tag);
+        self.record_exchange_transfer(ExchangeTransfer {
+            exchange,
+            kind: ExchangeTransferKind::Withdrawal,
+            when,
+            token,
+            amount,
+            fee,
+            reference: tag.clone(),
+        });
 
         if fee > 0 {
             assert!(lots[0].amount > fee); // TODO: handle a fee that's split across multiple lots
@@ -1176,6 +2902,8 @@ impl Db {
         order_id: String,
         lots: Vec<Lot>,
         ui_amount: Option<f64>,
+        group: Option<String>,
+        options: OrderOptions,
     ) -> DbResult<()> {
         match side {
             OrderSide::Buy => {
@@ -1199,6 +2927,8 @@ impl Db {
             deposit_address: deposit_account.address,
             token: deposit_account.token,
             ui_amount,
+            group,
+            options,
         });
         self.update_account(deposit_account) // `update_account` calls `save`...
     }
@@ -1338,6 +3068,7 @@ impl Db {
         lot_selection_method: LotSelectionMethod,
         lot_numbers: Option<HashSet<usize>>,
     ) -> DbResult<Vec<DisposedLot>> {
+        self.check_period_open(when)?;
         let mut from_account = self
             .get_account(from_address, token)
             .ok_or(DbError::AccountDoesNotExist(from_address, token))?;
@@ -1398,12 +3129,15 @@ impl Db {
             .collect()
     }
 
-    pub fn add_account_no_save(&mut self, account: TrackedAccount) -> DbResult<()> {
+    pub fn add_account_no_save(&mut self, mut account: TrackedAccount) -> DbResult<()> {
         account.assert_lot_balance();
 
         if self.get_account(account.address, account.token).is_some() {
             Err(DbError::AccountAlreadyExists(account.address))
         } else {
+            account
+                .last_activity
+                .get_or_insert_with(|| Local::now().date_naive());
             self.data.accounts.push(account);
             Ok(())
         }
@@ -1414,12 +3148,19 @@ impl Db {
         self.save()
     }
 
-    pub fn update_account(&mut self, account: TrackedAccount) -> DbResult<()> {
+    pub fn update_account(&mut self, mut account: TrackedAccount) -> DbResult<()> {
         account.assert_lot_balance();
 
         let position = self
             .get_account_position(account.address, account.token)
             .ok_or(DbError::AccountDoesNotExist(account.address, account.token))?;
+
+        let existing_account = &self.data.accounts[position];
+        if existing_account.last_update_balance != account.last_update_balance
+            || existing_account.last_update_epoch != account.last_update_epoch
+        {
+            account.last_activity = Some(Local::now().date_naive());
+        }
         self.data.accounts[position] = account;
         self.save()
     }
@@ -1437,6 +3178,51 @@ impl Db {
         self.save()
     }
 
+    // Archived accounts are retained with their lots but are excluded from `get_accounts()`, and
+    // therefore from `account ls` and sync
+    pub fn archive_account(&mut self, address: Pubkey, token: MaybeToken) -> DbResult<()> {
+        let position = self
+            .get_account_position(address, token)
+            .ok_or(DbError::AccountDoesNotExist(address, token))?;
+        let mut account = self.data.accounts.remove(position);
+        let archived_accounts = self.data.archived_accounts.get_or_insert_with(Vec::new);
+
+        // An account that was archived before, and tracked again since, is merged into its
+        // archived entry so that neither set of lots is lost
+        if let Some(archived_account) = archived_accounts.iter_mut().find(|tracked_account| {
+            tracked_account.address == address && tracked_account.token == token
+        }) {
+            account.last_update_balance += archived_account.last_update_balance;
+            account.lots.append(&mut archived_account.lots);
+            account.lots.sort_by_key(|lot| lot.acquisition.when);
+            *archived_account = account;
+        } else {
+            archived_accounts.push(account);
+        }
+        self.save()
+    }
+
+    pub fn restore_account(&mut self, address: Pubkey, token: MaybeToken) -> DbResult<()> {
+        if self.get_account(address, token).is_some() {
+            return Err(DbError::AccountAlreadyExists(address));
+        }
+
+        let archived_accounts = self.data.archived_accounts.get_or_insert_with(Vec::new);
+        let position = archived_accounts
+            .iter()
+            .position(|tracked_account| {
+                tracked_account.address == address && tracked_account.token == token
+            })
+            .ok_or(DbError::AccountDoesNotExist(address, token))?;
+        let account = archived_accounts.remove(position);
+        self.data.accounts.push(account);
+        self.save()
+    }
+
+    pub fn get_archived_accounts(&self) -> Vec<TrackedAccount> {
+        self.data.archived_accounts.clone().unwrap_or_default()
+    }
+
     fn get_account_position(&self, address: Pubkey, token: MaybeToken) -> Option<usize> {
         for (position, tracked_account) in self.data.accounts.iter().enumerate() {
             if tracked_account.address == address && tracked_account.token == token {
@@ -1472,9 +3258,12 @@ impl Db {
     }
 
     // The caller must call `save()`...
+    // Lot numbers are unique across all profiles, so the counter is kept with the records of the
+    // default profile
     pub fn next_lot_number(&mut self) -> usize {
-        let next_lot_number = self.data.next_lot_number;
-        self.data.next_lot_number += 1;
+        let data = self.default_profile_data.as_mut().unwrap_or(&mut self.data);
+        let next_lot_number = data.next_lot_number;
+        data.next_lot_number += 1;
         next_lot_number
     }
 
@@ -1496,6 +3285,17 @@ impl Db {
         self.save()
     }
 
+    // Keypair of the account that tops up authorities short of the rent of a new associated
+    // token account
+    pub fn get_ata_rent_funder(&self) -> Option<PathBuf> {
+        self.data.ata_rent_funder.clone()
+    }
+
+    pub fn set_ata_rent_funder(&mut self, ata_rent_funder: Option<PathBuf>) -> DbResult<()> {
+        self.data.ata_rent_funder = ata_rent_funder;
+        self.save()
+    }
+
     pub fn get_transitory_sweep_stake_addresses(&self) -> HashSet<Pubkey> {
         self.data
             .transitory_sweep_stake_accounts
@@ -1526,43 +3326,217 @@ impl Db {
             last_update_epoch: current_epoch,
             lots: vec![],
             no_sync: None,
+            last_activity: None,
         })
     }
 
-    pub fn remove_transitory_sweep_stake_address(&mut self, address: Pubkey) -> DbResult<()> {
-        let token = MaybeToken::SOL();
-        let _ = self.remove_account_no_save(address, token);
+    pub fn remove_transitory_sweep_stake_address(&mut self, address: Pubkey) -> DbResult<()> {
+        let token = MaybeToken::SOL();
+        let _ = self.remove_account_no_save(address, token);
+
+        let mut transitory_sweep_stake_addresses = self.get_transitory_sweep_stake_addresses();
+
+        if !transitory_sweep_stake_addresses.contains(&address) {
+            Err(DbError::AccountDoesNotExist(address, token))
+        } else {
+            transitory_sweep_stake_addresses.remove(&address);
+            self.set_transitory_sweep_stake_addresses(transitory_sweep_stake_addresses)
+        }
+    }
+
+    fn set_transitory_sweep_stake_addresses<T>(
+        &mut self,
+        transitory_sweep_stake_addresses: T,
+    ) -> DbResult<()>
+    where
+        T: IntoIterator<Item = Pubkey>,
+    {
+        self.data.transitory_sweep_stake_accounts = transitory_sweep_stake_addresses
+            .into_iter()
+            .map(|address| TransitorySweepStake { address })
+            .collect();
+        self.save()
+    }
+
+    pub fn get_tax_rate(&self) -> Option<&TaxRate> {
+        self.data.tax_rate.as_ref()
+    }
+
+    pub fn set_tax_rate(&mut self, tax_rate: TaxRate) -> DbResult<()> {
+        self.data.tax_rate = Some(tax_rate);
+        self.save()
+    }
+
+    pub fn get_fee_treatment(&self) -> FeeTreatment {
+        self.data.fee_treatment.unwrap_or_default()
+    }
+
+    pub fn set_fee_treatment(&mut self, fee_treatment: FeeTreatment) -> DbResult<()> {
+        self.data.fee_treatment = Some(fee_treatment);
+        self.save()
+    }
+
+    // The preferred layout of lot listings. Shared by all profiles, so kept with the records of
+    // the default profile
+    pub fn get_lot_columns(&self) -> Vec<LotColumn> {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .lot_columns
+            .clone()
+            .unwrap_or_else(|| DEFAULT_LOT_COLUMNS.to_vec())
+    }
+
+    // `None` restores the default layout
+    pub fn set_lot_columns(&mut self, lot_columns: Option<Vec<LotColumn>>) -> DbResult<()> {
+        self.default_profile_data
+            .as_mut()
+            .unwrap_or(&mut self.data)
+            .lot_columns = lot_columns;
+        self.save()
+    }
+
+    // How numbers and USD values are written in console output. Shared by all profiles
+    pub fn get_number_format(&self) -> NumberFormat {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .number_format
+            .clone()
+            .unwrap_or_default()
+    }
+
+    // `None` restores US-style formatting
+    pub fn set_number_format(&mut self, number_format: Option<NumberFormat>) -> DbResult<()> {
+        self.default_profile_data
+            .as_mut()
+            .unwrap_or(&mut self.data)
+            .number_format = number_format;
+        self.save()
+    }
+
+    // How many snapshots of the database taken before each command that modifies it are kept, `0`
+    // if none are taken. Shared by all profiles
+    pub fn get_auto_snapshots(&self) -> usize {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .auto_snapshots
+            .unwrap_or_default()
+    }
+
+    pub fn set_auto_snapshots(&mut self, keep: usize) -> DbResult<()> {
+        self.default_profile_data
+            .as_mut()
+            .unwrap_or(&mut self.data)
+            .auto_snapshots = Some(keep).filter(|keep| *keep > 0);
+        self.save()
+    }
+
+    // Which destinations each notification is sent to. Shared by all profiles, like the
+    // destinations themselves
+    pub fn get_notification_routes(&self) -> Vec<NotificationRoute> {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .notification_routes
+            .clone()
+            .unwrap_or_default()
+    }
+
+    pub fn set_notification_routes(&mut self, routes: Vec<NotificationRoute>) -> DbResult<()> {
+        self.default_profile_data
+            .as_mut()
+            .unwrap_or(&mut self.data)
+            .notification_routes = Some(routes).filter(|routes| !routes.is_empty());
+        self.save()
+    }
+
+    // Tokens registered by mint address. Shared by all profiles
+    pub fn custom_tokens(&self) -> Vec<CustomToken> {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .custom_tokens
+            .clone()
+            .unwrap_or_default()
+    }
 
-        let mut transitory_sweep_stake_addresses = self.get_transitory_sweep_stake_addresses();
+    pub fn add_custom_token(&mut self, custom_token: CustomToken) -> DbResult<()> {
+        let custom_tokens = self
+            .default_profile_data
+            .as_mut()
+            .unwrap_or(&mut self.data)
+            .custom_tokens
+            .get_or_insert_with(Vec::new);
+        if custom_tokens
+            .iter()
+            .any(|t| t.mint == custom_token.mint || t.name == custom_token.name)
+        {
+            return Err(DbError::TokenAlreadyExists(custom_token.name));
+        }
+        custom_tokens.push(custom_token);
+        self.save()
+    }
 
-        if !transitory_sweep_stake_addresses.contains(&address) {
-            Err(DbError::AccountDoesNotExist(address, token))
-        } else {
-            transitory_sweep_stake_addresses.remove(&address);
-            self.set_transitory_sweep_stake_addresses(transitory_sweep_stake_addresses)
+    // Symbols that amounts of a token are written with in place of its default symbol. Shared by
+    // all profiles
+    pub fn get_token_symbols(&self) -> HashMap<MaybeToken, String> {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .token_symbols
+            .iter()
+            .flatten()
+            .filter_map(|(name, symbol)| {
+                let token = if name == "SOL" {
+                    MaybeToken::SOL()
+                } else {
+                    Token::from_str(name).ok()?.into()
+                };
+                Some((token, symbol.clone()))
+            })
+            .collect()
+    }
+
+    // `None` restores the default symbol of `token`
+    pub fn set_token_symbol(&mut self, token: MaybeToken, symbol: Option<String>) -> DbResult<()> {
+        let token_symbols = self
+            .default_profile_data
+            .as_mut()
+            .unwrap_or(&mut self.data)
+            .token_symbols
+            .get_or_insert_with(BTreeMap::new);
+        match symbol {
+            Some(symbol) => {
+                token_symbols.insert(token.name().to_string(), symbol);
+            }
+            None => {
+                token_symbols.remove(token.name());
+            }
         }
+        self.save()
     }
 
-    fn set_transitory_sweep_stake_addresses<T>(
-        &mut self,
-        transitory_sweep_stake_addresses: T,
-    ) -> DbResult<()>
-    where
-        T: IntoIterator<Item = Pubkey>,
-    {
-        self.data.transitory_sweep_stake_accounts = transitory_sweep_stake_addresses
-            .into_iter()
-            .map(|address| TransitorySweepStake { address })
-            .collect();
+    pub fn get_income_withholding_rate(&self) -> Option<f64> {
+        self.data.income_withholding_rate
+    }
+
+    pub fn set_income_withholding_rate(&mut self, rate: Option<f64>) -> DbResult<()> {
+        self.data.income_withholding_rate = rate;
         self.save()
     }
 
-    pub fn get_tax_rate(&self) -> Option<&TaxRate> {
-        self.data.tax_rate.as_ref()
+    pub fn tax_reserve_payments(&self) -> Vec<TaxReservePayment> {
+        self.data.tax_reserve_payments.clone().unwrap_or_default()
     }
 
-    pub fn set_tax_rate(&mut self, tax_rate: TaxRate) -> DbResult<()> {
-        self.data.tax_rate = Some(tax_rate);
+    pub fn record_tax_reserve_payment(&mut self, payment: TaxReservePayment) -> DbResult<()> {
+        self.check_period_open(payment.when)?;
+        self.data
+            .tax_reserve_payments
+            .get_or_insert_with(Vec::new)
+            .push(payment);
         self.save()
     }
 
@@ -1610,6 +3584,63 @@ impl Db {
         self.save()
     }
 
+    // Prices are shared by all profiles, so are kept with the records of the default profile
+    fn historical_prices_data(&mut self) -> &mut Vec<HistoricalPrice> {
+        self.default_profile_data
+            .as_mut()
+            .unwrap_or(&mut self.data)
+            .historical_prices
+            .get_or_insert_with(Vec::new)
+    }
+
+    pub fn historical_prices(&self) -> Vec<HistoricalPrice> {
+        self.default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .historical_prices
+            .clone()
+            .unwrap_or_default()
+    }
+
+    // Adds prices not already recorded. The price cache is best effort, so nothing is recorded
+    // in a read-only database
+    pub fn record_historical_prices(
+        &mut self,
+        prices: impl IntoIterator<Item = HistoricalPrice>,
+    ) -> DbResult<()> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        let historical_prices = self.historical_prices_data();
+        let recorded = historical_prices
+            .iter()
+            .map(|historical_price| (historical_price.token, historical_price.when))
+            .collect::<HashSet<_>>();
+        let len = historical_prices.len();
+        historical_prices.extend(prices.into_iter().filter(|historical_price| {
+            !recorded.contains(&(historical_price.token, historical_price.when))
+        }));
+        if historical_prices.len() == len {
+            return Ok(());
+        }
+        historical_prices
+            .sort_by_key(|historical_price| (historical_price.token, historical_price.when));
+        self.save()
+    }
+
+    // Removes recorded prices from before `before`, or all of them. Returns the number removed
+    pub fn prune_historical_prices(&mut self, before: Option<NaiveDate>) -> DbResult<usize> {
+        let historical_prices = self.historical_prices_data();
+        let len = historical_prices.len();
+        historical_prices.retain(|historical_price| {
+            before.is_some_and(|before| historical_price.when >= before)
+        });
+        let pruned = len - historical_prices.len();
+        self.save()?;
+        Ok(pruned)
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn record_transfer(
         &mut self,
@@ -1705,23 +3736,29 @@ impl Db {
         self.auto_save(true)
     }
 
+    // Completes every transfer recorded with `signature`, a batched transaction carries several
     fn complete_transfer(
         &mut self,
         signature: Signature,
         success: Option<NaiveDate>,
     ) -> DbResult<()> {
-        let mut pending_transfers = self.pending_transfers();
-
-        let transfer = pending_transfers
-            .iter()
-            .find(|pt| pt.signature == signature)
-            .ok_or(DbError::PendingTransferDoesNotExist(signature))?
-            .clone();
-
-        pending_transfers.retain(|pt| pt.signature != signature);
+        let (transfers, pending_transfers): (Vec<_>, Vec<_>) = self
+            .pending_transfers()
+            .into_iter()
+            .partition(|pt| pt.signature == signature);
+        if transfers.is_empty() {
+            return Err(DbError::PendingTransferDoesNotExist(signature));
+        }
         self.data.pending_transfers = pending_transfers;
 
-        self.complete_transfer_or_deposit(transfer, success, true) // `complete_transfer_or_deposit` calls `save`...
+        for transfer in transfers {
+            if success.is_none() {
+                self.record_cancelled(CancelledRecord::Transfer(transfer.clone()));
+            }
+
+            self.complete_transfer_or_deposit(transfer, success, true)?; // `complete_transfer_or_deposit` calls `save`...
+        }
+        Ok(())
     }
 
     pub fn cancel_transfer(&mut self, signature: Signature) -> DbResult<()> {
@@ -1729,6 +3766,7 @@ impl Db {
     }
 
     pub fn confirm_transfer(&mut self, signature: Signature, when: NaiveDate) -> DbResult<()> {
+        self.journal_confirmation(signature);
         self.complete_transfer(signature, Some(when))
     }
 
@@ -1736,6 +3774,141 @@ impl Db {
         self.data.pending_transfers.clone()
     }
 
+    // Points the pending deposits, transfers or swap recorded with `signature` at the transaction
+    // that replaced it
+    pub fn replace_pending_signature(
+        &mut self,
+        signature: Signature,
+        new_signature: Signature,
+        last_valid_block_height: u64,
+    ) -> DbResult<()> {
+        let mut replaced = false;
+        for pending in self
+            .data
+            .pending_deposits
+            .iter_mut()
+            .map(|pd| {
+                (
+                    &mut pd.transfer.signature,
+                    &mut pd.transfer.last_valid_block_height,
+                )
+            })
+            .chain(
+                self.data
+                    .pending_transfers
+                    .iter_mut()
+                    .map(|pt| (&mut pt.signature, &mut pt.last_valid_block_height)),
+            )
+            .chain(
+                self.data
+                    .pending_swaps
+                    .iter_mut()
+                    .map(|ps| (&mut ps.signature, &mut ps.last_valid_block_height)),
+            )
+            .filter(|(pending_signature, _)| **pending_signature == signature)
+        {
+            *pending.0 = new_signature;
+            *pending.1 = last_valid_block_height;
+            replaced = true;
+        }
+        if !replaced {
+            return Err(DbError::PendingTransferDoesNotExist(signature));
+        }
+        self.save()
+    }
+
+    // The caller must call `save()`...
+    fn record_cancelled(&mut self, cancelled_record: CancelledRecord) {
+        let cancelled_records = self.data.cancelled_records.get_or_insert_with(Vec::new);
+        cancelled_records.push(cancelled_record);
+        if cancelled_records.len() > MAX_CANCELLED_RECORDS {
+            cancelled_records.drain(..cancelled_records.len() - MAX_CANCELLED_RECORDS);
+        }
+    }
+
+    pub fn cancelled_records(&self) -> Vec<CancelledRecord> {
+        self.data.cancelled_records.clone().unwrap_or_default()
+    }
+
+    // Returns the cancelled records of `signature` to pending, a batched transaction has several.
+    // The lots of a cancelled transfer or deposit were merged back into the sending account, so
+    // they are taken out of it again; this fails if they have since been disposed of or moved
+    pub fn restore_cancelled_record(&mut self, signature: Signature) -> DbResult<()> {
+        let cancelled_records = self
+            .cancelled_records()
+            .into_iter()
+            .filter(|cancelled_record| cancelled_record.signature() == signature)
+            .collect::<Vec<_>>();
+        if cancelled_records.is_empty() {
+            return Err(DbError::CancelledRecordDoesNotExist(signature));
+        }
+
+        let mut from_accounts: Vec<TrackedAccount> = vec![];
+        for cancelled_record in &cancelled_records {
+            let transfer = match cancelled_record {
+                CancelledRecord::Deposit(pending_deposit) => &pending_deposit.transfer,
+                CancelledRecord::Transfer(pending_transfer) => pending_transfer,
+                CancelledRecord::Swap(_) => continue,
+            };
+
+            let index = match from_accounts.iter().position(|from_account| {
+                from_account.address == transfer.from_address
+                    && from_account.token == transfer.from_token
+            }) {
+                Some(index) => index,
+                None => {
+                    from_accounts.push(
+                        self.get_account(transfer.from_address, transfer.from_token)
+                            .ok_or(DbError::AccountDoesNotExist(
+                                transfer.from_address,
+                                transfer.from_token,
+                            ))?,
+                    );
+                    from_accounts.len() - 1
+                }
+            };
+            let from_account = &mut from_accounts[index];
+
+            for lot in &transfer.lots {
+                let existing_lot = from_account
+                    .lots
+                    .iter_mut()
+                    .find(|l| l.acquisition == lot.acquisition && l.amount >= lot.amount)
+                    .ok_or_else(|| {
+                        DbError::CancelledRecordRestoreFailed(format!(
+                            "lot {} is no longer in {} ({})",
+                            lot.lot_number, transfer.from_address, transfer.from_token
+                        ))
+                    })?;
+                existing_lot.amount -= lot.amount;
+                from_account.last_update_balance -= lot.amount;
+            }
+            from_account.lots.retain(|lot| lot.amount > 0);
+            from_account.assert_lot_balance();
+        }
+
+        self.auto_save(false)?;
+        for cancelled_record in cancelled_records {
+            match cancelled_record {
+                CancelledRecord::Deposit(pending_deposit) => {
+                    self.data.pending_deposits.push(pending_deposit)
+                }
+                CancelledRecord::Transfer(pending_transfer) => {
+                    self.data.pending_transfers.push(pending_transfer)
+                }
+                CancelledRecord::Swap(pending_swap) => self.data.pending_swaps.push(pending_swap),
+            }
+        }
+        if let Some(cancelled_records) = self.data.cancelled_records.as_mut() {
+            cancelled_records.retain(|cancelled_record| cancelled_record.signature() != signature);
+        }
+        self.append_audit_log(format!("Restored cancelled record to pending: {signature}"));
+        for from_account in from_accounts {
+            self.update_account(from_account)?;
+        }
+        self.auto_save(true)
+    }
+
     pub fn disposed_lots(&self) -> Vec<DisposedLot> {
         let mut disposed_lots = self.data.disposed_lots.clone();
         disposed_lots.sort_by_key(|lot| lot.when);
@@ -1797,6 +3970,9 @@ impl Db {
                 )));
             }
 
+            self.check_period_open(disposed_lot.when)?;
+            self.check_period_open(lot2.acquisition.when)?;
+
             if lot2.acquisition.when >= disposed_lot.when {
                 return Err(DbError::LotSwapFailed(format!(
                     "Lot {} was acquired after disposal of lot {}",
@@ -1804,82 +3980,510 @@ impl Db {
                 )));
             }
 
-            let mut disposed_lots = self
-                .disposed_lots()
-                .into_iter()
-                .filter(|dl| disposed_lot.lot.lot_number != dl.lot.lot_number)
-                .collect::<Vec<_>>();
+            let mut disposed_lots = self
+                .disposed_lots()
+                .into_iter()
+                .filter(|dl| disposed_lot.lot.lot_number != dl.lot.lot_number)
+                .collect::<Vec<_>>();
+
+            std::mem::swap(&mut disposed_lot.lot.lot_number, &mut lot2.lot_number);
+
+            #[allow(clippy::comparison_chain)]
+            if disposed_lot.lot.amount < lot2.amount {
+                let mut lot2_split = lot2.clone();
+                lot2_split.amount -= disposed_lot.lot.amount;
+                account2.lots.push(lot2_split); // TODO: merge_or_add
+
+                lot2.lot_number = self.next_lot_number();
+                lot2.amount = disposed_lot.lot.amount;
+            } else if lot2.amount < disposed_lot.lot.amount {
+                let mut disposed_lot_split = disposed_lot.clone();
+                disposed_lot_split.lot.lot_number = self.next_lot_number();
+                disposed_lot_split.lot.amount -= lot2.amount;
+
+                disposed_lot.lot.amount = lot2.amount;
+            }
+
+            account2.merge_or_add_lot(disposed_lot.lot);
+            disposed_lot.lot = lot2;
+            disposed_lots.push(disposed_lot);
+
+            self.data.disposed_lots = disposed_lots;
+            self.update_account(account2)?;
+        } else {
+            if tracked_accounts.len() != 2 {
+                return Err(DbError::LotSwapFailed("Unknown lot".into()));
+            }
+
+            let (mut lot1, mut account1) = tracked_accounts.pop().unwrap();
+            let (mut lot2, mut account2) = tracked_accounts.pop().unwrap();
+
+            self.check_period_open(lot1.acquisition.when)?;
+            self.check_period_open(lot2.acquisition.when)?;
+
+            if account2.token != account1.token
+                && [account1.token, account2.token]
+                    .iter()
+                    .filter(|t| t.is_sol() || t.token() == Some(Token::wSOL))
+                    .count()
+                    != 2
+            {
+                return Err(DbError::LotSwapFailed(format!(
+                    "Token mismatch ({} != {})",
+                    account2.token, account1.token
+                )));
+            }
+
+            std::mem::swap(&mut lot1.lot_number, &mut lot2.lot_number);
+
+            #[allow(clippy::comparison_chain)]
+            if lot1.amount < lot2.amount {
+                let mut lot2_split = lot2.clone();
+                lot2_split.amount -= lot1.amount;
+                account2.merge_or_add_lot(lot2_split);
+
+                lot2.lot_number = self.next_lot_number();
+                lot2.amount = lot1.amount;
+            } else if lot2.amount < lot1.amount {
+                let mut lot1_split = lot1.clone();
+                lot1_split.amount -= lot2.amount;
+                account1.merge_or_add_lot(lot1_split);
+
+                lot1.lot_number = self.next_lot_number();
+                lot1.amount = lot2.amount;
+            }
+
+            account1.merge_or_add_lot(lot2);
+            account2.merge_or_add_lot(lot1);
+            self.update_account(account1)?;
+            self.update_account(account2)?;
+        }
+
+        self.auto_save(true)
+    }
+
+    // The caller must call `save()`...
+    fn append_audit_log(&mut self, message: String) {
+        self.data
+            .audit_log
+            .get_or_insert_with(Vec::new)
+            .push(AuditLogEntry {
+                when: Local::now().naive_local(),
+                message,
+            });
+    }
+
+    pub fn audit_log(&self) -> Vec<AuditLogEntry> {
+        self.data.audit_log.clone().unwrap_or_default()
+    }
+
+    // Journals the changes `command` makes to accounts, disposed lots and pending records from
+    // here on. The journal entry is updated whenever the database is saved, so changes saved
+    // before the command fails are journaled as well
+    pub fn begin_journal_entry(&mut self, command: String) {
+        let id = self
+            .data
+            .journal
+            .iter()
+            .flatten()
+            .map(|journal_entry| journal_entry.id + 1)
+            .max()
+            .unwrap_or(1);
+        self.journal_entry = Some((
+            JournalEntry {
+                id,
+                when: Local::now().naive_local(),
+                command,
+                changes: vec![],
+                signatures: vec![],
+                undone: false,
+                confirmed: false,
+            },
+            JournalSnapshot::new(&self.data),
+        ));
+    }
+
+    fn update_journal_entry(&mut self) {
+        let Some((journal_entry, journal_snapshot)) = self.journal_entry.as_mut() else {
+            return;
+        };
+
+        journal_entry.changes = journal_snapshot.changes(&self.data);
+        for signature in self.data.pending_signatures() {
+            if !journal_snapshot.pending_signatures.contains(&signature)
+                && !journal_entry.signatures.contains(&signature)
+            {
+                journal_entry.signatures.push(signature);
+            }
+        }
+
+        let journal = self.data.journal.get_or_insert_with(Vec::new);
+        journal.retain(|entry| entry.id != journal_entry.id);
+        if !journal_entry.changes.is_empty() || !journal_entry.signatures.is_empty() {
+            journal.push(journal_entry.clone());
+            if journal.len() > MAX_JOURNAL_ENTRIES {
+                journal.drain(..journal.len() - MAX_JOURNAL_ENTRIES);
+            }
+        }
+    }
+
+    pub fn journal(&self) -> Vec<JournalEntry> {
+        self.data.journal.clone().unwrap_or_default()
+    }
+
+    // Journals `signature` as sent by the current command, including a transaction that is not
+    // recorded as a pending transfer, deposit or swap
+    pub fn record_sent_transaction(&mut self, signature: Signature) -> DbResult<()> {
+        let Some((journal_entry, _)) = self.journal_entry.as_mut() else {
+            return Ok(());
+        };
+        if !journal_entry.signatures.contains(&signature) {
+            journal_entry.signatures.push(signature);
+        }
+        self.save()
+    }
+
+    fn journal_confirmation(&mut self, signature: Signature) {
+        if let Some((journal_entry, _)) = self.journal_entry.as_mut() {
+            if !journal_entry.signatures.contains(&signature) {
+                journal_entry.signatures.push(signature);
+            }
+            journal_entry.confirmed = true;
+        }
+    }
+
+    // Signatures of the pending deposits, transfers and swaps
+    pub fn pending_signatures(&self) -> HashSet<Signature> {
+        self.data.pending_signatures().collect()
+    }
+
+    // Reverts the changes of journal entry `id`. Nothing is reverted if the entry confirmed a
+    // transaction or placed or cancelled exchange orders, or if any of the records it changed has
+    // been changed again since or is dated in a closed period. Whether the transactions the entry
+    // sent have landed is left to the caller
+    pub fn undo_journal_entry(&mut self, id: usize) -> DbResult<()> {
+        let journal_entry = self
+            .data
+            .journal
+            .iter()
+            .flatten()
+            .find(|journal_entry| journal_entry.id == id && !journal_entry.undone)
+            .cloned()
+            .ok_or_else(|| DbError::UndoFailed(format!("No journal entry to undo: {id}")))?;
+
+        if journal_entry.confirmed {
+            return Err(DbError::UndoFailed(format!(
+                "journal entry {id} confirmed a transaction, which remains so on chain"
+            )));
+        }
+
+        for change in &journal_entry.changes {
+            if change.is_exchange_order() {
+                return Err(DbError::UndoFailed(format!(
+                    "journal entry {id} placed or cancelled {}, which remains so on the exchange",
+                    change.record_name()
+                )));
+            }
+            if !change.is_current(&self.data) {
+                return Err(DbError::UndoFailed(format!(
+                    "{} of journal entry {id} has changed since",
+                    change.record_name()
+                )));
+            }
+            for when in change.dates() {
+                self.check_period_open(when)?;
+            }
+        }
+
+        for change in journal_entry.changes.iter().rev() {
+            change.revert(&mut self.data);
+        }
+        for entry in self.data.journal.iter_mut().flatten() {
+            if entry.id == id {
+                entry.undone = true;
+            }
+        }
+        self.append_audit_log(format!(
+            "Undid journal entry {id}: {}",
+            journal_entry.command
+        ));
+        self.save()
+    }
+
+    // Closed periods, as the first day of each closed month
+    pub fn closed_periods(&self) -> Vec<NaiveDate> {
+        self.data.closed_periods.clone().unwrap_or_default()
+    }
 
-            std::mem::swap(&mut disposed_lot.lot.lot_number, &mut lot2.lot_number);
+    // Fails if `when` falls in a closed month
+    fn check_period_open(&self, when: NaiveDate) -> DbResult<()> {
+        let period = period_start(when);
+        if self
+            .data
+            .closed_periods
+            .iter()
+            .flatten()
+            .any(|p| *p == period)
+        {
+            return Err(DbError::PeriodClosed(period.format("%Y-%m").to_string()));
+        }
+        Ok(())
+    }
 
-            #[allow(clippy::comparison_chain)]
-            if disposed_lot.lot.amount < lot2.amount {
-                let mut lot2_split = lot2.clone();
-                lot2_split.amount -= disposed_lot.lot.amount;
-                account2.lots.push(lot2_split); // TODO: merge_or_add
+    // Locks the records dated in the month of `period` against modification
+    pub fn close_period(&mut self, period: NaiveDate) -> DbResult<()> {
+        let period = period_start(period);
+        let closed_periods = self.data.closed_periods.get_or_insert_with(Vec::new);
+        if closed_periods.contains(&period) {
+            return Err(DbError::PeriodAlreadyClosed(
+                period.format("%Y-%m").to_string(),
+            ));
+        }
+        closed_periods.push(period);
+        closed_periods.sort();
+        self.append_audit_log(format!("Closed period {}", period.format("%Y-%m")));
+        self.save()
+    }
 
-                lot2.lot_number = self.next_lot_number();
-                lot2.amount = disposed_lot.lot.amount;
-            } else if lot2.amount < disposed_lot.lot.amount {
-                let mut disposed_lot_split = disposed_lot.clone();
-                disposed_lot_split.lot.lot_number = self.next_lot_number();
-                disposed_lot_split.lot.amount -= lot2.amount;
+    pub fn reopen_period(&mut self, period: NaiveDate, reason: String) -> DbResult<()> {
+        let period = period_start(period);
+        let closed_periods = self.data.closed_periods.get_or_insert_with(Vec::new);
+        if !closed_periods.contains(&period) {
+            return Err(DbError::PeriodNotClosed(period.format("%Y-%m").to_string()));
+        }
+        closed_periods.retain(|p| *p != period);
+        self.append_audit_log(format!(
+            "Reopened period {}: {reason}",
+            period.format("%Y-%m")
+        ));
+        self.save()
+    }
 
-                disposed_lot.lot.amount = lot2.amount;
-            }
+    pub fn workflows(&self) -> Vec<Workflow> {
+        self.data.workflows.clone().unwrap_or_default()
+    }
 
-            account2.merge_or_add_lot(disposed_lot.lot);
-            disposed_lot.lot = lot2;
-            disposed_lots.push(disposed_lot);
+    pub fn get_workflow(&self, id: usize) -> Option<Workflow> {
+        self.data
+            .workflows
+            .iter()
+            .flatten()
+            .find(|workflow| workflow.id == id)
+            .cloned()
+    }
 
-            self.data.disposed_lots = disposed_lots;
-            self.update_account(account2)?;
-        } else {
-            if tracked_accounts.len() != 2 {
-                return Err(DbError::LotSwapFailed("Unknown lot".into()));
-            }
+    pub fn add_workflow(&mut self, name: &str, steps: Vec<WorkflowStep>) -> DbResult<Workflow> {
+        let workflows = self.data.workflows.get_or_insert_with(Vec::new);
+        let now = Utc::now();
+        let workflow = Workflow {
+            id: workflows
+                .iter()
+                .map(|workflow| workflow.id + 1)
+                .max()
+                .unwrap_or(1),
+            name: name.to_string(),
+            created: now,
+            updated: now,
+            steps,
+            current_step: 0,
+            state: WorkflowState::Ready,
+            attempts: 0,
+            amount: None,
+        };
+        workflows.push(workflow.clone());
+        self.append_audit_log(format!("Started workflow {}: {name}", workflow.id));
+        self.save()?;
+        Ok(workflow)
+    }
 
-            let (mut lot1, mut account1) = tracked_accounts.pop().unwrap();
-            let (mut lot2, mut account2) = tracked_accounts.pop().unwrap();
+    // Saves the progress of a workflow. Saved before and after each submission, so that a
+    // resumed workflow never submits a step twice
+    pub fn update_workflow(&mut self, mut workflow: Workflow) -> DbResult<()> {
+        let existing_workflow = self
+            .data
+            .workflows
+            .iter_mut()
+            .flatten()
+            .find(|existing_workflow| existing_workflow.id == workflow.id)
+            .ok_or(DbError::WorkflowDoesNotExist(workflow.id))?;
+        workflow.updated = Utc::now();
+        *existing_workflow = workflow;
+        self.save()
+    }
 
-            if account2.token != account1.token
-                && [account1.token, account2.token]
-                    .iter()
-                    .filter(|t| t.is_sol() || t.token() == Some(Token::wSOL))
-                    .count()
-                    != 2
-            {
-                return Err(DbError::LotSwapFailed(format!(
-                    "Token mismatch ({} != {})",
-                    account2.token, account1.token
+    pub fn cancel_workflow(&mut self, id: usize) -> DbResult<()> {
+        let mut workflow = self
+            .get_workflow(id)
+            .ok_or(DbError::WorkflowDoesNotExist(id))?;
+        if workflow.is_finished() {
+            return Err(DbError::WorkflowFinished(id));
+        }
+        workflow.state = WorkflowState::Cancelled;
+        self.append_audit_log(format!("Cancelled workflow {id}"));
+        self.update_workflow(workflow)
+    }
+
+    // Corrects the sale details of a disposed lot. Realized gains are always derived from the
+    // disposed lots, so no other records need to be updated
+    pub fn edit_disposed_lot(
+        &mut self,
+        lot_number: usize,
+        when: Option<NaiveDate>,
+        price: Option<Decimal>,
+        description: Option<String>,
+    ) -> DbResult<(DisposedLot, DisposedLot)> {
+        if let Some(disposed_lot) = self
+            .data
+            .disposed_lots
+            .iter()
+            .find(|disposed_lot| disposed_lot.lot.lot_number == lot_number)
+        {
+            self.check_period_open(disposed_lot.when)?;
+        }
+        if let Some(when) = when {
+            self.check_period_open(when)?;
+        }
+
+        let disposed_lot = self
+            .data
+            .disposed_lots
+            .iter_mut()
+            .find(|disposed_lot| disposed_lot.lot.lot_number == lot_number)
+            .ok_or_else(|| DbError::LotEditFailed(format!("Unknown disposed lot: {lot_number}")))?;
+        let original_disposed_lot = disposed_lot.clone();
+
+        if let Some(when) = when {
+            if when < disposed_lot.lot.acquisition.when {
+                return Err(DbError::LotEditFailed(format!(
+                    "Lot {lot_number} cannot be disposed on {when}, before it was acquired on {}",
+                    disposed_lot.lot.acquisition.when
                 )));
             }
+        }
+        if let Some(price) = price {
+            if price.is_sign_negative() {
+                return Err(DbError::LotEditFailed(format!(
+                    "Disposal price of lot {lot_number} cannot be negative: ${price}"
+                )));
+            }
+        }
+        if let Some(description) = description {
+            match disposed_lot.kind {
+                LotDisposalKind::Other { .. } => {
+                    disposed_lot.kind = LotDisposalKind::Other { description };
+                }
+                _ => {
+                    return Err(DbError::LotEditFailed(format!(
+                        "Description of lot {lot_number} cannot be edited: {}",
+                        disposed_lot.kind
+                    )));
+                }
+            }
+        }
+        if let Some(when) = when {
+            disposed_lot.when = when;
+        }
+        if let Some(price) = price {
+            disposed_lot.price = None;
+            disposed_lot.decimal_price = Some(price);
+        }
+        let edited_disposed_lot = disposed_lot.clone();
 
-            std::mem::swap(&mut lot1.lot_number, &mut lot2.lot_number);
-
-            #[allow(clippy::comparison_chain)]
-            if lot1.amount < lot2.amount {
-                let mut lot2_split = lot2.clone();
-                lot2_split.amount -= lot1.amount;
-                account2.merge_or_add_lot(lot2_split);
+        let mut changes = vec![];
+        if original_disposed_lot.when != edited_disposed_lot.when {
+            changes.push(format!(
+                "when {} -> {}",
+                original_disposed_lot.when, edited_disposed_lot.when
+            ));
+        }
+        if original_disposed_lot.price() != edited_disposed_lot.price() {
+            changes.push(format!(
+                "price ${} -> ${}",
+                original_disposed_lot.price(),
+                edited_disposed_lot.price()
+            ));
+        }
+        if original_disposed_lot.kind != edited_disposed_lot.kind {
+            changes.push(format!(
+                "description \"{}\" -> \"{}\"",
+                original_disposed_lot.kind, edited_disposed_lot.kind
+            ));
+        }
+        if !changes.is_empty() {
+            self.append_audit_log(format!(
+                "Edited disposed lot {lot_number}: {}",
+                changes.join(", ")
+            ));
+        }
 
-                lot2.lot_number = self.next_lot_number();
-                lot2.amount = lot1.amount;
-            } else if lot2.amount < lot1.amount {
-                let mut lot1_split = lot1.clone();
-                lot1_split.amount -= lot2.amount;
-                account1.merge_or_add_lot(lot1_split);
+        self.save()?;
+        Ok((original_disposed_lot, edited_disposed_lot))
+    }
 
-                lot1.lot_number = self.next_lot_number();
-                lot1.amount = lot2.amount;
+    // Lots within the same account that were acquired by the same event. Lots split by transfers
+    // share an acquisition across accounts, so only lots within a single account are considered.
+    // A single event may also legitimately yield several lots in one account, such as the rewards
+    // of stake accounts that were later merged, so only lots with the same acquisition and amount
+    // are duplicates
+    pub fn duplicate_lots(&self) -> Vec<(TrackedAccount, Vec<Lot>)> {
+        let mut duplicate_lots = vec![];
+        for account in self.get_accounts() {
+            let mut groups: Vec<Vec<Lot>> = vec![];
+            for lot in &account.lots {
+                match groups.iter_mut().find(|group| {
+                    group[0].amount == lot.amount
+                        && group[0].acquisition == lot.acquisition
+                        && group[0].acquisition.is_same_event(&lot.acquisition)
+                }) {
+                    Some(group) => group.push(lot.clone()),
+                    None => groups.push(vec![lot.clone()]),
+                }
             }
+            for mut group in groups.into_iter().filter(|group| group.len() > 1) {
+                group.sort_by_key(|lot| lot.lot_number);
+                duplicate_lots.push((account.clone(), group));
+            }
+        }
+        duplicate_lots
+    }
 
-            account1.merge_or_add_lot(lot2);
-            account2.merge_or_add_lot(lot1);
-            self.update_account(account1)?;
-            self.update_account(account2)?;
+    // Signatures that have been recorded more than once as the same pending operation. A batched
+    // transaction is recorded once per account it moves tokens out of, which is not a duplicate
+    pub fn duplicate_pending_signatures(&self) -> Vec<Signature> {
+        let pending_keys = self
+            .data
+            .pending_transfers
+            .iter()
+            .map(pending_transfer_key)
+            .chain(self.data.pending_deposits.iter().map(pending_deposit_key))
+            .chain(
+                self.data
+                    .pending_swaps
+                    .iter()
+                    .map(|ps| (ps.signature, ps.address, ps.from_token)),
+            );
+
+        let mut seen = HashSet::new();
+        let mut duplicates = vec![];
+        for pending_key in pending_keys {
+            let signature = pending_key.0;
+            if !seen.insert(pending_key) && !duplicates.contains(&signature) {
+                duplicates.push(signature);
+            }
         }
+        duplicates
+    }
 
+    pub fn remove_duplicate_lot(
+        &mut self,
+        lot_number: usize,
+        original_lot_number: usize,
+    ) -> DbResult<()> {
+        self.auto_save(false)?;
+        self.delete_lot(lot_number)?;
+        self.append_audit_log(format!(
+            "Deleted lot {lot_number} as a duplicate of lot {original_lot_number}"
+        ));
         self.auto_save(true)
     }
 
@@ -1901,6 +4505,7 @@ impl Db {
             .find(|lot| lot.lot_number == lot_number)
             .cloned()
             .unwrap();
+        self.check_period_open(lot.acquisition.when)?;
 
         account.remove_lot(lot_number);
         account.last_update_balance -= lot.amount;
@@ -1952,6 +4557,7 @@ impl Db {
             .find(|lot| lot.lot_number == lot_number)
             .cloned()
             .unwrap();
+        self.check_period_open(lot.acquisition.when)?;
 
         from_account.remove_lot(lot_number);
         to_account.last_update_balance += lot.amount;
@@ -1964,6 +4570,108 @@ impl Db {
         self.auto_save(true)
     }
 
+    // Serialized financial records, suitable for `restore_data()`. Exchange credentials are not
+    // included
+    pub fn backup_data(&self) -> DbResult<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(&self.data).map_err(io::Error::from)?)
+    }
+
+    // Replaces all financial records with those of a prior `backup_data()`. The current records are
+    // first copied next to the database so that an unwanted restore can be undone by hand
+    pub fn restore_data(&mut self, bytes: &[u8]) -> DbResult<()> {
+        let data: DbData = serde_json::from_slice(bytes)
+            .map_err(|err| DbError::RestoreFailed(format!("JSON parse failed: {err}")))?;
+        self.replace_data(data)
+    }
+
+    // The records of every profile as a `DbExport`, pretty printed with sorted keys so that
+    // exports of the same records are identical and diff cleanly
+    pub fn export_json(&self) -> DbResult<String> {
+        let mut data = self
+            .default_profile_data
+            .as_ref()
+            .unwrap_or(&self.data)
+            .clone();
+        if let Some(profile) = &self.profile {
+            data.profiles.get_or_insert_with(BTreeMap::default).insert(
+                profile.clone(),
+                serde_json::to_value(&self.data).map_err(io::Error::from)?,
+            );
+        }
+
+        // `serde_json::Value` objects sort their keys, including those of `HashMap` fields
+        let db_export = DbExport {
+            version: DB_EXPORT_VERSION,
+            data: serde_json::to_value(&data).map_err(io::Error::from)?,
+        };
+        let json = serde_json::to_string_pretty(&db_export).map_err(io::Error::from)?;
+        Ok(json + "\n")
+    }
+
+    // Replaces the records of every profile with those of an `export_json()`, copying the current
+    // records aside like `restore_data()`
+    pub fn import_json(&mut self, bytes: &[u8]) -> DbResult<()> {
+        if self.profile.is_some() {
+            return Err(DbError::ImportFailed(
+                "A JSON export replaces every profile, import it without selecting a profile"
+                    .into(),
+            ));
+        }
+        let db_export: DbExport = serde_json::from_slice(bytes)
+            .map_err(|err| DbError::ImportFailed(format!("JSON parse failed: {err}")))?;
+        if db_export.version != DB_EXPORT_VERSION {
+            return Err(DbError::ImportFailed(format!(
+                "Unsupported export version {}, expected {DB_EXPORT_VERSION}",
+                db_export.version
+            )));
+        }
+        let data = serde_json::from_value(db_export.data)
+            .map_err(|err| DbError::ImportFailed(format!("Invalid records: {err}")))?;
+        self.replace_data(data)
+    }
+
+    fn replace_data(&mut self, data: DbData) -> DbResult<()> {
+        let previous_data_filename = self.db_path.join(format!(
+            "data.json.pre-restore.{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        ));
+        fs::write(&previous_data_filename, self.backup_data()?)?;
+        println!(
+            "Previous database saved to {}",
+            previous_data_filename.display()
+        );
+
+        self.data = data;
+        self.save()
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn backend(&self) -> DbBackend {
+        self.storage.backend()
+    }
+
+    // Converts the database to `backend`. The records of the previous backend are moved aside
+    pub fn migrate(&mut self, backend: DbBackend) -> DbResult<Option<PathBuf>> {
+        if self.read_only {
+            return Err(DbError::ReadOnly);
+        }
+        if backend == self.storage.backend() {
+            return Ok(None);
+        }
+
+        let mut storage = db_storage::open(&self.db_path, backend)?;
+        storage.save(self.storage_data()?)?;
+        let retired_filename = self.storage.retire()?;
+        self.storage = storage;
+        Ok(retired_filename)
+    }
+
     pub fn import_db(&mut self, other_db: Self) -> DbResult<()> {
         if other_db.pending_deposits(None).len()
             + other_db.pending_swaps().len()
@@ -1998,3 +4706,274 @@ impl Db {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_test_db(name: &str) -> Db {
+        let db_path = std::env::temp_dir().join(format!("sys-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&db_path);
+        new(&db_path, None).unwrap()
+    }
+
+    #[test]
+    fn duplicate_lots_of_same_event() {
+        let mut db = new_test_db("duplicate-lots");
+        let when = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let epoch_reward = |db: &mut Db, amount| Lot {
+            lot_number: db.next_lot_number(),
+            acquisition: LotAcquistion::new(
+                when,
+                Decimal::from(100),
+                LotAcquistionKind::EpochReward {
+                    epoch: 500,
+                    slot: 216_000_000,
+                },
+            ),
+            amount,
+        };
+
+        // The rewards of two stake accounts that were merged, and a copy of the first
+        let lot = epoch_reward(&mut db, 1_000);
+        let merged_lot = epoch_reward(&mut db, 2_000);
+        let duplicate_lot = Lot {
+            lot_number: db.next_lot_number(),
+            ..lot.clone()
+        };
+        db.add_account(TrackedAccount {
+            address: Pubkey::new_unique(),
+            token: MaybeToken::SOL(),
+            description: String::default(),
+            last_update_epoch: 500,
+            last_update_balance: 4_000,
+            lots: vec![lot.clone(), merged_lot, duplicate_lot.clone()],
+            no_sync: None,
+            last_activity: None,
+        })
+        .unwrap();
+
+        let duplicate_lots = db.duplicate_lots();
+        assert_eq!(duplicate_lots.len(), 1);
+        assert_eq!(duplicate_lots[0].1, vec![lot, duplicate_lot]);
+    }
+
+    #[test]
+    fn profiles_share_lot_numbers() {
+        let mut db = new_test_db("profiles-share-lot-numbers");
+        assert_eq!(db.next_lot_number(), 0);
+        assert_eq!(db.next_lot_number(), 1);
+
+        db.add_profile("other").unwrap();
+        db.select_profile("other").unwrap();
+        assert_eq!(db.next_lot_number(), 2);
+        assert_eq!(db.default_profile_data.as_ref().unwrap().next_lot_number, 3);
+    }
+
+    #[test]
+    fn batched_transfers_share_signature() {
+        let mut db = new_test_db("batched-transfers");
+        let when = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let token = MaybeToken::SOL();
+        let tracked_account = |db: &mut Db, amount| {
+            let address = Pubkey::new_unique();
+            let lot = Lot {
+                lot_number: db.next_lot_number(),
+                acquisition: LotAcquistion::new(
+                    when,
+                    Decimal::from(100),
+                    LotAcquistionKind::NotAvailable,
+                ),
+                amount,
+            };
+            db.add_account(TrackedAccount {
+                address,
+                token,
+                description: String::default(),
+                last_update_epoch: 500,
+                last_update_balance: amount,
+                lots: if amount > 0 { vec![lot] } else { vec![] },
+                no_sync: None,
+                last_activity: None,
+            })
+            .unwrap();
+            address
+        };
+        let from_address1 = tracked_account(&mut db, 1_000);
+        let from_address2 = tracked_account(&mut db, 2_000);
+        let to_address = tracked_account(&mut db, 0);
+
+        // One transaction moving both accounts into `to_address`
+        let signature = Signature::new_unique();
+        for from_address in [from_address1, from_address2] {
+            db.record_transfer(
+                signature,
+                u64::MAX,
+                None,
+                from_address,
+                token,
+                to_address,
+                token,
+                LotSelectionMethod::default(),
+                None,
+            )
+            .unwrap();
+        }
+        assert!(db.duplicate_pending_signatures().is_empty());
+
+        db.confirm_transfer(signature, when).unwrap();
+        assert!(db.pending_transfers().is_empty());
+        assert_eq!(
+            db.get_account(to_address, token)
+                .unwrap()
+                .last_update_balance,
+            3_000
+        );
+    }
+
+    #[test]
+    fn confirmed_transfer_is_not_undone() {
+        let mut db = new_test_db("confirmed-transfer-undo");
+        let when = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let token = MaybeToken::SOL();
+        let (from_address, to_address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        for (address, amount) in [(from_address, 1_000), (to_address, 0)] {
+            let lot = Lot {
+                lot_number: db.next_lot_number(),
+                acquisition: LotAcquistion::new(
+                    when,
+                    Decimal::from(100),
+                    LotAcquistionKind::NotAvailable,
+                ),
+                amount,
+            };
+            db.add_account(TrackedAccount {
+                address,
+                token,
+                description: String::default(),
+                last_update_epoch: 500,
+                last_update_balance: amount,
+                lots: if amount > 0 { vec![lot] } else { vec![] },
+                no_sync: None,
+                last_activity: None,
+            })
+            .unwrap();
+        }
+
+        db.begin_journal_entry("account sweep".into());
+        let signature = Signature::new_unique();
+        db.record_transfer(
+            signature,
+            u64::MAX,
+            None,
+            from_address,
+            token,
+            to_address,
+            token,
+            LotSelectionMethod::default(),
+            None,
+        )
+        .unwrap();
+        db.confirm_transfer(signature, when).unwrap();
+
+        let journal_entry = db.journal().pop().unwrap();
+        assert!(journal_entry.confirmed);
+        assert_eq!(journal_entry.signatures, vec![signature]);
+        assert!(db.undo_journal_entry(journal_entry.id).is_err());
+        assert_eq!(
+            db.get_account(to_address, token)
+                .unwrap()
+                .last_update_balance,
+            1_000
+        );
+    }
+
+    #[test]
+    fn edit_disposed_lot_rejects_invalid_edits() {
+        let mut db = new_test_db("edit-disposed-lot");
+        let when = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let token = MaybeToken::SOL();
+        let address = Pubkey::new_unique();
+        let lot = Lot {
+            lot_number: db.next_lot_number(),
+            acquisition: LotAcquistion::new(
+                when,
+                Decimal::from(100),
+                LotAcquistionKind::NotAvailable,
+            ),
+            amount: 1_000,
+        };
+        db.add_account(TrackedAccount {
+            address,
+            token,
+            description: String::default(),
+            last_update_epoch: 500,
+            last_update_balance: 1_000,
+            lots: vec![lot.clone()],
+            no_sync: None,
+            last_activity: None,
+        })
+        .unwrap();
+        db.record_disposal(
+            address,
+            token,
+            1_000,
+            "Gift".into(),
+            when.succ_opt().unwrap(),
+            Decimal::from(200),
+            LotSelectionMethod::default(),
+            None,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            db.edit_disposed_lot(lot.lot_number, when.pred_opt(), None, None),
+            Err(DbError::LotEditFailed(_))
+        ));
+        assert!(matches!(
+            db.edit_disposed_lot(lot.lot_number, None, Some(Decimal::from(-1)), None),
+            Err(DbError::LotEditFailed(_))
+        ));
+        let (_, edited_disposed_lot) = db
+            .edit_disposed_lot(lot.lot_number, Some(when), Some(Decimal::ZERO), None)
+            .unwrap();
+        assert_eq!(edited_disposed_lot.when, when);
+        assert_eq!(edited_disposed_lot.price(), Decimal::ZERO);
+    }
+
+    #[test]
+    fn archive_account_merges_archived_entry() {
+        let mut db = new_test_db("archive-account");
+        let when = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let token = MaybeToken::SOL();
+        let address = Pubkey::new_unique();
+        for amount in [1_000, 2_000] {
+            let lot = Lot {
+                lot_number: db.next_lot_number(),
+                acquisition: LotAcquistion::new(
+                    when,
+                    Decimal::from(100),
+                    LotAcquistionKind::NotAvailable,
+                ),
+                amount,
+            };
+            db.add_account(TrackedAccount {
+                address,
+                token,
+                description: String::default(),
+                last_update_epoch: 500,
+                last_update_balance: amount,
+                lots: vec![lot],
+                no_sync: None,
+                last_activity: None,
+            })
+            .unwrap();
+            db.archive_account(address, token).unwrap();
+        }
+
+        let archived_accounts = db.get_archived_accounts();
+        assert_eq!(archived_accounts.len(), 1);
+        assert_eq!(archived_accounts[0].last_update_balance, 3_000);
+        assert_eq!(archived_accounts[0].lots.len(), 2);
+    }
+}
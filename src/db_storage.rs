@@ -0,0 +1,220 @@
+use {
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    strum::{EnumString, IntoStaticStr},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, IntoStaticStr)]
+pub enum DbBackend {
+    #[strum(serialize = "json")]
+    Json,
+    #[strum(serialize = "sqlite")]
+    Sqlite,
+}
+
+pub const POSSIBLE_DB_BACKEND_VALUES: &[&str] = &["json", "sqlite"];
+
+// Where the financial records of a database are persisted. Records are exchanged as a JSON
+// object with one entry per top-level field
+pub trait Storage {
+    fn backend(&self) -> DbBackend;
+
+    // `None` if nothing has been stored yet
+    fn load(&mut self) -> io::Result<Option<serde_json::Map<String, serde_json::Value>>>;
+
+    fn save(&mut self, data: serde_json::Map<String, serde_json::Value>) -> io::Result<()>;
+
+    // Moves the stored records aside, such as after they have been migrated to another backend
+    fn retire(&mut self) -> io::Result<Option<PathBuf>>;
+}
+
+fn retired_filename(filename: &Path) -> PathBuf {
+    PathBuf::from(format!(
+        "{}.pre-migrate.{}",
+        filename.display(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    ))
+}
+
+pub struct JsonStorage {
+    filename: PathBuf,
+}
+
+impl JsonStorage {
+    pub fn new(db_path: &Path) -> Self {
+        Self {
+            filename: db_path.join("data.json"),
+        }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn backend(&self) -> DbBackend {
+        DbBackend::Json
+    }
+
+    fn load(&mut self) -> io::Result<Option<serde_json::Map<String, serde_json::Value>>> {
+        if !self.filename.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(&self.filename)?;
+
+        serde_json::from_str(std::str::from_utf8(&bytes).expect("invalid utf8"))
+            .map(Some)
+            .map_err(|err| {
+                io::Error::new(io::ErrorKind::Other, format!("JSON parse failed: {err:?}"))
+            })
+    }
+
+    fn save(&mut self, data: serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let bytes = serde_json::to_string_pretty(&data)?.into_bytes();
+
+        let temp_filename = format!(
+            "{}.temp.{}",
+            self.filename.display(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+
+        fs::write(&temp_filename, bytes)?;
+        fs::rename(temp_filename, &self.filename)?;
+
+        Ok(())
+    }
+
+    fn retire(&mut self) -> io::Result<Option<PathBuf>> {
+        if !self.filename.exists() {
+            return Ok(None);
+        }
+        let retired_filename = retired_filename(&self.filename);
+        fs::rename(&self.filename, &retired_filename)?;
+        Ok(Some(retired_filename))
+    }
+}
+
+// Stores each top-level field in its own row, so that a save only rewrites the fields that
+// changed instead of the entire database
+#[cfg(feature = "sqlite")]
+pub struct SqliteStorage {
+    filename: PathBuf,
+    connection: rusqlite::Connection,
+    saved: std::collections::HashMap<String, String>,
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_error(err: rusqlite::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("SQLite: {err}"))
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStorage {
+    pub fn new(db_path: &Path) -> io::Result<Self> {
+        let filename = db_path.join("data.sqlite");
+        let connection = rusqlite::Connection::open(&filename).map_err(sqlite_error)?;
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS data (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+                [],
+            )
+            .map_err(sqlite_error)?;
+        Ok(Self {
+            filename,
+            connection,
+            saved: Default::default(),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Storage for SqliteStorage {
+    fn backend(&self) -> DbBackend {
+        DbBackend::Sqlite
+    }
+
+    fn load(&mut self) -> io::Result<Option<serde_json::Map<String, serde_json::Value>>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT key, value FROM data")
+            .map_err(sqlite_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sqlite_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sqlite_error)?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let mut data = serde_json::Map::new();
+        for (key, value) in rows {
+            data.insert(key.clone(), serde_json::from_str(&value)?);
+            self.saved.insert(key, value);
+        }
+        Ok(Some(data))
+    }
+
+    fn save(&mut self, data: serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let transaction = self.connection.transaction().map_err(sqlite_error)?;
+        let mut saved = self.saved.clone();
+        for (key, value) in data {
+            let value = serde_json::to_string(&value)?;
+            if saved.get(&key) != Some(&value) {
+                transaction
+                    .execute(
+                        "INSERT OR REPLACE INTO data (key, value) VALUES (?1, ?2)",
+                        [&key, &value],
+                    )
+                    .map_err(sqlite_error)?;
+                saved.insert(key, value);
+            }
+        }
+        transaction.commit().map_err(sqlite_error)?;
+        self.saved = saved;
+        Ok(())
+    }
+
+    fn retire(&mut self) -> io::Result<Option<PathBuf>> {
+        let retired_filename = retired_filename(&self.filename);
+        self.connection
+            .execute("VACUUM INTO ?1", [retired_filename.display().to_string()])
+            .map_err(sqlite_error)?;
+        // The storage is dropped once retired, so the open connection no longer matters
+        fs::remove_file(&self.filename)?;
+        self.saved.clear();
+        Ok(Some(retired_filename))
+    }
+}
+
+// The backend of the records already in `db_path`, if any
+pub fn detect(db_path: &Path) -> Option<DbBackend> {
+    if db_path.join("data.sqlite").exists() {
+        Some(DbBackend::Sqlite)
+    } else if db_path.join("data.json").exists() {
+        Some(DbBackend::Json)
+    } else {
+        None
+    }
+}
+
+pub fn open(db_path: &Path, backend: DbBackend) -> io::Result<Box<dyn Storage>> {
+    match backend {
+        DbBackend::Json => Ok(Box::new(JsonStorage::new(db_path))),
+        #[cfg(feature = "sqlite")]
+        DbBackend::Sqlite => Ok(Box::new(SqliteStorage::new(db_path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        DbBackend::Sqlite => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SQLite support not enabled, rebuild with `--features sqlite`",
+        )),
+    }
+}
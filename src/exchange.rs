@@ -1,21 +1,36 @@
 use {
-    crate::{binance_exchange, coinbase_exchange, kraken_exchange, token::MaybeToken},
+    crate::{
+        binance_exchange, bitstamp_exchange, bybit_exchange, coinbase_exchange, gemini_exchange,
+        kraken_exchange, kucoin_exchange,
+        operation_stats::{self, Operation},
+        token::{MaybeToken, Token},
+    },
     async_trait::async_trait,
     chrono::NaiveDate,
     serde::{Deserialize, Serialize},
     solana_sdk::pubkey::Pubkey,
-    std::{collections::HashMap, str::FromStr},
+    std::{
+        collections::HashMap,
+        future::Future,
+        str::FromStr,
+        time::{Instant, SystemTime, UNIX_EPOCH},
+    },
     thiserror::Error,
+    tokio::sync::mpsc::UnboundedReceiver,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Exchange {
     Binance,
     BinanceUs,
+    Bitstamp,
+    Bybit,
     Coinbase,
     Ftx,
     FtxUs,
+    Gemini,
     Kraken,
+    KuCoin,
 }
 
 impl std::fmt::Display for Exchange {
@@ -26,6 +41,20 @@ impl std::fmt::Display for Exchange {
 
 pub const USD_COINS: &[&str] = &["USD", "USDC", "USDT", "BUSD", "ZUSD"];
 
+// Exchange APIs return most numeric fields as strings
+pub fn parse_f64(field: &str, value: &str) -> Result<f64, Box<dyn std::error::Error>> {
+    value
+        .parse::<f64>()
+        .map_err(|err| format!("Invalid `{field}` field: {value}: {err}").into())
+}
+
+pub fn timestamp_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
+
 impl FromStr for Exchange {
     type Err = ParseExchangeError;
 
@@ -33,8 +62,12 @@ impl FromStr for Exchange {
         match s {
             "Binance" | "binance" => Ok(Exchange::Binance),
             "BinanceUs" | "binanceus" => Ok(Exchange::BinanceUs),
+            "Bitstamp" | "bitstamp" => Ok(Exchange::Bitstamp),
+            "Bybit" | "bybit" => Ok(Exchange::Bybit),
             "Coinbase" | "coinbase" => Ok(Exchange::Coinbase),
+            "Gemini" | "gemini" => Ok(Exchange::Gemini),
             "Kraken" | "kraken" => Ok(Exchange::Kraken),
+            "KuCoin" | "kucoin" => Ok(Exchange::KuCoin),
             _ => Err(ParseExchangeError::InvalidExchange),
         }
     }
@@ -51,21 +84,27 @@ pub struct ExchangeCredentials {
     pub api_key: String,
     pub secret: String,
     pub subaccount: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>, // required by exchanges that sign requests with one, like KuCoin
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct ExchangeBalance {
     pub available: f64,
     pub total: f64,
+    pub earn: f64, // portion of `total` held in savings/earn products, not `available` for trading
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DepositInfo {
     pub tx_id: String,
-    pub amount: f64, // TODO: rename to `ui_amount`
+    pub coin: String,         // as named by the exchange
+    pub amount: f64,          // TODO: rename to `ui_amount`
+    pub memo: Option<String>, // included by deposits to an address shared between accounts
+    pub when: Option<i64>,    // unix timestamp of the deposit, when reported by the exchange
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WithdrawalInfo {
     pub address: Pubkey,
     pub token: MaybeToken,
@@ -76,7 +115,83 @@ pub struct WithdrawalInfo {
     pub tx_id: Option<String>,
 }
 
+// Interest or staking rewards credited by the exchange
+#[derive(Debug, Clone)]
+pub struct RewardInfo {
+    pub id: String,
+    pub token: MaybeToken,
+    pub amount: f64, // TODO: rename to `ui_amount`
+    pub when: NaiveDate,
+}
+
+// Interest paid on a lending offer. `id` matches that of the `RewardInfo` of the same payment
+#[derive(Debug, Clone)]
+pub struct LendingInterest {
+    pub id: String,
+    pub coin: String,
+    pub amount: f64, // TODO: rename to `ui_amount`
+    pub when: NaiveDate,
+}
+
+impl LendingInterest {
+    // `None` if the coin is not a token sys knows
+    pub fn reward_info(&self) -> Option<RewardInfo> {
+        let token = match self.coin.as_str() {
+            "SOL" => MaybeToken::SOL(),
+            coin => Token::from_str(coin).ok()?.into(),
+        };
+        Some(RewardInfo {
+            id: self.id.clone(),
+            token,
+            amount: self.amount,
+            when: self.when,
+        })
+    }
+}
+
+// The exchange's 24 hour withdrawal limit. Exchanges limit either the USD value withdrawn across
+// all assets, the amount of each asset, or both
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WithdrawalLimit {
+    pub daily_limit_usd: Option<f64>,
+    pub remaining_usd: Option<f64>,
+    pub remaining: Option<f64>, // TODO: rename to `remaining_ui_amount`
+}
+
+// A balance too small to trade or withdraw that the exchange can convert into `converted_coin`
 #[derive(Debug)]
+pub struct DustBalance {
+    pub coin: String,
+    pub amount: f64,
+    pub converted_coin: String,
+    pub converted_amount: f64, // estimated, before fees
+}
+
+#[derive(Debug)]
+pub struct DustConversion {
+    pub id: String,
+    pub coin: String,
+    pub amount: f64,
+    pub converted_coin: String,
+    pub converted_amount: f64, // after fees
+    pub fee: f64,              // in `converted_coin`
+}
+
+// An asset borrowed in the exchange's cross margin account
+#[derive(Debug, Clone)]
+pub struct MarginBorrow {
+    pub coin: String,
+    pub borrowed: f64,
+    pub interest: f64, // accrued and not yet repaid
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MarginAccount {
+    pub margin_level: Option<f64>, // total assets over total liabilities, `None` without borrows
+    pub borrows: Vec<MarginBorrow>,
+}
+
+#[derive(Debug, Clone)]
 pub struct BidAsk {
     pub bid_price: f64,
     pub ask_price: f64,
@@ -96,7 +211,66 @@ impl std::fmt::Display for OrderSide {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum TimeInForce {
+    GoodTilCancelled,
+    ImmediateOrCancel,
+    FillOrKill,
+}
+
+impl std::fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TimeInForce::GoodTilCancelled => write!(f, "GTC"),
+            TimeInForce::ImmediateOrCancel => write!(f, "IOC"),
+            TimeInForce::FillOrKill => write!(f, "FOK"),
+        }
+    }
+}
+
+impl FromStr for TimeInForce {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "GTC" | "gtc" => Ok(TimeInForce::GoodTilCancelled),
+            "IOC" | "ioc" => Ok(TimeInForce::ImmediateOrCancel),
+            "FOK" | "fok" => Ok(TimeInForce::FillOrKill),
+            _ => Err(format!("Invalid time in force: {s}")),
+        }
+    }
+}
+
+pub const POSSIBLE_TIME_IN_FORCE_VALUES: &[&str] = &["gtc", "ioc", "fok"];
+
+// Limit order behavior. The default, a post-only GTC order, only ever adds liquidity to the
+// order book
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct OrderOptions {
+    pub time_in_force: TimeInForce,
+    pub post_only: bool,
+}
+
+impl Default for OrderOptions {
+    fn default() -> Self {
+        Self {
+            time_in_force: TimeInForce::GoodTilCancelled,
+            post_only: true,
+        }
+    }
+}
+
+impl std::fmt::Display for OrderOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.time_in_force)?;
+        if self.post_only {
+            write!(f, " post-only")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct OrderStatus {
     pub open: bool,
     pub side: OrderSide,
@@ -107,6 +281,13 @@ pub struct OrderStatus {
     pub fee: Option<(f64, String)>,
 }
 
+// Visible price levels of a market, best price first
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    pub bids: Vec<(/* price: */ f64, /* amount: */ f64)>,
+    pub asks: Vec<(/* price: */ f64, /* amount: */ f64)>,
+}
+
 #[derive(PartialEq, Eq)]
 pub enum MarketInfoFormat {
     All,
@@ -139,9 +320,21 @@ pub trait ExchangeClient {
         &self,
         token: MaybeToken,
     ) -> Result<Pubkey, Box<dyn std::error::Error>>;
+    // The memo that deposits must include to be credited when the deposit address of `token` is
+    // shared between accounts, `None` if it is not
+    async fn deposit_memo(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>>;
     async fn recent_deposits(&self)
         -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>>;
     async fn recent_withdrawals(&self) -> Result<Vec<WithdrawalInfo>, Box<dyn std::error::Error>>;
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>>;
+    // `None` if the exchange does not report a withdrawal limit for `token`
+    async fn withdrawal_limit(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>>;
     async fn request_withdraw(
         &self,
         address: Pubkey,
@@ -159,12 +352,22 @@ pub trait ExchangeClient {
         format: MarketInfoFormat,
     ) -> Result<(), Box<dyn std::error::Error>>;
     async fn bid_ask(&self, pair: &str) -> Result<BidAsk, Box<dyn std::error::Error>>;
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>>;
     async fn place_order(
         &self,
         pair: &str,
         side: OrderSide,
         price: f64,
         amount: f64,
+        options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>>;
+    // Places an order that fills immediately against the order book. `order_status` reports the
+    // average fill price of the order as its price
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
     ) -> Result<OrderId, Box<dyn std::error::Error>>;
     #[allow(clippy::ptr_arg)]
     async fn cancel_order(
@@ -178,6 +381,16 @@ pub trait ExchangeClient {
         pair: &str,
         order_id: &OrderId,
     ) -> Result<OrderStatus, Box<dyn std::error::Error>>;
+    // All open orders on the exchange for `pair`, including those placed outside of sys
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>>;
+    // Streams the ids of orders that were filled or cancelled, as reported by the exchange's
+    // user data websocket. `None` if the exchange does not support streaming order updates
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>>;
     async fn get_lending_info(
         &self,
         coin: &str,
@@ -185,12 +398,18 @@ pub trait ExchangeClient {
     async fn get_lending_history(
         &self,
         lending_history: LendingHistory,
-    ) -> Result<HashMap<String, f64>, Box<dyn std::error::Error>>;
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>>;
     async fn submit_lending_offer(
         &self,
         coin: &str,
         size: f64,
     ) -> Result<(), Box<dyn std::error::Error>>;
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>>;
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>>;
+    async fn convert_dust(
+        &self,
+        coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>>;
     fn preferred_solusd_pair(&self) -> &'static str;
 }
 
@@ -198,12 +417,271 @@ pub fn exchange_client_new(
     exchange: Exchange,
     exchange_credentials: ExchangeCredentials,
 ) -> Result<Box<dyn ExchangeClient>, Box<dyn std::error::Error>> {
-    let exchange_client: Box<dyn ExchangeClient> = match exchange {
+    let exchange_client: Box<dyn ExchangeClient + Sync> = match exchange {
         Exchange::Binance => Box::new(binance_exchange::new(exchange_credentials)?),
         Exchange::BinanceUs => Box::new(binance_exchange::new_us(exchange_credentials)?),
+        Exchange::Bitstamp => Box::new(bitstamp_exchange::new(exchange_credentials)?),
+        Exchange::Bybit => Box::new(bybit_exchange::new(exchange_credentials)?),
         Exchange::Coinbase => Box::new(coinbase_exchange::new(exchange_credentials)?),
+        Exchange::Gemini => Box::new(gemini_exchange::new(exchange_credentials)?),
         Exchange::Kraken => Box::new(kraken_exchange::new(exchange_credentials)?),
+        Exchange::KuCoin => Box::new(kucoin_exchange::new(exchange_credentials)?),
         Exchange::Ftx | Exchange::FtxUs => return Err("Unsupported Exchange".into()),
     };
-    Ok(exchange_client)
+    Ok(Box::new(TimedExchangeClient {
+        exchange,
+        exchange_client,
+    }))
+}
+
+// Records the latency and outcome of every API call of `exchange_client` in the operation stats
+struct TimedExchangeClient {
+    exchange: Exchange,
+    exchange_client: Box<dyn ExchangeClient + Sync>,
+}
+
+impl TimedExchangeClient {
+    async fn timed<T>(
+        &self,
+        method: &str,
+        call: impl Future<Output = Result<T, Box<dyn std::error::Error>>>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let result = call.await;
+        operation_stats::record(
+            Operation::ExchangeApi,
+            &format!("{:?} {method}", self.exchange),
+            start.elapsed(),
+            result.is_ok(),
+        );
+        result
+    }
+}
+
+#[async_trait]
+impl ExchangeClient for TimedExchangeClient {
+    async fn deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Pubkey, Box<dyn std::error::Error>> {
+        self.timed(
+            "deposit_address",
+            self.exchange_client.deposit_address(token),
+        )
+        .await
+    }
+
+    async fn deposit_memo(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        self.timed("deposit_memo", self.exchange_client.deposit_memo(token))
+            .await
+    }
+
+    async fn recent_deposits(
+        &self,
+    ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
+        self.timed("recent_deposits", self.exchange_client.recent_deposits())
+            .await
+    }
+
+    async fn recent_withdrawals(&self) -> Result<Vec<WithdrawalInfo>, Box<dyn std::error::Error>> {
+        self.timed(
+            "recent_withdrawals",
+            self.exchange_client.recent_withdrawals(),
+        )
+        .await
+    }
+
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        self.timed("recent_rewards", self.exchange_client.recent_rewards())
+            .await
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        self.timed(
+            "withdrawal_limit",
+            self.exchange_client.withdrawal_limit(token),
+        )
+        .await
+    }
+
+    async fn request_withdraw(
+        &self,
+        address: Pubkey,
+        token: MaybeToken,
+        amount: f64,
+        withdrawal_password: Option<String>,
+        withdrawal_code: Option<String>,
+    ) -> Result<(/* withdraw_id: */ String, /*withdraw_fee: */ f64), Box<dyn std::error::Error>>
+    {
+        self.timed(
+            "request_withdraw",
+            self.exchange_client.request_withdraw(
+                address,
+                token,
+                amount,
+                withdrawal_password,
+                withdrawal_code,
+            ),
+        )
+        .await
+    }
+
+    async fn balances(
+        &self,
+    ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
+        self.timed("balances", self.exchange_client.balances())
+            .await
+    }
+
+    async fn print_market_info(
+        &self,
+        pair: &str,
+        format: MarketInfoFormat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.timed(
+            "print_market_info",
+            self.exchange_client.print_market_info(pair, format),
+        )
+        .await
+    }
+
+    async fn bid_ask(&self, pair: &str) -> Result<BidAsk, Box<dyn std::error::Error>> {
+        self.timed("bid_ask", self.exchange_client.bid_ask(pair))
+            .await
+    }
+
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        self.timed("order_book", self.exchange_client.order_book(pair))
+            .await
+    }
+
+    async fn place_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        price: f64,
+        amount: f64,
+        options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        self.timed(
+            "place_order",
+            self.exchange_client
+                .place_order(pair, side, price, amount, options),
+        )
+        .await
+    }
+
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        self.timed(
+            "place_market_order",
+            self.exchange_client.place_market_order(pair, side, amount),
+        )
+        .await
+    }
+
+    async fn cancel_order(
+        &self,
+        pair: &str,
+        order_id: &OrderId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.timed(
+            "cancel_order",
+            self.exchange_client.cancel_order(pair, order_id),
+        )
+        .await
+    }
+
+    async fn order_status(
+        &self,
+        pair: &str,
+        order_id: &OrderId,
+    ) -> Result<OrderStatus, Box<dyn std::error::Error>> {
+        self.timed(
+            "order_status",
+            self.exchange_client.order_status(pair, order_id),
+        )
+        .await
+    }
+
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        self.timed("open_orders", self.exchange_client.open_orders(pair))
+            .await
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        self.timed("order_updates", self.exchange_client.order_updates())
+            .await
+    }
+
+    async fn get_lending_info(
+        &self,
+        coin: &str,
+    ) -> Result<Option<LendingInfo>, Box<dyn std::error::Error>> {
+        self.timed(
+            "get_lending_info",
+            self.exchange_client.get_lending_info(coin),
+        )
+        .await
+    }
+
+    async fn get_lending_history(
+        &self,
+        lending_history: LendingHistory,
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
+        self.timed(
+            "get_lending_history",
+            self.exchange_client.get_lending_history(lending_history),
+        )
+        .await
+    }
+
+    async fn submit_lending_offer(
+        &self,
+        coin: &str,
+        size: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.timed(
+            "submit_lending_offer",
+            self.exchange_client.submit_lending_offer(coin, size),
+        )
+        .await
+    }
+
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        self.timed("margin_account", self.exchange_client.margin_account())
+            .await
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        self.timed("dust_balances", self.exchange_client.dust_balances())
+            .await
+    }
+
+    async fn convert_dust(
+        &self,
+        coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        self.timed("convert_dust", self.exchange_client.convert_dust(coins))
+            .await
+    }
+
+    fn preferred_solusd_pair(&self) -> &'static str {
+        self.exchange_client.preferred_solusd_pair()
+    }
 }
@@ -0,0 +1,665 @@
+use {
+    crate::{
+        exchange::*,
+        http_client::{self, TracedSend},
+        token::MaybeToken,
+    },
+    async_trait::async_trait,
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    chrono::prelude::*,
+    hmac::{Hmac, Mac},
+    serde::{de::DeserializeOwned, Deserialize},
+    sha2::Sha384,
+    solana_sdk::pubkey::Pubkey,
+    std::collections::HashMap,
+    tokio::sync::mpsc::UnboundedReceiver,
+};
+
+const API_ENDPOINT: &str = "https://api.gemini.com";
+
+// Gemini names the Solana network "solana" for deposit addresses
+const SOLANA_NETWORK: &str = "solana";
+
+const SUPPORTED_COINS: &[&str] = &["SOL"];
+const SUPPORTED_PAIRS: &[&str] = &["SOLUSD"];
+
+// Gemini has no market orders, they are placed as immediate-or-cancel limit orders priced this
+// far through the opposite side of the book
+const MARKET_ORDER_SLIPPAGE: f64 = 0.05;
+
+pub struct GeminiExchangeClient {
+    client: reqwest::Client,
+    api_key: String,
+    secret: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    reason: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct DepositAddress {
+    address: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transfer {
+    #[serde(rename = "type")]
+    kind: String,
+    status: String,
+    eid: u64,
+    currency: String,
+    amount: String,
+    tx_hash: Option<String>,
+    destination: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Withdraw {
+    withdrawal_id: Option<String>,
+    fee: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Balance {
+    currency: String,
+    amount: String,
+    available: String,
+}
+
+#[derive(Deserialize)]
+struct Ticker {
+    bid: String,
+    ask: String,
+    high: String,
+    low: String,
+}
+
+#[derive(Deserialize)]
+struct BookEntry {
+    price: String,
+    amount: String,
+}
+
+#[derive(Deserialize)]
+struct Book {
+    bids: Vec<BookEntry>,
+    asks: Vec<BookEntry>,
+}
+
+#[derive(Deserialize)]
+struct Trade {
+    fee_amount: String,
+    fee_currency: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    order_id: String,
+    symbol: String,
+    side: String,
+    price: String,
+    avg_execution_price: String,
+    original_amount: String,
+    executed_amount: String,
+    is_live: bool,
+    timestampms: i64,
+    #[serde(default)]
+    options: Vec<String>,
+    #[serde(default)]
+    trades: Vec<Trade>,
+}
+
+// Gemini symbols are lowercase
+fn symbol(pair: &str) -> String {
+    pair.to_lowercase()
+}
+
+impl Order {
+    fn into_order_status(self) -> Result<(OrderId, OrderStatus), Box<dyn std::error::Error>> {
+        let side = match self.side.as_str() {
+            "buy" => OrderSide::Buy,
+            "sell" => OrderSide::Sell,
+            side => return Err(format!("Invalid order side: {side}").into()),
+        };
+
+        let mut fee = None::<(f64, String)>;
+        for trade in self.trades {
+            let amount = parse_f64("fee_amount", &trade.fee_amount)?;
+            fee = Some(match fee {
+                Some((total, coin)) => (total + amount, coin),
+                None => (amount, trade.fee_currency),
+            });
+        }
+
+        let last_update = Local
+            .timestamp_millis_opt(self.timestampms)
+            .single()
+            .ok_or("Invalid `timestampms` field")?
+            .date_naive();
+
+        Ok((
+            self.order_id,
+            OrderStatus {
+                open: self.is_live,
+                side,
+                // Market orders are placed far through the book, report the average fill price
+                // instead
+                price: if self.options.iter().any(|o| o == "immediate-or-cancel") {
+                    parse_f64("avg_execution_price", &self.avg_execution_price)?
+                } else {
+                    parse_f64("price", &self.price)?
+                },
+                amount: parse_f64("original_amount", &self.original_amount)?,
+                filled_amount: parse_f64("executed_amount", &self.executed_amount)?,
+                last_update,
+                fee: fee.filter(|(amount, _)| *amount > f64::EPSILON),
+            },
+        ))
+    }
+}
+
+impl GeminiExchangeClient {
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let response = request.traced_send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(match response.json::<ErrorResponse>().await {
+                Ok(error) => format!("Gemini API error {}: {}", error.reason, error.message),
+                Err(_) => format!("Gemini API error: {status}"),
+            }
+            .into());
+        }
+        Ok(response.json::<T>().await?)
+    }
+
+    async fn public_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        self.send(self.client.get(format!("{API_ENDPOINT}{path}")))
+            .await
+    }
+
+    // Private requests carry their parameters in a signed, base64 encoded JSON payload header
+    async fn private_post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: serde_json::Value,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let mut payload = serde_json::json!({
+            "request": path,
+            "nonce": timestamp_millis().to_string(),
+        });
+        if let (Some(payload), serde_json::Value::Object(params)) =
+            (payload.as_object_mut(), params)
+        {
+            payload.extend(params);
+        }
+        let payload = BASE64.encode(payload.to_string());
+
+        let mut mac = Hmac::<Sha384>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(payload.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        self.send(
+            self.client
+                .post(format!("{API_ENDPOINT}{path}"))
+                .header("Content-Type", "text/plain")
+                .header("Content-Length", "0")
+                .header("Cache-Control", "no-cache")
+                .header("X-GEMINI-APIKEY", &self.api_key)
+                .header("X-GEMINI-PAYLOAD", payload)
+                .header("X-GEMINI-SIGNATURE", signature),
+        )
+        .await
+    }
+
+    async fn transfers(&self) -> Result<Vec<Transfer>, Box<dyn std::error::Error>> {
+        self.private_post(
+            "/v1/transfers",
+            serde_json::json!({ "limit_transfers": 50 }),
+        )
+        .await
+    }
+
+    async fn order(&self, order_id: &OrderId) -> Result<Order, Box<dyn std::error::Error>> {
+        self.private_post(
+            "/v1/order/status",
+            serde_json::json!({
+                "order_id": order_id.parse::<u64>()?,
+                "include_trades": true,
+            }),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl ExchangeClient for GeminiExchangeClient {
+    async fn deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Pubkey, Box<dyn std::error::Error>> {
+        if !SUPPORTED_COINS.contains(&token.name()) {
+            return Err(format!("{token} deposits are not supported").into());
+        }
+
+        let deposit_address = match self
+            .private_post::<Vec<DepositAddress>>(
+                &format!("/v1/addresses/{SOLANA_NETWORK}"),
+                serde_json::json!({}),
+            )
+            .await?
+            .into_iter()
+            .next()
+        {
+            Some(deposit_address) => deposit_address,
+            None => {
+                self.private_post::<DepositAddress>(
+                    &format!("/v1/deposit/{SOLANA_NETWORK}/newAddress"),
+                    serde_json::json!({ "label": "sys" }),
+                )
+                .await?
+            }
+        };
+
+        Ok(deposit_address.address.parse::<Pubkey>()?)
+    }
+
+    async fn deposit_memo(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn recent_deposits(
+        &self,
+    ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
+        let mut successful_deposits = vec![];
+        for transfer in self.transfers().await? {
+            if transfer.kind == "Deposit"
+                && SUPPORTED_COINS.contains(&transfer.currency.as_str())
+                && matches!(transfer.status.as_str(), "Advanced" | "Complete")
+            {
+                if let Some(tx_id) = transfer.tx_hash {
+                    successful_deposits.push(DepositInfo {
+                        tx_id,
+                        coin: transfer.currency,
+                        amount: parse_f64("amount", &transfer.amount)?,
+                        memo: None,
+                        when: None,
+                    });
+                }
+            }
+        }
+        Ok(Some(successful_deposits))
+    }
+
+    async fn recent_withdrawals(&self) -> Result<Vec<WithdrawalInfo>, Box<dyn std::error::Error>> {
+        let mut withdrawals = vec![];
+        for transfer in self.transfers().await? {
+            if transfer.kind != "Withdrawal"
+                || !SUPPORTED_COINS.contains(&transfer.currency.as_str())
+            {
+                continue;
+            }
+
+            let (completed, tx_id) = match transfer.status.as_str() {
+                "Complete" => (true, transfer.tx_hash),
+                "Cancelled" => (true, None),
+                _ => (false, None),
+            };
+            withdrawals.push(WithdrawalInfo {
+                address: transfer
+                    .destination
+                    .and_then(|destination| destination.parse::<Pubkey>().ok())
+                    .unwrap_or_default(),
+                token: MaybeToken::SOL(),
+                amount: parse_f64("amount", &transfer.amount)?,
+                tag: transfer.eid.to_string(),
+                completed,
+                tx_id,
+            });
+        }
+        Ok(withdrawals)
+    }
+
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn request_withdraw(
+        &self,
+        address: Pubkey,
+        token: MaybeToken,
+        amount: f64,
+        _withdrawal_password: Option<String>,
+        _withdrawal_code: Option<String>,
+    ) -> Result<(/* withdraw_id: */ String, /*withdraw_fee: */ f64), Box<dyn std::error::Error>>
+    {
+        let coin = token.name();
+        if !SUPPORTED_COINS.contains(&coin) {
+            return Err(format!("{token} withdrawals are not supported").into());
+        }
+
+        let withdraw = self
+            .private_post::<Withdraw>(
+                &format!("/v1/withdraw/{}", coin.to_lowercase()),
+                serde_json::json!({
+                    "address": address.to_string(),
+                    "amount": amount.to_string(),
+                }),
+            )
+            .await?;
+
+        // Withdrawals are listed by `recent_withdrawals` under their event id, which the
+        // withdrawal id is
+        let withdraw_id = withdraw
+            .withdrawal_id
+            .ok_or("Gemini did not return a withdrawal id")?;
+        let withdraw_fee = match withdraw.fee {
+            Some(fee) => parse_f64("fee", &fee)?,
+            None => 0.,
+        };
+        Ok((withdraw_id, withdraw_fee))
+    }
+
+    async fn balances(
+        &self,
+    ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
+        let mut balances = HashMap::new();
+        for balance in self
+            .private_post::<Vec<Balance>>("/v1/balances", serde_json::json!({}))
+            .await?
+        {
+            if balance.currency == "SOL" || USD_COINS.contains(&balance.currency.as_str()) {
+                balances.insert(
+                    balance.currency,
+                    ExchangeBalance {
+                        available: parse_f64("available", &balance.available)?,
+                        total: parse_f64("amount", &balance.amount)?,
+                        earn: 0.,
+                    },
+                );
+            }
+        }
+        Ok(balances)
+    }
+
+    async fn print_market_info(
+        &self,
+        pair: &str,
+        format: MarketInfoFormat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let ticker = self
+            .public_get::<Ticker>(&format!("/v2/ticker/{}", symbol(pair)))
+            .await?;
+
+        // Each candle is [time, open, high, low, close, volume], newest first
+        let candles = self
+            .public_get::<Vec<[f64; 6]>>(&format!("/v2/candles/{}/1hr", symbol(pair)))
+            .await?;
+        let candles = &candles[..candles.len().min(24)];
+        let volume = candles.iter().map(|candle| candle[5]).sum::<f64>();
+        let weighted_24h_avg_price = candles
+            .iter()
+            .map(|candle| (candle[2] + candle[3]) / 2. * candle[5])
+            .sum::<f64>()
+            / volume;
+
+        match format {
+            MarketInfoFormat::All => {
+                println!("Pair: {pair}");
+                println!(
+                    "Ask: ${}, Bid: ${}, High: ${}, Low: ${}, ",
+                    ticker.ask, ticker.bid, ticker.high, ticker.low
+                );
+                println!("Weighted 24h average price: ${weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Ask => {
+                println!("{}", ticker.ask);
+            }
+            MarketInfoFormat::Weighted24hAveragePrice => {
+                println!("{weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Hourly => {
+                println!("hour,low,high,average,volume");
+                for candle in candles.iter().rev() {
+                    let time = Local
+                        .timestamp_millis_opt(candle[0] as i64)
+                        .single()
+                        .ok_or("Invalid API response")?;
+                    let (high, low) = (candle[2], candle[3]);
+                    println!(
+                        "{},{},{},{},{}",
+                        time,
+                        low,
+                        high,
+                        (low + high) / 2.,
+                        candle[5]
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn bid_ask(&self, pair: &str) -> Result<BidAsk, Box<dyn std::error::Error>> {
+        let ticker = self
+            .public_get::<Ticker>(&format!("/v2/ticker/{}", symbol(pair)))
+            .await?;
+
+        Ok(BidAsk {
+            bid_price: parse_f64("bid", &ticker.bid)?,
+            ask_price: parse_f64("ask", &ticker.ask)?,
+        })
+    }
+
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        let book = self
+            .public_get::<Book>(&format!(
+                "/v1/book/{}?limit_bids=200&limit_asks=200",
+                symbol(pair)
+            ))
+            .await?;
+
+        let parse_levels = |levels: Vec<BookEntry>| -> Vec<(f64, f64)> {
+            levels
+                .into_iter()
+                .filter_map(|entry| {
+                    Some((
+                        entry.price.parse::<f64>().ok()?,
+                        entry.amount.parse::<f64>().ok()?,
+                    ))
+                })
+                .collect()
+        };
+        Ok(OrderBook {
+            bids: parse_levels(book.bids),
+            asks: parse_levels(book.asks),
+        })
+    }
+
+    async fn place_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        price: f64,
+        amount: f64,
+        options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if !SUPPORTED_PAIRS.contains(&pair) {
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        let order_options = match (options.post_only, options.time_in_force) {
+            (true, TimeInForce::GoodTilCancelled) => vec!["maker-or-cancel"],
+            (true, time_in_force) => {
+                return Err(format!("Unsupported post-only time in force: {time_in_force}").into())
+            }
+            (false, TimeInForce::GoodTilCancelled) => vec![],
+            (false, TimeInForce::ImmediateOrCancel) => vec!["immediate-or-cancel"],
+            (false, TimeInForce::FillOrKill) => vec!["fill-or-kill"],
+        };
+
+        let order = self
+            .private_post::<Order>(
+                "/v1/order/new",
+                serde_json::json!({
+                    "symbol": symbol(pair),
+                    "amount": amount.to_string(),
+                    "price": price.to_string(),
+                    "side": side.to_string().to_lowercase(),
+                    "type": "exchange limit",
+                    "options": order_options,
+                }),
+            )
+            .await?;
+
+        Ok(order.order_id)
+    }
+
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        let bid_ask = self.bid_ask(pair).await?;
+        let price = match side {
+            OrderSide::Buy => bid_ask.ask_price * (1. + MARKET_ORDER_SLIPPAGE),
+            OrderSide::Sell => bid_ask.bid_price * (1. - MARKET_ORDER_SLIPPAGE),
+        };
+        // Gemini accepts SOL/USD prices with up to two decimal places
+        let price = (price * 100.).round() / 100.;
+
+        self.place_order(
+            pair,
+            side,
+            price,
+            amount,
+            OrderOptions {
+                time_in_force: TimeInForce::ImmediateOrCancel,
+                post_only: false,
+            },
+        )
+        .await
+    }
+
+    async fn cancel_order(
+        &self,
+        _pair: &str,
+        order_id: &OrderId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.private_post::<serde_json::Value>(
+            "/v1/order/cancel",
+            serde_json::json!({ "order_id": order_id.parse::<u64>()? }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn order_status(
+        &self,
+        _pair: &str,
+        order_id: &OrderId,
+    ) -> Result<OrderStatus, Box<dyn std::error::Error>> {
+        let (_, order_status) = self.order(order_id).await?.into_order_status()?;
+        Ok(order_status)
+    }
+
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        self.private_post::<Vec<Order>>("/v1/orders", serde_json::json!({}))
+            .await?
+            .into_iter()
+            .filter(|order| order.symbol == symbol(pair))
+            .map(Order::into_order_status)
+            .collect()
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn get_lending_info(
+        &self,
+        _coin: &str,
+    ) -> Result<Option<LendingInfo>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Gemini".into())
+    }
+
+    async fn get_lending_history(
+        &self,
+        _lending_history: LendingHistory,
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Gemini".into())
+    }
+
+    async fn submit_lending_offer(
+        &self,
+        _coin: &str,
+        _size: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for Gemini".into())
+    }
+
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        Err("Margin not currently supported for Gemini".into())
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Gemini".into())
+    }
+
+    async fn convert_dust(
+        &self,
+        _coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Gemini".into())
+    }
+
+    fn preferred_solusd_pair(&self) -> &'static str {
+        "SOLUSD"
+    }
+}
+
+pub fn new(
+    ExchangeCredentials {
+        api_key,
+        secret,
+        subaccount,
+        ..
+    }: ExchangeCredentials,
+) -> Result<GeminiExchangeClient, Box<dyn std::error::Error>> {
+    if subaccount.is_some() {
+        return Err("subaccounts not supported".into());
+    }
+
+    Ok(GeminiExchangeClient {
+        client: http_client::client(),
+        api_key,
+        secret,
+    })
+}
@@ -1,9 +1,9 @@
 use {
     chrono::prelude::*,
-    solana_client::rpc_client::RpcClient,
     solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature},
     solana_transaction_status::UiTransactionEncoding,
     std::str::FromStr,
+    sys::chain_client::ChainClient,
 };
 
 #[derive(Debug, Clone)]
@@ -16,7 +16,7 @@ pub struct GetTransactionAddrssBalanceChange {
 }
 
 pub fn get_transaction_balance_change(
-    rpc_client: &RpcClient,
+    rpc_client: &dyn ChainClient,
     signature: &Signature,
     address: &Pubkey,
     address_is_token: bool,
@@ -0,0 +1,127 @@
+use {
+    crate::http_client::{self, ServedResponse, TracedSend},
+    reqwest::Client,
+    serde_json::json,
+    std::{
+        env, io,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+// Pings a healthchecks.io style monitor after each automated run, so that the monitor alerts when
+// runs fail or silently stop.
+//
+// HEALTHCHECK_URL is pinged on success. Failures are reported to HEALTHCHECK_FAIL_URL, which
+// defaults to the healthchecks.io convention of HEALTHCHECK_URL with a `/fail` suffix
+pub struct Healthcheck {
+    client: Client,
+    success_url: Option<String>,
+    failure_url: Option<String>,
+}
+
+impl Default for Healthcheck {
+    fn default() -> Self {
+        let success_url = env::var("HEALTHCHECK_URL").ok();
+        let failure_url = env::var("HEALTHCHECK_FAIL_URL").ok().or_else(|| {
+            success_url
+                .as_ref()
+                .map(|success_url| format!("{}/fail", success_url.trim_end_matches('/')))
+        });
+        Healthcheck {
+            client: http_client::client(),
+            success_url,
+            failure_url,
+        }
+    }
+}
+
+impl Healthcheck {
+    // The request body is recorded by healthchecks.io as the ping's log
+    async fn ping(&self, url: &str, body: String) {
+        if let Err(err) = self.client.post(url).body(body).traced_send().await {
+            eprintln!("Failed to ping healthcheck: {err:?}");
+        }
+    }
+
+    pub async fn success(&self) {
+        if let Some(ref success_url) = self.success_url {
+            self.ping(success_url, String::new()).await;
+        }
+    }
+
+    pub async fn failure(&self, err: &str) {
+        if let Some(ref failure_url) = self.failure_url {
+            self.ping(failure_url, err.to_string()).await;
+        }
+    }
+
+    pub async fn report<T>(&self, result: &Result<T, Box<dyn std::error::Error>>) {
+        match result {
+            Ok(_) => self.success().await,
+            Err(err) => self.failure(&err.to_string()).await,
+        }
+    }
+}
+
+#[derive(Default)]
+struct HealthState {
+    cycles: usize,
+    last_success: Option<Instant>,
+    last_error: Option<String>,
+}
+
+// Serves the outcome of a long-running process's cycles over HTTP, for systemd or another
+// supervisor to probe.
+//
+// Any request is answered with 200 while a cycle has succeeded within `max_age`, or the process
+// started less than `max_age` ago, and with 503 otherwise. The JSON body has the details
+pub struct HealthEndpoint {
+    state: Arc<Mutex<HealthState>>,
+}
+
+impl HealthEndpoint {
+    pub fn serve(listen: SocketAddr, max_age: Duration) -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(HealthState::default()));
+        let started = Instant::now();
+
+        http_client::serve(listen, {
+            let state = state.clone();
+            move || {
+                let state = state.lock().unwrap();
+                let last_success_age = state.last_success.map(|when| when.elapsed());
+                let healthy = last_success_age.unwrap_or_else(|| started.elapsed()) < max_age;
+                ServedResponse {
+                    status: if healthy {
+                        "200 OK"
+                    } else {
+                        "503 Service Unavailable"
+                    },
+                    content_type: "application/json",
+                    body: json!({
+                        "healthy": healthy,
+                        "cycles": state.cycles,
+                        "last_success_secs_ago": last_success_age.map(|age| age.as_secs()),
+                        "last_error": state.last_error,
+                    })
+                    .to_string(),
+                }
+            }
+        })?;
+
+        Ok(Self { state })
+    }
+
+    pub fn record<T>(&self, result: &Result<T, Box<dyn std::error::Error>>) {
+        let mut state = self.state.lock().unwrap();
+        state.cycles += 1;
+        match result {
+            Ok(_) => {
+                state.last_success = Some(Instant::now());
+                state.last_error = None;
+            }
+            Err(err) => state.last_error = Some(err.to_string()),
+        }
+    }
+}
@@ -1,4 +1,23 @@
-use {solana_client::rpc_client::RpcClient, solana_sdk::instruction::Instruction};
+use {
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::instruction::Instruction,
+    std::{
+        collections::HashMap,
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+};
+
+// Estimates are reused for this long, so that flows which build several transactions touching the
+// same accounts don't re-query Helius for each one
+const PRIORITY_FEE_ESTIMATE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+type PriorityFeeEstimateCacheKey = (HeliusPriorityLevel, Vec<String>);
+
+lazy_static::lazy_static! {
+    static ref PRIORITY_FEE_ESTIMATE_CACHE: Mutex<HashMap<PriorityFeeEstimateCacheKey, (Instant, u64)>> =
+        Mutex::new(HashMap::new());
+}
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -50,12 +69,15 @@ pub fn get_priority_fee_estimate_for_instructions(
     priority_level: HeliusPriorityLevel,
     instructions: &[Instruction],
 ) -> Result<u64, String> {
+    // Only the writable accounts of a transaction contend for block space, so estimate the fee
+    // from their recent fees
     let mut account_keys: Vec<_> = instructions
         .iter()
         .flat_map(|instruction| {
             instruction
                 .accounts
                 .iter()
+                .filter(|account_meta| account_meta.is_writable)
                 .map(|account_meta| account_meta.pubkey.to_string())
                 .collect::<Vec<_>>()
         })
@@ -63,6 +85,15 @@ pub fn get_priority_fee_estimate_for_instructions(
     account_keys.sort();
     account_keys.dedup();
 
+    let cache_key = (priority_level, account_keys.clone());
+    if let Some((when, priority_fee_estimate)) =
+        PRIORITY_FEE_ESTIMATE_CACHE.lock().unwrap().get(&cache_key)
+    {
+        if when.elapsed() < PRIORITY_FEE_ESTIMATE_CACHE_TTL {
+            return Ok(*priority_fee_estimate);
+        }
+    }
+
     let request = serde_json::json!([HeliusGetPriorityFeeEstimateRequest {
         options: Some(HeliusGetPriorityFeeEstimateOptions {
             priority_level: Some(priority_level),
@@ -73,17 +104,21 @@ pub fn get_priority_fee_estimate_for_instructions(
         ..HeliusGetPriorityFeeEstimateRequest::default()
     }]);
 
-    rpc_client
+    let priority_fee_estimate = rpc_client
         .send::<HeliusGetPriorityFeeEstimateResponse>(
             solana_client::rpc_request::RpcRequest::Custom {
                 method: "getPriorityFeeEstimate",
             },
             request,
         )
-        .map(|response| {
-            response
-                .priority_fee_estimate
-                .expect("priority_fee_estimate") as u64
-        })
-        .map_err(|err| format!("Failed to invoke RPC method getPriorityFeeEstimate: {err}"))
+        .map_err(|err| format!("Failed to invoke RPC method getPriorityFeeEstimate: {err}"))?
+        .priority_fee_estimate
+        .ok_or("getPriorityFeeEstimate response is missing priorityFeeEstimate")?
+        as u64;
+
+    let mut cache = PRIORITY_FEE_ESTIMATE_CACHE.lock().unwrap();
+    cache.retain(|_, (when, _)| when.elapsed() < PRIORITY_FEE_ESTIMATE_CACHE_TTL);
+    cache.insert(cache_key, (Instant::now(), priority_fee_estimate));
+
+    Ok(priority_fee_estimate)
 }
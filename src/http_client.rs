@@ -0,0 +1,252 @@
+use {
+    reqwest::{
+        header::HeaderMap, Certificate, Client, ClientBuilder, Proxy, RequestBuilder, Response, Url,
+    },
+    std::{
+        env, fs,
+        future::Future,
+        io::{self, Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::OnceLock,
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+// Proxy and TLS settings applied to every HTTP client, for networks that only allow traffic
+// through a proxy or that intercept TLS with their own certificate authority.
+//
+// Clients created by sys apply them directly. Clients created inside dependencies, such as the
+// Solana RPC client and the Kraken, Binance and Jupiter APIs, instead pick them up from the
+// standard HTTPS_PROXY, HTTP_PROXY and SSL_CERT_FILE environment variables, which `configure`
+// sets when they are not already set.
+//
+// Request tracing likewise only covers requests sent by sys with `TracedSend`. Requests of
+// dependencies are traced through the `reqwest` debug log instead
+#[derive(Default)]
+struct HttpConfig {
+    proxy: Option<String>,
+    root_certificates: Vec<Certificate>,
+    trace: bool,
+}
+
+static HTTP_CONFIG: OnceLock<HttpConfig> = OnceLock::new();
+
+// Splits a PEM bundle into its certificates, `Certificate::from_pem` only reads the first
+fn read_ca_bundle(path: &str) -> Result<Vec<Certificate>, Box<dyn std::error::Error>> {
+    const END_CERTIFICATE: &str = "-----END CERTIFICATE-----";
+
+    let pem = fs::read_to_string(path).map_err(|err| format!("Unable to read {path}: {err}"))?;
+    let certificates = pem
+        .split_inclusive(END_CERTIFICATE)
+        .filter(|certificate| certificate.contains(END_CERTIFICATE))
+        .map(|certificate| Certificate::from_pem(certificate.trim().as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("Invalid certificate in {path}: {err}"))?;
+    if certificates.is_empty() {
+        return Err(format!("No certificates found in {path}").into());
+    }
+    Ok(certificates)
+}
+
+// Must be called once at startup, before any HTTP client is created. `proxy` is an http://,
+// https:// or socks5:// URL and `ca_bundle` the path of a PEM file of additional trusted root
+// certificates. `trace` logs a summary of each request sent with `TracedSend`
+pub fn configure(
+    proxy: Option<String>,
+    ca_bundle: Option<String>,
+    trace: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(ref proxy) = proxy {
+        Proxy::all(proxy).map_err(|err| format!("Invalid proxy {proxy}: {err}"))?;
+        for var in ["HTTPS_PROXY", "HTTP_PROXY"] {
+            if env::var_os(var).is_none() {
+                env::set_var(var, proxy);
+            }
+        }
+    }
+
+    let root_certificates = match ca_bundle {
+        Some(ref ca_bundle) => {
+            let root_certificates = read_ca_bundle(ca_bundle)?;
+            if env::var_os("SSL_CERT_FILE").is_none() {
+                env::set_var("SSL_CERT_FILE", ca_bundle);
+            }
+            root_certificates
+        }
+        None => vec![],
+    };
+
+    HTTP_CONFIG
+        .set(HttpConfig {
+            proxy,
+            root_certificates,
+            trace,
+        })
+        .map_err(|_| "HTTP clients already configured".into())
+}
+
+pub fn client_builder() -> ClientBuilder {
+    let http_config = HTTP_CONFIG.get_or_init(HttpConfig::default);
+
+    let mut builder = Client::builder();
+    if let Some(ref proxy) = http_config.proxy {
+        builder = builder.proxy(Proxy::all(proxy).expect("proxy validated by configure"));
+    }
+    for root_certificate in &http_config.root_certificates {
+        builder = builder.add_root_certificate(root_certificate.clone());
+    }
+    builder
+}
+
+pub fn client() -> Client {
+    client_builder()
+        .build()
+        .expect("HTTP client configuration is valid")
+}
+
+// Query parameters whose values are credentials or signatures
+const REDACTED_QUERY_PARAMS: &[&str] = &[
+    "signature",
+    "apikey",
+    "api_key",
+    "key",
+    "secret",
+    "token",
+    "passphrase",
+    "listenkey",
+    "x_cg_pro_api_key",
+];
+
+fn redact_url(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.query().is_some() {
+        let query = url
+            .query_pairs()
+            .map(|(name, value)| {
+                if REDACTED_QUERY_PARAMS.contains(&name.to_lowercase().as_str()) {
+                    (name.into_owned(), "REDACTED".to_string())
+                } else {
+                    (name.into_owned(), value.into_owned())
+                }
+            })
+            .collect::<Vec<_>>();
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+    let _ = url.set_password(None);
+    // Telegram bot API URLs carry the bot token in their path
+    if url.host_str() == Some("api.telegram.org") {
+        let method = url
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        url.set_path(&format!("/botREDACTED/{method}"));
+    }
+    url.to_string()
+}
+
+// Rate limit headers as sent by Binance (`x-mbx-used-weight-1m`), Bybit (`x-bapi-limit-status`),
+// Coinbase (`cb-ratelimit-remaining`) and the `ratelimit-*` and `retry-after` conventions
+fn rate_limit_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            name.contains("ratelimit")
+                || name.contains("rate-limit")
+                || name.contains("used-weight")
+                || name.contains("order-count")
+                || name.starts_with("x-bapi-limit")
+                || name == "retry-after"
+        })
+        .map(|(name, value)| format!(" {name}={}", value.to_str().unwrap_or("?")))
+        .collect()
+}
+
+pub trait TracedSend {
+    // `RequestBuilder::send`, logging the method, redacted URL, status, latency and rate limit
+    // headers of the request to stderr when tracing is enabled
+    fn traced_send(self) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+impl TracedSend for RequestBuilder {
+    fn traced_send(self) -> impl Future<Output = reqwest::Result<Response>> + Send {
+        async move {
+            if !HTTP_CONFIG
+                .get()
+                .is_some_and(|http_config| http_config.trace)
+            {
+                return self.send().await;
+            }
+
+            let (client, request) = self.build_split();
+            let request = request?;
+            let method = request.method().clone();
+            let url = redact_url(request.url());
+
+            let start = Instant::now();
+            let result = client.execute(request).await;
+            let latency = start.elapsed().as_millis();
+            match result {
+                Ok(ref response) => eprintln!(
+                    "http: {method} {url} -> {} in {latency}ms{}",
+                    response.status(),
+                    rate_limit_headers(response.headers())
+                ),
+                Err(ref err) => eprintln!(
+                    "http: {method} {url} -> {} error after {latency}ms",
+                    if err.is_timeout() {
+                        "timeout"
+                    } else if err.is_connect() {
+                        "connect"
+                    } else {
+                        "request"
+                    }
+                ),
+            }
+            result
+        }
+    }
+}
+
+pub struct ServedResponse {
+    // Such as `200 OK`
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+// Answers every request on `listen` with `respond()` from a background thread. The request itself
+// is ignored, this is only meant for the small read-only endpoints of long-running commands
+pub fn serve(
+    listen: SocketAddr,
+    respond: impl Fn() -> ServedResponse + Send + 'static,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(err) = write_response(stream, respond()) {
+                eprintln!("Failed to answer request on {listen}: {err}");
+            }
+        }
+    });
+    Ok(())
+}
+
+fn write_response(mut stream: TcpStream, response: ServedResponse) -> io::Result<()> {
+    // The request is read so that closing the connection doesn't reset it before the client sees
+    // the response
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let _ = stream.read(&mut [0; 1024])?;
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )
+}
@@ -0,0 +1,79 @@
+use {
+    crate::rpc_client_utils::MAX_MULTIPLE_ACCOUNTS,
+    solana_sdk::{
+        clock::{Epoch, Slot},
+        pubkey,
+        pubkey::Pubkey,
+    },
+    sys::chain_client::ChainClient,
+};
+
+const TIP_DISTRIBUTION_PROGRAM_ID: Pubkey = pubkey!("4R3gSG8BpU4t19KYj8CfnbtRpnT8gtk4dvTHxVRwc2r7");
+
+// MEV tips of one epoch claimed by a stake account
+pub struct MevReward {
+    pub amount: u64,
+    pub slot: Slot, // slot of the claim
+}
+
+// The MEV tips of `epoch` claimed by each `(stake account, voter)` of `stake_voters`.
+//
+// Claims are found through their `ClaimStatus` accounts, which the tip distribution program
+// closes a few epochs after the claim window ends, so older epochs yield nothing
+pub fn get_mev_rewards(
+    rpc_client: &dyn ChainClient,
+    stake_voters: &[(Pubkey, Pubkey)],
+    epoch: Epoch,
+) -> Result<Vec<Option<MevReward>>, Box<dyn std::error::Error>> {
+    let claim_status_addresses = stake_voters
+        .iter()
+        .map(|(stake_address, voter)| {
+            let tip_distribution_address = Pubkey::find_program_address(
+                &[
+                    b"TIP_DISTRIBUTION_ACCOUNT",
+                    voter.as_ref(),
+                    &epoch.to_le_bytes(),
+                ],
+                &TIP_DISTRIBUTION_PROGRAM_ID,
+            )
+            .0;
+            Pubkey::find_program_address(
+                &[
+                    b"CLAIM_STATUS",
+                    stake_address.as_ref(),
+                    tip_distribution_address.as_ref(),
+                ],
+                &TIP_DISTRIBUTION_PROGRAM_ID,
+            )
+            .0
+        })
+        .collect::<Vec<_>>();
+
+    let mut mev_rewards = vec![];
+    for addresses in claim_status_addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        for account in rpc_client.get_multiple_accounts(addresses)? {
+            mev_rewards.push(account.and_then(|account| {
+                if account.owner != TIP_DISTRIBUTION_PROGRAM_ID {
+                    return None;
+                }
+                parse_claim_status(&account.data)
+            }));
+        }
+    }
+    Ok(mev_rewards)
+}
+
+// `ClaimStatus` is an Anchor account: an 8 byte discriminator followed by
+// is_claimed: bool, claimant: Pubkey, claim_status_payer: Pubkey, slot_claimed_at: u64,
+// amount: u64, ...
+fn parse_claim_status(data: &[u8]) -> Option<MevReward> {
+    let u64_at = |offset: usize| {
+        data.get(offset..offset + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    let is_claimed = *data.get(8)? != 0;
+    let slot = u64_at(73)?;
+    let amount = u64_at(81)?;
+    (is_claimed && amount > 0).then_some(MevReward { amount, slot })
+}
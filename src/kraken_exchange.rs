@@ -1,15 +1,128 @@
 use {
-    crate::{exchange::*, token::MaybeToken},
+    crate::{
+        exchange::*,
+        http_client::{self, TracedSend},
+        token::MaybeToken,
+    },
     async_trait::async_trait,
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
     chrono::prelude::*,
+    hmac::{Hmac, Mac},
     kraken_sdk_rest::Client,
     rust_decimal::prelude::*,
+    serde::{de::DeserializeOwned, Deserialize},
+    sha2::{Digest, Sha256, Sha512},
     solana_sdk::pubkey::Pubkey,
-    std::collections::HashMap,
+    std::{
+        collections::HashMap,
+        sync::atomic::{AtomicU64, Ordering},
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tokio::sync::mpsc::UnboundedReceiver,
 };
 
+const API_ENDPOINT: &str = "https://api.kraken.com";
+
+// Ledger entries are listed 50 at a time, most recent first
+const LEDGER_PAGE_SIZE: usize = 50;
+
 pub struct KrakenExchangeClient {
     client: Client,
+    api_key: String,
+    secret: String,
+    last_nonce: AtomicU64,
+}
+
+// Earn and ledger responses, which `kraken_sdk_rest` does not cover
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    error: Vec<String>,
+    result: Option<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Items<T> {
+    items: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LockType {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AprEstimate {
+    low: String,
+    high: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EarnStrategy {
+    id: String,
+    lock_type: LockType,
+    apr_estimate: Option<AprEstimate>,
+    can_allocate: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct NativeAmount {
+    native: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AllocatedAmount {
+    total: NativeAmount,
+}
+
+#[derive(Debug, Deserialize)]
+struct EarnAllocation {
+    strategy_id: String,
+    amount_allocated: AllocatedAmount,
+}
+
+#[derive(Debug, Deserialize)]
+struct LedgerEntry {
+    time: f64,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    subtype: String,
+    asset: String,
+    amount: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ledgers {
+    ledger: HashMap<String, LedgerEntry>,
+    count: usize,
+}
+
+impl LedgerEntry {
+    // Interest paid on an Earn allocation, or a staking reward from before Kraken Earn
+    fn is_reward(&self) -> bool {
+        match self.kind.as_str() {
+            "staking" => true,
+            "earn" => self.subtype == "reward",
+            _ => false,
+        }
+    }
+
+    fn date(&self) -> NaiveDate {
+        Local
+            .timestamp_opt(self.time as i64, 0)
+            .unwrap()
+            .date_naive()
+    }
+
+    fn lending_interest(&self, id: String) -> Result<LendingInterest, Box<dyn std::error::Error>> {
+        Ok(LendingInterest {
+            id,
+            coin: earn_asset_coin(&self.asset).to_string(),
+            amount: parse_f64("amount", &self.amount)?,
+            when: self.date(),
+        })
+    }
 }
 
 fn normalize_coin_name(kraken_coin: &str) -> &str {
@@ -20,6 +133,145 @@ fn normalize_coin_name(kraken_coin: &str) -> &str {
     }
 }
 
+// Balances allocated to Earn are held as separate assets, such as `SOL.F` for flexible and
+// `SOL.S` for bonded SOL
+fn earn_asset_coin(kraken_asset: &str) -> &str {
+    normalize_coin_name(
+        kraken_asset
+            .split_once('.')
+            .map_or(kraken_asset, |(coin, _)| coin),
+    )
+}
+
+impl KrakenExchangeClient {
+    // Kraken rejects a nonce that's not greater than the last one used with the API key. Like
+    // `kraken_sdk_rest`, nonces are milliseconds since the epoch
+    fn nonce(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let next_nonce = |last_nonce: u64| now.max(last_nonce + 1);
+        next_nonce(
+            self.last_nonce
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last_nonce| {
+                    Some(next_nonce(last_nonce))
+                })
+                .unwrap(),
+        )
+    }
+
+    async fn private_request<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let nonce = self.nonce().to_string();
+        let post_data = std::iter::once(("nonce", nonce.as_str()))
+            .chain(params.iter().copied())
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut message = path.as_bytes().to_vec();
+        message.extend(Sha256::digest(format!("{nonce}{post_data}").as_bytes()));
+        let mut mac = Hmac::<Sha512>::new_from_slice(&BASE64.decode(&self.secret)?)
+            .expect("HMAC accepts keys of any size");
+        mac.update(&message);
+        let signature = BASE64.encode(mac.finalize().into_bytes());
+
+        let response = http_client::client()
+            .post(format!("{API_ENDPOINT}{path}"))
+            .header("API-Key", &self.api_key)
+            .header("API-Sign", signature)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(post_data)
+            .traced_send()
+            .await?
+            .json::<Response<T>>()
+            .await?;
+
+        if !response.error.is_empty() {
+            return Err(format!("Kraken API error: {}", response.error.join(", ")).into());
+        }
+        response
+            .result
+            .ok_or_else(|| "Kraken API response without a result".into())
+    }
+
+    // The flexible Earn strategy of `coin`, which can be allocated to and deallocated from at
+    // any time
+    async fn flexible_earn_strategy(
+        &self,
+        coin: &str,
+    ) -> Result<Option<EarnStrategy>, Box<dyn std::error::Error>> {
+        Ok(self
+            .private_request::<Items<EarnStrategy>>(
+                "/0/private/Earn/Strategies",
+                &[("asset", coin)],
+            )
+            .await?
+            .items
+            .into_iter()
+            .find(|strategy| strategy.lock_type.kind == "flex" && strategy.can_allocate))
+    }
+
+    async fn earn_allocation(&self, strategy_id: &str) -> Result<f64, Box<dyn std::error::Error>> {
+        match self
+            .private_request::<Items<EarnAllocation>>(
+                "/0/private/Earn/Allocations",
+                &[("hide_zero_allocations", "true")],
+            )
+            .await?
+            .items
+            .into_iter()
+            .find(|allocation| allocation.strategy_id == strategy_id)
+        {
+            Some(allocation) => parse_f64(
+                "amount_allocated.total.native",
+                &allocation.amount_allocated.total.native,
+            ),
+            None => Ok(0.),
+        }
+    }
+
+    // Reward ledger entries, most recent first, from `start` on. Reads at most `max_pages` pages
+    async fn reward_ledger_entries(
+        &self,
+        start: Option<NaiveDate>,
+        max_pages: usize,
+    ) -> Result<Vec<(String, LedgerEntry)>, Box<dyn std::error::Error>> {
+        let start = start.map(|start| {
+            Local
+                .from_local_datetime(&start.and_hms_opt(0, 0, 0).unwrap())
+                .unwrap()
+                .timestamp()
+                .to_string()
+        });
+
+        let mut rewards = vec![];
+        for page in 0..max_pages {
+            let offset = (page * LEDGER_PAGE_SIZE).to_string();
+            let mut params = vec![("ofs", offset.as_str())];
+            if let Some(ref start) = start {
+                params.push(("start", start));
+            }
+            let ledgers = self
+                .private_request::<Ledgers>("/0/private/Ledgers", &params)
+                .await?;
+
+            let listed = (page + 1) * LEDGER_PAGE_SIZE;
+            let mut entries = ledgers.ledger.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(_, a), (_, b)| b.time.total_cmp(&a.time));
+            rewards.extend(entries.into_iter().filter(|(_, entry)| entry.is_reward()));
+            if listed >= ledgers.count {
+                break;
+            }
+        }
+        Ok(rewards)
+    }
+}
+
 fn deposit_methods() -> HashMap</*coin: */ &'static str, /* method: */ &'static str> {
     HashMap::from([
         ("SOL", "Solana"),
@@ -97,17 +349,20 @@ impl ExchangeClient for KrakenExchangeClient {
                             Some(ExchangeBalance {
                                 total: balance,
                                 available: balance - in_order_sol,
+                                earn: 0.,
                             })
                         }
                         "USDC" => Some(ExchangeBalance {
                             total: balance,
                             available: balance,
+                            earn: 0.,
                         }),
                         "ZUSD" => {
                             assert!(balance >= in_order_usd);
                             Some(ExchangeBalance {
                                 total: balance,
                                 available: balance - in_order_usd,
+                                earn: 0.,
                             })
                         }
                         _ => None,
@@ -117,6 +372,13 @@ impl ExchangeClient for KrakenExchangeClient {
             .collect())
     }
 
+    async fn deposit_memo(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
     async fn recent_deposits(
         &self,
     ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
@@ -128,7 +390,10 @@ impl ExchangeClient for KrakenExchangeClient {
                 if deposit_status.status == "Success" {
                     successful_deposits.push(DepositInfo {
                         tx_id: deposit_status.txid,
+                        coin: coin.to_string(),
                         amount: deposit_status.amount.parse::<f64>().unwrap(),
+                        memo: None,
+                        when: None,
                     });
                 }
             }
@@ -141,6 +406,21 @@ impl ExchangeClient for KrakenExchangeClient {
         Ok(vec![])
     }
 
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        let mut rewards = vec![];
+        for (id, entry) in self.reward_ledger_entries(None, 1).await? {
+            rewards.extend(entry.lending_interest(id)?.reward_info());
+        }
+        Ok(rewards)
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        _token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
     async fn request_withdraw(
         &self,
         _address: Pubkey,
@@ -275,12 +555,76 @@ impl ExchangeClient for KrakenExchangeClient {
         Err("Invalid API response".into())
     }
 
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        let response = self.client.get_order_book(pair).count(100).send().await?;
+
+        let order_book = response.get(pair).ok_or("Invalid API response")?;
+        Ok(OrderBook {
+            bids: order_book
+                .bids
+                .iter()
+                .filter_map(|order_book_tier| {
+                    Some((
+                        order_book_tier.0.parse::<f64>().ok()?,
+                        order_book_tier.1.parse::<f64>().ok()?,
+                    ))
+                })
+                .collect(),
+            asks: order_book
+                .asks
+                .iter()
+                .filter_map(|order_book_tier| {
+                    Some((
+                        order_book_tier.0.parse::<f64>().ok()?,
+                        order_book_tier.1.parse::<f64>().ok()?,
+                    ))
+                })
+                .collect(),
+        })
+    }
+
     async fn place_order(
         &self,
         pair: &str,
         side: OrderSide,
         price: f64,
         amount: f64,
+        options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if pair != self.preferred_solusd_pair() {
+            // Currently only the `preferred_solusd_pair` is supported due to limitations in how
+            // the `available` token balances are computed in `Self::balances()`
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        let side = match side {
+            OrderSide::Buy => kraken_sdk_rest::OrderSide::Buy,
+            OrderSide::Sell => kraken_sdk_rest::OrderSide::Sell,
+        };
+
+        if options.time_in_force != TimeInForce::GoodTilCancelled {
+            return Err(format!("Unsupported time in force: {}", options.time_in_force).into());
+        }
+
+        let mut request =
+            self.client
+                .add_limit_order(pair, side, &amount.to_string(), &price.to_string());
+        if options.post_only {
+            request = request.post_only();
+        }
+        let response = request.send().await?;
+        //dbg!(&response);
+
+        let txid = response.txid.unwrap_or_default();
+        assert_eq!(txid.len(), 1);
+        Ok(txid[0].to_owned())
+    }
+
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
     ) -> Result<OrderId, Box<dyn std::error::Error>> {
         if pair != self.preferred_solusd_pair() {
             // Currently only the `preferred_solusd_pair` is supported due to limitations in how
@@ -295,11 +639,9 @@ impl ExchangeClient for KrakenExchangeClient {
 
         let response = self
             .client
-            .add_limit_order(pair, side, &amount.to_string(), &price.to_string())
-            .post_only()
+            .add_market_order(pair, side, &amount.to_string())
             .send()
             .await?;
-        //dbg!(&response);
 
         let txid = response.txid.unwrap_or_default();
         assert_eq!(txid.len(), 1);
@@ -327,7 +669,8 @@ impl ExchangeClient for KrakenExchangeClient {
             .ok_or_else(|| format!("Unknown order id: {order_id}"))?;
         //dbg!(&order);
 
-        assert_eq!(order.descr.ordertype, "limit");
+        let market_order = order.descr.ordertype == "market";
+        assert!(market_order || order.descr.ordertype == "limit");
 
         // Currently only the `preferred_solusd_pair` is supported due to limitations in how
         // the `available` token balances are computed in `Self::balances()`
@@ -356,7 +699,12 @@ impl ExchangeClient for KrakenExchangeClient {
                 "buy" => OrderSide::Buy,
                 side => panic!("Invalid order side: {side}"),
             },
-            price: order.descr.price.parse::<f64>().unwrap(),
+            // Market orders have no price of their own, `price` is the average fill price
+            price: if market_order {
+                order.price.parse::<f64>().unwrap()
+            } else {
+                order.descr.price.parse::<f64>().unwrap()
+            },
             amount: order.vol.parse::<f64>().unwrap(),
             filled_amount: order.vol_exec.parse::<f64>().unwrap(),
             last_update,
@@ -364,26 +712,151 @@ impl ExchangeClient for KrakenExchangeClient {
         })
     }
 
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        let response = self.client.get_open_orders().send().await?;
+
+        let today = Local::now().date_naive();
+        Ok(response
+            .open
+            .into_iter()
+            .filter(|(_, order)| order.descr.pair == pair)
+            .map(|(order_id, order)| {
+                (
+                    order_id,
+                    OrderStatus {
+                        open: true,
+                        side: match order.descr.orderside.as_str() {
+                            "sell" => OrderSide::Sell,
+                            "buy" => OrderSide::Buy,
+                            side => panic!("Invalid order side: {side}"),
+                        },
+                        price: order.descr.price.parse::<f64>().unwrap(),
+                        amount: order.vol.parse::<f64>().unwrap(),
+                        filled_amount: order.vol_exec.parse::<f64>().unwrap(),
+                        last_update: today,
+                        fee: None,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    // Lending is an allocation to the coin's flexible Earn strategy
     async fn get_lending_info(
         &self,
-        _coin: &str,
+        coin: &str,
     ) -> Result<Option<LendingInfo>, Box<dyn std::error::Error>> {
-        Err("Lending not currently supported for Kraken".into())
+        let Some(strategy) = self.flexible_earn_strategy(coin).await? else {
+            return Ok(None);
+        };
+        let offered = self.earn_allocation(&strategy.id).await?;
+
+        let balances = self.client.get_account_balance().send().await?;
+        let available = balances
+            .iter()
+            .find(|(kraken_coin, _)| normalize_coin_name(kraken_coin) == coin)
+            .map(|(_, balance)| parse_f64("balance", balance))
+            .transpose()?
+            .unwrap_or_default();
+
+        // Kraken only publishes a range for the rate of the next payout
+        let estimate_rate = match strategy.apr_estimate {
+            Some(apr_estimate) => {
+                (parse_f64("apr_estimate.low", &apr_estimate.low)?
+                    + parse_f64("apr_estimate.high", &apr_estimate.high)?)
+                    / 2.
+            }
+            None => 0.,
+        };
+        Ok(Some(LendingInfo {
+            lendable: available + offered,
+            offered,
+            locked: 0.,
+            estimate_rate,
+            previous_rate: estimate_rate,
+        }))
     }
 
     async fn get_lending_history(
         &self,
-        _lending_history: LendingHistory,
-    ) -> Result<HashMap<String, f64>, Box<dyn std::error::Error>> {
-        Err("Lending not currently supported for Kraken".into())
+        lending_history: LendingHistory,
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
+        let (start_date, end_date) = match lending_history {
+            LendingHistory::Range {
+                start_date,
+                end_date,
+            } => (start_date, end_date),
+            LendingHistory::Previous { days } => {
+                let today = Local::now().date_naive();
+                (today - chrono::Duration::days(days as i64), today)
+            }
+        };
+
+        let mut history = vec![];
+        for (id, entry) in self
+            .reward_ledger_entries(Some(start_date), usize::MAX)
+            .await?
+        {
+            if entry.date() <= end_date {
+                history.push(entry.lending_interest(id)?);
+            }
+        }
+        Ok(history)
     }
 
+    // Allocates to, or deallocates from, the flexible Earn strategy of `coin` so that `size` is
+    // allocated
     async fn submit_lending_offer(
         &self,
-        _coin: &str,
-        _size: f64,
+        coin: &str,
+        size: f64,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        Err("Lending not currently supported for Kraken".into())
+        let strategy = self
+            .flexible_earn_strategy(coin)
+            .await?
+            .ok_or_else(|| format!("No flexible Earn strategy for {coin}"))?;
+        let offered = self.earn_allocation(&strategy.id).await?;
+
+        let (path, amount) = if size > offered {
+            ("/0/private/Earn/Allocate", size - offered)
+        } else {
+            ("/0/private/Earn/Deallocate", offered - size)
+        };
+        if amount > 0. {
+            self.private_request::<bool>(
+                path,
+                &[
+                    ("amount", &amount.to_string()),
+                    ("strategy_id", &strategy.id),
+                ],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        Err("Margin not currently supported for Kraken".into())
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Kraken".into())
+    }
+
+    async fn convert_dust(
+        &self,
+        _coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for Kraken".into())
     }
 
     fn preferred_solusd_pair(&self) -> &'static str {
@@ -396,6 +869,7 @@ pub fn new(
         api_key,
         secret,
         subaccount,
+        ..
     }: ExchangeCredentials,
 ) -> Result<KrakenExchangeClient, Box<dyn std::error::Error>> {
     if subaccount.is_some() {
@@ -404,5 +878,8 @@ pub fn new(
 
     Ok(KrakenExchangeClient {
         client: Client::new(&api_key, &secret),
+        api_key,
+        secret,
+        last_nonce: AtomicU64::default(),
     })
 }
@@ -0,0 +1,774 @@
+use {
+    crate::{
+        exchange::*,
+        http_client::{self, TracedSend},
+        token::MaybeToken,
+    },
+    async_trait::async_trait,
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    chrono::prelude::*,
+    hmac::{Hmac, Mac},
+    serde::{de::DeserializeOwned, Deserialize},
+    sha2::Sha256,
+    solana_sdk::pubkey::Pubkey,
+    std::collections::HashMap,
+    tokio::sync::mpsc::UnboundedReceiver,
+};
+
+const API_ENDPOINT: &str = "https://api.kucoin.com";
+
+// KuCoin's name for the Solana network
+const SOLANA_CHAIN: &str = "sol";
+
+const SUPPORTED_COINS: &[&str] = &["SOL"];
+const SUPPORTED_PAIRS: &[&str] = &["SOLUSDT", "SOLUSDC"];
+
+pub struct KucoinExchangeClient {
+    client: reqwest::Client,
+    api_key: String,
+    secret: String,
+    passphrase: String,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    code: String,
+    msg: Option<String>,
+    data: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct DepositAddress {
+    address: String,
+    memo: Option<String>,
+    chain: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Deposit {
+    amount: String,
+    wallet_tx_id: Option<String>,
+    memo: Option<String>,
+    is_inner: bool,
+    created_at: i64, // milliseconds since the unix epoch
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Withdrawal {
+    id: String,
+    address: String,
+    amount: String,
+    wallet_tx_id: Option<String>,
+    // PROCESSING, WALLET_PROCESSING, SUCCESS or FAILURE
+    status: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawalCreated {
+    withdrawal_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawalQuotas {
+    remain_amount: String,
+    withdraw_min_fee: String,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    currency: String,
+    #[serde(rename = "type")]
+    kind: String,
+    balance: String,
+    available: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ticker {
+    best_bid: String,
+    best_ask: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Stats {
+    high: String,
+    low: String,
+    vol: String,
+    vol_value: String,
+}
+
+#[derive(Deserialize)]
+struct Book {
+    bids: Vec<[String; 2]>,
+    asks: Vec<[String; 2]>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderCreated {
+    order_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Order {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    side: String,
+    price: String,
+    size: String,
+    deal_funds: String,
+    deal_size: String,
+    fee: String,
+    fee_currency: String,
+    is_active: bool,
+    created_at: i64,
+}
+
+// KuCoin symbols separate the base and quote coins with a dash, such as `SOL-USDT`
+fn symbol(pair: &str) -> String {
+    format!("SOL-{}", pair.trim_start_matches("SOL"))
+}
+
+// On-chain transaction ids may carry an `@<index>` suffix
+fn strip_tx_index(wallet_tx_id: String) -> String {
+    match wallet_tx_id.split_once('@') {
+        Some((tx_id, _)) => tx_id.to_string(),
+        None => wallet_tx_id,
+    }
+}
+
+impl Order {
+    fn into_order_status(self) -> Result<(OrderId, OrderStatus), Box<dyn std::error::Error>> {
+        let side = match self.side.as_str() {
+            "buy" => OrderSide::Buy,
+            "sell" => OrderSide::Sell,
+            side => return Err(format!("Invalid order side: {side}").into()),
+        };
+
+        let filled_amount = parse_f64("dealSize", &self.deal_size)?;
+        let fee = parse_f64("fee", &self.fee)?;
+
+        let last_update = Local
+            .timestamp_millis_opt(self.created_at)
+            .single()
+            .ok_or("Invalid `createdAt` field")?
+            .date_naive();
+
+        Ok((
+            self.id,
+            OrderStatus {
+                open: self.is_active,
+                side,
+                // Market orders have no price of their own, report the average fill price instead
+                price: if self.kind == "market" {
+                    if filled_amount > 0. {
+                        parse_f64("dealFunds", &self.deal_funds)? / filled_amount
+                    } else {
+                        0.
+                    }
+                } else {
+                    parse_f64("price", &self.price)?
+                },
+                amount: parse_f64("size", &self.size)?,
+                filled_amount,
+                last_update,
+                fee: (fee > f64::EPSILON).then_some((fee, self.fee_currency)),
+            },
+        ))
+    }
+}
+
+impl KucoinExchangeClient {
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let response = request.traced_send().await?.json::<Response>().await?;
+
+        if response.code != "200000" {
+            return Err(format!(
+                "KuCoin API error {}: {}",
+                response.code,
+                response.msg.unwrap_or_default()
+            )
+            .into());
+        }
+        Ok(serde_json::from_value(
+            response.data.unwrap_or(serde_json::Value::Null),
+        )?)
+    }
+
+    fn hmac_base64(&self, message: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(message.as_bytes());
+        BASE64.encode(mac.finalize().into_bytes())
+    }
+
+    // Authenticates a request for `path_and_query` whose JSON body, if any, is `body`
+    fn sign(
+        &self,
+        request: reqwest::RequestBuilder,
+        method: &str,
+        path_and_query: &str,
+        body: &str,
+    ) -> reqwest::RequestBuilder {
+        let timestamp = timestamp_millis().to_string();
+        let signature = self.hmac_base64(&format!("{timestamp}{method}{path_and_query}{body}"));
+
+        request
+            .header("KC-API-KEY", &self.api_key)
+            .header("KC-API-SIGN", signature)
+            .header("KC-API-TIMESTAMP", timestamp)
+            .header("KC-API-PASSPHRASE", self.hmac_base64(&self.passphrase))
+            .header("KC-API-KEY-VERSION", "2")
+    }
+
+    fn path_and_query(path: &str, query: &[(&str, &str)]) -> String {
+        // All query values used are URL-safe, so the signed query string matches the one sent
+        if query.is_empty() {
+            path.to_string()
+        } else {
+            let query_string = query
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>()
+                .join("&");
+            format!("{path}?{query_string}")
+        }
+    }
+
+    async fn public_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        self.send(
+            self.client
+                .get(format!("{API_ENDPOINT}{path}"))
+                .query(query),
+        )
+        .await
+    }
+
+    async fn private_get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let path_and_query = Self::path_and_query(path, query);
+        let request = self.client.get(format!("{API_ENDPOINT}{path_and_query}"));
+        self.send(self.sign(request, "GET", &path_and_query, ""))
+            .await
+    }
+
+    async fn private_post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: serde_json::Value,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let body = body.to_string();
+        let request = self
+            .client
+            .post(format!("{API_ENDPOINT}{path}"))
+            .header("Content-Type", "application/json");
+        let request = self.sign(request, "POST", path, &body);
+        self.send(request.body(body)).await
+    }
+
+    async fn private_delete<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let request = self.client.delete(format!("{API_ENDPOINT}{path}"));
+        self.send(self.sign(request, "DELETE", path, "")).await
+    }
+
+    async fn withdrawal_quotas(
+        &self,
+        token: MaybeToken,
+    ) -> Result<WithdrawalQuotas, Box<dyn std::error::Error>> {
+        self.private_get(
+            "/api/v1/withdrawals/quotas",
+            &[("currency", token.name()), ("chain", SOLANA_CHAIN)],
+        )
+        .await
+    }
+
+    async fn solana_deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<DepositAddress, Box<dyn std::error::Error>> {
+        let coin = token.name();
+        if !SUPPORTED_COINS.contains(&coin) {
+            return Err(format!("{token} deposits are not supported").into());
+        }
+
+        let deposit_address = match self
+            .private_get::<Vec<DepositAddress>>("/api/v2/deposit-addresses", &[("currency", coin)])
+            .await?
+            .into_iter()
+            .find(|deposit_address| {
+                deposit_address
+                    .chain
+                    .as_deref()
+                    .is_some_and(|chain| chain.eq_ignore_ascii_case(SOLANA_CHAIN))
+            }) {
+            Some(deposit_address) => deposit_address,
+            None => {
+                self.private_post::<DepositAddress>(
+                    "/api/v1/deposit-addresses",
+                    serde_json::json!({
+                        "currency": coin,
+                        "chain": SOLANA_CHAIN,
+                    }),
+                )
+                .await?
+            }
+        };
+        Ok(deposit_address)
+    }
+}
+
+#[async_trait]
+impl ExchangeClient for KucoinExchangeClient {
+    async fn deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Pubkey, Box<dyn std::error::Error>> {
+        Ok(self
+            .solana_deposit_address(token)
+            .await?
+            .address
+            .parse::<Pubkey>()?)
+    }
+
+    // Deposits to an address shared between accounts are only credited when they include the memo
+    async fn deposit_memo(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(self
+            .solana_deposit_address(token)
+            .await?
+            .memo
+            .filter(|memo| !memo.is_empty()))
+    }
+
+    async fn recent_deposits(
+        &self,
+    ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
+        let deposits = self
+            .private_get::<Page<Deposit>>(
+                "/api/v1/deposits",
+                &[("currency", "SOL"), ("status", "SUCCESS")],
+            )
+            .await?;
+
+        let mut successful_deposits = vec![];
+        for deposit in deposits.items {
+            // Internal transfers between KuCoin accounts have no on-chain transaction
+            if deposit.is_inner {
+                continue;
+            }
+            let memo = deposit.memo.filter(|memo| !memo.is_empty());
+            // A deposit with a memo can still be matched by it without its transaction id
+            let tx_id = match deposit.wallet_tx_id {
+                Some(wallet_tx_id) => strip_tx_index(wallet_tx_id),
+                None if memo.is_some() => String::new(),
+                None => continue,
+            };
+            successful_deposits.push(DepositInfo {
+                tx_id,
+                coin: "SOL".into(),
+                amount: parse_f64("amount", &deposit.amount)?,
+                memo,
+                when: Some(deposit.created_at / 1_000),
+            });
+        }
+        Ok(Some(successful_deposits))
+    }
+
+    async fn recent_withdrawals(&self) -> Result<Vec<WithdrawalInfo>, Box<dyn std::error::Error>> {
+        let withdrawals = self
+            .private_get::<Page<Withdrawal>>("/api/v1/withdrawals", &[("currency", "SOL")])
+            .await?;
+
+        let mut recent_withdrawals = vec![];
+        for withdrawal in withdrawals.items {
+            let (completed, tx_id) = match withdrawal.status.as_str() {
+                "SUCCESS" => (true, withdrawal.wallet_tx_id.map(strip_tx_index)),
+                "FAILURE" => (true, None),
+                _ => (false, None),
+            };
+            recent_withdrawals.push(WithdrawalInfo {
+                address: withdrawal.address.parse::<Pubkey>().unwrap_or_default(),
+                token: MaybeToken::SOL(),
+                amount: parse_f64("amount", &withdrawal.amount)?,
+                tag: withdrawal.id,
+                completed,
+                tx_id,
+            });
+        }
+        Ok(recent_withdrawals)
+    }
+
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        let quotas = self.withdrawal_quotas(token).await?;
+        Ok(Some(WithdrawalLimit {
+            remaining: Some(parse_f64("remainAmount", &quotas.remain_amount)?),
+            ..WithdrawalLimit::default()
+        }))
+    }
+
+    async fn request_withdraw(
+        &self,
+        address: Pubkey,
+        token: MaybeToken,
+        amount: f64,
+        _withdrawal_password: Option<String>,
+        _withdrawal_code: Option<String>,
+    ) -> Result<(/* withdraw_id: */ String, /*withdraw_fee: */ f64), Box<dyn std::error::Error>>
+    {
+        if !SUPPORTED_COINS.contains(&token.name()) {
+            return Err(format!("{token} withdrawals are not supported").into());
+        }
+
+        let quotas = self.withdrawal_quotas(token).await?;
+        let withdrawal = self
+            .private_post::<WithdrawalCreated>(
+                "/api/v1/withdrawals",
+                serde_json::json!({
+                    "currency": token.name(),
+                    "address": address.to_string(),
+                    "amount": amount.to_string(),
+                    "chain": SOLANA_CHAIN,
+                }),
+            )
+            .await?;
+
+        Ok((
+            withdrawal.withdrawal_id,
+            parse_f64("withdrawMinFee", &quotas.withdraw_min_fee)?,
+        ))
+    }
+
+    async fn balances(
+        &self,
+    ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
+        // Deposits are credited to the main account while orders trade from the trade account,
+        // so only the trade account balance is `available`
+        let mut balances = HashMap::<String, ExchangeBalance>::new();
+        for account in self
+            .private_get::<Vec<Account>>("/api/v1/accounts", &[])
+            .await?
+        {
+            if account.currency == "SOL" || USD_COINS.contains(&account.currency.as_str()) {
+                let balance = balances.entry(account.currency).or_default();
+                balance.total += parse_f64("balance", &account.balance)?;
+                if account.kind == "trade" {
+                    balance.available += parse_f64("available", &account.available)?;
+                }
+            }
+        }
+        Ok(balances)
+    }
+
+    async fn print_market_info(
+        &self,
+        pair: &str,
+        format: MarketInfoFormat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let symbol = symbol(pair);
+        let ticker = self
+            .public_get::<Ticker>("/api/v1/market/orderbook/level1", &[("symbol", &symbol)])
+            .await?;
+        let stats = self
+            .public_get::<Stats>("/api/v1/market/stats", &[("symbol", &symbol)])
+            .await?;
+        let weighted_24h_avg_price =
+            parse_f64("volValue", &stats.vol_value)? / parse_f64("vol", &stats.vol)?;
+
+        match format {
+            MarketInfoFormat::All => {
+                println!("Pair: {pair}");
+                println!(
+                    "Ask: ${}, Bid: ${}, High: ${}, Low: ${}, ",
+                    ticker.best_ask, ticker.best_bid, stats.high, stats.low
+                );
+                println!("Weighted 24h average price: ${weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Ask => {
+                println!("{}", ticker.best_ask);
+            }
+            MarketInfoFormat::Weighted24hAveragePrice => {
+                println!("{weighted_24h_avg_price:.4}");
+            }
+            MarketInfoFormat::Hourly => {
+                let end_at = Utc::now().timestamp();
+                let start_at = (end_at - 24 * 60 * 60).to_string();
+                let end_at = end_at.to_string();
+
+                // Each candle is [time, open, close, high, low, volume, turnover], newest first
+                let candles = self
+                    .public_get::<Vec<[String; 7]>>(
+                        "/api/v1/market/candles",
+                        &[
+                            ("type", "1hour"),
+                            ("symbol", &symbol),
+                            ("startAt", &start_at),
+                            ("endAt", &end_at),
+                        ],
+                    )
+                    .await?;
+
+                println!("hour,low,high,average,volume");
+                for candle in candles.iter().rev() {
+                    let time = Local
+                        .timestamp_opt(candle[0].parse::<i64>()?, 0)
+                        .single()
+                        .ok_or("Invalid API response")?;
+                    let high = parse_f64("high", &candle[3])?;
+                    let low = parse_f64("low", &candle[4])?;
+                    println!(
+                        "{},{},{},{},{}",
+                        time,
+                        low,
+                        high,
+                        (low + high) / 2.,
+                        candle[5]
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn bid_ask(&self, pair: &str) -> Result<BidAsk, Box<dyn std::error::Error>> {
+        let ticker = self
+            .public_get::<Ticker>(
+                "/api/v1/market/orderbook/level1",
+                &[("symbol", &symbol(pair))],
+            )
+            .await?;
+
+        Ok(BidAsk {
+            bid_price: parse_f64("bestBid", &ticker.best_bid)?,
+            ask_price: parse_f64("bestAsk", &ticker.best_ask)?,
+        })
+    }
+
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        let book = self
+            .public_get::<Book>(
+                "/api/v1/market/orderbook/level2_100",
+                &[("symbol", &symbol(pair))],
+            )
+            .await?;
+
+        let parse_levels = |levels: Vec<[String; 2]>| -> Vec<(f64, f64)> {
+            levels
+                .into_iter()
+                .filter_map(|[price, size]| {
+                    Some((price.parse::<f64>().ok()?, size.parse::<f64>().ok()?))
+                })
+                .collect()
+        };
+        Ok(OrderBook {
+            bids: parse_levels(book.bids),
+            asks: parse_levels(book.asks),
+        })
+    }
+
+    async fn place_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        price: f64,
+        amount: f64,
+        options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if !SUPPORTED_PAIRS.contains(&pair) {
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        let time_in_force = match (options.post_only, options.time_in_force) {
+            (true, TimeInForce::GoodTilCancelled) => "GTC",
+            (true, time_in_force) => {
+                return Err(format!("Unsupported post-only time in force: {time_in_force}").into())
+            }
+            (false, TimeInForce::GoodTilCancelled) => "GTC",
+            (false, TimeInForce::ImmediateOrCancel) => "IOC",
+            (false, TimeInForce::FillOrKill) => "FOK",
+        };
+
+        let order = self
+            .private_post::<OrderCreated>(
+                "/api/v1/orders",
+                serde_json::json!({
+                    "clientOid": timestamp_millis().to_string(),
+                    "symbol": symbol(pair),
+                    "side": side.to_string().to_lowercase(),
+                    "type": "limit",
+                    "price": price.to_string(),
+                    "size": amount.to_string(),
+                    "timeInForce": time_in_force,
+                    "postOnly": options.post_only,
+                }),
+            )
+            .await?;
+
+        Ok(order.order_id)
+    }
+
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        if !SUPPORTED_PAIRS.contains(&pair) {
+            return Err(format!("Unsupported trading pair: {pair}").into());
+        }
+
+        let order = self
+            .private_post::<OrderCreated>(
+                "/api/v1/orders",
+                serde_json::json!({
+                    "clientOid": timestamp_millis().to_string(),
+                    "symbol": symbol(pair),
+                    "side": side.to_string().to_lowercase(),
+                    "type": "market",
+                    "size": amount.to_string(),
+                }),
+            )
+            .await?;
+
+        Ok(order.order_id)
+    }
+
+    async fn cancel_order(
+        &self,
+        _pair: &str,
+        order_id: &OrderId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.private_delete::<serde_json::Value>(&format!("/api/v1/orders/{order_id}"))
+            .await?;
+        Ok(())
+    }
+
+    async fn order_status(
+        &self,
+        _pair: &str,
+        order_id: &OrderId,
+    ) -> Result<OrderStatus, Box<dyn std::error::Error>> {
+        let (_, order_status) = self
+            .private_get::<Order>(&format!("/api/v1/orders/{order_id}"), &[])
+            .await?
+            .into_order_status()?;
+        Ok(order_status)
+    }
+
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        self.private_get::<Page<Order>>(
+            "/api/v1/orders",
+            &[("status", "active"), ("symbol", &symbol(pair))],
+        )
+        .await?
+        .items
+        .into_iter()
+        .map(Order::into_order_status)
+        .collect()
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn get_lending_info(
+        &self,
+        _coin: &str,
+    ) -> Result<Option<LendingInfo>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for KuCoin".into())
+    }
+
+    async fn get_lending_history(
+        &self,
+        _lending_history: LendingHistory,
+    ) -> Result<Vec<LendingInterest>, Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for KuCoin".into())
+    }
+
+    async fn submit_lending_offer(
+        &self,
+        _coin: &str,
+        _size: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Err("Lending not currently supported for KuCoin".into())
+    }
+
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        Err("Margin not currently supported for KuCoin".into())
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for KuCoin".into())
+    }
+
+    async fn convert_dust(
+        &self,
+        _coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported for KuCoin".into())
+    }
+
+    fn preferred_solusd_pair(&self) -> &'static str {
+        "SOLUSDT"
+    }
+}
+
+pub fn new(
+    ExchangeCredentials {
+        api_key,
+        secret,
+        subaccount,
+        passphrase,
+    }: ExchangeCredentials,
+) -> Result<KucoinExchangeClient, Box<dyn std::error::Error>> {
+    if subaccount.is_some() {
+        return Err("subaccounts not supported".into());
+    }
+
+    Ok(KucoinExchangeClient {
+        client: http_client::client(),
+        api_key,
+        secret,
+        passphrase: passphrase
+            .ok_or("KuCoin requires an API passphrase, set it with `--passphrase`")?,
+    })
+}
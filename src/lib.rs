@@ -1,26 +1,67 @@
 use {
+    chain_client::ChainClient,
+    operation_stats::Operation,
+    priority_fee::{override_compute_unit_price, ComputeBudget},
+    rate_limited_sender::{endpoint_label, RateLimitedSender, RpcEndpointState, RpcRequestStats},
+    serde_json::json,
     solana_clap_utils::input_validators::normalize_to_url_if_moniker,
     solana_client::{
-        rpc_client::{RpcClient, SerializableTransaction},
+        client_error::Result as ClientResult,
+        nonce_utils,
+        rpc_client::{RpcClient, RpcClientConfig, SerializableTransaction},
+        rpc_request::RpcRequest,
         rpc_response,
     },
-    solana_sdk::{clock::Slot, commitment_config::CommitmentConfig},
+    solana_sdk::{
+        address_lookup_table::{state::AddressLookupTable, AddressLookupTableAccount},
+        clock::Slot,
+        commitment_config::CommitmentConfig,
+        epoch_info::EpochInfo,
+        hash::Hash,
+        instruction::{CompiledInstruction, Instruction},
+        message::{v0, Message, VersionedMessage},
+        native_token::Sol,
+        pubkey,
+        pubkey::Pubkey,
+        signature::{Keypair, Signature, Signer},
+        signer::{signers::Signers, SignerError},
+        system_instruction::{self, SystemInstruction},
+        system_program,
+        transaction::{Result as TransactionResult, Transaction, VersionedTransaction},
+    },
     std::{
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
         thread::sleep,
         time::{Duration, Instant},
     },
 };
 
 pub mod binance_exchange;
+pub mod bitstamp_exchange;
+pub mod bybit_exchange;
+pub mod chain_client;
 pub mod coin_gecko;
 pub mod coinbase_exchange;
 pub mod exchange;
+pub mod gemini_exchange;
+pub mod healthcheck;
 pub mod helius_rpc;
+pub mod http_client;
 pub mod kraken_exchange;
+pub mod kucoin_exchange;
 pub mod metrics;
 pub mod notifier;
+pub mod number_format;
+pub mod operation_stats;
 pub mod priority_fee;
+pub mod rate_limited_sender;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 pub mod token;
+pub mod transaction_batch;
 pub mod vendor;
 //pub mod tulip;
 
@@ -45,9 +86,175 @@ where
     Ok(())
 }
 
+pub const DEFAULT_JITO_BLOCK_ENGINE_URL: &str =
+    "https://mainnet.block-engine.jito.wtf/api/v1/bundles";
+
+// Jito validators only include a bundle that transfers a tip to one of these accounts
+const JITO_TIP_ACCOUNTS: &[Pubkey] = &[
+    pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5"),
+    pubkey!("HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe"),
+    pubkey!("Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY"),
+    pubkey!("ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49"),
+    pubkey!("DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh"),
+    pubkey!("ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt"),
+    pubkey!("DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL"),
+    pubkey!("3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT"),
+];
+
+// Submits transactions to a Jito block engine as bundles, which are paid for with a tip
+pub struct Jito {
+    block_engine: RpcClient,
+    tip: u64, // lamports
+    tip_payer: Keypair,
+}
+
+impl Jito {
+    // Submits `transaction` in a bundle along with a transaction that pays the tip. The bundle
+    // lands atomically, so the tip is only paid if `transaction` is processed too
+    fn send_bundle(
+        &self,
+        rpc_client: &RpcClient,
+        transaction: &impl SerializableTransaction,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let signature = transaction.get_signature();
+        let tip_account =
+            JITO_TIP_ACCOUNTS[signature.as_ref()[0] as usize % JITO_TIP_ACCOUNTS.len()];
+
+        let tip_transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &self.tip_payer.pubkey(),
+                &tip_account,
+                self.tip,
+            )],
+            Some(&self.tip_payer.pubkey()),
+            &[&self.tip_payer],
+            rpc_client.get_latest_blockhash()?,
+        );
+
+        let bundle = [
+            bs58::encode(bincode::serialize(transaction)?).into_string(),
+            bs58::encode(bincode::serialize(&tip_transaction)?).into_string(),
+        ];
+        Ok(self.block_engine.send::<String>(
+            RpcRequest::Custom {
+                method: "sendBundle",
+            },
+            json!([bundle]),
+        )?)
+    }
+}
+
+// How often `send_transaction_until_expired` resends a transaction and polls for its
+// confirmation, and how long it waits in total before giving up
+#[derive(Debug, Clone, Copy)]
+pub struct SendConfig {
+    pub resend_interval: Duration,
+    pub poll_interval: Duration,
+    pub timeout: Option<Duration>, // `None` waits until the transaction expires
+    // Resends before the priority fee is raised, see
+    // `send_transaction_until_expired_with_fee_escalation`
+    pub escalate_after_resends: Option<usize>,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        Self {
+            resend_interval: Duration::from_secs(2),
+            poll_interval: Duration::from_millis(500),
+            timeout: None,
+            escalate_after_resends: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendTransactionStatus {
+    Confirmed,
+    Failed,
+    Expired,
+    TimedOut, // gave up waiting, but the transaction may still land until it expires
+}
+
+impl SendTransactionStatus {
+    pub fn confirmed(self) -> bool {
+        self == Self::Confirmed
+    }
+
+    // The transaction is known to have had no effect, so any record of it may be cancelled
+    pub fn abandoned(self) -> bool {
+        matches!(self, Self::Failed | Self::Expired)
+    }
+
+    // The transaction landed, successfully or not, so its fees were paid
+    pub fn landed(self) -> bool {
+        matches!(self, Self::Confirmed | Self::Failed)
+    }
+}
+
+// An endpoint is healthy while it is at most this many slots behind the most advanced endpoint,
+// and while at most this fraction of the requests made to it fail
+const MAX_HEALTHY_SLOT_LAG: Slot = 150;
+const MAX_HEALTHY_ERROR_RATE: f64 = 0.25;
+
+// How often the health of the endpoints is rechecked when there are several
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Request counts are halved once they reach this many, so that the error rate reflects recent
+// requests
+const HEALTH_REQUEST_WINDOW: u64 = 100;
+
+#[derive(Default)]
+struct EndpointHealth {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    slot: AtomicU64, // as of the last health check, 0 if the endpoint did not answer
+}
+
+impl EndpointHealth {
+    fn record<T>(&self, result: &ClientResult<T>) {
+        let requests = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        if result.is_err() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if requests >= HEALTH_REQUEST_WINDOW {
+            self.requests.store(requests / 2, Ordering::Relaxed);
+            self.errors
+                .store(self.errors.load(Ordering::Relaxed) / 2, Ordering::Relaxed);
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        let requests = self.requests.load(Ordering::Relaxed);
+        if requests == 0 {
+            0.
+        } else {
+            self.errors.load(Ordering::Relaxed) as f64 / requests as f64
+        }
+    }
+
+    fn is_healthy(&self, max_slot: Slot) -> bool {
+        let slot = self.slot.load(Ordering::Relaxed);
+        slot > 0
+            && max_slot.saturating_sub(slot) <= MAX_HEALTHY_SLOT_LAG
+            && self.error_rate() <= MAX_HEALTHY_ERROR_RATE
+    }
+}
+
+// The first `num_endpoints` clients are the `--url` endpoints, which requests fail over between.
+// The rest only send transactions
 pub struct RpcClients {
     clients: Vec<(String, RpcClient)>,
+    endpoint_states: Vec<Arc<RpcEndpointState>>, // of each client
+    num_endpoints: usize,
+    health: Vec<EndpointHealth>,
+    active: AtomicUsize, // the endpoint `default()` returns
+    last_health_check: Mutex<Option<Instant>>,
     helius: Option<RpcClient>,
+    jito: Option<Jito>,
+    address_lookup_tables: Vec<Pubkey>,
+    max_total_fees: Option<u64>,
+    total_fees: AtomicU64,
+    send_config: SendConfig,
 }
 
 impl RpcClients {
@@ -56,32 +263,248 @@ impl RpcClients {
         send_json_rpc_urls: Option<String>,
         helius: Option<String>,
     ) -> Self {
-        let mut json_rpc_urls = vec![json_rpc_url];
+        let mut json_rpc_urls = json_rpc_url
+            .split(',')
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let num_endpoints = json_rpc_urls.len();
         if let Some(send_json_rpc_urls) = send_json_rpc_urls {
             for send_json_rpc_url in send_json_rpc_urls.split(',') {
                 json_rpc_urls.push(send_json_rpc_url.into());
             }
         }
 
+        let endpoint_states = json_rpc_urls
+            .iter()
+            .map(|_| Arc::new(RpcEndpointState::default()))
+            .collect::<Vec<_>>();
+
         Self {
             clients: json_rpc_urls
                 .into_iter()
-                .map(|json_rpc_url| {
+                .zip(&endpoint_states)
+                .map(|(json_rpc_url, endpoint_state)| {
                     let json_rpc_url = normalize_to_url_if_moniker(json_rpc_url);
                     (
                         json_rpc_url.clone(),
-                        RpcClient::new_with_commitment(json_rpc_url, CommitmentConfig::confirmed()),
+                        RpcClient::new_sender(
+                            RateLimitedSender::new(json_rpc_url, endpoint_state.clone()),
+                            RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+                        ),
                     )
                 })
                 .collect(),
+            endpoint_states,
+            num_endpoints,
+            health: (0..num_endpoints)
+                .map(|_| EndpointHealth::default())
+                .collect(),
+            active: AtomicUsize::default(),
+            last_health_check: Mutex::default(),
             helius: helius.map(|helius_json_rpc_url| {
                 RpcClient::new_with_commitment(helius_json_rpc_url, CommitmentConfig::confirmed())
             }),
+            jito: None,
+            address_lookup_tables: vec![],
+            max_total_fees: None,
+            total_fees: AtomicU64::default(),
+            send_config: SendConfig::default(),
+        }
+    }
+
+    pub fn with_send_config(mut self, send_config: SendConfig) -> Self {
+        self.send_config = send_config;
+        self
+    }
+
+    // Limits the requests to each endpoint to `requests_per_second`, by holding requests back
+    // once the limit is reached
+    pub fn with_rate_limit(self, requests_per_second: Option<f64>) -> Self {
+        for endpoint_state in &self.endpoint_states {
+            endpoint_state.set_rate_limit(requests_per_second);
         }
+        self
+    }
+
+    // Limits the total fees, in lamports, of all transactions sent through these clients
+    pub fn with_max_total_fees(mut self, max_total_fees: Option<u64>) -> Self {
+        self.max_total_fees = max_total_fees;
+        self
+    }
+
+    // Sends transactions as Jito bundles that tip `tip` lamports, paid by `tip_payer`, instead of
+    // through the RPC clients
+    pub fn with_jito(mut self, block_engine_url: String, tip: u64, tip_payer: Keypair) -> Self {
+        self.jito = Some(Jito {
+            block_engine: RpcClient::new(block_engine_url),
+            tip,
+            tip_payer,
+        });
+        self
+    }
+
+    // Compiles transactions built by `new_unsigned_transaction` into v0 transactions that load
+    // the accounts found in these address lookup tables by index
+    pub fn with_address_lookup_tables(mut self, address_lookup_tables: Vec<Pubkey>) -> Self {
+        self.address_lookup_tables = address_lookup_tables;
+        self
     }
 
+    // An unsigned transaction of `instructions` paid by `payer`. A legacy transaction unless
+    // address lookup tables were given, which fit more accounts into a v0 transaction
+    pub fn new_unsigned_transaction(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        recent_blockhash: Hash,
+    ) -> Result<VersionedTransaction, Box<dyn std::error::Error>> {
+        let message = if self.address_lookup_tables.is_empty() {
+            let mut message = Message::new(instructions, Some(payer));
+            message.recent_blockhash = recent_blockhash;
+            VersionedMessage::Legacy(message)
+        } else {
+            let address_lookup_table_accounts = self
+                .with_failover(|rpc_client| {
+                    rpc_client.get_multiple_accounts(&self.address_lookup_tables)
+                })?
+                .into_iter()
+                .zip(&self.address_lookup_tables)
+                .map(|(account, address)| {
+                    let account = account
+                        .ok_or_else(|| format!("Address lookup table {address} not found"))?;
+                    let address_lookup_table = AddressLookupTable::deserialize(&account.data)
+                        .map_err(|err| format!("Invalid address lookup table {address}: {err}"))?;
+                    Ok(AddressLookupTableAccount {
+                        key: *address,
+                        addresses: address_lookup_table.addresses.to_vec(),
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            VersionedMessage::V0(v0::Message::try_compile(
+                payer,
+                instructions,
+                &address_lookup_table_accounts,
+                recent_blockhash,
+            )?)
+        };
+
+        Ok(VersionedTransaction {
+            signatures: vec![
+                Signature::default();
+                message.header().num_required_signatures as usize
+            ],
+            message,
+        })
+    }
+
+    pub fn jito(&self) -> Option<&Jito> {
+        self.jito.as_ref()
+    }
+
+    // Total fees, in lamports, of the transactions sent so far
+    pub fn total_fees(&self) -> u64 {
+        self.total_fees.load(Ordering::Relaxed)
+    }
+
+    // The active endpoint, which is switched to the first healthy endpoint, in `--url` order,
+    // every `HEALTH_CHECK_INTERVAL`
     pub fn default(&self) -> &RpcClient {
-        &self.clients[0].1
+        if self.num_endpoints > 1 {
+            let mut last_health_check = self.last_health_check.lock().unwrap();
+            if !last_health_check.is_some_and(|last| last.elapsed() < HEALTH_CHECK_INTERVAL) {
+                *last_health_check = Some(Instant::now());
+                self.check_health();
+            }
+        }
+        &self.clients[self.active.load(Ordering::Relaxed)].1
+    }
+
+    // Refreshes the slot of every endpoint and activates the first healthy one, or the most
+    // advanced one if none are
+    fn check_health(&self) {
+        for ((_, rpc_client), health) in self.clients.iter().zip(&self.health) {
+            let result = rpc_client.get_slot();
+            health.record(&result);
+            health
+                .slot
+                .store(result.unwrap_or_default(), Ordering::Relaxed);
+        }
+
+        let slots = self
+            .health
+            .iter()
+            .map(|health| health.slot.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let max_slot = slots.iter().copied().max().unwrap_or_default();
+        let active = self
+            .health
+            .iter()
+            .position(|health| health.is_healthy(max_slot))
+            .or_else(|| slots.iter().position(|slot| *slot == max_slot))
+            .unwrap_or_default();
+        self.activate(active);
+    }
+
+    fn activate(&self, endpoint: usize) {
+        let previous = self.active.swap(endpoint, Ordering::Relaxed);
+        if previous != endpoint {
+            println!(
+                "Switched RPC endpoint from {} to {}",
+                self.clients[previous].0, self.clients[endpoint].0
+            );
+        }
+    }
+
+    // Runs `f` against the active endpoint, and then against each other endpoint in turn while
+    // it fails. The first endpoint that succeeds becomes the active one
+    pub fn with_failover<T>(&self, f: impl Fn(&RpcClient) -> ClientResult<T>) -> ClientResult<T> {
+        let _ = self.default();
+        let active = self.active.load(Ordering::Relaxed);
+
+        let mut result = f(&self.clients[active].1);
+        self.health[active].record(&result);
+        for endpoint in (1..self.num_endpoints).map(|i| (active + i) % self.num_endpoints) {
+            if result.is_ok() {
+                break;
+            }
+            self.endpoint_states[endpoint]
+                .stats
+                .retries
+                .fetch_add(1, Ordering::Relaxed);
+            metrics::prometheus_counter(
+                "sys_rpc_retries_total",
+                "RPC requests retried against another endpoint after failing",
+                &[(
+                    "endpoint",
+                    &rate_limited_sender::endpoint_label(&self.clients[endpoint].0),
+                )],
+                1.,
+            );
+            result = f(&self.clients[endpoint].1);
+            self.health[endpoint].record(&result);
+            if result.is_ok() {
+                self.activate(endpoint);
+            }
+        }
+        result
+    }
+
+    pub fn get_epoch_info(&self) -> ClientResult<EpochInfo> {
+        self.with_failover(|rpc_client| rpc_client.get_epoch_info())
+    }
+
+    pub fn get_latest_blockhash_with_commitment(&self) -> ClientResult<(Hash, u64)> {
+        self.with_failover(|rpc_client| {
+            rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())
+        })
+    }
+
+    // The URL and request stats of each endpoint
+    pub fn request_stats(&self) -> impl Iterator<Item = (&str, &RpcRequestStats)> {
+        self.clients
+            .iter()
+            .zip(&self.endpoint_states)
+            .map(|((url, _), endpoint_state)| (url.as_str(), &endpoint_state.stats))
     }
 
     pub fn helius_or_default(&self) -> &RpcClient {
@@ -91,90 +514,651 @@ impl RpcClients {
     }
 }
 
+// Signs `transaction` with those of its required signers found in `signers`, which may be
+// called once for each set of signers. `transaction.signatures` holds a default signature for
+// each signer still missing
+pub fn try_partial_sign_transaction<T: Signers + ?Sized>(
+    transaction: &mut VersionedTransaction,
+    signers: &T,
+) -> Result<(), SignerError> {
+    let num_required_signatures = transaction.message.header().num_required_signatures as usize;
+    let signer_keys = &transaction.message.static_account_keys()[..num_required_signatures];
+
+    let positions = signers
+        .pubkeys()
+        .iter()
+        .map(|pubkey| {
+            signer_keys
+                .iter()
+                .position(|signer_key| signer_key == pubkey)
+                .ok_or(SignerError::KeypairPubkeyMismatch)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let signatures = signers.try_sign_message(&transaction.message.serialize())?;
+    for (position, signature) in positions.into_iter().zip(signatures) {
+        transaction.signatures[position] = signature;
+    }
+    Ok(())
+}
+
+// Signs `transaction` with the last of its required signers, failing if any are still missing
+pub fn try_sign_transaction<T: Signers + ?Sized>(
+    transaction: &mut VersionedTransaction,
+    signers: &T,
+) -> Result<(), SignerError> {
+    try_partial_sign_transaction(transaction, signers)?;
+    if transaction.signatures.contains(&Signature::default()) {
+        return Err(SignerError::NotEnoughSigners);
+    }
+    Ok(())
+}
+
+// A transaction whose fee can be determined before it is sent
+pub trait FeeTransaction: SerializableTransaction + Clone {
+    fn get_fee(&self, rpc_client: &RpcClient) -> Result<u64, String>;
+
+    // The nonce account of a durable nonce transaction
+    fn durable_nonce_account(&self) -> Option<Pubkey>;
+
+    // The priority fee that the compute budget instructions of the transaction pay
+    fn priority_fee_lamports(&self) -> u64;
+}
+
+impl FeeTransaction for Transaction {
+    fn get_fee(&self, rpc_client: &RpcClient) -> Result<u64, String> {
+        rpc_client
+            .get_fee_for_message(&self.message)
+            .map_err(|err| err.to_string())
+    }
+
+    fn durable_nonce_account(&self) -> Option<Pubkey> {
+        durable_nonce_account(&self.message.account_keys, &self.message.instructions)
+    }
+
+    fn priority_fee_lamports(&self) -> u64 {
+        ComputeBudget::from_compiled_instructions(
+            &self.message.account_keys,
+            &self.message.instructions,
+        )
+        .priority_fee_lamports()
+    }
+}
+
+impl FeeTransaction for VersionedTransaction {
+    fn get_fee(&self, rpc_client: &RpcClient) -> Result<u64, String> {
+        match &self.message {
+            VersionedMessage::Legacy(message) => rpc_client.get_fee_for_message(message),
+            VersionedMessage::V0(message) => rpc_client.get_fee_for_message(message),
+        }
+        .map_err(|err| err.to_string())
+    }
+
+    fn durable_nonce_account(&self) -> Option<Pubkey> {
+        durable_nonce_account(
+            self.message.static_account_keys(),
+            self.message.instructions(),
+        )
+    }
+
+    fn priority_fee_lamports(&self) -> u64 {
+        ComputeBudget::from_compiled_instructions(
+            self.message.static_account_keys(),
+            self.message.instructions(),
+        )
+        .priority_fee_lamports()
+    }
+}
+
+// A durable nonce transaction advances its nonce account in its first instruction
+fn durable_nonce_account(
+    account_keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+) -> Option<Pubkey> {
+    let instruction = instructions.first()?;
+    if account_keys.get(instruction.program_id_index as usize) != Some(&system_program::id()) {
+        return None;
+    }
+    match bincode::deserialize(&instruction.data) {
+        Ok(SystemInstruction::AdvanceNonceAccount) => account_keys
+            .get(*instruction.accounts.first()? as usize)
+            .copied(),
+        _ => None,
+    }
+}
+
+// Durable nonce transactions do not expire at a block height, so their database records use this
+// last valid block height instead
+pub const DURABLE_NONCE_LAST_VALID_BLOCK_HEIGHT: u64 = u64::MAX;
+
+// A durable nonce, which stands in for the recent blockhash of a transaction so that it does not
+// expire while waiting to be signed, such as by an offline signer. The transaction instead
+// remains valid until its nonce is advanced
+pub struct DurableNonce {
+    pub account: Pubkey,
+    pub authority: Box<dyn Signer>,
+}
+
+impl DurableNonce {
+    // The blockhash held by the nonce account, to be used as the recent blockhash
+    pub fn blockhash(&self, rpc_clients: &RpcClients) -> Result<Hash, Box<dyn std::error::Error>> {
+        get_durable_nonce_blockhash(rpc_clients, &self.account)
+    }
+
+    // Inserts the instruction that advances the nonce, which must come first
+    pub fn advance(&self, instructions: &mut Vec<Instruction>) {
+        instructions.insert(
+            0,
+            system_instruction::advance_nonce_account(&self.account, &self.authority.pubkey()),
+        );
+    }
+
+    // Signs `transaction` with the nonce authority, leaving the other signers to the caller
+    pub fn partial_sign(&self, transaction: &mut VersionedTransaction) -> Result<(), SignerError> {
+        try_partial_sign_transaction(transaction, &vec![self.authority.as_ref()])
+    }
+}
+
+fn get_durable_nonce_blockhash(
+    rpc_clients: &RpcClients,
+    nonce_account: &Pubkey,
+) -> Result<Hash, Box<dyn std::error::Error>> {
+    let account = rpc_clients
+        .with_failover(|rpc_client| {
+            rpc_client.get_account_with_commitment(nonce_account, rpc_client.commitment())
+        })?
+        .value
+        .ok_or_else(|| format!("Nonce account {nonce_account} does not exist"))?;
+    Ok(nonce_utils::data_from_account(&account)?.blockhash())
+}
+
+// How long to wait before a final check of the status of an apparently expired transaction. One
+// that landed in the last blocks before it expired may not yet be visible to the RPC node that
+// reported it unconfirmed
+pub const EXPIRED_TRANSACTION_RECHECK_DELAY: Duration = Duration::from_secs(10);
+
+// Rechecks the status of an apparently expired transaction, including in the ledger history,
+// after `EXPIRED_TRANSACTION_RECHECK_DELAY`. `Some` means the transaction did land, so its
+// database record must be confirmed or failed rather than cancelled
+pub fn recheck_expired_transaction(
+    rpc_clients: &[&dyn ChainClient],
+    signature: &Signature,
+) -> ClientResult<Option<TransactionResult<()>>> {
+    Ok(recheck_expired_transactions(rpc_clients, &[*signature])?.map(|(_, status)| status))
+}
+
+// Same as `recheck_expired_transaction` for several transactions, returning the index of the
+// one that landed
+fn recheck_expired_transactions(
+    rpc_clients: &[&dyn ChainClient],
+    signatures: &[Signature],
+) -> ClientResult<Option<(usize, TransactionResult<()>)>> {
+    sleep(EXPIRED_TRANSACTION_RECHECK_DELAY);
+
+    // The transactions are only considered expired if at least one endpoint answered
+    let mut err = None;
+    let mut answered = false;
+    for rpc_client in rpc_clients {
+        match rpc_client.get_signature_statuses_with_history(signatures) {
+            Ok(response) => {
+                if let Some((i, Some(transaction_status))) = response
+                    .value
+                    .into_iter()
+                    .enumerate()
+                    .find(|(_, transaction_status)| transaction_status.is_some())
+                {
+                    return Ok(Some((i, transaction_status.status)));
+                }
+                answered = true;
+            }
+            Err(rpc_err) => err = Some(rpc_err),
+        }
+    }
+    match err {
+        Some(err) if !answered => Err(err),
+        _ => Ok(None),
+    }
+}
+
+// Adds the fee of `transaction` to the total fees of this run, returning the fee. `None` if the
+// fee would exceed the maximum total fees of `rpc_clients`
+fn reserve_transaction_fee(
+    rpc_clients: &RpcClients,
+    transaction: &impl FeeTransaction,
+) -> Option<u64> {
+    let Some(max_total_fees) = rpc_clients.max_total_fees else {
+        return Some(0);
+    };
+
+    let jito_tip = rpc_clients.jito().map(|jito| jito.tip).unwrap_or_default();
+    let fee = match transaction.get_fee(rpc_clients.default()) {
+        Ok(fee) => fee + jito_tip,
+        Err(err) => {
+            println!("Unable to determine transaction fee: {err}");
+            return None;
+        }
+    };
+
+    let total_fees = rpc_clients.total_fees.fetch_add(fee, Ordering::Relaxed) + fee;
+    if total_fees > max_total_fees {
+        rpc_clients.total_fees.fetch_sub(fee, Ordering::Relaxed);
+        println!(
+            "Transaction not sent: its fee of {} would bring the total fees of this run to {}, \
+             exceeding the maximum of {}",
+            Sol(fee),
+            Sol(total_fees),
+            Sol(max_total_fees)
+        );
+        return None;
+    }
+    Some(fee)
+}
+
 // Assumes `transaction` has already been signed and simulated...
+//
+// The transaction is not sent, and is reported as failed, if its fee would exceed the remaining
+// fee budget of `rpc_clients`
 pub fn send_transaction_until_expired(
     rpc_clients: &RpcClients,
-    transaction: &impl SerializableTransaction,
-    last_valid_block_height: u64,
-) -> Option<bool> {
-    send_transaction_until_expired_with_slot(rpc_clients, transaction, last_valid_block_height)
-        .map(|(_context_slot, success)| success)
+    transaction: &impl FeeTransaction,
+    mut last_valid_block_height: u64,
+) -> SendTransactionStatus {
+    let Some(fee) = reserve_transaction_fee(rpc_clients, transaction) else {
+        return SendTransactionStatus::Failed;
+    };
+
+    let start = Instant::now();
+    let (_context_slot, status) = send_transaction_until_expired_with_slot(
+        rpc_clients,
+        &mut transaction.clone(),
+        &mut last_valid_block_height,
+        &mut |_| None,
+    );
+    operation_stats::record(
+        Operation::Confirmation,
+        "",
+        start.elapsed(),
+        status.confirmed(),
+    );
+
+    // An expired transaction was never processed, so its fee was not charged
+    if status == SendTransactionStatus::Expired {
+        rpc_clients.total_fees.fetch_sub(fee, Ordering::Relaxed);
+    }
+    status
 }
 
-// Same as `send_transaction_until_expired` but on success returns a `Slot` that the transaction
-// was observed to be confirmed at
-fn send_transaction_until_expired_with_slot(
+// Same as `send_transaction_until_expired`, but when `SendConfig::escalate_after_resends` is set
+// a durable nonce transaction still unconfirmed after that many resends is replaced by one paying
+// twice the compute unit price, up to a priority fee of `max_priority_fee_lamports`. The
+// replacement is signed by `sign` with the same nonce, and `replace_signature(signature,
+// new_signature, last_valid_block_height)` moves the database record over to it before it is
+// sent.
+//
+// Only durable nonce transactions are replaced: the first of them to land advances the nonce, so
+// the others can never land too. A replacement of a recent blockhash transaction would need a new
+// blockhash, and both it and the transaction it replaced could land.
+//
+// A replaced transaction is polled along with its replacement. If it lands instead, the record
+// is moved back to it. On return `transaction` is the transaction whose status is reported
+pub fn send_transaction_until_expired_with_fee_escalation(
     rpc_clients: &RpcClients,
-    transaction: &impl SerializableTransaction,
+    transaction: &mut VersionedTransaction,
     last_valid_block_height: u64,
-) -> Option<(Slot, bool)> {
+    max_priority_fee_lamports: u64,
+    sign: impl Fn(&mut VersionedTransaction) -> Result<(), Box<dyn std::error::Error>>,
+    mut replace_signature: impl FnMut(
+        Signature,
+        Signature,
+        u64,
+    ) -> Result<(), Box<dyn std::error::Error>>,
+) -> SendTransactionStatus {
+    let Some(mut fee) = reserve_transaction_fee(rpc_clients, &*transaction) else {
+        return SendTransactionStatus::Failed;
+    };
+    let mut last_valid_block_height = last_valid_block_height;
+    let mut recorded_signature = transaction.signatures[0];
+
+    let start = Instant::now();
+    let (_context_slot, status) = send_transaction_until_expired_with_slot(
+        rpc_clients,
+        transaction,
+        &mut last_valid_block_height,
+        &mut |transaction: &mut VersionedTransaction| {
+            let (replacement, replacement_last_valid_block_height) =
+                match escalate_priority_fee(transaction, max_priority_fee_lamports, &sign) {
+                    Ok(Some(replacement)) => replacement,
+                    Ok(None) => return None,
+                    Err(err) => {
+                        println!("Unable to escalate the priority fee: {err}");
+                        return None;
+                    }
+                };
+
+            // Only one of the transactions can be charged for, since they share a nonce
+            let replacement_fee = reserve_transaction_fee(rpc_clients, &replacement)?;
+            rpc_clients.total_fees.fetch_sub(fee, Ordering::Relaxed);
+
+            if let Err(err) = replace_signature(
+                transaction.signatures[0],
+                replacement.signatures[0],
+                replacement_last_valid_block_height,
+            ) {
+                println!("Unable to record the replacement transaction: {err}");
+                rpc_clients
+                    .total_fees
+                    .fetch_sub(replacement_fee, Ordering::Relaxed);
+                rpc_clients.total_fees.fetch_add(fee, Ordering::Relaxed);
+                return None;
+            }
+            fee = replacement_fee;
+            recorded_signature = replacement.signatures[0];
+
+            println!(
+                "Replacing transaction {} with {} at a higher priority fee",
+                transaction.signatures[0], replacement.signatures[0]
+            );
+            *transaction = replacement;
+            Some(replacement_last_valid_block_height)
+        },
+    );
+    operation_stats::record(
+        Operation::Confirmation,
+        "",
+        start.elapsed(),
+        status.confirmed(),
+    );
+
+    if transaction.signatures[0] != recorded_signature {
+        if let Err(err) = replace_signature(
+            recorded_signature,
+            transaction.signatures[0],
+            last_valid_block_height,
+        ) {
+            println!("Unable to record the transaction that landed: {err}");
+            return SendTransactionStatus::TimedOut;
+        }
+    }
+
+    if status == SendTransactionStatus::Expired {
+        rpc_clients.total_fees.fetch_sub(fee, Ordering::Relaxed);
+    }
+    status
+}
+
+// A copy of the durable nonce `transaction` paying twice its compute unit price, and at least a
+// tenth of `max_priority_fee_lamports`, signed by `sign`. `None` once the priority fee is at its
+// maximum, or if `transaction` uses a recent blockhash
+fn escalate_priority_fee(
+    transaction: &VersionedTransaction,
+    max_priority_fee_lamports: u64,
+    sign: &dyn Fn(&mut VersionedTransaction) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<Option<(VersionedTransaction, u64)>, Box<dyn std::error::Error>> {
+    if transaction.durable_nonce_account().is_none() {
+        return Ok(None);
+    }
+
+    let account_keys = transaction.message.static_account_keys();
+    let compute_budget =
+        ComputeBudget::from_compiled_instructions(account_keys, transaction.message.instructions());
+    if compute_budget.compute_unit_limit == 0 {
+        return Ok(None);
+    }
+
+    let max_compute_unit_price_micro_lamports =
+        ComputeBudget::new(compute_budget.compute_unit_limit, max_priority_fee_lamports)
+            .compute_unit_price_micro_lamports;
+    let compute_unit_price_micro_lamports = (compute_budget.compute_unit_price_micro_lamports * 2)
+        .max(max_compute_unit_price_micro_lamports / 10)
+        .min(max_compute_unit_price_micro_lamports);
+    if compute_unit_price_micro_lamports <= compute_budget.compute_unit_price_micro_lamports {
+        return Ok(None);
+    }
+
+    // The replacement keeps the nonce, so that only one of it and `transaction` can land
+    let mut replacement = transaction.clone();
+    override_compute_unit_price(&mut replacement.message, compute_unit_price_micro_lamports);
+    replacement.signatures.fill(Signature::default());
+    sign(&mut replacement)?;
+
+    Ok(Some((replacement, DURABLE_NONCE_LAST_VALID_BLOCK_HEIGHT)))
+}
+
+// Same as `send_transaction_until_expired` but also returns a `Slot` that the transaction was
+// observed to be processed at, if it was.
+//
+// Whenever `escalate` replaces `transaction`, returning the last valid block height of the
+// replacement, the replaced transaction continues to be polled. If it is the one that lands,
+// `transaction` and `last_valid_block_height` are set back to it
+fn send_transaction_until_expired_with_slot<T: FeeTransaction>(
+    rpc_clients: &RpcClients,
+    transaction: &mut T,
+    last_valid_block_height: &mut u64,
+    escalate: &mut dyn FnMut(&mut T) -> Option<u64>,
+) -> (Option<Slot>, SendTransactionStatus) {
+    let SendConfig {
+        resend_interval,
+        poll_interval,
+        timeout,
+        mut escalate_after_resends,
+    } = rpc_clients.send_config;
+    let start = Instant::now();
     let mut last_send_attempt = None;
+    let mut resends = 0;
+    let mut replaced = vec![];
+    let durable_nonce_account = transaction.durable_nonce_account();
 
     loop {
+        if let Some(timeout) = timeout {
+            if start.elapsed() > timeout {
+                if durable_nonce_account.is_some() {
+                    println!(
+                        "Transaction {} unconfirmed after {timeout:?}, it may still land until \
+                         its nonce is advanced",
+                        transaction.get_signature()
+                    );
+                } else {
+                    println!(
+                        "Transaction {} unconfirmed after {timeout:?}, it may still land until \
+                         block height {last_valid_block_height}",
+                        transaction.get_signature()
+                    );
+                }
+                return (None, SendTransactionStatus::TimedOut);
+            }
+        }
+
         if last_send_attempt.is_none()
-            || Instant::now()
-                .duration_since(*last_send_attempt.as_ref().unwrap())
-                .as_secs()
-                > 2
+            || Instant::now().duration_since(*last_send_attempt.as_ref().unwrap()) > resend_interval
         {
-            for (json_rpc_url, rpc_client) in rpc_clients.clients.iter().rev() {
+            if last_send_attempt.is_some() {
+                if escalate_after_resends == Some(resends) {
+                    let previous = (transaction.clone(), *last_valid_block_height);
+                    match escalate(transaction) {
+                        Some(replacement_last_valid_block_height) => {
+                            replaced.push(previous);
+                            *last_valid_block_height = replacement_last_valid_block_height;
+                            resends = 0;
+                        }
+                        None => escalate_after_resends = None,
+                    }
+                } else {
+                    resends += 1;
+                }
+            }
+
+            let bundle_sent = rpc_clients.jito().is_some_and(|jito| {
                 println!(
-                    "Sending transaction {} [{json_rpc_url}]",
-                    transaction.get_signature()
+                    "Sending transaction {} as a Jito bundle with a {} tip",
+                    transaction.get_signature(),
+                    Sol(jito.tip)
                 );
+                let send_start = Instant::now();
+                let result = jito.send_bundle(rpc_clients.default(), &*transaction);
+                operation_stats::record(
+                    Operation::Send,
+                    "jito",
+                    send_start.elapsed(),
+                    result.is_ok(),
+                );
+                match result {
+                    Ok(bundle_id) => {
+                        println!("Bundle id: {bundle_id}");
+                        true
+                    }
+                    Err(err) => {
+                        println!("Unable to send bundle, falling back to RPC: {err}");
+                        false
+                    }
+                }
+            });
+
+            if !bundle_sent {
+                for (json_rpc_url, rpc_client) in rpc_clients.clients.iter().rev() {
+                    println!(
+                        "Sending transaction {} [{json_rpc_url}]",
+                        transaction.get_signature()
+                    );
 
-                if let Err(err) = rpc_client.send_transaction(transaction) {
-                    println!("Unable to send transaction: {err:?}");
+                    let send_start = Instant::now();
+                    let result = rpc_client.send_transaction(&*transaction);
+                    operation_stats::record(
+                        Operation::Send,
+                        &endpoint_label(json_rpc_url),
+                        send_start.elapsed(),
+                        result.is_ok(),
+                    );
+                    if let Err(err) = result {
+                        println!("Unable to send transaction: {err:?}");
+                    }
                 }
             }
             last_send_attempt = Some(Instant::now());
         }
 
-        sleep(Duration::from_millis(500));
+        sleep(poll_interval);
 
-        match rpc_clients
-            .default()
-            .get_signature_statuses(&[*transaction.get_signature()])
-        {
-            Ok(rpc_response::Response { context, value }) => {
-                let confirmation_context_slot = context.slot;
-                if let Some(ref transaction_status) = value[0] {
-                    return Some((
-                        confirmation_context_slot,
-                        match transaction_status.err {
-                            None => true,
-                            Some(ref err) => {
-                                println!("Transaction failed: {err}");
-                                false
-                            }
-                        },
-                    ));
-                } else {
-                    match rpc_clients.default().get_epoch_info() {
-                        Ok(epoch_info) => {
-                            if epoch_info.block_height > last_valid_block_height
-                                && epoch_info.absolute_slot >= confirmation_context_slot
-                            {
-                                println!(
-                                    "Transaction expired as of slot {confirmation_context_slot}"
-                                );
-                                return None;
-                            }
-                            println!(
-                                "(transaction unconfirmed as of slot {}, {} blocks until expiry)",
-                                confirmation_context_slot,
-                                last_valid_block_height.saturating_sub(epoch_info.block_height),
-                            );
+        // Replaced transactions come first, in the order they were sent
+        let signatures = replaced
+            .iter()
+            .map(|(replaced_transaction, _): &(T, u64)| *replaced_transaction.get_signature())
+            .chain(std::iter::once(*transaction.get_signature()))
+            .collect::<Vec<_>>();
+
+        // Poll every endpoint so that one lagging behind, or that never saw the transaction,
+        // doesn't cause a landed transaction to be reported as expired
+        let mut confirmation_context_slot = None;
+        for (i, (json_rpc_url, rpc_client)) in rpc_clients.clients.iter().enumerate() {
+            let result = rpc_client.get_signature_statuses(&signatures);
+            if let Some(health) = rpc_clients.health.get(i) {
+                health.record(&result);
+            }
+            match result {
+                Ok(rpc_response::Response { context, value }) => {
+                    if let Some((landed, Some(transaction_status))) = value
+                        .into_iter()
+                        .enumerate()
+                        .find(|(_, transaction_status)| transaction_status.is_some())
+                    {
+                        if landed < replaced.len() {
+                            println!("Replaced transaction {} landed", signatures[landed]);
+                            (*transaction, *last_valid_block_height) = replaced.swap_remove(landed);
+                        }
+                        return (
+                            Some(context.slot),
+                            match transaction_status.err {
+                                None => SendTransactionStatus::Confirmed,
+                                Some(ref err) => {
+                                    println!("Transaction failed: {err}");
+                                    SendTransactionStatus::Failed
+                                }
+                            },
+                        );
+                    }
+                    confirmation_context_slot = confirmation_context_slot.max(Some(context.slot));
+                }
+                Err(err) => {
+                    println!("Unable to get transaction status [{json_rpc_url}]: {err:?}");
+                }
+            }
+        }
+
+        // The transaction is only expired once the block height is past its last valid block
+        // height as of the most recent slot that any endpoint reported it unconfirmed at. A
+        // replacement always expires after the transactions it replaced.
+        //
+        // A durable nonce transaction instead expires once its nonce has been advanced, by
+        // another transaction or by one of its replacements landing
+        let Some(confirmation_context_slot) = confirmation_context_slot else {
+            continue;
+        };
+        let expired = match durable_nonce_account {
+            Some(nonce_account) => match get_durable_nonce_blockhash(rpc_clients, &nonce_account) {
+                Ok(blockhash) => {
+                    let expired = blockhash != *transaction.get_recent_blockhash();
+                    if !expired {
+                        println!(
+                            "(transaction unconfirmed as of slot {confirmation_context_slot}, \
+                             nonce not yet advanced)"
+                        );
+                    }
+                    expired
+                }
+                Err(err) => {
+                    println!("Unable to get nonce account {nonce_account}: {err}");
+                    false
+                }
+            },
+            None => match rpc_clients.get_epoch_info() {
+                Ok(epoch_info) => {
+                    let expired = epoch_info.block_height > *last_valid_block_height
+                        && epoch_info.absolute_slot >= confirmation_context_slot;
+                    if !expired {
+                        println!(
+                            "(transaction unconfirmed as of slot {}, {} blocks until expiry)",
+                            confirmation_context_slot,
+                            last_valid_block_height.saturating_sub(epoch_info.block_height),
+                        );
+                    }
+                    expired
+                }
+                Err(err) => {
+                    println!("Unable to get epoch info: {err:?}");
+                    false
+                }
+            },
+        };
+
+        if expired {
+            println!("Transaction expired as of slot {confirmation_context_slot}");
+
+            let rpc_clients = rpc_clients
+                .clients
+                .iter()
+                .map(|(_, rpc_client)| rpc_client as &dyn ChainClient)
+                .collect::<Vec<_>>();
+            return match recheck_expired_transactions(&rpc_clients, &signatures) {
+                Ok(Some((landed, status))) => {
+                    if landed < replaced.len() {
+                        (*transaction, *last_valid_block_height) = replaced.swap_remove(landed);
+                    }
+                    match status {
+                        Ok(()) => {
+                            println!("Transaction landed before it expired");
+                            (None, SendTransactionStatus::Confirmed)
                         }
                         Err(err) => {
-                            println!("Unable to get epoch info: {err:?}")
+                            println!("Transaction failed: {err}");
+                            (None, SendTransactionStatus::Failed)
                         }
                     }
                 }
-            }
-            Err(err) => {
-                println!("Unable to get transaction status: {err:?}");
-            }
+                Ok(None) => (None, SendTransactionStatus::Expired),
+                Err(err) => {
+                    println!("Unable to recheck expired transaction: {err:?}");
+                    (None, SendTransactionStatus::TimedOut)
+                }
+            };
         }
     }
 }
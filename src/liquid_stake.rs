@@ -0,0 +1,150 @@
+use {
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        pubkey,
+        pubkey::Pubkey,
+        system_program,
+    },
+    strum::{EnumString, IntoStaticStr},
+    sys::token::Token,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, IntoStaticStr)]
+pub enum LiquidStakeProtocol {
+    #[strum(serialize = "marinade")]
+    Marinade,
+    #[strum(serialize = "jito")]
+    Jito,
+    #[strum(serialize = "blaze")]
+    Blaze,
+}
+
+pub const POSSIBLE_LIQUID_STAKE_PROTOCOL_VALUES: &[&str] = &["marinade", "jito", "blaze"];
+
+const MARINADE_PROGRAM_ID: Pubkey = pubkey!("MarBmsSgKXdrN1egZf5sqe1TMai9K1rChYNDJgjq7aD");
+const MARINADE_STATE: Pubkey = pubkey!("8szGkuLTAux9XMgZ2vtY39jVSowEcpBfFfD8hXSEqdGC");
+const MARINADE_LIQ_POOL_MSOL_LEG: Pubkey = pubkey!("7GgPYjS5Dza89wV6FpZ23kUJRG5vbQ1GM25ezspYFSoE");
+
+const SPL_STAKE_POOL_PROGRAM_ID: Pubkey = pubkey!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+const JITO_STAKE_POOL: Pubkey = pubkey!("Jito4APyf642JPZPx3hGc6WWJ8zPKtRbRs4P815Awbb");
+const BLAZE_STAKE_POOL: Pubkey = pubkey!("stk9ApL5HeVAwPLr3TLhDXdZS8ptVu7zp6ov8HFDuMi");
+
+impl LiquidStakeProtocol {
+    pub fn token(&self) -> Token {
+        match self {
+            LiquidStakeProtocol::Marinade => Token::mSOL,
+            LiquidStakeProtocol::Jito => Token::JitoSOL,
+            LiquidStakeProtocol::Blaze => Token::bSOL,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    // Instruction that deposits `lamports` from `address` into the protocol, minting its liquid
+    // staking token into the associated token account of `address`, which must already exist
+    pub fn deposit_instruction(
+        &self,
+        rpc_client: &RpcClient,
+        address: Pubkey,
+        lamports: u64,
+    ) -> Result<Instruction, Box<dyn std::error::Error>> {
+        match self {
+            LiquidStakeProtocol::Marinade => Ok(marinade_deposit_instruction(address, lamports)),
+            LiquidStakeProtocol::Jito => {
+                stake_pool_deposit_sol_instruction(rpc_client, JITO_STAKE_POOL, address, lamports)
+            }
+            LiquidStakeProtocol::Blaze => {
+                stake_pool_deposit_sol_instruction(rpc_client, BLAZE_STAKE_POOL, address, lamports)
+            }
+        }
+    }
+}
+
+fn marinade_deposit_instruction(address: Pubkey, lamports: u64) -> Instruction {
+    let msol = Token::mSOL;
+    let pda = |seed: &[u8]| {
+        Pubkey::find_program_address(&[MARINADE_STATE.as_ref(), seed], &MARINADE_PROGRAM_ID).0
+    };
+
+    // Anchor discriminator of the `deposit` instruction, the first 8 bytes of
+    // sha256("global:deposit")
+    let mut data = vec![242, 35, 198, 137, 82, 225, 242, 182];
+    data.extend(lamports.to_le_bytes());
+
+    Instruction::new_with_bytes(
+        MARINADE_PROGRAM_ID,
+        &data,
+        vec![
+            AccountMeta::new(MARINADE_STATE, false),
+            AccountMeta::new(msol.mint(), false),
+            AccountMeta::new(pda(b"liq_sol"), false),
+            AccountMeta::new(MARINADE_LIQ_POOL_MSOL_LEG, false),
+            AccountMeta::new_readonly(pda(b"liq_st_sol_authority"), false),
+            AccountMeta::new(pda(b"reserve"), false),
+            AccountMeta::new(address, true),
+            AccountMeta::new(msol.ata(&address), false),
+            AccountMeta::new_readonly(pda(b"st_mint"), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(msol.program_id(), false),
+        ],
+    )
+}
+
+// The `DepositSol` instruction of the SPL stake pool program. Accounts of the pool are read from
+// the fixed-size head of its `StakePool` state
+fn stake_pool_deposit_sol_instruction(
+    rpc_client: &RpcClient,
+    stake_pool: Pubkey,
+    address: Pubkey,
+    lamports: u64,
+) -> Result<Instruction, Box<dyn std::error::Error>> {
+    let data = rpc_client.get_account_data(&stake_pool)?;
+    let pubkey_at = |offset: usize| -> Result<Pubkey, Box<dyn std::error::Error>> {
+        Ok(Pubkey::try_from(
+            data.get(offset..offset + 32)
+                .ok_or_else(|| format!("Stake pool account is too small: {stake_pool}"))?,
+        )
+        .unwrap())
+    };
+    // account_type: u8, manager, staker, stake_deposit_authority: Pubkey,
+    // stake_withdraw_bump_seed: u8, validator_list: Pubkey, then:
+    let reserve_stake = pubkey_at(130)?;
+    let pool_mint = pubkey_at(162)?;
+    let manager_fee_account = pubkey_at(194)?;
+    let token_program_id = pubkey_at(226)?;
+
+    let withdraw_authority = Pubkey::find_program_address(
+        &[stake_pool.as_ref(), b"withdraw"],
+        &SPL_STAKE_POOL_PROGRAM_ID,
+    )
+    .0;
+    let pool_token_account =
+        spl_associated_token_account::get_associated_token_address_with_program_id(
+            &address,
+            &pool_mint,
+            &token_program_id,
+        );
+
+    let mut data = vec![14]; // `StakePoolInstruction::DepositSol`
+    data.extend(lamports.to_le_bytes());
+
+    Ok(Instruction::new_with_bytes(
+        SPL_STAKE_POOL_PROGRAM_ID,
+        &data,
+        vec![
+            AccountMeta::new(stake_pool, false),
+            AccountMeta::new_readonly(withdraw_authority, false),
+            AccountMeta::new(reserve_stake, false),
+            AccountMeta::new(address, true),
+            AccountMeta::new(pool_token_account, false),
+            AccountMeta::new(manager_fee_account, false),
+            AccountMeta::new(pool_token_account, false), // no referrer
+            AccountMeta::new(pool_mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(token_program_id, false),
+        ],
+    ))
+}
@@ -1,58 +1,92 @@
 mod amount;
+mod backup;
+mod credentials_cipher;
 mod db;
+mod db_storage;
 mod field_as_string;
 mod get_transaction_balance_change;
+mod jito_mev;
+mod liquid_stake;
+mod output;
+mod passphrase_key;
+mod portfolio_import;
+mod progress;
 mod rpc_client_utils;
+mod self_update;
 mod stake_spreader;
+mod streamflow;
+mod tax_export;
+mod transaction_cost;
 
 use {
-    crate::{amount::Amount, get_transaction_balance_change::*},
+    crate::{amount::Amount, backup::BackupLocation, get_transaction_balance_change::*, output::*},
     chrono::prelude::*,
     chrono_humanize::HumanTime,
     clap::{
-        crate_description, crate_name, value_t, value_t_or_exit, values_t, App, AppSettings, Arg,
-        ArgMatches, SubCommand,
+        crate_description, crate_name, value_t, value_t_or_exit, values_t, values_t_or_exit, App,
+        AppSettings, Arg, ArgGroup, ArgMatches, SubCommand,
     },
     console::{style, Style},
     db::*,
+    db_storage::{DbBackend, POSSIBLE_DB_BACKEND_VALUES},
     itertools::{izip, Itertools},
+    liquid_stake::{LiquidStakeProtocol, POSSIBLE_LIQUID_STAKE_PROTOCOL_VALUES},
+    portfolio_import::{ImportFormat, POSSIBLE_IMPORT_FORMAT_VALUES},
+    progress::Progress,
     rpc_client_utils::get_signature_date,
     rust_decimal::prelude::*,
-    separator::FixedPlaceSeparatable,
     solana_clap_utils::{self, input_parsers::*, input_validators::*},
     solana_client::{
         rpc_client::RpcClient, rpc_config::RpcTransactionConfig, rpc_response::StakeActivationState,
     },
     solana_sdk::{
-        clock::Slot,
+        clock::{Epoch, Slot, UnixTimestamp},
         compute_budget,
-        message::Message,
+        hash::Hash,
+        instruction::{AccountMeta, Instruction},
+        message::{Message, VersionedMessage},
         native_token::{lamports_to_sol, sol_to_lamports, Sol},
         pubkey::Pubkey,
         signature::{read_keypair_file, Keypair, Signature, Signer},
         signers::Signers,
         system_instruction, system_program,
-        transaction::Transaction,
+        transaction::{Transaction, VersionedTransaction},
     },
     std::{
-        collections::{BTreeMap, HashSet},
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
         fs,
-        path::PathBuf,
+        path::{Path, PathBuf},
         process::exit,
         str::FromStr,
-        thread::sleep,
-        time::Duration,
+        sync::atomic::Ordering,
+        time::{Duration, Instant},
     },
     sys::{
+        chain_client::ChainClient,
         exchange::{self, *},
+        healthcheck::{HealthEndpoint, Healthcheck},
+        http_client,
         metrics::{self, dp, MetricsConfig},
         notifier::*,
-        priority_fee::{apply_priority_fee, PriorityFee},
+        number_format::{self, currency, LocalizedFixedPlace, NumberFormat},
+        operation_stats::{self, DailyOperationStats, Operation, OperationStats},
+        priority_fee::{
+            apply_priority_fee, compute_priority_fee, override_compute_unit_price, PriorityFee,
+        },
+        rate_limited_sender,
         send_transaction_until_expired,
+        send_transaction_until_expired_with_fee_escalation,
         token::*,
+        transaction_batch::TransactionBatcher,
+        try_partial_sign_transaction,
+        try_sign_transaction,
+        FeeTransaction,
+        SendConfig,
         //tulip,
         *,
     },
+    tax_export::{ExportFormat, ExportOptions, POSSIBLE_EXPORT_FORMAT_VALUES},
+    transaction_cost::println_transaction_cost,
 };
 
 fn is_amount_or_all_or_half<T>(amount: T) -> Result<(), String>
@@ -105,48 +139,70 @@ fn format_filled_amount(filled_amount: f64) -> String {
     } else {
         Style::new().bold()
     }
-    .apply_to(format!(" [◎{filled_amount} filled]"))
+    .apply_to(format!(
+        " [{}{filled_amount} filled]",
+        MaybeToken::SOL().symbol()
+    ))
     .to_string()
 }
 
+// USD values are kept as `Decimal` and only rounded to cents for display
+fn format_usd(value: Decimal) -> String {
+    f64::try_from(value.round_dp(2))
+        .unwrap()
+        .localized_string_with_fixed_place(2)
+}
+
+// A USD value with the configured currency symbol
+fn format_currency(value: Decimal) -> String {
+    currency(format_usd(value))
+}
+
+fn format_percent(value: Decimal, of: Decimal) -> String {
+    (f64::try_from(value).unwrap() / f64::try_from(of).unwrap() * 100.)
+        .localized_string_with_fixed_place(2)
+}
+
 fn naivedate_of(string: &str) -> Result<NaiveDate, String> {
     NaiveDate::parse_from_str(string, "%y/%m/%d")
         .or_else(|_| NaiveDate::parse_from_str(string, "%Y/%m/%d"))
         .map_err(|err| format!("error parsing '{string}': {err}"))
 }
 
+fn period_of(string: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{string}-01"), "%Y-%m-%d")
+        .map_err(|err| format!("error parsing '{string}', expected YYYY-MM: {err}"))
+}
+
+// A duration such as `90s`, `15m`, `2h` or `1d`. A bare number is seconds
+fn duration_of(string: &str) -> Result<Duration, String> {
+    let (number, unit_secs) = match string.char_indices().last() {
+        Some((i, 's')) => (&string[..i], 1),
+        Some((i, 'm')) => (&string[..i], 60),
+        Some((i, 'h')) => (&string[..i], 60 * 60),
+        Some((i, 'd')) => (&string[..i], 24 * 60 * 60),
+        _ => (string, 1),
+    };
+    match number.parse::<u64>() {
+        Ok(number) if number > 0 => Ok(Duration::from_secs(number * unit_secs)),
+        _ => Err(format!(
+            "error parsing '{string}', expected a duration such as 90s, 15m or 1h"
+        )),
+    }
+}
+
 async fn get_block_date_and_price(
-    rpc_client: &RpcClient,
+    rpc_client: &dyn ChainClient,
     slot: Slot,
     token: MaybeToken,
 ) -> Result<(NaiveDate, Decimal), Box<dyn std::error::Error>> {
     let block_date = rpc_client_utils::get_block_date(rpc_client, slot).await?;
     Ok((
         block_date,
-        retry_get_historical_price(rpc_client, block_date, token).await?,
+        token.get_historical_price(rpc_client, block_date).await?,
     ))
 }
 
-async fn retry_get_historical_price(
-    rpc_client: &RpcClient,
-    block_date: NaiveDate,
-    token: MaybeToken,
-) -> Result<Decimal, Box<dyn std::error::Error>> {
-    const NUM_RETRIES: usize = 20;
-    for _ in 1..NUM_RETRIES {
-        let price = token.get_historical_price(rpc_client, block_date).await;
-        if price.is_ok() {
-            return price;
-        }
-        // Empirically observed cool down period is ~14s
-        //
-        // TODO: Move this retry logic into `coin_gecko::get_historical_price()`, and respect the
-        // HTTP `Retry-After:` response header from Coin Gecko
-        sleep(Duration::from_secs(5));
-    }
-    token.get_historical_price(rpc_client, block_date).await
-}
-
 fn add_exchange_deposit_address_to_db(
     db: &mut Db,
     exchange: Exchange,
@@ -165,22 +221,154 @@ fn add_exchange_deposit_address_to_db(
             last_update_balance: 0,
             lots: vec![],
             no_sync: Some(true),
+            last_activity: None,
         })?;
     }
     Ok(())
 }
 
+// Acquires the rewards not already recorded as income lots of the exchange deposit account of
+// their token, if that account is tracked
+async fn record_exchange_rewards(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    rpc_client: &dyn ChainClient,
+    rewards: Vec<RewardInfo>,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let recorded_rewards = db
+        .exchange_rewards(exchange)
+        .into_iter()
+        .map(|exchange_reward| exchange_reward.reference)
+        .collect::<HashSet<_>>();
+    let mut deposit_addresses = HashMap::new();
+    for reward in rewards {
+        let token = reward.token;
+        let amount = token.amount(reward.amount);
+        if amount == 0 || recorded_rewards.contains(&reward.id) {
+            continue;
+        }
+
+        // Rewards are only acquired into tracked exchange deposit accounts
+        if !deposit_addresses.contains_key(&token) {
+            let deposit_address = exchange_client.deposit_address(token).await.ok();
+            deposit_addresses.insert(
+                token,
+                deposit_address
+                    .filter(|deposit_address| db.get_account(*deposit_address, token).is_some()),
+            );
+        }
+        let Some(deposit_address) = deposit_addresses[&token] else {
+            continue;
+        };
+
+        let decimal_price = token.get_historical_price(rpc_client, reward.when).await?;
+        db.add_exchange_reward(
+            ExchangeReward {
+                exchange,
+                when: reward.when,
+                token,
+                amount,
+                reference: reward.id.clone(),
+            },
+            deposit_address,
+            decimal_price,
+        )?;
+
+        let msg = format!(
+            "{} {}{} reward on {} ({})",
+            token,
+            token.symbol(),
+            token.ui_amount(amount),
+            reward.when,
+            reward.id,
+        );
+        println!("{msg}");
+        notifier
+            .send(
+                NotificationKind::Reward,
+                Severity::Info,
+                &format!("{exchange:?}: {msg}"),
+            )
+            .await;
+    }
+    Ok(())
+}
+
+// The deposit of `pending_deposit` that the exchange reported with its transaction signature. A
+// batched transaction carries one deposit of each token, so the signature and token identify the
+// deposit, preferring a record of the expected amount. Records in `matched_deposits`, the indices
+// of those already matched to another pending deposit, are skipped
+fn find_deposit_by_signature<'a>(
+    recent_deposits: &'a [DepositInfo],
+    matched_deposits: &HashSet<usize>,
+    pending_deposit: &PendingDeposit,
+    token: MaybeToken,
+) -> Option<(usize, &'a DepositInfo)> {
+    let signature = pending_deposit.transfer.signature.to_string();
+    let mut deposits = recent_deposits
+        .iter()
+        .enumerate()
+        .filter(|(i, deposit_info)| {
+            !matched_deposits.contains(i)
+                && deposit_info.tx_id == signature
+                && deposit_info.coin.eq_ignore_ascii_case(token.name())
+        });
+    deposits
+        .clone()
+        .find(|(_, deposit_info)| {
+            token
+                .amount(deposit_info.amount)
+                .abs_diff(pending_deposit.amount)
+                < 10
+        })
+        .or_else(|| deposits.next())
+}
+
+// The deposit to a shared deposit address that the exchange reported without the transaction
+// signature of `pending_deposit`, matched by its memo and amount instead. A record with another
+// signature is of a different transaction, so is never matched. Deposits of the same amount with
+// the same memo are told apart by how close their time is to `block_time`, that of the block of
+// `pending_deposit`'s transaction
+fn find_deposit_by_memo<'a>(
+    recent_deposits: &'a [DepositInfo],
+    matched_deposits: &HashSet<usize>,
+    pending_deposit: &PendingDeposit,
+    token: MaybeToken,
+    block_time: Option<UnixTimestamp>,
+) -> Option<(usize, &'a DepositInfo)> {
+    let memo = pending_deposit.memo.as_ref()?;
+    recent_deposits
+        .iter()
+        .enumerate()
+        .filter(|(i, deposit_info)| {
+            !matched_deposits.contains(i)
+                && deposit_info.memo.as_ref() == Some(memo)
+                && deposit_info.tx_id.parse::<Signature>().is_err()
+                && token
+                    .amount(deposit_info.amount)
+                    .abs_diff(pending_deposit.amount)
+                    < 10
+        })
+        .min_by_key(|(_, deposit_info)| match (deposit_info.when, block_time) {
+            (Some(when), Some(block_time)) => when.abs_diff(block_time),
+            _ => u64::MAX,
+        })
+}
+
 async fn process_sync_exchange(
     db: &mut Db,
     exchange: Exchange,
     exchange_client: &dyn ExchangeClient,
-    rpc_client: &RpcClient,
+    rpc_client: &dyn ChainClient,
+    adopt_unknown_orders: bool,
     notifier: &Notifier,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let recent_deposits = exchange_client.recent_deposits().await?;
     let recent_withdrawals = exchange_client.recent_withdrawals().await?;
 
-    let epoch_info = rpc_client.get_epoch_info_with_commitment(rpc_client.commitment())?;
+    let epoch_info = rpc_client.get_epoch_info()?;
 
     for pending_withdrawal in db.pending_withdrawals(Some(exchange)) {
         let wi = recent_withdrawals
@@ -207,14 +395,20 @@ async fn process_sync_exchange(
                     token.symbol(),
                     token
                         .ui_amount(pending_withdrawal.amount)
-                        .separated_string_with_fixed_place(2),
+                        .localized_string_with_fixed_place(2),
                     wi.address,
                     tx_id,
                 );
                 println!("{msg}");
 
                 db.confirm_withdrawal(pending_withdrawal, today())?;
-                notifier.send(&format!("{exchange:?}: {msg}")).await;
+                notifier
+                    .send(
+                        NotificationKind::Transfer,
+                        Severity::Info,
+                        &format!("{exchange:?}: {msg}"),
+                    )
+                    .await;
             } else {
                 println!("Pending {} withdrawal to {} cancelled", token, wi.address);
                 db.cancel_withdrawal(pending_withdrawal)?;
@@ -230,6 +424,8 @@ async fn process_sync_exchange(
         }
     }
 
+    // The indices of the `recent_deposits` matched to a pending deposit, so that no other is
+    let mut matched_deposits = HashSet::new();
     for pending_deposit in db.pending_deposits(Some(exchange)) {
         let response = rpc_client
             .get_signature_statuses_with_history(&[pending_deposit.transfer.signature])?;
@@ -268,7 +464,7 @@ async fn process_sync_exchange(
             match recent_deposits.as_ref() {
                 None => {
                     if token.fiat_fungible() {
-                        db.drop_deposit(pending_deposit.transfer.signature)?;
+                        db.drop_deposit(pending_deposit.transfer.signature, token)?;
 
                         let msg = format!(
                             "{} {}{} BLIND deposit successful ({})",
@@ -278,7 +474,13 @@ async fn process_sync_exchange(
                             pending_deposit.transfer.signature
                         );
                         println!("{msg}");
-                        notifier.send(&format!("{exchange:?}: {msg}")).await;
+                        notifier
+                            .send(
+                                NotificationKind::Transfer,
+                                Severity::Info,
+                                &format!("{exchange:?}: {msg}"),
+                            )
+                            .await;
                     } else {
                         // Refuse to forget these lots, there may be a tax implication with doing
                         // so.
@@ -286,20 +488,44 @@ async fn process_sync_exchange(
                     }
                 }
                 Some(recent_deposits) => {
-                    if let Some(deposit_info) = recent_deposits.iter().find(|deposit_info| {
-                        deposit_info.tx_id == pending_deposit.transfer.signature.to_string()
+                    let block_time = || {
+                        response.value[0]
+                            .as_ref()
+                            .and_then(|status| rpc_client.get_block_time(status.slot).ok())
+                    };
+                    if let Some((i, deposit_info)) = find_deposit_by_signature(
+                        recent_deposits,
+                        &matched_deposits,
+                        &pending_deposit,
+                        token,
+                    )
+                    .or_else(|| {
+                        find_deposit_by_memo(
+                            recent_deposits,
+                            &matched_deposits,
+                            &pending_deposit,
+                            token,
+                            block_time(),
+                        )
                     }) {
+                        matched_deposits.insert(i);
                         let missing_tokens = (token.amount(deposit_info.amount) as i64
                             - (pending_deposit.amount as i64))
                             .abs();
                         if missing_tokens >= 10 {
                             let msg = format!(
-                                "Error! {} deposit amount mismatch for {}! Actual amount: ◎{}, expected amount: ◎{}",
+                                "Error! {} deposit amount mismatch for {}! Actual amount: {}{}, expected amount: {}",
                                 token,
-                                pending_deposit.transfer.signature, deposit_info.amount, pending_deposit.amount
+                                pending_deposit.transfer.signature, token.symbol(), deposit_info.amount, token.format_amount(pending_deposit.amount)
                             );
                             println!("{msg}");
-                            notifier.send(&format!("{exchange:?}: {msg}")).await;
+                            notifier
+                                .send(
+                                    NotificationKind::Transfer,
+                                    Severity::Alert,
+                                    &format!("{exchange:?}: {msg}"),
+                                )
+                                .await;
 
                             // TODO: Do something more here...?
                         } else {
@@ -309,13 +535,19 @@ async fn process_sync_exchange(
                                     "{exchange:?} just stole {missing_tokens} tokens from your deposit!"
                                 );
                                 println!("{msg}");
-                                notifier.send(&format!("{exchange:?}: {msg}")).await;
+                                notifier
+                                    .send(
+                                        NotificationKind::Transfer,
+                                        Severity::Warn,
+                                        &format!("{exchange:?}: {msg}"),
+                                    )
+                                    .await;
                             }
 
                             let when =
                                 get_signature_date(rpc_client, pending_deposit.transfer.signature)
                                     .await?;
-                            db.confirm_deposit(pending_deposit.transfer.signature, when)?;
+                            db.confirm_deposit(pending_deposit.transfer.signature, token, when)?;
 
                             let msg = format!(
                                 "{} {}{} deposit successful ({})",
@@ -325,18 +557,45 @@ async fn process_sync_exchange(
                                 pending_deposit.transfer.signature
                             );
                             println!("{msg}");
-                            notifier.send(&format!("{exchange:?}: {msg}")).await;
+                            notifier
+                                .send(
+                                    NotificationKind::Transfer,
+                                    Severity::Info,
+                                    &format!("{exchange:?}: {msg}"),
+                                )
+                                .await;
                         }
                     }
                 }
             }
         } else if epoch_info.block_height > pending_deposit.transfer.last_valid_block_height {
+            match recheck_expired_transaction(&[rpc_client], &pending_deposit.transfer.signature)? {
+                Some(Ok(())) => {
+                    // Processed like any other confirmed deposit by the next sync
+                    println!(
+                        "Pending {} deposit landed before it expired: {}",
+                        token, pending_deposit.transfer.signature
+                    );
+                }
+                Some(Err(_)) | None => {
+                    println!(
+                        "Pending {} deposit cancelled: {}",
+                        token, pending_deposit.transfer.signature
+                    );
+                    db.cancel_deposit(pending_deposit.transfer.signature, token)
+                        .expect("cancel_deposit");
+                }
+            }
+        } else if pending_deposit.transfer.last_valid_block_height
+            == DURABLE_NONCE_LAST_VALID_BLOCK_HEIGHT
+        {
             println!(
-                "Pending {} deposit cancelled: {}",
-                token, pending_deposit.transfer.signature
+                "{} {}{} deposit pending until its nonce is advanced ({} unconfirmed)",
+                token,
+                token.symbol(),
+                token.ui_amount(pending_deposit.amount),
+                pending_deposit.transfer.signature,
             );
-            db.cancel_deposit(pending_deposit.transfer.signature)
-                .expect("cancel_deposit");
         } else {
             println!(
                 "{} {}{} deposit pending for at most {} blocks ({} unconfirmed)",
@@ -352,6 +611,9 @@ async fn process_sync_exchange(
         }
     }
 
+    let rewards = exchange_client.recent_rewards().await?;
+    record_exchange_rewards(db, exchange, exchange_client, rpc_client, rewards, notifier).await?;
+
     for order_info in db.open_orders(Some(exchange), None) {
         let token = order_info.token;
         let order_status = exchange_client
@@ -378,7 +640,13 @@ async fn process_sync_exchange(
             if order_status.filled_amount > 0. {
                 let msg = format!("Partial {order_summary}");
                 println!("{msg}");
-                notifier.send(&format!("{exchange:?}: {msg}")).await;
+                notifier
+                    .send(
+                        NotificationKind::Order,
+                        Severity::Info,
+                        &format!("{exchange:?}: {msg}"),
+                    )
+                    .await;
             } else {
                 println!("   Open {order_summary}");
             }
@@ -416,114 +684,516 @@ async fn process_sync_exchange(
                 format!("Partial {order_summary}{fee_summary}")
             };
             println!("{msg}");
-            notifier.send(&format!("{exchange:?}: {msg}")).await;
+            notifier
+                .send(
+                    NotificationKind::Order,
+                    Severity::Info,
+                    &format!("{exchange:?}: {msg}"),
+                )
+                .await;
         }
     }
 
-    Ok(())
-}
+    // Reverse check: flag open orders on the exchange that are unknown to the db, such as orders
+    // placed manually through the exchange website
+    let mut pairs = BTreeMap::from([(
+        exchange_client.preferred_solusd_pair().to_string(),
+        MaybeToken::SOL(),
+    )]);
+    for order_info in db.open_orders(Some(exchange), None) {
+        pairs.insert(order_info.pair, order_info.token);
+    }
 
-#[allow(clippy::too_many_arguments)]
-async fn process_exchange_deposit<T: Signers>(
-    db: &mut Db,
-    rpc_clients: &RpcClients,
-    exchange: Exchange,
-    exchange_client: &dyn ExchangeClient,
-    token: MaybeToken,
-    deposit_address: Pubkey,
-    amount: Amount,
-    from_address: Pubkey,
-    if_source_balance_exceeds: Option<u64>,
-    if_exchange_balance_less_than: Option<u64>,
-    authority_address: Pubkey,
-    signers: T,
-    lot_selection_method: LotSelectionMethod,
-    lot_numbers: Option<HashSet<usize>>,
-    priority_fee: PriorityFee,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_client = rpc_clients.default();
+    for (pair, token) in pairs {
+        let known_order_ids = db
+            .open_orders(Some(exchange), None)
+            .into_iter()
+            .map(|order_info| order_info.order_id)
+            .collect::<HashSet<_>>();
 
-    if let Some(if_exchange_balance_less_than) = if_exchange_balance_less_than {
-        let exchange_balance = exchange_client
-            .balances()
-            .await?
-            .get(&token.to_string())
-            .map(|b| token.amount(b.total))
-            .unwrap_or(0)
-            + db.pending_deposits(Some(exchange))
-                .into_iter()
-                .map(|pd| pd.amount)
-                .sum::<u64>();
+        for (order_id, order_status) in exchange_client.open_orders(&pair).await? {
+            if known_order_ids.contains(&order_id) {
+                continue;
+            }
 
-        if exchange_balance < if_exchange_balance_less_than {
-            println!(
-                "{0} deposit declined because {1:?} balance ({4}{2}) is less than {4}{3}",
+            let order_summary = format!(
+                "{}: {} {} {}{:<5} at ${:<.2}{} | id {}",
+                pair,
                 token,
-                exchange,
-                token.ui_amount(exchange_balance),
-                token.ui_amount(if_exchange_balance_less_than),
+                format_order_side(order_status.side),
                 token.symbol(),
+                order_status.amount,
+                order_status.price,
+                if order_status.filled_amount == 0. {
+                    String::default()
+                } else {
+                    format_filled_amount(order_status.filled_amount)
+                },
+                order_id,
             );
-            return Ok(());
-        }
-    }
 
-    let from_tracked_account = db
-        .get_account(from_address, token)
-        .ok_or_else(|| format!("Account, {from_address}, is not tracked"))?;
-    let from_account_balance = from_tracked_account.last_update_balance;
+            if !adopt_unknown_orders {
+                let msg = format!("Unknown {order_summary}");
+                println!("{msg}");
+                notifier
+                    .send(
+                        NotificationKind::Order,
+                        Severity::Warn,
+                        &format!("{exchange:?}: {msg}"),
+                    )
+                    .await;
+                continue;
+            }
 
-    if let Some(if_source_balance_exceeds) = if_source_balance_exceeds {
-        if from_account_balance < if_source_balance_exceeds {
-            println!(
-                "{} deposit declined because {} balance is less than {}{}",
+            adopt_exchange_order(
+                db,
+                exchange,
+                exchange_client,
+                pair.clone(),
                 token,
-                from_address,
-                token.symbol(),
-                token.ui_amount(if_source_balance_exceeds)
-            );
-            return Ok(());
+                order_id,
+                &order_status,
+            )
+            .await?;
+
+            let msg = format!("Adopted {order_summary}");
+            println!("{msg}");
+            notifier
+                .send(
+                    NotificationKind::Order,
+                    Severity::Info,
+                    &format!("{exchange:?}: {msg}"),
+                )
+                .await;
         }
     }
 
-    let (recent_blockhash, last_valid_block_height) =
-        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
-    let fee_calculator = get_deprecated_fee_calculator(rpc_client)?;
+    Ok(())
+}
 
-    let from_account = rpc_client
-        .get_account_with_commitment(&from_address, rpc_client.commitment())?
-        .value
-        .ok_or_else(|| format!("From account, {from_address}, does not exist"))?;
+// Track an order that was placed outside of sys as an open order of `exchange`.
+//
+// Lots for the entire order amount are earmarked for sell orders, so that `close_order` can record
+// the disposal of the filled amount once the order is synchronized
+async fn adopt_exchange_order(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    pair: String,
+    token: MaybeToken,
+    order_id: OrderId,
+    order_status: &OrderStatus,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deposit_address = exchange_client.deposit_address(token).await?;
+    let mut deposit_account = db.get_account(deposit_address, token).ok_or_else(|| {
+        format!(
+            "Exchange deposit account does not exist, run `sync` first: {deposit_address} ({token})",
+        )
+    })?;
 
-    let authority_account = if from_address == authority_address {
-        #[allow(clippy::redundant_clone)]
-        from_account.clone()
-    } else {
-        rpc_client
-            .get_account_with_commitment(&authority_address, rpc_client.commitment())?
-            .value
-            .ok_or_else(|| format!("Authority account, {authority_address}, does not exist"))?
+    let (order_lots, ui_amount) = match order_status.side {
+        OrderSide::Buy => (vec![], Some(order_status.amount)),
+        OrderSide::Sell => (
+            deposit_account.extract_lots(
+                db,
+                token.amount(order_status.amount),
+                LotSelectionMethod::default(),
+                None,
+            )?,
+            None,
+        ),
     };
+    db.open_order(
+        order_status.side,
+        deposit_account,
+        exchange,
+        pair,
+        order_status.price,
+        order_id,
+        order_lots,
+        ui_amount,
+        None,
+        OrderOptions::default(),
+    )?;
+    Ok(())
+}
 
-    if authority_account.lamports < fee_calculator.lamports_per_signature {
+async fn process_exchange_adopt_order(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    pair: String,
+    token: MaybeToken,
+    order_id: OrderId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if db
+        .open_orders(Some(exchange), None)
+        .iter()
+        .any(|order_info| order_info.order_id == order_id)
+    {
+        return Err(format!("Order {order_id} is already tracked").into());
+    }
+
+    let order_status = exchange_client.order_status(&pair, &order_id).await?;
+    if !order_status.open && order_status.filled_amount < f64::EPSILON {
+        return Err(format!("Order {order_id} was cancelled without any fills").into());
+    }
+
+    adopt_exchange_order(
+        db,
+        exchange,
+        exchange_client,
+        pair,
+        token,
+        order_id.clone(),
+        &order_status,
+    )
+    .await?;
+    println!(
+        "Adopted {} order {} for {}{} at ${}",
+        order_status.side,
+        order_id,
+        token.symbol(),
+        order_status.amount,
+        order_status.price
+    );
+    Ok(())
+}
+
+// Refuses to spend `lamports` of the `balance` of `address` when that would leave it with less
+// than its minimum SOL reserve
+fn check_sol_reserve(
+    db: &Db,
+    address: Pubkey,
+    balance: u64,
+    lamports: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sol_reserve = db.sol_reserve(address);
+    let remaining_balance = balance.saturating_sub(lamports);
+    if remaining_balance < sol_reserve {
         return Err(format!(
-            "Authority has insufficient funds for the transaction fee of {}",
-            lamports_to_sol(fee_calculator.lamports_per_signature)
+            "{address} would be left with {}, below its minimum SOL reserve of {}",
+            Sol(remaining_balance),
+            Sol(sol_reserve)
         )
         .into());
     }
+    Ok(())
+}
 
-    let (mut instructions, amount, compute_units) = match token.token() {
-        /*SOL*/
-        None => {
-            assert_eq!(from_account.lamports, from_account_balance);
+// Makes sure that `payer` can pay the `rent` of a new associated token account and a transaction
+// fee of up to `max_fee`, while keeping its minimum SOL reserve and staying rent exempt. A
+// shortfall is topped up from the ATA rent funder by a transfer inserted ahead of
+// `instructions`, and the funder keypair that must then sign is returned
+fn prefund_ata_rent(
+    db: &Db,
+    rpc_client: &RpcClient,
+    instructions: &mut Vec<Instruction>,
+    payer: Pubkey,
+    payer_balance: u64,
+    rent: u64,
+    max_fee: u64,
+) -> Result<Option<Keypair>, Box<dyn std::error::Error>> {
+    let required_balance = rent
+        + max_fee
+        + db.sol_reserve(payer)
+            .max(rpc_client.get_minimum_balance_for_rent_exemption(0)?);
+    if payer_balance >= required_balance {
+        return Ok(None);
+    }
+    let shortfall = required_balance - payer_balance;
 
-            if from_account.owner == system_program::id() {
-                let amount = amount.unwrap_or_else(|| {
+    let ata_rent_funder = match db.get_ata_rent_funder() {
+        Some(ata_rent_funder) => read_keypair_file(&ata_rent_funder)
+            .map_err(|err| format!("Failed to read {}: {}", ata_rent_funder.display(), err))?,
+        None => {
+            return Err(format!(
+                "{payer} is {} short of the {} rent of a new token account. Fund it, or set an \
+                 account to top it up with `sys account set-ata-rent-funder`",
+                Sol(shortfall),
+                Sol(rent)
+            )
+            .into())
+        }
+    };
+    let ata_rent_funder_address = ata_rent_funder.pubkey();
+    if ata_rent_funder_address == payer {
+        return Err(format!(
+            "{payer} is {} short of the {} rent of a new token account",
+            Sol(shortfall),
+            Sol(rent)
+        )
+        .into());
+    }
+
+    let ata_rent_funder_balance = rpc_client.get_balance(&ata_rent_funder_address)?;
+    if ata_rent_funder_balance < shortfall {
+        return Err(format!(
+            "ATA rent funder {ata_rent_funder_address} has insufficient funds to top up {payer} \
+             with {}",
+            Sol(shortfall)
+        )
+        .into());
+    }
+    check_sol_reserve(
+        db,
+        ata_rent_funder_address,
+        ata_rent_funder_balance,
+        shortfall,
+    )?;
+
+    println!(
+        "Topping up {payer} with {} from ATA rent funder {ata_rent_funder_address}",
+        Sol(shortfall)
+    );
+    instructions.insert(
+        0,
+        system_instruction::transfer(&ata_rent_funder_address, &payer, shortfall),
+    );
+    Ok(Some(ata_rent_funder))
+}
+
+// Journals a sent transaction, and records the priority fee it paid if it landed
+async fn record_sent_transaction(
+    db: &mut Db,
+    category: PriorityFeeCategory,
+    transaction: &impl FeeTransaction,
+    status: SendTransactionStatus,
+) -> Result<(), Box<dyn std::error::Error>> {
+    db.record_sent_transaction(*transaction.get_signature())?;
+
+    let lamports = transaction.priority_fee_lamports();
+    if !status.landed() || lamports == 0 {
+        return Ok(());
+    }
+    db.record_priority_fee_payment(category, *transaction.get_signature(), lamports)?;
+    metrics::push(dp::priority_fee(category.into(), lamports)).await;
+    Ok(())
+}
+
+// The recent blockhash of a new transaction and its last valid block height. A durable nonce
+// stands in for the blockhash, and the transaction then does not expire until the nonce is
+// advanced
+fn get_recent_blockhash(
+    rpc_clients: &RpcClients,
+    durable_nonce: Option<&DurableNonce>,
+) -> Result<(Hash, u64), Box<dyn std::error::Error>> {
+    Ok(match durable_nonce {
+        Some(durable_nonce) => (
+            durable_nonce.blockhash(rpc_clients)?,
+            DURABLE_NONCE_LAST_VALID_BLOCK_HEIGHT,
+        ),
+        None => rpc_clients.get_latest_blockhash_with_commitment()?,
+    })
+}
+
+// Builds and simulates the transaction of a `TransactionBatcher` batch of token transfers paid for
+// by `authority_address`, which creates associated token accounts costing `ata_rent`. Like a
+// single deposit, the authority keeps its minimum SOL reserve, a shortfall in rent is topped up
+// by the ATA rent funder, and a durable nonce stands in for the recent blockhash. Returns the
+// unsigned transaction, its last valid block height and the ATA rent funder that must sign it
+#[allow(clippy::too_many_arguments)]
+fn new_token_batch_transaction(
+    db: &Db,
+    rpc_clients: &RpcClients,
+    mut instructions: Vec<Instruction>,
+    mut compute_units: u32,
+    authority_address: Pubkey,
+    ata_rent: u64,
+    durable_nonce: Option<&DurableNonce>,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(VersionedTransaction, u64, Option<Keypair>), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    let (recent_blockhash, last_valid_block_height) =
+        get_recent_blockhash(rpc_clients, durable_nonce)?;
+    let fee_calculator = get_deprecated_fee_calculator(rpc_client)?;
+    let authority_balance = rpc_client.get_balance(&authority_address)?;
+
+    let ata_rent_funder = if ata_rent > 0 {
+        prefund_ata_rent(
+            db,
+            rpc_client,
+            &mut instructions,
+            authority_address,
+            authority_balance,
+            ata_rent,
+            priority_fee.max_lamports() + 2 * fee_calculator.lamports_per_signature,
+        )?
+    } else {
+        check_sol_reserve(
+            db,
+            authority_address,
+            authority_balance,
+            fee_calculator.lamports_per_signature,
+        )?;
+        None
+    };
+    if ata_rent_funder.is_some() {
+        compute_units += 1_000;
+    }
+    if durable_nonce.is_some() {
+        compute_units += 1_000;
+    }
+    apply_priority_fee(rpc_clients, &mut instructions, compute_units, priority_fee)?;
+    if let Some(durable_nonce) = durable_nonce {
+        durable_nonce.advance(&mut instructions);
+    }
+
+    let transaction = rpc_clients.new_unsigned_transaction(
+        &instructions,
+        &authority_address,
+        recent_blockhash,
+    )?;
+    if transaction.get_fee(rpc_client)? > authority_balance {
+        return Err("Insufficient funds for transaction fee".into());
+    }
+
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(rpc_client, db, &transaction.message)?;
+    }
+
+    Ok((transaction, last_valid_block_height, ata_rent_funder))
+}
+
+// Reserves room in each batch of `batcher` for the instructions that `new_token_batch_transaction`
+// adds: advancing the durable nonce, and topping up the authority from the ATA rent funder when
+// the batched operations create associated token accounts
+fn reserve_token_batch_instructions<T>(
+    batcher: &mut TransactionBatcher<T>,
+    authority_address: Pubkey,
+    creates_atas: bool,
+    durable_nonce: Option<&DurableNonce>,
+) {
+    let mut instructions = vec![];
+    let mut compute_units = 0;
+    if let Some(durable_nonce) = durable_nonce {
+        durable_nonce.advance(&mut instructions);
+        compute_units += 1_000;
+    }
+    if creates_atas {
+        instructions.push(system_instruction::transfer(
+            &Pubkey::new_unique(),
+            &authority_address,
+            0,
+        ));
+        compute_units += 1_000;
+    }
+    batcher.reserve(instructions, compute_units);
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_exchange_deposit<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    token: MaybeToken,
+    deposit_address: Pubkey,
+    amount: Amount,
+    from_address: Pubkey,
+    if_source_balance_exceeds: Option<u64>,
+    if_exchange_balance_less_than: Option<u64>,
+    authority_address: Pubkey,
+    signers: T,
+    durable_nonce: Option<DurableNonce>,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    if let Some(if_exchange_balance_less_than) = if_exchange_balance_less_than {
+        let exchange_balance = exchange_client
+            .balances()
+            .await?
+            .get(&token.to_string())
+            .map(|b| token.amount(b.total))
+            .unwrap_or(0)
+            + db.pending_deposits(Some(exchange))
+                .into_iter()
+                .map(|pd| pd.amount)
+                .sum::<u64>();
+
+        if exchange_balance < if_exchange_balance_less_than {
+            println!(
+                "{0} deposit declined because {1:?} balance ({4}{2}) is less than {4}{3}",
+                token,
+                exchange,
+                token.ui_amount(exchange_balance),
+                token.ui_amount(if_exchange_balance_less_than),
+                token.symbol(),
+            );
+            return Ok(());
+        }
+    }
+
+    let from_tracked_account = db
+        .get_account(from_address, token)
+        .ok_or_else(|| format!("Account, {from_address}, is not tracked"))?;
+    let from_account_balance = from_tracked_account.last_update_balance;
+
+    if let Some(if_source_balance_exceeds) = if_source_balance_exceeds {
+        if from_account_balance < if_source_balance_exceeds {
+            println!(
+                "{} deposit declined because {} balance is less than {}{}",
+                token,
+                from_address,
+                token.symbol(),
+                token.ui_amount(if_source_balance_exceeds)
+            );
+            return Ok(());
+        }
+    }
+
+    let (recent_blockhash, last_valid_block_height) =
+        get_recent_blockhash(rpc_clients, durable_nonce.as_ref())?;
+    let fee_calculator = get_deprecated_fee_calculator(rpc_client)?;
+
+    let from_account = rpc_client
+        .get_account_with_commitment(&from_address, rpc_client.commitment())?
+        .value
+        .ok_or_else(|| format!("From account, {from_address}, does not exist"))?;
+
+    let authority_account = if from_address == authority_address {
+        #[allow(clippy::redundant_clone)]
+        from_account.clone()
+    } else {
+        rpc_client
+            .get_account_with_commitment(&authority_address, rpc_client.commitment())?
+            .value
+            .ok_or_else(|| format!("Authority account, {authority_address}, does not exist"))?
+    };
+
+    if authority_account.lamports < fee_calculator.lamports_per_signature {
+        return Err(format!(
+            "Authority has insufficient funds for the transaction fee of {}",
+            lamports_to_sol(fee_calculator.lamports_per_signature)
+        )
+        .into());
+    }
+
+    let mut ata_rent = 0;
+    let (mut instructions, amount, mut compute_units) = match token.token() {
+        /*SOL*/
+        None => {
+            assert_eq!(from_account.lamports, from_account_balance);
+
+            let sol_reserve = db.sol_reserve(from_address);
+            if from_account.owner == system_program::id() {
+                let amount = amount.unwrap_or_else(|| {
                     if from_address == authority_address {
-                        from_account_balance.saturating_sub(fee_calculator.lamports_per_signature)
-                    } else {
                         from_account_balance
+                            .saturating_sub(fee_calculator.lamports_per_signature + sol_reserve)
+                    } else {
+                        from_account_balance.saturating_sub(sol_reserve)
                     }
                 });
 
@@ -541,8 +1211,9 @@ async fn process_exchange_deposit<T: Signers>(
                     solana_vote_program::vote_state::VoteState::size_of(),
                 )?;
 
-                let amount =
-                    amount.unwrap_or_else(|| from_account_balance.saturating_sub(minimum_balance));
+                let amount = amount.unwrap_or_else(|| {
+                    from_account_balance.saturating_sub(minimum_balance.max(sol_reserve))
+                });
 
                 (
                     vec![solana_vote_program::vote_instruction::withdraw(
@@ -555,7 +1226,8 @@ async fn process_exchange_deposit<T: Signers>(
                     1_000,
                 )
             } else if from_account.owner == solana_sdk::stake::program::id() {
-                let amount = amount.unwrap_or(from_account_balance);
+                let amount =
+                    amount.unwrap_or_else(|| from_account_balance.saturating_sub(sol_reserve));
 
                 (
                     vec![solana_sdk::stake::instruction::withdraw(
@@ -586,35 +1258,62 @@ async fn process_exchange_deposit<T: Signers>(
                 .value
                 .is_none()
             {
-                instructions.push(
-                    spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-                        &authority_address,
-                        &deposit_address,
-                        &token.mint(),
-                        &spl_token::id(),
-                    ),
-                );
-                compute_units += 20_000
+                instructions
+                    .push(token.create_ata_instruction(&authority_address, &deposit_address));
+                compute_units += 20_000;
+                ata_rent = token.ata_rent(rpc_client)?;
             }
 
-            instructions.push(
-                spl_token::instruction::transfer_checked(
-                    &spl_token::id(),
-                    &token.ata(&from_address),
-                    &token.mint(),
-                    &token.ata(&deposit_address),
-                    &authority_address,
-                    &[],
-                    amount,
-                    token.decimals(),
+            let fee = token.transfer_fee(rpc_client, amount)?;
+            if fee > 0 {
+                return Err(format!(
+                    "{} transfer fee of {} is not supported",
+                    token.name(),
+                    token.format_amount(fee)
                 )
-                .unwrap(),
-            );
+                .into());
+            }
+            instructions.push(token.transfer_checked_instruction(
+                &from_address,
+                &deposit_address,
+                &authority_address,
+                amount,
+                fee,
+            ));
 
             (instructions, amount, compute_units)
         }
     };
+
+    let memo = exchange_client.deposit_memo(token).await?;
+    if let Some(memo) = memo.as_ref() {
+        instructions.push(spl_memo::build_memo(memo.as_bytes(), &[]));
+        compute_units += 5_000;
+    }
+
+    let ata_rent_funder = if ata_rent > 0 {
+        prefund_ata_rent(
+            db,
+            rpc_client,
+            &mut instructions,
+            authority_address,
+            authority_account.lamports,
+            ata_rent,
+            priority_fee.max_lamports() + 2 * fee_calculator.lamports_per_signature,
+        )?
+    } else {
+        None
+    };
+    if ata_rent_funder.is_some() {
+        compute_units += 1_000;
+    }
+    if durable_nonce.is_some() {
+        compute_units += 1_000;
+    }
     apply_priority_fee(rpc_clients, &mut instructions, compute_units, priority_fee)?;
+    if let Some(durable_nonce) = durable_nonce.as_ref() {
+        durable_nonce.advance(&mut instructions);
+    }
 
     if amount == 0 {
         return Err("Nothing to deposit".into());
@@ -622,27 +1321,67 @@ async fn process_exchange_deposit<T: Signers>(
     if from_account_balance < amount {
         return Err("From account has insufficient funds".into());
     }
+    if token.is_sol() {
+        check_sol_reserve(
+            db,
+            from_address,
+            from_account.lamports,
+            amount
+                + if from_address == authority_address {
+                    fee_calculator.lamports_per_signature
+                } else {
+                    0
+                },
+        )?;
+    }
+    // `prefund_ata_rent` has already kept the reserve of an authority paying rent
+    if ata_rent == 0 && (!token.is_sol() || from_address != authority_address) {
+        check_sol_reserve(
+            db,
+            authority_address,
+            authority_account.lamports,
+            fee_calculator.lamports_per_signature,
+        )?;
+    }
 
     println!("From address: {from_address} ({token})");
     if from_address != authority_address {
         println!("Authority address: {authority_address}");
     }
     println!("Amount: {}{}", token.symbol(), token.ui_amount(amount));
-    println!("{token} {exchange:?} deposit address: {deposit_address}");
+    println!("{token} {exchange} deposit address: {deposit_address}");
+    if let Some(memo) = memo.as_ref() {
+        println!("{token} {exchange} deposit memo: {memo}");
+    }
 
-    let mut message = Message::new(&instructions, Some(&authority_address));
-    message.recent_blockhash = recent_blockhash;
-    if rpc_client.get_fee_for_message(&message)? > authority_account.lamports {
+    let mut transaction = rpc_clients.new_unsigned_transaction(
+        &instructions,
+        &authority_address,
+        recent_blockhash,
+    )?;
+    if transaction.get_fee(rpc_client)? > authority_account.lamports {
         return Err("Insufficient funds for transaction fee".into());
     }
 
-    let mut transaction = Transaction::new_unsigned(message);
     let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
     if simulation_result.err.is_some() {
         return Err(format!("Simulation failure: {simulation_result:?}").into());
     }
 
-    transaction.try_sign(&signers, recent_blockhash)?;
+    if verbose {
+        println_transaction_cost(rpc_client, db, &transaction.message)?;
+    }
+
+    let sign = |transaction: &mut VersionedTransaction| -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ata_rent_funder) = ata_rent_funder.as_ref() {
+            try_partial_sign_transaction(transaction, &[ata_rent_funder])?;
+        }
+        if let Some(durable_nonce) = durable_nonce.as_ref() {
+            durable_nonce.partial_sign(transaction)?;
+        }
+        Ok(try_sign_transaction(transaction, &signers)?)
+    };
+    sign(&mut transaction)?;
     let signature = transaction.signatures[0];
     println!("Transaction signature: {signature}");
 
@@ -653,42 +1392,222 @@ async fn process_exchange_deposit<T: Signers>(
         amount,
         exchange,
         deposit_address,
+        memo,
         token,
         lot_selection_method,
         lot_numbers,
     )?;
-    if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-        .unwrap_or_default()
-    {
+    let status = send_transaction_until_expired_with_fee_escalation(
+        rpc_clients,
+        &mut transaction,
+        last_valid_block_height,
+        priority_fee.max_lamports(),
+        sign,
+        |signature, new_signature, last_valid_block_height| {
+            Ok(db.replace_pending_signature(signature, new_signature, last_valid_block_height)?)
+        },
+    );
+    record_sent_transaction(db, PriorityFeeCategory::Deposit, &transaction, status).await?;
+    if !status.confirmed() {
         return Err("Deposit failed".into());
     }
     Ok(())
 }
 
+// Deposits the full tracked balance of each of `deposit_addresses`' tokens held by `from_address`,
+// packing the deposits into as few transactions as fit. Each transaction goes through the same
+// SOL reserve, ATA rent and durable nonce handling as a single deposit. The exchange credits each
+// deposit of a batched transaction on its own, so they are matched up by transaction signature
+// and token when synced
 #[allow(clippy::too_many_arguments)]
-async fn process_exchange_withdraw(
+async fn process_exchange_deposit_all<T: Signers>(
     db: &mut Db,
+    rpc_clients: &RpcClients,
     exchange: Exchange,
     exchange_client: &dyn ExchangeClient,
-    token: MaybeToken,
-    deposit_address: Pubkey,
-    amount: Option<u64>,
-    to_address: Pubkey,
-    lot_selection_method: LotSelectionMethod,
-    lot_numbers: Option<HashSet<usize>>,
-    withdrawal_password: Option<String>,
-    withdrawal_code: Option<String>,
+    from_address: Pubkey,
+    deposit_addresses: Vec<(Token, Pubkey)>,
+    authority_address: Pubkey,
+    signers: T,
+    durable_nonce: Option<DurableNonce>,
+    priority_fee: PriorityFee,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let deposit_account = db
-        .get_account(deposit_address, token)
-        .expect("unknown deposit address");
-    let _to_account = db
-        .get_account(to_address, token)
-        .expect("unknown to address");
+    let rpc_client = rpc_clients.default();
 
-    let amount = amount.unwrap_or(deposit_account.last_update_balance);
+    let mut deposits = vec![];
+    for (token, deposit_address) in deposit_addresses {
+        let Some(from_tracked_account) = db.get_account(from_address, token.into()) else {
+            continue;
+        };
+        let amount = from_tracked_account.last_update_balance;
+        if amount == 0 {
+            continue;
+        }
+        if token.transfer_fee(rpc_client, amount)? > 0 {
+            println!("Skipping {}, transfer fees are not supported", token.name());
+            continue;
+        }
 
-    let (tag, fee_as_ui_amount) = exchange_client
+        let mut instructions = vec![];
+        let mut compute_units = 7_500;
+        let mut ata_rent = 0;
+
+        if rpc_client
+            .get_account_with_commitment(&token.ata(&deposit_address), rpc_client.commitment())?
+            .value
+            .is_none()
+        {
+            instructions.push(token.create_ata_instruction(&authority_address, &deposit_address));
+            compute_units += 20_000;
+            ata_rent = token.ata_rent(rpc_client)?;
+        }
+
+        instructions.push(token.transfer_checked_instruction(
+            &from_address,
+            &deposit_address,
+            &authority_address,
+            amount,
+            0,
+        ));
+
+        let memo = exchange_client.deposit_memo(token.into()).await?;
+        if let Some(memo) = memo.as_ref() {
+            instructions.push(spl_memo::build_memo(memo.as_bytes(), &[]));
+            compute_units += 5_000;
+        }
+
+        deposits.push((
+            (token, deposit_address, amount, memo, ata_rent),
+            instructions,
+            compute_units,
+        ));
+    }
+
+    let mut batcher = TransactionBatcher::new(authority_address);
+    reserve_token_batch_instructions(
+        &mut batcher,
+        authority_address,
+        deposits.iter().any(|((.., ata_rent), ..)| *ata_rent > 0),
+        durable_nonce.as_ref(),
+    );
+    for (deposit, instructions, compute_units) in deposits {
+        let (token, deposit_address, amount, ..) = &deposit;
+        println!(
+            "Depositing {}{} into {deposit_address}",
+            token.symbol(),
+            token.ui_amount(*amount),
+        );
+        batcher.add(deposit, instructions, compute_units)?;
+    }
+
+    if batcher.is_empty() {
+        return Err("Nothing to deposit".into());
+    }
+
+    for batch in batcher.into_batches() {
+        let (mut transaction, last_valid_block_height, ata_rent_funder) =
+            new_token_batch_transaction(
+                db,
+                rpc_clients,
+                batch.instructions,
+                batch.compute_units,
+                authority_address,
+                batch.operations.iter().map(|(.., ata_rent)| ata_rent).sum(),
+                durable_nonce.as_ref(),
+                priority_fee,
+                verbose,
+            )?;
+
+        let sign =
+            |transaction: &mut VersionedTransaction| -> Result<(), Box<dyn std::error::Error>> {
+                if let Some(ata_rent_funder) = ata_rent_funder.as_ref() {
+                    try_partial_sign_transaction(transaction, &[ata_rent_funder])?;
+                }
+                if let Some(durable_nonce) = durable_nonce.as_ref() {
+                    durable_nonce.partial_sign(transaction)?;
+                }
+                Ok(try_sign_transaction(transaction, &signers)?)
+            };
+        sign(&mut transaction)?;
+        let signature = transaction.signatures[0];
+        println!(
+            "Transaction signature: {signature} ({} deposits)",
+            batch.operations.len()
+        );
+
+        let tokens = batch
+            .operations
+            .iter()
+            .map(|(token, ..)| MaybeToken::from(*token))
+            .collect::<Vec<_>>();
+        for (token, deposit_address, amount, memo, _ata_rent) in batch.operations {
+            db.record_deposit(
+                signature,
+                last_valid_block_height,
+                from_address,
+                amount,
+                exchange,
+                deposit_address,
+                memo,
+                token.into(),
+                LotSelectionMethod::default(),
+                None,
+            )?;
+        }
+
+        let status = send_transaction_until_expired_with_fee_escalation(
+            rpc_clients,
+            &mut transaction,
+            last_valid_block_height,
+            priority_fee.max_lamports(),
+            sign,
+            |signature, new_signature, last_valid_block_height| {
+                Ok(db.replace_pending_signature(
+                    signature,
+                    new_signature,
+                    last_valid_block_height,
+                )?)
+            },
+        );
+        let signature = transaction.signatures[0];
+        record_sent_transaction(db, PriorityFeeCategory::Deposit, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                for token in tokens {
+                    db.cancel_deposit(signature, token)?;
+                }
+            }
+            return Err("Deposit failed".into());
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_exchange_withdraw(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    token: MaybeToken,
+    deposit_address: Pubkey,
+    amount: Option<u64>,
+    to_address: Pubkey,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    withdrawal_password: Option<String>,
+    withdrawal_code: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deposit_account = db
+        .get_account(deposit_address, token)
+        .expect("unknown deposit address");
+    let _to_account = db
+        .get_account(to_address, token)
+        .expect("unknown to address");
+
+    let amount = amount.unwrap_or(deposit_account.last_update_balance);
+
+    let (tag, fee_as_ui_amount) = exchange_client
         .request_withdraw(
             to_address,
             token,
@@ -713,144 +1632,655 @@ async fn process_exchange_withdraw(
     Ok(())
 }
 
-enum LimitOrderPrice {
-    At(f64),
-    AmountOverAsk(f64),
-    AmountUnderBid(f64),
+// Remaining withdrawal allowances within the exchange's 24 hour withdrawal limits. Limits are
+// queried once per token and reduced locally by each withdrawal made
+struct WithdrawalAllowances<'a> {
+    exchange_client: &'a dyn ExchangeClient,
+    rpc_client: &'a RpcClient,
+    limits: HashMap<MaybeToken, Option<WithdrawalLimit>>,
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn process_exchange_cancel(
-    db: &mut Db,
-    exchange: Exchange,
-    exchange_client: &dyn ExchangeClient,
-    order_ids: HashSet<String>,
-    max_create_time: Option<DateTime<Utc>>,
-    side: Option<OrderSide>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut cancelled_count = 0;
-    for order_info in db.open_orders(Some(exchange), side) {
-        let mut cancel = false;
-        if order_ids.contains(&order_info.order_id) {
-            cancel = true;
+impl<'a> WithdrawalAllowances<'a> {
+    fn new(exchange_client: &'a dyn ExchangeClient, rpc_client: &'a RpcClient) -> Self {
+        Self {
+            exchange_client,
+            rpc_client,
+            limits: HashMap::default(),
         }
+    }
 
-        if let Some(ref max_create_time) = max_create_time {
-            if order_info.creation_time < *max_create_time {
-                cancel = true;
-            }
+    async fn limit(
+        &mut self,
+        token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        if !self.limits.contains_key(&token) {
+            let limit = self.exchange_client.withdrawal_limit(token).await?;
+            self.limits.insert(token, limit);
         }
+        Ok(self.limits[&token])
+    }
 
-        if cancel {
-            println!("Cancelling order {}", order_info.order_id);
-            cancelled_count += 1;
-            exchange_client
-                .cancel_order(&order_info.pair, &order_info.order_id)
-                .await
-                .unwrap_or_else(|err| eprintln!("{err:?}"));
+    // The amount of `token` that can still be withdrawn, `None` if unlimited
+    async fn remaining(
+        &mut self,
+        token: MaybeToken,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let Some(limit) = self.limit(token).await? else {
+            return Ok(None);
+        };
+
+        let mut remaining = limit
+            .remaining
+            .map(|remaining| token.amount(remaining.max(0.)));
+        if let Some(remaining_usd) = limit.remaining_usd {
+            let price = f64::try_from(token.get_current_price(self.rpc_client).await?)?;
+            let remaining_by_usd = token.amount((remaining_usd / price).max(0.));
+            remaining = Some(match remaining {
+                Some(remaining) => remaining.min(remaining_by_usd),
+                None => remaining_by_usd,
+            });
         }
+        Ok(remaining)
     }
 
-    println!("{cancelled_count} orders cancelled");
-    Ok(())
+    // Accounts for a withdrawal. USD limits span all assets so every cached limit is reduced
+    async fn withdrawn(
+        &mut self,
+        token: MaybeToken,
+        amount: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let value = f64::try_from(token.get_current_price(self.rpc_client).await?)?
+            * token.ui_amount(amount);
+        for (limit_token, limit) in self.limits.iter_mut() {
+            let Some(limit) = limit else {
+                continue;
+            };
+            if let Some(ref mut remaining_usd) = limit.remaining_usd {
+                *remaining_usd -= value;
+            }
+            if *limit_token == token {
+                if let Some(ref mut remaining) = limit.remaining {
+                    *remaining -= token.ui_amount(amount);
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn process_exchange_buy(
+// Withdraws the next chunk of each due withdrawal schedule, reduced to fit within the exchange's
+// remaining 24 hour withdrawal limit. A schedule that is limited to nothing is retried next sync
+async fn process_exchange_withdrawal_schedules(
     db: &mut Db,
     exchange: Exchange,
+    exchange_account: &str,
     exchange_client: &dyn ExchangeClient,
-    token: MaybeToken,
-    pair: String,
-    amount: Option<f64>,
-    price: LimitOrderPrice,
-    if_balance_exceeds: Option<f64>,
+    rpc_client: &RpcClient,
     notifier: &Notifier,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let bid_ask = exchange_client.bid_ask(&pair).await?;
-    println!(
-        "{} | Ask: ${}, Bid: ${}",
-        pair, bid_ask.ask_price, bid_ask.bid_price
-    );
+    let now = Utc::now();
+    let due_withdrawal_schedules = db
+        .withdrawal_schedules(exchange)
+        .into_iter()
+        .filter(|withdrawal_schedule| {
+            withdrawal_schedule.exchange_account == exchange_account
+                && withdrawal_schedule.next_withdrawal <= now
+        })
+        .collect::<Vec<_>>();
+    if due_withdrawal_schedules.is_empty() {
+        return Ok(());
+    }
 
-    let deposit_address = exchange_client.deposit_address(token).await?;
-    let deposit_account = db.get_account(deposit_address, token).ok_or_else(|| {
-        format!(
-            "Exchange deposit account does not exist, run `sync` first: {deposit_address} ({token})",
-        )
-    })?;
+    let mut withdrawal_allowances = WithdrawalAllowances::new(exchange_client, rpc_client);
 
-    let balances = exchange_client.balances().await?;
-    let usd_balance = balances.get("USD").cloned().unwrap_or_default().available;
+    for mut withdrawal_schedule in due_withdrawal_schedules {
+        let token = withdrawal_schedule.token;
+        let mut amount = withdrawal_schedule
+            .chunk_amount
+            .min(withdrawal_schedule.remaining_amount);
 
-    if let Some(if_balance_exceeds) = if_balance_exceeds {
-        if usd_balance < if_balance_exceeds {
+        if let Some(remaining) = withdrawal_allowances.remaining(token).await? {
+            if remaining < amount {
+                println!(
+                    "Limiting scheduled {token} withdrawal to {} by the {exchange:?} withdrawal limit",
+                    token.format_amount(remaining)
+                );
+                amount = remaining;
+            }
+        }
+        if amount == 0 {
             println!(
-                "Order declined because {exchange:?} available balance is less than ${if_balance_exceeds}"
+                "Deferring scheduled {token} withdrawal to {}, {exchange:?} withdrawal limit reached",
+                withdrawal_schedule.to_address
             );
-            return Ok(());
+            continue;
+        }
+
+        let msg = format!(
+            "Scheduled withdrawal of {} to {} ({} remaining after)",
+            token.format_amount(amount),
+            withdrawal_schedule.to_address,
+            token.format_amount(withdrawal_schedule.remaining_amount - amount),
+        );
+        if db.is_dry_run() {
+            println!("Dry run: {msg}");
+            continue;
         }
+
+        let deposit_address = exchange_client.deposit_address(token).await?;
+        add_exchange_deposit_address_to_db(
+            db,
+            exchange,
+            exchange_account,
+            token,
+            deposit_address,
+            rpc_client,
+        )?;
+        process_exchange_withdraw(
+            db,
+            exchange,
+            exchange_client,
+            token,
+            deposit_address,
+            Some(amount),
+            withdrawal_schedule.to_address,
+            withdrawal_schedule.lot_selection_method,
+            None,
+            None,
+            None,
+        )
+        .await?;
+
+        withdrawal_allowances.withdrawn(token, amount).await?;
+        withdrawal_schedule.remaining_amount -= amount;
+        withdrawal_schedule.next_withdrawal =
+            now + chrono::Duration::try_hours(withdrawal_schedule.interval_hours.into()).unwrap();
+        db.set_withdrawal_schedule(withdrawal_schedule)?;
+
+        println!("{msg}");
+        notifier
+            .send(NotificationKind::Transfer, Severity::Info, &msg)
+            .await;
     }
+    Ok(())
+}
 
-    let price = match price {
-        LimitOrderPrice::At(price) => price,
-        LimitOrderPrice::AmountOverAsk(_) => panic!("Bug: AmountOverAsk invalid for a buy order"),
-        LimitOrderPrice::AmountUnderBid(extra) => bid_ask.bid_price - extra,
+// The current USD price of an exchange coin, if it's a USD coin or a token sys knows
+async fn exchange_coin_price(rpc_client: &RpcClient, coin: &str) -> Option<Decimal> {
+    if exchange::USD_COINS.contains(&coin) {
+        return Some(Decimal::ONE);
+    }
+    let token = match coin {
+        "SOL" => MaybeToken::SOL(),
+        coin => Token::from_str(coin).ok()?.into(),
     };
-    let price = (price * 10_000.).round() / 10_000.; // Round to four decimal places
+    token.get_current_price(rpc_client).await.ok()
+}
 
-    if price > bid_ask.bid_price {
-        return Err(format!("Order price, {price}, is greater than bid price").into());
+// The USD value of the fee of each disposal, by disposed lot number and disposal date. Fees paid
+// in a coin other than USD are valued at its price on the disposal date
+async fn disposal_fees_usd(
+    db: &Db,
+    rpc_client: &RpcClient,
+) -> Result<HashMap<(usize, NaiveDate), Decimal>, Box<dyn std::error::Error>> {
+    let mut fees_usd = HashMap::new();
+    for disposed_lot in db.disposed_lots() {
+        let Some((amount, coin)) = disposed_lot.kind.fee() else {
+            continue;
+        };
+        let amount = Decimal::from_f64(*amount).unwrap_or_default();
+        let fee_usd = if exchange::USD_COINS.contains(&coin.as_str()) || amount == Decimal::ZERO {
+            amount
+        } else {
+            let token = match coin.as_str() {
+                "SOL" => MaybeToken::SOL(),
+                coin => Token::from_str(coin)
+                    .map_err(|_| format!("Unsupported disposal fee currency: {coin}"))?
+                    .into(),
+            };
+            amount
+                * token
+                    .get_historical_price(rpc_client, disposed_lot.when)
+                    .await?
+        };
+        fees_usd.insert((disposed_lot.lot.lot_number, disposed_lot.when), fee_usd);
     }
+    Ok(fees_usd)
+}
 
-    let amount = match amount {
-        None => (usd_balance / price).floor(),
-        Some(amount) => amount,
+// Refreshes the borrow positions of a tracked margin account and notifies when its margin level
+// falls below a threshold. Interest that accrued since the last sync is added to the interest
+// expense, so interest charged and repaid between two syncs is missed
+async fn process_exchange_margin(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_account: &str,
+    exchange_client: &dyn ExchangeClient,
+    rpc_client: &RpcClient,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(mut margin_tracking) = db.get_margin_tracking(exchange, exchange_account) else {
+        return Ok(());
     };
+    let margin_account = exchange_client.margin_account().await?;
+
+    let mut positions = vec![];
+    for MarginBorrow {
+        coin,
+        borrowed,
+        interest,
+    } in margin_account.borrows
+    {
+        let price = exchange_coin_price(rpc_client, &coin).await;
+        let previous_interest = margin_tracking
+            .positions
+            .iter()
+            .find(|position| position.coin == coin)
+            .map(|position| position.interest)
+            .unwrap_or_default();
+        if let Some(price) = price {
+            if interest > previous_interest {
+                margin_tracking.interest_expense +=
+                    price * Decimal::from_f64(interest - previous_interest).unwrap();
+            }
+        } else {
+            println!("Unable to price borrowed {coin}, it is left out of net worth");
+        }
+        positions.push(BorrowPosition {
+            value: price.map(|price| price * Decimal::from_f64(borrowed + interest).unwrap()),
+            coin,
+            borrowed,
+            interest,
+        });
+    }
 
-    println!("Placing buy order for ◎{amount} at ${price}");
+    let previous_margin_level = margin_tracking.margin_level;
+    if let Some(margin_level) = margin_account.margin_level {
+        let crossed = |threshold: Option<f64>| {
+            threshold.is_some_and(|threshold| {
+                margin_level < threshold
+                    && previous_margin_level.map_or(true, |previous| previous >= threshold)
+            })
+        };
+        let severity = if crossed(margin_tracking.alert_margin_level) {
+            Some(Severity::Alert)
+        } else if crossed(margin_tracking.warn_margin_level) {
+            Some(Severity::Warn)
+        } else {
+            None
+        };
+        if let Some(severity) = severity {
+            let msg = format!(
+                "{exchange:?}{}: margin level fell to {margin_level:.2}",
+                if exchange_account.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {exchange_account}")
+                }
+            );
+            println!("{msg}");
+            notifier
+                .send(NotificationKind::Balance, severity, &msg)
+                .await;
+        }
+    }
 
-    let order_id = exchange_client
-        .place_order(&pair, OrderSide::Buy, price, amount)
-        .await?;
-    let msg = format!(
-        "Order created: {}: {:?} ◎{} at ${}, id {}",
-        pair,
-        OrderSide::Buy,
-        amount,
-        price,
-        order_id,
-    );
-    db.open_order(
-        OrderSide::Buy,
-        deposit_account,
-        exchange,
-        pair,
-        price,
-        order_id,
-        vec![],
-        Some(amount),
-    )?;
-    println!("{msg}");
-    notifier.send(&format!("{exchange:?}: {msg}")).await;
+    margin_tracking.margin_level = margin_account.margin_level;
+    margin_tracking.positions = positions;
+    margin_tracking.last_update = Some(Utc::now());
+    db.set_margin_tracking(margin_tracking)?;
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn process_exchange_sell(
+// The token of `coin` if it's held in a tracked deposit account of the exchange
+async fn tracked_exchange_token(
+    db: &Db,
+    exchange_client: &dyn ExchangeClient,
+    coin: &str,
+) -> Option<(MaybeToken, Pubkey)> {
+    let token = match coin {
+        "SOL" => MaybeToken::SOL(),
+        coin => Token::from_str(coin).ok()?.into(),
+    };
+    let deposit_address = exchange_client.deposit_address(token).await.ok()?;
+    db.get_account(deposit_address, token)
+        .map(|_| (token, deposit_address))
+}
+
+async fn process_exchange_dust_convert(
     db: &mut Db,
     exchange: Exchange,
     exchange_client: &dyn ExchangeClient,
-    token: MaybeToken,
-    pair: String,
-    amount: f64,
-    price: LimitOrderPrice,
-    if_balance_exceeds: Option<u64>,
-    if_price_over: Option<f64>,
-    if_price_over_basis: bool,
-    price_floor: Option<f64>,
-    lot_selection_method: LotSelectionMethod,
-    lot_numbers: Option<HashSet<usize>>,
+    coins: Option<Vec<String>>,
+    confirm: bool,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dust_balances = exchange_client
+        .dust_balances()
+        .await?
+        .into_iter()
+        .filter(|dust_balance| {
+            coins
+                .as_ref()
+                .is_none_or(|coins| coins.contains(&dust_balance.coin))
+        })
+        .collect::<Vec<_>>();
+
+    if dust_balances.is_empty() {
+        println!("No dust to convert");
+        return Ok(());
+    }
+    for dust_balance in &dust_balances {
+        println!(
+            "{} {} -> ~{} {}",
+            dust_balance.coin,
+            dust_balance.amount,
+            dust_balance.converted_amount,
+            dust_balance.converted_coin
+        );
+    }
+    if !confirm {
+        println!("Add --confirm to convert");
+        return Ok(());
+    }
+
+    let coins = dust_balances
+        .into_iter()
+        .map(|dust_balance| dust_balance.coin)
+        .collect::<Vec<_>>();
+    for conversion in exchange_client.convert_dust(&coins).await? {
+        let msg = format!(
+            "Converted {} {} dust to {} {} (fee: {} {}, id {})",
+            conversion.amount,
+            conversion.coin,
+            conversion.converted_amount,
+            conversion.converted_coin,
+            conversion.fee,
+            conversion.converted_coin,
+            conversion.id,
+        );
+        println!("{msg}");
+        notifier
+            .send(
+                NotificationKind::Disposal,
+                Severity::Info,
+                &format!("{exchange:?}: {msg}"),
+            )
+            .await;
+
+        // Value the conversion with the USD price of the coin received
+        let converted_coin_price = match exchange_client
+            .bid_ask(&format!("{}USDT", conversion.converted_coin))
+            .await
+        {
+            Ok(bid_ask) => bid_ask.bid_price,
+            Err(err) => {
+                let msg = format!(
+                    "Unable to price {}, record the dust conversion {} manually: {err}",
+                    conversion.converted_coin, conversion.id
+                );
+                println!("{msg}");
+                notifier
+                    .send(
+                        NotificationKind::Disposal,
+                        Severity::Alert,
+                        &format!("{exchange:?}: {msg}"),
+                    )
+                    .await;
+                continue;
+            }
+        };
+
+        if let Some((token, deposit_address)) =
+            tracked_exchange_token(db, exchange_client, &conversion.coin).await
+        {
+            let price = conversion.converted_amount * converted_coin_price / conversion.amount;
+            if let Err(err) = db.record_disposal(
+                deposit_address,
+                token,
+                token.amount(conversion.amount),
+                format!(
+                    "Dust conversion to {} {} ({})",
+                    conversion.converted_amount, conversion.converted_coin, conversion.id
+                ),
+                today(),
+                Decimal::from_f64(price).unwrap(),
+                LotSelectionMethod::default(),
+                None,
+            ) {
+                let msg = format!(
+                    "Failed to record the disposal of dust conversion {}: {err}",
+                    conversion.id
+                );
+                println!("{msg}");
+                notifier
+                    .send(
+                        NotificationKind::Disposal,
+                        Severity::Alert,
+                        &format!("{exchange:?}: {msg}"),
+                    )
+                    .await;
+            }
+        }
+
+        if let Some((token, deposit_address)) =
+            tracked_exchange_token(db, exchange_client, &conversion.converted_coin).await
+        {
+            let mut deposit_account = db.get_account(deposit_address, token).unwrap();
+            let amount = token.amount(conversion.converted_amount);
+            deposit_account.lots.push(Lot {
+                lot_number: db.next_lot_number(),
+                acquisition: LotAcquistion::new(
+                    today(),
+                    Decimal::from_f64(converted_coin_price).unwrap(),
+                    LotAcquistionKind::Exchange {
+                        exchange,
+                        pair: format!("{}{}", conversion.coin, conversion.converted_coin),
+                        order_id: conversion.id.clone(),
+                    },
+                ),
+                amount,
+            });
+            deposit_account.last_update_balance += amount;
+            db.update_account(deposit_account)?;
+        }
+    }
+    Ok(())
+}
+
+enum LimitOrderPrice {
+    At(f64),
+    AmountOverAsk(f64),
+    AmountUnderBid(f64),
+}
+
+// Market orders are recorded as taker orders that fill immediately
+const MARKET_ORDER_OPTIONS: OrderOptions = OrderOptions {
+    time_in_force: TimeInForce::ImmediateOrCancel,
+    post_only: false,
+};
+
+#[allow(clippy::too_many_arguments)]
+async fn process_exchange_cancel(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    order_ids: HashSet<String>,
+    max_create_time: Option<DateTime<Utc>>,
+    side: Option<OrderSide>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cancelled_count = 0;
+    for order_info in db.open_orders(Some(exchange), side) {
+        let mut cancel = false;
+        if order_ids.contains(&order_info.order_id) {
+            cancel = true;
+        }
+
+        if let Some(ref max_create_time) = max_create_time {
+            if order_info.creation_time < *max_create_time {
+                cancel = true;
+            }
+        }
+
+        if cancel {
+            println!("Cancelling order {}", order_info.order_id);
+            cancelled_count += 1;
+            exchange_client
+                .cancel_order(&order_info.pair, &order_info.order_id)
+                .await
+                .unwrap_or_else(|err| eprintln!("{err:?}"));
+        }
+    }
+
+    println!("{cancelled_count} orders cancelled");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_exchange_buy(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    token: MaybeToken,
+    pair: String,
+    amount: Option<f64>,
+    price: Option<LimitOrderPrice>, // `None` for a market order
+    if_balance_exceeds: Option<f64>,
+    group: Option<String>,
+    options: OrderOptions,
+    max_book_fraction: Option<f64>,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bid_ask = exchange_client.bid_ask(&pair).await?;
+    println!(
+        "{} | Ask: ${}, Bid: ${}",
+        pair, bid_ask.ask_price, bid_ask.bid_price
+    );
+
+    let deposit_address = exchange_client.deposit_address(token).await?;
+    let deposit_account = db.get_account(deposit_address, token).ok_or_else(|| {
+        format!(
+            "Exchange deposit account does not exist, run `sync` first: {deposit_address} ({token})",
+        )
+    })?;
+
+    let balances = exchange_client.balances().await?;
+    let usd_balance = balances.get("USD").cloned().unwrap_or_default().available;
+
+    if let Some(if_balance_exceeds) = if_balance_exceeds {
+        if usd_balance < if_balance_exceeds {
+            println!(
+                "Order declined because {exchange:?} available balance is less than ${if_balance_exceeds}"
+            );
+            return Ok(());
+        }
+    }
+
+    let market = price.is_none();
+    let price = match price {
+        Some(LimitOrderPrice::At(price)) => price,
+        Some(LimitOrderPrice::AmountOverAsk(_)) => {
+            panic!("Bug: AmountOverAsk invalid for a buy order")
+        }
+        Some(LimitOrderPrice::AmountUnderBid(extra)) => bid_ask.bid_price - extra,
+        // Estimated until the order fills, `sync` records the actual fill price
+        None => bid_ask.ask_price,
+    };
+    let price = (price * 10_000.).round() / 10_000.; // Round to four decimal places
+
+    if !market && options.post_only && price > bid_ask.bid_price {
+        return Err(format!("Order price, {price}, is greater than bid price").into());
+    }
+
+    let amount = match amount {
+        None => (usd_balance / price).floor(),
+        Some(amount) => amount,
+    };
+
+    if let Some(max_book_fraction) = max_book_fraction {
+        check_price_impact(
+            exchange_client,
+            &pair,
+            OrderSide::Buy,
+            amount,
+            max_book_fraction,
+        )
+        .await?;
+    }
+
+    let (order_id, options) = if market {
+        println!(
+            "Placing market buy order for {}{amount} at about ${price}",
+            token.symbol()
+        );
+        (
+            exchange_client
+                .place_market_order(&pair, OrderSide::Buy, amount)
+                .await?,
+            MARKET_ORDER_OPTIONS,
+        )
+    } else {
+        println!(
+            "Placing {options} buy order for {}{amount} at ${price}",
+            token.symbol()
+        );
+        (
+            exchange_client
+                .place_order(&pair, OrderSide::Buy, price, amount, options)
+                .await?,
+            options,
+        )
+    };
+    let msg = format!(
+        "Order created: {}: {:?} {}{} at ${}, id {}",
+        pair,
+        OrderSide::Buy,
+        token.symbol(),
+        amount,
+        price,
+        order_id,
+    );
+    db.open_order(
+        OrderSide::Buy,
+        deposit_account,
+        exchange,
+        pair,
+        price,
+        order_id,
+        vec![],
+        Some(amount),
+        group,
+        options,
+    )?;
+    println!("{msg}");
+    notifier
+        .send(
+            NotificationKind::Order,
+            Severity::Info,
+            &format!("{exchange:?}: {msg}"),
+        )
+        .await;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_exchange_sell(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    token: MaybeToken,
+    pair: String,
+    amount: f64,
+    price: Option<LimitOrderPrice>, // `None` for a market order
+    if_balance_exceeds: Option<u64>,
+    if_price_over: Option<f64>,
+    if_price_over_basis: bool,
+    price_floor: Option<f64>,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    group: Option<String>,
+    options: OrderOptions,
+    max_book_fraction: Option<f64>,
     notifier: &Notifier,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let bid_ask = exchange_client.bid_ask(&pair).await?;
@@ -877,12 +2307,15 @@ async fn process_exchange_sell(
         }
     }
 
+    let market = price.is_none();
     let price = match price {
-        LimitOrderPrice::At(price) => price,
-        LimitOrderPrice::AmountOverAsk(extra) => bid_ask.ask_price + extra,
-        LimitOrderPrice::AmountUnderBid(_) => {
+        Some(LimitOrderPrice::At(price)) => price,
+        Some(LimitOrderPrice::AmountOverAsk(extra)) => bid_ask.ask_price + extra,
+        Some(LimitOrderPrice::AmountUnderBid(_)) => {
             panic!("Bug: AmountUnderBid invalid for a sell order")
         }
+        // Estimated until the order fills, `sync` records the actual fill price
+        None => bid_ask.bid_price,
     };
     let mut price = (price * 100.).round() / 100.; // Round to two decimal places
 
@@ -892,7 +2325,13 @@ async fn process_exchange_sell(
                 "Order declined because price, ${price}, is not greater than ${if_price_over}",
             );
             println!("{msg}");
-            notifier.send(&format!("{exchange:?}: {msg}")).await;
+            notifier
+                .send(
+                    NotificationKind::Order,
+                    Severity::Warn,
+                    &format!("{exchange:?}: {msg}"),
+                )
+                .await;
             return Ok(());
         }
     }
@@ -903,7 +2342,13 @@ async fn process_exchange_sell(
                 format!("Proposed price, ${price}, is beneath price floor. Adjusting upwards");
             price = price_floor;
             println!("{msg}");
-            notifier.send(&format!("{exchange:?}: {msg}")).await;
+            notifier
+                .send(
+                    NotificationKind::Order,
+                    Severity::Warn,
+                    &format!("{exchange:?}: {msg}"),
+                )
+                .await;
         }
     }
 
@@ -925,16 +2370,43 @@ async fn process_exchange_sell(
             let msg =
                 format!("Order declined because price, ${price}, is less than basis ${basis}",);
             println!("{msg}");
-            notifier.send(&format!("{exchange:?}: {msg}")).await;
+            notifier
+                .send(
+                    NotificationKind::Order,
+                    Severity::Warn,
+                    &format!("{exchange:?}: {msg}"),
+                )
+                .await;
             return Ok(());
         }
     }
 
-    if price < bid_ask.ask_price {
+    if !market && options.post_only && price < bid_ask.ask_price {
         return Err("Order price is less than ask price".into());
     }
 
-    println!("Placing sell order for ◎{amount} at ${price}");
+    if let Some(max_book_fraction) = max_book_fraction {
+        check_price_impact(
+            exchange_client,
+            &pair,
+            OrderSide::Sell,
+            amount,
+            max_book_fraction,
+        )
+        .await?;
+    }
+
+    if market {
+        println!(
+            "Placing market sell order for {}{amount} at about ${price}",
+            token.symbol()
+        );
+    } else {
+        println!(
+            "Placing {options} sell order for {}{amount} at ${price}",
+            token.symbol()
+        );
+    }
     println!("Lots");
     for lot in &order_lots {
         maybe_println_lot(
@@ -942,25 +2414,40 @@ async fn process_exchange_sell(
             lot,
             Decimal::from_f64(price),
             None,
-            &mut 0.,
-            &mut 0.,
-            &mut 0.,
+            None,
+            &mut Decimal::ZERO,
+            &mut Decimal::ZERO,
+            &mut Decimal::ZERO,
             &mut false,
-            &mut 0.,
+            &mut Decimal::ZERO,
             None,
+            DEFAULT_LOT_COLUMNS,
             true,
             true,
         )
         .await;
     }
 
-    let order_id = exchange_client
-        .place_order(&pair, OrderSide::Sell, price, amount)
-        .await?;
+    let (order_id, options) = if market {
+        (
+            exchange_client
+                .place_market_order(&pair, OrderSide::Sell, amount)
+                .await?,
+            MARKET_ORDER_OPTIONS,
+        )
+    } else {
+        (
+            exchange_client
+                .place_order(&pair, OrderSide::Sell, price, amount, options)
+                .await?,
+            options,
+        )
+    };
     let msg = format!(
-        "Order created: {}: {:?} ◎{} at ${}, id {}",
+        "Order created: {}: {:?} {}{} at ${}, id {}",
         pair,
         OrderSide::Sell,
+        token.symbol(),
         amount,
         price,
         order_id,
@@ -974,23 +2461,509 @@ async fn process_exchange_sell(
         order_id,
         order_lots,
         None,
+        group,
+        options,
     )?;
     println!("{msg}");
-    notifier.send(&format!("{exchange:?}: {msg}")).await;
+    notifier
+        .send(
+            NotificationKind::Order,
+            Severity::Info,
+            &format!("{exchange:?}: {msg}"),
+        )
+        .await;
     Ok(())
 }
 
-fn println_jup_quote(from_token: MaybeToken, to_token: MaybeToken, quote: &jup_ag::Quote) {
-    let route = quote
-        .route_plan
+#[allow(clippy::too_many_arguments)]
+async fn process_exchange_sell_ladder(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    token: MaybeToken,
+    pair: String,
+    total_amount: f64,
+    from_price: f64,
+    to_price: f64,
+    steps: u64,
+    lot_selection_method: LotSelectionMethod,
+    group: String,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if db
+        .open_orders(Some(exchange), None)
         .iter()
-        .map(|route_plan| route_plan.swap_info.label.clone().unwrap_or_default())
-        .join(", ");
-    println!(
-        "Swap {}{} for {}{} (min: {}{}) via {}",
-        from_token.symbol(),
-        from_token.ui_amount(quote.in_amount),
-        to_token.symbol(),
+        .any(|order_info| order_info.group.as_ref() == Some(&group))
+    {
+        return Err(format!("Order group {group} already exists").into());
+    }
+
+    // Split the amount evenly across the steps, with any remainder in the final order
+    let total_amount = token.amount(total_amount);
+    let step_amount = total_amount / steps;
+    if step_amount == 0 {
+        return Err("Amount is too small for the number of steps".into());
+    }
+
+    println!("Placing {steps} sell orders from ${from_price} to ${to_price} in group {group}");
+    for step in 0..steps {
+        let amount = if step + 1 == steps {
+            total_amount - step_amount * (steps - 1)
+        } else {
+            step_amount
+        };
+        let price = if steps == 1 {
+            from_price
+        } else {
+            from_price + (to_price - from_price) * step as f64 / (steps - 1) as f64
+        };
+        let price = (price * 10_000.).round() / 10_000.; // Round to four decimal places
+
+        process_exchange_sell(
+            db,
+            exchange,
+            exchange_client,
+            token,
+            pair.clone(),
+            token.ui_amount(amount),
+            Some(LimitOrderPrice::At(price)),
+            None,
+            None,
+            false,
+            None,
+            lot_selection_method,
+            None,
+            Some(group.clone()),
+            OrderOptions::default(),
+            Some(DEFAULT_MAX_BOOK_FRACTION),
+            notifier,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Export the fills, deposits, withdrawals and fees of `exchange` in the month starting on
+// `month` as CSV
+fn process_exchange_statement(
+    db: &Db,
+    exchange: Exchange,
+    month: NaiveDate,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let next_month = month
+        .checked_add_months(chrono::Months::new(1))
+        .ok_or("Invalid month")?;
+    let in_month = |when: NaiveDate| month <= when && when < next_month;
+
+    struct StatementRow {
+        when: NaiveDate,
+        kind: &'static str,
+        token: MaybeToken,
+        amount: f64,
+        price: Option<f64>,
+        fee: Option<(f64, String)>,
+        reference: String,
+    }
+    let mut rows = vec![];
+
+    // Buy fills, aggregated across the lots they were since split into
+    let mut buy_fills = BTreeMap::<(NaiveDate, String), (MaybeToken, u64, f64)>::new();
+    let lots = db
+        .get_accounts()
+        .into_iter()
+        .chain(db.get_archived_accounts())
+        .flat_map(|account| {
+            let token = account.token;
+            account.lots.into_iter().map(move |lot| (token, lot))
+        })
+        .chain(
+            db.disposed_lots()
+                .into_iter()
+                .map(|disposed_lot| (disposed_lot.token, disposed_lot.lot)),
+        );
+    for (token, lot) in lots {
+        if let LotAcquistionKind::Exchange {
+            exchange: lot_exchange,
+            ref order_id,
+            ..
+        } = lot.acquisition.kind
+        {
+            if lot_exchange == exchange && in_month(lot.acquisition.when) {
+                let price = lot.acquisition.price().to_f64().unwrap_or_default();
+                buy_fills
+                    .entry((lot.acquisition.when, order_id.clone()))
+                    .or_insert((token, 0, price))
+                    .1 += lot.amount;
+            }
+        }
+    }
+    for ((when, order_id), (token, amount, price)) in buy_fills {
+        rows.push(StatementRow {
+            when,
+            kind: "Buy",
+            token,
+            amount: token.ui_amount(amount),
+            price: Some(price),
+            fee: None,
+            reference: order_id,
+        });
+    }
+
+    // Sell fills, with the fee that was split across the disposed lots
+    let mut sell_fills =
+        BTreeMap::<(NaiveDate, String), (MaybeToken, u64, f64, Option<(f64, String)>)>::new();
+    for disposed_lot in db.disposed_lots() {
+        if let LotDisposalKind::Usd {
+            exchange: lot_exchange,
+            ref order_id,
+            ref fee,
+            ..
+        } = disposed_lot.kind
+        {
+            if lot_exchange == exchange && in_month(disposed_lot.when) {
+                let sell_fill = sell_fills
+                    .entry((disposed_lot.when, order_id.clone()))
+                    .or_insert((
+                        disposed_lot.token,
+                        0,
+                        disposed_lot.price().to_f64().unwrap_or_default(),
+                        None,
+                    ));
+                sell_fill.1 += disposed_lot.lot.amount;
+                if let Some((fee_amount, fee_coin)) = fee {
+                    sell_fill.3.get_or_insert_with(|| (0., fee_coin.clone())).0 += fee_amount;
+                }
+            }
+        }
+    }
+    for ((when, order_id), (token, amount, price, fee)) in sell_fills {
+        rows.push(StatementRow {
+            when,
+            kind: "Sell",
+            token,
+            amount: token.ui_amount(amount),
+            price: Some(price),
+            fee,
+            reference: order_id,
+        });
+    }
+
+    for exchange_transfer in db.exchange_transfers(exchange) {
+        if in_month(exchange_transfer.when) {
+            let token = exchange_transfer.token;
+            rows.push(StatementRow {
+                when: exchange_transfer.when,
+                kind: match exchange_transfer.kind {
+                    ExchangeTransferKind::Deposit => "Deposit",
+                    ExchangeTransferKind::Withdrawal => "Withdrawal",
+                },
+                token,
+                amount: token.ui_amount(exchange_transfer.amount),
+                price: None,
+                fee: (exchange_transfer.fee > 0)
+                    .then(|| (token.ui_amount(exchange_transfer.fee), token.to_string())),
+                reference: exchange_transfer.reference,
+            });
+        }
+    }
+    rows.sort_by_key(|row| row.when);
+
+    let mut csv =
+        "Date,Type,Token,Amount,Price (USD),Value (USD),Fee,Fee Currency,Reference\n".to_string();
+    for row in &rows {
+        let (fee_amount, fee_coin) = match &row.fee {
+            Some((fee_amount, fee_coin)) => (fee_amount.to_string(), fee_coin.clone()),
+            None => (String::new(), String::new()),
+        };
+        csv += &[
+            row.when.to_string(),
+            row.kind.to_string(),
+            row.token.to_string(),
+            row.amount.to_string(),
+            row.price.map(|price| price.to_string()).unwrap_or_default(),
+            row.price
+                .map(|price| format!("{:.2}", price * row.amount))
+                .unwrap_or_default(),
+            fee_amount,
+            fee_coin,
+            row.reference.clone(),
+        ]
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",");
+        csv += "\n";
+    }
+    fs::write(output, csv)?;
+
+    println!(
+        "Wrote {} {exchange:?} statement entries for {} to {}",
+        rows.len(),
+        month.format("%Y-%m"),
+        output.display()
+    );
+    Ok(())
+}
+
+const DEFAULT_MAX_BOOK_FRACTION: f64 = 0.1;
+
+// Refuse orders larger than `max_book_fraction` of the visible depth on the side of the order book
+// they would trade against, reporting the price impact of filling the order against it
+async fn check_price_impact(
+    exchange_client: &dyn ExchangeClient,
+    pair: &str,
+    side: OrderSide,
+    amount: f64,
+    max_book_fraction: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let order_book = exchange_client.order_book(pair).await?;
+    let price_levels = match side {
+        OrderSide::Buy => order_book.asks,
+        OrderSide::Sell => order_book.bids,
+    };
+    let depth = price_levels
+        .iter()
+        .map(|(_price, level_amount)| level_amount)
+        .sum::<f64>();
+    if amount <= depth * max_book_fraction {
+        return Ok(());
+    }
+
+    let best_price = price_levels
+        .first()
+        .map(|(price, _level_amount)| *price)
+        .ok_or("Order book is empty")?;
+    let (mut remaining_amount, mut total_value) = (amount, 0.);
+    for (price, level_amount) in price_levels {
+        let fill_amount = remaining_amount.min(level_amount);
+        total_value += fill_amount * price;
+        remaining_amount -= fill_amount;
+        if remaining_amount <= 0. {
+            break;
+        }
+    }
+    let average_price = total_value / (amount - remaining_amount);
+    let price_impact = (average_price - best_price).abs() / best_price * 100.;
+
+    let sol = MaybeToken::SOL().symbol();
+    Err(format!(
+        "{side} order for {sol}{amount} is {:.1}% of the visible order book depth of {sol}{depth:.2}. \
+         Filling it against the book would move the price {price_impact:.2}% to ${average_price:.4}{}. \
+         Use --allow-impact to proceed",
+        amount / depth * 100.,
+        if remaining_amount > 0. {
+            format!(", with {sol}{remaining_amount:.2} left unfilled")
+        } else {
+            String::new()
+        },
+    )
+    .into())
+}
+
+// Keep the exchange synchronized, closing orders as soon as the exchange reports a fill
+async fn process_exchange_watch(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    rpc_client: &RpcClient,
+    poll_interval: Duration,
+    notifier: &Notifier,
+    healthcheck: &Healthcheck,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut order_updates = exchange_client.order_updates().await?;
+    if order_updates.is_none() {
+        println!(
+            "{exchange:?} does not support streaming order updates, polling every {}s",
+            poll_interval.as_secs()
+        );
+    }
+
+    loop {
+        let result =
+            process_sync_exchange(db, exchange, exchange_client, rpc_client, false, notifier).await;
+        healthcheck.report(&result).await;
+        result?;
+
+        match order_updates.as_mut() {
+            None => tokio::time::sleep(poll_interval).await,
+            Some(receiver) => match tokio::time::timeout(poll_interval, receiver.recv()).await {
+                Err(_) => {} // No order updates, synchronize anyway to catch deposits and withdrawals
+                Ok(Some(order_id)) => println!("Order update received for {order_id}"),
+                Ok(None) => {
+                    println!("{exchange:?} order update stream closed, reconnecting");
+                    order_updates = exchange_client.order_updates().await?;
+                }
+            },
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_exchange_orders(
+    db: &mut Db,
+    exchange: Exchange,
+    exchange_client: &dyn ExchangeClient,
+    rpc_client: &RpcClient,
+    group: Option<String>,
+    cancel: bool,
+    reprice_by: Option<f64>,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let orders = db
+        .open_orders(Some(exchange), None)
+        .into_iter()
+        .filter(|order_info| group.is_none() || order_info.group == group)
+        .collect::<Vec<_>>();
+    if orders.is_empty() {
+        match group {
+            Some(group) => println!("No open orders in group {group}"),
+            None => println!("No open orders"),
+        }
+        return Ok(());
+    }
+
+    let (mut total_amount, mut total_filled_amount) = (0., 0.);
+    let mut remaining_orders = vec![];
+    for order_info in orders {
+        let token = order_info.token;
+        let order_status = exchange_client
+            .order_status(&order_info.pair, &order_info.order_id)
+            .await?;
+        println!(
+            "{}: {} {} {}{:<5} at ${:<.2}{} {} | id {} created {}{}",
+            order_info.pair,
+            token,
+            format_order_side(order_info.side),
+            token.symbol(),
+            order_status.amount,
+            order_status.price,
+            format_filled_amount(order_status.filled_amount),
+            order_info.options,
+            order_info.order_id,
+            HumanTime::from(order_info.creation_time),
+            match &order_info.group {
+                Some(group) => format!(" | group {group}"),
+                None => String::new(),
+            }
+        );
+        total_amount += order_status.amount;
+        total_filled_amount += order_status.filled_amount;
+
+        if cancel || reprice_by.is_some() {
+            println!("Cancelling order {}", order_info.order_id);
+            exchange_client
+                .cancel_order(&order_info.pair, &order_info.order_id)
+                .await?;
+            remaining_orders.push((
+                order_info,
+                order_status.amount - order_status.filled_amount,
+                order_status.price,
+            ));
+        }
+    }
+    if let Some(group) = group {
+        println!("Group {group}: {total_filled_amount} of {total_amount} filled");
+    }
+    if remaining_orders.is_empty() {
+        return Ok(());
+    }
+
+    // Close the cancelled orders, returning the earmarked lots of sell orders to the deposit
+    // account
+    process_sync_exchange(db, exchange, exchange_client, rpc_client, false, notifier).await?;
+
+    if let Some(reprice_by) = reprice_by {
+        for (order_info, amount, price) in remaining_orders {
+            if db
+                .open_orders(Some(exchange), None)
+                .iter()
+                .any(|open_order| open_order.order_id == order_info.order_id)
+            {
+                return Err(format!(
+                    "Order {} has not been cancelled yet, unable to re-price",
+                    order_info.order_id
+                )
+                .into());
+            }
+            if amount < f64::EPSILON {
+                continue;
+            }
+
+            let price = Some(LimitOrderPrice::At(
+                ((price + reprice_by) * 10_000.).round() / 10_000.,
+            ));
+            match order_info.side {
+                OrderSide::Buy => {
+                    process_exchange_buy(
+                        db,
+                        exchange,
+                        exchange_client,
+                        order_info.token,
+                        order_info.pair,
+                        Some(amount),
+                        price,
+                        None,
+                        order_info.group,
+                        order_info.options,
+                        Some(DEFAULT_MAX_BOOK_FRACTION),
+                        notifier,
+                    )
+                    .await?
+                }
+                OrderSide::Sell => {
+                    // Re-sell the lots that the original order earmarked
+                    let lot_numbers = order_info
+                        .lots
+                        .iter()
+                        .map(|lot| lot.lot_number)
+                        .collect::<HashSet<_>>();
+                    process_exchange_sell(
+                        db,
+                        exchange,
+                        exchange_client,
+                        order_info.token,
+                        order_info.pair,
+                        amount,
+                        price,
+                        None,
+                        None,
+                        false,
+                        None,
+                        LotSelectionMethod::default(),
+                        Some(lot_numbers),
+                        order_info.group,
+                        order_info.options,
+                        Some(DEFAULT_MAX_BOOK_FRACTION),
+                        notifier,
+                    )
+                    .await?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn println_jup_quote(from_token: MaybeToken, to_token: MaybeToken, quote: &jup_ag::Quote) {
+    let route = quote
+        .route_plan
+        .iter()
+        .map(|route_plan| route_plan.swap_info.label.clone().unwrap_or_default())
+        .join(", ");
+    println!(
+        "Swap {}{} for {}{} (min: {}{}) via {}",
+        from_token.symbol(),
+        from_token.ui_amount(quote.in_amount),
+        to_token.symbol(),
         to_token.ui_amount(quote.out_amount),
         to_token.symbol(),
         to_token.ui_amount(quote.other_amount_threshold),
@@ -998,6 +2971,142 @@ fn println_jup_quote(from_token: MaybeToken, to_token: MaybeToken, quote: &jup_a
     );
 }
 
+fn swap_route(quote: &jup_ag::Quote) -> Vec<SwapRouteStep> {
+    quote
+        .route_plan
+        .iter()
+        .map(|route_plan| SwapRouteStep {
+            venue: route_plan.swap_info.label.clone().unwrap_or_default(),
+            percent: route_plan.percent,
+            fee_amount: route_plan.swap_info.fee_amount,
+            fee_mint: route_plan.swap_info.fee_mint,
+        })
+        .collect()
+}
+
+// Fees charged in one of `tokens` are shown in its UI amount, others in the raw amount of their
+// mint
+fn format_swap_route(route: &[SwapRouteStep], tokens: &[MaybeToken]) -> String {
+    route
+        .iter()
+        .map(|step| {
+            let fee = match tokens.iter().find(|token| token.mint() == step.fee_mint) {
+                Some(token) => format!("{}{}", token.symbol(), token.ui_amount(step.fee_amount)),
+                None => format!("{} of {}", step.fee_amount, step.fee_mint),
+            };
+            format!("{} {}% (fee {})", step.venue, step.percent, fee)
+        })
+        .join(", ")
+}
+
+// Lists the completed swaps, optionally only those of `year`, and writes them to `csv` as CSV.
+// The value lost is that of the source tokens less that of the destination tokens, at the prices
+// the swap was quoted at
+fn process_jup_history(
+    db: &Db,
+    year: Option<i32>,
+    csv: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let swap_records = db
+        .swap_records()
+        .into_iter()
+        .filter(|swap_record| year.map_or(true, |year| swap_record.when.year() == year))
+        .collect::<Vec<_>>();
+
+    let mut rows = vec![];
+    for swap_record in &swap_records {
+        let SwapRecord {
+            signature,
+            when,
+            from_token,
+            from_amount,
+            from_token_price,
+            to_token,
+            to_amount,
+            to_token_price,
+            fee,
+            disposed_lots,
+            acquired_lot,
+            route,
+            ..
+        } = swap_record;
+
+        let from_ui_amount = from_token.ui_amount_decimal(*from_amount);
+        let to_ui_amount = to_token.ui_amount_decimal(*to_amount);
+        let from_value = from_ui_amount * from_token_price;
+        let value_loss = from_value - to_ui_amount * to_token_price;
+        let effective_price = to_ui_amount / from_ui_amount;
+        let disposed_lots = disposed_lots.iter().map(|lot| lot.to_string()).join(" ");
+        let route = route
+            .as_ref()
+            .map(|route| format_swap_route(route, &[*from_token, *to_token]))
+            .unwrap_or_default();
+
+        println!(
+            "{} | {}{} -> {}{} | {}{} per {}1 | value loss {} ({}%) | fee {}{} | lots {} -> {} | {}",
+            when,
+            from_token.symbol(),
+            from_token
+                .ui_amount(*from_amount)
+                .localized_string_with_fixed_place(2),
+            to_token.symbol(),
+            to_token
+                .ui_amount(*to_amount)
+                .localized_string_with_fixed_place(2),
+            to_token.symbol(),
+            f64::try_from(effective_price)
+                .unwrap()
+                .localized_string_with_fixed_place(4),
+            from_token.symbol(),
+            format_currency(value_loss),
+            format_percent(value_loss, from_value),
+            MaybeToken::SOL().symbol(),
+            lamports_to_sol(*fee),
+            disposed_lots,
+            acquired_lot,
+            signature,
+        );
+        if !route.is_empty() {
+            println!("  via {route}");
+        }
+
+        rows.push([
+            when.to_string(),
+            from_token.to_string(),
+            from_ui_amount.to_string(),
+            from_token_price.to_string(),
+            to_token.to_string(),
+            to_ui_amount.to_string(),
+            to_token_price.to_string(),
+            effective_price.round_dp(9).normalize().to_string(),
+            value_loss.round_dp(2).to_string(),
+            lamports_to_sol(*fee).to_string(),
+            disposed_lots,
+            acquired_lot.to_string(),
+            route,
+            signature.to_string(),
+        ]);
+    }
+
+    if let Some(csv_path) = csv {
+        let mut csv = "Date,From Token,From Amount,From Price (USD),To Token,To Amount,\
+                       To Price (USD),Effective Price,Value Loss (USD),Fee (SOL),Disposed Lots,\
+                       Acquired Lot,Route,Signature\n"
+            .to_string();
+        for row in &rows {
+            csv += &row
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(",");
+            csv += "\n";
+        }
+        fs::write(csv_path, csv)?;
+        println!("Wrote {} swaps to {}", rows.len(), csv_path.display());
+    }
+    Ok(())
+}
+
 async fn process_jup_quote(
     from_token: MaybeToken,
     to_token: MaybeToken,
@@ -1035,7 +3144,9 @@ async fn process_jup_swap<T: Signers>(
     for_no_less_than: Option<f64>,
     max_coingecko_value_percentage_loss: f64,
     priority_fee: PriorityFee,
+    force_priority_fee: bool,
     notifier: &Notifier,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let rpc_client = rpc_clients.default();
 
@@ -1056,10 +3167,14 @@ async fn process_jup_swap<T: Signers>(
             to_token,
             to_token_price,
             lot_selection_method,
+            None,
         )?;
     } else {
         let amount = match ui_amount {
             Some(ui_amount) => from_token.amount(ui_amount),
+            None if from_token.is_sol() => from_account
+                .last_update_balance
+                .saturating_sub(db.sol_reserve(address)),
             None => from_account.last_update_balance,
         };
 
@@ -1072,6 +3187,11 @@ async fn process_jup_swap<T: Signers>(
             )
             .into());
         }
+        if from_token.is_sol() {
+            check_sol_reserve(db, address, from_account.last_update_balance, amount)?;
+        } else if db.sol_reserve(address) > 0 {
+            check_sol_reserve(db, address, rpc_client.get_balance(&address)?, 0)?;
+        }
 
         let swap_prefix = format!("Swap {}->{}", from_token, to_token);
 
@@ -1113,6 +3233,12 @@ async fn process_jup_swap<T: Signers>(
         .await?;
 
         println_jup_quote(from_token, to_token, &quote);
+        if verbose {
+            println!(
+                "Route: {}",
+                format_swap_route(&swap_route(&quote), &[from_token, to_token])
+            );
+        }
 
         let from_value =
             from_token_price * Decimal::from_f64(from_token.ui_amount(quote.in_amount)).unwrap();
@@ -1139,7 +3265,9 @@ async fn process_jup_swap<T: Signers>(
                 let to_token_symbol = to_token.symbol();
                 let msg = format!("{swap_prefix} would not result in at least {to_token_symbol}{for_no_less_than} tokens, only would have received {to_token_symbol}{to_token_amount}");
                 println!("{msg}");
-                notifier.send(&msg).await;
+                notifier
+                    .send(NotificationKind::Swap, Severity::Info, &msg)
+                    .await;
                 return Ok(());
             }
         }
@@ -1156,31 +3284,53 @@ async fn process_jup_swap<T: Signers>(
         let mut transaction = jup_ag::swap(swap_request).await?.swap_transaction;
 
         {
-            let mut transaction_compute_budget = sys::priority_fee::ComputeBudget::default();
+            let mut transaction_compute_budget =
+                sys::priority_fee::ComputeBudget::from_compiled_instructions(
+                    transaction.message.static_account_keys(),
+                    transaction.message.instructions(),
+                );
 
-            let static_account_keys = transaction.message.static_account_keys();
-            for instruction in transaction.message.instructions() {
-                if let Some(program_id) =
-                    static_account_keys.get(instruction.program_id_index as usize)
-                {
-                    if *program_id == compute_budget::id() {
-                        match solana_sdk::borsh0_10::try_from_slice_unchecked(&instruction.data) {
-                            Ok(compute_budget::ComputeBudgetInstruction::SetComputeUnitLimit(
-                                compute_unit_limit,
-                            )) => {
-                                transaction_compute_budget.compute_unit_limit = compute_unit_limit;
-                            }
-                            Ok(compute_budget::ComputeBudgetInstruction::SetComputeUnitPrice(
-                                micro_lamports,
-                            )) => {
-                                transaction_compute_budget.compute_unit_price_micro_lamports =
-                                    micro_lamports;
-                            }
-                            _ => {}
-                        }
-                    }
+            if force_priority_fee {
+                if transaction_compute_budget.compute_unit_limit == 0 {
+                    return Err("Swap transaction does not set a compute unit limit".into());
                 }
+
+                // Price the swap against the same accounts the Jupiter transaction writes to
+                let account_metas = transaction
+                    .message
+                    .static_account_keys()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, pubkey)| AccountMeta {
+                        pubkey: *pubkey,
+                        is_signer: false,
+                        is_writable: transaction.message.is_maybe_writable(i),
+                    })
+                    .collect::<Vec<_>>();
+                let compute_budget = compute_priority_fee(
+                    rpc_clients,
+                    &[Instruction::new_with_bytes(
+                        compute_budget::id(),
+                        &[],
+                        account_metas,
+                    )],
+                    transaction_compute_budget.compute_unit_limit,
+                    priority_fee,
+                )?;
+
+                override_compute_unit_price(
+                    &mut transaction.message,
+                    compute_budget.compute_unit_price_micro_lamports,
+                );
+
+                println!(
+                    "Swap priority fee re-priced from {} to {}",
+                    Sol(transaction_compute_budget.priority_fee_lamports()),
+                    Sol(compute_budget.priority_fee_lamports())
+                );
+                transaction_compute_budget = compute_budget;
             }
+
             if transaction_compute_budget.priority_fee_lamports() > priority_fee.max_lamports() {
                 return Err(format!(
                     "Swap too expensive. Priority fee of {} is greater than max fee of {}",
@@ -1196,7 +3346,7 @@ async fn process_jup_swap<T: Signers>(
         }
 
         let (recent_blockhash, last_valid_block_height) =
-            rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+            rpc_clients.get_latest_blockhash_with_commitment()?;
         transaction.message.set_recent_blockhash(recent_blockhash);
 
         let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
@@ -1206,6 +3356,10 @@ async fn process_jup_swap<T: Signers>(
             );
         }
 
+        if verbose {
+            println_transaction_cost(rpc_client, db, &transaction.message)?;
+        }
+
         assert_eq!(transaction.signatures[0], Signature::default());
         let signatures = signers.try_sign_message(&transaction.message.serialize())?;
         assert_eq!(signatures.len(), 1);
@@ -1222,6 +3376,7 @@ async fn process_jup_swap<T: Signers>(
                 last_update_balance: 0,
                 lots: vec![],
                 no_sync: None,
+                last_activity: None,
             })?;
         }
         db.record_swap(
@@ -1233,12 +3388,16 @@ async fn process_jup_swap<T: Signers>(
             to_token,
             to_token_price,
             lot_selection_method,
+            Some(swap_route(&quote)),
         )?;
 
-        if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-            .unwrap_or_default()
-        {
-            db.cancel_swap(signature)?;
+        let status =
+            send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+        record_sent_transaction(db, PriorityFeeCategory::Swap, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                db.cancel_swap(signature)?;
+            }
             return Err("Swap failed".into());
         }
     }
@@ -1318,6 +3477,7 @@ async fn process_tulip_deposit<T: Signers>(
             last_update_balance: 0,
             lots: vec![],
             no_sync: Some(true),
+            last_activity: None,
         })?;
     }
 
@@ -1477,6 +3637,7 @@ async fn process_sync_swaps(
     db: &mut Db,
     rpc_client: &RpcClient,
     notifier: &Notifier,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let block_height = rpc_client.get_epoch_info()?.block_height;
 
@@ -1485,17 +3646,23 @@ async fn process_sync_swaps(
         last_valid_block_height,
         address,
         from_token,
+        from_token_price,
         to_token,
+        to_token_price,
+        route,
         ..
     } in db.pending_swaps()
     {
         let swap = format!("swap ({address}: {from_token} -> {to_token})");
 
-        let status = rpc_client.get_signature_status_with_commitment_and_history(
+        let mut status = rpc_client.get_signature_status_with_commitment_and_history(
             &signature,
             rpc_client.commitment(),
             true,
         )?;
+        if status.is_none() && block_height > last_valid_block_height {
+            status = recheck_expired_transaction(&[rpc_client], &signature)?;
+        }
         match status {
             Some(result) => {
                 if result.is_ok() {
@@ -1644,19 +3811,49 @@ async fn process_sync_swaps(
                         from_token.symbol(),
                         from_token
                             .ui_amount(from_amount)
-                            .separated_string_with_fixed_place(2),
+                            .localized_string_with_fixed_place(2),
                         to_token.symbol(),
                         to_token
                             .ui_amount(to_amount)
-                            .separated_string_with_fixed_place(2),
+                            .localized_string_with_fixed_place(2),
                         to_token.symbol(),
                         (to_token.ui_amount(to_amount) / from_token.ui_amount(from_amount))
-                            .separated_string_with_fixed_place(2),
+                            .localized_string_with_fixed_place(2),
                         from_token.symbol(),
                     );
-                    db.confirm_swap(signature, when, from_amount, to_amount)?;
-                    notifier.send(&msg).await;
+                    let capitalized_fee = match db.get_fee_treatment() {
+                        FeeTreatment::Expense => Decimal::ZERO,
+                        FeeTreatment::Capitalize => {
+                            let sol_price = if from_token.is_sol() {
+                                from_token_price
+                            } else if to_token.is_sol() {
+                                to_token_price
+                            } else {
+                                MaybeToken::SOL()
+                                    .get_historical_price(rpc_client, when)
+                                    .await?
+                            };
+                            Decimal::from_f64(lamports_to_sol(fee)).unwrap() * sol_price
+                        }
+                    };
+                    db.confirm_swap(
+                        signature,
+                        when,
+                        from_amount,
+                        to_amount,
+                        capitalized_fee,
+                        fee,
+                    )?;
+                    notifier
+                        .send(NotificationKind::Swap, Severity::Info, &msg)
+                        .await;
                     println!("{msg}");
+                    if let Some(route) = route.filter(|_| verbose) {
+                        println!(
+                            "  via {}",
+                            format_swap_route(&route, &[from_token, to_token])
+                        );
+                    }
                 } else {
                     println!("Pending {swap} failed with {result:?}: {signature}");
                     db.cancel_swap(signature)?;
@@ -1720,7 +3917,7 @@ fn liquidity_token_ui_amount(
                                 "[{}{}]",
                                 liquidity_token.symbol(),
                                 (liquidity_ui_amount - acquisition_liquidity_ui_amount)
-                                    .separated_string_with_fixed_place(2)
+                                    .localized_string_with_fixed_place(2)
                             )
                         })
                         .unwrap_or_default(),
@@ -1735,20 +3932,20 @@ async fn maybe_println_lot(
     token: MaybeToken,
     lot: &Lot,
     current_price: Option<Decimal>,
+    valuation_date: Option<NaiveDate>, // Defaults to today
     liquidity_token_info: Option<&LiquidityTokenInfo>,
-    total_basis: &mut f64,
-    total_income: &mut f64,
-    total_cap_gain: &mut f64,
+    total_basis: &mut Decimal,
+    total_income: &mut Decimal,
+    total_cap_gain: &mut Decimal,
     long_term_cap_gain: &mut bool,
-    total_current_value: &mut f64,
+    total_current_value: &mut Decimal,
     notifier: Option<&Notifier>,
+    columns: &[LotColumn],
     verbose: bool,
     print: bool,
 ) {
-    let current_value = current_price.map(|current_price| {
-        f64::try_from(Decimal::from_f64(token.ui_amount(lot.amount)).unwrap() * current_price)
-            .unwrap()
-    });
+    let current_value =
+        current_price.map(|current_price| token.ui_amount_decimal(lot.amount) * current_price);
     let basis = lot.basis(token);
     let income = lot.income(token);
     let cap_gain = lot.cap_gain(token, current_price.unwrap_or_default());
@@ -1771,7 +3968,7 @@ async fn maybe_println_lot(
     *total_income += income;
     *total_cap_gain += cap_gain;
     *total_current_value += current_value.unwrap_or_default();
-    *long_term_cap_gain = is_long_term_cap_gain(lot.acquisition.when, None);
+    *long_term_cap_gain = is_long_term_cap_gain(lot.acquisition.when, valuation_date);
 
     let ui_amount = token.ui_amount(lot.amount);
     let (liquidity_ui_amount, liquidity_token_cap_gain) = liquidity_token_ui_amount(
@@ -1785,56 +3982,83 @@ async fn maybe_println_lot(
         .map(|current_value| {
             format!(
                 "value: {:>14}{}",
-                current_value.separated_string_with_fixed_place(2),
+                format_usd(current_value),
                 liquidity_ui_amount
             )
         })
         .unwrap_or_else(|| "value: ?".into());
 
-    let description = if verbose {
-        format!("| {}", lot.acquisition.kind,)
-    } else {
-        String::new()
-    };
+    // The acquisition kind is always shown when verbose
+    let kind = [LotColumn::Kind];
+    let columns = columns.iter().chain(
+        kind.iter()
+            .filter(|_| verbose && !columns.contains(&LotColumn::Kind)),
+    );
 
-    let msg = format!(
-        "{:>5}. {} | {:>17} at {:>6} | {} | income: {:>11} | {} gain: {:>14}{} {}",
-        lot.lot_number,
-        lot.acquisition.when,
-        token.format_ui_amount(ui_amount),
-        f64::try_from(lot.acquisition.price())
+    let mut msg = String::new();
+    let mut previous_column = None;
+    for column in columns {
+        msg += match (previous_column, column) {
+            (None, _) => "",
+            (Some(LotColumn::Lot), _) => " ",
+            (Some(LotColumn::Amount), LotColumn::Price) => " at ",
+            _ => " | ",
+        };
+        let price = f64::try_from(lot.acquisition.price())
             .unwrap()
-            .separated_string_with_fixed_place(2),
-        current_value,
-        income.separated_string_with_fixed_place(2),
-        if *long_term_cap_gain {
-            " long"
-        } else {
-            "short"
-        },
-        cap_gain.separated_string_with_fixed_place(2),
-        liquidity_token_cap_gain,
-        description,
-    );
+            .localized_string_with_fixed_place(2);
+        msg += &match column {
+            LotColumn::Lot => format!("{:>5}.", lot.lot_number),
+            LotColumn::Date => lot.acquisition.when.to_string(),
+            LotColumn::Amount => format!("{:>17}", token.format_ui_amount(ui_amount)),
+            LotColumn::Price if previous_column == Some(LotColumn::Amount) => {
+                format!("{price:>6}")
+            }
+            LotColumn::Price => format!("at {price:>6}"),
+            LotColumn::Basis => format!("basis: {:>14}", format_usd(basis)),
+            LotColumn::Value => current_value.clone(),
+            LotColumn::Income => format!("income: {:>11}", format_usd(income)),
+            LotColumn::Gain => format!(
+                "{} gain: {:>14}{}",
+                if *long_term_cap_gain {
+                    " long"
+                } else {
+                    "short"
+                },
+                format_usd(cap_gain),
+                liquidity_token_cap_gain,
+            ),
+            LotColumn::Days => format!(
+                "{:>5} days",
+                (valuation_date.unwrap_or_else(|| Local::now().date_naive())
+                    - lot.acquisition.when)
+                    .num_days()
+            ),
+            LotColumn::Kind => lot.acquisition.kind.to_string(),
+        };
+        previous_column = Some(*column);
+    }
 
     // if !token.fiat_fungible() {
 
     if let Some(notifier) = notifier {
-        notifier.send(&msg).await;
+        notifier
+            .send(NotificationKind::Disposal, Severity::Info, &msg)
+            .await;
     }
 
     if print {
-        println!("{msg}");
+        progress::println(&msg);
     }
     // }
 }
 
 fn format_disposed_lot(
     disposed_lot: &DisposedLot,
-    total_income: &mut f64,
-    total_cap_gain: &mut f64,
+    total_income: &mut Decimal,
+    total_cap_gain: &mut Decimal,
     long_term_cap_gain: &mut bool,
-    total_current_value: &mut f64,
+    total_current_value: &mut Decimal,
     verbose: bool,
 ) -> String {
     #![allow(clippy::to_string_in_format_args)]
@@ -1859,21 +4083,21 @@ fn format_disposed_lot(
     };
 
     format!(
-        "{:>5}. {} | {:<7} | {:<17} at ${:<6} | income: ${:<11} | sold {} at ${:6} | {} gain: ${:<14} {}",
+        "{:>5}. {} | {:<7} | {:<17} at {:<7} | income: {:<12} | sold {} at {:7} | {} gain: {:<15} {}",
         disposed_lot.lot.lot_number,
         disposed_lot.lot.acquisition.when,
         disposed_lot.token.to_string(),
         disposed_lot.token.format_amount(disposed_lot.lot.amount),
-        f64::try_from(disposed_lot.lot.acquisition.price()).unwrap().separated_string_with_fixed_place(2),
-        income.separated_string_with_fixed_place(2),
+        currency(f64::try_from(disposed_lot.lot.acquisition.price()).unwrap().localized_string_with_fixed_place(2)),
+        format_currency(income),
         disposed_lot.when,
-        f64::try_from(disposed_lot.price()).unwrap().separated_string_with_fixed_place(2),
+        currency(f64::try_from(disposed_lot.price()).unwrap().localized_string_with_fixed_place(2)),
         if *long_term_cap_gain {
             " long"
         } else {
             "short"
         },
-        cap_gain.separated_string_with_fixed_place(2),
+        format_currency(cap_gain),
         description,
     )
 }
@@ -1984,12 +4208,14 @@ async fn process_account_add(
             &lot,
             Some(current_price),
             None,
-            &mut 0.,
-            &mut 0.,
-            &mut 0.,
+            None,
+            &mut Decimal::ZERO,
+            &mut Decimal::ZERO,
+            &mut Decimal::ZERO,
             &mut false,
-            &mut 0.,
+            &mut Decimal::ZERO,
             None,
+            DEFAULT_LOT_COLUMNS,
             true,
             true,
         )
@@ -2006,6 +4232,7 @@ async fn process_account_add(
         last_update_balance: amount,
         lots,
         no_sync: Some(no_sync),
+        last_activity: None,
     };
     db.add_account(account)?;
 
@@ -2048,7 +4275,14 @@ async fn process_account_dispose(
         for disposed_lot in disposed_lots {
             println!(
                 "{}",
-                format_disposed_lot(&disposed_lot, &mut 0., &mut 0., &mut false, &mut 0., true)
+                format_disposed_lot(
+                    &disposed_lot,
+                    &mut Decimal::ZERO,
+                    &mut Decimal::ZERO,
+                    &mut false,
+                    &mut Decimal::ZERO,
+                    true
+                )
             );
         }
         println!();
@@ -2058,10 +4292,10 @@ async fn process_account_dispose(
 
 #[derive(Default, Debug, PartialEq)]
 struct RealizedGain {
-    income: f64,
-    short_term_cap_gain: f64,
-    long_term_cap_gain: f64,
-    basis: f64,
+    income: Decimal,
+    short_term_cap_gain: Decimal,
+    long_term_cap_gain: Decimal,
+    basis: Decimal,
 }
 
 #[derive(Default)]
@@ -2073,31 +4307,71 @@ struct AnnualRealizedGain {
 impl AnnualRealizedGain {
     const MONTH_TO_PAYMENT_PERIOD: [usize; 12] = [0, 0, 0, 1, 1, 2, 2, 2, 3, 3, 3, 3];
 
-    fn record_income(&mut self, month: usize, income: f64) {
+    fn record_income(&mut self, month: usize, income: Decimal) {
         self.by_quarter[month / 3].income += income;
         self.by_payment_period[Self::MONTH_TO_PAYMENT_PERIOD[month]].income += income;
     }
 
-    fn record_short_term_cap_gain(&mut self, month: usize, cap_gain: f64) {
+    fn record_short_term_cap_gain(&mut self, month: usize, cap_gain: Decimal) {
         self.by_quarter[month / 3].short_term_cap_gain += cap_gain;
         self.by_payment_period[Self::MONTH_TO_PAYMENT_PERIOD[month]].short_term_cap_gain +=
             cap_gain;
     }
 
-    fn record_long_term_cap_gain(&mut self, month: usize, cap_gain: f64) {
+    fn record_long_term_cap_gain(&mut self, month: usize, cap_gain: Decimal) {
         self.by_quarter[month / 3].long_term_cap_gain += cap_gain;
         self.by_payment_period[Self::MONTH_TO_PAYMENT_PERIOD[month]].long_term_cap_gain += cap_gain;
     }
 }
 
+impl RealizedGain {
+    // Income and cap gains are taxed separately, so a loss in one does not offset the other
+    fn estimated_tax(&self, tax_rate: &TaxRate) -> Decimal {
+        [
+            self.income * Decimal::from_f64(tax_rate.income).unwrap(),
+            self.short_term_cap_gain * Decimal::from_f64(tax_rate.short_term_gain).unwrap()
+                + self.long_term_cap_gain * Decimal::from_f64(tax_rate.long_term_gain).unwrap(),
+        ]
+        .into_iter()
+        .map(|x| x.max(Decimal::ZERO))
+        .sum::<Decimal>()
+    }
+}
+
+// Income by acquisition date and cap gains by disposal date, of every lot
+fn annual_realized_gains(db: &Db) -> BTreeMap<usize, AnnualRealizedGain> {
+    let mut annual_realized_gains = BTreeMap::<usize, AnnualRealizedGain>::default();
+    for (token, lot) in all_lots(db) {
+        annual_realized_gains
+            .entry(lot.acquisition.when.year() as usize)
+            .or_default()
+            .record_income(lot.acquisition.when.month0() as usize, lot.income(token));
+    }
+    for disposed_lot in db.disposed_lots() {
+        let cap_gain = disposed_lot
+            .lot
+            .cap_gain(disposed_lot.token, disposed_lot.price());
+        let month = disposed_lot.when.month0() as usize;
+        let annual_realized_gain = annual_realized_gains
+            .entry(disposed_lot.when.year() as usize)
+            .or_default();
+        if is_long_term_cap_gain(disposed_lot.lot.acquisition.when, Some(disposed_lot.when)) {
+            annual_realized_gain.record_long_term_cap_gain(month, cap_gain);
+        } else {
+            annual_realized_gain.record_short_term_cap_gain(month, cap_gain);
+        }
+    }
+    annual_realized_gains
+}
+
 async fn process_account_cost_basis(
     db: &Db,
     when: NaiveDate,
+    output_format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut held_tokens =
         BTreeMap::<MaybeToken, Vec<(/*amount: */ u64, /*price: */ Decimal)>>::default();
 
-    println!("Average Cost Basis on {when}");
     for disposed_lot in db.disposed_lots() {
         if disposed_lot.lot.acquisition.when > when || disposed_lot.when < when {
             continue;
@@ -2128,6 +4402,7 @@ async fn process_account_cost_basis(
             .append(&mut lots);
     }
 
+    let mut cost_basis = vec![];
     for (token, lots) in held_tokens {
         if lots.is_empty() || token.fiat_fungible() {
             continue;
@@ -2138,16 +4413,35 @@ async fn process_account_cost_basis(
 
         for (amount, price) in lots {
             total_amount += amount;
-            total_price += Decimal::from_f64(token.ui_amount(amount)).unwrap() * price;
+            total_price += token.ui_amount_decimal(amount) * price;
         }
+        cost_basis.push((token, total_amount, total_price));
+    }
+
+    if output_format == OutputFormat::Json {
+        return println_json(
+            &cost_basis
+                .into_iter()
+                .map(|(token, total_amount, total_price)| CostBasisOutput {
+                    token,
+                    amount: token.ui_amount(total_amount),
+                    basis: total_price.try_into().unwrap(),
+                    average_price: (total_price / token.ui_amount_decimal(total_amount))
+                        .try_into()
+                        .unwrap(),
+                })
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    println!("Average Cost Basis on {when}");
+    for (token, total_amount, total_price) in cost_basis {
         println!(
-            "  {:>7}: {:<20} at ${} ; ${:.2} per {}",
+            "  {:>7}: {:<20} at {} ; {} per {}",
             token.to_string(),
             token.format_amount(total_amount),
-            TryInto::<f64>::try_into(total_price)
-                .unwrap()
-                .separated_string_with_fixed_place(2),
-            total_price / Decimal::from_f64(token.ui_amount(total_amount)).unwrap(),
+            format_currency(total_price),
+            format_currency(total_price / token.ui_amount_decimal(total_amount)),
             token.name()
         );
     }
@@ -2164,11 +4458,7 @@ fn print_current_holdings(
         .map(
             |(held_token, (current_token_price, total_held_amount, unrealized_gain))| {
                 let total_value = current_token_price.map(|current_token_price| {
-                    f64::try_from(
-                        Decimal::from_f64(held_token.ui_amount(*total_held_amount)).unwrap()
-                            * current_token_price,
-                    )
-                    .unwrap()
+                    held_token.ui_amount_decimal(*total_held_amount) * current_token_price
                 });
 
                 (
@@ -2183,8 +4473,7 @@ fn print_current_holdings(
         .collect::<Vec<_>>();
 
     // Order current holdings by `total_value`
-    held_tokens
-        .sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    held_tokens.sort_unstable_by(|a, b| b.1.cmp(&a.1));
 
     for (held_token, total_value, current_token_price, total_held_amount, unrealized_gain) in
         held_tokens
@@ -2195,14 +4484,13 @@ fn print_current_holdings(
 
         let estimated_tax = tax_rate
             .and_then(|tax_rate| {
-                let tax = unrealized_gain.short_term_cap_gain * tax_rate.short_term_gain
-                    + unrealized_gain.long_term_cap_gain * tax_rate.long_term_gain;
+                let tax = unrealized_gain.short_term_cap_gain
+                    * Decimal::from_f64(tax_rate.short_term_gain).unwrap()
+                    + unrealized_gain.long_term_cap_gain
+                        * Decimal::from_f64(tax_rate.long_term_gain).unwrap();
 
-                if tax > 0. {
-                    Some(format!(
-                        "; ${} estimated tax",
-                        tax.separated_string_with_fixed_place(2)
-                    ))
+                if tax > Decimal::ZERO {
+                    Some(format!("; {} estimated tax", format_currency(tax)))
                 } else {
                     None
                 }
@@ -2217,23 +4505,24 @@ fn print_current_holdings(
             );
         } else {
             println!(
-                "  {:<7}       {:<22} [{}; ${:>4} per {:>4}{}]",
+                "  {:<7}       {:<22} [{}; {:>5} per {:>4}{}]",
                 held_token.to_string(),
                 held_token.format_amount(*total_held_amount),
                 total_value
                     .map(|tv| {
                         format!(
-                            "${:14} ({:>8}%)",
-                            tv.separated_string_with_fixed_place(2),
-                            ((tv - unrealized_gain.basis) / unrealized_gain.basis * 100.)
-                                .separated_string_with_fixed_place(2)
+                            "{:15} ({:>8}%)",
+                            format_currency(tv),
+                            format_percent(tv - unrealized_gain.basis, unrealized_gain.basis)
                         )
                     })
                     .unwrap_or_else(|| "?".into()),
                 current_token_price
-                    .map(|current_token_price| f64::try_from(current_token_price)
-                        .unwrap()
-                        .separated_string_with_fixed_place(3))
+                    .map(|current_token_price| currency(
+                        f64::try_from(current_token_price)
+                            .unwrap()
+                            .localized_string_with_fixed_place(3)
+                    ))
                     .unwrap_or_else(|| "?".into()),
                 held_token,
                 estimated_tax,
@@ -2243,15 +4532,166 @@ fn print_current_holdings(
     println!();
 }
 
+// Returns the income tax reserve at the withholding rate on all income ever acquired, and the
+// portion of it that has been satisfied by estimated tax payments
+fn tax_reserve(db: &Db) -> Option<(Decimal, Decimal)> {
+    let income_withholding_rate = db.get_income_withholding_rate()?;
+
+    let income = db
+        .get_accounts()
+        .into_iter()
+        .flat_map(|account| {
+            let token = account.token;
+            account.lots.into_iter().map(move |lot| lot.income(token))
+        })
+        .chain(
+            db.open_orders(None, None)
+                .into_iter()
+                .flat_map(|open_order| {
+                    let token = open_order.token;
+                    open_order
+                        .lots
+                        .into_iter()
+                        .map(move |lot| lot.income(token))
+                }),
+        )
+        .chain(
+            db.disposed_lots()
+                .into_iter()
+                .map(|disposed_lot| disposed_lot.lot.income(disposed_lot.token)),
+        )
+        .sum::<Decimal>();
+
+    let paid = db
+        .tax_reserve_payments()
+        .into_iter()
+        .map(|payment| Decimal::from_f64(payment.amount).unwrap())
+        .sum::<Decimal>();
+
+    Some((
+        income * Decimal::from_f64(income_withholding_rate).unwrap(),
+        paid,
+    ))
+}
+
+fn println_tax_reserve(db: &Db) {
+    if let Some((reserve, paid)) = tax_reserve(db) {
+        println!(
+            "  Tax reserve:         {} (withheld: {}, paid: {})",
+            format_currency(reserve - paid),
+            format_currency(reserve),
+            format_currency(paid),
+        );
+    }
+}
+
+fn lot_output(token: MaybeToken, lot: &Lot) -> LotOutput {
+    LotOutput {
+        lot_number: lot.lot_number,
+        acquired: lot.acquisition.when,
+        acquisition: lot.acquisition.kind.to_string(),
+        amount: token.ui_amount(lot.amount),
+        price: lot.acquisition.price().try_into().unwrap(),
+        basis: lot.basis(token).try_into().unwrap(),
+        income: lot.income(token).try_into().unwrap(),
+    }
+}
+
+// The machine-readable counterpart of `process_account_list`, which always includes every lot
+async fn process_account_list_json(
+    db: &Db,
+    rpc_client: &RpcClient,
+    account_filter: Option<Pubkey>,
+    as_of: Option<NaiveDate>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let held_on_as_of = |lot: &Lot| match as_of {
+        Some(as_of) => lot.acquisition.when <= as_of,
+        None => true,
+    };
+
+    let mut prices = BTreeMap::<MaybeToken, Option<f64>>::default();
+    let open_orders = db.open_orders(None, None);
+    let mut accounts = vec![];
+    for account in db.get_accounts() {
+        if account_filter.is_some_and(|account_filter| account.address != account_filter) {
+            continue;
+        }
+
+        let token = account.token;
+        if let std::collections::btree_map::Entry::Vacant(e) = prices.entry(token) {
+            let price = match as_of {
+                Some(as_of) => token.get_historical_price(rpc_client, as_of).await,
+                None => token.get_current_price(rpc_client).await,
+            };
+            e.insert(price.ok().and_then(|price| price.to_f64()));
+        }
+
+        let lots = account
+            .lots
+            .iter()
+            .filter(|lot| held_on_as_of(lot))
+            .collect::<Vec<_>>();
+        let balance = if as_of.is_some() {
+            lots.iter().map(|lot| lot.amount).sum()
+        } else {
+            account.last_update_balance
+        };
+
+        accounts.push(AccountOutput {
+            address: account.address,
+            token,
+            description: account.description.clone(),
+            balance: token.ui_amount(balance),
+            value: prices[&token].map(|price| price * token.ui_amount(balance)),
+            lots: lots.into_iter().map(|lot| lot_output(token, lot)).collect(),
+            open_orders: open_orders
+                .iter()
+                .filter(|oo| oo.deposit_address == account.address && oo.token == token)
+                .map(|open_order| OpenOrderOutput {
+                    pair: open_order.pair.clone(),
+                    side: open_order.side,
+                    order_id: open_order.order_id.clone(),
+                    price: open_order.price,
+                    amount: open_order.ui_amount.unwrap_or_else(|| {
+                        token.ui_amount(open_order.lots.iter().map(|lot| lot.amount).sum())
+                    }),
+                    lots: open_order
+                        .lots
+                        .iter()
+                        .filter(|lot| held_on_as_of(lot))
+                        .map(|lot| lot_output(token, lot))
+                        .collect(),
+                })
+                .collect(),
+        });
+    }
+
+    println_json(&AccountListOutput { as_of, accounts })
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn process_account_list(
     db: &Db,
     rpc_client: &RpcClient,
     account_filter: Option<Pubkey>,
     show_all_lots: bool,
     summary_only: bool,
+    as_of: Option<NaiveDate>,
+    lot_columns: &[LotColumn],
     notifier: &Notifier,
     verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // When `as_of` is provided, holdings are reconstructed from the lots acquired on or before that
+    // date and valued at the historical price of that date
+    let held_on_as_of = |lot: &Lot| match as_of {
+        Some(as_of) => lot.acquisition.when <= as_of,
+        None => true,
+    };
+    if let Some(as_of) = as_of {
+        println!("Holdings as of {as_of}, valued at historical prices");
+        println!();
+    }
+
     let mut annual_realized_gains = BTreeMap::<usize, AnnualRealizedGain>::default();
     let mut held_tokens = BTreeMap::<
         MaybeToken,
@@ -2282,12 +4722,12 @@ async fn process_account_list(
     if accounts.is_empty() {
         println!("No accounts");
     } else {
-        let mut total_income = 0.;
-        let mut total_unrealized_short_term_gain = 0.;
-        let mut total_unrealized_long_term_gain = 0.;
-        let mut total_current_basis = 0.;
-        let mut total_current_fiat_value = 0.;
-        let mut total_current_value = 0.;
+        let mut total_income = Decimal::ZERO;
+        let mut total_unrealized_short_term_gain = Decimal::ZERO;
+        let mut total_unrealized_long_term_gain = Decimal::ZERO;
+        let mut total_current_basis = Decimal::ZERO;
+        let mut total_current_fiat_value = Decimal::ZERO;
+        let mut total_current_value = Decimal::ZERO;
 
         let open_orders = db.open_orders(None, None);
 
@@ -2300,42 +4740,54 @@ async fn process_account_list(
 
             if let std::collections::btree_map::Entry::Vacant(e) = held_tokens.entry(account.token)
             {
-                e.insert((
-                    account.token.get_current_price(rpc_client).await.ok(),
-                    0,
-                    RealizedGain::default(),
-                ));
+                let price = match as_of {
+                    Some(as_of) => account.token.get_historical_price(rpc_client, as_of).await,
+                    None => account.token.get_current_price(rpc_client).await,
+                };
+                e.insert((price.ok(), 0, RealizedGain::default()));
             }
 
+            let balance = if as_of.is_some() {
+                account
+                    .lots
+                    .iter()
+                    .filter(|lot| held_on_as_of(lot))
+                    .map(|lot| lot.amount)
+                    .sum()
+            } else {
+                account.last_update_balance
+            };
+
             let held_token = held_tokens.get_mut(&account.token).unwrap();
             let current_token_price = held_token.0;
-            held_token.1 += account.last_update_balance;
+            held_token.1 += balance;
 
-            let ui_amount = account.token.ui_amount(account.last_update_balance);
+            let ui_amount = account.token.ui_amount(balance);
 
-            let liquidity_token_info =
-                if let Some(liquidity_token) = account.token.liquidity_token() {
-                    if let Ok(current_liquidity_token_rate) = account
-                        .token
-                        .get_current_liquidity_token_rate(rpc_client)
-                        .await
-                    {
-                        Some(LiquidityTokenInfo {
-                            liquidity_token,
-                            current_liquidity_token_rate,
-                            current_apr: None,
-                            /*
-                            current_apr: tulip::get_current_lending_apr(rpc_client, &account.token)
-                                .await
-                                .ok(),
-                            */
-                        })
-                    } else {
-                        None
-                    }
+            let liquidity_token_info = if as_of.is_some() {
+                None // Historical liquidity token rates are not available
+            } else if let Some(liquidity_token) = account.token.liquidity_token() {
+                if let Ok(current_liquidity_token_rate) = account
+                    .token
+                    .get_current_liquidity_token_rate(rpc_client)
+                    .await
+                {
+                    Some(LiquidityTokenInfo {
+                        liquidity_token,
+                        current_liquidity_token_rate,
+                        current_apr: None,
+                        /*
+                        current_apr: tulip::get_current_lending_apr(rpc_client, &account.token)
+                            .await
+                            .ok(),
+                        */
+                    })
                 } else {
                     None
-                };
+                }
+            } else {
+                None
+            };
 
             let (liquidity_ui_amount, _) =
                 liquidity_token_ui_amount(None, ui_amount, liquidity_token_info.as_ref(), true);
@@ -2344,15 +4796,19 @@ async fn process_account_list(
                 account.address,
                 account.token,
                 account.token.symbol(),
-                ui_amount.separated_string_with_fixed_place(9),
+                ui_amount.localized_string_with_fixed_place(9),
                 liquidity_ui_amount,
                 account.description
             );
             println!("{msg}");
-            if ui_amount > 0.01 {
-                notifier.send(&msg).await;
+            if as_of.is_none() {
+                if ui_amount > 0.01 {
+                    notifier
+                        .send(NotificationKind::Balance, Severity::Info, &msg)
+                        .await;
+                }
+                account.assert_lot_balance();
             }
-            account.assert_lot_balance();
 
             if summary_only {
                 continue;
@@ -2364,27 +4820,32 @@ async fn process_account_list(
                 .collect::<Vec<_>>();
 
             if !account.lots.is_empty() || !open_orders.is_empty() {
-                let mut lots = account.lots.iter().collect::<Vec<_>>();
+                let mut lots = account
+                    .lots
+                    .iter()
+                    .filter(|lot| held_on_as_of(lot))
+                    .collect::<Vec<_>>();
                 lots.sort_by_key(|lot| lot.acquisition.when);
 
-                let mut account_basis = 0.;
-                let mut account_income = 0.;
-                let mut account_current_value = 0.;
-                let mut account_unrealized_short_term_gain = 0.;
-                let mut account_unrealized_long_term_gain = 0.;
+                let mut account_basis = Decimal::ZERO;
+                let mut account_income = Decimal::ZERO;
+                let mut account_current_value = Decimal::ZERO;
+                let mut account_unrealized_short_term_gain = Decimal::ZERO;
+                let mut account_unrealized_long_term_gain = Decimal::ZERO;
 
                 if !show_all_lots && lots.len() > 5 {
                     println!("  ...");
                 }
 
                 for (i, lot) in lots.iter().enumerate() {
-                    let mut account_unrealized_gain = 0.;
+                    let mut account_unrealized_gain = Decimal::ZERO;
                     let mut long_term_cap_gain = false;
 
                     maybe_println_lot(
                         account.token,
                         lot,
                         current_token_price,
+                        as_of,
                         liquidity_token_info.as_ref(),
                         &mut account_basis,
                         &mut account_income,
@@ -2392,6 +4853,7 @@ async fn process_account_list(
                         &mut long_term_cap_gain,
                         &mut account_current_value,
                         None,
+                        lot_columns,
                         verbose,
                         if show_all_lots {
                             true
@@ -2417,7 +4879,11 @@ async fn process_account_list(
                 }
 
                 for open_order in open_orders {
-                    let mut lots = open_order.lots.iter().collect::<Vec<_>>();
+                    let mut lots = open_order
+                        .lots
+                        .iter()
+                        .filter(|lot| held_on_as_of(lot))
+                        .collect::<Vec<_>>();
                     lots.sort_by_key(|lot| lot.acquisition.when);
                     let ui_amount = open_order.ui_amount.unwrap_or_else(|| {
                         account
@@ -2425,21 +4891,22 @@ async fn process_account_list(
                             .ui_amount(lots.iter().map(|lot| lot.amount).sum::<u64>())
                     });
                     println!(
-                        " [Open {}: {} {} at ${} | id {} created {}]",
+                        " [Open {}: {} {} at {} | id {} created {}]",
                         open_order.pair,
                         format_order_side(open_order.side),
                         account.token.format_ui_amount(ui_amount),
-                        open_order.price,
+                        currency(open_order.price),
                         open_order.order_id,
                         HumanTime::from(open_order.creation_time),
                     );
                     for lot in lots {
-                        let mut account_unrealized_gain = 0.;
+                        let mut account_unrealized_gain = Decimal::ZERO;
                         let mut long_term_cap_gain = false;
                         maybe_println_lot(
                             account.token,
                             lot,
                             current_token_price,
+                            as_of,
                             liquidity_token_info.as_ref(),
                             &mut account_basis,
                             &mut account_income,
@@ -2447,6 +4914,7 @@ async fn process_account_list(
                             &mut long_term_cap_gain,
                             &mut account_current_value,
                             None,
+                            lot_columns,
                             verbose,
                             true,
                         )
@@ -2469,34 +4937,31 @@ async fn process_account_list(
                 }
 
                 println!(
-                    "    Value: ${}{}",
-                    account_current_value.separated_string_with_fixed_place(2),
+                    "    Value: {}{}",
+                    format_currency(account_current_value),
                     if account.token.fiat_fungible() {
                         "".into()
                     } else {
                         format!(
                             " ({}%), {}{}",
-                            ((account_current_value - account_basis) / account_basis * 100.)
-                                .separated_string_with_fixed_place(2),
-                            if account_income > 0. {
-                                format!(
-                                    "income: ${}, ",
-                                    account_income.separated_string_with_fixed_place(2)
-                                )
+                            format_percent(account_current_value - account_basis, account_basis),
+                            if account_income > Decimal::ZERO {
+                                format!("income: {}, ", format_currency(account_income))
                             } else {
                                 "".into()
                             },
                             if unified_tax_rate {
                                 format!(
-                                    "unrealized cap gain: ${}",
-                                    (account_unrealized_short_term_gain
-                                        + account_unrealized_long_term_gain)
-                                        .separated_string_with_fixed_place(2)
+                                    "unrealized cap gain: {}",
+                                    format_currency(
+                                        account_unrealized_short_term_gain
+                                            + account_unrealized_long_term_gain
+                                    )
                                 )
                             } else {
-                                format!("unrealized short-term cap gain: ${}, unrealized long-term cap gain: ${}",
-                                    account_unrealized_short_term_gain.separated_string_with_fixed_place(2),
-                                    account_unrealized_long_term_gain.separated_string_with_fixed_place(2)
+                                format!("unrealized short-term cap gain: {}, unrealized long-term cap gain: {}",
+                                    format_currency(account_unrealized_short_term_gain),
+                                    format_currency(account_unrealized_long_term_gain)
                                 )
                             }
                         )
@@ -2531,17 +4996,94 @@ async fn process_account_list(
 
         let mut disposed_lots = db.disposed_lots();
         disposed_lots.sort_by_key(|lot| lot.when);
+
+        if let Some(as_of) = as_of {
+            // Lots disposed of after `as_of` were still held on that date
+            let (disposed_before_as_of, disposed_since_as_of): (Vec<_>, Vec<_>) = disposed_lots
+                .into_iter()
+                .filter(|disposed_lot| disposed_lot.lot.acquisition.when <= as_of)
+                .partition(|disposed_lot| disposed_lot.when <= as_of);
+            disposed_lots = disposed_before_as_of;
+
+            if !disposed_since_as_of.is_empty() {
+                println!(
+                    "Held on {as_of}, disposed since ({} lots):",
+                    disposed_since_as_of.len()
+                );
+
+                for disposed_lot in disposed_since_as_of {
+                    let token = disposed_lot.token;
+                    if let std::collections::btree_map::Entry::Vacant(e) = held_tokens.entry(token)
+                    {
+                        e.insert((
+                            token.get_historical_price(rpc_client, as_of).await.ok(),
+                            0,
+                            RealizedGain::default(),
+                        ));
+                    }
+                    let held_token = held_tokens.get_mut(&token).unwrap();
+                    held_token.1 += disposed_lot.lot.amount;
+
+                    let mut basis = Decimal::ZERO;
+                    let mut income = Decimal::ZERO;
+                    let mut unrealized_gain = Decimal::ZERO;
+                    let mut long_term_cap_gain = false;
+                    let mut value = Decimal::ZERO;
+                    maybe_println_lot(
+                        token,
+                        &disposed_lot.lot,
+                        held_token.0,
+                        Some(as_of),
+                        None,
+                        &mut basis,
+                        &mut income,
+                        &mut unrealized_gain,
+                        &mut long_term_cap_gain,
+                        &mut value,
+                        None,
+                        lot_columns,
+                        verbose,
+                        true,
+                    )
+                    .await;
+
+                    annual_realized_gains
+                        .entry(disposed_lot.lot.acquisition.when.year() as usize)
+                        .or_default()
+                        .record_income(disposed_lot.lot.acquisition.when.month0() as usize, income);
+
+                    if long_term_cap_gain {
+                        total_unrealized_long_term_gain += unrealized_gain;
+                        held_token.2.long_term_cap_gain += unrealized_gain;
+                    } else {
+                        total_unrealized_short_term_gain += unrealized_gain;
+                        held_token.2.short_term_cap_gain += unrealized_gain;
+                    }
+                    held_token.2.basis += basis;
+
+                    total_income += income;
+                    total_current_value += value;
+                    if token.fiat_fungible() {
+                        total_current_fiat_value += value;
+                    } else {
+                        total_current_basis += basis;
+                    }
+                }
+                println!();
+            }
+        }
+
         if !disposed_lots.is_empty() {
             println!("Disposed ({} lots):", disposed_lots.len());
 
-            let mut disposed_income = 0.;
-            let mut disposed_short_term_cap_gain = 0.;
-            let mut disposed_long_term_cap_gain = 0.;
-            let mut disposed_value = 0.;
+            let mut disposed_income = Decimal::ZERO;
+            let mut disposed_short_term_cap_gain = Decimal::ZERO;
+            let mut disposed_long_term_cap_gain = Decimal::ZERO;
+            let mut disposed_value = Decimal::ZERO;
 
             for (i, disposed_lot) in disposed_lots.iter().enumerate() {
                 let mut long_term_cap_gain = false;
-                let mut disposed_cap_gain = 0.;
+                let mut disposed_cap_gain = Decimal::ZERO;
                 let msg = format_disposed_lot(
                     disposed_lot,
                     &mut disposed_income,
@@ -2589,27 +5131,23 @@ async fn process_account_list(
                 }
             }
             println!(
-                "    Disposed value: ${} ({}{})",
-                disposed_value.separated_string_with_fixed_place(2),
-                if disposed_income > 0. {
-                    format!(
-                        "income: ${}, ",
-                        disposed_income.separated_string_with_fixed_place(2)
-                    )
+                "    Disposed value: {} ({}{})",
+                format_currency(disposed_value),
+                if disposed_income > Decimal::ZERO {
+                    format!("income: {}, ", format_currency(disposed_income))
                 } else {
                     "".into()
                 },
                 if unified_tax_rate {
                     format!(
-                        "cap gain: ${}",
-                        (disposed_short_term_cap_gain + disposed_long_term_cap_gain)
-                            .separated_string_with_fixed_place(2)
+                        "cap gain: {}",
+                        format_currency(disposed_short_term_cap_gain + disposed_long_term_cap_gain)
                     )
                 } else {
                     format!(
-                        "short-term cap gain: ${}, long-term cap gain: ${}",
-                        disposed_short_term_cap_gain.separated_string_with_fixed_place(2),
-                        disposed_long_term_cap_gain.separated_string_with_fixed_place(2)
+                        "short-term cap gain: {}, long-term cap gain: {}",
+                        format_currency(disposed_short_term_cap_gain),
+                        format_currency(disposed_long_term_cap_gain)
                     )
                 }
             );
@@ -2643,17 +5181,9 @@ async fn process_account_list(
             for (q, realized_gain) in realized_gains.iter().enumerate() {
                 if *realized_gain != RealizedGain::default() {
                     let tax = if let Some(tax_rate) = tax_rate {
-                        let tax = [
-                            realized_gain.income * tax_rate.income,
-                            realized_gain.short_term_cap_gain * tax_rate.short_term_gain
-                                + realized_gain.long_term_cap_gain * tax_rate.long_term_gain,
-                        ]
-                        .into_iter()
-                        .map(|x| x.max(0.))
-                        .sum::<f64>();
-
-                        if tax > 0. {
-                            format!("${}", tax.separated_string_with_fixed_place(2))
+                        let tax = realized_gain.estimated_tax(tax_rate);
+                        if tax > Decimal::ZERO {
+                            format_currency(tax)
                         } else {
                             String::new()
                         }
@@ -2662,27 +5192,24 @@ async fn process_account_list(
                     };
 
                     println!(
-                        "  {} {}{} | ${:14} | {}| {}",
+                        "  {} {}{} | {:15} | {}| {}",
                         year,
                         symbol,
                         q + 1,
-                        realized_gain.income.separated_string_with_fixed_place(2),
+                        format_currency(realized_gain.income),
                         if unified_tax_rate {
                             format!(
-                                "${:14}",
-                                (realized_gain.short_term_cap_gain
-                                    + realized_gain.long_term_cap_gain)
-                                    .separated_string_with_fixed_place(2)
+                                "{:15}",
+                                format_currency(
+                                    realized_gain.short_term_cap_gain
+                                        + realized_gain.long_term_cap_gain
+                                )
                             )
                         } else {
                             format!(
-                                "${:14} | ${:14}",
-                                realized_gain
-                                    .short_term_cap_gain
-                                    .separated_string_with_fixed_place(2),
-                                realized_gain
-                                    .long_term_cap_gain
-                                    .separated_string_with_fixed_place(2)
+                                "{:15} | {:15}",
+                                format_currency(realized_gain.short_term_cap_gain),
+                                format_currency(realized_gain.long_term_cap_gain)
                             )
                         },
                         tax
@@ -2696,36 +5223,58 @@ async fn process_account_list(
 
         println!("Summary");
         println!(
-            "  Current Value:       ${} ({}%)",
-            total_current_value.separated_string_with_fixed_place(2),
-            (((total_current_value - total_current_fiat_value) - total_current_basis)
-                / total_current_basis
-                * 100.)
-                .separated_string_with_fixed_place(2),
+            "  Current Value:       {} ({}%)",
+            format_currency(total_current_value),
+            format_percent(
+                (total_current_value - total_current_fiat_value) - total_current_basis,
+                total_current_basis
+            ),
         );
-        if total_income > 0. {
+        if total_income > Decimal::ZERO {
             println!(
-                "  Income:              ${} (realized)",
-                total_income.separated_string_with_fixed_place(2)
+                "  Income:              {} (realized)",
+                format_currency(total_income)
             );
         }
         if unified_tax_rate {
             println!(
-                "  Cap gain:            ${} (unrealized)",
-                (total_unrealized_short_term_gain + total_unrealized_long_term_gain)
-                    .separated_string_with_fixed_place(2)
+                "  Cap gain:            {} (unrealized)",
+                format_currency(total_unrealized_short_term_gain + total_unrealized_long_term_gain)
             );
         } else {
             println!(
-                "  Short-term cap gain: ${} (unrealized)",
-                total_unrealized_short_term_gain.separated_string_with_fixed_place(2)
+                "  Short-term cap gain: {} (unrealized)",
+                format_currency(total_unrealized_short_term_gain)
+            );
+            println!(
+                "  Long-term cap gain:  {} (unrealized)",
+                format_currency(total_unrealized_long_term_gain)
+            );
+        }
+
+        let margin_tracking = db.margin_tracking();
+        if !margin_tracking.is_empty() {
+            let liabilities = margin_tracking
+                .iter()
+                .map(MarginTracking::liabilities)
+                .sum::<Decimal>();
+            let interest_expense = margin_tracking
+                .iter()
+                .map(|margin_tracking| margin_tracking.interest_expense)
+                .sum::<Decimal>();
+            println!(
+                "  Borrowed:            {} (margin interest paid: {})",
+                format_currency(liabilities),
+                format_currency(interest_expense)
             );
             println!(
-                "  Long-term cap gain:  ${} (unrealized)",
-                total_unrealized_long_term_gain.separated_string_with_fixed_place(2)
+                "  Net worth:           {}",
+                format_currency(total_current_value - liabilities)
             );
         }
 
+        println_tax_reserve(db);
+
         let pending_deposits = db.pending_deposits(None).len();
         let pending_withdrawals = db.pending_withdrawals(None).len();
         let pending_transfers = db.pending_transfers().len();
@@ -2751,1636 +5300,5714 @@ async fn process_account_list(
     Ok(())
 }
 
-async fn process_account_xls(
+async fn process_account_list_archived(
     db: &Db,
-    outfile: &str,
-    filter_by_year: Option<i32>,
+    rpc_client: &RpcClient,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use simple_excel_writer::*;
+    let archived_accounts = db.get_archived_accounts();
+    if archived_accounts.is_empty() {
+        println!("No archived accounts");
+        return Ok(());
+    }
 
-    let mut workbook = Workbook::create(outfile);
+    for account in archived_accounts {
+        println!(
+            "{} ({}): {}{} - {}",
+            account.address,
+            account.token,
+            account.token.symbol(),
+            account
+                .token
+                .ui_amount(account.last_update_balance)
+                .localized_string_with_fixed_place(9),
+            account.description
+        );
 
-    let mut sheet = workbook.create_sheet(&match filter_by_year {
-        Some(year) => format!("Disposed in {year}"),
-        None => "Disposed".into(),
-    });
-    sheet.add_column(Column { width: 12. });
-    sheet.add_column(Column { width: 15. });
-    sheet.add_column(Column { width: 12. });
-    sheet.add_column(Column { width: 12. });
-    sheet.add_column(Column { width: 10. });
-    sheet.add_column(Column { width: 40. });
-    sheet.add_column(Column { width: 12. });
-    sheet.add_column(Column { width: 10. });
-    sheet.add_column(Column { width: 10. });
-    sheet.add_column(Column { width: 10. });
-    sheet.add_column(Column { width: 10. });
-    sheet.add_column(Column { width: 40. });
+        let current_price = account.token.get_current_price(rpc_client).await.ok();
+        let mut total_basis = Decimal::ZERO;
+        let mut total_income = Decimal::ZERO;
+        let mut total_cap_gain = Decimal::ZERO;
+        let mut long_term_cap_gain = false;
+        let mut total_current_value = Decimal::ZERO;
 
-    let mut disposed_lots = db.disposed_lots();
-    disposed_lots.sort_by_key(|lot| lot.when);
+        let mut lots = account.lots.iter().collect::<Vec<_>>();
+        lots.sort_by_key(|lot| lot.acquisition.when);
+        for lot in lots {
+            maybe_println_lot(
+                account.token,
+                lot,
+                current_price,
+                None,
+                None,
+                &mut total_basis,
+                &mut total_income,
+                &mut total_cap_gain,
+                &mut long_term_cap_gain,
+                &mut total_current_value,
+                None,
+                DEFAULT_LOT_COLUMNS,
+                verbose,
+                true,
+            )
+            .await;
+        }
+        println!();
+    }
+    Ok(())
+}
 
-    if let Some(year) = filter_by_year {
-        // Exclude disposed lots that were neither acquired nor disposed of in the filter year
-        disposed_lots.retain(|disposed_lot| {
-            (disposed_lot.lot.acquisition.when.year() == year
-                && disposed_lot.lot.income(disposed_lot.token) > 0.)
-                || disposed_lot.when.year() == year
-        })
+// A USD total as it was figured before USD values were kept as `Decimal`, summing an `f64`
+// rounded from each lot, and exactly
+#[derive(Default)]
+struct RoundingDrift {
+    float: f64,
+    exact: Decimal,
+    max_lot_drift: Decimal,
+}
+
+impl RoundingDrift {
+    fn record(&mut self, ui_amount: f64, price: Decimal, exact: Decimal) {
+        let float = f64::try_from(price * Decimal::from_f64(ui_amount).unwrap()).unwrap();
+        self.float += float;
+        self.exact += exact;
+        self.max_lot_drift = self
+            .max_lot_drift
+            .max((Decimal::from_f64(float).unwrap() - exact).abs());
     }
 
-    workbook.write_sheet(&mut sheet, |sheet_writer| {
-        sheet_writer.append_row(row![
-            "Token",
-            "Amount",
-            "Income (USD)",
-            "Acq. Date",
-            "Acq. Price (USD)",
-            "Acquisition Description",
-            "Cap Gain (USD)",
-            "Cap Gain Type",
-            "Sale Date",
-            "Sale Price (USD)",
-            "Fee (USD)",
-            "Sale Description"
-        ])?;
+    fn drift(&self) -> Decimal {
+        Decimal::from_f64(self.float).unwrap_or_default() - self.exact
+    }
+}
 
-        for disposed_lot in disposed_lots {
-            let long_term_cap_gain =
-                is_long_term_cap_gain(disposed_lot.lot.acquisition.when, Some(disposed_lot.when));
+// Quantifies the difference between the float arithmetic previously used for basis, income and
+// realized gains and exact arithmetic, per token
+fn process_db_history(db: &Db) {
+    let journal = db.journal();
+    if journal.is_empty() {
+        println!("No journal entries");
+    }
+    for entry in journal {
+        println!(
+            "{:>3}. {} {}{}",
+            entry.id,
+            entry.when,
+            entry.command,
+            if entry.undone { " (undone)" } else { "" }
+        );
+        for change in &entry.changes {
+            println!("       {change}");
+        }
+    }
+}
 
-            let mut income = disposed_lot.lot.income(disposed_lot.token);
-            if let Some(year) = filter_by_year {
-                if disposed_lot.lot.acquisition.when.year() != year {
-                    income = 0. // Exclude income from other years
-                }
-            }
+// Reverts the `count` most recent journal entries that can be undone. Those that placed or
+// cancelled exchange orders, or whose transactions are still pending or have been confirmed, are
+// skipped, the undo would not take back what happened on the exchange or on chain
+fn process_db_undo(
+    db: &mut Db,
+    rpc_client: &RpcClient,
+    count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pending_signatures = db.pending_signatures();
+    let mut undone = 0;
 
-            sheet_writer.append_row(row![
-                disposed_lot.token.to_string(),
-                disposed_lot.token.ui_amount(disposed_lot.lot.amount),
+    for entry in db.journal().into_iter().rev().filter(|entry| !entry.undone) {
+        if undone == count {
+            break;
+        }
+
+        if entry.changes.iter().any(JournalChange::is_exchange_order) {
+            println!(
+                "Skipped {}. {}: placed or cancelled exchange orders",
+                entry.id, entry.command
+            );
+            continue;
+        }
+        if entry
+            .signatures
+            .iter()
+            .any(|signature| pending_signatures.contains(signature))
+        {
+            println!(
+                "Skipped {}. {}: transaction still pending, sync first",
+                entry.id, entry.command
+            );
+            continue;
+        }
+        if !entry.signatures.is_empty()
+            && rpc_client
+                .get_signature_statuses_with_history(&entry.signatures)?
+                .value
+                .into_iter()
+                .flatten()
+                .any(|status| status.err.is_none())
+        {
+            println!(
+                "Skipped {}. {}: transaction confirmed",
+                entry.id, entry.command
+            );
+            continue;
+        }
+
+        db.undo_journal_entry(entry.id)?;
+        println!("Undid {}. {}", entry.id, entry.command);
+        for change in &entry.changes {
+            println!("  {change}");
+        }
+        undone += 1;
+    }
+
+    if undone == 0 {
+        println!("Nothing to undo");
+    }
+    Ok(())
+}
+
+fn process_db_audit_rounding(db: &Db) {
+    let mut drifts = BTreeMap::<(MaybeToken, &str), RoundingDrift>::new();
+
+    for (token, lot) in all_lots(db) {
+        let ui_amount = token.ui_amount(lot.amount);
+        let income = lot.income(token);
+        if income > Decimal::ZERO {
+            drifts.entry((token, "income")).or_default().record(
+                ui_amount,
+                lot.acquisition.price(),
                 income,
-                disposed_lot.lot.acquisition.when.to_string(),
-                disposed_lot.lot.acquisition.price().to_string(),
-                disposed_lot.lot.acquisition.kind.to_string(),
-                disposed_lot
-                    .lot
-                    .cap_gain(disposed_lot.token, disposed_lot.price()),
-                if long_term_cap_gain { "Long" } else { "Short" },
-                disposed_lot.when.to_string(),
-                disposed_lot.price().to_string(),
-                disposed_lot
-                    .kind
-                    .fee()
-                    .map(|(amount, currency)| {
-                        assert_eq!(currency, "USD");
-                        *amount
-                    })
-                    .unwrap_or_default(),
-                disposed_lot.kind.to_string()
-            ])?;
+            );
         }
-        Ok(())
-    })?;
+        drifts.entry((token, "basis")).or_default().record(
+            ui_amount,
+            lot.acquisition.price(),
+            lot.basis(token),
+        );
+    }
+    for disposed_lot in db.disposed_lots() {
+        let token = disposed_lot.token;
+        drifts.entry((token, "realized gain")).or_default().record(
+            token.ui_amount(disposed_lot.lot.amount),
+            disposed_lot.price() - disposed_lot.lot.acquisition.price(),
+            disposed_lot.lot.cap_gain(token, disposed_lot.price()),
+        );
+    }
 
-    let mut current_holdings_rows = vec![];
-    let mut current_holdings_by_year_rows = vec![];
+    if drifts.is_empty() {
+        println!("No lots");
+        return;
+    }
 
-    #[derive(Clone)]
-    enum R {
-        Number(f64),
-        Text(String),
+    let mut total_drift = Decimal::ZERO;
+    for ((token, kind), drift) in drifts {
+        println!(
+            "{token} {kind}: ${} exact, ${} with float rounding | drift: ${}, largest per lot: ${}",
+            drift.exact.round_dp(6),
+            Decimal::from_f64(drift.float)
+                .unwrap_or_default()
+                .round_dp(6),
+            drift.drift().round_dp(6),
+            drift.max_lot_drift.round_dp(6),
+        );
+        total_drift += drift.drift().abs();
     }
+    println!("Total absolute drift: ${}", total_drift.round_dp(6));
+}
 
-    impl ToCellValue for R {
-        fn to_cell_value(&self) -> CellValue {
-            match self {
-                R::Number(x) => x.to_cell_value(),
-                R::Text(x) => x.to_cell_value(),
+// Rechecks the signatures of pending and recently cancelled records, with history, and fixes
+// records that were cancelled although their transaction landed or were never resolved
+async fn process_db_repair_pending(
+    db: &mut Db,
+    rpc_client: &RpcClient,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut restored = 0;
+    let mut checked_signatures = HashSet::new();
+    for cancelled_record in db.cancelled_records() {
+        let signature = cancelled_record.signature();
+        // All the records of a batched transaction are restored together
+        if !checked_signatures.insert(signature) {
+            continue;
+        }
+        let status = rpc_client.get_signature_status_with_commitment_and_history(
+            &signature,
+            rpc_client.commitment(),
+            true,
+        )?;
+        if !matches!(status, Some(Ok(()))) {
+            continue;
+        }
+
+        let record = match cancelled_record {
+            CancelledRecord::Deposit(_) => "deposit",
+            CancelledRecord::Transfer(_) => "transfer",
+            CancelledRecord::Swap(_) => "swap",
+        };
+        match db.restore_cancelled_record(signature) {
+            Ok(()) => {
+                println!("Cancelled {record} landed, restored to pending: {signature}");
+                restored += 1;
+            }
+            Err(err) => {
+                eprintln!("Cancelled {record} landed but could not be restored: {signature}: {err}")
             }
         }
     }
 
-    for account in db.get_accounts() {
-        for lot in account.lots.iter() {
-            let row = (
-                lot.acquisition.when,
-                vec![
-                    R::Text(account.token.to_string()),
-                    R::Number(account.token.ui_amount(lot.amount)),
-                    R::Number(lot.income(account.token)),
-                    R::Text(lot.acquisition.when.to_string()),
-                    R::Text(lot.acquisition.price().to_string()),
-                    R::Text(lot.acquisition.kind.to_string()),
-                    R::Text(account.description.clone()),
-                    R::Text(account.address.to_string()),
-                ],
-            );
-            current_holdings_rows.push(row.clone());
-            if let Some(year) = filter_by_year {
-                if lot.acquisition.when.year() == year {
-                    current_holdings_by_year_rows.push(row);
-                    continue;
-                }
+    process_account_sync_pending_transfers(db, rpc_client).await?;
+    process_sync_swaps(db, rpc_client, notifier, false).await?;
+
+    // Only the exchange can credit a deposit, so landed deposits are left for `sync`
+    let block_height = rpc_client.get_epoch_info()?.block_height;
+    for PendingDeposit {
+        exchange,
+        transfer:
+            PendingTransfer {
+                signature,
+                last_valid_block_height,
+                from_token,
+                ..
+            },
+        ..
+    } in db.pending_deposits(None)
+    {
+        let status = rpc_client.get_signature_status_with_commitment_and_history(
+            &signature,
+            rpc_client.commitment(),
+            true,
+        )?;
+        match status {
+            Some(Ok(())) => {
+                println!("Pending {exchange} deposit landed, run `sync` to credit it: {signature}")
+            }
+            Some(Err(err)) => {
+                println!("Pending {exchange} deposit failed with {err:?}: {signature}");
+                db.cancel_deposit(signature, from_token)?;
             }
+            None if block_height > last_valid_block_height => {
+                println!("Pending {exchange} deposit cancelled: {signature}");
+                db.cancel_deposit(signature, from_token)?;
+            }
+            None => println!(
+                "{exchange} deposit pending for at most {} blocks: {signature}",
+                last_valid_block_height.saturating_sub(block_height),
+            ),
         }
     }
 
-    for open_order in db.open_orders(None, Some(OrderSide::Sell)) {
-        for lot in open_order.lots.iter() {
-            let row = (
+    if restored == 0 {
+        println!("No cancelled records to restore");
+    }
+    Ok(())
+}
+
+fn process_db_dedupe(db: &mut Db, confirm: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let duplicate_lots = db.duplicate_lots();
+    let duplicate_pending_signatures = db.duplicate_pending_signatures();
+
+    if duplicate_lots.is_empty() && duplicate_pending_signatures.is_empty() {
+        println!("No duplicates found");
+        return Ok(());
+    }
+
+    for (account, lots) in &duplicate_lots {
+        println!(
+            "{} ({}) - {}: {}",
+            account.address, account.token, account.description, lots[0].acquisition.kind
+        );
+        for lot in lots {
+            println!(
+                "{:>5}. {} | {:<17} at {:<7} | {}",
+                lot.lot_number,
                 lot.acquisition.when,
-                vec![
-                    R::Text(open_order.token.to_string()),
-                    R::Number(open_order.token.ui_amount(lot.amount)),
-                    R::Number(lot.income(open_order.token)),
-                    R::Text(lot.acquisition.when.to_string()),
-                    R::Text(lot.acquisition.price().to_string()),
-                    R::Text(lot.acquisition.kind.to_string()),
-                    R::Text(format!(
-                        "Open Order: {:?} {}",
-                        open_order.exchange, open_order.pair
-                    )),
-                    R::Text(open_order.deposit_address.to_string()),
-                ],
+                account.token.format_amount(lot.amount),
+                currency(
+                    f64::try_from(lot.acquisition.price())
+                        .unwrap()
+                        .localized_string_with_fixed_place(2)
+                ),
+                lot.acquisition.kind,
             );
-            current_holdings_rows.push(row.clone());
-            if let Some(year) = filter_by_year {
-                if lot.acquisition.when.year() == year {
-                    current_holdings_by_year_rows.push(row);
-                    continue;
-                }
-            }
         }
+        println!();
     }
-    current_holdings_rows.sort_by_key(|row| row.0);
-    current_holdings_by_year_rows.sort_by_key(|row| row.0);
 
-    let mut write_holdings = |name: String, rows: Vec<(_, Vec<R>)>| {
-        let mut sheet = workbook.create_sheet(&name);
+    for signature in &duplicate_pending_signatures {
+        println!("Pending operation recorded more than once: {signature}");
+    }
+    if !duplicate_pending_signatures.is_empty() {
+        println!("Pending operations must be reviewed manually");
+    }
 
-        sheet.add_column(Column { width: 12. });
-        sheet.add_column(Column { width: 15. });
-        sheet.add_column(Column { width: 12. });
-        sheet.add_column(Column { width: 12. });
-        sheet.add_column(Column { width: 10. });
-        sheet.add_column(Column { width: 40. });
-        sheet.add_column(Column { width: 40. });
-        sheet.add_column(Column { width: 50. });
+    if duplicate_lots.is_empty() {
+        return Ok(());
+    }
+    if !confirm {
+        println!("Add --confirm to delete the duplicate lots");
+        return Ok(());
+    }
 
-        workbook.write_sheet(&mut sheet, |sheet_writer| {
-            sheet_writer.append_row(row![
-                "Token",
-                "Amount",
-                "Income (USD)",
-                "Acq. Date",
-                "Acq. Price (USD)",
-                "Acquisition Description",
-                "Account Description",
-                "Account Address"
-            ])?;
+    for (_account, lots) in duplicate_lots {
+        let original_lot_number = lots[0].lot_number;
+        for lot in lots.into_iter().skip(1) {
+            db.remove_duplicate_lot(lot.lot_number, original_lot_number)?;
+            println!(
+                "Deleted lot {} as a duplicate of lot {original_lot_number}",
+                lot.lot_number
+            );
+        }
+    }
+    Ok(())
+}
 
-            for (_, row) in rows {
-                sheet_writer.append_row(Row::from_iter(row.into_iter()))?;
-            }
+fn process_account_stale(
+    db: &Db,
+    rpc_client: &RpcClient,
+    days: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let current_epoch = rpc_client.get_epoch_info()?.epoch;
+    let today = today();
 
-            Ok(())
+    let mut stale_accounts = db
+        .get_accounts()
+        .into_iter()
+        .filter_map(|account| {
+            // Accounts created before activity was tracked fall back to their most recent lot
+            let last_activity = account
+                .last_activity
+                .or_else(|| account.lots.iter().map(|lot| lot.acquisition.when).max());
+            let idle_days = last_activity.map(|last_activity| (today - last_activity).num_days());
+            (idle_days.unwrap_or(i64::MAX) >= days).then_some((last_activity, account))
         })
-    };
-    if let Some(year) = filter_by_year {
-        write_holdings(
-            format!("Holdings acquired in {year}"),
-            current_holdings_by_year_rows,
-        )?;
-    }
-    write_holdings("All Holdings".to_string(), current_holdings_rows)?;
+        .collect::<Vec<_>>();
+    stale_accounts.sort_by_key(|(last_activity, account)| (*last_activity, account.address));
 
-    workbook.close()?;
-    println!("Wrote {outfile}");
+    if stale_accounts.is_empty() {
+        println!("No accounts have been idle for {days} days");
+        return Ok(());
+    }
 
+    for (last_activity, account) in stale_accounts {
+        println!(
+            "{} ({}): {}{} - {}",
+            account.address,
+            account.token,
+            account.token.symbol(),
+            account
+                .token
+                .ui_amount(account.last_update_balance)
+                .localized_string_with_fixed_place(9),
+            account.description
+        );
+        println!(
+            "  Last activity: {}",
+            match last_activity {
+                Some(last_activity) => format!(
+                    "{last_activity} ({} days ago)",
+                    (today - last_activity).num_days()
+                ),
+                None => "unknown".into(),
+            }
+        );
+        if account.no_sync.unwrap_or_default() {
+            println!("  Sync: disabled");
+        } else {
+            println!(
+                "  Sync: last epoch {} ({} epochs behind)",
+                account.last_update_epoch,
+                current_epoch.saturating_sub(account.last_update_epoch)
+            );
+        }
+    }
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn process_account_merge<T: Signers>(
-    db: &mut Db,
-    rpc_clients: &RpcClients,
-    from_address: Pubkey,
-    into_address: Pubkey,
-    authority_address: Pubkey,
-    signers: T,
-    priority_fee: PriorityFee,
-    existing_signature: Option<Signature>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_client = rpc_clients.default();
-    let token = MaybeToken::SOL(); // TODO: Support merging tokens one day
+const WASH_SALE_WINDOW_DAYS: i64 = 30;
+
+// A loss disposal that was replaced by an acquisition of the same token within 30 days before or
+// after the disposal. The loss on the replaced amount is disallowed and added to the basis of the
+// replacement lot
+struct WashSale {
+    disposed_lot: DisposedLot,
+    replacement_token: MaybeToken,
+    replacement_lot: Lot,
+    amount: u64, // replaced amount of the disposed lot, in lamports/tokens
+    disallowed_loss: Decimal,
+    adjusted_basis: Decimal, // of the replaced amount of the replacement lot
+}
 
-    if let Some(existing_signature) = existing_signature {
-        db.record_transfer(
-            existing_signature,
-            0, /*last_valid_block_height*/
-            None,
-            from_address,
-            token,
-            into_address,
-            token,
-            LotSelectionMethod::default(),
-            None,
-        )?;
+// wSOL is substantially identical to SOL
+fn wash_sale_token(token: MaybeToken) -> MaybeToken {
+    if token == Token::wSOL.into() {
+        MaybeToken::SOL()
     } else {
-        let (recent_blockhash, last_valid_block_height) =
-            rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
-
-        let from_account = rpc_client
-            .get_account_with_commitment(&from_address, rpc_client.commitment())?
-            .value
-            .ok_or_else(|| format!("From account, {from_address}, does not exist"))?;
-
-        let from_tracked_account = db
-            .get_account(from_address, token)
-            .ok_or_else(|| format!("Account, {from_address}, is not tracked"))?;
+        token
+    }
+}
 
-        let into_account = rpc_client
-            .get_account_with_commitment(&into_address, rpc_client.commitment())?
-            .value
-            .ok_or_else(|| format!("From account, {into_address}, does not exist"))?;
+// Only purchases replace a sold lot. Income and transfers are not purchases
+fn is_wash_sale_replacement(kind: &LotAcquistionKind) -> bool {
+    match kind {
+        LotAcquistionKind::Exchange { .. }
+        | LotAcquistionKind::Fiat
+        | LotAcquistionKind::Swap { .. } => true,
+        LotAcquistionKind::EpochReward { .. }
+        | LotAcquistionKind::ExchangeReward { .. }
+        | LotAcquistionKind::MevReward { .. }
+        | LotAcquistionKind::Vesting { .. }
+        | LotAcquistionKind::StreamPayment { .. }
+        | LotAcquistionKind::NotAvailable
+        | LotAcquistionKind::Transaction { .. } => false,
+    }
+}
 
-        let authority_account = if from_address == authority_address {
-            from_account.clone()
-        } else {
-            rpc_client
-                .get_account_with_commitment(&authority_address, rpc_client.commitment())?
-                .value
-                .ok_or_else(|| format!("Authority account, {authority_address}, does not exist"))?
-        };
+// Every lot ever acquired, wherever it is now
+fn all_lots(db: &Db) -> Vec<(MaybeToken, Lot)> {
+    let mut lots = vec![];
+    for account in db
+        .get_accounts()
+        .into_iter()
+        .chain(db.get_archived_accounts())
+    {
+        lots.extend(account.lots.into_iter().map(|lot| (account.token, lot)));
+    }
+    for open_order in db.open_orders(None, None) {
+        lots.extend(
+            open_order
+                .lots
+                .into_iter()
+                .map(|lot| (open_order.token, lot)),
+        );
+    }
+    for pending_withdrawal in db.pending_withdrawals(None) {
+        lots.extend(
+            pending_withdrawal
+                .lots
+                .into_iter()
+                .map(|lot| (pending_withdrawal.token, lot)),
+        );
+    }
+    for pending_transfer in db.pending_transfers().into_iter().chain(
+        db.pending_deposits(None)
+            .into_iter()
+            .map(|pending_deposit| pending_deposit.transfer),
+    ) {
+        lots.extend(
+            pending_transfer
+                .lots
+                .into_iter()
+                .map(|lot| (pending_transfer.from_token, lot)),
+        );
+    }
+    for disposed_lot in db.disposed_lots() {
+        lots.push((disposed_lot.token, disposed_lot.lot));
+    }
+    lots
+}
 
-        let amount = from_tracked_account.last_update_balance;
+// Matches loss disposals, oldest first, against the purchases of the same token within the wash
+// sale window. Each purchased amount replaces at most one disposed amount
+fn wash_sales(db: &Db) -> Vec<WashSale> {
+    let mut replacements = all_lots(db)
+        .into_iter()
+        .filter(|(_, lot)| is_wash_sale_replacement(&lot.acquisition.kind))
+        .map(|(token, lot)| (token, lot.amount, lot))
+        .collect::<Vec<_>>();
+    replacements.sort_by_key(|(_, _, lot)| (lot.acquisition.when, lot.lot_number));
 
-        let mut instructions = if from_account.owner == solana_sdk::stake::program::id()
-            && into_account.owner == solana_sdk::stake::program::id()
-        {
-            solana_sdk::stake::instruction::merge(&into_address, &from_address, &authority_address)
-        } else if from_account.owner == solana_sdk::stake::program::id()
-            && into_account.owner == system_program::id()
-        {
-            vec![solana_sdk::stake::instruction::withdraw(
-                &from_address,
-                &authority_address,
-                &into_address,
-                amount,
-                None,
-            )]
-        } else {
-            return Err(format!(
-                "Unsupported merge from {} account to {} account",
-                from_account.owner, into_account.owner
-            )
-            .into());
-        };
-        apply_priority_fee(rpc_clients, &mut instructions, 10_000, priority_fee)?;
+    let mut disposed_lots = db.disposed_lots();
+    disposed_lots.sort_by_key(|disposed_lot| (disposed_lot.when, disposed_lot.lot.lot_number));
 
-        println!("Merging {from_address} into {into_address}");
-        if from_address != authority_address {
-            println!("Authority address: {authority_address}");
+    let mut wash_sales = vec![];
+    for disposed_lot in disposed_lots {
+        let token = disposed_lot.token;
+        let loss = -disposed_lot.lot.cap_gain(token, disposed_lot.price());
+        if loss <= Decimal::ZERO {
+            continue;
         }
 
-        let mut message = Message::new(&instructions, Some(&authority_address));
-        message.recent_blockhash = recent_blockhash;
-        if rpc_client.get_fee_for_message(&message)? > authority_account.lamports {
-            return Err("Insufficient funds for transaction fee".into());
-        }
+        let mut unreplaced_amount = disposed_lot.lot.amount;
+        for (replacement_token, remaining_amount, replacement_lot) in replacements.iter_mut() {
+            if unreplaced_amount == 0 {
+                break;
+            }
+            // Lots split off the disposed lot share its acquisition and are not replacements
+            if *remaining_amount == 0
+                || wash_sale_token(*replacement_token) != wash_sale_token(token)
+                || replacement_lot.acquisition == disposed_lot.lot.acquisition
+                || (replacement_lot.acquisition.when - disposed_lot.when)
+                    .num_days()
+                    .abs()
+                    > WASH_SALE_WINDOW_DAYS
+            {
+                continue;
+            }
 
-        let mut transaction = Transaction::new_unsigned(message);
-        let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
-        if simulation_result.err.is_some() {
-            return Err(format!("Simulation failure: {simulation_result:?}").into());
+            let amount = unreplaced_amount.min(*remaining_amount);
+            unreplaced_amount -= amount;
+            *remaining_amount -= amount;
+
+            let disallowed_loss =
+                loss * Decimal::from(amount) / Decimal::from(disposed_lot.lot.amount);
+            let replacement_basis = replacement_lot.basis(*replacement_token)
+                * Decimal::from(amount)
+                / Decimal::from(replacement_lot.amount);
+            wash_sales.push(WashSale {
+                disposed_lot: disposed_lot.clone(),
+                replacement_token: *replacement_token,
+                replacement_lot: replacement_lot.clone(),
+                amount,
+                disallowed_loss,
+                adjusted_basis: replacement_basis + disallowed_loss,
+            });
         }
+    }
+    wash_sales
+}
 
-        transaction.try_sign(&signers, recent_blockhash)?;
-        let signature = transaction.signatures[0];
-        println!("Transaction signature: {signature}");
+fn process_account_wash_sales(db: &Db, filter_by_year: Option<i32>) {
+    let wash_sales = wash_sales(db)
+        .into_iter()
+        .filter(|wash_sale| {
+            filter_by_year.is_none_or(|year| wash_sale.disposed_lot.when.year() == year)
+        })
+        .collect::<Vec<_>>();
 
-        db.record_transfer(
-            signature,
-            last_valid_block_height,
-            Some(amount),
-            from_address,
-            token,
-            into_address,
+    if wash_sales.is_empty() {
+        println!("No wash sales");
+        return;
+    }
+
+    let mut total_disallowed_loss = Decimal::ZERO;
+    for wash_sale in wash_sales {
+        let token = wash_sale.disposed_lot.token;
+        println!(
+            "{} lot {} disposed {}: {} replaced by {} lot {} acquired {} | \
+             disallowed loss: {}, adjusted basis: {}",
             token,
-            LotSelectionMethod::default(),
-            None,
-        )?;
+            wash_sale.disposed_lot.lot.lot_number,
+            wash_sale.disposed_lot.when,
+            token.format_amount(wash_sale.amount),
+            wash_sale.replacement_token,
+            wash_sale.replacement_lot.lot_number,
+            wash_sale.replacement_lot.acquisition.when,
+            format_currency(wash_sale.disallowed_loss),
+            format_currency(wash_sale.adjusted_basis),
+        );
+        total_disallowed_loss += wash_sale.disallowed_loss;
+    }
+    println!(
+        "Total disallowed loss: {}",
+        format_currency(total_disallowed_loss)
+    );
+}
 
-        if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-            .unwrap_or_default()
+// Print the epoch rewards of the stake account at `address` received in `year` as CSV. Transfers
+// and disposals split a reward lot into lots with new lot numbers but the same acquisition, so the
+// amount of a reward is the total of the held and disposed lots with its acquisition
+fn process_account_rewards_csv(
+    db: &Db,
+    address: Pubkey,
+    year: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let token = MaybeToken::SOL();
+    let account = db
+        .get_account(address, token)
+        .or_else(|| {
+            db.get_archived_accounts()
+                .into_iter()
+                .find(|account| account.address == address && account.token == token)
+        })
+        .ok_or_else(|| format!("{address} does not exist"))?;
+
+    let mut rewards = Vec::<(Epoch, Lot)>::new();
+    for lot in account.lots {
+        match lot.acquisition.kind {
+            LotAcquistionKind::EpochReward { epoch, .. } if lot.acquisition.when.year() == year => {
+                if !rewards
+                    .iter()
+                    .any(|(_, reward)| reward.acquisition == lot.acquisition)
+                {
+                    rewards.push((epoch, Lot { amount: 0, ..lot }));
+                }
+            }
+            _ => {}
+        }
+    }
+    let lots = all_lots(db)
+        .into_iter()
+        .chain(
+            db.disposed_lots()
+                .into_iter()
+                .map(|disposed_lot| (disposed_lot.token, disposed_lot.lot)),
+        )
+        .filter(|(lot_token, _)| *lot_token == token);
+    for (_, lot) in lots {
+        if let Some((_, reward)) = rewards
+            .iter_mut()
+            .find(|(_, reward)| reward.acquisition == lot.acquisition)
         {
-            db.cancel_transfer(signature)?;
-            return Err("Merge failed".into());
+            reward.amount += lot.amount;
         }
-        let when = get_signature_date(rpc_client, signature).await?;
-        db.confirm_transfer(signature, when)?;
-        db.remove_account(from_address, token)?;
+    }
+    rewards.sort_by_key(|(epoch, _)| *epoch);
+
+    println!("Epoch,Date,Amount (SOL),Price (USD),Value (USD)");
+    for (epoch, lot) in rewards {
+        let price = lot.acquisition.price();
+        println!(
+            "{},{},{},{},{}",
+            epoch,
+            lot.acquisition.when,
+            token.ui_amount_decimal(lot.amount),
+            price,
+            lot.basis(token).round_dp(2),
+        );
     }
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn process_account_sweep<T: Signers>(
-    db: &mut Db,
-    rpc_clients: &RpcClients,
-    from_address: Pubkey,
-    token: MaybeToken,
-    retain_amount: u64,
-    exact_amount: Option<u64>,
-    no_sweep_ok: bool,
-    from_authority_address: Pubkey,
-    signers: T,
-    to_address: Option<Pubkey>,
-    notifier: &Notifier,
-    priority_fee: PriorityFee,
-    existing_signature: Option<Signature>,
+// Summarize the staking income of `year` across all SOL accounts, by month and by validator. A
+// reward is attributed to the validator that its account is currently delegated to, or to the
+// account itself for vote accounts. The effective APY of a validator is its income relative to
+// the balance its accounts held before that income, annualized over the part of the year passed
+fn process_account_rewards_summary(
+    db: &Db,
+    rpc_client: &RpcClient,
+    year: i32,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_client = rpc_clients.default();
-
-    let (recent_blockhash, last_valid_block_height) =
-        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
-    let fee_calculator = get_deprecated_fee_calculator(rpc_client)?;
+    let token = MaybeToken::SOL();
+    let is_reward = |lot: &Lot| {
+        matches!(
+            lot.acquisition.kind,
+            LotAcquistionKind::EpochReward { .. } | LotAcquistionKind::MevReward { .. }
+        ) && lot.acquisition.when.year() == year
+    };
 
-    let from_account = rpc_client
-        .get_account_with_commitment(&from_address, rpc_client.commitment())?
-        .value
-        .ok_or_else(|| format!("Account, {from_address}, does not exist"))?;
+    let accounts = db
+        .get_accounts()
+        .into_iter()
+        .filter(|account| account.token == token)
+        .collect::<Vec<_>>();
+    let addresses = accounts
+        .iter()
+        .map(|account| account.address)
+        .collect::<Vec<_>>();
+    let voters = rpc_client_utils::get_stake_voters(rpc_client, &addresses)?;
+
+    // Income amount and value by month, and by validator along with the current balance of its
+    // accounts. Rewards that have since been disposed of have no validator
+    let mut by_month = BTreeMap::<u32, (u64, Decimal)>::new();
+    let mut by_validator = BTreeMap::<Option<Pubkey>, (u64, Decimal, u64)>::new();
+    for (account, voter) in accounts.into_iter().zip(voters) {
+        let validator = by_validator
+            .entry(Some(voter.unwrap_or(account.address)))
+            .or_default();
+        validator.2 += account.last_update_balance;
+        for lot in account.lots.iter().filter(|lot| is_reward(lot)) {
+            let month = by_month.entry(lot.acquisition.when.month()).or_default();
+            month.0 += lot.amount;
+            month.1 += lot.basis(token);
+            validator.0 += lot.amount;
+            validator.1 += lot.basis(token);
+        }
+    }
+    for disposed_lot in db.disposed_lots() {
+        if disposed_lot.token == token && is_reward(&disposed_lot.lot) {
+            let lot = &disposed_lot.lot;
+            let month = by_month.entry(lot.acquisition.when.month()).or_default();
+            month.0 += lot.amount;
+            month.1 += lot.basis(token);
+            let validator = by_validator.entry(None).or_default();
+            validator.0 += lot.amount;
+            validator.1 += lot.basis(token);
+        }
+    }
+    by_validator.retain(|_, (income, ..)| *income > 0);
 
-    let from_tracked_account = db
-        .get_account(from_address, token)
-        .ok_or_else(|| format!("Account, {from_address}, is not tracked"))?;
+    if by_month.is_empty() {
+        println!("No rewards in {year}");
+        return Ok(());
+    }
 
-    let authority_account = if from_address == from_authority_address {
-        from_account.clone()
-    } else {
-        rpc_client
-            .get_account_with_commitment(&from_authority_address, rpc_client.commitment())?
-            .value
-            .ok_or_else(|| format!("Authority account, {from_authority_address}, does not exist"))?
-    };
+    let (total_income, total_value) = by_month
+        .values()
+        .fold((0, Decimal::ZERO), |(amount, value), month| {
+            (amount + month.0, value + month.1)
+        });
 
-    let mut num_transaction_signatures = 1; // from_address_authority
+    println!("Rewards by month:");
+    for (month, (income, value)) in &by_month {
+        println!(
+            "  {year}-{month:02}: {} ({})",
+            token.format_amount(*income),
+            format_currency(*value)
+        );
+    }
+    println!(
+        "  Total: {} ({})",
+        token.format_amount(total_income),
+        format_currency(total_value)
+    );
 
-    let (to_address, via_transitory_stake) = if let Some(to_address) = to_address {
-        let _ = db
-            .get_account(to_address, token)
-            .ok_or_else(|| format!("Account {to_address} ({token}) does not exist"))?;
-        (to_address, None)
+    let today = Local::now().date_naive();
+    let days_in_year = NaiveDate::from_ymd_opt(year, 12, 31).unwrap().ordinal() as f64;
+    let days_passed = if year == today.year() {
+        today.ordinal() as f64
     } else {
-        if !token.is_sol() {
-            return Err("--to <ADDRESS> must be provided for token sweeps".into());
-        }
-
-        if existing_signature.is_some() {
-            return Err("--signature only supported for token sweeps".into());
-        }
-
-        let transitory_stake_account = Keypair::new();
+        days_in_year
+    };
 
-        let sweep_stake_account = db
-            .get_sweep_stake_account()
-            .ok_or("Sweep stake account not configured")?;
-        let sweep_stake_authority_keypair = read_keypair_file(&sweep_stake_account.stake_authority)
-            .map_err(|err| {
+    println!();
+    println!("Rewards by validator:");
+    for (validator, (income, value, balance)) in by_validator {
+        let apy = match validator {
+            Some(_) if balance > income => {
+                let rate = income as f64 / (balance - income) as f64;
                 format!(
-                    "Failed to read {}: {}",
-                    sweep_stake_account.stake_authority.display(),
-                    err
+                    ", effective APY {:.2}%",
+                    ((1. + rate).powf(days_in_year / days_passed) - 1.) * 100.
                 )
-            })?;
+            }
+            _ => String::new(),
+        };
+        println!(
+            "  {}: {} ({}){}",
+            validator.map_or_else(|| "Disposed of".to_string(), |v| v.to_string()),
+            token.format_amount(income),
+            format_currency(value),
+            apy,
+        );
+    }
+    Ok(())
+}
 
-        num_transaction_signatures += 1; // transitory_stake_account
-        if from_authority_address != sweep_stake_authority_keypair.pubkey() {
-            num_transaction_signatures += 1;
-        }
+fn process_fees_report(db: &Db, year: Option<i32>) -> Result<(), Box<dyn std::error::Error>> {
+    let payments = db
+        .priority_fee_payments()
+        .into_iter()
+        .filter(|payment| year.map_or(true, |year| payment.when.year() == year))
+        .collect::<Vec<_>>();
+    if payments.is_empty() {
+        println!("No priority fees paid");
+        return Ok(());
+    }
 
-        (
-            transitory_stake_account.pubkey(),
-            Some((
-                transitory_stake_account,
-                sweep_stake_authority_keypair,
-                sweep_stake_account.address,
-            )),
-        )
-    };
+    let mut by_month = BTreeMap::<(i32, u32), BTreeMap<PriorityFeeCategory, u64>>::new();
+    let mut by_category = BTreeMap::<PriorityFeeCategory, (u64, usize)>::new();
+    for payment in &payments {
+        *by_month
+            .entry((payment.when.year(), payment.when.month()))
+            .or_default()
+            .entry(payment.category)
+            .or_default() += payment.lamports;
+        let category = by_category.entry(payment.category).or_default();
+        category.0 += payment.lamports;
+        category.1 += 1;
+    }
 
-    if authority_account.lamports
-        < num_transaction_signatures * fee_calculator.lamports_per_signature
+    println!("Priority fees by month:");
+    for ((year, month), categories) in &by_month {
+        println!(
+            "  {year}-{month:02}: {} ({})",
+            Sol(categories.values().sum()),
+            categories
+                .iter()
+                .map(|(category, lamports)| format!(
+                    "{}: {}",
+                    <&str>::from(category),
+                    Sol(*lamports)
+                ))
+                .join(", ")
+        );
+    }
+
+    println!();
+    println!("Priority fees by category:");
+    for (category, (lamports, transactions)) in &by_category {
+        println!(
+            "  {}: {} over {transactions} transactions, {} on average",
+            <&str>::from(category),
+            Sol(*lamports),
+            Sol(*lamports / *transactions as u64),
+        );
+    }
+    println!(
+        "  Total: {} over {} transactions",
+        Sol(payments.iter().map(|payment| payment.lamports).sum()),
+        payments.len()
+    );
+    Ok(())
+}
+
+fn format_operation_stats(stats: &OperationStats) -> String {
+    format!(
+        "{} ({:.1}% succeeded), median {}",
+        stats.count(),
+        stats.success_rate() * 100.,
+        stats
+            .median_latency()
+            .map_or_else(|| "-".into(), |latency| format!("{latency:.1?}")),
+    )
+}
+
+fn process_stats(days: u32) {
+    let since = Local::now().date_naive() - chrono::Duration::try_days(days.into()).unwrap();
+    let daily_stats = operation_stats::daily_stats()
+        .into_iter()
+        .filter(|daily_stats| daily_stats.date > since)
+        .collect::<Vec<_>>();
+    if daily_stats.is_empty() {
+        println!("No operations recorded in the last {days} days");
+        return;
+    }
+
+    // Totals of each operation, and of each RPC endpoint or exchange API method of the operation
+    let mut by_operation =
+        BTreeMap::<Operation, (OperationStats, BTreeMap<String, OperationStats>)>::new();
+    let mut by_date = BTreeMap::<NaiveDate, BTreeMap<Operation, OperationStats>>::new();
+    for DailyOperationStats {
+        date,
+        operation,
+        name,
+        stats,
+    } in &daily_stats
     {
-        return Err(format!(
-            "Authority has insufficient funds for the transaction fee of {}",
-            token.ui_amount(num_transaction_signatures * fee_calculator.lamports_per_signature)
-        )
-        .into());
+        let (operation_stats, by_name) = by_operation.entry(*operation).or_default();
+        operation_stats.merge(stats);
+        if !name.is_empty() {
+            by_name.entry(name.clone()).or_default().merge(stats);
+        }
+        by_date
+            .entry(*date)
+            .or_default()
+            .entry(*operation)
+            .or_default()
+            .merge(stats);
     }
 
-    let apply_exact_amount = |amount: u64| -> Result<u64, Box<dyn std::error::Error>> {
-        if let Some(exact_amount) = exact_amount {
-            if exact_amount > amount {
-                Err(format!("Account has insufficient balance: {}", from_address).into())
-            } else {
-                Ok(exact_amount)
-            }
-        } else {
-            Ok(amount)
+    println!("Operations in the last {days} days:");
+    for (operation, (operation_stats, by_name)) in &by_operation {
+        println!(
+            "  {}: {}",
+            <&str>::from(operation),
+            format_operation_stats(operation_stats)
+        );
+        for (name, stats) in by_name {
+            println!("    {name}: {}", format_operation_stats(stats));
         }
-    };
+    }
 
-    let (mut instructions, sweep_amount) = if token.is_sol() {
-        if from_account.lamports < from_tracked_account.last_update_balance {
+    println!();
+    println!("Operations by day:");
+    for (date, by_operation) in &by_date {
+        println!("  {date}:");
+        for (operation, stats) in by_operation {
             println!(
-                "Warning: {}: On-chain account balance ({}) less than tracked balance ({})",
-                from_address,
-                token.ui_amount(from_account.lamports),
-                token.ui_amount(from_tracked_account.last_update_balance)
+                "    {}: {}",
+                <&str>::from(operation),
+                format_operation_stats(stats)
             );
         }
+    }
+}
 
-        if from_account.owner == system_program::id() {
-            let lamports = apply_exact_amount(if from_address == from_authority_address {
-                from_tracked_account.last_update_balance.saturating_sub(
-                    num_transaction_signatures * fee_calculator.lamports_per_signature
-                        + retain_amount,
-                )
-            } else {
-                from_tracked_account
-                    .last_update_balance
-                    .saturating_sub(retain_amount)
-            })?;
-
-            (
-                vec![system_instruction::transfer(
-                    &from_address,
-                    &to_address,
-                    lamports,
-                )],
-                lamports,
-            )
-        } else if from_account.owner == solana_vote_program::id() {
-            let minimum_balance = rpc_client.get_minimum_balance_for_rent_exemption(
-                solana_vote_program::vote_state::VoteState::size_of(),
-            )?;
+#[allow(clippy::too_many_arguments)]
+fn process_account_vesting_add(
+    db: &mut Db,
+    name: String,
+    address: Pubkey,
+    token: MaybeToken,
+    total_amount: u64,
+    start: NaiveDate,
+    cliff: NaiveDate,
+    end: NaiveDate,
+    interval_months: u32,
+    received_amount: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if db.get_account(address, token).is_none() {
+        return Err(format!("{token} account does not exist for {address}").into());
+    }
+    if !(start <= cliff && cliff <= end && start < end) {
+        return Err("The cliff must be between the start and end of the grant".into());
+    }
+    if received_amount > total_amount {
+        return Err("The amount received exceeds the grant".into());
+    }
 
-            let lamports = apply_exact_amount(
-                from_tracked_account
-                    .last_update_balance
-                    .saturating_sub(minimum_balance + retain_amount),
-            )?;
+    let vesting_grant = VestingGrant {
+        name,
+        address,
+        token,
+        total_amount,
+        start,
+        cliff,
+        end,
+        interval_months,
+        received_amount,
+    };
+    let today = today();
+    println!(
+        "Vesting grant {} of {} to {address}: {} unlocked, {} locked",
+        vesting_grant.name,
+        token.format_amount(total_amount),
+        token.format_amount(vesting_grant.unlocked_amount(today)),
+        token.format_amount(vesting_grant.locked_amount(today)),
+    );
+    db.set_vesting_grant(vesting_grant)?;
+    Ok(())
+}
 
-            (
-                vec![solana_vote_program::vote_instruction::withdraw(
-                    &from_address,
-                    &from_authority_address,
-                    lamports,
-                    &to_address,
-                )],
-                lamports,
-            )
-        } else if from_account.owner == solana_sdk::stake::program::id() {
-            let lamports = apply_exact_amount(
-                from_tracked_account
-                    .last_update_balance
-                    .saturating_sub(retain_amount),
-            )?;
+// Lists the vesting grants with their amounts still locked, which are not held by any account
+// yet and so are left out of net worth
+async fn process_account_vesting_ls(
+    db: &Db,
+    rpc_client: &RpcClient,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let vesting_grants = db.vesting_grants();
+    if vesting_grants.is_empty() {
+        println!("No vesting grants");
+        return Ok(());
+    }
 
-            (
-                vec![solana_sdk::stake::instruction::withdraw(
-                    &from_address,
-                    &from_authority_address,
-                    &to_address,
-                    lamports,
-                    None,
-                )],
-                lamports,
-            )
+    let today = today();
+    let mut total_locked_value = Decimal::ZERO;
+    for vesting_grant in vesting_grants {
+        let token = vesting_grant.token;
+        let locked_amount = vesting_grant.locked_amount(today);
+        let locked_value = if locked_amount > 0 {
+            token.get_current_price(rpc_client).await? * token.ui_amount_decimal(locked_amount)
         } else {
-            return Err(format!("Unsupported `from` account owner: {}", from_account.owner).into());
+            Decimal::ZERO
+        };
+        total_locked_value += locked_value;
+
+        println!(
+            "{} ({}): {}",
+            vesting_grant.name, vesting_grant.address, vesting_grant.token
+        );
+        println!(
+            "  Total:    {}, from {} to {}, cliff {}, every {} month(s)",
+            token.format_amount(vesting_grant.total_amount),
+            vesting_grant.start,
+            vesting_grant.end,
+            vesting_grant.cliff,
+            vesting_grant.interval_months,
+        );
+        println!(
+            "  Unlocked: {} ({} received)",
+            token.format_amount(vesting_grant.unlocked_amount(today)),
+            token.format_amount(vesting_grant.received_amount),
+        );
+        println!(
+            "  Locked:   {} ({}){}",
+            token.format_amount(locked_amount),
+            format_currency(locked_value),
+            vesting_grant
+                .next_unlock(today)
+                .map(|next_unlock| format!(", next unlock {next_unlock}"))
+                .unwrap_or_default(),
+        );
+        let unreceived_amount = vesting_grant.unreceived_amount(today);
+        if unreceived_amount > 0 {
+            println!(
+                "  Warning: {} unlocked but not yet received",
+                token.format_amount(unreceived_amount)
+            );
         }
-    } else {
-        let token = token.token().unwrap();
+        println!();
+    }
+    println!(
+        "Locked value: {} (excluded from net worth)",
+        format_currency(total_locked_value)
+    );
+    Ok(())
+}
 
-        let amount = apply_exact_amount(
-            from_tracked_account
-                .last_update_balance
-                .saturating_sub(retain_amount),
-        )?;
+fn unix_timestamp_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
 
-        (
-            vec![spl_token::instruction::transfer_checked(
-                &spl_token::id(),
-                &token.ata(&from_address),
-                &token.mint(),
-                &token.ata(&to_address),
-                &from_authority_address,
-                &[],
-                amount,
-                token.decimals(),
-            )
-            .unwrap()],
-            amount,
-        )
-    };
+// Lists the incoming streams found by `sync`, with their amounts unlocked but not yet claimed
+async fn process_account_stream_ls(
+    db: &Db,
+    rpc_client: &RpcClient,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let token_streams = db.token_streams();
+    if token_streams.is_empty() {
+        println!("No incoming streams");
+        return Ok(());
+    }
 
-    if sweep_amount < token.amount(1.) {
-        let msg = format!(
-            "{} has less than {}1 to sweep ({})",
-            from_address,
-            token.symbol(),
-            token.ui_amount(sweep_amount)
-        );
-        return if no_sweep_ok {
-            println!("{msg}");
-            Ok(())
+    let now = unix_timestamp_now();
+    let mut total_claimable_value = Decimal::ZERO;
+    for token_stream in token_streams {
+        let token = token_stream.token;
+        let stream = streamflow::get_stream(rpc_client, token_stream.address)?;
+        let claimable_amount = stream.claimable_amount(now);
+        let claimable_value = if claimable_amount > 0 {
+            token.get_current_price(rpc_client).await? * token.ui_amount_decimal(claimable_amount)
         } else {
-            Err(msg.into())
+            Decimal::ZERO
         };
+        total_claimable_value += claimable_value;
+
+        println!(
+            "{} ({}): {} to {}",
+            token_stream.name, token_stream.address, token, token_stream.recipient
+        );
+        println!(
+            "  Deposited: {} by {}",
+            token.format_amount(stream.net_amount_deposited),
+            stream.sender
+        );
+        println!(
+            "  Claimed:   {}",
+            token.format_amount(stream.withdrawn_amount)
+        );
+        println!(
+            "  Claimable: {} ({})",
+            token.format_amount(claimable_amount),
+            format_currency(claimable_value)
+        );
+        println!();
     }
+    println!(
+        "Claimable value: {}",
+        format_currency(total_claimable_value)
+    );
+    Ok(())
+}
 
-    println!("From address: {from_address}");
-    if from_address != from_authority_address {
-        println!("Authority address: {from_authority_address}");
+// Withdraws everything unlocked from an incoming stream into the recipient's token account,
+// acquiring it as income
+async fn process_account_stream_claim<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    address: Pubkey,
+    signers: T,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    let mut token_stream = db
+        .token_streams()
+        .into_iter()
+        .find(|token_stream| token_stream.address == address)
+        .ok_or_else(|| format!("Stream {address} is not tracked, `sync` finds incoming streams"))?;
+    let token = token_stream.token;
+    let mut account = db
+        .get_account(token_stream.recipient, token)
+        .ok_or_else(|| {
+            format!(
+                "{token} account does not exist for {}",
+                token_stream.recipient
+            )
+        })?;
+
+    let stream = streamflow::get_stream(rpc_client, address)?;
+    let amount = stream.claimable_amount(unix_timestamp_now());
+    if amount == 0 {
+        println!("Nothing to claim");
+        return Ok(());
     }
-    println!("Destination address: {to_address}");
+
+    let (recent_blockhash, last_valid_block_height) =
+        rpc_clients.get_latest_blockhash_with_commitment()?;
+
+    let mut instructions = vec![stream.withdraw_instruction(
+        amount,
+        token
+            .token()
+            .ok_or("Only token streams are supported")?
+            .program_id(),
+    )];
+    apply_priority_fee(rpc_clients, &mut instructions, 50_000, priority_fee)?;
+
+    let message = Message::new(&instructions, Some(&stream.recipient));
+
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.message.recent_blockhash = recent_blockhash;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(
+            rpc_client,
+            db,
+            &VersionedMessage::Legacy(transaction.message.clone()),
+        )?;
+    }
+
     println!(
-        "Sweep amount: {}{}",
-        token.symbol(),
-        token.ui_amount(sweep_amount)
+        "Claiming {} from stream {}",
+        token.format_amount(amount),
+        token_stream.name
     );
 
-    let msg = if let Some((
-        transitory_stake_account,
-        sweep_stake_authority_keypair,
-        sweep_stake_address,
-    )) = via_transitory_stake.as_ref()
-    {
-        assert!(existing_signature.is_none());
-        assert_eq!(to_address, transitory_stake_account.pubkey());
+    transaction.try_sign(&signers, recent_blockhash)?;
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
 
-        let (sweep_stake_authorized, sweep_stake_vote_account_address) =
-            rpc_client_utils::get_stake_authorized(rpc_client, *sweep_stake_address)?;
+    let status = send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+    record_sent_transaction(db, PriorityFeeCategory::Other, &transaction, status).await?;
+    if !status.confirmed() {
+        return Err("Claim failed".into());
+    }
 
-        if sweep_stake_authorized.staker != sweep_stake_authority_keypair.pubkey() {
-            return Err("Stake authority mismatch".into());
-        }
+    let (when, decimal_price) =
+        get_block_date_and_price(rpc_client, rpc_client.get_slot()?, token).await?;
+    account.lots.push(Lot {
+        lot_number: db.next_lot_number(),
+        acquisition: LotAcquistion::new(
+            when,
+            decimal_price,
+            LotAcquistionKind::StreamPayment { stream: address },
+        ),
+        amount,
+    });
+    account.last_update_balance += amount;
+    db.update_account(account)?;
 
-        instructions.append(&mut vec![
-            system_instruction::allocate(
-                &transitory_stake_account.pubkey(),
-                std::mem::size_of::<solana_sdk::stake::state::StakeStateV2>() as u64,
-            ),
-            system_instruction::assign(
-                &transitory_stake_account.pubkey(),
-                &solana_sdk::stake::program::id(),
-            ),
-            solana_sdk::stake::instruction::initialize(
-                &transitory_stake_account.pubkey(),
-                &sweep_stake_authorized,
-                &solana_sdk::stake::state::Lockup::default(),
-            ),
-            solana_sdk::stake::instruction::delegate_stake(
-                &transitory_stake_account.pubkey(),
-                &sweep_stake_authority_keypair.pubkey(),
-                &sweep_stake_vote_account_address,
-            ),
-        ]);
-        format!(
-            "Sweeping {}{} from {} into {} (via {})",
-            token.symbol(),
-            token
-                .ui_amount(sweep_amount)
-                .separated_string_with_fixed_place(2),
-            from_address,
-            sweep_stake_address,
-            to_address
-        )
-    } else {
-        format!(
-            "Sweeping {}{} from {} into {}",
-            token.symbol(),
-            token
-                .ui_amount(sweep_amount)
-                .separated_string_with_fixed_place(2),
-            from_address,
-            to_address
-        )
-    };
+    token_stream.received_amount += amount;
+    db.set_token_stream(token_stream)?;
 
-    let (signature, maybe_transaction) = match existing_signature {
-        None => {
-            apply_priority_fee(rpc_clients, &mut instructions, 7_000, priority_fee)?;
+    println!("Claim confirmed: {signature}");
+    Ok(())
+}
 
-            let mut message = Message::new(&instructions, Some(&from_authority_address));
-            message.recent_blockhash = recent_blockhash;
+// A lot, or the part of one, proposed for disposal to realize a loss
+struct HarvestLot {
+    address: Pubkey,
+    token: MaybeToken,
+    lot_number: usize,
+    amount: u64,
+    loss: f64,
+    long_term: bool,
+}
 
-            let mut transaction = Transaction::new_unsigned(message);
-            let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
-            if simulation_result.err.is_some() {
-                return Err(format!("Simulation failure: {simulation_result:?}").into());
-            }
+// Tokens purchased within the wash sale window before today. A loss realized on them now would
+// be disallowed
+fn tokens_with_recent_purchases(db: &Db, today: NaiveDate) -> HashSet<MaybeToken> {
+    all_lots(db)
+        .into_iter()
+        .filter(|(_, lot)| {
+            is_wash_sale_replacement(&lot.acquisition.kind)
+                && (today - lot.acquisition.when).num_days() <= WASH_SALE_WINDOW_DAYS
+        })
+        .map(|(token, _)| wash_sale_token(token))
+        .collect()
+}
 
-            transaction.partial_sign(&signers, recent_blockhash);
-            if let Some((transitory_stake_account, sweep_stake_authority_keypair, ..)) =
-                via_transitory_stake.as_ref()
-            {
-                assert!(existing_signature.is_none());
-                transaction.try_sign(
-                    &[transitory_stake_account, sweep_stake_authority_keypair],
-                    recent_blockhash,
-                )?;
-            }
+// Proposes lots to dispose of to realize `target_loss`. Lots are taken from each account in
+// highest basis order, the order `LotSelectionMethod::HighestBasis` disposes of them in, always
+// taking the next lot with the largest loss across accounts so that as few lots as possible are
+// used. The last lot is only partially disposed of if that suffices
+fn propose_harvest(
+    db: &Db,
+    prices: &HashMap<MaybeToken, Decimal>,
+    excluded_tokens: &HashSet<MaybeToken>,
+    target_loss: f64,
+    long_term: Option<bool>,
+    today: NaiveDate,
+) -> Vec<HarvestLot> {
+    let mut queues = db
+        .get_accounts()
+        .into_iter()
+        .filter(|account| !excluded_tokens.contains(&wash_sale_token(account.token)))
+        .filter_map(|account| {
+            let price = *prices.get(&account.token)?;
+            let mut lots = account
+                .lots
+                .into_iter()
+                .filter(|lot| {
+                    lot.acquisition.price() > price
+                        && long_term.is_none_or(|long_term| {
+                            is_long_term_cap_gain(lot.acquisition.when, Some(today)) == long_term
+                        })
+                })
+                .collect::<Vec<_>>();
+            sort_lots_by_selection_method(&mut lots, LotSelectionMethod::HighestBasis);
+            lots.reverse(); // Pop the highest basis lot first
+            Some((account.address, account.token, price, lots))
+        })
+        .collect::<Vec<_>>();
 
-            let signature = transaction.signatures[0];
-            println!("Transaction signature: {signature}");
+    let mut harvest = vec![];
+    let mut remaining_loss = target_loss;
+    while remaining_loss > 0. {
+        let next = queues
+            .iter_mut()
+            .filter_map(|(address, token, price, lots)| {
+                let lot = lots.last()?;
+                let loss = f64::try_from(-lot.cap_gain(*token, *price)).unwrap();
+                Some((loss, address, token, price, lots))
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        let Some((loss, address, token, price, lots)) = next else {
+            break;
+        };
+        let lot = lots.pop().unwrap();
 
-            let epoch = rpc_client.get_epoch_info()?.epoch;
-            if let Some((transitory_stake_account, ..)) = via_transitory_stake.as_ref() {
-                assert!(existing_signature.is_none());
-                db.add_transitory_sweep_stake_address(transitory_stake_account.pubkey(), epoch)?;
-            }
-            (signature, Some(transaction))
-        }
-        Some(existing_signature) => (existing_signature, None),
-    };
-    db.record_transfer(
-        signature,
-        last_valid_block_height,
-        Some(sweep_amount),
-        from_address,
-        token,
-        to_address,
-        token,
-        LotSelectionMethod::default(),
-        None,
-    )?;
+        let (amount, loss) = if loss <= remaining_loss {
+            (lot.amount, loss)
+        } else {
+            let loss_per_token = f64::try_from(lot.acquisition.price() - *price).unwrap();
+            let amount = token
+                .amount(remaining_loss / loss_per_token)
+                .clamp(1, lot.amount);
+            (amount, loss_per_token * token.ui_amount(amount))
+        };
+        remaining_loss -= loss;
+        harvest.push(HarvestLot {
+            address: *address,
+            token: *token,
+            lot_number: lot.lot_number,
+            amount,
+            loss,
+            long_term: is_long_term_cap_gain(lot.acquisition.when, Some(today)),
+        });
+    }
+    harvest
+}
 
-    if let Some(transaction) = maybe_transaction {
-        if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-            .unwrap_or_default()
-        {
-            db.cancel_transfer(signature)?;
-            if let Some((transitory_stake_account, ..)) = via_transitory_stake.as_ref() {
-                db.remove_transitory_sweep_stake_address(transitory_stake_account.pubkey())?;
-            }
-            return Err("Sweep failed".into());
+// The exchange, and a client for it, whose deposit address for `token` is `address`
+async fn exchange_of_deposit_address(
+    db: &Db,
+    address: Pubkey,
+    token: MaybeToken,
+) -> Result<Option<(Exchange, Box<dyn ExchangeClient>)>, Box<dyn std::error::Error>> {
+    for (exchange, exchange_credentials, _exchange_account) in
+        db.get_accounts_from_configured_exchanges()
+    {
+        let exchange_client = exchange_client_new(exchange, exchange_credentials)?;
+        if exchange_client.deposit_address(token).await.ok() == Some(address) {
+            return Ok(Some((exchange, exchange_client)));
         }
     }
-    println!("Confirming sweep: {signature}");
-    let when = get_signature_date(rpc_client, signature).await?;
-    db.confirm_transfer(signature, when)?;
-
-    notifier.send(&msg).await;
-    println!("{msg}");
-    Ok(())
+    Ok(None)
 }
 
+// Disposes of the proposed lots of one account. Exchange deposit accounts sell the exact lots at
+// market, other accounts swap to USDC with Jupiter, which only disposes of the proposed lots if
+// they are the account's highest basis lots
 #[allow(clippy::too_many_arguments)]
-async fn process_account_split<T: Signers>(
+async fn execute_harvest(
     db: &mut Db,
     rpc_clients: &RpcClients,
-    from_address: Pubkey,
-    amount: Option<u64>,
-    description: Option<String>,
-    lot_selection_method: LotSelectionMethod,
-    lot_numbers: Option<HashSet<usize>>,
-    authority_address: Pubkey,
-    signers: T,
-    into_keypair: Option<Keypair>,
-    if_balance_exceeds: Option<f64>,
+    address: Pubkey,
+    token: MaybeToken,
+    harvest_lots: &[&HarvestLot],
+    signer: Option<&dyn Signer>,
     priority_fee: PriorityFee,
+    notifier: &Notifier,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_client = rpc_clients.default();
-
-    // TODO: Support splitting two system accounts? Tokens? Otherwise at least error cleanly when it's attempted
-    let token = MaybeToken::SOL(); // TODO: Support splitting tokens one day
-
-    let (recent_blockhash, last_valid_block_height) =
-        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+    let amount = harvest_lots
+        .iter()
+        .map(|harvest_lot| harvest_lot.amount)
+        .sum::<u64>();
+    let lot_numbers = harvest_lots
+        .iter()
+        .map(|harvest_lot| harvest_lot.lot_number)
+        .collect::<HashSet<_>>();
 
-    let into_keypair = into_keypair.unwrap_or_else(Keypair::new);
-    if db.get_account(into_keypair.pubkey(), token).is_some() {
-        return Err(format!(
-            "Account {} ({}) already exists",
-            into_keypair.pubkey(),
-            token
+    if let Some((exchange, exchange_client)) =
+        exchange_of_deposit_address(db, address, token).await?
+    {
+        if !token.is_sol() {
+            println!("Skipping {address} ({token}): only SOL can be sold on {exchange:?}");
+            return Ok(());
+        }
+        let pair = exchange_client.preferred_solusd_pair().to_string();
+        return process_exchange_sell(
+            db,
+            exchange,
+            exchange_client.as_ref(),
+            token,
+            pair,
+            token.ui_amount(amount),
+            None,
+            None,
+            None,
+            false,
+            None,
+            LotSelectionMethod::HighestBasis,
+            Some(lot_numbers),
+            None,
+            OrderOptions::default(),
+            Some(DEFAULT_MAX_BOOK_FRACTION),
+            notifier,
         )
-        .into());
+        .await;
     }
 
-    let from_account = db
-        .get_account(from_address, MaybeToken::SOL())
-        .ok_or_else(|| format!("SOL account does not exist for {from_address}"))?;
-
-    let (split_all, amount, description) = match amount {
-        None => (
-            true,
-            from_account.last_update_balance,
-            description.unwrap_or(from_account.description),
-        ),
-        Some(amount) => (
-            false,
-            amount,
-            description.unwrap_or_else(|| format!("Split at {}", Local::now())),
-        ),
+    let Some(signer) = signer else {
+        println!("Skipping {address} ({token}): --signer is not the keypair of this account");
+        return Ok(());
     };
+    let to_token = MaybeToken::from(Token::USDC);
+    if token == to_token {
+        return Ok(());
+    }
 
-    if let Some(if_balance_exceeds) = if_balance_exceeds {
-        if token.ui_amount(amount) < if_balance_exceeds {
-            println!(
-                "Split declined because {:?} balance is less than {}",
-                from_address,
-                token.format_ui_amount(if_balance_exceeds)
-            );
-            return Ok(());
-        }
+    let mut account_lots = db
+        .get_account(address, token)
+        .ok_or_else(|| format!("{token} account does not exist for {address}"))?
+        .lots;
+    sort_lots_by_selection_method(&mut account_lots, LotSelectionMethod::HighestBasis);
+    if account_lots
+        .iter()
+        .take(lot_numbers.len())
+        .any(|lot| !lot_numbers.contains(&lot.lot_number))
+    {
+        println!(
+            "Skipping {address} ({token}): the proposed lots are not its highest basis lots, \
+             which a swap would dispose of instead"
+        );
+        return Ok(());
     }
 
-    let minimum_stake_account_balance = rpc_client
-        .get_minimum_balance_for_rent_exemption(solana_sdk::stake::state::StakeStateV2::size_of())?;
+    process_jup_swap(
+        db,
+        rpc_clients,
+        address,
+        token,
+        to_token,
+        Some(token.ui_amount(amount)),
+        100,
+        LotSelectionMethod::HighestBasis,
+        vec![signer],
+        None,
+        None,
+        None,
+        5.,
+        priority_fee,
+        false,
+        notifier,
+        verbose,
+    )
+    .await?;
+    process_sync_swaps(db, rpc_clients.default(), notifier, verbose).await
+}
 
-    let mut instructions = vec![];
-    apply_priority_fee(rpc_clients, &mut instructions, 10_000, priority_fee)?;
+#[allow(clippy::too_many_arguments)]
+async fn process_account_harvest(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    target_loss: f64,
+    long_term: Option<bool>,
+    execute: bool,
+    signer: Option<(Pubkey, Box<dyn Signer>)>,
+    priority_fee: PriorityFee,
+    notifier: &Notifier,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let today = Local::now().date_naive();
 
-    instructions.push(system_instruction::transfer(
-        &authority_address,
-        &into_keypair.pubkey(),
-        minimum_stake_account_balance,
-    ));
-    instructions.append(&mut solana_sdk::stake::instruction::split(
-        &from_address,
-        &authority_address,
-        amount,
-        &into_keypair.pubkey(),
-    ));
+    let mut prices = HashMap::new();
+    for account in db.get_accounts() {
+        if account.lots.is_empty() || account.token.fiat_fungible() {
+            continue;
+        }
+        if let std::collections::hash_map::Entry::Vacant(entry) = prices.entry(account.token) {
+            entry.insert(account.token.get_current_price(rpc_client).await?);
+        }
+    }
 
-    let message = Message::new(&instructions, Some(&authority_address));
+    let excluded_tokens = tokens_with_recent_purchases(db, today);
+    for token in &excluded_tokens {
+        println!("Excluding {token}: purchased within the last {WASH_SALE_WINDOW_DAYS} days");
+    }
 
-    let mut transaction = Transaction::new_unsigned(message);
-    transaction.message.recent_blockhash = recent_blockhash;
-    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
-    if simulation_result.err.is_some() {
-        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    let harvest = propose_harvest(db, &prices, &excluded_tokens, target_loss, long_term, today);
+    if harvest.is_empty() {
+        println!("No lots with unrealized losses");
+        return Ok(());
     }
 
+    let mut short_term_loss = 0.;
+    let mut long_term_loss = 0.;
+    for harvest_lot in &harvest {
+        println!(
+            "{} lot {} in {}: dispose of {} for a {}-term loss of {}",
+            harvest_lot.token,
+            harvest_lot.lot_number,
+            harvest_lot.address,
+            harvest_lot.token.format_amount(harvest_lot.amount),
+            if harvest_lot.long_term {
+                "long"
+            } else {
+                "short"
+            },
+            currency(harvest_lot.loss.localized_string_with_fixed_place(2)),
+        );
+        if harvest_lot.long_term {
+            long_term_loss += harvest_lot.loss;
+        } else {
+            short_term_loss += harvest_lot.loss;
+        }
+    }
     println!(
-        "Splitting {} from {} into {}",
-        token.ui_amount(amount),
-        from_address,
-        into_keypair.pubkey(),
+        "Total loss: {} (short-term: {}, long-term: {})",
+        currency((short_term_loss + long_term_loss).localized_string_with_fixed_place(2)),
+        currency(short_term_loss.localized_string_with_fixed_place(2)),
+        currency(long_term_loss.localized_string_with_fixed_place(2)),
+    );
+    if short_term_loss + long_term_loss < target_loss {
+        println!(
+            "Only {} of unrealized losses are available",
+            currency((short_term_loss + long_term_loss).localized_string_with_fixed_place(2))
+        );
+    }
+    let harvested_tokens = harvest
+        .iter()
+        .map(|harvest_lot| wash_sale_token(harvest_lot.token))
+        .collect::<BTreeSet<_>>();
+    println!(
+        "To keep the losses allowed, do not buy {} until {}",
+        harvested_tokens.iter().join(", "),
+        today + chrono::Duration::try_days(WASH_SALE_WINDOW_DAYS + 1).unwrap()
     );
 
-    transaction.partial_sign(&signers, recent_blockhash);
-    transaction.try_sign(&[&into_keypair], recent_blockhash)?;
-
-    let signature = transaction.signatures[0];
-    println!("Transaction signature: {signature}");
+    if execute {
+        let mut harvest_by_account = BTreeMap::<(Pubkey, MaybeToken), Vec<&HarvestLot>>::new();
+        for harvest_lot in &harvest {
+            harvest_by_account
+                .entry((harvest_lot.address, harvest_lot.token))
+                .or_default()
+                .push(harvest_lot);
+        }
+        for ((address, token), harvest_lots) in harvest_by_account {
+            let account_signer = signer
+                .as_ref()
+                .filter(|(signer_address, _)| *signer_address == address)
+                .map(|(_, signer)| signer.as_ref());
+            execute_harvest(
+                db,
+                rpc_clients,
+                address,
+                token,
+                &harvest_lots,
+                account_signer,
+                priority_fee,
+                notifier,
+                verbose,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
 
-    let epoch = rpc_client.get_epoch_info()?.epoch;
-    db.add_account(TrackedAccount {
-        address: into_keypair.pubkey(),
-        token,
-        description,
-        last_update_epoch: epoch.saturating_sub(1),
-        last_update_balance: 0,
-        lots: vec![],
-        no_sync: from_account.no_sync,
-    })?;
-    db.record_transfer(
-        signature,
-        last_valid_block_height,
-        Some(amount),
-        from_address,
-        token,
-        into_keypair.pubkey(),
-        token,
-        lot_selection_method,
-        lot_numbers,
-    )?;
-
-    if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-        .unwrap_or_default()
-    {
-        db.cancel_transfer(signature)?;
-        db.remove_account(into_keypair.pubkey(), MaybeToken::SOL())?;
-        return Err("Split failed".into());
-    }
-    println!("Split confirmed: {signature}");
-    let when = get_signature_date(rpc_client, signature).await?;
-    db.confirm_transfer(signature, when)?;
-    if split_all {
-        // TODO: This `remove_account` is racy and won't work in all cases. Consider plumbing the
-        // removal through `confirm_transfer` instead
-        let from_account = db.get_account(from_address, MaybeToken::SOL()).unwrap();
-        assert!(from_account.lots.is_empty());
-        db.remove_account(from_address, MaybeToken::SOL())?;
-    }
-    Ok(())
-}
-
-#[allow(clippy::too_many_arguments)]
-async fn process_account_redelegate<T: Signers>(
-    db: &mut Db,
-    rpc_clients: &RpcClients,
-    from_address: Pubkey,
-    vote_account_address: Pubkey,
-    lot_selection_method: LotSelectionMethod,
-    authority_address: Pubkey,
-    signers: &T,
-    into_keypair: Option<Keypair>,
+async fn process_account_xls(
+    db: &Db,
+    rpc_client: &RpcClient,
+    outfile: &str,
+    filter_by_year: Option<i32>,
+    include_wash_sales: bool,
+    options: &ExportOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_client = rpc_clients.default();
-    let (recent_blockhash, last_valid_block_height) =
-        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+    use simple_excel_writer::*;
 
-    let minimum_stake_account_balance = rpc_client
-        .get_minimum_balance_for_rent_exemption(solana_sdk::stake::state::StakeStateV2::size_of())?;
+    let mut workbook = Workbook::create(outfile);
 
-    let into_keypair = into_keypair.unwrap_or_else(Keypair::new);
-    if db
-        .get_account(into_keypair.pubkey(), MaybeToken::SOL())
-        .is_some()
-    {
-        return Err(format!(
-            "Account {} ({}) already exists",
-            into_keypair.pubkey(),
-            MaybeToken::SOL()
-        )
-        .into());
+    let mut sheet = workbook.create_sheet(&match filter_by_year {
+        Some(year) => format!("Disposed in {year}"),
+        None => "Disposed".into(),
+    });
+    sheet.add_column(Column { width: 12. });
+    sheet.add_column(Column { width: 15. });
+    sheet.add_column(Column { width: 12. });
+    sheet.add_column(Column { width: 12. });
+    sheet.add_column(Column { width: 10. });
+    sheet.add_column(Column { width: 40. });
+    sheet.add_column(Column { width: 12. });
+    sheet.add_column(Column { width: 10. });
+    sheet.add_column(Column { width: 10. });
+    sheet.add_column(Column { width: 10. });
+    sheet.add_column(Column { width: 10. });
+    sheet.add_column(Column { width: 40. });
+    if include_wash_sales {
+        sheet.add_column(Column { width: 15. });
     }
 
-    let from_account = db
-        .get_account(from_address, MaybeToken::SOL())
-        .ok_or_else(|| format!("SOL account does not exist for {from_address}"))?;
-
-    if from_account.last_update_balance < minimum_stake_account_balance * 2 {
-        return Err(format!(
-            "Account {} ({}) has insufficient balance",
-            into_keypair.pubkey(),
-            MaybeToken::SOL()
-        )
-        .into());
+    // Disallowed loss by disposed lot number and disposal date
+    let mut disallowed_losses = HashMap::<(usize, NaiveDate), Decimal>::new();
+    if include_wash_sales {
+        for wash_sale in wash_sales(db) {
+            *disallowed_losses
+                .entry((
+                    wash_sale.disposed_lot.lot.lot_number,
+                    wash_sale.disposed_lot.when,
+                ))
+                .or_default() += wash_sale.disallowed_loss;
+        }
     }
-    let redelegated_amount = from_account.last_update_balance - minimum_stake_account_balance;
 
-    let instructions = solana_sdk::stake::instruction::redelegate(
-        &from_address,
-        &authority_address,
-        &vote_account_address,
-        &into_keypair.pubkey(),
-    );
+    let fees_usd = disposal_fees_usd(db, rpc_client).await?;
 
-    let message = Message::new(&instructions, Some(&authority_address));
+    let mut disposed_lots = db.disposed_lots();
+    disposed_lots.sort_by_key(|lot| lot.when);
 
-    let mut transaction = Transaction::new_unsigned(message);
-    transaction.message.recent_blockhash = recent_blockhash;
-    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
-    if simulation_result.err.is_some() {
-        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    if let Some(year) = filter_by_year {
+        // Exclude disposed lots that were neither acquired nor disposed of in the filter year
+        disposed_lots.retain(|disposed_lot| {
+            (disposed_lot.lot.acquisition.when.year() == year
+                && disposed_lot.lot.income(disposed_lot.token) > Decimal::ZERO)
+                || disposed_lot.when.year() == year
+        })
     }
 
-    println!(
-        "Relegating {} to {} via{}",
-        from_address,
-        vote_account_address,
-        into_keypair.pubkey(),
-    );
+    workbook.write_sheet(&mut sheet, |sheet_writer| {
+        let mut header = row![
+            "Token",
+            "Amount",
+            "Income (USD)",
+            "Acq. Date",
+            "Acq. Price (USD)",
+            "Acquisition Description",
+            "Cap Gain (USD)",
+            "Cap Gain Type",
+            "Sale Date",
+            "Sale Price (USD)",
+            "Fee (USD)",
+            "Sale Description"
+        ];
+        if include_wash_sales {
+            header.add_cell("Wash Sale Disallowed Loss (USD)");
+        }
+        sheet_writer.append_row(header)?;
 
-    transaction.partial_sign(signers, recent_blockhash);
-    transaction.try_sign(&[&into_keypair], recent_blockhash)?;
+        for disposed_lot in disposed_lots {
+            let token = disposed_lot.token;
+            if options.is_below_min_value(disposed_lot.lot.basis(token))
+                && options.is_below_min_value(
+                    disposed_lot.price() * token.ui_amount_decimal(disposed_lot.lot.amount),
+                )
+            {
+                continue;
+            }
+            let long_term_cap_gain =
+                is_long_term_cap_gain(disposed_lot.lot.acquisition.when, Some(disposed_lot.when));
 
-    let signature = transaction.signatures[0];
-    println!("Transaction signature: {signature}");
+            let mut income = disposed_lot.lot.income(disposed_lot.token);
+            if let Some(year) = filter_by_year {
+                if disposed_lot.lot.acquisition.when.year() != year {
+                    income = Decimal::ZERO // Exclude income from other years
+                }
+            }
 
-    let epoch = rpc_client.get_epoch_info()?.epoch;
-    db.add_account(TrackedAccount {
-        address: into_keypair.pubkey(),
-        token: MaybeToken::SOL(),
-        description: from_account.description,
-        last_update_epoch: epoch.saturating_sub(1),
-        last_update_balance: 0,
-        lots: vec![],
-        no_sync: None,
+            let mut row = row![
+                disposed_lot.token.to_string(),
+                disposed_lot.token.ui_amount(disposed_lot.lot.amount),
+                f64::try_from(options.round(income)).unwrap(),
+                disposed_lot.lot.acquisition.when.to_string(),
+                disposed_lot.lot.acquisition.price().to_string(),
+                disposed_lot.lot.acquisition.kind.to_string(),
+                f64::try_from(
+                    options.round(
+                        disposed_lot
+                            .lot
+                            .cap_gain(disposed_lot.token, disposed_lot.price())
+                    )
+                )
+                .unwrap(),
+                if long_term_cap_gain { "Long" } else { "Short" },
+                disposed_lot.when.to_string(),
+                disposed_lot.price().to_string(),
+                fees_usd
+                    .get(&(disposed_lot.lot.lot_number, disposed_lot.when))
+                    .map(|fee_usd| f64::try_from(options.round(*fee_usd)).unwrap())
+                    .unwrap_or_default(),
+                disposed_lot.kind.to_string()
+            ];
+            if include_wash_sales {
+                row.add_cell(
+                    disallowed_losses
+                        .get(&(disposed_lot.lot.lot_number, disposed_lot.when))
+                        .map(|disallowed_loss| {
+                            f64::try_from(options.round(*disallowed_loss)).unwrap()
+                        })
+                        .unwrap_or_default(),
+                );
+            }
+            sheet_writer.append_row(row)?;
+        }
+        Ok(())
     })?;
-    db.record_transfer(
-        signature,
-        last_valid_block_height,
-        Some(redelegated_amount),
-        from_address,
-        MaybeToken::SOL(),
-        into_keypair.pubkey(),
-        MaybeToken::SOL(),
-        lot_selection_method,
-        None,
-    )?;
-
-    if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-        .unwrap_or_default()
-    {
-        db.cancel_transfer(signature)?;
-        db.remove_account(into_keypair.pubkey(), MaybeToken::SOL())?;
-        return Err("Redelegate failed".into());
-    }
-    println!("Redelegation confirmed: {signature}");
-    let when = get_signature_date(rpc_client, signature).await?;
-    db.confirm_transfer(signature, when)?;
 
-    Ok(())
-}
+    let mut current_holdings_rows = vec![];
+    let mut current_holdings_by_year_rows = vec![];
 
-async fn process_account_sync(
-    db: &mut Db,
-    rpc_clients: &RpcClients,
-    address: Option<Pubkey>,
-    max_epochs_to_process: Option<u64>,
-    reconcile_no_sync_account_balances: bool,
-    force_rescan_balances: bool,
-    notifier: &Notifier,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_client = rpc_clients.default();
-    process_account_sync_pending_transfers(db, rpc_client).await?;
-    process_account_sync_sweep(db, rpc_clients, notifier).await?;
+    #[derive(Clone)]
+    enum R {
+        Number(f64),
+        Text(String),
+    }
 
-    let (mut accounts, mut no_sync_accounts): (_, Vec<_>) = match address {
-        Some(address) => {
-            // sync all tokens for the given address...
-            let accounts = db.get_account_tokens(address);
-            if accounts.is_empty() {
-                return Err(format!("{address} does not exist").into());
+    impl ToCellValue for R {
+        fn to_cell_value(&self) -> CellValue {
+            match self {
+                R::Number(x) => x.to_cell_value(),
+                R::Text(x) => x.to_cell_value(),
             }
-            accounts
         }
-        None => db.get_accounts(),
     }
-    .into_iter()
-    .partition(|account| !account.no_sync.unwrap_or_default());
 
-    if reconcile_no_sync_account_balances {
-        for account in no_sync_accounts.iter_mut() {
-            if account.lots.is_empty() {
-                continue;
-            }
-
-            let current_balance = account.token.balance(rpc_client, &account.address)?;
-
-            match current_balance.cmp(&account.last_update_balance) {
-                std::cmp::Ordering::Less => {
-                    println!(
-                        "\nWarning: {} ({}) balance is less than expected. Actual: {}{}, expected: {}{}\n",
-                        account.address,
-                        account.token,
-                        account.token.symbol(),
-                        account.token.ui_amount(current_balance),
-                        account.token.symbol(),
-                        account.token.ui_amount(account.last_update_balance)
-                    );
+    let archived_accounts = db.get_archived_accounts().into_iter().map(|mut account| {
+        account.description = format!("{} (archived)", account.description);
+        account
+    });
+    for account in db.get_accounts().into_iter().chain(archived_accounts) {
+        for lot in account.lots.iter() {
+            let row = (
+                lot.acquisition.when,
+                vec![
+                    R::Text(account.token.to_string()),
+                    R::Number(account.token.ui_amount(lot.amount)),
+                    R::Number(lot.income(account.token).try_into().unwrap()),
+                    R::Text(lot.acquisition.when.to_string()),
+                    R::Text(lot.acquisition.price().to_string()),
+                    R::Text(lot.acquisition.kind.to_string()),
+                    R::Text(account.description.clone()),
+                    R::Text(account.address.to_string()),
+                ],
+            );
+            current_holdings_rows.push(row.clone());
+            if let Some(year) = filter_by_year {
+                if lot.acquisition.when.year() == year {
+                    current_holdings_by_year_rows.push(row);
+                    continue;
                 }
-                std::cmp::Ordering::Greater => {
-                    // sort by lowest basis
-                    account
-                        .lots
-                        .sort_by(|a, b| a.acquisition.price().cmp(&b.acquisition.price()));
-
-                    let lowest_basis_lot = &mut account.lots[0];
-                    let additional_balance = current_balance - account.last_update_balance;
-                    lowest_basis_lot.amount += additional_balance;
-
-                    let msg = format!(
-                        "{} ({}): Additional {}{} added",
-                        account.address,
-                        account.token,
-                        account.token.symbol(),
-                        account.token.ui_amount(additional_balance)
-                    );
-                    notifier.send(&msg).await;
-                    println!("{msg}");
+            }
+        }
+    }
 
-                    account.last_update_balance = current_balance;
-                    db.update_account(account.clone())?;
+    for open_order in db.open_orders(None, Some(OrderSide::Sell)) {
+        for lot in open_order.lots.iter() {
+            let row = (
+                lot.acquisition.when,
+                vec![
+                    R::Text(open_order.token.to_string()),
+                    R::Number(open_order.token.ui_amount(lot.amount)),
+                    R::Number(lot.income(open_order.token).try_into().unwrap()),
+                    R::Text(lot.acquisition.when.to_string()),
+                    R::Text(lot.acquisition.price().to_string()),
+                    R::Text(lot.acquisition.kind.to_string()),
+                    R::Text(format!(
+                        "Open Order: {:?} {}",
+                        open_order.exchange, open_order.pair
+                    )),
+                    R::Text(open_order.deposit_address.to_string()),
+                ],
+            );
+            current_holdings_rows.push(row.clone());
+            if let Some(year) = filter_by_year {
+                if lot.acquisition.when.year() == year {
+                    current_holdings_by_year_rows.push(row);
+                    continue;
                 }
-                _ => {}
             }
         }
     }
+    current_holdings_rows.sort_by_key(|row| row.0);
+    current_holdings_by_year_rows.sort_by_key(|row| row.0);
 
-    let current_sol_price = MaybeToken::SOL().get_current_price(rpc_client).await?;
-
-    let addresses: Vec<Pubkey> = accounts
-        .iter()
-        .map(|TrackedAccount { address, .. }| *address)
-        .collect::<Vec<_>>();
+    let mut write_holdings = |name: String, rows: Vec<(_, Vec<R>)>| {
+        let mut sheet = workbook.create_sheet(&name);
 
-    let epoch_info = rpc_client.get_epoch_info()?;
-    let mut stop_epoch = epoch_info.epoch.saturating_sub(1);
+        sheet.add_column(Column { width: 12. });
+        sheet.add_column(Column { width: 15. });
+        sheet.add_column(Column { width: 12. });
+        sheet.add_column(Column { width: 12. });
+        sheet.add_column(Column { width: 10. });
+        sheet.add_column(Column { width: 40. });
+        sheet.add_column(Column { width: 40. });
+        sheet.add_column(Column { width: 50. });
 
-    let start_epoch = accounts
-        .iter()
-        .map(
-            |TrackedAccount {
-                 last_update_epoch, ..
-             }| last_update_epoch,
-        )
-        .min()
-        .unwrap_or(&stop_epoch)
-        + 1;
+        workbook.write_sheet(&mut sheet, |sheet_writer| {
+            sheet_writer.append_row(row![
+                "Token",
+                "Amount",
+                "Income (USD)",
+                "Acq. Date",
+                "Acq. Price (USD)",
+                "Acquisition Description",
+                "Account Description",
+                "Account Address"
+            ])?;
 
-    if start_epoch > stop_epoch && !force_rescan_balances {
-        println!("Processed up to epoch {stop_epoch}");
-        return Ok(());
-    }
+            for (_, row) in rows {
+                sheet_writer.append_row(Row::from_iter(row.into_iter()))?;
+            }
 
-    if let Some(max_epochs_to_process) = max_epochs_to_process {
-        if max_epochs_to_process == 0 && !force_rescan_balances {
-            return Ok(());
-        }
-        stop_epoch = stop_epoch.min(start_epoch.saturating_add(max_epochs_to_process - 1));
+            Ok(())
+        })
+    };
+    if let Some(year) = filter_by_year {
+        write_holdings(
+            format!("Holdings acquired in {year}"),
+            current_holdings_by_year_rows,
+        )?;
     }
+    write_holdings("All Holdings".to_string(), current_holdings_rows)?;
 
-    // Look for inflationary rewards
-    for epoch in start_epoch..=stop_epoch {
-        let msg = format!("Processing epoch: {epoch}");
-        notifier.send(&msg).await;
-        println!("{msg}");
+    // The realized gains table of `account ls`, by estimated tax payment period
+    let mut sheet = workbook.create_sheet("Realized Gains");
+    sheet.add_column(Column { width: 8. });
+    sheet.add_column(Column { width: 8. });
+    sheet.add_column(Column { width: 15. });
+    sheet.add_column(Column { width: 20. });
+    sheet.add_column(Column { width: 20. });
+    sheet.add_column(Column { width: 15. });
 
-        let inflation_rewards = rpc_client.get_inflation_reward(&addresses, Some(epoch))?;
+    let tax_rate = db.get_tax_rate();
+    workbook.write_sheet(&mut sheet, |sheet_writer| {
+        sheet_writer.append_row(row![
+            "Year",
+            "Period",
+            "Income (USD)",
+            "Short-term Gain (USD)",
+            "Long-term Gain (USD)",
+            "Estimated Tax (USD)"
+        ])?;
 
-        for (inflation_reward, address, account) in
-            itertools::izip!(inflation_rewards, addresses.iter(), accounts.iter_mut(),)
-        {
-            assert_eq!(*address, account.address);
-            if account.last_update_epoch >= epoch {
+        for (year, annual_realized_gain) in annual_realized_gains(db) {
+            if filter_by_year.is_some_and(|filter_by_year| filter_by_year as usize != year) {
                 continue;
             }
-
-            if let Some(inflation_reward) = inflation_reward {
-                assert!(!account.token.is_token()); // Only SOL accounts can receive inflationary rewards
-
-                account.last_update_balance += inflation_reward.amount;
-
-                let slot = inflation_reward.effective_slot;
-                let (when, price) =
-                    get_block_date_and_price(rpc_client, slot, account.token).await?;
-                let lot = Lot {
-                    lot_number: db.next_lot_number(),
-                    acquisition: LotAcquistion::new(
-                        when,
-                        price,
-                        LotAcquistionKind::EpochReward { epoch, slot },
+            for (p, realized_gain) in annual_realized_gain.by_payment_period.iter().enumerate() {
+                if *realized_gain == RealizedGain::default() {
+                    continue;
+                }
+                let mut row = row![
+                    year.to_string(),
+                    format!("P{}", p + 1),
+                    f64::try_from(options.round(realized_gain.income)).unwrap(),
+                    f64::try_from(options.round(realized_gain.short_term_cap_gain)).unwrap(),
+                    f64::try_from(options.round(realized_gain.long_term_cap_gain)).unwrap()
+                ];
+                match tax_rate {
+                    Some(tax_rate) => row.add_cell(
+                        f64::try_from(options.round(realized_gain.estimated_tax(tax_rate)))
+                            .unwrap(),
                     ),
-                    amount: inflation_reward.amount,
-                };
-
-                let msg = format!("{}: {}", account.address, account.description);
-                notifier.send(&msg).await;
-                println!("{msg}");
-
-                maybe_println_lot(
-                    account.token,
-                    &lot,
-                    Some(current_sol_price),
-                    None,
-                    &mut 0.,
-                    &mut 0.,
-                    &mut 0.,
-                    &mut false,
-                    &mut 0.,
-                    Some(notifier),
-                    true,
-                    true,
-                )
-                .await;
-                account.lots.push(lot);
+                    None => row.add_cell("-"),
+                }
+                sheet_writer.append_row(row)?;
             }
         }
-    }
-
-    // Look for unexpected balance changes (such as transaction and rent rewards)
-    for account in accounts.iter_mut() {
-        account.last_update_epoch = stop_epoch;
-
-        let current_balance = account.token.balance(rpc_client, &account.address)?;
-        if current_balance < account.last_update_balance {
-            println!(
-                "\nWarning: {} ({}) balance is less than expected. Actual: {}{}, expected: {}{}\n",
-                account.address,
-                account.token,
-                account.token.symbol(),
-                account.token.ui_amount(current_balance),
-                account.token.symbol(),
-                account.token.ui_amount(account.last_update_balance)
-            );
-        } else if current_balance > account.last_update_balance + account.token.amount(0.005) {
-            let slot = epoch_info.absolute_slot;
-            let current_token_price = account.token.get_current_price(rpc_client).await?;
-            let (when, decimal_price) =
-                get_block_date_and_price(rpc_client, slot, account.token).await?;
-            let amount = current_balance - account.last_update_balance;
-
-            let lot = Lot {
-                lot_number: db.next_lot_number(),
-                acquisition: LotAcquistion::new(
-                    when,
-                    decimal_price,
-                    LotAcquistionKind::NotAvailable,
-                ),
-                amount,
-            };
-
-            let msg = format!(
-                "{} ({}): {}",
-                account.address, account.token, account.description
-            );
-            notifier.send(&msg).await;
-            println!("{msg}");
-
-            maybe_println_lot(
-                account.token,
-                &lot,
-                Some(current_token_price),
-                None,
-                &mut 0.,
-                &mut 0.,
-                &mut 0.,
-                &mut false,
-                &mut 0.,
-                Some(notifier),
-                true,
-                true,
-            )
-            .await;
-            account.lots.push(lot);
-            account.last_update_balance = current_balance;
-        }
+        Ok(())
+    })?;
 
-        db.update_account(account.clone())?;
-    }
+    workbook.close()?;
+    println!("Wrote {outfile}");
 
     Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
-async fn process_account_wrap<T: Signers>(
+async fn process_account_merge<T: Signers>(
     db: &mut Db,
     rpc_clients: &RpcClients,
-    address: Pubkey,
-    amount: Amount,
-    if_source_balance_exceeds: Option<u64>,
-    lot_selection_method: LotSelectionMethod,
-    lot_numbers: Option<HashSet<usize>>,
+    from_address: Pubkey,
+    into_address: Pubkey,
     authority_address: Pubkey,
     signers: T,
+    durable_nonce: Option<DurableNonce>,
     priority_fee: PriorityFee,
+    existing_signature: Option<Signature>,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let rpc_client = rpc_clients.default();
-    let sol = MaybeToken::SOL();
-    let wsol = Token::wSOL;
-    let wsol_address = wsol.ata(&address);
-
-    let from_account = db
-        .get_account(address, sol)
-        .ok_or_else(|| format!("SOL account does not exist for {address}"))?;
-    let amount = amount.unwrap_or(from_account.last_update_balance);
+    let token = MaybeToken::SOL(); // TODO: Support merging tokens one day
 
-    if let Some(if_source_balance_exceeds) = if_source_balance_exceeds {
-        if from_account.last_update_balance < if_source_balance_exceeds {
-            println!(
-                "wrap declined because {} balance is less than {}{}",
-                address,
-                sol.symbol(),
-                sol.ui_amount(if_source_balance_exceeds)
-            );
-            return Ok(());
-        }
-    }
+    if let Some(existing_signature) = existing_signature {
+        db.record_transfer(
+            existing_signature,
+            0, /*last_valid_block_height*/
+            None,
+            from_address,
+            token,
+            into_address,
+            token,
+            LotSelectionMethod::default(),
+            None,
+        )?;
+    } else {
+        let (recent_blockhash, last_valid_block_height) =
+            get_recent_blockhash(rpc_clients, durable_nonce.as_ref())?;
 
-    if amount == 0 {
-        println!("Nothing to wrap");
-        return Ok(());
-    }
+        let from_account = rpc_client
+            .get_account_with_commitment(&from_address, rpc_client.commitment())?
+            .value
+            .ok_or_else(|| format!("From account, {from_address}, does not exist"))?;
 
-    if db.get_account(address, wsol.into()).is_none() {
-        let epoch = rpc_client.get_epoch_info()?.epoch;
-        db.add_account(TrackedAccount {
-            address,
-            token: wsol.into(),
-            description: from_account.description,
-            last_update_epoch: epoch,
-            last_update_balance: 0,
-            lots: vec![],
-            no_sync: None,
-        })?;
-    }
+        let from_tracked_account = db
+            .get_account(from_address, token)
+            .ok_or_else(|| format!("Account, {from_address}, is not tracked"))?;
 
-    let (recent_blockhash, last_valid_block_height) =
-        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+        let into_account = rpc_client
+            .get_account_with_commitment(&into_address, rpc_client.commitment())?
+            .value
+            .ok_or_else(|| format!("From account, {into_address}, does not exist"))?;
 
-    let mut instructions = vec![];
-    instructions.extend([
-        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-            &authority_address,
-            &address,
-            &wsol.mint(),
-            &spl_token::id(),
-        ),
-        system_instruction::transfer(&address, &wsol_address, amount),
-        spl_token::instruction::sync_native(&spl_token::id(), &wsol_address).unwrap(),
-    ]);
+        let authority_account = if from_address == authority_address {
+            from_account.clone()
+        } else {
+            rpc_client
+                .get_account_with_commitment(&authority_address, rpc_client.commitment())?
+                .value
+                .ok_or_else(|| format!("Authority account, {authority_address}, does not exist"))?
+        };
 
-    apply_priority_fee(rpc_clients, &mut instructions, 30_000, priority_fee)?;
-    let message = Message::new(&instructions, Some(&authority_address));
+        let amount = from_tracked_account.last_update_balance;
 
-    let mut transaction = Transaction::new_unsigned(message);
-    transaction.message.recent_blockhash = recent_blockhash;
-    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
-    if simulation_result.err.is_some() {
-        return Err(format!("Simulation failure: {simulation_result:?}").into());
-    }
+        let mut instructions = if from_account.owner == solana_sdk::stake::program::id()
+            && into_account.owner == solana_sdk::stake::program::id()
+        {
+            solana_sdk::stake::instruction::merge(&into_address, &from_address, &authority_address)
+        } else if from_account.owner == solana_sdk::stake::program::id()
+            && into_account.owner == system_program::id()
+        {
+            vec![solana_sdk::stake::instruction::withdraw(
+                &from_address,
+                &authority_address,
+                &into_address,
+                amount,
+                None,
+            )]
+        } else {
+            return Err(format!(
+                "Unsupported merge from {} account to {} account",
+                from_account.owner, into_account.owner
+            )
+            .into());
+        };
+        apply_priority_fee(
+            rpc_clients,
+            &mut instructions,
+            10_000 + if durable_nonce.is_some() { 1_000 } else { 0 },
+            priority_fee,
+        )?;
+        if let Some(durable_nonce) = durable_nonce.as_ref() {
+            durable_nonce.advance(&mut instructions);
+        }
 
-    println!("Wrapping {} for {}", wsol.ui_amount(amount), address);
+        println!("Merging {from_address} into {into_address}");
+        if from_address != authority_address {
+            println!("Authority address: {authority_address}");
+        }
 
-    transaction.try_sign(&signers, recent_blockhash)?;
+        let mut message = Message::new(&instructions, Some(&authority_address));
+        message.recent_blockhash = recent_blockhash;
+        if rpc_client.get_fee_for_message(&message)? > authority_account.lamports {
+            return Err("Insufficient funds for transaction fee".into());
+        }
 
-    let signature = transaction.signatures[0];
-    println!("Transaction signature: {signature}");
+        let mut transaction = Transaction::new_unsigned(message);
+        let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+        if simulation_result.err.is_some() {
+            return Err(format!("Simulation failure: {simulation_result:?}").into());
+        }
 
-    db.record_transfer(
-        signature,
-        last_valid_block_height,
-        Some(amount),
-        address,
-        sol,
-        address,
-        wsol.into(),
-        lot_selection_method,
-        lot_numbers,
-    )?;
+        if verbose {
+            println_transaction_cost(
+                rpc_client,
+                db,
+                &VersionedMessage::Legacy(transaction.message.clone()),
+            )?;
+        }
 
-    if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-        .unwrap_or_default()
-    {
-        db.cancel_transfer(signature)?;
-        return Err("Wrap failed".into());
-    }
-    println!("Wrap confirmed: {signature}");
-    let when = get_signature_date(rpc_client, signature).await?;
-    db.confirm_transfer(signature, when)?;
+        if let Some(durable_nonce) = durable_nonce.as_ref() {
+            transaction.try_partial_sign(&[durable_nonce.authority.as_ref()], recent_blockhash)?;
+        }
+        transaction.try_sign(&signers, recent_blockhash)?;
+        let signature = transaction.signatures[0];
+        println!("Transaction signature: {signature}");
+
+        db.record_transfer(
+            signature,
+            last_valid_block_height,
+            Some(amount),
+            from_address,
+            token,
+            into_address,
+            token,
+            LotSelectionMethod::default(),
+            None,
+        )?;
 
+        let status =
+            send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+        record_sent_transaction(db, PriorityFeeCategory::Stake, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                db.cancel_transfer(signature)?;
+            }
+            return Err("Merge failed".into());
+        }
+        let when = get_signature_date(rpc_client, signature).await?;
+        db.confirm_transfer(signature, when)?;
+        db.remove_account(from_address, token)?;
+    }
     Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
-async fn process_account_unwrap<T: Signers>(
+async fn process_account_sweep<T: Signers>(
     db: &mut Db,
     rpc_clients: &RpcClients,
-    address: Pubkey,
-    amount: Option<u64>,
-    lot_selection_method: LotSelectionMethod,
-    lot_numbers: Option<HashSet<usize>>,
-    authority_address: Pubkey,
+    from_address: Pubkey,
+    token: MaybeToken,
+    retain_amount: u64,
+    exact_amount: Option<u64>,
+    no_sweep_ok: bool,
+    from_authority_address: Pubkey,
     signers: T,
+    durable_nonce: Option<DurableNonce>,
+    to_address: Option<Pubkey>,
+    notifier: &Notifier,
     priority_fee: PriorityFee,
+    existing_signature: Option<Signature>,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let rpc_client = rpc_clients.default();
-    let sol = MaybeToken::SOL();
-    let wsol = Token::wSOL;
-
-    let from_account = db
-        .get_account(address, wsol.into())
-        .ok_or_else(|| format!("Wrapped SOL account does not exist for {address}"))?;
-    let amount = amount.unwrap_or(from_account.last_update_balance);
-
-    let _to_account = db
-        .get_account(address, sol)
-        .ok_or_else(|| format!("SOL account does not exist for {address}"))?;
 
     let (recent_blockhash, last_valid_block_height) =
-        rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
-
-    let ephemeral_token_account = Keypair::new();
+        get_recent_blockhash(rpc_clients, durable_nonce.as_ref())?;
+    let fee_calculator = get_deprecated_fee_calculator(rpc_client)?;
 
-    let mut instructions = vec![
-        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-            &authority_address,
-            &ephemeral_token_account.pubkey(),
-            &wsol.mint(),
-            &spl_token::id(),
-        ),
-        spl_token::instruction::transfer_checked(
-            &spl_token::id(),
-            &wsol.ata(&address),
-            &wsol.mint(),
-            &wsol.ata(&ephemeral_token_account.pubkey()),
-            &authority_address,
-            &[],
-            amount,
-            wsol.decimals(),
-        )
-        .unwrap(),
-        spl_token::instruction::close_account(
-            &spl_token::id(),
-            &wsol.ata(&ephemeral_token_account.pubkey()),
-            &address,
-            &ephemeral_token_account.pubkey(),
-            &[],
-        )
-        .unwrap(),
-    ];
-    apply_priority_fee(rpc_clients, &mut instructions, 30_000, priority_fee)?;
+    let from_account = rpc_client
+        .get_account_with_commitment(&from_address, rpc_client.commitment())?
+        .value
+        .ok_or_else(|| format!("Account, {from_address}, does not exist"))?;
 
-    let message = Message::new(&instructions, Some(&authority_address));
+    let from_tracked_account = db
+        .get_account(from_address, token)
+        .ok_or_else(|| format!("Account, {from_address}, is not tracked"))?;
 
-    let mut transaction = Transaction::new_unsigned(message);
-    transaction.message.recent_blockhash = recent_blockhash;
-    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
-    if simulation_result.err.is_some() {
-        return Err(format!("Simulation failure: {simulation_result:?}").into());
-    }
+    let authority_account = if from_address == from_authority_address {
+        from_account.clone()
+    } else {
+        rpc_client
+            .get_account_with_commitment(&from_authority_address, rpc_client.commitment())?
+            .value
+            .ok_or_else(|| format!("Authority account, {from_authority_address}, does not exist"))?
+    };
 
-    println!("Unwrapping {} for {}", wsol.ui_amount(amount), address);
+    let mut num_transaction_signatures = 1; // from_address_authority
 
-    transaction.partial_sign(&signers, recent_blockhash);
-    transaction.try_sign(&[&ephemeral_token_account], recent_blockhash)?;
+    let (to_address, via_transitory_stake) = if let Some(to_address) = to_address {
+        let _ = db
+            .get_account(to_address, token)
+            .ok_or_else(|| format!("Account {to_address} ({token}) does not exist"))?;
+        (to_address, None)
+    } else {
+        if !token.is_sol() {
+            return Err("--to <ADDRESS> must be provided for token sweeps".into());
+        }
 
-    let signature = transaction.signatures[0];
-    println!("Transaction signature: {signature}");
+        if existing_signature.is_some() {
+            return Err("--signature only supported for token sweeps".into());
+        }
 
-    db.record_transfer(
-        signature,
-        last_valid_block_height,
-        Some(amount),
-        address,
-        wsol.into(),
-        address,
-        sol,
-        lot_selection_method,
-        lot_numbers,
-    )?;
+        let transitory_stake_account = Keypair::new();
 
-    if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-        .unwrap_or_default()
-    {
-        db.cancel_transfer(signature)?;
-        return Err("Wrap failed".into());
-    }
-    println!("Unwrap confirmed: {signature}");
-    let when = get_signature_date(rpc_client, signature).await?;
-    db.confirm_transfer(signature, when)?;
+        let sweep_stake_account = db
+            .get_sweep_stake_account()
+            .ok_or("Sweep stake account not configured")?;
+        let sweep_stake_authority_keypair = read_keypair_file(&sweep_stake_account.stake_authority)
+            .map_err(|err| {
+                format!(
+                    "Failed to read {}: {}",
+                    sweep_stake_account.stake_authority.display(),
+                    err
+                )
+            })?;
 
-    Ok(())
-}
+        num_transaction_signatures += 1; // transitory_stake_account
+        if from_authority_address != sweep_stake_authority_keypair.pubkey() {
+            num_transaction_signatures += 1;
+        }
 
-async fn process_account_sync_pending_transfers(
-    db: &mut Db,
-    rpc_client: &RpcClient,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let block_height = rpc_client.get_epoch_info()?.block_height;
-    for PendingTransfer {
-        signature,
-        last_valid_block_height,
-        ..
-    } in db.pending_transfers()
+        (
+            transitory_stake_account.pubkey(),
+            Some((
+                transitory_stake_account,
+                sweep_stake_authority_keypair,
+                sweep_stake_account.address,
+            )),
+        )
+    };
+
+    if authority_account.lamports
+        < num_transaction_signatures * fee_calculator.lamports_per_signature
     {
-        let status = rpc_client.get_signature_status_with_commitment_and_history(
-            &signature,
-            rpc_client.commitment(),
-            true,
-        )?;
-        match status {
-            Some(result) => {
-                if result.is_ok() {
-                    println!("Pending transfer confirmed: {signature}");
-                    let when = get_signature_date(rpc_client, signature).await?;
-                    db.confirm_transfer(signature, when)?;
-                } else {
-                    println!("Pending transfer failed with {result:?}: {signature}");
-                    db.cancel_transfer(signature)?;
-                }
-            }
-            None => {
-                if block_height > last_valid_block_height {
-                    println!("Pending transfer cancelled: {signature}");
-                    db.cancel_transfer(signature)?;
-                } else {
-                    println!(
-                        "Transfer pending for at most {} blocks: {}",
-                        last_valid_block_height.saturating_sub(block_height),
-                        signature
-                    );
-                }
-            }
-        }
+        return Err(format!(
+            "Authority has insufficient funds for the transaction fee of {}",
+            token.ui_amount(num_transaction_signatures * fee_calculator.lamports_per_signature)
+        )
+        .into());
     }
-    Ok(())
-}
 
-async fn process_account_sync_sweep(
-    db: &mut Db,
-    rpc_clients: &RpcClients,
-    _notifier: &Notifier,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let rpc_client = rpc_clients.default();
-    let token = MaybeToken::SOL();
+    let apply_exact_amount = |amount: u64| -> Result<u64, Box<dyn std::error::Error>> {
+        if let Some(exact_amount) = exact_amount {
+            if exact_amount > amount {
+                Err(format!("Account has insufficient balance: {}", from_address).into())
+            } else {
+                Ok(exact_amount)
+            }
+        } else {
+            Ok(amount)
+        }
+    };
 
-    let transitory_sweep_stake_addresses = db.get_transitory_sweep_stake_addresses();
-    if transitory_sweep_stake_addresses.is_empty() {
-        return Ok(());
-    }
+    let mut compute_units = 7_000;
+    let mut ata_rent = 0;
+    let (mut instructions, sweep_amount) = if token.is_sol() {
+        let sol_reserve = db.sol_reserve(from_address);
+        if from_account.lamports < from_tracked_account.last_update_balance {
+            println!(
+                "Warning: {}: On-chain account balance ({}) less than tracked balance ({})",
+                from_address,
+                token.ui_amount(from_account.lamports),
+                token.ui_amount(from_tracked_account.last_update_balance)
+            );
+        }
 
-    let sweep_stake_account_info = db
-        .get_sweep_stake_account()
-        .ok_or("Sweep stake account is not configured")?;
+        if from_account.owner == system_program::id() {
+            let lamports = apply_exact_amount(if from_address == from_authority_address {
+                from_tracked_account.last_update_balance.saturating_sub(
+                    num_transaction_signatures * fee_calculator.lamports_per_signature
+                        + retain_amount.max(sol_reserve),
+                )
+            } else {
+                from_tracked_account
+                    .last_update_balance
+                    .saturating_sub(retain_amount.max(sol_reserve))
+            })?;
 
-    let sweep_stake_account_authority_keypair =
-        read_keypair_file(&sweep_stake_account_info.stake_authority).map_err(|err| {
-            format!(
-                "Failed to read {}: {}",
-                sweep_stake_account_info.stake_authority.display(),
-                err
+            (
+                vec![system_instruction::transfer(
+                    &from_address,
+                    &to_address,
+                    lamports,
+                )],
+                lamports,
             )
-        })?;
+        } else if from_account.owner == solana_vote_program::id() {
+            let minimum_balance = rpc_client.get_minimum_balance_for_rent_exemption(
+                solana_vote_program::vote_state::VoteState::size_of(),
+            )?;
 
-    let sweep_stake_account = rpc_client
-        .get_account_with_commitment(&sweep_stake_account_info.address, rpc_client.commitment())?
-        .value
-        .ok_or("Sweep stake account does not exist")?;
+            let lamports = apply_exact_amount(
+                from_tracked_account
+                    .last_update_balance
+                    .saturating_sub((minimum_balance + retain_amount).max(sol_reserve)),
+            )?;
 
-    let sweep_stake_activation = rpc_client
-        .get_stake_activation(sweep_stake_account_info.address, None)
-        .map_err(|err| {
-            format!(
-                "Unable to get activation information for sweep stake account: {}: {}",
-                sweep_stake_account_info.address, err
+            (
+                vec![solana_vote_program::vote_instruction::withdraw(
+                    &from_address,
+                    &from_authority_address,
+                    lamports,
+                    &to_address,
+                )],
+                lamports,
             )
-        })?;
+        } else if from_account.owner == solana_sdk::stake::program::id() {
+            let lamports = apply_exact_amount(
+                from_tracked_account
+                    .last_update_balance
+                    .saturating_sub(retain_amount.max(sol_reserve)),
+            )?;
 
-    if sweep_stake_activation.state != StakeActivationState::Active {
-        println!(
-            "Sweep stake account is not active, unable to continue: {sweep_stake_activation:?}"
-        );
-        return Ok(());
-    }
+            (
+                vec![solana_sdk::stake::instruction::withdraw(
+                    &from_address,
+                    &from_authority_address,
+                    &to_address,
+                    lamports,
+                    None,
+                )],
+                lamports,
+            )
+        } else {
+            return Err(format!("Unsupported `from` account owner: {}", from_account.owner).into());
+        }
+    } else {
+        let token = token.token().unwrap();
 
-    for transitory_sweep_stake_address in transitory_sweep_stake_addresses {
-        println!("Considering merging transitory stake {transitory_sweep_stake_address}");
+        let amount = apply_exact_amount(
+            from_tracked_account
+                .last_update_balance
+                .saturating_sub(retain_amount),
+        )?;
 
-        let transitory_sweep_stake_account = match rpc_client
-            .get_account_with_commitment(&transitory_sweep_stake_address, rpc_client.commitment())?
+        // The withheld fee would leave the destination short of the lots swept into it
+        let fee = token.transfer_fee(rpc_client, amount)?;
+        if fee > 0 {
+            return Err(format!(
+                "{} transfer fee of {} is not supported",
+                token.name(),
+                token.format_amount(fee)
+            )
+            .into());
+        }
+
+        let mut instructions = vec![];
+        if rpc_client
+            .get_account_with_commitment(&token.ata(&to_address), rpc_client.commitment())?
             .value
+            .is_none()
         {
-            None => {
-                println!(
-                    "  Transitory sweep stake account does not exist, removing it: {transitory_sweep_stake_address}"
-                );
-
-                if let Some(tracked_account) = db.get_account(transitory_sweep_stake_address, token)
-                {
-                    if tracked_account.last_update_balance > 0 || !tracked_account.lots.is_empty() {
-                        panic!("Tracked account is not empty: {tracked_account:?}");
+            instructions.push(token.create_ata_instruction(&from_authority_address, &to_address));
+            compute_units += 20_000;
+            ata_rent = token.ata_rent(rpc_client)?;
+        }
+        instructions.push(token.transfer_checked_instruction(
+            &from_address,
+            &to_address,
+            &from_authority_address,
+            amount,
+            fee,
+        ));
 
-                        // TODO: Simulate a transfer to move the lots into the sweep account in
-                        // this case?
-                        /*
-                        let signature = Signature::default();
-                        db.record_transfer(
-                            signature,
-                            None,
-                            transitory_sweep_stake_address,
-                            sweep_stake_account_info.address,
-                            None,
-                        )?;
-                        db.confirm_transfer(signature)?;
-                        */
-                    }
-                }
-                db.remove_transitory_sweep_stake_address(transitory_sweep_stake_address)?;
-                continue;
-            }
-            Some(x) => x,
-        };
+        (instructions, amount)
+    };
 
-        let transient_stake_activation = rpc_client
-            .get_stake_activation(transitory_sweep_stake_address, None)
-            .map_err(|err| {
-                format!(
-                    "Unable to get activation information for transient stake: {transitory_sweep_stake_address}: {err}"
-                )
-            })?;
+    if sweep_amount < token.amount(1.) {
+        let msg = format!(
+            "{} has less than {}1 to sweep ({})",
+            from_address,
+            token.symbol(),
+            token.ui_amount(sweep_amount)
+        );
+        return if no_sweep_ok {
+            println!("{msg}");
+            Ok(())
+        } else {
+            Err(msg.into())
+        };
+    }
 
-        if transient_stake_activation.state != StakeActivationState::Active {
-            println!("  Transitory stake is not yet active: {transient_stake_activation:?}");
-            continue;
+    if existing_signature.is_none() {
+        let fee = num_transaction_signatures * fee_calculator.lamports_per_signature;
+        if token.is_sol() {
+            check_sol_reserve(
+                db,
+                from_address,
+                from_account.lamports,
+                sweep_amount
+                    + if from_address == from_authority_address {
+                        fee
+                    } else {
+                        0
+                    },
+            )?;
         }
+        // `prefund_ata_rent` keeps the reserve of an authority paying rent
+        if ata_rent == 0 && (!token.is_sol() || from_address != from_authority_address) {
+            check_sol_reserve(db, from_authority_address, authority_account.lamports, fee)?;
+        }
+    }
 
-        if !rpc_client_utils::stake_accounts_have_same_credits_observed(
-            &sweep_stake_account,
-            &transitory_sweep_stake_account,
-        )? {
-            println!(
-                "  Transitory stake credits observed mismatch with sweep stake account: {transitory_sweep_stake_address}"
-            );
-            continue;
+    let ata_rent_funder = if ata_rent > 0 && existing_signature.is_none() {
+        prefund_ata_rent(
+            db,
+            rpc_client,
+            &mut instructions,
+            from_authority_address,
+            authority_account.lamports,
+            ata_rent,
+            priority_fee.max_lamports()
+                + (num_transaction_signatures + 1) * fee_calculator.lamports_per_signature,
+        )?
+    } else {
+        None
+    };
+    if ata_rent_funder.is_some() {
+        compute_units += 1_000;
+    }
+    if durable_nonce.is_some() {
+        compute_units += 1_000;
+    }
+
+    println!("From address: {from_address}");
+    if from_address != from_authority_address {
+        println!("Authority address: {from_authority_address}");
+    }
+    println!("Destination address: {to_address}");
+    println!(
+        "Sweep amount: {}{}",
+        token.symbol(),
+        token.ui_amount(sweep_amount)
+    );
+
+    let msg = if let Some((
+        transitory_stake_account,
+        sweep_stake_authority_keypair,
+        sweep_stake_address,
+    )) = via_transitory_stake.as_ref()
+    {
+        assert!(existing_signature.is_none());
+        assert_eq!(to_address, transitory_stake_account.pubkey());
+
+        let (sweep_stake_authorized, sweep_stake_vote_account_address) =
+            rpc_client_utils::get_stake_authorized(rpc_client, *sweep_stake_address)?;
+
+        if sweep_stake_authorized.staker != sweep_stake_authority_keypair.pubkey() {
+            return Err("Stake authority mismatch".into());
         }
-        println!("  Merging into sweep stake account");
 
-        let message = Message::new(
-            &solana_sdk::stake::instruction::merge(
-                &sweep_stake_account_info.address,
-                &transitory_sweep_stake_address,
-                &sweep_stake_account_authority_keypair.pubkey(),
+        instructions.append(&mut vec![
+            system_instruction::allocate(
+                &transitory_stake_account.pubkey(),
+                std::mem::size_of::<solana_sdk::stake::state::StakeStateV2>() as u64,
             ),
-            Some(&sweep_stake_account_authority_keypair.pubkey()),
-        );
-        let mut transaction = Transaction::new_unsigned(message);
-
-        let (recent_blockhash, last_valid_block_height) =
-            rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+            system_instruction::assign(
+                &transitory_stake_account.pubkey(),
+                &solana_sdk::stake::program::id(),
+            ),
+            solana_sdk::stake::instruction::initialize(
+                &transitory_stake_account.pubkey(),
+                &sweep_stake_authorized,
+                &solana_sdk::stake::state::Lockup::default(),
+            ),
+            solana_sdk::stake::instruction::delegate_stake(
+                &transitory_stake_account.pubkey(),
+                &sweep_stake_authority_keypair.pubkey(),
+                &sweep_stake_vote_account_address,
+            ),
+        ]);
+        format!(
+            "Sweeping {}{} from {} into {} (via {})",
+            token.symbol(),
+            token
+                .ui_amount(sweep_amount)
+                .localized_string_with_fixed_place(2),
+            from_address,
+            sweep_stake_address,
+            to_address
+        )
+    } else {
+        format!(
+            "Sweeping {}{} from {} into {}",
+            token.symbol(),
+            token
+                .ui_amount(sweep_amount)
+                .localized_string_with_fixed_place(2),
+            from_address,
+            to_address
+        )
+    };
 
-        transaction.message.recent_blockhash = recent_blockhash;
-        let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
-        if simulation_result.err.is_some() {
-            return Err(format!("Simulation failure: {simulation_result:?}").into());
+    let sign = |transaction: &mut VersionedTransaction| -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ata_rent_funder) = ata_rent_funder.as_ref() {
+            try_partial_sign_transaction(transaction, &[ata_rent_funder])?;
         }
+        if let Some(durable_nonce) = durable_nonce.as_ref() {
+            durable_nonce.partial_sign(transaction)?;
+        }
+        if let Some((transitory_stake_account, sweep_stake_authority_keypair, ..)) =
+            via_transitory_stake.as_ref()
+        {
+            try_partial_sign_transaction(transaction, &signers)?;
+            try_sign_transaction(
+                transaction,
+                &[transitory_stake_account, sweep_stake_authority_keypair],
+            )?;
+        } else {
+            try_sign_transaction(transaction, &signers)?;
+        }
+        Ok(())
+    };
 
-        transaction.sign(&[&sweep_stake_account_authority_keypair], recent_blockhash);
+    let (mut signature, maybe_transaction) = match existing_signature {
+        None => {
+            apply_priority_fee(rpc_clients, &mut instructions, compute_units, priority_fee)?;
+            if let Some(durable_nonce) = durable_nonce.as_ref() {
+                durable_nonce.advance(&mut instructions);
+            }
 
-        let signature = transaction.signatures[0];
-        println!("Transaction signature: {signature}");
-        db.record_transfer(
-            signature,
-            last_valid_block_height,
-            None,
-            transitory_sweep_stake_address,
-            token,
-            sweep_stake_account_info.address,
-            token,
-            LotSelectionMethod::default(),
-            None,
-        )?;
+            let mut transaction = rpc_clients.new_unsigned_transaction(
+                &instructions,
+                &from_authority_address,
+                recent_blockhash,
+            )?;
+            let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+            if simulation_result.err.is_some() {
+                return Err(format!("Simulation failure: {simulation_result:?}").into());
+            }
 
-        if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-            .unwrap_or_default()
-        {
-            db.cancel_transfer(signature)?;
-            return Err("Merge failed".into());
+            if verbose {
+                println_transaction_cost(rpc_client, db, &transaction.message)?;
+            }
+
+            sign(&mut transaction)?;
+
+            let signature = transaction.signatures[0];
+            println!("Transaction signature: {signature}");
+
+            let epoch = rpc_client.get_epoch_info()?.epoch;
+            if let Some((transitory_stake_account, ..)) = via_transitory_stake.as_ref() {
+                assert!(existing_signature.is_none());
+                db.add_transitory_sweep_stake_address(transitory_stake_account.pubkey(), epoch)?;
+            }
+            (signature, Some(transaction))
+        }
+        Some(existing_signature) => (existing_signature, None),
+    };
+    db.record_transfer(
+        signature,
+        last_valid_block_height,
+        Some(sweep_amount),
+        from_address,
+        token,
+        to_address,
+        token,
+        LotSelectionMethod::default(),
+        None,
+    )?;
+
+    if let Some(mut transaction) = maybe_transaction {
+        let status = send_transaction_until_expired_with_fee_escalation(
+            rpc_clients,
+            &mut transaction,
+            last_valid_block_height,
+            priority_fee.max_lamports(),
+            sign,
+            |signature, new_signature, last_valid_block_height| {
+                Ok(db.replace_pending_signature(
+                    signature,
+                    new_signature,
+                    last_valid_block_height,
+                )?)
+            },
+        );
+        signature = transaction.signatures[0];
+        record_sent_transaction(db, PriorityFeeCategory::Sweep, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                db.cancel_transfer(signature)?;
+                if let Some((transitory_stake_account, ..)) = via_transitory_stake.as_ref() {
+                    db.remove_transitory_sweep_stake_address(transitory_stake_account.pubkey())?;
+                }
+            }
+            return Err("Sweep failed".into());
         }
-        let when = get_signature_date(rpc_client, signature).await?;
-        db.confirm_transfer(signature, when)?;
-        db.remove_transitory_sweep_stake_address(transitory_sweep_stake_address)?;
     }
+    println!("Confirming sweep: {signature}");
+    let when = get_signature_date(rpc_client, signature).await?;
+    db.confirm_transfer(signature, when)?;
+
+    notifier
+        .send(NotificationKind::Transfer, Severity::Info, &msg)
+        .await;
+    println!("{msg}");
     Ok(())
 }
 
-fn lot_numbers_of(matches: &ArgMatches<'_>, name: &str) -> Option<HashSet<usize>> {
-    values_t!(matches, name, usize)
-        .ok()
-        .map(|x| x.into_iter().collect())
-}
+#[allow(clippy::too_many_arguments)]
+async fn process_account_split<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    from_address: Pubkey,
+    amount: Option<u64>,
+    description: Option<String>,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    authority_address: Pubkey,
+    signers: T,
+    durable_nonce: Option<DurableNonce>,
+    into_keypair: Option<Keypair>,
+    if_balance_exceeds: Option<f64>,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
 
-fn lot_numbers_arg<'a, 'b>() -> Arg<'a, 'b> {
-    Arg::with_name("lot_numbers")
-        .long("lot")
-        .value_name("LOT NUMBER")
-        .takes_value(true)
-        .multiple(true)
-        .validator(is_parsable::<usize>)
-        .help("Lot to fund the wrap from")
-}
+    // TODO: Support splitting two system accounts? Tokens? Otherwise at least error cleanly when it's attempted
+    let token = MaybeToken::SOL(); // TODO: Support splitting tokens one day
 
-fn lot_selection_arg<'a, 'b>() -> Arg<'a, 'b> {
-    Arg::with_name("lot_selection")
-        .long("lot-selection")
-        .value_name("METHOD")
-        .takes_value(true)
-        .validator(is_parsable::<LotSelectionMethod>)
-        .default_value(POSSIBLE_LOT_SELECTION_METHOD_VALUES[0])
-        .possible_values(POSSIBLE_LOT_SELECTION_METHOD_VALUES)
-        .help("Lot selection method")
-}
+    let (recent_blockhash, last_valid_block_height) =
+        get_recent_blockhash(rpc_clients, durable_nonce.as_ref())?;
 
-fn is_tax_rate(s: String) -> Result<(), String> {
-    is_parsable::<f64>(s.clone())?;
-    let f = s.parse::<f64>().unwrap();
-    if (0. ..=1.).contains(&f) {
-        Ok(())
-    } else {
-        Err(format!("rate must be in the range [0,1]: {f}"))
+    let into_keypair = into_keypair.unwrap_or_else(Keypair::new);
+    if db.get_account(into_keypair.pubkey(), token).is_some() {
+        return Err(format!(
+            "Account {} ({}) already exists",
+            into_keypair.pubkey(),
+            token
+        )
+        .into());
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let from_account = db
+        .get_account(from_address, MaybeToken::SOL())
+        .ok_or_else(|| format!("SOL account does not exist for {from_address}"))?;
+
+    let (split_all, amount, description) = match amount {
+        None => (
+            true,
+            from_account.last_update_balance,
+            description.unwrap_or(from_account.description),
+        ),
+        Some(amount) => (
+            false,
+            amount,
+            description.unwrap_or_else(|| format!("Split at {}", Local::now())),
+        ),
+    };
+
+    if let Some(if_balance_exceeds) = if_balance_exceeds {
+        if token.ui_amount(amount) < if_balance_exceeds {
+            println!(
+                "Split declined because {:?} balance is less than {}",
+                from_address,
+                token.format_ui_amount(if_balance_exceeds)
+            );
+            return Ok(());
+        }
+    }
+
+    let minimum_stake_account_balance = rpc_client
+        .get_minimum_balance_for_rent_exemption(solana_sdk::stake::state::StakeStateV2::size_of())?;
+
+    let mut instructions = vec![];
+    apply_priority_fee(
+        rpc_clients,
+        &mut instructions,
+        10_000 + if durable_nonce.is_some() { 1_000 } else { 0 },
+        priority_fee,
+    )?;
+
+    instructions.push(system_instruction::transfer(
+        &authority_address,
+        &into_keypair.pubkey(),
+        minimum_stake_account_balance,
+    ));
+    instructions.append(&mut solana_sdk::stake::instruction::split(
+        &from_address,
+        &authority_address,
+        amount,
+        &into_keypair.pubkey(),
+    ));
+    if let Some(durable_nonce) = durable_nonce.as_ref() {
+        durable_nonce.advance(&mut instructions);
+    }
+
+    let mut transaction = rpc_clients.new_unsigned_transaction(
+        &instructions,
+        &authority_address,
+        recent_blockhash,
+    )?;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(rpc_client, db, &transaction.message)?;
+    }
+
+    println!(
+        "Splitting {} from {} into {}",
+        token.ui_amount(amount),
+        from_address,
+        into_keypair.pubkey(),
+    );
+
+    let sign = |transaction: &mut VersionedTransaction| -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(durable_nonce) = durable_nonce.as_ref() {
+            durable_nonce.partial_sign(transaction)?;
+        }
+        try_partial_sign_transaction(transaction, &signers)?;
+        try_sign_transaction(transaction, &[&into_keypair])?;
+        Ok(())
+    };
+    sign(&mut transaction)?;
+
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
+
+    let epoch = rpc_client.get_epoch_info()?.epoch;
+    db.add_account(TrackedAccount {
+        address: into_keypair.pubkey(),
+        token,
+        description,
+        last_update_epoch: epoch.saturating_sub(1),
+        last_update_balance: 0,
+        lots: vec![],
+        no_sync: from_account.no_sync,
+        last_activity: None,
+    })?;
+    db.record_transfer(
+        signature,
+        last_valid_block_height,
+        Some(amount),
+        from_address,
+        token,
+        into_keypair.pubkey(),
+        token,
+        lot_selection_method,
+        lot_numbers,
+    )?;
+
+    let status = send_transaction_until_expired_with_fee_escalation(
+        rpc_clients,
+        &mut transaction,
+        last_valid_block_height,
+        priority_fee.max_lamports(),
+        sign,
+        |signature, new_signature, last_valid_block_height| {
+            Ok(db.replace_pending_signature(signature, new_signature, last_valid_block_height)?)
+        },
+    );
+    let signature = transaction.signatures[0];
+    record_sent_transaction(db, PriorityFeeCategory::Stake, &transaction, status).await?;
+    if !status.confirmed() {
+        if status.abandoned() {
+            db.cancel_transfer(signature)?;
+            db.remove_account(into_keypair.pubkey(), MaybeToken::SOL())?;
+        }
+        return Err("Split failed".into());
+    }
+    println!("Split confirmed: {signature}");
+    let when = get_signature_date(rpc_client, signature).await?;
+    db.confirm_transfer(signature, when)?;
+    if split_all {
+        // TODO: This `remove_account` is racy and won't work in all cases. Consider plumbing the
+        // removal through `confirm_transfer` instead
+        let from_account = db.get_account(from_address, MaybeToken::SOL()).unwrap();
+        assert!(from_account.lots.is_empty());
+        db.remove_account(from_address, MaybeToken::SOL())?;
+    }
+    Ok(())
+}
+
+// Create a new stake account funded with `amount` from the system account at `from_address`,
+// delegate it to `vote_account_address`, and move the lots of `amount` into it
+#[allow(clippy::too_many_arguments)]
+async fn process_account_delegate<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    from_address: Pubkey,
+    amount: u64,
+    vote_account_address: Pubkey,
+    description: Option<String>,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    signers: T,
+    stake_keypair: Option<Keypair>,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let token = MaybeToken::SOL();
+
+    let (recent_blockhash, last_valid_block_height) =
+        rpc_clients.get_latest_blockhash_with_commitment()?;
+
+    let stake_keypair = stake_keypair.unwrap_or_else(Keypair::new);
+    if db.get_account(stake_keypair.pubkey(), token).is_some() {
+        return Err(format!(
+            "Account {} ({}) already exists",
+            stake_keypair.pubkey(),
+            token
+        )
+        .into());
+    }
+
+    let from_account = db
+        .get_account(from_address, token)
+        .ok_or_else(|| format!("SOL account does not exist for {from_address}"))?;
+    if from_account.last_update_balance < amount {
+        return Err(format!(
+            "Insufficient balance in {}. Tracked balance is {}",
+            from_address,
+            token.format_amount(from_account.last_update_balance)
+        )
+        .into());
+    }
+    check_sol_reserve(db, from_address, from_account.last_update_balance, amount)?;
+
+    let minimum_stake_account_balance = rpc_client
+        .get_minimum_balance_for_rent_exemption(solana_sdk::stake::state::StakeStateV2::size_of())?;
+    if amount <= minimum_stake_account_balance {
+        return Err(format!(
+            "Amount must be greater than the stake account rent exemption of {}",
+            token.format_amount(minimum_stake_account_balance)
+        )
+        .into());
+    }
+
+    let mut instructions = vec![];
+    apply_priority_fee(rpc_clients, &mut instructions, 10_000, priority_fee)?;
+    instructions.append(
+        &mut solana_sdk::stake::instruction::create_account_and_delegate_stake(
+            &from_address,
+            &stake_keypair.pubkey(),
+            &vote_account_address,
+            &solana_sdk::stake::state::Authorized::auto(&from_address),
+            &solana_sdk::stake::state::Lockup::default(),
+            amount,
+        ),
+    );
+
+    let message = Message::new(&instructions, Some(&from_address));
+
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.message.recent_blockhash = recent_blockhash;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(
+            rpc_client,
+            db,
+            &VersionedMessage::Legacy(transaction.message.clone()),
+        )?;
+    }
+
+    println!(
+        "Delegating {} from {} to {} via new stake account {}",
+        token.format_amount(amount),
+        from_address,
+        vote_account_address,
+        stake_keypair.pubkey(),
+    );
+
+    transaction.partial_sign(&signers, recent_blockhash);
+    transaction.try_sign(&[&stake_keypair], recent_blockhash)?;
+
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
+
+    let epoch = rpc_client.get_epoch_info()?.epoch;
+    db.add_account(TrackedAccount {
+        address: stake_keypair.pubkey(),
+        token,
+        description: description.unwrap_or_else(|| format!("Delegated at {}", Local::now())),
+        last_update_epoch: epoch.saturating_sub(1),
+        last_update_balance: 0,
+        lots: vec![],
+        no_sync: from_account.no_sync,
+        last_activity: None,
+    })?;
+    db.record_transfer(
+        signature,
+        last_valid_block_height,
+        Some(amount),
+        from_address,
+        token,
+        stake_keypair.pubkey(),
+        token,
+        lot_selection_method,
+        lot_numbers,
+    )?;
+
+    let status = send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+    record_sent_transaction(db, PriorityFeeCategory::Stake, &transaction, status).await?;
+    if !status.confirmed() {
+        if status.abandoned() {
+            db.cancel_transfer(signature)?;
+            db.remove_account(stake_keypair.pubkey(), token)?;
+        }
+        return Err("Delegate failed".into());
+    }
+    println!("Delegate confirmed: {signature}");
+    let when = get_signature_date(rpc_client, signature).await?;
+    db.confirm_transfer(signature, when)?;
+    Ok(())
+}
+
+async fn process_account_deactivate<T: Signers>(
+    db: &Db,
+    rpc_clients: &RpcClients,
+    address: Pubkey,
+    authority_address: Pubkey,
+    signers: T,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    let (recent_blockhash, last_valid_block_height) =
+        rpc_clients.get_latest_blockhash_with_commitment()?;
+
+    let mut instructions = vec![solana_sdk::stake::instruction::deactivate_stake(
+        &address,
+        &authority_address,
+    )];
+    apply_priority_fee(rpc_clients, &mut instructions, 10_000, priority_fee)?;
+
+    let message = Message::new(&instructions, Some(&authority_address));
+
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.message.recent_blockhash = recent_blockhash;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(
+            rpc_client,
+            db,
+            &VersionedMessage::Legacy(transaction.message.clone()),
+        )?;
+    }
+
+    println!("Deactivating {address}");
+
+    transaction.try_sign(&signers, recent_blockhash)?;
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
+
+    let status = send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+    record_sent_transaction(db, PriorityFeeCategory::Stake, &transaction, status).await?;
+    if !status.confirmed() {
+        return Err("Deactivate failed".into());
+    }
+    println!("Deactivate confirmed: {signature}");
+    Ok(())
+}
+
+// Withdraw `amount`, or all, of the deactivated stake account at `from_address` into the
+// system account at `to_address`, moving the lots along with it
+#[allow(clippy::too_many_arguments)]
+async fn process_account_withdraw_stake<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    from_address: Pubkey,
+    to_address: Pubkey,
+    amount: Option<u64>,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    authority_address: Pubkey,
+    signers: T,
+    priority_fee: PriorityFee,
+    existing_signature: Option<Signature>,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let token = MaybeToken::SOL();
+
+    let from_tracked_account = db
+        .get_account(from_address, token)
+        .ok_or_else(|| format!("Account, {from_address}, is not tracked"))?;
+    if db.get_account(to_address, token).is_none() {
+        return Err(format!(
+            "Account, {to_address}, is not tracked. Add it with `sys account add {to_address}`"
+        )
+        .into());
+    }
+
+    let (withdraw_all, amount) = match amount {
+        None => (true, from_tracked_account.last_update_balance),
+        Some(amount) => (false, amount),
+    };
+
+    let signature = if let Some(existing_signature) = existing_signature {
+        db.record_transfer(
+            existing_signature,
+            0, /*last_valid_block_height*/
+            Some(amount),
+            from_address,
+            token,
+            to_address,
+            token,
+            lot_selection_method,
+            lot_numbers,
+        )?;
+        existing_signature
+    } else {
+        let (recent_blockhash, last_valid_block_height) =
+            rpc_clients.get_latest_blockhash_with_commitment()?;
+
+        let from_account = rpc_client
+            .get_account_with_commitment(&from_address, rpc_client.commitment())?
+            .value
+            .ok_or_else(|| format!("Account, {from_address}, does not exist"))?;
+        if from_account.owner != solana_sdk::stake::program::id() {
+            return Err(format!("{from_address} is not a stake account").into());
+        }
+        if withdraw_all && from_account.lamports != amount {
+            return Err(format!(
+                "{from_address} balance of {} does not match its tracked balance of {}. \
+                 Run `sys account sync {from_address}` first",
+                token.format_amount(from_account.lamports),
+                token.format_amount(amount),
+            )
+            .into());
+        }
+
+        let mut instructions = vec![solana_sdk::stake::instruction::withdraw(
+            &from_address,
+            &authority_address,
+            &to_address,
+            amount,
+            None,
+        )];
+        apply_priority_fee(rpc_clients, &mut instructions, 10_000, priority_fee)?;
+
+        let message = Message::new(&instructions, Some(&authority_address));
+
+        let mut transaction = Transaction::new_unsigned(message);
+        transaction.message.recent_blockhash = recent_blockhash;
+        let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+        if simulation_result.err.is_some() {
+            return Err(format!("Simulation failure: {simulation_result:?}").into());
+        }
+
+        if verbose {
+            println_transaction_cost(
+                rpc_client,
+                db,
+                &VersionedMessage::Legacy(transaction.message.clone()),
+            )?;
+        }
+
+        println!(
+            "Withdrawing {} from {} into {}",
+            token.format_amount(amount),
+            from_address,
+            to_address
+        );
+
+        transaction.try_sign(&signers, recent_blockhash)?;
+        let signature = transaction.signatures[0];
+        println!("Transaction signature: {signature}");
+
+        db.record_transfer(
+            signature,
+            last_valid_block_height,
+            Some(amount),
+            from_address,
+            token,
+            to_address,
+            token,
+            lot_selection_method,
+            lot_numbers,
+        )?;
+
+        let status =
+            send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+        record_sent_transaction(db, PriorityFeeCategory::Stake, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                db.cancel_transfer(signature)?;
+            }
+            return Err("Withdraw failed".into());
+        }
+        println!("Withdraw confirmed: {signature}");
+        signature
+    };
+
+    let when = get_signature_date(rpc_client, signature).await?;
+    db.confirm_transfer(signature, when)?;
+    if withdraw_all {
+        db.remove_account(from_address, token)?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_account_redelegate<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    from_address: Pubkey,
+    vote_account_address: Pubkey,
+    lot_selection_method: LotSelectionMethod,
+    authority_address: Pubkey,
+    signers: &T,
+    into_keypair: Option<Keypair>,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let (recent_blockhash, last_valid_block_height) =
+        rpc_clients.get_latest_blockhash_with_commitment()?;
+
+    let minimum_stake_account_balance = rpc_client
+        .get_minimum_balance_for_rent_exemption(solana_sdk::stake::state::StakeStateV2::size_of())?;
+
+    let into_keypair = into_keypair.unwrap_or_else(Keypair::new);
+    if db
+        .get_account(into_keypair.pubkey(), MaybeToken::SOL())
+        .is_some()
+    {
+        return Err(format!(
+            "Account {} ({}) already exists",
+            into_keypair.pubkey(),
+            MaybeToken::SOL()
+        )
+        .into());
+    }
+
+    let from_account = db
+        .get_account(from_address, MaybeToken::SOL())
+        .ok_or_else(|| format!("SOL account does not exist for {from_address}"))?;
+
+    if from_account.last_update_balance < minimum_stake_account_balance * 2 {
+        return Err(format!(
+            "Account {} ({}) has insufficient balance",
+            into_keypair.pubkey(),
+            MaybeToken::SOL()
+        )
+        .into());
+    }
+    let redelegated_amount = from_account.last_update_balance - minimum_stake_account_balance;
+
+    let instructions = solana_sdk::stake::instruction::redelegate(
+        &from_address,
+        &authority_address,
+        &vote_account_address,
+        &into_keypair.pubkey(),
+    );
+
+    let message = Message::new(&instructions, Some(&authority_address));
+
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.message.recent_blockhash = recent_blockhash;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(
+            rpc_client,
+            db,
+            &VersionedMessage::Legacy(transaction.message.clone()),
+        )?;
+    }
+
+    println!(
+        "Relegating {} to {} via{}",
+        from_address,
+        vote_account_address,
+        into_keypair.pubkey(),
+    );
+
+    transaction.partial_sign(signers, recent_blockhash);
+    transaction.try_sign(&[&into_keypair], recent_blockhash)?;
+
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
+
+    let epoch = rpc_client.get_epoch_info()?.epoch;
+    db.add_account(TrackedAccount {
+        address: into_keypair.pubkey(),
+        token: MaybeToken::SOL(),
+        description: from_account.description,
+        last_update_epoch: epoch.saturating_sub(1),
+        last_update_balance: 0,
+        lots: vec![],
+        no_sync: None,
+        last_activity: None,
+    })?;
+    db.record_transfer(
+        signature,
+        last_valid_block_height,
+        Some(redelegated_amount),
+        from_address,
+        MaybeToken::SOL(),
+        into_keypair.pubkey(),
+        MaybeToken::SOL(),
+        lot_selection_method,
+        None,
+    )?;
+
+    let status = send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+    record_sent_transaction(db, PriorityFeeCategory::Stake, &transaction, status).await?;
+    if !status.confirmed() {
+        if status.abandoned() {
+            db.cancel_transfer(signature)?;
+            db.remove_account(into_keypair.pubkey(), MaybeToken::SOL())?;
+        }
+        return Err("Redelegate failed".into());
+    }
+    println!("Redelegation confirmed: {signature}");
+    let when = get_signature_date(rpc_client, signature).await?;
+    db.confirm_transfer(signature, when)?;
+
+    Ok(())
+}
+
+// Merging transitory sweep stake accounts submits transactions, so is skipped without the
+// `rpc_clients` to send them through
+#[allow(clippy::too_many_arguments)]
+async fn process_account_sync(
+    db: &mut Db,
+    rpc_client: &dyn ChainClient,
+    rpc_clients: Option<&RpcClients>,
+    address: Option<Pubkey>,
+    max_epochs_to_process: Option<u64>,
+    reconcile_no_sync_account_balances: bool,
+    force_rescan_balances: bool,
+    notifier: &Notifier,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    process_account_sync_pending_transfers(db, rpc_client).await?;
+    match rpc_clients {
+        // A dry run must not submit transactions either
+        Some(rpc_clients) if !db.is_dry_run() => {
+            process_account_sync_sweep(db, rpc_clients, notifier, verbose).await?;
+        }
+        _ => {
+            let transitory_sweep_stake_addresses = db.get_transitory_sweep_stake_addresses();
+            if !transitory_sweep_stake_addresses.is_empty() {
+                println!(
+                    "Skipping merge of {} transitory sweep stake account(s)",
+                    transitory_sweep_stake_addresses.len()
+                );
+            }
+        }
+    }
+
+    let (mut accounts, mut no_sync_accounts): (_, Vec<_>) = match address {
+        Some(address) => {
+            // sync all tokens for the given address...
+            let accounts = db.get_account_tokens(address);
+            if accounts.is_empty() {
+                return Err(format!("{address} does not exist").into());
+            }
+            accounts
+        }
+        None => db.get_accounts(),
+    }
+    .into_iter()
+    .partition(|account| !account.no_sync.unwrap_or_default());
+
+    if reconcile_no_sync_account_balances {
+        for account in no_sync_accounts.iter_mut() {
+            if account.lots.is_empty() {
+                continue;
+            }
+
+            let current_balance = account.token.balance(rpc_client, &account.address)?;
+
+            match current_balance.cmp(&account.last_update_balance) {
+                std::cmp::Ordering::Less => {
+                    let msg = format!(
+                        "Warning: {} ({}) balance is less than expected. Actual: {}{}, expected: {}{}",
+                        account.address,
+                        account.token,
+                        account.token.symbol(),
+                        account.token.ui_amount(current_balance),
+                        account.token.symbol(),
+                        account.token.ui_amount(account.last_update_balance)
+                    );
+                    println!("\n{msg}\n");
+                    notifier
+                        .send(NotificationKind::Balance, Severity::Alert, &msg)
+                        .await;
+                }
+                std::cmp::Ordering::Greater => {
+                    // sort by lowest basis
+                    account
+                        .lots
+                        .sort_by(|a, b| a.acquisition.price().cmp(&b.acquisition.price()));
+
+                    let lowest_basis_lot = &mut account.lots[0];
+                    let additional_balance = current_balance - account.last_update_balance;
+                    lowest_basis_lot.amount += additional_balance;
+
+                    let msg = format!(
+                        "{} ({}): Additional {}{} added",
+                        account.address,
+                        account.token,
+                        account.token.symbol(),
+                        account.token.ui_amount(additional_balance)
+                    );
+                    notifier
+                        .send(NotificationKind::Balance, Severity::Info, &msg)
+                        .await;
+                    println!("{msg}");
+
+                    account.last_update_balance = current_balance;
+                    db.update_account(account.clone())?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    process_account_sync_streams(db, rpc_client, &accounts, notifier).await?;
+
+    let addresses: Vec<Pubkey> = accounts
+        .iter()
+        .map(|TrackedAccount { address, .. }| *address)
+        .collect::<Vec<_>>();
+
+    let epoch_info = rpc_client.get_epoch_info()?;
+    let mut stop_epoch = epoch_info.epoch.saturating_sub(1);
+
+    let start_epoch = accounts
+        .iter()
+        .map(
+            |TrackedAccount {
+                 last_update_epoch, ..
+             }| last_update_epoch,
+        )
+        .min()
+        .unwrap_or(&stop_epoch)
+        + 1;
+
+    if start_epoch > stop_epoch && !force_rescan_balances {
+        println!("Processed up to epoch {stop_epoch}");
+        return Ok(());
+    }
+
+    if let Some(max_epochs_to_process) = max_epochs_to_process {
+        if max_epochs_to_process == 0 && !force_rescan_balances {
+            return Ok(());
+        }
+        stop_epoch = stop_epoch.min(start_epoch.saturating_add(max_epochs_to_process - 1));
+    }
+
+    let current_sol_price = MaybeToken::SOL().get_current_price(rpc_client).await?;
+    let mut prices_fetched = 0;
+
+    // Jito MEV tips are claimed directly into the stake accounts delegated to Jito validators
+    let stake_voters = rpc_client_utils::get_stake_voters(rpc_client, &addresses)?;
+
+    // Look for inflationary rewards
+    let progress = Progress::new(
+        "Epochs processed",
+        (start_epoch..=stop_epoch).count() as u64,
+    );
+    for epoch in start_epoch..=stop_epoch {
+        let msg = format!("Processing epoch: {epoch}");
+        notifier
+            .send(NotificationKind::Sync, Severity::Info, &msg)
+            .await;
+        progress::println(&msg);
+
+        let inflation_rewards = rpc_client.get_inflation_reward(&addresses, Some(epoch))?;
+
+        for (inflation_reward, address, account) in
+            itertools::izip!(inflation_rewards, addresses.iter(), accounts.iter_mut(),)
+        {
+            assert_eq!(*address, account.address);
+            if account.last_update_epoch >= epoch {
+                continue;
+            }
+
+            if let Some(inflation_reward) = inflation_reward {
+                assert!(!account.token.is_token()); // Only SOL accounts can receive inflationary rewards
+
+                account.last_update_balance += inflation_reward.amount;
+
+                let slot = inflation_reward.effective_slot;
+                let (when, price) =
+                    get_block_date_and_price(rpc_client, slot, account.token).await?;
+                prices_fetched += 1;
+                progress.set_message(format!("({prices_fetched} prices fetched)"));
+                let lot = Lot {
+                    lot_number: db.next_lot_number(),
+                    acquisition: LotAcquistion::new(
+                        when,
+                        price,
+                        LotAcquistionKind::EpochReward { epoch, slot },
+                    ),
+                    amount: inflation_reward.amount,
+                };
+
+                let msg = format!("{}: {}", account.address, account.description);
+                notifier
+                    .send(NotificationKind::Reward, Severity::Info, &msg)
+                    .await;
+                progress::println(&msg);
+
+                maybe_println_lot(
+                    account.token,
+                    &lot,
+                    Some(current_sol_price),
+                    None,
+                    None,
+                    &mut Decimal::ZERO,
+                    &mut Decimal::ZERO,
+                    &mut Decimal::ZERO,
+                    &mut false,
+                    &mut Decimal::ZERO,
+                    Some(notifier),
+                    DEFAULT_LOT_COLUMNS,
+                    true,
+                    true,
+                )
+                .await;
+                account.lots.push(lot);
+            }
+        }
+
+        // Look for MEV rewards. The tips of an epoch are claimed during the following one, so a
+        // claim that lands after the epoch was processed is found as an unexpected balance change
+        // instead
+        let (mev_accounts, mev_stake_voters): (Vec<_>, Vec<_>) = accounts
+            .iter()
+            .zip(&stake_voters)
+            .enumerate()
+            .filter_map(|(i, (account, voter))| {
+                let voter = (*voter)?;
+                (account.last_update_epoch < epoch && account.token.is_sol())
+                    .then_some((i, (account.address, voter)))
+            })
+            .unzip();
+        if !mev_stake_voters.is_empty() {
+            let mev_rewards = jito_mev::get_mev_rewards(rpc_client, &mev_stake_voters, epoch)?;
+            for (i, mev_reward) in mev_accounts.into_iter().zip(mev_rewards) {
+                let Some(jito_mev::MevReward { amount, slot }) = mev_reward else {
+                    continue;
+                };
+                let account = &mut accounts[i];
+                account.last_update_balance += amount;
+
+                let (when, price) =
+                    get_block_date_and_price(rpc_client, slot, account.token).await?;
+                prices_fetched += 1;
+                progress.set_message(format!("({prices_fetched} prices fetched)"));
+                let lot = Lot {
+                    lot_number: db.next_lot_number(),
+                    acquisition: LotAcquistion::new(
+                        when,
+                        price,
+                        LotAcquistionKind::MevReward { epoch },
+                    ),
+                    amount,
+                };
+
+                let msg = format!("{}: {} (MEV)", account.address, account.description);
+                notifier
+                    .send(NotificationKind::Reward, Severity::Info, &msg)
+                    .await;
+                progress::println(&msg);
+
+                maybe_println_lot(
+                    account.token,
+                    &lot,
+                    Some(current_sol_price),
+                    None,
+                    None,
+                    &mut Decimal::ZERO,
+                    &mut Decimal::ZERO,
+                    &mut Decimal::ZERO,
+                    &mut false,
+                    &mut Decimal::ZERO,
+                    Some(notifier),
+                    DEFAULT_LOT_COLUMNS,
+                    true,
+                    true,
+                )
+                .await;
+                account.lots.push(lot);
+            }
+        }
+        progress.inc();
+    }
+    drop(progress);
+
+    // Look for unexpected balance changes (such as transaction and rent rewards)
+    let progress = Progress::new("Accounts scanned", accounts.len() as u64);
+    for account in accounts.iter_mut() {
+        account.last_update_epoch = stop_epoch;
+
+        let current_balance = account.token.balance(rpc_client, &account.address)?;
+        if current_balance < account.last_update_balance {
+            let msg = format!(
+                "Warning: {} ({}) balance is less than expected. Actual: {}{}, expected: {}{}",
+                account.address,
+                account.token,
+                account.token.symbol(),
+                account.token.ui_amount(current_balance),
+                account.token.symbol(),
+                account.token.ui_amount(account.last_update_balance)
+            );
+            progress::println(&format!("\n{msg}\n"));
+            notifier
+                .send(NotificationKind::Balance, Severity::Alert, &msg)
+                .await;
+        } else if current_balance > account.last_update_balance + account.token.amount(0.005) {
+            let slot = epoch_info.absolute_slot;
+            let current_token_price = account.token.get_current_price(rpc_client).await?;
+            let (when, decimal_price) =
+                get_block_date_and_price(rpc_client, slot, account.token).await?;
+            prices_fetched += 1;
+            progress.set_message(format!("({prices_fetched} prices fetched)"));
+            let mut amount = current_balance - account.last_update_balance;
+
+            // The increase is first attributed to withdrawals from incoming streams and to vesting
+            // tranches that have unlocked, since they were last received
+            let mut acquisitions = vec![];
+            for mut token_stream in db.token_streams() {
+                if token_stream.recipient != account.address || token_stream.token != account.token
+                {
+                    continue;
+                }
+                let stream = streamflow::get_stream(rpc_client, token_stream.address)?;
+                let withdrawn_amount = stream
+                    .withdrawn_amount
+                    .saturating_sub(token_stream.received_amount)
+                    .min(amount);
+                if withdrawn_amount == 0 {
+                    continue;
+                }
+                amount -= withdrawn_amount;
+                token_stream.received_amount += withdrawn_amount;
+                acquisitions.push((
+                    withdrawn_amount,
+                    LotAcquistionKind::StreamPayment {
+                        stream: token_stream.address,
+                    },
+                ));
+                db.set_token_stream(token_stream)?;
+            }
+            for mut vesting_grant in db.vesting_grants() {
+                if vesting_grant.address != account.address || vesting_grant.token != account.token
+                {
+                    continue;
+                }
+                let vested_amount = vesting_grant.unreceived_amount(when).min(amount);
+                if vested_amount == 0 {
+                    continue;
+                }
+                amount -= vested_amount;
+                vesting_grant.received_amount += vested_amount;
+                acquisitions.push((
+                    vested_amount,
+                    LotAcquistionKind::Vesting {
+                        grant: vesting_grant.name.clone(),
+                    },
+                ));
+                db.set_vesting_grant(vesting_grant)?;
+            }
+            if amount > 0 {
+                acquisitions.push((amount, LotAcquistionKind::NotAvailable));
+            }
+
+            let msg = format!(
+                "{} ({}): {}",
+                account.address, account.token, account.description
+            );
+            notifier
+                .send(NotificationKind::Balance, Severity::Info, &msg)
+                .await;
+            progress::println(&msg);
+
+            for (amount, kind) in acquisitions {
+                let lot = Lot {
+                    lot_number: db.next_lot_number(),
+                    acquisition: LotAcquistion::new(when, decimal_price, kind),
+                    amount,
+                };
+
+                maybe_println_lot(
+                    account.token,
+                    &lot,
+                    Some(current_token_price),
+                    None,
+                    None,
+                    &mut Decimal::ZERO,
+                    &mut Decimal::ZERO,
+                    &mut Decimal::ZERO,
+                    &mut false,
+                    &mut Decimal::ZERO,
+                    Some(notifier),
+                    DEFAULT_LOT_COLUMNS,
+                    true,
+                    true,
+                )
+                .await;
+                account.lots.push(lot);
+            }
+            account.last_update_balance = current_balance;
+        }
+
+        db.update_account(account.clone())?;
+        progress.inc();
+    }
+
+    Ok(())
+}
+
+// Deposits SOL into a liquid staking protocol. The deposit is recorded as a swap of SOL for the
+// protocol's token, so it's confirmed by `process_sync_swaps` like a Jupiter swap
+#[allow(clippy::too_many_arguments)]
+async fn process_account_liquid_stake<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    address: Pubkey,
+    amount: Amount,
+    protocol: LiquidStakeProtocol,
+    lot_selection_method: LotSelectionMethod,
+    signers: T,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let sol = MaybeToken::SOL();
+    let lst = MaybeToken::from(protocol.token());
+
+    let from_account = db
+        .get_account(address, sol)
+        .ok_or_else(|| format!("SOL account does not exist for {address}"))?;
+    let amount = amount.unwrap_or_else(|| {
+        from_account
+            .last_update_balance
+            .saturating_sub(db.sol_reserve(address))
+    });
+    if amount == 0 {
+        println!("Nothing to stake");
+        return Ok(());
+    }
+    if from_account.last_update_balance < amount {
+        return Err(format!(
+            "Insufficient SOL balance in {}. Tracked balance is {}",
+            address,
+            sol.ui_amount(from_account.last_update_balance)
+        )
+        .into());
+    }
+    check_sol_reserve(db, address, from_account.last_update_balance, amount)?;
+
+    let from_token_price = sol.get_current_price(rpc_client).await?;
+    let to_token_price = lst.get_current_price(rpc_client).await?;
+
+    let (recent_blockhash, last_valid_block_height) =
+        rpc_clients.get_latest_blockhash_with_commitment()?;
+
+    let mut instructions = vec![
+        protocol.token().create_ata_instruction(&address, &address),
+        protocol.deposit_instruction(rpc_client, address, amount)?,
+    ];
+    apply_priority_fee(rpc_clients, &mut instructions, 100_000, priority_fee)?;
+
+    let mut transaction = Transaction::new_unsigned(Message::new(&instructions, Some(&address)));
+    transaction.message.recent_blockhash = recent_blockhash;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(
+            rpc_client,
+            db,
+            &VersionedMessage::Legacy(transaction.message.clone()),
+        )?;
+    }
+
+    println!(
+        "Staking {}{} with {} for {}",
+        sol.symbol(),
+        sol.ui_amount(amount),
+        protocol.name(),
+        address
+    );
+
+    transaction.try_sign(&signers, recent_blockhash)?;
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
+
+    if db.get_account(address, lst).is_none() {
+        let epoch = rpc_client.get_epoch_info()?.epoch;
+        db.add_account(TrackedAccount {
+            address,
+            token: lst,
+            description: from_account.description,
+            last_update_epoch: epoch,
+            last_update_balance: 0,
+            lots: vec![],
+            no_sync: None,
+            last_activity: None,
+        })?;
+    }
+    db.record_swap(
+        signature,
+        last_valid_block_height,
+        address,
+        sol,
+        from_token_price,
+        lst,
+        to_token_price,
+        lot_selection_method,
+        None,
+    )?;
+
+    let status = send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+    record_sent_transaction(db, PriorityFeeCategory::Swap, &transaction, status).await?;
+    if !status.confirmed() {
+        if status.abandoned() {
+            db.cancel_swap(signature)?;
+        }
+        return Err("Liquid stake failed".into());
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_account_wrap<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    address: Pubkey,
+    amount: Amount,
+    if_source_balance_exceeds: Option<u64>,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    authority_address: Pubkey,
+    signers: T,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let sol = MaybeToken::SOL();
+    let wsol = Token::wSOL;
+    let wsol_address = wsol.ata(&address);
+
+    let from_account = db
+        .get_account(address, sol)
+        .ok_or_else(|| format!("SOL account does not exist for {address}"))?;
+    let amount = amount.unwrap_or(from_account.last_update_balance);
+
+    if let Some(if_source_balance_exceeds) = if_source_balance_exceeds {
+        if from_account.last_update_balance < if_source_balance_exceeds {
+            println!(
+                "wrap declined because {} balance is less than {}{}",
+                address,
+                sol.symbol(),
+                sol.ui_amount(if_source_balance_exceeds)
+            );
+            return Ok(());
+        }
+    }
+
+    if amount == 0 {
+        println!("Nothing to wrap");
+        return Ok(());
+    }
+
+    if db.get_account(address, wsol.into()).is_none() {
+        let epoch = rpc_client.get_epoch_info()?.epoch;
+        db.add_account(TrackedAccount {
+            address,
+            token: wsol.into(),
+            description: from_account.description,
+            last_update_epoch: epoch,
+            last_update_balance: 0,
+            lots: vec![],
+            no_sync: None,
+            last_activity: None,
+        })?;
+    }
+
+    let (recent_blockhash, last_valid_block_height) =
+        rpc_clients.get_latest_blockhash_with_commitment()?;
+
+    let mut instructions = vec![];
+    instructions.extend([
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &authority_address,
+            &address,
+            &wsol.mint(),
+            &spl_token::id(),
+        ),
+        system_instruction::transfer(&address, &wsol_address, amount),
+        spl_token::instruction::sync_native(&spl_token::id(), &wsol_address).unwrap(),
+    ]);
+
+    apply_priority_fee(rpc_clients, &mut instructions, 30_000, priority_fee)?;
+
+    let mut transaction = rpc_clients.new_unsigned_transaction(
+        &instructions,
+        &authority_address,
+        recent_blockhash,
+    )?;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(rpc_client, db, &transaction.message)?;
+    }
+
+    println!("Wrapping {} for {}", wsol.ui_amount(amount), address);
+
+    try_sign_transaction(&mut transaction, &signers)?;
+
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
+
+    db.record_transfer(
+        signature,
+        last_valid_block_height,
+        Some(amount),
+        address,
+        sol,
+        address,
+        wsol.into(),
+        lot_selection_method,
+        lot_numbers,
+    )?;
+
+    let status = send_transaction_until_expired_with_fee_escalation(
+        rpc_clients,
+        &mut transaction,
+        last_valid_block_height,
+        priority_fee.max_lamports(),
+        |transaction| Ok(try_sign_transaction(transaction, &signers)?),
+        |signature, new_signature, last_valid_block_height| {
+            Ok(db.replace_pending_signature(signature, new_signature, last_valid_block_height)?)
+        },
+    );
+    let signature = transaction.signatures[0];
+    record_sent_transaction(db, PriorityFeeCategory::Wrap, &transaction, status).await?;
+    if !status.confirmed() {
+        if status.abandoned() {
+            db.cancel_transfer(signature)?;
+        }
+        return Err("Wrap failed".into());
+    }
+    println!("Wrap confirmed: {signature}");
+    let when = get_signature_date(rpc_client, signature).await?;
+    db.confirm_transfer(signature, when)?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_account_unwrap<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    address: Pubkey,
+    amount: Option<u64>,
+    lot_selection_method: LotSelectionMethod,
+    lot_numbers: Option<HashSet<usize>>,
+    authority_address: Pubkey,
+    signers: T,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let sol = MaybeToken::SOL();
+    let wsol = Token::wSOL;
+
+    let from_account = db
+        .get_account(address, wsol.into())
+        .ok_or_else(|| format!("Wrapped SOL account does not exist for {address}"))?;
+    let amount = amount.unwrap_or(from_account.last_update_balance);
+
+    let _to_account = db
+        .get_account(address, sol)
+        .ok_or_else(|| format!("SOL account does not exist for {address}"))?;
+
+    let (recent_blockhash, last_valid_block_height) =
+        rpc_clients.get_latest_blockhash_with_commitment()?;
+
+    let ephemeral_token_account = Keypair::new();
+
+    let mut instructions = vec![
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &authority_address,
+            &ephemeral_token_account.pubkey(),
+            &wsol.mint(),
+            &spl_token::id(),
+        ),
+        spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            &wsol.ata(&address),
+            &wsol.mint(),
+            &wsol.ata(&ephemeral_token_account.pubkey()),
+            &authority_address,
+            &[],
+            amount,
+            wsol.decimals(),
+        )
+        .unwrap(),
+        spl_token::instruction::close_account(
+            &spl_token::id(),
+            &wsol.ata(&ephemeral_token_account.pubkey()),
+            &address,
+            &ephemeral_token_account.pubkey(),
+            &[],
+        )
+        .unwrap(),
+    ];
+    apply_priority_fee(rpc_clients, &mut instructions, 30_000, priority_fee)?;
+
+    let mut transaction = rpc_clients.new_unsigned_transaction(
+        &instructions,
+        &authority_address,
+        recent_blockhash,
+    )?;
+    let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+    if simulation_result.err.is_some() {
+        return Err(format!("Simulation failure: {simulation_result:?}").into());
+    }
+
+    if verbose {
+        println_transaction_cost(rpc_client, db, &transaction.message)?;
+    }
+
+    println!("Unwrapping {} for {}", wsol.ui_amount(amount), address);
+
+    try_partial_sign_transaction(&mut transaction, &signers)?;
+    try_sign_transaction(&mut transaction, &[&ephemeral_token_account])?;
+
+    let signature = transaction.signatures[0];
+    println!("Transaction signature: {signature}");
+
+    db.record_transfer(
+        signature,
+        last_valid_block_height,
+        Some(amount),
+        address,
+        wsol.into(),
+        address,
+        sol,
+        lot_selection_method,
+        lot_numbers,
+    )?;
+
+    let status = send_transaction_until_expired_with_fee_escalation(
+        rpc_clients,
+        &mut transaction,
+        last_valid_block_height,
+        priority_fee.max_lamports(),
+        |transaction| {
+            try_partial_sign_transaction(transaction, &signers)?;
+            try_sign_transaction(transaction, &[&ephemeral_token_account])?;
+            Ok(())
+        },
+        |signature, new_signature, last_valid_block_height| {
+            Ok(db.replace_pending_signature(signature, new_signature, last_valid_block_height)?)
+        },
+    );
+    let signature = transaction.signatures[0];
+    record_sent_transaction(db, PriorityFeeCategory::Wrap, &transaction, status).await?;
+    if !status.confirmed() {
+        if status.abandoned() {
+            db.cancel_transfer(signature)?;
+        }
+        return Err("Wrap failed".into());
+    }
+    println!("Unwrap confirmed: {signature}");
+    let when = get_signature_date(rpc_client, signature).await?;
+    db.confirm_transfer(signature, when)?;
+
+    Ok(())
+}
+
+// Starts tracking the Streamflow streams paying out to tracked token accounts. Withdrawals made
+// before a stream was found are assumed to be already accounted for
+async fn process_account_sync_streams(
+    db: &mut Db,
+    rpc_client: &dyn ChainClient,
+    accounts: &[TrackedAccount],
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let recipients = accounts
+        .iter()
+        .filter(|account| account.token.is_token())
+        .map(|account| account.address)
+        .collect::<BTreeSet<_>>();
+    let token_streams = db.token_streams();
+
+    for recipient in recipients {
+        let streams = match streamflow::get_incoming_streams(rpc_client, recipient) {
+            Ok(streams) => streams,
+            Err(err) => {
+                println!("Unable to look for incoming streams to {recipient}: {err}");
+                continue;
+            }
+        };
+        for stream in streams {
+            if token_streams
+                .iter()
+                .any(|token_stream| token_stream.address == stream.address)
+            {
+                continue;
+            }
+            let Some(account) = accounts.iter().find(|account| {
+                account.address == recipient && account.token.mint() == stream.mint
+            }) else {
+                continue;
+            };
+
+            let msg = format!(
+                "New incoming stream {} to {recipient}: {} of {}",
+                stream.name,
+                account.token.format_amount(stream.net_amount_deposited),
+                account.token,
+            );
+            notifier
+                .send(NotificationKind::Balance, Severity::Info, &msg)
+                .await;
+            println!("{msg}");
+
+            db.set_token_stream(TokenStream {
+                address: stream.address,
+                name: stream.name,
+                recipient,
+                token: account.token,
+                received_amount: stream.withdrawn_amount,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+async fn process_account_sync_pending_transfers(
+    db: &mut Db,
+    rpc_client: &dyn ChainClient,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let block_height = rpc_client.get_epoch_info()?.block_height;
+    let mut checked_signatures = HashSet::new();
+    for PendingTransfer {
+        signature,
+        last_valid_block_height,
+        ..
+    } in db.pending_transfers()
+    {
+        // The transfers of a batched transaction are completed together
+        if !checked_signatures.insert(signature) {
+            continue;
+        }
+        let mut status = rpc_client
+            .get_signature_statuses_with_history(&[signature])?
+            .value
+            .pop()
+            .flatten()
+            .filter(|status| status.satisfies_commitment(rpc_client.commitment()))
+            .map(|status| status.status);
+        if status.is_none() && block_height > last_valid_block_height {
+            status = recheck_expired_transaction(&[rpc_client], &signature)?;
+        }
+        match status {
+            Some(result) => {
+                if result.is_ok() {
+                    println!("Pending transfer confirmed: {signature}");
+                    let when = get_signature_date(rpc_client, signature).await?;
+                    db.confirm_transfer(signature, when)?;
+                } else {
+                    println!("Pending transfer failed with {result:?}: {signature}");
+                    db.cancel_transfer(signature)?;
+                }
+            }
+            None => {
+                if block_height > last_valid_block_height {
+                    println!("Pending transfer cancelled: {signature}");
+                    db.cancel_transfer(signature)?;
+                } else if last_valid_block_height == DURABLE_NONCE_LAST_VALID_BLOCK_HEIGHT {
+                    println!("Transfer pending until its nonce is advanced: {signature}");
+                } else {
+                    println!(
+                        "Transfer pending for at most {} blocks: {}",
+                        last_valid_block_height.saturating_sub(block_height),
+                        signature
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Compute units of a stake program instruction, as budgeted for stake withdrawals elsewhere
+const STAKE_INSTRUCTION_COMPUTE_UNITS: u32 = 1_000;
+
+async fn process_account_sync_sweep(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    _notifier: &Notifier,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let token = MaybeToken::SOL();
+
+    let transitory_sweep_stake_addresses = db.get_transitory_sweep_stake_addresses();
+    if transitory_sweep_stake_addresses.is_empty() {
+        return Ok(());
+    }
+
+    let sweep_stake_account_info = db
+        .get_sweep_stake_account()
+        .ok_or("Sweep stake account is not configured")?;
+
+    let sweep_stake_account_authority_keypair =
+        read_keypair_file(&sweep_stake_account_info.stake_authority).map_err(|err| {
+            format!(
+                "Failed to read {}: {}",
+                sweep_stake_account_info.stake_authority.display(),
+                err
+            )
+        })?;
+
+    let sweep_stake_account = rpc_client
+        .get_account_with_commitment(&sweep_stake_account_info.address, rpc_client.commitment())?
+        .value
+        .ok_or("Sweep stake account does not exist")?;
+
+    let sweep_stake_activation = rpc_client
+        .get_stake_activation(sweep_stake_account_info.address, None)
+        .map_err(|err| {
+            format!(
+                "Unable to get activation information for sweep stake account: {}: {}",
+                sweep_stake_account_info.address, err
+            )
+        })?;
+
+    if sweep_stake_activation.state != StakeActivationState::Active {
+        println!(
+            "Sweep stake account is not active, unable to continue: {sweep_stake_activation:?}"
+        );
+        return Ok(());
+    }
+
+    // Merges of the transitory stakes that are ready are packed into as few transactions as fit
+    let mut batcher = TransactionBatcher::new(sweep_stake_account_authority_keypair.pubkey());
+    for transitory_sweep_stake_address in transitory_sweep_stake_addresses {
+        println!("Considering merging transitory stake {transitory_sweep_stake_address}");
+
+        let transitory_sweep_stake_account = match rpc_client
+            .get_account_with_commitment(&transitory_sweep_stake_address, rpc_client.commitment())?
+            .value
+        {
+            None => {
+                println!(
+                    "  Transitory sweep stake account does not exist, removing it: {transitory_sweep_stake_address}"
+                );
+
+                if let Some(tracked_account) = db.get_account(transitory_sweep_stake_address, token)
+                {
+                    if tracked_account.last_update_balance > 0 || !tracked_account.lots.is_empty() {
+                        panic!("Tracked account is not empty: {tracked_account:?}");
+
+                        // TODO: Simulate a transfer to move the lots into the sweep account in
+                        // this case?
+                        /*
+                        let signature = Signature::default();
+                        db.record_transfer(
+                            signature,
+                            None,
+                            transitory_sweep_stake_address,
+                            sweep_stake_account_info.address,
+                            None,
+                        )?;
+                        db.confirm_transfer(signature)?;
+                        */
+                    }
+                }
+                db.remove_transitory_sweep_stake_address(transitory_sweep_stake_address)?;
+                continue;
+            }
+            Some(x) => x,
+        };
+
+        let transient_stake_activation = rpc_client
+            .get_stake_activation(transitory_sweep_stake_address, None)
+            .map_err(|err| {
+                format!(
+                    "Unable to get activation information for transient stake: {transitory_sweep_stake_address}: {err}"
+                )
+            })?;
+
+        if transient_stake_activation.state != StakeActivationState::Active {
+            println!("  Transitory stake is not yet active: {transient_stake_activation:?}");
+            continue;
+        }
+
+        if !rpc_client_utils::stake_accounts_have_same_credits_observed(
+            &sweep_stake_account,
+            &transitory_sweep_stake_account,
+        )? {
+            println!(
+                "  Transitory stake credits observed mismatch with sweep stake account: {transitory_sweep_stake_address}"
+            );
+            continue;
+        }
+        println!("  Merging into sweep stake account");
+
+        batcher.add(
+            transitory_sweep_stake_address,
+            solana_sdk::stake::instruction::merge(
+                &sweep_stake_account_info.address,
+                &transitory_sweep_stake_address,
+                &sweep_stake_account_authority_keypair.pubkey(),
+            ),
+            STAKE_INSTRUCTION_COMPUTE_UNITS,
+        )?;
+    }
+
+    for batch in batcher.into_batches() {
+        let message = Message::new(
+            &batch.instructions,
+            Some(&sweep_stake_account_authority_keypair.pubkey()),
+        );
+        let mut transaction = Transaction::new_unsigned(message);
+
+        let (recent_blockhash, last_valid_block_height) =
+            rpc_clients.get_latest_blockhash_with_commitment()?;
+
+        transaction.message.recent_blockhash = recent_blockhash;
+        let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+        if simulation_result.err.is_some() {
+            return Err(format!("Simulation failure: {simulation_result:?}").into());
+        }
+
+        if verbose {
+            println_transaction_cost(
+                rpc_client,
+                db,
+                &VersionedMessage::Legacy(transaction.message.clone()),
+            )?;
+        }
+
+        transaction.sign(&[&sweep_stake_account_authority_keypair], recent_blockhash);
+
+        let signature = transaction.signatures[0];
+        println!(
+            "Transaction signature: {signature} ({} merges)",
+            batch.operations.len()
+        );
+        for transitory_sweep_stake_address in &batch.operations {
+            db.record_transfer(
+                signature,
+                last_valid_block_height,
+                None,
+                *transitory_sweep_stake_address,
+                token,
+                sweep_stake_account_info.address,
+                token,
+                LotSelectionMethod::default(),
+                None,
+            )?;
+        }
+
+        let status =
+            send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+        record_sent_transaction(db, PriorityFeeCategory::Sweep, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                db.cancel_transfer(signature)?;
+            }
+            return Err("Merge failed".into());
+        }
+        let when = get_signature_date(rpc_client, signature).await?;
+        db.confirm_transfer(signature, when)?;
+        for transitory_sweep_stake_address in batch.operations {
+            db.remove_transitory_sweep_stake_address(transitory_sweep_stake_address)?;
+        }
+    }
+    Ok(())
+}
+
+// Token transfers from `address` and the idempotent creation of the recipient's associated token
+// account, as budgeted for exchange deposits
+const TOKEN_TRANSFER_COMPUTE_UNITS: u32 = 27_500;
+
+// Sweeps every token balance of `address` worth less than `below` into `to_address`, packing the
+// transfers into as few transactions as fit. Each transaction goes through the same SOL reserve,
+// ATA rent and durable nonce handling as an exchange deposit
+#[allow(clippy::too_many_arguments)]
+async fn process_account_dust_sweep<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    address: Pubkey,
+    to_address: Pubkey,
+    below: f64,
+    authority_address: Pubkey,
+    signers: T,
+    durable_nonce: Option<DurableNonce>,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    let mut transfers = vec![];
+    for account in db.get_accounts() {
+        let Some(token) = account.token.token() else {
+            continue;
+        };
+        let amount = account.last_update_balance;
+        if account.address != address || amount == 0 {
+            continue;
+        }
+
+        let price = account.token.get_current_price(rpc_client).await?;
+        let value = f64::try_from(price)? * token.ui_amount(amount);
+        if value >= below {
+            continue;
+        }
+        if token.transfer_fee(rpc_client, amount)? > 0 {
+            println!("Skipping {}, transfer fees are not supported", token.name());
+            continue;
+        }
+
+        let ata_rent = if rpc_client
+            .get_account_with_commitment(&token.ata(&to_address), rpc_client.commitment())?
+            .value
+            .is_none()
+        {
+            token.ata_rent(rpc_client)?
+        } else {
+            0
+        };
+
+        println!(
+            "Sweeping {}{} ({}) into {}",
+            token.symbol(),
+            token.ui_amount(amount),
+            currency(value.localized_string_with_fixed_place(2)),
+            to_address
+        );
+        transfers.push((
+            (account.token, amount, ata_rent),
+            vec![
+                token.create_ata_instruction(&authority_address, &to_address),
+                token.transfer_checked_instruction(
+                    &address,
+                    &to_address,
+                    &authority_address,
+                    amount,
+                    0,
+                ),
+            ],
+            TOKEN_TRANSFER_COMPUTE_UNITS,
+        ));
+    }
+
+    let mut batcher = TransactionBatcher::new(authority_address);
+    reserve_token_batch_instructions(
+        &mut batcher,
+        authority_address,
+        transfers.iter().any(|((.., ata_rent), ..)| *ata_rent > 0),
+        durable_nonce.as_ref(),
+    );
+    for (transfer, instructions, compute_units) in transfers {
+        batcher.add(transfer, instructions, compute_units)?;
+    }
+
+    if batcher.is_empty() {
+        println!("Nothing to sweep");
+        return Ok(());
+    }
+
+    for batch in batcher.into_batches() {
+        let (mut transaction, last_valid_block_height, ata_rent_funder) =
+            new_token_batch_transaction(
+                db,
+                rpc_clients,
+                batch.instructions,
+                batch.compute_units,
+                authority_address,
+                batch.operations.iter().map(|(.., ata_rent)| ata_rent).sum(),
+                durable_nonce.as_ref(),
+                priority_fee,
+                verbose,
+            )?;
+
+        let sign =
+            |transaction: &mut VersionedTransaction| -> Result<(), Box<dyn std::error::Error>> {
+                if let Some(ata_rent_funder) = ata_rent_funder.as_ref() {
+                    try_partial_sign_transaction(transaction, &[ata_rent_funder])?;
+                }
+                if let Some(durable_nonce) = durable_nonce.as_ref() {
+                    durable_nonce.partial_sign(transaction)?;
+                }
+                Ok(try_sign_transaction(transaction, &signers)?)
+            };
+        sign(&mut transaction)?;
+        let signature = transaction.signatures[0];
+        println!(
+            "Transaction signature: {signature} ({} transfers)",
+            batch.operations.len()
+        );
+
+        for (token, amount, _ata_rent) in batch.operations {
+            if db.get_account(to_address, token).is_none() {
+                let epoch = rpc_client.get_epoch_info()?.epoch;
+                db.add_account(TrackedAccount {
+                    address: to_address,
+                    token,
+                    description: "Dust sweep".into(),
+                    last_update_epoch: epoch,
+                    last_update_balance: 0,
+                    lots: vec![],
+                    no_sync: None,
+                    last_activity: None,
+                })?;
+            }
+            db.record_transfer(
+                signature,
+                last_valid_block_height,
+                Some(amount),
+                address,
+                token,
+                to_address,
+                token,
+                LotSelectionMethod::default(),
+                None,
+            )?;
+        }
+
+        let status = send_transaction_until_expired_with_fee_escalation(
+            rpc_clients,
+            &mut transaction,
+            last_valid_block_height,
+            priority_fee.max_lamports(),
+            sign,
+            |signature, new_signature, last_valid_block_height| {
+                Ok(db.replace_pending_signature(
+                    signature,
+                    new_signature,
+                    last_valid_block_height,
+                )?)
+            },
+        );
+        let signature = transaction.signatures[0];
+        record_sent_transaction(db, PriorityFeeCategory::Sweep, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                db.cancel_transfer(signature)?;
+            }
+            return Err("Dust sweep failed".into());
+        }
+        println!("Dust sweep confirmed: {signature}");
+        let when = get_signature_date(rpc_client, signature).await?;
+        db.confirm_transfer(signature, when)?;
+    }
+    Ok(())
+}
+
+// Withdraws the balance above the delegated stake, such as MEV rewards, of the tracked stake
+// accounts withdrawn by `authority_address` into `to_address`, packing the withdrawals into as few
+// transactions as fit
+#[allow(clippy::too_many_arguments)]
+async fn process_account_collect<T: Signers>(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    stake_addresses: Option<HashSet<Pubkey>>,
+    to_address: Pubkey,
+    authority_address: Pubkey,
+    signers: T,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+    let token = MaybeToken::SOL();
+
+    if db.get_account(to_address, token).is_none() {
+        return Err(format!(
+            "Account, {to_address}, is not tracked. Add it with `sys account add {to_address}`"
+        )
+        .into());
+    }
+
+    let addresses = db
+        .get_accounts()
+        .into_iter()
+        .filter(|account| {
+            account.token == token
+                && account.address != to_address
+                && stake_addresses.as_ref().map_or(true, |stake_addresses| {
+                    stake_addresses.contains(&account.address)
+                })
+        })
+        .map(|account| account.address)
+        .collect::<Vec<_>>();
+    let excess_balances = rpc_client_utils::get_stake_excess_balances(rpc_client, &addresses)?;
+
+    let mut batcher = TransactionBatcher::new(authority_address);
+    for (address, excess_balance) in addresses.into_iter().zip(excess_balances) {
+        let Some((withdrawer, amount)) = excess_balance else {
+            continue;
+        };
+        if withdrawer != authority_address || amount == 0 {
+            continue;
+        }
+
+        println!(
+            "Collecting {} from {} into {}",
+            token.format_amount(amount),
+            address,
+            to_address
+        );
+        batcher.add(
+            (address, amount),
+            vec![solana_sdk::stake::instruction::withdraw(
+                &address,
+                &authority_address,
+                &to_address,
+                amount,
+                None,
+            )],
+            STAKE_INSTRUCTION_COMPUTE_UNITS,
+        )?;
+    }
+
+    if batcher.is_empty() {
+        println!("Nothing to collect");
+        return Ok(());
+    }
+
+    for batch in batcher.into_batches() {
+        let (recent_blockhash, last_valid_block_height) =
+            rpc_clients.get_latest_blockhash_with_commitment()?;
+
+        let mut instructions = batch.instructions;
+        apply_priority_fee(
+            rpc_clients,
+            &mut instructions,
+            batch.compute_units,
+            priority_fee,
+        )?;
+
+        let message = Message::new(&instructions, Some(&authority_address));
+        let mut transaction = Transaction::new_unsigned(message);
+        transaction.message.recent_blockhash = recent_blockhash;
+        let simulation_result = rpc_client.simulate_transaction(&transaction)?.value;
+        if simulation_result.err.is_some() {
+            return Err(format!("Simulation failure: {simulation_result:?}").into());
+        }
+
+        if verbose {
+            println_transaction_cost(
+                rpc_client,
+                db,
+                &VersionedMessage::Legacy(transaction.message.clone()),
+            )?;
+        }
+
+        transaction.try_sign(&signers, recent_blockhash)?;
+        let signature = transaction.signatures[0];
+        println!(
+            "Transaction signature: {signature} ({} withdrawals)",
+            batch.operations.len()
+        );
+
+        for (address, amount) in batch.operations {
+            db.record_transfer(
+                signature,
+                last_valid_block_height,
+                Some(amount),
+                address,
+                token,
+                to_address,
+                token,
+                LotSelectionMethod::default(),
+                None,
+            )?;
+        }
+
+        let status =
+            send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+        record_sent_transaction(db, PriorityFeeCategory::Stake, &transaction, status).await?;
+        if !status.confirmed() {
+            if status.abandoned() {
+                db.cancel_transfer(signature)?;
+            }
+            return Err("Collect failed".into());
+        }
+        println!("Collect confirmed: {signature}");
+        let when = get_signature_date(rpc_client, signature).await?;
+        db.confirm_transfer(signature, when)?;
+    }
+    Ok(())
+}
+
+fn lot_numbers_of(matches: &ArgMatches<'_>, name: &str) -> Option<HashSet<usize>> {
+    values_t!(matches, name, usize)
+        .ok()
+        .map(|x| x.into_iter().collect())
+}
+
+fn lot_numbers_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("lot_numbers")
+        .long("lot")
+        .value_name("LOT NUMBER")
+        .takes_value(true)
+        .multiple(true)
+        .validator(is_parsable::<usize>)
+        .help("Lot to fund the wrap from")
+}
+
+fn time_in_force_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("time_in_force")
+        .long("time-in-force")
+        .value_name("TIF")
+        .takes_value(true)
+        .default_value(POSSIBLE_TIME_IN_FORCE_VALUES[0])
+        .possible_values(POSSIBLE_TIME_IN_FORCE_VALUES)
+        .help("Time in force of the order; IOC and FOK orders are never post-only")
+}
+
+fn no_post_only_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("no_post_only")
+        .long("no-post-only")
+        .takes_value(false)
+        .help("Allow the order to take liquidity from the order book when placed")
+}
+
+fn max_book_fraction_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("max_book_fraction")
+        .long("max-book-fraction")
+        .value_name("FRACTION")
+        .takes_value(true)
+        .validator(is_tax_rate)
+        .help(
+            "Largest fraction of the visible order book depth the order may consume \
+               without --allow-impact [default: 0.1]",
+        )
+}
+
+fn allow_impact_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("allow_impact")
+        .long("allow-impact")
+        .takes_value(false)
+        .help("Place the order even if it is large relative to the visible order book depth")
+}
+
+// `None` if the price impact guard is disabled
+fn max_book_fraction_of(matches: &ArgMatches<'_>) -> Option<f64> {
+    if matches.is_present("allow_impact") {
+        None
+    } else {
+        Some(value_t!(matches, "max_book_fraction", f64).unwrap_or(DEFAULT_MAX_BOOK_FRACTION))
+    }
+}
+
+fn order_options_of(matches: &ArgMatches<'_>) -> OrderOptions {
+    let time_in_force = value_t_or_exit!(matches, "time_in_force", TimeInForce);
+    OrderOptions {
+        time_in_force,
+        post_only: time_in_force == TimeInForce::GoodTilCancelled
+            && !matches.is_present("no_post_only"),
+    }
+}
+
+// Parses an `EXCHANGE[:ACCOUNT_NAME]` selector, such as "kraken" or "binance:main"
+fn parse_exchange_account_selector(value: &str) -> Result<(Exchange, Option<String>), String> {
+    let (exchange, exchange_account) = match value.split_once(':') {
+        Some((exchange, exchange_account)) => (exchange, Some(exchange_account.to_string())),
+        None => (value, None),
+    };
+    let exchange = Exchange::from_str(exchange).map_err(|err| format!("{exchange}: {err}"))?;
+    Ok((exchange, exchange_account))
+}
+
+fn is_exchange_account_selected(
+    selectors: &[(Exchange, Option<String>)],
+    exchange: Exchange,
+    exchange_account: &str,
+) -> bool {
+    selectors
+        .iter()
+        .any(|(selected_exchange, selected_account)| {
+            *selected_exchange == exchange
+                && match selected_account {
+                    Some(selected_account) => selected_account == exchange_account,
+                    None => true,
+                }
+        })
+}
+
+fn values_of_exchange_account_selectors(
+    matches: &ArgMatches<'_>,
+    name: &str,
+) -> Option<Vec<(Exchange, Option<String>)>> {
+    matches.values_of(name).map(|values| {
+        values
+            .map(|value| parse_exchange_account_selector(value).unwrap())
+            .collect()
+    })
+}
+
+async fn process_sync(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    only: Option<Vec<(Exchange, Option<String>)>>,
+    skip: Option<Vec<(Exchange, Option<String>)>>,
+    max_epochs_to_process: Option<u64>,
+    notifier: &Notifier,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sync_start = Instant::now();
+    let result = process_sync_phases(
+        db,
+        rpc_clients,
+        only,
+        skip,
+        max_epochs_to_process,
+        notifier,
+        verbose,
+    )
+    .await;
+    operation_stats::record(Operation::Sync, "", sync_start.elapsed(), result.is_ok());
+    result
+}
+
+async fn process_sync_phases(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    only: Option<Vec<(Exchange, Option<String>)>>,
+    skip: Option<Vec<(Exchange, Option<String>)>>,
+    max_epochs_to_process: Option<u64>,
+    notifier: &Notifier,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    let sync_start = Instant::now();
+    process_sync_swaps(db, rpc_client, notifier, verbose).await?;
+    metrics::push(dp::sync_duration("swaps", sync_start.elapsed())).await;
+
+    for (exchange, exchange_credentials, exchange_account) in db
+        .get_accounts_from_configured_exchanges()
+        .into_iter()
+        .filter(|(exchange, _, exchange_account)| {
+            let selected = match &only {
+                Some(only) => is_exchange_account_selected(only, *exchange, exchange_account),
+                None => exchange_account.is_empty(),
+            };
+            let skipped = skip.as_ref().is_some_and(|skip| {
+                is_exchange_account_selected(skip, *exchange, exchange_account)
+            });
+            selected && !skipped
+        })
+    {
+        println!("Synchronizing {exchange:?} {exchange_account}...");
+        let phase_start = Instant::now();
+        let exchange_client = exchange_client_new(exchange, exchange_credentials)?;
+        process_sync_exchange(
+            db,
+            exchange,
+            exchange_client.as_ref(),
+            rpc_client,
+            false,
+            notifier,
+        )
+        .await?;
+        process_exchange_withdrawal_schedules(
+            db,
+            exchange,
+            &exchange_account,
+            exchange_client.as_ref(),
+            rpc_client,
+            notifier,
+        )
+        .await?;
+        process_exchange_margin(
+            db,
+            exchange,
+            &exchange_account,
+            exchange_client.as_ref(),
+            rpc_client,
+            notifier,
+        )
+        .await?;
+        metrics::push(dp::sync_duration(
+            &format!("exchange:{exchange:?}{exchange_account}"),
+            phase_start.elapsed(),
+        ))
+        .await;
+    }
+
+    let phase_start = Instant::now();
+    process_account_sync(
+        db,
+        rpc_client,
+        Some(rpc_clients),
+        None,
+        max_epochs_to_process,
+        false,
+        false,
+        notifier,
+        verbose,
+    )
+    .await?;
+    metrics::push(dp::sync_duration("accounts", phase_start.elapsed())).await;
+    metrics::push(dp::sync_duration("total", sync_start.elapsed())).await;
+    metrics::push(dp::pending_items(
+        db.pending_deposits(None).len(),
+        db.pending_withdrawals(None).len(),
+        db.pending_transfers().len(),
+        db.pending_swaps().len(),
+    ))
+    .await;
+    for (url, stats) in rpc_clients.request_stats() {
+        metrics::push(dp::rpc_requests(
+            &rate_limited_sender::endpoint_label(url),
+            stats.requests.load(Ordering::Relaxed),
+            stats.retries.load(Ordering::Relaxed),
+            stats.throttled.load(Ordering::Relaxed),
+            stats.rate_limited_time(),
+        ))
+        .await;
+    }
+    push_holdings_metrics(db, rpc_client).await?;
+    Ok(())
+}
+
+// Pushes the balance, value and unrealized gain of each token held, summed over all accounts
+async fn push_holdings_metrics(
+    db: &Db,
+    rpc_client: &RpcClient,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut holdings = BTreeMap::<MaybeToken, (u64, Decimal)>::new();
+    for account in db.get_accounts() {
+        let (amount, basis) = holdings.entry(account.token).or_default();
+        for lot in &account.lots {
+            *amount += lot.amount;
+            *basis += lot.basis(account.token);
+        }
+    }
+
+    for (token, (amount, basis)) in holdings {
+        let current_price = token.get_current_price(rpc_client).await?;
+        let value = current_price * token.ui_amount_decimal(amount);
+        metrics::push(dp::token_holdings(
+            token,
+            token.ui_amount(amount),
+            f64::try_from(value)?,
+            f64::try_from(value - basis)?,
+        ))
+        .await;
+    }
+    Ok(())
+}
+
+const MAX_WORKFLOW_ATTEMPTS: usize = 3;
+const WORKFLOW_RETRY_DELAY: Duration = Duration::from_secs(10);
+const WORKFLOW_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+fn exchange_client_of(
+    db: &Db,
+    exchange: Exchange,
+    exchange_account: &str,
+) -> Result<Box<dyn ExchangeClient>, Box<dyn std::error::Error>> {
+    let exchange_credentials = db
+        .get_exchange_credentials(exchange, exchange_account)
+        .ok_or_else(|| {
+            if db.credentials_locked() {
+                DbError::CredentialsLocked.to_string()
+            } else {
+                format!("No API key set for {exchange:?}")
+            }
+        })?;
+    exchange_client_new(exchange, exchange_credentials)
+}
+
+enum WorkflowStepOutcome {
+    // The step is done, having moved `amount`
+    Settled(Option<u64>),
+    // The step submitted a transaction that has yet to settle
+    Submitted {
+        reference: String,
+        amount: Option<u64>,
+    },
+}
+
+enum WorkflowStepSettlement {
+    Pending,
+    Settled,
+    Cancelled,
+}
+
+// The outcome of a transfer submission that failed with `err`. If it recorded a transfer not in
+// `pending_transfers` before failing, that transfer may still land
+fn submitted_transfer_outcome(
+    db: &Db,
+    pending_transfers: &[PendingTransfer],
+    err: Box<dyn std::error::Error>,
+) -> Result<WorkflowStepOutcome, Box<dyn std::error::Error>> {
+    let Some(pending_transfer) = db
+        .pending_transfers()
+        .into_iter()
+        .find(|pending_transfer| !pending_transfers.contains(pending_transfer))
+    else {
+        return Err(err);
+    };
+    eprintln!("{err}");
+    Ok(WorkflowStepOutcome::Submitted {
+        reference: pending_transfer.signature.to_string(),
+        amount: Some(pending_transfer.lots.iter().map(|lot| lot.amount).sum()),
+    })
+}
+
+// Submits `step`. A submission that fails after its transaction was recorded is reported as
+// submitted, the transaction may still land and is settled like any other
+#[allow(clippy::too_many_arguments)]
+async fn submit_workflow_step(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    step: &WorkflowStep,
+    previous_amount: Option<u64>,
+    signer_from_path: &mut dyn FnMut(&str) -> Result<Box<dyn Signer>, Box<dyn std::error::Error>>,
+    notifier: &Notifier,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<WorkflowStepOutcome, Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    match step.clone() {
+        WorkflowStep::Sweep {
+            from_address,
+            token,
+            retain_amount,
+            to_address,
+            authority,
+        } => {
+            let lots_amount = |db: &Db| {
+                db.get_account(to_address, token)
+                    .map(|account| account.lots.iter().map(|lot| lot.amount).sum::<u64>())
+                    .unwrap_or_default()
+            };
+            let to_amount = lots_amount(db);
+            let pending_transfers = db.pending_transfers();
+
+            let authority = signer_from_path(&authority)?;
+            let result = process_account_sweep(
+                db,
+                rpc_clients,
+                from_address,
+                token,
+                retain_amount,
+                None,
+                false,
+                authority.pubkey(),
+                vec![authority],
+                None,
+                Some(to_address),
+                notifier,
+                priority_fee,
+                None,
+                verbose,
+            )
+            .await;
+
+            match result {
+                Ok(()) => Ok(WorkflowStepOutcome::Settled(Some(
+                    lots_amount(db).saturating_sub(to_amount),
+                ))),
+                Err(err) => submitted_transfer_outcome(db, &pending_transfers, err),
+            }
+        }
+        WorkflowStep::ExchangeDeposit {
+            exchange,
+            exchange_account,
+            token,
+            from_address,
+            amount,
+            authority,
+        } => {
+            let amount = match amount.or(previous_amount) {
+                Some(amount) => Amount::Exact(amount),
+                None => Amount::All,
+            };
+            let exchange_client = exchange_client_of(db, exchange, &exchange_account)?;
+            let deposit_address = exchange_client.deposit_address(token).await?;
+            add_exchange_deposit_address_to_db(
+                db,
+                exchange,
+                &exchange_account,
+                token,
+                deposit_address,
+                rpc_client,
+            )?;
+            let pending_deposits = db.pending_deposits(Some(exchange));
+
+            let authority = signer_from_path(&authority)?;
+            let result = process_exchange_deposit(
+                db,
+                rpc_clients,
+                exchange,
+                exchange_client.as_ref(),
+                token,
+                deposit_address,
+                amount,
+                from_address,
+                None,
+                None,
+                authority.pubkey(),
+                vec![authority],
+                None,
+                LotSelectionMethod::default(),
+                None,
+                priority_fee,
+                verbose,
+            )
+            .await;
+
+            let Some(pending_deposit) = db
+                .pending_deposits(Some(exchange))
+                .into_iter()
+                .find(|pending_deposit| !pending_deposits.contains(pending_deposit))
+            else {
+                result?;
+                return Err("No deposit was recorded".into());
+            };
+            if let Err(err) = result {
+                eprintln!("{err}");
+            }
+            Ok(WorkflowStepOutcome::Submitted {
+                reference: pending_deposit.transfer.signature.to_string(),
+                amount: Some(pending_deposit.amount),
+            })
+        }
+        WorkflowStep::ExchangeSell {
+            exchange,
+            exchange_account,
+            pair,
+            amount,
+            price,
+        } => {
+            let token = MaybeToken::SOL();
+            let amount = amount
+                .or(previous_amount)
+                .ok_or("No amount to sell, the previous step moved nothing")?;
+            let exchange_client = exchange_client_of(db, exchange, &exchange_account)?;
+            let pair = pair.unwrap_or_else(|| exchange_client.preferred_solusd_pair().into());
+            let open_orders = db.open_orders(Some(exchange), None);
+
+            let result = process_exchange_sell(
+                db,
+                exchange,
+                exchange_client.as_ref(),
+                token,
+                pair,
+                token.ui_amount(amount),
+                price.map(LimitOrderPrice::At),
+                None,
+                None,
+                false,
+                None,
+                LotSelectionMethod::default(),
+                None,
+                None,
+                OrderOptions::default(),
+                None,
+                notifier,
+            )
+            .await;
+
+            // The order is followed by `sync` from here on, like any other
+            let placed = db
+                .open_orders(Some(exchange), None)
+                .iter()
+                .any(|open_order| !open_orders.contains(open_order));
+            match result {
+                Err(err) if !placed => Err(err),
+                _ if !placed => Err("No order was placed".into()),
+                _ => Ok(WorkflowStepOutcome::Settled(Some(amount))),
+            }
+        }
+        WorkflowStep::Deactivate {
+            stake_address,
+            authority,
+        } => {
+            let amount = db
+                .get_account(stake_address, MaybeToken::SOL())
+                .ok_or_else(|| format!("Account, {stake_address}, is not tracked"))?
+                .last_update_balance;
+
+            // A deactivation that went through before the workflow was interrupted is not
+            // submitted again
+            if matches!(
+                rpc_client.get_stake_activation(stake_address, None)?.state,
+                StakeActivationState::Active | StakeActivationState::Activating
+            ) {
+                let authority = signer_from_path(&authority)?;
+                process_account_deactivate(
+                    db,
+                    rpc_clients,
+                    stake_address,
+                    authority.pubkey(),
+                    vec![authority],
+                    priority_fee,
+                    verbose,
+                )
+                .await?;
+            }
+            Ok(WorkflowStepOutcome::Submitted {
+                reference: stake_address.to_string(),
+                amount: Some(amount),
+            })
+        }
+        WorkflowStep::Withdraw {
+            stake_address,
+            to_address,
+            authority,
+        } => {
+            let amount = db
+                .get_account(stake_address, MaybeToken::SOL())
+                .ok_or_else(|| format!("Account, {stake_address}, is not tracked"))?
+                .last_update_balance;
+            let pending_transfers = db.pending_transfers();
+
+            let authority = signer_from_path(&authority)?;
+            let result = process_account_withdraw_stake(
+                db,
+                rpc_clients,
+                stake_address,
+                to_address,
+                None,
+                LotSelectionMethod::default(),
+                None,
+                authority.pubkey(),
+                vec![authority],
+                priority_fee,
+                None,
+                verbose,
+            )
+            .await;
+
+            match result {
+                Ok(()) => Ok(WorkflowStepOutcome::Settled(Some(amount))),
+                Err(err) => submitted_transfer_outcome(db, &pending_transfers, err),
+            }
+        }
+        WorkflowStep::Swap {
+            address,
+            from_token,
+            to_token,
+            amount,
+            slippage_bps,
+            max_coingecko_value_percentage_loss,
+            authority,
+        } => {
+            let amount = amount.or(previous_amount);
+            let pending_swaps = db.pending_swaps();
+
+            let authority = signer_from_path(&authority)?;
+            let result = process_jup_swap(
+                db,
+                rpc_clients,
+                address,
+                from_token,
+                to_token,
+                amount.map(|amount| from_token.ui_amount(amount)),
+                slippage_bps,
+                LotSelectionMethod::default(),
+                vec![authority],
+                None,
+                None,
+                None,
+                max_coingecko_value_percentage_loss,
+                priority_fee,
+                false,
+                notifier,
+                verbose,
+            )
+            .await;
+
+            // The swapped amount is only known once the swap is synced, and no step follows
+            let Some(pending_swap) = db
+                .pending_swaps()
+                .into_iter()
+                .find(|pending_swap| !pending_swaps.contains(pending_swap))
+            else {
+                result?;
+                return Err("No swap was recorded".into());
+            };
+            if let Err(err) = result {
+                eprintln!("{err}");
+            }
+            Ok(WorkflowStepOutcome::Submitted {
+                reference: pending_swap.signature.to_string(),
+                amount: None,
+            })
+        }
+    }
+}
+
+fn workflow_step_settlement(
+    db: &Db,
+    rpc_client: &dyn ChainClient,
+    step: &WorkflowStep,
+    reference: &str,
+) -> Result<WorkflowStepSettlement, Box<dyn std::error::Error>> {
+    Ok(match step {
+        WorkflowStep::Sweep { .. } | WorkflowStep::Withdraw { .. } => {
+            let signature = reference.parse::<Signature>()?;
+            if db
+                .pending_transfers()
+                .iter()
+                .any(|pending_transfer| pending_transfer.signature == signature)
+            {
+                WorkflowStepSettlement::Pending
+            } else if db
+                .cancelled_records()
+                .iter()
+                .any(|cancelled_record| cancelled_record.signature() == signature)
+            {
+                WorkflowStepSettlement::Cancelled
+            } else {
+                WorkflowStepSettlement::Settled
+            }
+        }
+        WorkflowStep::ExchangeDeposit { exchange, .. } => {
+            if db
+                .pending_deposits(Some(*exchange))
+                .iter()
+                .any(|pending_deposit| pending_deposit.transfer.signature.to_string() == reference)
+            {
+                WorkflowStepSettlement::Pending
+            } else if db
+                .exchange_transfers(*exchange)
+                .iter()
+                .any(|exchange_transfer| {
+                    exchange_transfer.kind == ExchangeTransferKind::Deposit
+                        && exchange_transfer.reference == reference
+                })
+            {
+                WorkflowStepSettlement::Settled
+            } else {
+                WorkflowStepSettlement::Cancelled
+            }
+        }
+        WorkflowStep::ExchangeSell { .. } => WorkflowStepSettlement::Settled,
+        WorkflowStep::Deactivate { stake_address, .. } => {
+            match rpc_client.get_stake_activation(*stake_address, None)?.state {
+                StakeActivationState::Inactive => WorkflowStepSettlement::Settled,
+                _ => WorkflowStepSettlement::Pending,
+            }
+        }
+        WorkflowStep::Swap { .. } => {
+            let signature = reference.parse::<Signature>()?;
+            if db
+                .pending_swaps()
+                .iter()
+                .any(|pending_swap| pending_swap.signature == signature)
+            {
+                WorkflowStepSettlement::Pending
+            } else if db
+                .cancelled_records()
+                .iter()
+                .any(|cancelled_record| cancelled_record.signature() == signature)
+            {
+                WorkflowStepSettlement::Cancelled
+            } else {
+                WorkflowStepSettlement::Settled
+            }
+        }
+    })
+}
+
+// Synchronizes whatever a pending step is waiting on
+async fn sync_workflow_step(
+    db: &mut Db,
+    rpc_client: &RpcClient,
+    step: &WorkflowStep,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match step {
+        WorkflowStep::Sweep { .. } | WorkflowStep::Withdraw { .. } => {
+            process_account_sync_pending_transfers(db, rpc_client).await
+        }
+        WorkflowStep::ExchangeDeposit {
+            exchange,
+            exchange_account,
+            ..
+        } => {
+            let exchange_client = exchange_client_of(db, *exchange, exchange_account)?;
+            process_sync_exchange(
+                db,
+                *exchange,
+                exchange_client.as_ref(),
+                rpc_client,
+                false,
+                notifier,
+            )
+            .await
+        }
+        WorkflowStep::Swap { .. } => process_sync_swaps(db, rpc_client, notifier, false).await,
+        WorkflowStep::ExchangeSell { .. } | WorkflowStep::Deactivate { .. } => Ok(()),
+    }
+}
+
+// Runs a workflow from where it left off until it completes or fails. Failed submissions are
+// retried up to MAX_WORKFLOW_ATTEMPTS times before the workflow is marked failed; `retry_step`
+// resumes a failed or interrupted workflow by submitting its current step again
+#[allow(clippy::too_many_arguments)]
+async fn process_workflow_resume(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    id: usize,
+    mut retry_step: bool,
+    signer_from_path: &mut dyn FnMut(&str) -> Result<Box<dyn Signer>, Box<dyn std::error::Error>>,
+    notifier: &Notifier,
+    priority_fee: PriorityFee,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = rpc_clients.default();
+
+    loop {
+        let mut workflow = db
+            .get_workflow(id)
+            .ok_or(DbError::WorkflowDoesNotExist(id))?;
+        if workflow.is_finished() {
+            println!("Workflow {id} {}", workflow.state);
+            return Ok(());
+        }
+        let step = workflow.steps[workflow.current_step].clone();
+        let step_number = workflow.current_step + 1;
+
+        let failed_attempt = |workflow: &mut Workflow, error: String| {
+            workflow.attempts += 1;
+            if workflow.attempts >= MAX_WORKFLOW_ATTEMPTS {
+                workflow.state = WorkflowState::Failed { error };
+            } else {
+                eprintln!(
+                    "Workflow {id} step {step_number} attempt {} failed: {error}",
+                    workflow.attempts
+                );
+                workflow.state = WorkflowState::Ready;
+            }
+        };
+        let settled = |workflow: &mut Workflow, amount: Option<u64>| {
+            println!("Workflow {id} step {step_number} done: {step}");
+            workflow.current_step += 1;
+            workflow.attempts = 0;
+            workflow.amount = amount;
+            workflow.state = if workflow.current_step == workflow.steps.len() {
+                WorkflowState::Completed
+            } else {
+                WorkflowState::Ready
+            };
+        };
+
+        match workflow.state.clone() {
+            WorkflowState::Submitting | WorkflowState::Failed { .. } if retry_step => {
+                retry_step = false;
+                workflow.attempts = 0;
+                workflow.state = WorkflowState::Ready;
+            }
+            WorkflowState::Submitting => {
+                return Err(format!(
+                    "Workflow {id} was interrupted while submitting step {step_number}, {step}. \
+                     Check whether it went through, then run `sys workflow resume {id} \
+                     --retry-step` to submit it again or `sys workflow cancel {id}`"
+                )
+                .into());
+            }
+            WorkflowState::Failed { error } => {
+                return Err(format!(
+                    "Workflow {id} failed at step {step_number}, {step}: {error}. Run \
+                     `sys workflow resume {id} --retry-step` to retry it"
+                )
+                .into());
+            }
+            WorkflowState::Ready => {
+                if workflow.attempts > 0 {
+                    tokio::time::sleep(WORKFLOW_RETRY_DELAY).await;
+                }
+                println!(
+                    "Workflow {id} step {step_number} of {}: {step}",
+                    workflow.steps.len()
+                );
+                workflow.state = WorkflowState::Submitting;
+                db.update_workflow(workflow.clone())?;
+
+                match submit_workflow_step(
+                    db,
+                    rpc_clients,
+                    &step,
+                    workflow.amount,
+                    signer_from_path,
+                    notifier,
+                    priority_fee,
+                    verbose,
+                )
+                .await
+                {
+                    Ok(WorkflowStepOutcome::Settled(amount)) => settled(&mut workflow, amount),
+                    Ok(WorkflowStepOutcome::Submitted { reference, amount }) => {
+                        workflow.state = WorkflowState::Submitted { reference, amount };
+                    }
+                    Err(err) => failed_attempt(&mut workflow, err.to_string()),
+                }
+            }
+            WorkflowState::Submitted { reference, amount } => {
+                match workflow_step_settlement(db, rpc_client, &step, &reference)? {
+                    WorkflowStepSettlement::Pending => {
+                        println!("Workflow {id} step {step_number} waiting for {reference}");
+                        tokio::time::sleep(WORKFLOW_POLL_INTERVAL).await;
+                        sync_workflow_step(db, rpc_client, &step, notifier).await?;
+                        continue;
+                    }
+                    WorkflowStepSettlement::Settled => settled(&mut workflow, amount),
+                    WorkflowStepSettlement::Cancelled => {
+                        failed_attempt(&mut workflow, format!("{reference} was cancelled"))
+                    }
+                }
+            }
+            WorkflowState::Completed | WorkflowState::Cancelled => unreachable!(),
+        }
+
+        if let WorkflowState::Failed { ref error } = workflow.state {
+            notifier
+                .send(
+                    NotificationKind::Workflow,
+                    Severity::Alert,
+                    &format!("Workflow {id} ({}) failed: {error}", workflow.name),
+                )
+                .await;
+        }
+        db.update_workflow(workflow)?;
+    }
+}
+
+// Run `sync` every `interval` until killed. A failed cycle is reported and retried at the next
+// interval rather than ending the daemon
+#[allow(clippy::too_many_arguments)]
+async fn process_daemon(
+    db: &mut Db,
+    rpc_clients: &RpcClients,
+    only: Option<Vec<(Exchange, Option<String>)>>,
+    skip: Option<Vec<(Exchange, Option<String>)>>,
+    interval: Duration,
+    notifier: &Notifier,
+    healthcheck: &Healthcheck,
+    health_endpoint: Option<&HealthEndpoint>,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        let cycle_start = Instant::now();
+        let sync_snapshot = SyncSnapshot::new(db);
+
+        let mut result = process_sync(
+            db,
+            rpc_clients,
+            only.clone(),
+            skip.clone(),
+            None,
+            notifier,
+            verbose,
+        )
+        .await;
+        if result.is_ok() {
+            result = record_fetched_historical_prices(db).await;
+        }
+        healthcheck.report(&result).await;
+        if let Some(health_endpoint) = health_endpoint {
+            health_endpoint.record(&result);
+        }
+
+        let mut summary = sync_snapshot.summary(db, false);
+        if let Err(err) = &result {
+            eprintln!("Sync failed: {err}");
+            summary.errors.push(err.to_string());
+        }
+        notifier.send_summary(&summary).await;
+        metrics::send(db.get_metrics_config()).await;
+        operation_stats::save();
+
+        let next_cycle = interval.saturating_sub(cycle_start.elapsed());
+        if verbose {
+            println!(
+                "Cycle took {}s, next in {}s",
+                cycle_start.elapsed().as_secs(),
+                next_cycle.as_secs()
+            );
+        }
+        tokio::time::sleep(next_cycle).await;
+    }
+}
+
+async fn record_fetched_historical_prices(db: &mut Db) -> Result<(), Box<dyn std::error::Error>> {
+    db.record_historical_prices(
+        coin_gecko::take_fetched_historical_prices()
+            .await
+            .into_iter()
+            .map(|(when, token, price)| HistoricalPrice { token, when, price }),
+    )?;
+    Ok(())
+}
+
+// The records a sync may settle, used to report what a sync changed or a dry run would change
+struct SyncSnapshot {
+    account_lots: HashSet<usize>,
+    pending_deposits: Vec<PendingDeposit>,
+    pending_withdrawals: Vec<PendingWithdrawal>,
+    open_orders: Vec<OpenOrder>,
+    pending_swaps: Vec<PendingSwap>,
+    pending_transfers: Vec<PendingTransfer>,
+}
+
+impl SyncSnapshot {
+    fn new(db: &Db) -> Self {
+        Self {
+            account_lots: db
+                .get_accounts()
+                .into_iter()
+                .flat_map(|account| account.lots)
+                .map(|lot| lot.lot_number)
+                .collect(),
+            pending_deposits: db.pending_deposits(None),
+            pending_withdrawals: db.pending_withdrawals(None),
+            open_orders: db.open_orders(None, None),
+            pending_swaps: db.pending_swaps(),
+            pending_transfers: db.pending_transfers(),
+        }
+    }
+
+    // What changed in `db` since the snapshot was taken. A dry run describes the changes that
+    // would have been recorded
+    fn summary(&self, db: &Db, dry_run: bool) -> CycleSummary {
+        let state = |state: &str| {
+            if dry_run {
+                format!("to be {state}")
+            } else {
+                state.to_string()
+            }
+        };
+        let mut summary = CycleSummary::default();
+
+        for account in db.get_accounts() {
+            for lot in account.lots {
+                if !self.account_lots.contains(&lot.lot_number) {
+                    summary.new_lots.push(format!(
+                        "{} ({}): lot {}, {} acquired {} from {}",
+                        account.address,
+                        account.token,
+                        lot.lot_number,
+                        account.token.format_amount(lot.amount),
+                        lot.acquisition.when,
+                        lot.acquisition.kind,
+                    ));
+                }
+            }
+        }
+
+        let pending_deposits = db.pending_deposits(None);
+        for pending_deposit in &self.pending_deposits {
+            if pending_deposits.contains(pending_deposit) {
+                continue;
+            }
+            let confirmed =
+                db.exchange_transfers(pending_deposit.exchange)
+                    .iter()
+                    .any(|exchange_transfer| {
+                        exchange_transfer.kind == ExchangeTransferKind::Deposit
+                            && exchange_transfer.reference
+                                == pending_deposit.transfer.signature.to_string()
+                    });
+            summary.confirmed_transfers.push(format!(
+                "Deposit to {:?} {}: {} ({})",
+                pending_deposit.exchange,
+                state(if confirmed { "confirmed" } else { "cancelled" }),
+                pending_deposit
+                    .transfer
+                    .from_token
+                    .format_amount(pending_deposit.amount),
+                pending_deposit.transfer.signature,
+            ));
+        }
+
+        let pending_withdrawals = db.pending_withdrawals(None);
+        for pending_withdrawal in &self.pending_withdrawals {
+            if pending_withdrawals.contains(pending_withdrawal) {
+                continue;
+            }
+            let confirmed = db
+                .exchange_transfers(pending_withdrawal.exchange)
+                .iter()
+                .any(|exchange_transfer| {
+                    exchange_transfer.kind == ExchangeTransferKind::Withdrawal
+                        && exchange_transfer.reference == pending_withdrawal.tag
+                });
+            summary.confirmed_transfers.push(format!(
+                "Withdrawal from {:?} {}: {} ({})",
+                pending_withdrawal.exchange,
+                state(if confirmed { "confirmed" } else { "cancelled" }),
+                pending_withdrawal
+                    .token
+                    .format_amount(pending_withdrawal.amount),
+                pending_withdrawal.tag,
+            ));
+        }
+
+        let open_orders = db.open_orders(None, None);
+        for open_order in &self.open_orders {
+            if open_orders
+                .iter()
+                .any(|order| order.order_id == open_order.order_id)
+            {
+                continue;
+            }
+            summary.fills.push(format!(
+                "{:?} {:?} order {}: {} at ${} ({})",
+                open_order.exchange,
+                open_order.side,
+                state("closed"),
+                open_order.pair,
+                open_order.price,
+                open_order.order_id,
+            ));
+        }
+
+        let pending_swaps = db.pending_swaps();
+        for pending_swap in &self.pending_swaps {
+            if pending_swaps
+                .iter()
+                .any(|swap| swap.signature == pending_swap.signature)
+            {
+                continue;
+            }
+            summary.confirmed_transfers.push(format!(
+                "Swap from {} to {} {} ({})",
+                pending_swap.from_token,
+                pending_swap.to_token,
+                state("settled"),
+                pending_swap.signature,
+            ));
+        }
+
+        let pending_transfers = db.pending_transfers();
+        for pending_transfer in &self.pending_transfers {
+            if pending_transfers.contains(pending_transfer) {
+                continue;
+            }
+            summary.confirmed_transfers.push(format!(
+                "Transfer from {} to {} {} ({})",
+                pending_transfer.from_address,
+                pending_transfer.to_address,
+                state("settled"),
+                pending_transfer.signature,
+            ));
+        }
+
+        summary
+    }
+
+    fn println_changes(&self, db: &Db) {
+        let summary = self.summary(db, true);
+        if summary.is_empty() {
+            println!("No changes");
+            return;
+        }
+
+        if !summary.new_lots.is_empty() {
+            println!("Lots to be created:");
+            for new_lot in &summary.new_lots {
+                println!("  {new_lot}");
+            }
+        }
+        for change in summary.confirmed_transfers.iter().chain(&summary.fills) {
+            println!("{change}");
+        }
+    }
+}
+
+fn lot_selection_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("lot_selection")
+        .long("lot-selection")
+        .value_name("METHOD")
+        .takes_value(true)
+        .validator(is_parsable::<LotSelectionMethod>)
+        .default_value(POSSIBLE_LOT_SELECTION_METHOD_VALUES[0])
+        .possible_values(POSSIBLE_LOT_SELECTION_METHOD_VALUES)
+        .help("Lot selection method")
+}
+
+fn export_options_args<'a, 'b>() -> [Arg<'a, 'b>; 3] {
+    [
+        Arg::with_name("precision")
+            .long("precision")
+            .value_name("DECIMALS")
+            .takes_value(true)
+            .default_value("2")
+            .validator(is_parsable::<u32>)
+            .help("Decimal places of exported USD values"),
+        Arg::with_name("min_value")
+            .long("min-value")
+            .value_name("USD")
+            .takes_value(true)
+            .validator(is_parsable::<Decimal>)
+            .help("Leave out rows of a lesser USD value"),
+        Arg::with_name("aggregate_below")
+            .long("aggregate-below")
+            .value_name("USD")
+            .takes_value(true)
+            .validator(is_parsable::<Decimal>)
+            .help(
+                "Merge income lots of a lesser USD value into one row per token and month \
+                   [CSV formats other than turbotax]",
+            ),
+    ]
+}
+
+fn export_options_of(matches: &ArgMatches<'_>) -> ExportOptions {
+    ExportOptions {
+        precision: value_t_or_exit!(matches, "precision", u32),
+        min_value: value_t!(matches, "min_value", Decimal).ok(),
+        aggregate_below: value_t!(matches, "aggregate_below", Decimal).ok(),
+    }
+}
+
+fn durable_nonce_args<'a, 'b>() -> [Arg<'a, 'b>; 2] {
+    [
+        Arg::with_name("nonce_account")
+            .long("nonce-account")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .requires("nonce_authority")
+            .validator(is_valid_pubkey)
+            .help(
+                "Use the durable nonce of this nonce account instead of a recent blockhash, \
+                   so that the transaction does not expire before it is sent",
+            ),
+        Arg::with_name("nonce_authority")
+            .long("nonce-authority")
+            .value_name("KEYPAIR")
+            .takes_value(true)
+            .requires("nonce_account")
+            .validator(is_valid_signer)
+            .help("Authority of the nonce account"),
+    ]
+}
+
+fn durable_nonce_of(
+    matches: &ArgMatches<'_>,
+    wallet_manager: &mut Option<
+        std::rc::Rc<solana_remote_wallet::remote_wallet::RemoteWalletManager>,
+    >,
+) -> Result<Option<DurableNonce>, Box<dyn std::error::Error>> {
+    let Some(account) = pubkey_of(matches, "nonce_account") else {
+        return Ok(None);
+    };
+    let (authority, _) = signer_of(matches, "nonce_authority", wallet_manager)?;
+    Ok(Some(DurableNonce {
+        account,
+        authority: authority.expect("nonce_authority"),
+    }))
+}
+
+fn is_tax_rate(s: String) -> Result<(), String> {
+    is_parsable::<f64>(s.clone())?;
+    let f = s.parse::<f64>().unwrap();
+    if (0. ..=1.).contains(&f) {
+        Ok(())
+    } else {
+        Err(format!("rate must be in the range [0,1]: {f}"))
+    }
+}
+
+// The passphrase of encrypted credentials, from the environment or, when built with the `keychain`
+// feature, the OS keychain
+#[cfg_attr(not(feature = "keychain"), allow(unused_variables))]
+fn credentials_passphrase(db_path: &Path) -> Option<String> {
+    if let Ok(passphrase) = std::env::var("SYS_CREDENTIALS_PASSPHRASE") {
+        return Some(passphrase);
+    }
+    #[cfg(feature = "keychain")]
+    if let Ok(passphrase) = keychain_entry(db_path).and_then(|entry| entry.get_password()) {
+        return Some(passphrase);
+    }
+    None
+}
+
+#[cfg(feature = "keychain")]
+fn keychain_entry(db_path: &Path) -> keyring::Result<keyring::Entry> {
+    let db_path = fs::canonicalize(db_path).unwrap_or_else(|_| db_path.to_path_buf());
+    keyring::Entry::new("sys", &db_path.display().to_string())
+}
+
+// Commands that never modify the database
+fn is_read_only_command(app_matches: &ArgMatches<'_>) -> bool {
+    match app_matches.subcommand() {
+        ("price" | "stats", _) => true,
+        ("db", Some(matches)) => match matches.subcommand() {
+            (
+                "audit-log" | "audit-rounding" | "backup" | "closed-periods" | "export" | "history",
+                _,
+            ) => true,
+            ("cache", Some(matches)) => matches.subcommand_name() == Some("stats"),
+            ("snapshots", Some(matches)) => !matches.is_present("keep"),
+            _ => false,
+        },
+        ("influxdb", Some(matches)) => matches.subcommand_name() == Some("show"),
+        ("profile", Some(matches)) => matches.subcommand_name() == Some("ls"),
+        ("token", Some(matches)) => matches.subcommand_name() == Some("ls"),
+        ("workflow", Some(matches)) => matches.subcommand_name() == Some("ls"),
+        ("notifications", Some(matches)) => matches.subcommand_name() == Some("routes"),
+        ("fees", Some(matches)) => matches.subcommand_name() == Some("report"),
+        ("sync", Some(matches)) => matches.is_present("dry_run"),
+        ("account", Some(matches)) => match matches.subcommand() {
+            ("stream" | "vesting", Some(matches)) => matches.subcommand_name() == Some("ls"),
+            (subcommand, _) => matches!(
+                subcommand,
+                "ls" | "stale"
+                    | "cost-basis"
+                    | "xls"
+                    | "csv"
+                    | "tax-rate"
+                    | "sol-reserves"
+                    | "tax-reserve"
+                    | "wash-sales"
+                    | "rewards-csv"
+                    | "rewards-summary"
+            ),
+        },
+        ("jup", Some(matches)) => matches!(matches.subcommand_name(), Some("quote" | "history")),
+        ("tulip", Some(matches)) => matches.subcommand_name() == Some("apr"),
+        _ => false,
+    }
+}
+
+// The `--db-path` argument, found before the command line is parsed
+fn db_path_arg(default_db_path: &str) -> PathBuf {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--db-path" {
+            if let Some(db_path) = args.next() {
+                return db_path.into();
+            }
+        } else if let Some(db_path) = arg.to_str().and_then(|arg| arg.strip_prefix("--db-path=")) {
+            return db_path.into();
+        }
+    }
+    default_db_path.into()
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     solana_logger::setup_with_default("solana=info");
     let default_db_path = "sell-your-sol";
+
+    // Custom tokens are registered first so the token arguments of the command line accept them
+    match db::custom_tokens(db_path_arg(default_db_path)) {
+        Ok(custom_tokens) => configure_custom_tokens(custom_tokens),
+        Err(err) => eprintln!("Unable to read custom tokens: {err}"),
+    }
     let default_json_rpc_url = "https://api.mainnet-beta.solana.com";
     let default_when = {
         let today = Local::now().date_naive();
         format!("{}/{}/{}", today.year(), today.month(), today.day())
     };
-    let exchanges = ["binance", "binanceus", "coinbase", "kraken"];
+    let exchanges = [
+        "binance",
+        "binanceus",
+        "bitstamp",
+        "bybit",
+        "coinbase",
+        "gemini",
+        "kraken",
+        "kucoin",
+    ];
 
     let app_version = &*app_version();
     let mut app = App::new(crate_name!())
@@ -4398,6 +11025,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .global(true)
                 .help("Database path"),
         )
+        .arg(
+            Arg::with_name("db_backend")
+                .long("db-backend")
+                .value_name("BACKEND")
+                .takes_value(true)
+                .possible_values(POSSIBLE_DB_BACKEND_VALUES)
+                .global(true)
+                .help("Storage backend of a new database; existing databases are converted \
+                       with `db migrate` [default: json]"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("NAME")
+                .takes_value(true)
+                .global(true)
+                .help("Use the accounts, tax rates and exchange credentials of this profile \
+                       [default: SYS_PROFILE environment variable, or the default profile]"),
+        )
         .arg(
             Arg::with_name("json_rpc_url")
                 .short("u")
@@ -4405,9 +11051,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("URL")
                 .takes_value(true)
                 .global(true)
-                .validator(is_url_or_moniker)
+                .validator(is_comma_separated_url_or_moniker_list)
                 .default_value(default_json_rpc_url)
-                .help("JSON RPC URL for the cluster"),
+                .help("JSON RPC URL for the cluster. Several URLs, separated by commas, \
+                       are failed over between in order, skipping unhealthy ones"),
         )
         .arg(
             Arg::with_name("send_json_rpc_urls")
@@ -4419,224 +11066,1649 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                        submit transactions with in addition to --url"),
         )
         .arg(
-            Arg::with_name("helius_json_rpc_url")
-                .long("helius-url")
-                .value_name("URL")
+            Arg::with_name("address_lookup_tables")
+                .long("address-lookup-table")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .validator(is_valid_pubkey)
+                .help("Send deposits, sweeps, splits, wraps and unwraps as v0 transactions that \
+                       load accounts from this address lookup table. May be specified \
+                       multiple times"),
+        )
+        .arg(
+            Arg::with_name("rpc_rate_limit")
+                .long("rpc-rate-limit")
+                .value_name("REQUESTS_PER_SECOND")
+                .takes_value(true)
+                .global(true)
+                .validator(is_parsable::<f64>)
+                .help("Hold back JSON RPC requests to stay below this many requests per second \
+                       to each RPC endpoint [default: SYS_RPC_RATE_LIMIT environment variable, \
+                       or no limit]"),
+        )
+        .arg(
+            Arg::with_name("helius_json_rpc_url")
+                .long("helius-url")
+                .value_name("URL")
+                .takes_value(true)
+                .global(true)
+                .validator(is_url)
+                .help("Helius JSON RPC URL to use only for the proprietary getPriorityFeeEstimate RPC method"),
+        )
+        .arg(
+            Arg::with_name("output_format")
+                .long("output")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .global(true)
+                .possible_values(POSSIBLE_OUTPUT_FORMAT_VALUES)
+                .default_value("display")
+                .help("Output format of account listings, cost basis, pending deposits and \
+                       withdrawals, prices and exchange balances"),
+        )
+        .arg(
+            Arg::with_name("proxy")
+                .long("proxy")
+                .value_name("URL")
+                .takes_value(true)
+                .global(true)
+                .help("HTTP, HTTPS or SOCKS5 proxy for all network requests \
+                       [default: SYS_PROXY environment variable]"),
+        )
+        .arg(
+            Arg::with_name("ca_bundle")
+                .long("ca-bundle")
+                .value_name("PEM_FILE")
+                .takes_value(true)
+                .global(true)
+                .help("Additional trusted root certificates for all network requests \
+                       [default: SYS_CA_BUNDLE environment variable]"),
+        )
+        .arg(
+            Arg::with_name("trace_http")
+                .long("trace-http")
+                .takes_value(false)
+                .global(true)
+                .help("Log the method, URL, status, latency and rate limit headers of each \
+                       network request to stderr, with credentials redacted"),
+        )
+        .arg(
+            Arg::with_name("quiet_notifications")
+                .long("quiet-notifications")
+                .takes_value(false)
+                .global(true)
+                .help("Only send alert notifications"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .takes_value(false)
+                .global(true)
+                .help("Show additional information"),
+        )
+        .arg(
+            Arg::with_name("priority_fee_exact")
+                .long("priority-fee-exact")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(is_parsable::<f64>)
+                .help("Exactly specify the Solana priority fee to use for transactions"),
+        )
+        .arg(
+            Arg::with_name("priority_fee_auto")
+                .long("priority-fee-auto")
+                .value_name("SOL")
+                .takes_value(true)
+                .conflicts_with("priority_fee_exact")
+                .validator(is_parsable::<f64>)
+                .help("Automatically select the Solana priority fee to use for transactions, \
+                       but do not exceed the specified amount of SOL [default]"),
+        )
+        .arg(
+            Arg::with_name("max_total_fees")
+                .long("max-total-fees")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(is_parsable::<f64>)
+                .help("Stop before sending a transaction whose base and priority fees would bring \
+                       the total fees of all transactions sent by this command above the \
+                       specified amount of SOL"),
+        )
+        .arg(
+            Arg::with_name("resend_interval")
+                .long("resend-interval")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .global(true)
+                .validator(|value| match value.parse::<f64>() {
+                    // Also rejects infinite intervals and ones too long for a `Duration`
+                    Ok(resend_interval)
+                        if resend_interval > 0.
+                            && Duration::try_from_secs_f64(resend_interval).is_ok() =>
+                    {
+                        Ok(())
+                    }
+                    _ => Err(format!("Invalid resend interval: {value}")),
+                })
+                .help("Resend an unconfirmed transaction this often [default: 2]"),
+        )
+        .arg(
+            Arg::with_name("confirmation_poll_interval")
+                .long("confirmation-poll-interval")
+                .value_name("MILLISECONDS")
+                .takes_value(true)
+                .global(true)
+                .validator(is_parsable::<u64>)
+                .help("Check whether a sent transaction is confirmed this often [default: 500]"),
+        )
+        .arg(
+            Arg::with_name("send_timeout")
+                .long("send-timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .global(true)
+                .validator(is_parsable::<u64>)
+                .help("Stop waiting for a sent transaction to be confirmed after this long, \
+                       leaving it pending instead of waiting until it expires"),
+        )
+        .arg(
+            Arg::with_name("escalate_priority_fee_after")
+                .long("escalate-priority-fee-after")
+                .value_name("RESENDS")
+                .takes_value(true)
+                .global(true)
+                .validator(is_parsable::<usize>)
+                .help("Replace durable nonce transactions (see --nonce-account) still \
+                       unconfirmed after this many resends with a transaction paying twice \
+                       the priority fee, up to the maximum priority fee. The replacement uses \
+                       the same nonce, so only one of them can land"),
+        )
+        .arg(
+            Arg::with_name("jito_tip")
+                .long("jito-tip")
+                .value_name("SOL")
                 .takes_value(true)
                 .global(true)
-                .validator(is_url)
-                .help("Helius JSON RPC URL to use only for the proprietary getPriorityFeeEstimate RPC method"),
+                .requires("jito_tip_payer")
+                .validator(is_amount)
+                .help("Send transactions to the Jito block engine as bundles that tip this \
+                       amount of SOL, instead of through the JSON RPC URLs"),
         )
         .arg(
-            Arg::with_name("verbose")
-                .short("v")
-                .long("verbose")
-                .takes_value(false)
+            Arg::with_name("jito_tip_payer")
+                .long("jito-tip-payer")
+                .value_name("KEYPAIR")
+                .takes_value(true)
                 .global(true)
-                .help("Show additional information"),
+                .validator(is_keypair)
+                .help("Keypair that pays the Jito tip"),
         )
         .arg(
-            Arg::with_name("priority_fee_exact")
-                .long("priority-fee-exact")
-                .value_name("SOL")
+            Arg::with_name("jito_url")
+                .long("jito-url")
+                .value_name("URL")
                 .takes_value(true)
-                .validator(is_parsable::<f64>)
-                .help("Exactly specify the Solana priority fee to use for transactions"),
+                .global(true)
+                .validator(is_url)
+                .default_value(DEFAULT_JITO_BLOCK_ENGINE_URL)
+                .help("Jito block engine bundles URL"),
+        )
+        .subcommand(
+            SubCommand::with_name("self-update")
+                .about("Replace this executable with the latest release. The download is checked \
+                        against the SHA-256 checksum published with the release, which detects \
+                        corruption but does not authenticate the release")
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .value_name("TAG")
+                        .takes_value(true)
+                        .help("Install this release instead of the latest"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .takes_value(false)
+                        .help("Reinstall even if already running the release"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("price")
+                .about("Get token price")
+                .arg(
+                    Arg::with_name("token")
+                        .value_name("SOL or SPL Token")
+                        .takes_value(true)
+                        .required(true)
+                        .validator(is_valid_token_or_sol)
+                        .default_value("SOL")
+                        .help("Token type"),
+                )
+                .arg(
+                    Arg::with_name("when")
+                        .value_name("YY/MM/DD")
+                        .takes_value(true)
+                        .required(false)
+                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                        .help("Date to fetch the price for [default: current spot price]"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Synchronize with all exchanges and accounts")
+                .arg(
+                    Arg::with_name("max_epochs_to_process")
+                        .long("max-epochs-to-process")
+                        .value_name("NUMBER")
+                        .takes_value(true)
+                        .validator(is_parsable::<u64>)
+                        .help("Only process up to this number of epochs for account balance changes [default: all]"),
+                )
+                .arg(
+                    Arg::with_name("only")
+                        .long("only")
+                        .value_name("EXCHANGE[:ACCOUNT_NAME],...")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .validator(|value| parse_exchange_account_selector(&value).map(|_| ()))
+                        .help("Only synchronize these exchange accounts. An exchange without an \
+                               account name selects all of its configured accounts \
+                               [default: the default account of each configured exchange]"),
+                )
+                .arg(
+                    Arg::with_name("skip")
+                        .long("skip")
+                        .value_name("EXCHANGE[:ACCOUNT_NAME],...")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .validator(|value| parse_exchange_account_selector(&value).map(|_| ()))
+                        .help("Do not synchronize these exchange accounts"),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .takes_value(false)
+                        .help("Report what would change without updating the database, \
+                               submitting transactions or sending notifications"),
+                ))
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Synchronize with all exchanges and accounts on a schedule, until killed")
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .value_name("DURATION")
+                        .takes_value(true)
+                        .default_value("15m")
+                        .validator(|value| duration_of(&value).map(|_| ()))
+                        .help("Time from the start of one synchronization to the next, \
+                               such as 90s, 15m or 1h"),
+                )
+                .arg(
+                    Arg::with_name("only")
+                        .long("only")
+                        .value_name("EXCHANGE[:ACCOUNT_NAME],...")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .validator(|value| parse_exchange_account_selector(&value).map(|_| ()))
+                        .help("Only synchronize these exchange accounts"),
+                )
+                .arg(
+                    Arg::with_name("skip")
+                        .long("skip")
+                        .value_name("EXCHANGE[:ACCOUNT_NAME],...")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .validator(|value| parse_exchange_account_selector(&value).map(|_| ()))
+                        .help("Do not synchronize these exchange accounts"),
+                )
+                .arg(
+                    Arg::with_name("health_listen")
+                        .long("health-listen")
+                        .value_name("ADDRESS:PORT")
+                        .takes_value(true)
+                        .validator(is_parsable::<std::net::SocketAddr>)
+                        .help("Serve the daemon's health over HTTP on this address. It is \
+                               unhealthy when no synchronization has succeeded in three \
+                               intervals [default: no health endpoint]"),
+                )
+                .arg(
+                    Arg::with_name("metrics_listen")
+                        .long("metrics-listen")
+                        .value_name("ADDRESS:PORT")
+                        .takes_value(true)
+                        .validator(is_parsable::<std::net::SocketAddr>)
+                        .help("Serve metrics for Prometheus to scrape on this address \
+                               [default: no metrics endpoint]"),
+                ))
+        .subcommand(
+            SubCommand::with_name("db")
+                .about("Database management")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .setting(AppSettings::InferSubcommands)
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("Import another database, or the lot history of another portfolio tool")
+                        .arg(
+                            Arg::with_name("other_db_path")
+                                .value_name("PATH")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Path to the database, JSON export or CSV export to \
+                                       import"),
+                        )
+                        .arg(
+                            Arg::with_name("format")
+                                .long("format")
+                                .value_name("FORMAT")
+                                .takes_value(true)
+                                .default_value(POSSIBLE_IMPORT_FORMAT_VALUES[0])
+                                .possible_values(POSSIBLE_IMPORT_FORMAT_VALUES)
+                                .help("Format of PATH. A JSON export of `db export` replaces \
+                                       all records of the database. Koinly and CoinTracking \
+                                       transaction categories are mapped onto sys acquisition \
+                                       and disposal kinds"),
+                        )
+                        .arg(
+                            Arg::with_name("address")
+                                .long("address")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .validator(is_valid_pubkey)
+                                .required_ifs(&[("format", "koinly"), ("format", "cointracking")])
+                                .help("Account to record the imported lots in. \
+                                       Missing accounts are added with syncing disabled"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Export all records of the database, for `db import` on another \
+                                machine, versioned backups or external analysis")
+                        .arg(
+                            Arg::with_name("path")
+                                .value_name("PATH")
+                                .takes_value(true)
+                                .help("File to write the export to [default: standard output]"),
+                        )
+                        .arg(
+                            Arg::with_name("format")
+                                .long("format")
+                                .value_name("FORMAT")
+                                .takes_value(true)
+                                .default_value("json")
+                                .possible_values(&["json"])
+                                .help("Export format. JSON exports of the same records are \
+                                       identical, and exchange credentials are left out"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("backup")
+                        .about("Copy the database directory to PATH, or encrypt the database and \
+                                upload it to cloud storage. The SYS_BACKUP_PASSPHRASE environment \
+                                variable provides the encryption passphrase")
+                        .arg(
+                            Arg::with_name("path")
+                                .value_name("PATH")
+                                .takes_value(true)
+                                .conflicts_with("to")
+                                .help("New directory to copy the database files into"),
+                        )
+                        .arg(
+                            Arg::with_name("gzip")
+                                .long("gzip")
+                                .takes_value(false)
+                                .requires("path")
+                                .help("Compress the copied files with gzip"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .value_name("LOCATION")
+                                .takes_value(true)
+                                .required_unless("path")
+                                .validator(is_parsable::<BackupLocation>)
+                                .help("s3://bucket/path or gs://bucket/path. \
+                                       S3 credentials are read from AWS_ACCESS_KEY_ID, \
+                                       AWS_SECRET_ACCESS_KEY and AWS_REGION; \
+                                       GCS HMAC credentials from GCS_HMAC_ACCESS_KEY_ID \
+                                       and GCS_HMAC_SECRET"),
+                        )
+                        .arg(
+                            Arg::with_name("keep")
+                                .long("keep")
+                                .value_name("COUNT")
+                                .takes_value(true)
+                                .validator(|value| match value.parse::<usize>() {
+                                    // Keeping none would delete the backup just uploaded too
+                                    Ok(keep) if keep > 0 => Ok(()),
+                                    _ => Err(format!("Invalid backup count: {value}")),
+                                })
+                                .requires("to")
+                                .help("Delete all but the most recent COUNT backups at LOCATION \
+                                       [default: keep all backups]"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Replace the database with a copy made by `db backup`, or a \
+                                backup from cloud storage")
+                        .arg(
+                            Arg::with_name("path")
+                                .value_name("PATH")
+                                .takes_value(true)
+                                .conflicts_with("from")
+                                .help("Directory written by `db backup PATH`, or a snapshot in the \
+                                       snapshots/ subdirectory of the database. The current \
+                                       database files are first copied into the snapshots/ \
+                                       subdirectory"),
+                        )
+                        .arg(
+                            Arg::with_name("from")
+                                .long("from")
+                                .value_name("LOCATION")
+                                .takes_value(true)
+                                .required_unless("path")
+                                .validator(is_parsable::<BackupLocation>)
+                                .help("Backup to restore, or the directory passed to `db backup` \
+                                       to restore its most recent backup"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("snapshots")
+                        .about("List the snapshots of the database taken before each command that \
+                                modifies it, or set how many are kept")
+                        .arg(
+                            Arg::with_name("keep")
+                                .long("keep")
+                                .value_name("COUNT")
+                                .takes_value(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Keep the most recent COUNT snapshots, 0 to stop taking \
+                                       them"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("migrate")
+                        .about("Convert the database to another storage backend in place")
+                        .arg(
+                            Arg::with_name("backend")
+                                .value_name("BACKEND")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(POSSIBLE_DB_BACKEND_VALUES)
+                                .help("Storage backend to convert to"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("audit-log")
+                        .about("Show the log of manual database corrections")
+                )
+                .subcommand(
+                    SubCommand::with_name("history")
+                        .about("Show the journal of commands that changed accounts, lots, \
+                                disposals or pending transfers")
+                )
+                .subcommand(
+                    SubCommand::with_name("undo")
+                        .about("Revert the changes of the most recent journaled commands")
+                        .arg(
+                            Arg::with_name("count")
+                                .value_name("N")
+                                .takes_value(true)
+                                .default_value("1")
+                                .validator(is_parsable::<usize>)
+                                .help("Number of commands to revert"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("audit-rounding")
+                        .about("Report how far the basis, income and realized gains figured with \
+                                float arithmetic drift from the exact amounts")
+                )
+                .subcommand(
+                    SubCommand::with_name("cache")
+                        .about("Manage the cache of block dates fetched from the cluster")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(
+                            SubCommand::with_name("stats")
+                                .about("Show the size of the cache")
+                        )
+                        .subcommand(
+                            SubCommand::with_name("clear")
+                                .about("Delete the cache, dates are fetched again as needed")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("price-cache")
+                        .about("Manage the cache of historical prices fetched from CoinGecko")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(
+                            SubCommand::with_name("warm")
+                                .about("Fetch the daily prices of a date range in one request")
+                                .arg(
+                                    Arg::with_name("from")
+                                        .long("from")
+                                        .value_name("YY/MM/DD")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                                        .help("First date to fetch the price for"),
+                                )
+                                .arg(
+                                    Arg::with_name("to")
+                                        .long("to")
+                                        .value_name("YY/MM/DD")
+                                        .takes_value(true)
+                                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                                        .help("Last date to fetch the price for [default: yesterday]"),
+                                )
+                                .arg(
+                                    Arg::with_name("token")
+                                        .long("token")
+                                        .value_name("SOL or SPL Token")
+                                        .takes_value(true)
+                                        .validator(is_valid_token_or_sol)
+                                        .default_value("SOL")
+                                        .help("Token to fetch prices for"),
+                                )
+                        )
+                        .subcommand(
+                            SubCommand::with_name("prune")
+                                .about("Remove cached prices, they are fetched again as needed")
+                                .arg(
+                                    Arg::with_name("before")
+                                        .long("before")
+                                        .value_name("YY/MM/DD")
+                                        .takes_value(true)
+                                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                                        .help("Only remove prices of dates before this one \
+                                               [default: remove all prices]"),
+                                )
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("encrypt")
+                        .about("Encrypt exchange credentials and the InfluxDb configuration with a \
+                                passphrase, taken from SYS_CREDENTIALS_PASSPHRASE or prompted for. \
+                                Later commands unlock them with SYS_CREDENTIALS_PASSPHRASE")
+                )
+                .subcommand(
+                    SubCommand::with_name("decrypt")
+                        .about("Store exchange credentials and the InfluxDb configuration in \
+                                plaintext again")
+                )
+                .subcommand(
+                    SubCommand::with_name("dedupe")
+                        .about("Find lots and pending operations that were recorded twice")
+                        .arg(
+                            Arg::with_name("confirm")
+                                .long("confirm")
+                                .takes_value(false)
+                                .help("Delete duplicate lots, keeping the lowest numbered lot"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("close-period")
+                        .about("Lock the records dated in a month against modification")
+                        .arg(
+                            Arg::with_name("period")
+                                .value_name("YYYY-MM")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(|value| period_of(&value).map(|_| ()))
+                                .help("Month to close"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("reopen-period")
+                        .about("Unlock the records of a closed month, recording why in the audit log")
+                        .arg(
+                            Arg::with_name("period")
+                                .value_name("YYYY-MM")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(|value| period_of(&value).map(|_| ()))
+                                .help("Month to reopen"),
+                        )
+                        .arg(
+                            Arg::with_name("reason")
+                                .long("reason")
+                                .value_name("TEXT")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Why the closed month needs to be changed"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("closed-periods")
+                        .about("List the closed months")
+                )
+                .subcommand(
+                    SubCommand::with_name("repair-pending")
+                        .about("Recheck pending and recently cancelled deposits, transfers and \
+                                swaps on-chain, and fix records that were cancelled although \
+                                their transaction landed")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("influxdb")
+                .about("InfluxDb metrics management")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .setting(AppSettings::InferSubcommands)
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Clear InfluxDb configuration")
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Show InfluxDb configuration")
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Set InfluxDb configuration")
+                        .arg(
+                            Arg::with_name("url")
+                                .value_name("URL")
+                                .takes_value(true)
+                                .required(true)
+                                .help("InfluxDb URL"),
+                        )
+                        .arg(
+                            Arg::with_name("token")
+                                .value_name("TOKEN")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Access Token"),
+                        )
+                        .arg(
+                            Arg::with_name("bucket")
+                                .value_name("BUCKET")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Bucket name"),
+                        )
+                )
         )
-        .arg(
-            Arg::with_name("priority_fee_auto")
-                .long("priority-fee-auto")
-                .value_name("SOL")
-                .takes_value(true)
-                .conflicts_with("priority_fee_exact")
-                .validator(is_parsable::<f64>)
-                .help("Automatically select the Solana priority fee to use for transactions, \
-                       but do not exceed the specified amount of SOL [default]"),
+        .subcommand(
+            SubCommand::with_name("notifications")
+                .about("Notification routing. Without routes, every notification is sent to \
+                        every destination configured in the environment")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .setting(AppSettings::InferSubcommands)
+                .subcommand(
+                    SubCommand::with_name("routes")
+                        .about("Show the notification routes")
+                )
+                .subcommand(
+                    SubCommand::with_name("add-route")
+                        .about("Send notifications of a severity to destinations")
+                        .arg(
+                            Arg::with_name("severity")
+                                .value_name("SEVERITY")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(POSSIBLE_SEVERITY_VALUES)
+                                .help("Severity of the notifications to route"),
+                        )
+                        .arg(
+                            Arg::with_name("destinations")
+                                .value_name("DESTINATION")
+                                .takes_value(true)
+                                .required(true)
+                                .multiple(true)
+                                .possible_values(POSSIBLE_DESTINATION_VALUES)
+                                .help("Where to send them"),
+                        )
+                        .arg(
+                            Arg::with_name("kind")
+                                .long("kind")
+                                .value_name("KIND")
+                                .takes_value(true)
+                                .possible_values(POSSIBLE_NOTIFICATION_KIND_VALUES)
+                                .help("Only route notifications of this kind"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("remove-route")
+                        .about("Remove a notification route")
+                        .arg(
+                            Arg::with_name("route")
+                                .value_name("NUMBER")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Number of the route, as shown by `notifications routes`"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("clear-routes")
+                        .about("Remove all notification routes")
+                )
         )
         .subcommand(
-            SubCommand::with_name("price")
-                .about("Get token price")
+            SubCommand::with_name("stats")
+                .about("Success rates and median latencies of transaction sends and \
+                        confirmations, exchange API calls and syncs")
                 .arg(
-                    Arg::with_name("token")
-                        .value_name("SOL or SPL Token")
+                    Arg::with_name("days")
+                        .long("days")
+                        .value_name("DAYS")
                         .takes_value(true)
-                        .required(true)
-                        .validator(is_valid_token_or_sol)
-                        .default_value("SOL")
-                        .help("Token type"),
+                        .default_value("30")
+                        .validator(is_parsable::<u32>)
+                        .help("Summarize the operations of this many days, up to 90"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fees")
+                .about("Transaction fees")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .setting(AppSettings::InferSubcommands)
+                .subcommand(
+                    SubCommand::with_name("report")
+                        .about("Total the priority fees paid by landed transactions by month and \
+                                by kind of operation")
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .value_name("YYYY")
+                                .takes_value(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Limit report to fees paid in the given year"),
+                        ),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("workflow")
+                .about("Multi-step operations that resume where they left off after a crash or \
+                        error")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .setting(AppSettings::InferSubcommands)
+                .subcommand(
+                    SubCommand::with_name("ls")
+                        .about("List workflows")
+                )
+                .subcommand(
+                    SubCommand::with_name("resume")
+                        .about("Resume a workflow")
+                        .arg(
+                            Arg::with_name("id")
+                                .value_name("ID")
+                                .takes_value(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Workflow to resume [default: all unfinished workflows]"),
+                        )
+                        .arg(
+                            Arg::with_name("retry_step")
+                                .long("retry-step")
+                                .takes_value(false)
+                                .help("Submit the current step again after it failed or was \
+                                       interrupted. Check that an interrupted step did not go \
+                                       through first"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("cancel")
+                        .about("Cancel a workflow, leaving the steps already taken as they are")
+                        .arg(
+                            Arg::with_name("id")
+                                .value_name("ID")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Workflow to cancel"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("deposit-and-sell")
+                        .about("Deposit SOL into an exchange and sell it once the deposit is \
+                                confirmed")
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_amount_or_all)
+                                .help("The amount to deposit and sell, in SOL; accepts keyword ALL"),
+                        )
+                        .arg(
+                            Arg::with_name("exchange")
+                                .long("exchange")
+                                .value_name("EXCHANGE")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(&exchanges)
+                                .help("Exchange to deposit into"),
+                        )
+                        .arg(
+                            Arg::with_name("exchange_account")
+                                .long("exchange-account")
+                                .value_name("ACCOUNT_NAME")
+                                .takes_value(true)
+                                .help("User-defined name of the exchange account to use [default: '']"),
+                        )
+                        .arg(
+                            Arg::with_name("from")
+                                .long("from")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Source account of the deposit"),
+                        )
+                        .arg(
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_valid_signer)
+                                .help("Authority of the source account [default: --from]"),
+                        )
+                        .arg(
+                            Arg::with_name("at")
+                                .long("at")
+                                .value_name("PRICE")
+                                .takes_value(true)
+                                .validator(is_parsable::<f64>)
+                                .help("Sell at this limit price"),
+                        )
+                        .arg(
+                            Arg::with_name("market")
+                                .long("market")
+                                .takes_value(false)
+                                .help("Sell at market"),
+                        )
+                        .group(
+                            ArgGroup::with_name("price")
+                                .args(&["at", "market"])
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("pair")
+                                .long("pair")
+                                .value_name("PAIR")
+                                .takes_value(true)
+                                .help("Market to sell on [default: the exchange's preferred SOL/USD pair]"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("sweep-then-deposit")
+                        .about("Sweep an account into a system account, then deposit what was \
+                                swept into an exchange")
+                        .arg(
+                            Arg::with_name("from")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Account to sweep"),
+                        )
+                        .arg(
+                            Arg::with_name("authority")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_signer)
+                                .help("Authority of the account to sweep"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Account to sweep into and deposit from"),
+                        )
+                        .arg(
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_valid_signer)
+                                .help("Authority of the --to account [default: --to]"),
+                        )
+                        .arg(
+                            Arg::with_name("token")
+                                .long("token")
+                                .value_name("TOKEN")
+                                .takes_value(true)
+                                .validator(is_valid_token)
+                                .help("Token to sweep and deposit [default: SOL]"),
+                        )
+                        .arg(
+                            Arg::with_name("retain")
+                                .long("retain")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount)
+                                .help("Amount to leave in the swept account"),
+                        )
+                        .arg(
+                            Arg::with_name("exchange")
+                                .long("exchange")
+                                .value_name("EXCHANGE")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(&exchanges)
+                                .help("Exchange to deposit into"),
+                        )
+                        .arg(
+                            Arg::with_name("exchange_account")
+                                .long("exchange-account")
+                                .value_name("ACCOUNT_NAME")
+                                .takes_value(true)
+                                .help("User-defined name of the exchange account to use [default: '']"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("unstake-then-swap")
+                        .about("Deactivate a stake account, withdraw it into a system account once \
+                                it is inactive, then swap what was withdrawn for a token")
+                        .arg(
+                            Arg::with_name("stake_address")
+                                .value_name("STAKE_ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Stake account to unstake"),
+                        )
+                        .arg(
+                            Arg::with_name("authority")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_signer)
+                                .help("Stake and withdraw authority of the stake account"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Tracked system account to withdraw into and swap from"),
+                        )
+                        .arg(
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_valid_signer)
+                                .help("Authority of the --to account [default: --to]"),
+                        )
+                        .arg(
+                            Arg::with_name("to_token")
+                                .long("for")
+                                .value_name("TOKEN")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_token)
+                                .help("Token to swap the unstaked SOL for"),
+                        )
+                        .arg(
+                            Arg::with_name("slippage_bps")
+                                .long("slippage")
+                                .value_name("BPS")
+                                .takes_value(true)
+                                .validator(is_parsable::<u64>)
+                                .default_value("100")
+                                .help("Maximum slippage bps"),
+                        )
+                        .arg(
+                            Arg::with_name("max_coingecko_value_percentage_loss")
+                                .long("max-coingecko-value-percentage-loss")
+                                .value_name("PERCENT")
+                                .takes_value(true)
+                                .validator(is_parsable::<f64>)
+                                .default_value("5")
+                                .help("Reject if the value lost relative to CoinGecko token \
+                                      price exceeds this percentage"),
+                        )
                 )
-                .arg(
-                    Arg::with_name("when")
-                        .value_name("YY/MM/DD")
-                        .takes_value(true)
-                        .required(false)
-                        .validator(|value| naivedate_of(&value).map(|_| ()))
-                        .help("Date to fetch the price for [default: current spot price]"),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Profile management")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .setting(AppSettings::InferSubcommands)
+                .subcommand(
+                    SubCommand::with_name("ls")
+                        .about("List profiles")
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a profile, with its own accounts, tax rates and exchange credentials")
+                        .arg(
+                            Arg::with_name("name")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Profile name"),
+                        )
                 )
         )
         .subcommand(
-            SubCommand::with_name("sync")
-                .about("Synchronize with all exchanges and accounts"))
-                .arg(
-                    Arg::with_name("max_epochs_to_process")
-                        .long("max-epochs-to-process")
-                        .value_name("NUMBER")
-                        .takes_value(true)
-                        .validator(is_parsable::<u64>)
-                        .help("Only process up to this number of epochs for account balance changes [default: all]"),
+            SubCommand::with_name("token")
+                .about("Token management")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .setting(AppSettings::InferSubcommands)
+                .subcommand(
+                    SubCommand::with_name("ls")
+                        .about("List custom tokens")
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Register an SPL token by mint address")
+                        .arg(
+                            Arg::with_name("mint")
+                                .value_name("MINT")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Token mint address"),
+                        )
+                        .arg(
+                            Arg::with_name("symbol")
+                                .long("symbol")
+                                .value_name("SYMBOL")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Name to refer to the token by"),
+                        )
+                        .arg(
+                            Arg::with_name("coingecko_id")
+                                .long("coingecko-id")
+                                .value_name("ID")
+                                .takes_value(true)
+                                .required(true)
+                                .help("CoinGecko id of the token, for price data"),
+                        )
+                        .arg(
+                            Arg::with_name("decimals")
+                                .long("decimals")
+                                .value_name("N")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_parsable::<u8>)
+                                .help("Token decimals, checked against the mint"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("symbol")
+                        .about("Show or set the symbol amounts of a token are written with in \
+                                console output, notifications and export descriptions")
+                        .arg(
+                            Arg::with_name("token")
+                                .value_name("TOKEN")
+                                .takes_value(true)
+                                .validator(is_valid_token_or_sol)
+                                .help("Token to show or set the symbol of. Without it, list the \
+                                       tokens whose symbol is set"),
+                        )
+                        .arg(
+                            Arg::with_name("symbol")
+                                .value_name("SYMBOL")
+                                .takes_value(true)
+                                .requires("token")
+                                .help("Symbol prefixed to amounts of the token, such as \"◎\", \
+                                       \"mSOL \" or an emoji"),
+                        )
+                        .arg(
+                            Arg::with_name("default")
+                                .long("default")
+                                .takes_value(false)
+                                .requires("token")
+                                .conflicts_with("symbol")
+                                .help("Restore the default symbol of the token"),
+                        )
                 )
+        )
         .subcommand(
-            SubCommand::with_name("db")
-                .about("Database management")
+            SubCommand::with_name("account")
+                .about("Account management")
                 .setting(AppSettings::SubcommandRequiredElseHelp)
                 .setting(AppSettings::InferSubcommands)
                 .subcommand(
-                    SubCommand::with_name("import")
-                        .about("Import another database")
+                    SubCommand::with_name("add")
+                        .about("Register an account")
+                        .arg(
+                            Arg::with_name("token")
+                                .value_name("SOL or SPL Token")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_token_or_sol)
+                                .help("Token type"),
+                        )
+                        .arg(
+                            Arg::with_name("address")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Account address to add"),
+                        )
+                        .arg(
+                            Arg::with_name("description")
+                                .short("d")
+                                .long("description")
+                                .value_name("TEXT")
+                                .takes_value(true)
+                                .help("Account description"),
+                        )
+                        .arg(
+                            Arg::with_name("when")
+                                .short("w")
+                                .long("when")
+                                .value_name("YY/MM/DD")
+                                .takes_value(true)
+                                .validator(|value| naivedate_of(&value).map(|_| ()))
+                                .help("Date acquired (ignored if the --transaction argument is provided) [default: now]"),
+                        )
+                        .arg(
+                            Arg::with_name("transaction")
+                                .short("t")
+                                .long("transaction")
+                                .value_name("SIGNATURE")
+                                .takes_value(true)
+                                .validator(is_parsable::<Signature>)
+                                .help("Acquisition transaction signature"),
+                        )
+                        .arg(
+                            Arg::with_name("price")
+                                .short("p")
+                                .long("price")
+                                .value_name("USD")
+                                .takes_value(true)
+                                .validator(is_parsable::<f64>)
+                                .help("Acquisition price per SOL/token [default: market price on acquisition date]"),
+                        )
+                        .arg(
+                            Arg::with_name("income")
+                                .long("income")
+                                .takes_value(false)
+                                .conflicts_with("transaction")
+                                .help("Consider the acquisition value to be subject to income tax [default: post-tax fiat]"),
+                        )
+                        .arg(
+                            Arg::with_name("no_sync")
+                                .long("no-sync")
+                                .takes_value(false)
+                                .help("Never synchronize this account with the on-chain state (advanced; uncommon)"),
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .long("amount")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_parsable::<f64>)
+                                .conflicts_with("transaction")
+                                .help("Consider the account to have this amount of tokens rather than \
+                                       using the current value on chain (advanced; uncommon)"),
+                        )
+                        .arg(
+                            Arg::with_name("neg_amount")
+                                .long("neg-amount")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_parsable::<f64>)
+                                .conflicts_with("amount")
+                                .conflicts_with("transaction")
+                                .help("If a negative amount is specified, subtract the provided AMOUNT from the \
+                                       on-chain balance (advanced; uncommon)"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("dispose")
+                        .about("Manually record the disposal of SOL/tokens from an account")
+                        .arg(
+                            Arg::with_name("token")
+                                .value_name("SOL or SPL Token")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_token_or_sol)
+                                .help("Token type"),
+                        )
+                        .arg(
+                            Arg::with_name("address")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Account that the SOL/tokens was/where disposed from"),
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount)
+                                .required(true)
+                                .help("Amount of SOL/tokens that was/where disposed from the account"),
+                        )
+                        .arg(
+                            Arg::with_name("description")
+                                .short("d")
+                                .long("description")
+                                .value_name("TEXT")
+                                .takes_value(true)
+                                .help("Description to associate with the disposal event"),
+                        )
+                        .arg(
+                            Arg::with_name("when")
+                                .short("w")
+                                .long("when")
+                                .value_name("YY/MM/DD")
+                                .takes_value(true)
+                                .validator(|value| naivedate_of(&value).map(|_| ()))
+                                .help("Disposal date [default: now]"),
+                        )
+                        .arg(
+                            Arg::with_name("price")
+                                .short("p")
+                                .long("price")
+                                .value_name("USD")
+                                .takes_value(true)
+                                .validator(is_parsable::<f64>)
+                                .help("Disposal price per SOL/token [default: market price on disposal date]"),
+                        )
+                        .arg(lot_selection_arg())
+                        .arg(lot_numbers_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("ls")
+                        .about("List registered accounts")
+                        .alias("sl")
+                        .arg(
+                            Arg::with_name("all")
+                                .short("a")
+                                .long("all")
+                                .help("Display all lots")
+                        )
+                        .arg(
+                            Arg::with_name("account")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .validator(is_valid_pubkey)
+                                .help("Limit output to this address"),
+                        )
+                        .arg(
+                            Arg::with_name("summary")
+                                .long("summary")
+                                .takes_value(false)
+                                .help("Limit output to summary line"),
+                        )
+                        .arg(
+                            Arg::with_name("as_of")
+                                .long("as-of")
+                                .value_name("YY/MM/DD")
+                                .takes_value(true)
+                                .conflicts_with_all(&["archived", "summary"])
+                                .validator(|value| naivedate_of(&value).map(|_| ()))
+                                .help("Value holdings and unrealized gains using historical \
+                                       prices on this date"),
+                        )
+                        .arg(
+                            Arg::with_name("archived")
+                                .long("archived")
+                                .takes_value(false)
+                                .conflicts_with_all(&["account", "summary"])
+                                .help("List archived accounts instead"),
+                        )
+                        .arg(
+                            Arg::with_name("all_profiles")
+                                .long("all-profiles")
+                                .takes_value(false)
+                                .conflicts_with("archived")
+                                .help("Combine the accounts of all profiles"),
+                        )
                         .arg(
-                            Arg::with_name("other_db_path")
-                                .value_name("PATH")
+                            Arg::with_name("columns")
+                                .long("columns")
+                                .value_name("COLUMN,...")
                                 .takes_value(true)
-                                .help("Path to the database to import"),
-                        )
+                                .multiple(true)
+                                .use_delimiter(true)
+                                .possible_values(POSSIBLE_LOT_COLUMN_VALUES)
+                                .help("Lot columns to list, in order \
+                                       [default: as set by `account lot-columns`]"),
+                        ),
                 )
-        )
-        .subcommand(
-            SubCommand::with_name("influxdb")
-                .about("InfluxDb metrics management")
-                .setting(AppSettings::SubcommandRequiredElseHelp)
-                .setting(AppSettings::InferSubcommands)
                 .subcommand(
-                    SubCommand::with_name("clear")
-                        .about("Clear InfluxDb configuration")
+                    SubCommand::with_name("stale")
+                        .about("List accounts without balance changes or sync activity")
+                        .arg(
+                            Arg::with_name("days")
+                                .long("days")
+                                .value_name("N")
+                                .takes_value(true)
+                                .validator(is_parsable::<u32>)
+                                .default_value("90")
+                                .help("Number of idle days before an account is considered stale"),
+                        ),
                 )
                 .subcommand(
-                    SubCommand::with_name("show")
-                        .about("Show InfluxDb configuration")
+                    SubCommand::with_name("cost-basis")
+                        .about("Display average cost basis of holdings")
+                        .arg(
+                            Arg::with_name("when")
+                                .value_name("YY/MM/DD")
+                                .takes_value(true)
+                                .required(false)
+                                .validator(|value| naivedate_of(&value).map(|_| ()))
+                                .default_value(&default_when)
+                                .help("Date to calculate cost basis for")
+                        )
                 )
                 .subcommand(
-                    SubCommand::with_name("set")
-                        .about("Set InfluxDb configuration")
+                    SubCommand::with_name("xls")
+                        .about("Export an Excel spreadsheet file")
                         .arg(
-                            Arg::with_name("url")
-                                .value_name("URL")
+                            Arg::with_name("outfile")
+                                .value_name("FILEPATH")
                                 .takes_value(true)
-                                .required(true)
-                                .help("InfluxDb URL"),
+                                .help(".xls file to write"),
                         )
                         .arg(
-                            Arg::with_name("token")
-                                .value_name("TOKEN")
+                            Arg::with_name("year")
+                                .long("year")
+                                .value_name("YYYY")
                                 .takes_value(true)
-                                .required(true)
-                                .help("Access Token"),
+                                .validator(is_parsable::<usize>)
+                                .help("Limit export to realized gains affecting the given year"),
                         )
                         .arg(
-                            Arg::with_name("bucket")
-                                .value_name("BUCKET")
-                                .takes_value(true)
-                                .required(true)
-                                .help("Bucket name"),
+                            Arg::with_name("wash_sales")
+                                .long("wash-sales")
+                                .takes_value(false)
+                                .help("Add a column with the loss of each disposal disallowed \
+                                       by wash sales"),
                         )
+                        .args(&export_options_args()),
                 )
-        )
-        .subcommand(
-            SubCommand::with_name("account")
-                .about("Account management")
-                .setting(AppSettings::SubcommandRequiredElseHelp)
-                .setting(AppSettings::InferSubcommands)
                 .subcommand(
-                    SubCommand::with_name("add")
-                        .about("Register an account")
+                    SubCommand::with_name("csv")
+                        .about("Export a CSV file for import into other tax tools")
                         .arg(
-                            Arg::with_name("token")
-                                .value_name("SOL or SPL Token")
-                                .takes_value(true)
-                                .required(true)
-                                .validator(is_valid_token_or_sol)
-                                .help("Token type"),
-                        )
-                        .arg(
-                            Arg::with_name("address")
-                                .value_name("ADDRESS")
+                            Arg::with_name("outfile")
+                                .value_name("FILEPATH")
                                 .takes_value(true)
                                 .required(true)
-                                .validator(is_valid_pubkey)
-                                .help("Account address to add"),
-                        )
-                        .arg(
-                            Arg::with_name("description")
-                                .short("d")
-                                .long("description")
-                                .value_name("TEXT")
-                                .takes_value(true)
-                                .help("Account description"),
+                                .help(".csv file to write"),
                         )
                         .arg(
-                            Arg::with_name("when")
-                                .short("w")
-                                .long("when")
-                                .value_name("YY/MM/DD")
+                            Arg::with_name("format")
+                                .long("format")
+                                .value_name("FORMAT")
                                 .takes_value(true)
-                                .validator(|value| naivedate_of(&value).map(|_| ()))
-                                .help("Date acquired (ignored if the --transaction argument is provided) [default: now]"),
+                                .default_value(POSSIBLE_EXPORT_FORMAT_VALUES[0])
+                                .possible_values(POSSIBLE_EXPORT_FORMAT_VALUES)
+                                .help("Export format: turbotax exports disposals only, koinly \
+                                       and cointracker export all acquisitions and disposals"),
                         )
                         .arg(
-                            Arg::with_name("transaction")
-                                .short("t")
-                                .long("transaction")
-                                .value_name("SIGNATURE")
+                            Arg::with_name("year")
+                                .long("year")
+                                .value_name("YYYY")
                                 .takes_value(true)
-                                .validator(is_parsable::<Signature>)
-                                .help("Acquisition transaction signature"),
+                                .validator(is_parsable::<usize>)
+                                .help("Limit export to transactions in the given year"),
                         )
+                        .args(&export_options_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("harvest")
+                        .about("Propose lots to dispose of to realize a tax loss")
                         .arg(
-                            Arg::with_name("price")
-                                .short("p")
-                                .long("price")
+                            Arg::with_name("target_loss")
+                                .long("target-loss")
                                 .value_name("USD")
                                 .takes_value(true)
+                                .required(true)
                                 .validator(is_parsable::<f64>)
-                                .help("Acquisition price per SOL/token [default: market price on acquisition date]"),
+                                .help("Loss to realize"),
                         )
                         .arg(
-                            Arg::with_name("income")
-                                .long("income")
-                                .takes_value(false)
-                                .conflicts_with("transaction")
-                                .help("Consider the acquisition value to be subject to income tax [default: post-tax fiat]"),
+                            Arg::with_name("term")
+                                .long("term")
+                                .value_name("TERM")
+                                .takes_value(true)
+                                .possible_values(&["short", "long"])
+                                .help("Only dispose of short-term or long-term lots [default: both]"),
                         )
                         .arg(
-                            Arg::with_name("no_sync")
-                                .long("no-sync")
+                            Arg::with_name("execute")
+                                .long("execute")
                                 .takes_value(false)
-                                .help("Never synchronize this account with the on-chain state (advanced; uncommon)"),
+                                .help("Dispose of the proposed lots: sell at market on the \
+                                       exchange holding them, or swap to USDC with Jupiter \
+                                       from the --signer account"),
                         )
                         .arg(
-                            Arg::with_name("amount")
-                                .long("amount")
-                                .value_name("AMOUNT")
+                            Arg::with_name("signer")
+                                .long("signer")
+                                .value_name("KEYPAIR")
                                 .takes_value(true)
-                                .validator(is_parsable::<f64>)
-                                .conflicts_with("transaction")
-                                .help("Consider the account to have this amount of tokens rather than \
-                                       using the current value on chain (advanced; uncommon)"),
+                                .requires("execute")
+                                .validator(is_valid_signer)
+                                .help("Keypair of the account whose proposed lots are swapped"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("wash-sales")
+                        .about("Report losses disallowed by purchases of the same token within \
+                                30 days of the loss")
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .value_name("YYYY")
+                                .takes_value(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Limit report to disposals in the given year"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("rewards-csv")
+                        .about("Print the epoch rewards of a stake account in a year as CSV")
+                        .arg(
+                            Arg::with_name("address")
+                                .value_name("STAKE_ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Stake account address"),
                         )
                         .arg(
-                            Arg::with_name("neg_amount")
-                                .long("neg-amount")
-                                .value_name("AMOUNT")
+                            Arg::with_name("year")
+                                .long("year")
+                                .value_name("YYYY")
                                 .takes_value(true)
-                                .validator(is_parsable::<f64>)
-                                .conflicts_with("amount")
-                                .conflicts_with("transaction")
-                                .help("If a negative amount is specified, subtract the provided AMOUNT from the \
-                                       on-chain balance (advanced; uncommon)"),
+                                .required(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Year the rewards were received in"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("rewards-summary")
+                        .about("Summarize the staking income of all accounts in a year by \
+                                month and by validator")
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .value_name("YYYY")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_parsable::<usize>)
+                                .help("Year the rewards were received in"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("stream")
+                        .about("Incoming Streamflow token streams, which `sync` finds and \
+                                acquires the claims of as income")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(
+                            SubCommand::with_name("ls")
+                                .about("List incoming streams with their amounts unlocked but \
+                                        not yet claimed"),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("claim")
+                                .about("Claim everything unlocked from an incoming stream")
+                                .arg(
+                                    Arg::with_name("address")
+                                        .value_name("STREAM_ADDRESS")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_valid_pubkey)
+                                        .help("Address of the stream"),
+                                )
+                                .arg(
+                                    Arg::with_name("by")
+                                        .long("by")
+                                        .value_name("KEYPAIR")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_valid_signer)
+                                        .help("Recipient of the stream"),
+                                ),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("vesting")
+                        .about("Token grants that unlock over time")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(
+                            SubCommand::with_name("add")
+                                .about("Register a vesting grant to a tracked account. `sync` \
+                                        acquires unlocked tranches as income when they arrive \
+                                        in the account")
+                                .arg(
+                                    Arg::with_name("token")
+                                        .value_name("SOL or SPL Token")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_valid_token_or_sol)
+                                        .help("Token type"),
+                                )
+                                .arg(
+                                    Arg::with_name("address")
+                                        .value_name("ADDRESS")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_valid_pubkey)
+                                        .help("Account address the grant unlocks into"),
+                                )
+                                .arg(
+                                    Arg::with_name("amount")
+                                        .value_name("AMOUNT")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_parsable::<f64>)
+                                        .help("Total amount of the grant"),
+                                )
+                                .arg(
+                                    Arg::with_name("name")
+                                        .long("name")
+                                        .value_name("NAME")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .help("Name of the grant"),
+                                )
+                                .arg(
+                                    Arg::with_name("start")
+                                        .long("start")
+                                        .value_name("YY/MM/DD")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                                        .help("Date the grant starts vesting"),
+                                )
+                                .arg(
+                                    Arg::with_name("cliff")
+                                        .long("cliff")
+                                        .value_name("YY/MM/DD")
+                                        .takes_value(true)
+                                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                                        .help("Date of the first unlock, of everything vested \
+                                               since the start [default: no cliff]"),
+                                )
+                                .arg(
+                                    Arg::with_name("end")
+                                        .long("end")
+                                        .value_name("YY/MM/DD")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                                        .help("Date the grant is fully unlocked"),
+                                )
+                                .arg(
+                                    Arg::with_name("interval_months")
+                                        .long("interval-months")
+                                        .value_name("MONTHS")
+                                        .takes_value(true)
+                                        .default_value("1")
+                                        .validator(|value| match value.parse::<u32>() {
+                                            Ok(interval_months) if interval_months > 0 => Ok(()),
+                                            _ => Err(format!("Invalid interval: {value}")),
+                                        })
+                                        .help("Months between unlocks after the cliff"),
+                                )
+                                .arg(
+                                    Arg::with_name("received")
+                                        .long("received")
+                                        .value_name("AMOUNT")
+                                        .takes_value(true)
+                                        .validator(is_parsable::<f64>)
+                                        .help("Amount of the grant already received into the \
+                                               account [default: 0]"),
+                                ),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("remove")
+                                .about("Unregister a vesting grant")
+                                .arg(
+                                    Arg::with_name("token")
+                                        .value_name("SOL or SPL Token")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_valid_token_or_sol)
+                                        .help("Token type"),
+                                )
+                                .arg(
+                                    Arg::with_name("address")
+                                        .value_name("ADDRESS")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_valid_pubkey)
+                                        .help("Account address the grant unlocks into"),
+                                )
+                                .arg(
+                                    Arg::with_name("name")
+                                        .long("name")
+                                        .value_name("NAME")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .help("Name of the grant"),
+                                ),
                         )
+                        .subcommand(
+                            SubCommand::with_name("ls")
+                                .about("List vesting grants with their amounts still locked"),
+                        ),
                 )
                 .subcommand(
-                    SubCommand::with_name("dispose")
-                        .about("Manually record the disposal of SOL/tokens from an account")
+                    SubCommand::with_name("remove")
+                        .about("Unregister an account. The account and its lots are archived")
+                        .alias("delete")
                         .arg(
                             Arg::with_name("token")
                                 .value_name("SOL or SPL Token")
@@ -4651,104 +12723,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .takes_value(true)
                                 .required(true)
                                 .validator(is_valid_pubkey)
-                                .help("Account that the SOL/tokens was/where disposed from"),
-                        )
-                        .arg(
-                            Arg::with_name("amount")
-                                .value_name("AMOUNT")
-                                .takes_value(true)
-                                .validator(is_amount)
-                                .required(true)
-                                .help("Amount of SOL/tokens that was/where disposed from the account"),
-                        )
-                        .arg(
-                            Arg::with_name("description")
-                                .short("d")
-                                .long("description")
-                                .value_name("TEXT")
-                                .takes_value(true)
-                                .help("Description to associate with the disposal event"),
-                        )
-                        .arg(
-                            Arg::with_name("when")
-                                .short("w")
-                                .long("when")
-                                .value_name("YY/MM/DD")
-                                .takes_value(true)
-                                .validator(|value| naivedate_of(&value).map(|_| ()))
-                                .help("Disposal date [default: now]"),
-                        )
-                        .arg(
-                            Arg::with_name("price")
-                                .short("p")
-                                .long("price")
-                                .value_name("USD")
-                                .takes_value(true)
-                                .validator(is_parsable::<f64>)
-                                .help("Disposal price per SOL/token [default: market price on disposal date]"),
-                        )
-                        .arg(lot_selection_arg())
-                        .arg(lot_numbers_arg()),
-                )
-                .subcommand(
-                    SubCommand::with_name("ls")
-                        .about("List registered accounts")
-                        .alias("sl")
-                        .arg(
-                            Arg::with_name("all")
-                                .short("a")
-                                .long("all")
-                                .help("Display all lots")
-                        )
-                        .arg(
-                            Arg::with_name("account")
-                                .value_name("ADDRESS")
-                                .takes_value(true)
-                                .validator(is_valid_pubkey)
-                                .help("Limit output to this address"),
+                                .help("Account address to remove"),
                         )
                         .arg(
-                            Arg::with_name("summary")
-                                .long("summary")
+                            Arg::with_name("confirm")
+                                .long("confirm")
                                 .takes_value(false)
-                                .help("Limit output to summary line"),
-                        ),
-                )
-                .subcommand(
-                    SubCommand::with_name("cost-basis")
-                        .about("Display average cost basis of holdings")
-                        .arg(
-                            Arg::with_name("when")
-                                .value_name("YY/MM/DD")
-                                .takes_value(true)
-                                .required(false)
-                                .validator(|value| naivedate_of(&value).map(|_| ()))
-                                .default_value(&default_when)
-                                .help("Date to calculate cost basis for")
-                        )
-                )
-                .subcommand(
-                    SubCommand::with_name("xls")
-                        .about("Export an Excel spreadsheet file")
-                        .arg(
-                            Arg::with_name("outfile")
-                                .value_name("FILEPATH")
-                                .takes_value(true)
-                                .help(".xls file to write"),
+                                .help("Confirm the operation"),
                         )
                         .arg(
-                            Arg::with_name("year")
-                                .long("year")
-                                .value_name("YYYY")
-                                .takes_value(true)
-                                .validator(is_parsable::<usize>)
-                                .help("Limit export to realized gains affecting the given year"),
+                            Arg::with_name("proceed_even_if_lots_exist")
+                                .long("proceed-even-if-lots-exist")
+                                .takes_value(false)
+                                .help("Proceed even if the account has lots (advanced; uncommon)"),
                         ),
                 )
                 .subcommand(
-                    SubCommand::with_name("remove")
-                        .about("Unregister an account")
-                        .alias("delete")
+                    SubCommand::with_name("restore")
+                        .about("Restore an archived account")
                         .arg(
                             Arg::with_name("token")
                                 .value_name("SOL or SPL Token")
@@ -4763,19 +12755,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .takes_value(true)
                                 .required(true)
                                 .validator(is_valid_pubkey)
-                                .help("Account address to remove"),
-                        )
-                        .arg(
-                            Arg::with_name("confirm")
-                                .long("confirm")
-                                .takes_value(false)
-                                .help("Confirm the operation"),
-                        )
-                        .arg(
-                            Arg::with_name("proceed_even_if_lots_exist")
-                                .long("proceed-even-if-lots-exist")
-                                .takes_value(false)
-                                .help("Proceed even if the account has lots (advanced; uncommon)"),
+                                .help("Account address to restore"),
                         ),
                 )
                 .subcommand(
@@ -4797,6 +12777,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .help("Stake authority keypair"),
                         )
                 )
+                .subcommand(
+                    SubCommand::with_name("set-sol-reserve")
+                        .about("Set the minimum SOL balance that deposits, sweeps, delegations \
+                                and swaps leave in an account, to pay its rent and fees")
+                        .arg(
+                            Arg::with_name("address")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Account address"),
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("SOL")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_amount)
+                                .help("Minimum SOL reserve, or 0 to remove the reserve"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("sol-reserves")
+                        .about("Show the minimum SOL reserve of each account")
+                )
+                .subcommand(
+                    SubCommand::with_name("set-ata-rent-funder")
+                        .about("Set the account that tops up the authority of a token deposit or \
+                                sweep when it is short of the rent of a new associated token \
+                                account")
+                        .arg(
+                            Arg::with_name("keypair")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .help("Funder keypair. Omit to stop topping up authorities"),
+                        )
+                )
                 .subcommand(
                     SubCommand::with_name("set-tax-rate")
                         .about("Set entity tax rate for account listing")
@@ -4825,6 +12842,127 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     SubCommand::with_name("tax-rate")
                         .about("Show entity tax rate for account listing")
                 )
+                .subcommand(
+                    SubCommand::with_name("lot-columns")
+                        .about("Show or set the lot columns listed by `account ls`")
+                        .arg(
+                            Arg::with_name("columns")
+                                .value_name("COLUMN,...")
+                                .takes_value(true)
+                                .multiple(true)
+                                .use_delimiter(true)
+                                .possible_values(POSSIBLE_LOT_COLUMN_VALUES)
+                                .help("Columns to list, in order"),
+                        )
+                        .arg(
+                            Arg::with_name("default")
+                                .long("default")
+                                .takes_value(false)
+                                .conflicts_with("columns")
+                                .help("Restore the default columns"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("number-format")
+                        .about("Show or set how numbers and USD values are written in console \
+                                output")
+                        .arg(
+                            Arg::with_name("thousands_separator")
+                                .long("thousands-separator")
+                                .value_name("SEPARATOR")
+                                .takes_value(true)
+                                .allow_hyphen_values(true)
+                                .help("Digit group separator, such as \",\", \".\", \"'\" or \" \". \
+                                       Empty to not group digits"),
+                        )
+                        .arg(
+                            Arg::with_name("decimal_separator")
+                                .long("decimal-separator")
+                                .value_name("SEPARATOR")
+                                .takes_value(true)
+                                .validator(|value| {
+                                    let mut chars = value.chars();
+                                    match (chars.next(), chars.next()) {
+                                        (Some(c), None) if !c.is_ascii_digit() => Ok(()),
+                                        _ => Err(format!("'{value}' is not a single non-digit \
+                                                          character")),
+                                    }
+                                })
+                                .help("Decimal separator, such as \".\" or \",\""),
+                        )
+                        .arg(
+                            Arg::with_name("currency_symbol")
+                                .long("currency-symbol")
+                                .value_name("SYMBOL")
+                                .takes_value(true)
+                                .help("Prefix of USD values, such as \"$\", \"US$\" or \"USD \""),
+                        )
+                        .arg(
+                            Arg::with_name("default")
+                                .long("default")
+                                .takes_value(false)
+                                .conflicts_with_all(&[
+                                    "thousands_separator",
+                                    "decimal_separator",
+                                    "currency_symbol",
+                                ])
+                                .help("Restore US-style formatting"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("fee-treatment")
+                        .about("Show or set how the transaction fees of swaps are accounted for")
+                        .arg(
+                            Arg::with_name("treatment")
+                                .value_name("TREATMENT")
+                                .takes_value(true)
+                                .possible_values(POSSIBLE_FEE_TREATMENT_VALUES)
+                                .help("Expense fees [default], or capitalize them into the basis \
+                                       of the acquired lot")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("set-withholding-rate")
+                        .about("Set the portion of income to reserve for taxes")
+                        .arg(
+                            Arg::with_name("rate")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_tax_rate)
+                                .help("Income withholding rate, or 0 to disable withholding")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("tax-reserve")
+                        .about("Show the income tax reserve and the estimated tax payments made against it")
+                )
+                .subcommand(
+                    SubCommand::with_name("tax-reserve-payment")
+                        .about("Mark part of the income tax reserve as satisfied by an estimated tax payment")
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("USD")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_parsable::<f64>)
+                                .help("Amount paid")
+                        )
+                        .arg(
+                            Arg::with_name("when")
+                                .long("when")
+                                .value_name("YY/MM/DD")
+                                .takes_value(true)
+                                .validator(|value| naivedate_of(&value).map(|_| ()))
+                                .help("Payment date [default: today]")
+                        )
+                        .arg(
+                            Arg::with_name("description")
+                                .long("description")
+                                .value_name("TEXT")
+                                .takes_value(true)
+                                .help("Payment description, such as the tax period it covers")
+                        )
+                )
                 .subcommand(
                     SubCommand::with_name("merge")
                         .about("Merge one stake account into another")
@@ -4853,11 +12991,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .validator(is_valid_signer)
                                 .help("Optional authority for the merge"),
                         )
+                        .args(&durable_nonce_args())
                         .arg(
                             Arg::with_name("transaction")
                                 .long("transaction")
                                 .value_name("SIGNATURE")
                                 .takes_value(true)
+                                .conflicts_with("nonce_account")
                                 .validator(is_parsable::<Signature>)
                                 .help("Use an existing transaction signature for merge. \
                                       That is, perform the local database operations only. \
@@ -4907,31 +13047,107 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .help("Exit successfully if a sweep is not possible due to low source account balance"),
                         )
                         .arg(
-                            Arg::with_name("exactly")
-                                .long("exactly")
-                                .value_name("AMOUNT")
+                            Arg::with_name("exactly")
+                                .long("exactly")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount)
+                                .help("Sweep exactly this amount [default: full account balance minus the value provided to --retain]"),
+                        )
+                        .arg(
+                            Arg::with_name("retain")
+                                .short("r")
+                                .long("retain")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount)
+                                .help("Amount of SOL/tokens to leave in source account [default: 0]"),
+                        )
+                        .args(&durable_nonce_args())
+                        .arg(
+                            Arg::with_name("transaction")
+                                .long("transaction")
+                                .value_name("SIGNATURE")
+                                .takes_value(true)
+                                .conflicts_with("nonce_account")
+                                .validator(is_parsable::<Signature>)
+                                .help("Use an existing transaction signature for sweep. \
+                                      That is, perform the local database operations only. \
+                                      Careful!")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("dust-sweep")
+                        .about("Sweep the token balances of an address that are worth little into another address")
+                        .arg(
+                            Arg::with_name("address")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Source address to sweep from"),
+                        )
+                        .arg(
+                            Arg::with_name("to_address")
+                                .long("to")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Sweep destination address"),
+                        )
+                        .arg(
+                            Arg::with_name("below")
+                                .long("below")
+                                .value_name("USD")
+                                .takes_value(true)
+                                .default_value("1")
+                                .validator(|value| match value.parse::<f64>() {
+                                    Ok(x) if x > 0. && x.is_finite() => Ok(()),
+                                    _ => Err(format!("Invalid value: {value}")),
+                                })
+                                .help("Sweep the tokens whose balance is worth less than this amount"),
+                        )
+                        .arg(
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_valid_signer)
+                                .help("Optional authority of the source address"),
+                        )
+                        .args(&durable_nonce_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("collect")
+                        .about("Withdraw the balance above the delegated stake, such as MEV rewards, \
+                                of stake accounts into an address")
+                        .arg(
+                            Arg::with_name("to_address")
+                                .value_name("ADDRESS")
                                 .takes_value(true)
-                                .validator(is_amount)
-                                .help("Sweep exactly this amount [default: full account balance minus the value provided to --retain]"),
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Tracked system account to withdraw into"),
                         )
                         .arg(
-                            Arg::with_name("retain")
-                                .short("r")
-                                .long("retain")
-                                .value_name("AMOUNT")
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
                                 .takes_value(true)
-                                .validator(is_amount)
-                                .help("Amount of SOL/tokens to leave in source account [default: 0]"),
+                                .required(true)
+                                .validator(is_valid_signer)
+                                .help("Withdraw authority of the stake accounts"),
                         )
                         .arg(
-                            Arg::with_name("transaction")
-                                .long("transaction")
-                                .value_name("SIGNATURE")
+                            Arg::with_name("stake_addresses")
+                                .long("stake")
+                                .value_name("STAKE_ADDRESS")
                                 .takes_value(true)
-                                .validator(is_parsable::<Signature>)
-                                .help("Use an existing transaction signature for sweep. \
-                                      That is, perform the local database operations only. \
-                                      Careful!")
+                                .multiple(true)
+                                .validator(is_valid_pubkey)
+                                .help("Stake account to collect from \
+                                       [default: all tracked stake accounts withdrawn by the authority]"),
                         )
                 )
                 .subcommand(
@@ -4988,6 +13204,124 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                        the account balance is less than this amount",
                                 ),
                         )
+                        .args(&durable_nonce_args())
+                        .arg(lot_selection_arg())
+                        .arg(lot_numbers_arg())
+                )
+                .subcommand(
+                    SubCommand::with_name("delegate")
+                        .about("Create a stake account funded from a system account and \
+                                delegate it")
+                        .arg(
+                            Arg::with_name("from_address")
+                                .value_name("FROM_SYSTEM_ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_signer)
+                                .help("System account to fund the stake account from")
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount)
+                                .required(true)
+                                .help("The amount to stake, in SOL"),
+                        )
+                        .arg(
+                            Arg::with_name("vote_account_address")
+                                .long("to")
+                                .value_name("VOTE_ACCOUNT")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Vote account to delegate the stake to")
+                        )
+                        .arg(
+                            Arg::with_name("description")
+                                .short("d")
+                                .long("description")
+                                .value_name("TEXT")
+                                .takes_value(true)
+                                .help("Description of the new stake account"),
+                        )
+                        .arg(
+                            Arg::with_name("stake_keypair")
+                                .long("stake-keypair")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_keypair)
+                                .help("Optional keypair of the new stake account [default: randomly generated]"),
+                        )
+                        .arg(lot_selection_arg())
+                        .arg(lot_numbers_arg())
+                )
+                .subcommand(
+                    SubCommand::with_name("deactivate")
+                        .about("Deactivate a stake account")
+                        .arg(
+                            Arg::with_name("address")
+                                .value_name("STAKE_ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Address of the stake account to deactivate")
+                        )
+                        .arg(
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_valid_signer)
+                                .help("Optional stake authority for the deactivation"),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("withdraw-stake")
+                        .about("Withdraw a deactivated stake account into a system account")
+                        .arg(
+                            Arg::with_name("from_address")
+                                .value_name("STAKE_ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Address of the stake account to withdraw from")
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount_or_all)
+                                .default_value("ALL")
+                                .help("The amount to withdraw, in SOL; accepts keyword ALL"),
+                        )
+                        .arg(
+                            Arg::with_name("to_address")
+                                .long("to")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Tracked system account to withdraw into")
+                        )
+                        .arg(
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_valid_signer)
+                                .help("Optional withdraw authority for the withdrawal"),
+                        )
+                        .arg(
+                            Arg::with_name("transaction")
+                                .long("transaction")
+                                .value_name("SIGNATURE")
+                                .takes_value(true)
+                                .validator(is_parsable::<Signature>)
+                                .help("Use an existing transaction signature for the withdrawal. \
+                                      That is, perform the local database operations only. \
+                                      Careful!")
+                        )
                         .arg(lot_selection_arg())
                         .arg(lot_numbers_arg())
                 )
@@ -5061,6 +13395,37 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .help("Rescan for account balance changes even in same epoch (advanced; uncommon)"),
                         )
                 )
+                .subcommand(
+                    SubCommand::with_name("liquid-stake")
+                        .about("Deposit SOL into a liquid staking protocol")
+                        .arg(
+                            Arg::with_name("address")
+                                .value_name("ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_signer)
+                                .help("Address of the account holding the SOL to stake")
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount_or_all_or_half)
+                                .required(true)
+                                .help("The amount to stake, in SOL; accepts keywords ALL and HALF"),
+                        )
+                        .arg(
+                            Arg::with_name("protocol")
+                                .long("protocol")
+                                .value_name("PROTOCOL")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(POSSIBLE_LIQUID_STAKE_PROTOCOL_VALUES)
+                                .help("Liquid staking protocol: marinade for mSOL, jito for \
+                                       JitoSOL, blaze for bSOL"),
+                        )
+                        .arg(lot_selection_arg())
+                )
                 .subcommand(
                     SubCommand::with_name("wrap")
                         .about("Wrap SOL into wSOL")
@@ -5218,6 +13583,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                         .validator(is_valid_pubkey)
                                         .help("Address to receive the lot"),
                                 )
+                        )
+                        .subcommand(
+                            SubCommand::with_name("edit-disposal")
+                                .about("Correct the sale details of a disposed lot")
+                                .arg(
+                                    Arg::with_name("lot_number")
+                                        .value_name("LOT NUMBER")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .validator(is_parsable::<usize>)
+                                        .help("Disposed lot number to edit"),
+                                )
+                                .arg(
+                                    Arg::with_name("price")
+                                        .long("price")
+                                        .value_name("USD")
+                                        .takes_value(true)
+                                        .validator(is_parsable::<Decimal>)
+                                        .help("Corrected sale price per token"),
+                                )
+                                .arg(
+                                    Arg::with_name("when")
+                                        .long("when")
+                                        .value_name("YY/MM/DD")
+                                        .takes_value(true)
+                                        .validator(|value| naivedate_of(&value).map(|_| ()))
+                                        .help("Corrected sale date"),
+                                )
+                                .arg(
+                                    Arg::with_name("description")
+                                        .long("description")
+                                        .value_name("TEXT")
+                                        .takes_value(true)
+                                        .help("Corrected description of a manual disposal"),
+                                )
+                                .group(
+                                    ArgGroup::with_name("edits")
+                                        .args(&["price", "when", "description"])
+                                        .multiple(true)
+                                        .required(true),
+                                )
                         ),
                 ),
         )
@@ -5352,6 +13758,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .help("Reject if the value lost relative to CoinGecko token \
                                       price exceeds this percentage"),
                         )
+                        .arg(
+                            Arg::with_name("force_priority_fee")
+                                .long("force-priority-fee")
+                                .takes_value(false)
+                                .help("Replace the priority fee selected by Jupiter with one \
+                                      computed locally, within the maximum priority fee"),
+                        )
                         .arg(lot_selection_arg())
                         .arg(
                             Arg::with_name("transaction")
@@ -5364,6 +13777,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                       considered it to have failed. Careful!")
                         )
                 )
+                .subcommand(
+                    SubCommand::with_name("history")
+                        .about("List completed swaps")
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .value_name("YYYY")
+                                .takes_value(true)
+                                .validator(is_parsable::<i32>)
+                                .help("Only list swaps completed in this year"),
+                        )
+                        .arg(
+                            Arg::with_name("csv")
+                                .long("csv")
+                                .value_name("FILEPATH")
+                                .takes_value(true)
+                                .help("Also write the swaps to this .csv file"),
+                        )
+                )
         )
         .subcommand(
             SubCommand::with_name("stake-spreader")
@@ -5641,7 +14073,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .about("Set API key")
                                 .arg(Arg::with_name("api_key").required(true).takes_value(true))
                                 .arg(Arg::with_name("secret").required(true).takes_value(true))
-                                .arg(Arg::with_name("subaccount").takes_value(true)),
+                                .arg(Arg::with_name("subaccount").takes_value(true))
+                                .arg(
+                                    Arg::with_name("passphrase")
+                                        .long("passphrase")
+                                        .value_name("PASSPHRASE")
+                                        .takes_value(true)
+                                        .help("API passphrase, required by KuCoin"),
+                                ),
                         )
                         .subcommand(SubCommand::with_name("show").about("Show API key"))
                         .subcommand(SubCommand::with_name("clear").about("Clear API key")),
@@ -5705,7 +14144,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     "Exit successfully without depositing if the \
                                         exchange SOL balance is less than this amount",
                                 ),
-                        ),
+                        )
+                        .args(&durable_nonce_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("deposit-all")
+                        .about("Deposit the full balance of every SPL Token held by an address")
+                        .arg(
+                            Arg::with_name("from")
+                                .long("from")
+                                .value_name("FROM_ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Source account of funds"),
+                        )
+                        .arg(
+                            Arg::with_name("by")
+                                .long("by")
+                                .value_name("KEYPAIR")
+                                .takes_value(true)
+                                .validator(is_valid_signer)
+                                .help("Optional authority of the FROM_ADDRESS"),
+                        )
+                        .arg(
+                            Arg::with_name("token")
+                                .long("token")
+                                .value_name("SPL Token")
+                                .takes_value(true)
+                                .multiple(true)
+                                .validator(is_valid_token)
+                                .help("Token to deposit [default: all tracked SPL Tokens of the FROM_ADDRESS]"),
+                        )
+                        .args(&durable_nonce_args()),
                 )
                 .subcommand(
                     SubCommand::with_name("withdraw")
@@ -5743,6 +14214,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .takes_value(true)
                                 .help("2FA withdrawal code"),
                         )
+                        .arg(
+                            Arg::with_name("split")
+                                .long("split")
+                                .takes_value(false)
+                                .conflicts_with("lot_numbers")
+                                .help(
+                                    "If the amount exceeds the exchange withdrawal limit, withdraw \
+                                     what the limit allows now and schedule the remainder",
+                                ),
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("withdraw-schedule")
+                        .about("Schedule a withdrawal in chunks, one chunk withdrawn by `sync` each interval")
+                        .arg(
+                            Arg::with_name("token")
+                                .value_name("SOL or SPL Token")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_token_or_sol)
+                                .help("Token type"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .value_name("RECIPIENT_ADDRESS")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_valid_pubkey)
+                                .help("Address to receive the withdrawals"),
+                        )
+                        .arg(
+                            Arg::with_name("total")
+                                .value_name("TOTAL")
+                                .takes_value(true)
+                                .validator(is_amount)
+                                .required(true)
+                                .help("The total amount to withdraw; 0 cancels an existing schedule"),
+                        )
+                        .arg(
+                            Arg::with_name("chunks")
+                                .long("chunks")
+                                .value_name("N")
+                                .takes_value(true)
+                                .default_value("1")
+                                .validator(is_parsable::<u32>)
+                                .help("Number of withdrawals to split the total into"),
+                        )
+                        .arg(
+                            Arg::with_name("interval")
+                                .long("interval")
+                                .value_name("HOURS")
+                                .takes_value(true)
+                                .default_value("24")
+                                .validator(is_parsable::<u32>)
+                                .help("Hours between withdrawals"),
+                        )
+                        .arg(lot_selection_arg())
                 )
                 .subcommand(
                     SubCommand::with_name("cancel")
@@ -5772,6 +14300,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .help("Restrict to only buy or sell orders")
                         )
                 )
+                .subcommand(
+                    SubCommand::with_name("adopt-order")
+                        .about("Record an order placed outside of sys, such as on the exchange website")
+                        .arg(
+                            Arg::with_name("order_id")
+                                .value_name("ORDER ID")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The order id to adopt"),
+                        )
+                        .arg(
+                            Arg::with_name("pair")
+                                .long("pair")
+                                .value_name("TRADING_PAIR")
+                                .takes_value(true)
+                                .help("Market of the order [default: preferred SOL/USD pair for the exchange]"),
+                        )
+                )
                 .subcommand(
                     SubCommand::with_name("buy")
                         .about("Place an order to buy SOL")
@@ -5800,6 +14346,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .validator(is_parsable::<f64>)
                                 .help("Place a limit order at this amount under the current bid"),
                         )
+                        .arg(
+                            Arg::with_name("market")
+                                .long("market")
+                                .takes_value(false)
+                                .conflicts_with_all(&["at", "bid_minus", "no_post_only"])
+                                .help("Place a market order that fills immediately at the best available prices"),
+                        )
+                        .arg(
+                            Arg::with_name("group")
+                                .long("group")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .help("Add the order to this order group"),
+                        )
+                        .arg(time_in_force_arg())
+                        .arg(no_post_only_arg())
+                        .arg(max_book_fraction_arg())
+                        .arg(allow_impact_arg())
                         .arg(
                             Arg::with_name("pair")
                                 .long("pair")
@@ -5847,6 +14411,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .validator(is_parsable::<f64>)
                                 .help("Place a limit order at this amount over the current ask"),
                         )
+                        .arg(
+                            Arg::with_name("market")
+                                .long("market")
+                                .takes_value(false)
+                                .conflicts_with_all(&["at", "ask_plus", "price_floor", "no_post_only"])
+                                .help("Place a market order that fills immediately at the best available prices"),
+                        )
+                        .arg(
+                            Arg::with_name("group")
+                                .long("group")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .help("Add the order to this order group"),
+                        )
+                        .arg(time_in_force_arg())
+                        .arg(no_post_only_arg())
+                        .arg(max_book_fraction_arg())
+                        .arg(allow_impact_arg())
                         .arg(lot_selection_arg())
                         .arg(lot_numbers_arg())
                         .arg(
@@ -5902,6 +14484,177 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 ),
                         ),
                 )
+                .subcommand(
+                    SubCommand::with_name("sell-ladder")
+                        .about("Place a group of sell orders spaced across a price range")
+                        .arg(
+                            Arg::with_name("amount")
+                                .value_name("TOTAL_AMOUNT")
+                                .takes_value(true)
+                                .validator(is_amount)
+                                .required(true)
+                                .help("The total amount to sell, in SOL"),
+                        )
+                        .arg(
+                            Arg::with_name("from")
+                                .long("from")
+                                .value_name("PRICE")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_parsable::<f64>)
+                                .help("Price of the first order"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .value_name("PRICE")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_parsable::<f64>)
+                                .help("Price of the last order"),
+                        )
+                        .arg(
+                            Arg::with_name("steps")
+                                .long("steps")
+                                .value_name("N")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(|value| match value.parse::<u64>() {
+                                    Ok(steps) if steps > 0 => Ok(()),
+                                    _ => Err(format!("Invalid number of steps: {value}")),
+                                })
+                                .help("Number of orders to split the amount into"),
+                        )
+                        .arg(
+                            Arg::with_name("group")
+                                .long("group")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .help("Name of the order group [default: ladder-<timestamp>]"),
+                        )
+                        .arg(lot_selection_arg())
+                        .arg(
+                            Arg::with_name("pair")
+                                .long("pair")
+                                .value_name("TRADING_PAIR")
+                                .takes_value(true)
+                                .help("Market to place the orders in [default: preferred SOL/USD pair for the exchange]"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("statement")
+                        .about("Export the fills, deposits, withdrawals and fees of a month as CSV")
+                        .arg(
+                            Arg::with_name("month")
+                                .long("month")
+                                .value_name("YYYY-MM")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(|value| {
+                                    NaiveDate::parse_from_str(&format!("{value}-01"), "%Y-%m-%d")
+                                        .map(|_| ())
+                                        .map_err(|err| format!("Invalid month {value}: {err}"))
+                                })
+                                .help("Month of the statement"),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .value_name("PATH")
+                                .takes_value(true)
+                                .help("Statement file [default: <exchange>-<YYYY-MM>.csv]"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("watch")
+                        .about("Continuously synchronize the exchange, closing orders as soon as \
+                                they fill")
+                        .arg(
+                            Arg::with_name("interval")
+                                .long("interval")
+                                .value_name("SECONDS")
+                                .takes_value(true)
+                                .default_value("60")
+                                .validator(|value| match value.parse::<u64>() {
+                                    // A zero interval would poll the exchange in a busy loop
+                                    Ok(interval) if interval > 0 => Ok(()),
+                                    _ => Err(format!("Invalid interval: {value}")),
+                                })
+                                .help("Seconds between synchronizations when no order updates \
+                                       are received"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("orders")
+                        .about("List, cancel, or re-price open orders")
+                        .arg(
+                            Arg::with_name("group")
+                                .long("group")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .help("Only operate on the orders in this order group"),
+                        )
+                        .arg(
+                            Arg::with_name("cancel")
+                                .long("cancel")
+                                .takes_value(false)
+                                .requires("group")
+                                .help("Cancel the orders"),
+                        )
+                        .arg(
+                            Arg::with_name("reprice_by")
+                                .long("reprice-by")
+                                .value_name("AMOUNT")
+                                .takes_value(true)
+                                .allow_hyphen_values(true)
+                                .requires("group")
+                                .conflicts_with("cancel")
+                                .validator(is_parsable::<f64>)
+                                .help("Replace the unfilled portion of each order with a new \
+                                       order at that order's price plus this amount"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("cancel-group")
+                        .about("Cancel all open orders in a group")
+                        .arg(
+                            Arg::with_name("group")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Name of the order group"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("status-group")
+                        .about("Display the status of the open orders in a group")
+                        .arg(
+                            Arg::with_name("group")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Name of the order group"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("dust-convert")
+                        .about("Convert balances too small to trade or withdraw using the \
+                                exchange's dust conversion")
+                        .arg(
+                            Arg::with_name("coin")
+                                .long("coin")
+                                .value_name("COIN")
+                                .takes_value(true)
+                                .multiple(true)
+                                .help("Only convert these coins [default: all dust balances]"),
+                        )
+                        .arg(
+                            Arg::with_name("confirm")
+                                .long("confirm")
+                                .takes_value(false)
+                                .help("Convert the listed dust balances"),
+                        ),
+                )
                 .subcommand(
                     SubCommand::with_name("pending-deposits")
                         .about("Display pending deposits")
@@ -5992,13 +14745,83 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 )
                         )
                 )
-                .subcommand(SubCommand::with_name("sync").about("Synchronize exchange")),
+                .subcommand(
+                    SubCommand::with_name("margin")
+                        .about("Track the borrows of the cross margin account as negative \
+                                positions")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(
+                            SubCommand::with_name("show")
+                                .about("Show the borrow positions as of the last sync")
+                        )
+                        .subcommand(
+                            SubCommand::with_name("track")
+                                .about("Track borrows and interest during `sync`, or change the \
+                                        margin level thresholds of a tracked account")
+                                .arg(
+                                    Arg::with_name("warn_below")
+                                        .long("warn-below")
+                                        .value_name("MARGIN_LEVEL")
+                                        .takes_value(true)
+                                        .validator(is_parsable::<f64>)
+                                        .help("Send a warning when the margin level falls \
+                                               below this level"),
+                                )
+                                .arg(
+                                    Arg::with_name("alert_below")
+                                        .long("alert-below")
+                                        .value_name("MARGIN_LEVEL")
+                                        .takes_value(true)
+                                        .validator(is_parsable::<f64>)
+                                        .help("Send an alert when the margin level falls \
+                                               below this level"),
+                                )
+                        )
+                        .subcommand(
+                            SubCommand::with_name("untrack")
+                                .about("Stop tracking borrows")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("sync")
+                        .about("Synchronize exchange")
+                        .arg(
+                            Arg::with_name("adopt_unknown_orders")
+                                .long("adopt-unknown-orders")
+                                .takes_value(false)
+                                .help("Track open orders that were placed outside of sys, \
+                                       earmarking lots for sell orders"),
+                        ),
+                ),
         );
     }
 
     let app_matches = app.get_matches();
     let db_path = value_t_or_exit!(app_matches, "db_path", PathBuf);
     let verbose = app_matches.is_present("verbose");
+    let output_format = value_t_or_exit!(app_matches, "output_format", OutputFormat);
+
+    let trace_http = app_matches.is_present("trace_http");
+    if trace_http {
+        // Also traces the requests of the Solana RPC client and the exchange SDKs, which are not
+        // sent through `http_client`
+        solana_logger::setup_with_default("solana=info,reqwest=debug");
+    }
+    http_client::configure(
+        value_t!(app_matches, "proxy", String)
+            .ok()
+            .or_else(|| std::env::var("SYS_PROXY").ok()),
+        value_t!(app_matches, "ca_bundle", String)
+            .ok()
+            .or_else(|| std::env::var("SYS_CA_BUNDLE").ok()),
+        trace_http,
+    )?;
+
+    // Does not touch the database, so runs without waiting for its lock
+    if let ("self-update", Some(arg_matches)) = app_matches.subcommand() {
+        let tag = value_t!(arg_matches, "tag", String).ok();
+        return self_update::self_update(tag.as_deref(), arg_matches.is_present("force")).await;
+    }
 
     let priority_fee = if let Ok(ui_priority_fee) = value_t!(app_matches, "priority_fee_exact", f64)
     {
@@ -6015,36 +14838,204 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         value_t_or_exit!(app_matches, "json_rpc_url", String),
         value_t!(app_matches, "send_json_rpc_urls", String).ok(),
         value_t!(app_matches, "helius_json_rpc_url", String).ok(),
-    );
+    )
+    .with_rate_limit(
+        value_t!(app_matches, "rpc_rate_limit", f64)
+            .ok()
+            .or_else(|| {
+                std::env::var("SYS_RPC_RATE_LIMIT")
+                    .ok()
+                    .and_then(|rate_limit| rate_limit.parse().ok())
+            })
+            .filter(|rate_limit| *rate_limit > 0.),
+    )
+    .with_address_lookup_tables(
+        pubkeys_of(&app_matches, "address_lookup_tables").unwrap_or_default(),
+    )
+    .with_max_total_fees(
+        value_t!(app_matches, "max_total_fees", f64)
+            .ok()
+            .map(sol_to_lamports),
+    )
+    .with_send_config({
+        let default_send_config = SendConfig::default();
+        SendConfig {
+            resend_interval: value_t!(app_matches, "resend_interval", f64)
+                .map(Duration::from_secs_f64)
+                .unwrap_or(default_send_config.resend_interval),
+            poll_interval: value_t!(app_matches, "confirmation_poll_interval", u64)
+                .map(Duration::from_millis)
+                .unwrap_or(default_send_config.poll_interval),
+            timeout: value_t!(app_matches, "send_timeout", u64)
+                .ok()
+                .map(Duration::from_secs),
+            escalate_after_resends: value_t!(app_matches, "escalate_priority_fee_after", usize)
+                .ok(),
+        }
+    });
+    let rpc_clients = match value_t!(app_matches, "jito_tip", f64) {
+        Ok(jito_tip) => {
+            let jito_tip_payer = value_t_or_exit!(app_matches, "jito_tip_payer", String);
+            let jito_tip_payer = read_keypair_file(&jito_tip_payer).map_err(|err| {
+                format!("Failed to read Jito tip payer keypair: {jito_tip_payer}: {err}")
+            })?;
+            rpc_clients.with_jito(
+                value_t_or_exit!(app_matches, "jito_url", String),
+                sol_to_lamports(jito_tip),
+                jito_tip_payer,
+            )
+        }
+        Err(_) => rpc_clients,
+    };
 
     let rpc_client = rpc_clients.default();
 
     let mut wallet_manager = None;
-    let notifier = Notifier::default();
+    let dry_run = matches!(
+        app_matches.subcommand(),
+        ("sync", Some(arg_matches)) if arg_matches.is_present("dry_run")
+    );
+    let healthcheck = Healthcheck::default();
 
     if !db_path.exists() {
         fs::create_dir_all(&db_path)?;
     }
+    self_update::notify_if_outdated(&db_path).await;
+
+    // Database saves atomically replace the data file, so read-only commands can work from the
+    // last saved snapshot instead of waiting for another instance to release the write lock
+    let read_only = is_read_only_command(&app_matches);
 
     let mut db_fd_lock = fd_lock::RwLock::new(fs::File::open(&db_path).unwrap());
-    let _db_write_lock = loop {
-        match db_fd_lock.try_write() {
-            Ok(lock) => break lock,
-            Err(err) => {
-                eprintln!(
-                    "Unable to lock database directory: {}: {}",
-                    db_path.display(),
-                    err
-                );
-                std::thread::sleep(std::time::Duration::from_secs(1));
-            }
+    let (_db_write_lock, _db_read_lock) = if read_only {
+        let db_read_lock = db_fd_lock.try_read().ok();
+        if db_read_lock.is_none() {
+            eprintln!(
+                "Database is locked by another instance, using its last saved state: {}",
+                db_path.display()
+            );
         }
+        (None, db_read_lock)
+    } else {
+        let db_write_lock = loop {
+            match db_fd_lock.try_write() {
+                Ok(lock) => break lock,
+                Err(err) => {
+                    eprintln!(
+                        "Unable to lock database directory: {}: {}",
+                        db_path.display(),
+                        err
+                    );
+                    std::thread::sleep(std::time::Duration::from_secs(1));
+                }
+            }
+        };
+        (Some(db_write_lock), None)
     };
 
-    let mut db = db::new(&db_path).unwrap_or_else(|err| {
+    // Copies of the database directory are made and restored without opening the database, which
+    // may be corrupt
+    if let ("db", Some(db_matches)) = app_matches.subcommand() {
+        match db_matches.subcommand() {
+            ("backup", Some(arg_matches)) if arg_matches.is_present("path") => {
+                let path = value_t_or_exit!(arg_matches, "path", PathBuf);
+                backup::snapshot(&db_path, &path, arg_matches.is_present("gzip"))
+                    .map_err(|err| format!("Backup failed: {err}"))?;
+                println!("Backed up to {}", path.display());
+                return Ok(());
+            }
+            ("restore", Some(arg_matches)) if arg_matches.is_present("path") => {
+                let path = value_t_or_exit!(arg_matches, "path", PathBuf);
+                let previous_path = backup::restore_snapshot(&db_path, &path)
+                    .map_err(|err| format!("Restore failed: {err}"))?;
+                println!("Previous database saved to {}", previous_path.display());
+                println!("Database restored");
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    let db_backend = value_t!(app_matches, "db_backend", DbBackend).ok();
+    let db = if dry_run {
+        db::new_dry_run(&db_path, db_backend)
+    } else if read_only {
+        db::new_read_only(&db_path, db_backend)
+    } else {
+        db::new(&db_path, db_backend)
+    };
+    let mut db = db.unwrap_or_else(|err| {
         eprintln!("Failed to open {}: {}", db_path.display(), err);
         exit(1)
     });
+    if !read_only && !dry_run && db.get_auto_snapshots() > 0 {
+        backup::auto_snapshot(&db_path, db.get_auto_snapshots())
+            .map_err(|err| format!("Unable to snapshot the database: {err}"))?;
+    }
+    let _block_date_cache = rpc_client_utils::load_block_date_cache(&db_path, read_only);
+    let _operation_stats = operation_stats::load(&db_path, read_only || dry_run);
+
+    // Profiles are managed from the default profile
+    let profile = value_t!(app_matches, "profile", String)
+        .ok()
+        .or_else(|| std::env::var("SYS_PROFILE").ok())
+        .filter(|_| app_matches.subcommand_name() != Some("profile"));
+    if let Some(profile) = profile {
+        db.select_profile(&profile).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            exit(1)
+        });
+    }
+
+    number_format::configure(db.get_number_format());
+    configure_display_symbols(db.get_token_symbols());
+
+    // Global options are left out of the journaled command, RPC URLs may hold API keys
+    let journaled = match app_matches.subcommand() {
+        ("sync" | "daemon", _) => false,
+        ("db", Some(db_matches)) => !matches!(
+            db_matches.subcommand_name(),
+            Some("undo" | "history" | "restore")
+        ),
+        _ => true,
+    };
+    if journaled && !read_only && !dry_run {
+        db.begin_journal_entry(
+            std::env::args()
+                .skip_while(|arg| Some(arg.as_str()) != app_matches.subcommand_name())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+
+    let notifier = if dry_run {
+        Notifier::disabled()
+    } else {
+        Notifier::default()
+            .with_routes(db.get_notification_routes())
+            .with_quiet(app_matches.is_present("quiet_notifications"))
+    };
+
+    coin_gecko::seed_historical_prices(
+        db.historical_prices()
+            .into_iter()
+            .map(|HistoricalPrice { token, when, price }| (when, token, price)),
+    )
+    .await;
+
+    if db.credentials_encrypted() {
+        if let Some(passphrase) = credentials_passphrase(&db_path) {
+            db.unlock_credentials(&passphrase).unwrap_or_else(|err| {
+                eprintln!("{err}");
+                exit(1)
+            });
+        } else if app_matches.subcommand_name() == Some("sync") {
+            eprintln!(
+                "Warning: {}, exchanges will not be synced",
+                DbError::CredentialsLocked
+            );
+        }
+    }
 
     match app_matches.subcommand() {
         ("price", Some(arg_matches)) => {
@@ -6053,6 +15044,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .ok();
             let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
 
+            if output_format == OutputFormat::Json {
+                let price = match when {
+                    Some(when) => token.get_historical_price(rpc_client, when).await?,
+                    None => token.get_current_price(rpc_client).await?,
+                };
+                return println_json(&PriceOutput {
+                    token,
+                    when,
+                    price: price.try_into()?,
+                });
+            }
+
             let (price, verbose_msg) = if let Some(when) = when {
                 (
                     token.get_historical_price(rpc_client, when).await?,
@@ -6077,65 +15080,299 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         Decimal::from_usize(1).unwrap() / rate
                     );
                 }
-            } else {
-                println!("{price:.6}");
+            } else {
+                println!("{price:.6}");
+            }
+        }
+        ("sync", Some(arg_matches)) => {
+            let max_epochs_to_process = value_t!(arg_matches, "max_epochs_to_process", u64).ok();
+            let only = values_of_exchange_account_selectors(arg_matches, "only");
+            let skip = values_of_exchange_account_selectors(arg_matches, "skip");
+            let sync_snapshot = SyncSnapshot::new(&db);
+
+            let result = process_sync(
+                &mut db,
+                &rpc_clients,
+                only,
+                skip,
+                max_epochs_to_process,
+                &notifier,
+                verbose,
+            )
+            .await;
+            if !dry_run {
+                healthcheck.report(&result).await;
+
+                let mut summary = sync_snapshot.summary(&db, false);
+                if let Err(err) = &result {
+                    summary.errors.push(err.to_string());
+                }
+                notifier.send_summary(&summary).await;
+            }
+            result?;
+
+            if dry_run {
+                println!();
+                println!("Dry run, nothing was recorded:");
+                sync_snapshot.println_changes(&db);
+                // Metrics gathered during the dry run are not sent either
+                return Ok(());
+            }
+        }
+        ("daemon", Some(arg_matches)) => {
+            let interval = duration_of(arg_matches.value_of("interval").unwrap()).unwrap();
+            let only = values_of_exchange_account_selectors(arg_matches, "only");
+            let skip = values_of_exchange_account_selectors(arg_matches, "skip");
+            let health_endpoint = match value_t!(arg_matches, "health_listen", std::net::SocketAddr)
+            {
+                Ok(listen) => Some(HealthEndpoint::serve(listen, interval * 3).map_err(|err| {
+                    format!("Failed to serve the health endpoint on {listen}: {err}")
+                })?),
+                Err(_) => None,
+            };
+            if let Ok(listen) = value_t!(arg_matches, "metrics_listen", std::net::SocketAddr) {
+                metrics::serve_prometheus(listen)
+                    .map_err(|err| format!("Failed to serve metrics on {listen}: {err}"))?;
+            }
+
+            process_daemon(
+                &mut db,
+                &rpc_clients,
+                only,
+                skip,
+                interval,
+                &notifier,
+                &healthcheck,
+                health_endpoint.as_ref(),
+                verbose,
+            )
+            .await?;
+        }
+        ("db", Some(db_matches)) => match db_matches.subcommand() {
+            ("import", Some(arg_matches)) => {
+                let other_db_path = value_t_or_exit!(arg_matches, "other_db_path", PathBuf);
+                let format = value_t_or_exit!(arg_matches, "format", ImportFormat);
+
+                if format == ImportFormat::Json {
+                    let bytes = fs::read(&other_db_path).map_err(|err| {
+                        format!("Unable to read {}: {err}", other_db_path.display())
+                    })?;
+                    println!("Importing {}", other_db_path.display());
+                    db.import_json(&bytes)?;
+                } else if format != ImportFormat::Sys {
+                    let address = pubkey_of(arg_matches, "address").unwrap();
+                    println!("Importing {}", other_db_path.display());
+                    portfolio_import::import(&mut db, rpc_client, format, &other_db_path, address)
+                        .await?;
+                } else {
+                    let mut other_db_fd_lock =
+                        fd_lock::RwLock::new(fs::File::open(&other_db_path).unwrap());
+                    let _other_db_write_lock = loop {
+                        match other_db_fd_lock.try_write() {
+                            Ok(lock) => break lock,
+                            Err(err) => {
+                                eprintln!(
+                                    "Unable to lock database directory: {}: {}",
+                                    other_db_path.display(),
+                                    err
+                                );
+                                std::thread::sleep(std::time::Duration::from_secs(1));
+                            }
+                        }
+                    };
+
+                    let other_db = db::new(&other_db_path, None).unwrap_or_else(|err| {
+                        eprintln!("Failed to open {}: {}", other_db_path.display(), err);
+                        exit(1)
+                    });
+
+                    println!("Importing {}", other_db_path.display());
+                    db.import_db(other_db)?;
+                }
+            }
+            ("export", Some(arg_matches)) => {
+                let json = db.export_json()?;
+                match value_t!(arg_matches, "path", PathBuf) {
+                    Ok(path) => {
+                        fs::write(&path, json)?;
+                        println!("Exported to {}", path.display());
+                    }
+                    Err(_) => print!("{json}"),
+                }
+            }
+            ("backup", Some(arg_matches)) => {
+                let location = value_t_or_exit!(arg_matches, "to", BackupLocation);
+                let keep = value_t!(arg_matches, "keep", usize).ok();
+
+                backup::backup(&location, &db.backup_data()?, keep).await?;
+            }
+            ("restore", Some(arg_matches)) => {
+                let location = value_t_or_exit!(arg_matches, "from", BackupLocation);
+
+                let data = backup::restore(&location).await?;
+                db.restore_data(&data)?;
+                println!("Database restored");
+            }
+            ("snapshots", Some(arg_matches)) => {
+                if let Ok(keep) = value_t!(arg_matches, "keep", usize) {
+                    db.set_auto_snapshots(keep)?;
+                }
+                match db.get_auto_snapshots() {
+                    0 => println!("Snapshots are not taken before commands"),
+                    keep => println!("Keeping the most recent {keep} snapshots"),
+                }
+                for snapshot in backup::snapshots(&db_path)? {
+                    println!("{}", snapshot.display());
+                }
             }
-        }
-        ("sync", Some(arg_matches)) => {
-            let max_epochs_to_process = value_t!(arg_matches, "max_epochs_to_process", u64).ok();
-            process_sync_swaps(&mut db, rpc_client, &notifier).await?;
-            for (exchange, exchange_credentials, exchange_account) in
-                db.get_default_accounts_from_configured_exchanges()
-            {
-                println!("Synchronizing {exchange:?} {exchange_account}...");
-                let exchange_client = exchange_client_new(exchange, exchange_credentials)?;
-                process_sync_exchange(
-                    &mut db,
-                    exchange,
-                    exchange_client.as_ref(),
-                    rpc_client,
-                    &notifier,
-                )
-                .await?
+            ("migrate", Some(arg_matches)) => {
+                let backend = value_t_or_exit!(arg_matches, "backend", DbBackend);
+
+                let previous_backend = db.backend();
+                match db.migrate(backend)? {
+                    None if previous_backend == backend => {
+                        println!(
+                            "Database already uses the {} backend",
+                            <&str>::from(backend)
+                        )
+                    }
+                    retired_filename => {
+                        println!(
+                            "Database migrated from the {} to the {} backend",
+                            <&str>::from(previous_backend),
+                            <&str>::from(backend)
+                        );
+                        if let Some(retired_filename) = retired_filename {
+                            println!("Previous records saved to {}", retired_filename.display());
+                        }
+                    }
+                }
             }
-            process_account_sync(
-                &mut db,
-                &rpc_clients,
-                None,
-                max_epochs_to_process,
-                false,
-                false,
-                &notifier,
-            )
-            .await?;
-        }
-        ("db", Some(db_matches)) => match db_matches.subcommand() {
-            ("import", Some(arg_matches)) => {
-                let other_db_path = value_t_or_exit!(arg_matches, "other_db_path", PathBuf);
+            ("cache", Some(cache_matches)) => match cache_matches.subcommand() {
+                ("stats", Some(_arg_matches)) => {
+                    let stats = rpc_client_utils::block_date_cache_stats(&db_path);
+                    println!("Cached block dates: {}", stats.entries);
+                    if let Some((first_slot, last_slot)) = stats.slots {
+                        println!("Slots: {first_slot} to {last_slot}");
+                    }
+                    println!("Size: {} bytes", stats.file_size);
+                }
+                ("clear", Some(_arg_matches)) => {
+                    rpc_client_utils::clear_block_date_cache(&db_path)?;
+                    println!("Block date cache cleared");
+                }
+                _ => unreachable!(),
+            },
+            ("price-cache", Some(price_cache_matches)) => match price_cache_matches.subcommand() {
+                ("warm", Some(arg_matches)) => {
+                    let from =
+                        naivedate_of(&value_t_or_exit!(arg_matches, "from", String)).unwrap();
+                    let to = value_t!(arg_matches, "to", String)
+                        .map(|s| naivedate_of(&s).unwrap())
+                        .unwrap_or_else(|_| Local::now().date_naive().pred_opt().unwrap());
+                    let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
 
-                let mut other_db_fd_lock =
-                    fd_lock::RwLock::new(fs::File::open(&other_db_path).unwrap());
-                let _other_db_write_lock = loop {
-                    match other_db_fd_lock.try_write() {
-                        Ok(lock) => break lock,
-                        Err(err) => {
-                            eprintln!(
-                                "Unable to lock database directory: {}: {}",
-                                other_db_path.display(),
-                                err
-                            );
-                            std::thread::sleep(std::time::Duration::from_secs(1));
+                    let prices = coin_gecko::get_historical_prices(from, to, &token).await?;
+                    println!(
+                        "Fetched {} {token} prices from {from} to {to}",
+                        prices.len()
+                    );
+                }
+                ("prune", Some(arg_matches)) => {
+                    let before = value_t!(arg_matches, "before", String)
+                        .map(|s| naivedate_of(&s).unwrap())
+                        .ok();
+                    let pruned = db.prune_historical_prices(before)?;
+                    println!("Removed {pruned} cached prices");
+                }
+                _ => unreachable!(),
+            },
+            ("encrypt", Some(_arg_matches)) => {
+                let passphrase = match std::env::var("SYS_CREDENTIALS_PASSPHRASE") {
+                    Ok(passphrase) => passphrase,
+                    Err(_) => {
+                        let passphrase =
+                            solana_clap_utils::keypair::prompt_passphrase("Passphrase: ")?;
+                        if passphrase
+                            != solana_clap_utils::keypair::prompt_passphrase(
+                                "Confirm passphrase: ",
+                            )?
+                        {
+                            return Err("Passphrases do not match".into());
                         }
+                        passphrase
                     }
                 };
+                if passphrase.is_empty() {
+                    return Err("Passphrase must not be empty".into());
+                }
 
-                let other_db = db::new(&other_db_path).unwrap_or_else(|err| {
-                    eprintln!("Failed to open {}: {}", other_db_path.display(), err);
-                    exit(1)
-                });
+                db.encrypt_credentials(&passphrase)?;
+                #[cfg(feature = "keychain")]
+                {
+                    keychain_entry(&db_path)?.set_password(&passphrase)?;
+                    println!("Passphrase saved to the OS keychain");
+                }
+                println!("Credentials encrypted");
+            }
+            ("decrypt", Some(_arg_matches)) => {
+                if db.credentials_locked() {
+                    let passphrase = solana_clap_utils::keypair::prompt_passphrase("Passphrase: ")?;
+                    db.unlock_credentials(&passphrase)?;
+                }
 
-                println!("Importing {}", other_db_path.display());
-                db.import_db(other_db)?;
+                db.decrypt_credentials()?;
+                #[cfg(feature = "keychain")]
+                if let Ok(entry) = keychain_entry(&db_path) {
+                    let _ = entry.delete_password();
+                }
+                println!("Credentials decrypted");
+            }
+            ("dedupe", Some(arg_matches)) => {
+                let confirm = arg_matches.is_present("confirm");
+                process_db_dedupe(&mut db, confirm)?;
+            }
+            ("close-period", Some(arg_matches)) => {
+                let period = period_of(&value_t_or_exit!(arg_matches, "period", String)).unwrap();
+                db.close_period(period)?;
+                println!("Closed {}", period.format("%Y-%m"));
+            }
+            ("reopen-period", Some(arg_matches)) => {
+                let period = period_of(&value_t_or_exit!(arg_matches, "period", String)).unwrap();
+                let reason = value_t_or_exit!(arg_matches, "reason", String);
+                db.reopen_period(period, reason)?;
+                println!("Reopened {}", period.format("%Y-%m"));
+            }
+            ("closed-periods", Some(_arg_matches)) => {
+                let closed_periods = db.closed_periods();
+                if closed_periods.is_empty() {
+                    println!("No closed periods");
+                }
+                for period in closed_periods {
+                    println!("{}", period.format("%Y-%m"));
+                }
+            }
+            ("repair-pending", Some(_arg_matches)) => {
+                process_db_repair_pending(&mut db, rpc_client, &notifier).await?;
+            }
+            ("audit-rounding", Some(_arg_matches)) => {
+                process_db_audit_rounding(&db);
+            }
+            ("audit-log", Some(_arg_matches)) => {
+                let audit_log = db.audit_log();
+                if audit_log.is_empty() {
+                    println!("No audit log entries");
+                }
+                for entry in audit_log {
+                    println!("{}: {}", entry.when, entry.message);
+                }
+            }
+            ("history", Some(_arg_matches)) => {
+                process_db_history(&db);
+            }
+            ("undo", Some(arg_matches)) => {
+                let count = value_t_or_exit!(arg_matches, "count", usize);
+                process_db_undo(&mut db, rpc_client, count)?;
             }
             _ => unreachable!(),
         },
@@ -6168,6 +15405,349 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             _ => unreachable!(),
         },
+        ("notifications", Some(notifications_matches)) => {
+            let mut routes = db.get_notification_routes();
+            match notifications_matches.subcommand() {
+                ("routes", Some(_arg_matches)) => {}
+                ("add-route", Some(arg_matches)) => {
+                    routes.push(NotificationRoute {
+                        severity: value_t_or_exit!(arg_matches, "severity", Severity),
+                        kind: value_t!(arg_matches, "kind", NotificationKind).ok(),
+                        destinations: values_t_or_exit!(arg_matches, "destinations", Destination)
+                            .into_iter()
+                            .unique()
+                            .collect(),
+                    });
+                    db.set_notification_routes(routes.clone())?;
+                }
+                ("remove-route", Some(arg_matches)) => {
+                    let route = value_t_or_exit!(arg_matches, "route", usize);
+                    if route == 0 || route > routes.len() {
+                        return Err(format!("Route {route} does not exist").into());
+                    }
+                    routes.remove(route - 1);
+                    db.set_notification_routes(routes.clone())?;
+                }
+                ("clear-routes", Some(_arg_matches)) => {
+                    routes.clear();
+                    db.set_notification_routes(routes.clone())?;
+                }
+                _ => unreachable!(),
+            }
+
+            if routes.is_empty() {
+                println!("No notification routes, all notifications go to every destination");
+            } else {
+                for (i, route) in routes.iter().enumerate() {
+                    println!("{:>3}. {route}", i + 1);
+                }
+            }
+        }
+        ("stats", Some(arg_matches)) => {
+            let days = value_t_or_exit!(arg_matches, "days", u32);
+            process_stats(days);
+        }
+        ("fees", Some(fees_matches)) => match fees_matches.subcommand() {
+            ("report", Some(arg_matches)) => {
+                let year = value_t!(arg_matches, "year", i32).ok();
+                process_fees_report(&db, year)?;
+            }
+            _ => unreachable!(),
+        },
+        ("workflow", Some(workflow_matches)) => {
+            let mut signer_from_path = |path: &str| {
+                solana_clap_utils::keypair::signer_from_path(
+                    workflow_matches,
+                    path,
+                    "authority",
+                    &mut wallet_manager,
+                )
+            };
+
+            let id = match workflow_matches.subcommand() {
+                ("ls", Some(_arg_matches)) => {
+                    let workflows = db.workflows();
+                    if workflows.is_empty() {
+                        println!("No workflows");
+                    }
+                    for workflow in workflows {
+                        println!(
+                            "{}: {}, started {}, step {} of {}, {}",
+                            workflow.id,
+                            workflow.name,
+                            HumanTime::from(workflow.created),
+                            (workflow.current_step + 1).min(workflow.steps.len()),
+                            workflow.steps.len(),
+                            workflow.state,
+                        );
+                        if verbose {
+                            for (i, step) in workflow.steps.iter().enumerate() {
+                                println!("  {}. {step}", i + 1);
+                            }
+                        }
+                    }
+                    None
+                }
+                ("resume", Some(arg_matches)) => {
+                    let retry_step = arg_matches.is_present("retry_step");
+                    let ids = match value_t!(arg_matches, "id", usize) {
+                        Ok(id) => vec![id],
+                        Err(_) => db
+                            .workflows()
+                            .into_iter()
+                            .filter(|workflow| !workflow.is_finished())
+                            .map(|workflow| workflow.id)
+                            .collect(),
+                    };
+                    for id in ids {
+                        process_workflow_resume(
+                            &mut db,
+                            &rpc_clients,
+                            id,
+                            retry_step,
+                            &mut signer_from_path,
+                            &notifier,
+                            priority_fee,
+                            verbose,
+                        )
+                        .await?;
+                    }
+                    None
+                }
+                ("cancel", Some(arg_matches)) => {
+                    let id = value_t_or_exit!(arg_matches, "id", usize);
+                    db.cancel_workflow(id)?;
+                    println!("Cancelled workflow {id}");
+                    None
+                }
+                ("deposit-and-sell", Some(arg_matches)) => {
+                    let token = MaybeToken::SOL();
+                    let exchange = value_t_or_exit!(arg_matches, "exchange", Exchange);
+                    let exchange_account = value_t!(arg_matches, "exchange_account", String)
+                        .ok()
+                        .unwrap_or_default();
+                    let amount = match arg_matches.value_of("amount").unwrap() {
+                        "ALL" => None,
+                        amount => Some(token.amount(amount.parse().unwrap())),
+                    };
+                    let from_address = pubkey_of(arg_matches, "from").unwrap();
+                    let authority = arg_matches
+                        .value_of("by")
+                        .or_else(|| arg_matches.value_of("from"))
+                        .unwrap()
+                        .to_string();
+                    let price = value_t!(arg_matches, "at", f64).ok();
+                    let pair = value_t!(arg_matches, "pair", String).ok();
+
+                    let workflow = db.add_workflow(
+                        "deposit-and-sell",
+                        vec![
+                            WorkflowStep::ExchangeDeposit {
+                                exchange,
+                                exchange_account: exchange_account.clone(),
+                                token,
+                                from_address,
+                                amount,
+                                authority,
+                            },
+                            WorkflowStep::ExchangeSell {
+                                exchange,
+                                exchange_account,
+                                pair,
+                                amount: None,
+                                price,
+                            },
+                        ],
+                    )?;
+                    Some(workflow.id)
+                }
+                ("sweep-then-deposit", Some(arg_matches)) => {
+                    let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
+                    let exchange = value_t_or_exit!(arg_matches, "exchange", Exchange);
+                    let exchange_account = value_t!(arg_matches, "exchange_account", String)
+                        .ok()
+                        .unwrap_or_default();
+                    let from_address = pubkey_of(arg_matches, "from").unwrap();
+                    let to_address = pubkey_of(arg_matches, "to").unwrap();
+                    let retain_amount =
+                        token.amount(value_t!(arg_matches, "retain", f64).unwrap_or(0.));
+                    let sweep_authority = arg_matches.value_of("authority").unwrap().to_string();
+                    let deposit_authority = arg_matches
+                        .value_of("by")
+                        .or_else(|| arg_matches.value_of("to"))
+                        .unwrap()
+                        .to_string();
+
+                    let workflow = db.add_workflow(
+                        "sweep-then-deposit",
+                        vec![
+                            WorkflowStep::Sweep {
+                                from_address,
+                                token,
+                                retain_amount,
+                                to_address,
+                                authority: sweep_authority,
+                            },
+                            WorkflowStep::ExchangeDeposit {
+                                exchange,
+                                exchange_account,
+                                token,
+                                from_address: to_address,
+                                amount: None,
+                                authority: deposit_authority,
+                            },
+                        ],
+                    )?;
+                    Some(workflow.id)
+                }
+                ("unstake-then-swap", Some(arg_matches)) => {
+                    let stake_address = pubkey_of(arg_matches, "stake_address").unwrap();
+                    let to_address = pubkey_of(arg_matches, "to").unwrap();
+                    let to_token = MaybeToken::from(value_t!(arg_matches, "to_token", Token).ok());
+                    let slippage_bps = value_t_or_exit!(arg_matches, "slippage_bps", u64);
+                    let max_coingecko_value_percentage_loss =
+                        value_t_or_exit!(arg_matches, "max_coingecko_value_percentage_loss", f64);
+                    let stake_authority = arg_matches.value_of("authority").unwrap().to_string();
+                    let swap_authority = arg_matches
+                        .value_of("by")
+                        .or_else(|| arg_matches.value_of("to"))
+                        .unwrap()
+                        .to_string();
+
+                    let workflow = db.add_workflow(
+                        "unstake-then-swap",
+                        vec![
+                            WorkflowStep::Deactivate {
+                                stake_address,
+                                authority: stake_authority.clone(),
+                            },
+                            WorkflowStep::Withdraw {
+                                stake_address,
+                                to_address,
+                                authority: stake_authority,
+                            },
+                            WorkflowStep::Swap {
+                                address: to_address,
+                                from_token: MaybeToken::SOL(),
+                                to_token,
+                                amount: None,
+                                slippage_bps,
+                                max_coingecko_value_percentage_loss,
+                                authority: swap_authority,
+                            },
+                        ],
+                    )?;
+                    Some(workflow.id)
+                }
+                _ => unreachable!(),
+            };
+
+            if let Some(id) = id {
+                println!("Started workflow {id}");
+                process_workflow_resume(
+                    &mut db,
+                    &rpc_clients,
+                    id,
+                    false,
+                    &mut signer_from_path,
+                    &notifier,
+                    priority_fee,
+                    verbose,
+                )
+                .await?;
+            }
+        }
+        ("profile", Some(profile_matches)) => match profile_matches.subcommand() {
+            ("ls", Some(_arg_matches)) => {
+                println!("default");
+                for profile in db.profiles() {
+                    println!("{profile}");
+                }
+            }
+            ("add", Some(arg_matches)) => {
+                let name = value_t_or_exit!(arg_matches, "name", String);
+                db.add_profile(&name)?;
+                println!("Added profile {name}, select it with `--profile {name}`");
+            }
+            _ => unreachable!(),
+        },
+        ("token", Some(token_matches)) => match token_matches.subcommand() {
+            ("ls", Some(_arg_matches)) => {
+                for custom_token in db.custom_tokens() {
+                    println!(
+                        "{}: mint {}, {} decimals, CoinGecko id {}",
+                        custom_token.name,
+                        custom_token.mint,
+                        custom_token.decimals,
+                        custom_token.coingecko_id
+                    );
+                }
+            }
+            ("add", Some(arg_matches)) => {
+                let mint = pubkey_of(arg_matches, "mint").unwrap();
+                let name = value_t_or_exit!(arg_matches, "symbol", String);
+                let coingecko_id = value_t_or_exit!(arg_matches, "coingecko_id", String);
+                let decimals = value_t_or_exit!(arg_matches, "decimals", u8);
+
+                if name == "SOL" || Token::from_str(&name).is_ok() {
+                    return Err(format!("Token already exists: {name}").into());
+                }
+
+                let mint_account = rpc_client
+                    .get_account_with_commitment(&mint, rpc_client.commitment())?
+                    .value
+                    .ok_or_else(|| format!("Mint does not exist: {mint}"))?;
+                if mint_account.owner != spl_token::id()
+                    && mint_account.owner != spl_token_2022::id()
+                {
+                    return Err(format!("Not a token mint: {mint}").into());
+                }
+                let mint_state = spl_token_2022::extension::StateWithExtensions::<
+                    spl_token_2022::state::Mint,
+                >::unpack(&mint_account.data)?;
+                if mint_state.base.decimals != decimals {
+                    return Err(format!(
+                        "Mint has {} decimals, not {decimals}",
+                        mint_state.base.decimals
+                    )
+                    .into());
+                }
+
+                db.add_custom_token(CustomToken {
+                    mint,
+                    name: name.clone(),
+                    coingecko_id,
+                    decimals,
+                    program_id: mint_account.owner,
+                })?;
+                println!("Added token {name}");
+            }
+            ("symbol", Some(arg_matches)) => match arg_matches.value_of("token") {
+                None => {
+                    let token_symbols = db.get_token_symbols();
+                    if token_symbols.is_empty() {
+                        println!("No token symbols set");
+                    }
+                    for (token, symbol) in token_symbols.into_iter().sorted() {
+                        println!("{token}: {symbol:?}, default {:?}", token.default_symbol());
+                    }
+                }
+                Some(token_name) => {
+                    let token = MaybeToken::from(Token::from_str(token_name).ok());
+                    let symbol = if arg_matches.is_present("default") {
+                        db.set_token_symbol(token, None)?;
+                        token.default_symbol().to_string()
+                    } else if let Some(symbol) = arg_matches.value_of("symbol") {
+                        db.set_token_symbol(token, Some(symbol.to_string()))?;
+                        symbol.to_string()
+                    } else {
+                        token.symbol().to_string()
+                    };
+                    println!("{token} symbol: {symbol:?}");
+                }
+            },
+            _ => unreachable!(),
+        },
         ("account", Some(account_matches)) => match account_matches.subcommand() {
             ("lot", Some(lot_matches)) => match lot_matches.subcommand() {
                 ("swap", Some(arg_matches)) => {
@@ -6250,6 +15830,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             .expect("to");
                     db.move_lot(lot_number, to_address)?;
                 }
+                ("edit-disposal", Some(arg_matches)) => {
+                    let lot_number = value_t_or_exit!(arg_matches, "lot_number", usize);
+                    let price = value_t!(arg_matches, "price", Decimal).ok();
+                    let when = value_t!(arg_matches, "when", String)
+                        .map(|s| naivedate_of(&s).unwrap())
+                        .ok();
+                    let description = value_t!(arg_matches, "description", String).ok();
+
+                    let (original_disposed_lot, edited_disposed_lot) =
+                        db.edit_disposed_lot(lot_number, when, price, description)?;
+                    println!(
+                        "Before: {}",
+                        format_disposed_lot(
+                            &original_disposed_lot,
+                            &mut Decimal::ZERO,
+                            &mut Decimal::ZERO,
+                            &mut false,
+                            &mut Decimal::ZERO,
+                            true
+                        )
+                    );
+                    println!(
+                        "After:  {}",
+                        format_disposed_lot(
+                            &edited_disposed_lot,
+                            &mut Decimal::ZERO,
+                            &mut Decimal::ZERO,
+                            &mut false,
+                            &mut Decimal::ZERO,
+                            true
+                        )
+                    );
+                }
                 ("delete", Some(arg_matches)) => {
                     let lot_numbers = lot_numbers_of(arg_matches, "lot_numbers").unwrap();
                     let confirm = arg_matches.is_present("confirm");
@@ -6297,12 +15910,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .await?;
                 process_account_sync(
                     &mut db,
-                    &rpc_clients,
+                    rpc_clients.default(),
+                    Some(&rpc_clients),
                     Some(address),
                     None,
                     false,
                     false,
                     &notifier,
+                    verbose,
                 )
                 .await?;
             }
@@ -6335,32 +15950,187 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 )
                 .await?;
             }
+            ("ls", Some(arg_matches)) if arg_matches.is_present("archived") => {
+                process_account_list_archived(&db, rpc_client, verbose).await?;
+            }
             ("ls", Some(arg_matches)) => {
                 let all = arg_matches.is_present("all");
                 let summary = arg_matches.is_present("summary");
                 let account_filter = pubkey_of(arg_matches, "account");
+                let as_of = value_t!(arg_matches, "as_of", String)
+                    .map(|s| naivedate_of(&s).unwrap())
+                    .ok();
+                if arg_matches.is_present("all_profiles") {
+                    if let Some(profile) = db.profile() {
+                        return Err(format!(
+                            "--all-profiles cannot be combined with profile {profile}"
+                        )
+                        .into());
+                    }
+                    db.combine_profiles()?;
+                }
+                if output_format == OutputFormat::Json {
+                    return process_account_list_json(&db, rpc_client, account_filter, as_of).await;
+                }
+                let lot_columns = match values_t!(arg_matches, "columns", LotColumn) {
+                    Ok(lot_columns) => lot_columns,
+                    Err(_) => db.get_lot_columns(),
+                };
                 process_account_list(
                     &db,
                     rpc_client,
                     account_filter,
                     all,
                     summary,
+                    as_of,
+                    &lot_columns,
                     &notifier,
                     verbose,
                 )
                 .await?;
             }
+            ("stale", Some(arg_matches)) => {
+                let days = value_t_or_exit!(arg_matches, "days", u32);
+                process_account_stale(&db, rpc_client, days.into())?;
+            }
             ("cost-basis", Some(arg_matches)) => {
                 let when = value_t!(arg_matches, "when", String)
                     .map(|s| naivedate_of(&s).unwrap())
                     .unwrap();
 
-                process_account_cost_basis(&db, when).await?;
+                process_account_cost_basis(&db, when, output_format).await?;
             }
             ("xls", Some(arg_matches)) => {
                 let outfile = value_t_or_exit!(arg_matches, "outfile", String);
                 let filter_by_year = value_t!(arg_matches, "year", i32).ok();
-                process_account_xls(&db, &outfile, filter_by_year).await?;
+                let include_wash_sales = arg_matches.is_present("wash_sales");
+                process_account_xls(
+                    &db,
+                    rpc_client,
+                    &outfile,
+                    filter_by_year,
+                    include_wash_sales,
+                    &export_options_of(arg_matches),
+                )
+                .await?;
+            }
+            ("csv", Some(arg_matches)) => {
+                let outfile = value_t_or_exit!(arg_matches, "outfile", PathBuf);
+                let format = value_t_or_exit!(arg_matches, "format", ExportFormat);
+                let filter_by_year = value_t!(arg_matches, "year", i32).ok();
+                let fees_usd = disposal_fees_usd(&db, rpc_client).await?;
+                tax_export::export(
+                    &db,
+                    format,
+                    &outfile,
+                    filter_by_year,
+                    &fees_usd,
+                    &export_options_of(arg_matches),
+                )?;
+            }
+            ("harvest", Some(arg_matches)) => {
+                let target_loss = value_t_or_exit!(arg_matches, "target_loss", f64);
+                let long_term = arg_matches.value_of("term").map(|term| term == "long");
+                let execute = arg_matches.is_present("execute");
+                let signer = match signer_of(arg_matches, "signer", &mut wallet_manager)? {
+                    (Some(signer), Some(address)) => Some((address, signer)),
+                    _ => None,
+                };
+
+                process_account_harvest(
+                    &mut db,
+                    &rpc_clients,
+                    target_loss,
+                    long_term,
+                    execute,
+                    signer,
+                    priority_fee,
+                    &notifier,
+                    verbose,
+                )
+                .await?;
+            }
+            ("wash-sales", Some(arg_matches)) => {
+                let filter_by_year = value_t!(arg_matches, "year", i32).ok();
+                process_account_wash_sales(&db, filter_by_year);
+            }
+            ("rewards-summary", Some(arg_matches)) => {
+                let year = value_t_or_exit!(arg_matches, "year", i32);
+                process_account_rewards_summary(&db, rpc_client, year)?;
+            }
+            ("stream", Some(stream_matches)) => match stream_matches.subcommand() {
+                ("ls", Some(_arg_matches)) => {
+                    process_account_stream_ls(&db, rpc_client).await?;
+                }
+                ("claim", Some(arg_matches)) => {
+                    let address = pubkey_of(arg_matches, "address").unwrap();
+                    let (recipient_signer, _recipient_address) =
+                        signer_of(arg_matches, "by", &mut wallet_manager)?;
+
+                    process_account_stream_claim(
+                        &mut db,
+                        &rpc_clients,
+                        address,
+                        vec![recipient_signer.expect("recipient_signer")],
+                        priority_fee,
+                        verbose,
+                    )
+                    .await?;
+                }
+                _ => unreachable!(),
+            },
+            ("vesting", Some(vesting_matches)) => match vesting_matches.subcommand() {
+                ("add", Some(arg_matches)) => {
+                    let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
+                    let address = pubkey_of(arg_matches, "address").unwrap();
+                    let amount = token.amount(value_t_or_exit!(arg_matches, "amount", f64));
+                    let name = value_t_or_exit!(arg_matches, "name", String);
+                    let start = naivedate_of(&value_t_or_exit!(arg_matches, "start", String))?;
+                    let cliff = value_t!(arg_matches, "cliff", String)
+                        .ok()
+                        .map(|cliff| naivedate_of(&cliff).unwrap())
+                        .unwrap_or(start);
+                    let end = naivedate_of(&value_t_or_exit!(arg_matches, "end", String))?;
+                    let interval_months = value_t_or_exit!(arg_matches, "interval_months", u32);
+                    let received = value_t!(arg_matches, "received", f64)
+                        .map(|received| token.amount(received))
+                        .unwrap_or_default();
+                    process_account_vesting_add(
+                        &mut db,
+                        name,
+                        address,
+                        token,
+                        amount,
+                        start,
+                        cliff,
+                        end,
+                        interval_months,
+                        received,
+                    )?;
+                }
+                ("remove", Some(arg_matches)) => {
+                    let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
+                    let address = pubkey_of(arg_matches, "address").unwrap();
+                    let name = value_t_or_exit!(arg_matches, "name", String);
+                    if !db.vesting_grants().iter().any(|vesting_grant| {
+                        vesting_grant.name == name
+                            && vesting_grant.address == address
+                            && vesting_grant.token == token
+                    }) {
+                        return Err(format!("No vesting grant {name} to {address}").into());
+                    }
+                    db.remove_vesting_grant(&name, address, token)?;
+                    println!("Removed vesting grant {name}");
+                }
+                ("ls", Some(_arg_matches)) => {
+                    process_account_vesting_ls(&db, rpc_client).await?;
+                }
+                _ => unreachable!(),
+            },
+            ("rewards-csv", Some(arg_matches)) => {
+                let address = pubkey_of(arg_matches, "address").unwrap();
+                let year = value_t_or_exit!(arg_matches, "year", i32);
+                process_account_rewards_csv(&db, address, year)?;
             }
             ("remove", Some(arg_matches)) => {
                 let address = pubkey_of(arg_matches, "address").unwrap();
@@ -6384,12 +16154,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
 
                 if confirm {
-                    db.remove_account(address, token)?;
-                    println!("Removed {address} ({token})");
+                    db.archive_account(address, token)?;
+                    println!("Archived {address} ({token})");
                 } else {
                     println!("Add --confirm to remove {address} ({token})");
                 }
             }
+            ("restore", Some(arg_matches)) => {
+                let address = pubkey_of(arg_matches, "address").unwrap();
+                let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
+
+                db.restore_account(address, token)?;
+                println!("Restored {address} ({token})");
+            }
             ("set-sweep-stake-account", Some(arg_matches)) => {
                 let address = pubkey_of(arg_matches, "address").unwrap();
                 let stake_authority = std::fs::canonicalize(value_t_or_exit!(
@@ -6416,6 +16193,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 println!("Sweep stake account set to {address}");
             }
+            ("set-sol-reserve", Some(arg_matches)) => {
+                let address = pubkey_of(arg_matches, "address").unwrap();
+                let lamports = sol_to_lamports(value_t_or_exit!(arg_matches, "amount", f64));
+
+                db.set_sol_reserve(address, lamports)?;
+                if lamports == 0 {
+                    println!("Minimum SOL reserve of {address} removed");
+                } else {
+                    println!("Minimum SOL reserve of {address} set to {}", Sol(lamports));
+                }
+            }
+            ("sol-reserves", Some(_arg_matches)) => {
+                let sol_reserves = db.sol_reserves();
+                if sol_reserves.is_empty() {
+                    println!("No SOL reserves");
+                }
+                for SolReserve { address, lamports } in sol_reserves {
+                    println!("{address}: {}", Sol(lamports));
+                }
+                if let Some(ata_rent_funder) = db.get_ata_rent_funder() {
+                    println!("ATA rent funder: {}", ata_rent_funder.display());
+                }
+            }
+            ("set-ata-rent-funder", Some(arg_matches)) => {
+                match value_t!(arg_matches, "keypair", PathBuf) {
+                    Ok(keypair) => {
+                        let keypair = std::fs::canonicalize(keypair)?;
+                        let ata_rent_funder = read_keypair_file(&keypair).map_err(|err| {
+                            format!("Failed to read {}: {}", keypair.display(), err)
+                        })?;
+                        db.set_ata_rent_funder(Some(keypair))?;
+                        println!("ATA rent funder set to {}", ata_rent_funder.pubkey());
+                    }
+                    Err(_) => {
+                        db.set_ata_rent_funder(None)?;
+                        println!("ATA rent funder removed");
+                    }
+                }
+            }
             ("set-tax-rate", Some(arg_matches)) => {
                 let income = arg_matches
                     .value_of("income")
@@ -6455,6 +16271,104 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 } else {
                     println!("(unset)");
                 }
+                if let Some(income_withholding_rate) = db.get_income_withholding_rate() {
+                    println!("Income withholding rate: {income_withholding_rate:.2}");
+                }
+            }
+            ("lot-columns", Some(arg_matches)) => {
+                if arg_matches.is_present("default") {
+                    db.set_lot_columns(None)?;
+                } else if let Ok(lot_columns) = values_t!(arg_matches, "columns", LotColumn) {
+                    db.set_lot_columns(Some(lot_columns))?;
+                }
+                println!(
+                    "Lot columns: {}",
+                    db.get_lot_columns().into_iter().map(<&str>::from).join(",")
+                );
+            }
+            ("number-format", Some(arg_matches)) => {
+                let mut number_format = db.get_number_format();
+                if arg_matches.is_present("default") {
+                    db.set_number_format(None)?;
+                    number_format = NumberFormat::default();
+                } else if arg_matches.is_present("thousands_separator")
+                    || arg_matches.is_present("decimal_separator")
+                    || arg_matches.is_present("currency_symbol")
+                {
+                    if let Ok(thousands_separator) =
+                        value_t!(arg_matches, "thousands_separator", String)
+                    {
+                        number_format.thousands_separator = thousands_separator;
+                    }
+                    if let Ok(decimal_separator) = value_t!(arg_matches, "decimal_separator", char)
+                    {
+                        number_format.decimal_separator = decimal_separator;
+                    }
+                    if let Ok(currency_symbol) = value_t!(arg_matches, "currency_symbol", String) {
+                        number_format.currency_symbol = currency_symbol;
+                    }
+                    if number_format
+                        .thousands_separator
+                        .contains(number_format.decimal_separator)
+                    {
+                        return Err("The thousands and decimal separators must differ".into());
+                    }
+                    db.set_number_format(Some(number_format.clone()))?;
+                }
+                println!("Number format: {number_format}");
+            }
+            ("fee-treatment", Some(arg_matches)) => {
+                if let Ok(fee_treatment) = value_t!(arg_matches, "treatment", FeeTreatment) {
+                    db.set_fee_treatment(fee_treatment)?;
+                }
+                println!(
+                    "Transaction fees of swaps: {}",
+                    match db.get_fee_treatment() {
+                        FeeTreatment::Expense => "expensed",
+                        FeeTreatment::Capitalize => "capitalized into the basis of acquired lots",
+                    }
+                );
+            }
+            ("set-withholding-rate", Some(arg_matches)) => {
+                let rate = value_t_or_exit!(arg_matches, "rate", f64);
+
+                if rate > 0. {
+                    println!("Income withholding rate: {rate:.2}");
+                    db.set_income_withholding_rate(Some(rate))?;
+                } else {
+                    println!("Income withholding disabled");
+                    db.set_income_withholding_rate(None)?;
+                }
+            }
+            ("tax-reserve", Some(_arg_matches)) => {
+                if db.get_income_withholding_rate().is_none() {
+                    println!("Income withholding rate is not set");
+                } else {
+                    for payment in db.tax_reserve_payments() {
+                        println!(
+                            "{} | {:>15} | {}",
+                            payment.when,
+                            currency(payment.amount.localized_string_with_fixed_place(2)),
+                            payment.description
+                        );
+                    }
+                    println_tax_reserve(&db);
+                }
+            }
+            ("tax-reserve-payment", Some(arg_matches)) => {
+                let amount = value_t_or_exit!(arg_matches, "amount", f64);
+                let when = value_t!(arg_matches, "when", String)
+                    .map(|s| naivedate_of(&s).unwrap())
+                    .unwrap_or_else(|_| today());
+                let description = value_t!(arg_matches, "description", String)
+                    .unwrap_or_else(|_| "Estimated tax payment".into());
+
+                db.record_tax_reserve_payment(TaxReservePayment {
+                    when,
+                    amount,
+                    description,
+                })?;
+                println_tax_reserve(&db);
             }
             ("merge", Some(arg_matches)) => {
                 let from_address = pubkey_of(arg_matches, "from_address").unwrap();
@@ -6471,6 +16385,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let authority_address = authority_address.expect("authority_address");
                 let authority_signer = authority_signer.expect("authority_signer");
                 let signature = value_t!(arg_matches, "transaction", Signature).ok();
+                let durable_nonce = durable_nonce_of(arg_matches, &mut wallet_manager)?;
 
                 process_account_merge(
                     &mut db,
@@ -6479,8 +16394,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     into_address,
                     authority_address,
                     vec![authority_signer],
+                    durable_nonce,
                     priority_fee,
                     signature,
+                    verbose,
                 )
                 .await?;
             }
@@ -6496,6 +16413,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let no_sweep_ok = arg_matches.is_present("no_sweep_ok");
                 let to_address = pubkey_of(arg_matches, "to");
                 let signature = value_t!(arg_matches, "transaction", Signature).ok();
+                let durable_nonce = durable_nonce_of(arg_matches, &mut wallet_manager)?;
 
                 process_account_sweep(
                     &mut db,
@@ -6507,10 +16425,59 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     no_sweep_ok,
                     from_authority_address,
                     vec![from_authority_signer],
+                    durable_nonce,
                     to_address,
                     &notifier,
                     priority_fee,
                     signature,
+                    verbose,
+                )
+                .await?;
+            }
+            ("dust-sweep", Some(arg_matches)) => {
+                let address = pubkey_of(arg_matches, "address").unwrap();
+                let to_address = pubkey_of(arg_matches, "to_address").unwrap();
+                let below = value_t_or_exit!(arg_matches, "below", f64);
+
+                let (authority_signer, authority_address) = if arg_matches.is_present("by") {
+                    signer_of(arg_matches, "by", &mut wallet_manager)?
+                } else {
+                    signer_of(arg_matches, "address", &mut wallet_manager).map_err(|err| {
+                        format!("Authority not found, consider using the `--by` argument): {err}")
+                    })?
+                };
+                let durable_nonce = durable_nonce_of(arg_matches, &mut wallet_manager)?;
+
+                process_account_dust_sweep(
+                    &mut db,
+                    &rpc_clients,
+                    address,
+                    to_address,
+                    below,
+                    authority_address.expect("authority_address"),
+                    vec![authority_signer.expect("authority_signer")],
+                    durable_nonce,
+                    priority_fee,
+                    verbose,
+                )
+                .await?;
+            }
+            ("collect", Some(arg_matches)) => {
+                let to_address = pubkey_of(arg_matches, "to_address").unwrap();
+                let stake_addresses = pubkeys_of(arg_matches, "stake_addresses")
+                    .map(|stake_addresses| stake_addresses.into_iter().collect());
+                let (authority_signer, authority_address) =
+                    signer_of(arg_matches, "by", &mut wallet_manager)?;
+
+                process_account_collect(
+                    &mut db,
+                    &rpc_clients,
+                    stake_addresses,
+                    to_address,
+                    authority_address.expect("authority_address"),
+                    vec![authority_signer.expect("authority_signer")],
+                    priority_fee,
+                    verbose,
                 )
                 .await?;
             }
@@ -6537,6 +16504,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let authority_address = authority_address.expect("authority_address");
                 let authority_signer = authority_signer.expect("authority_signer");
                 let if_balance_exceeds = value_t!(arg_matches, "if_balance_exceeds", f64).ok();
+                let durable_nonce = durable_nonce_of(arg_matches, &mut wallet_manager)?;
 
                 process_account_split(
                     &mut db,
@@ -6548,9 +16516,96 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     lot_numbers,
                     authority_address,
                     vec![authority_signer],
+                    durable_nonce,
                     into_keypair,
                     if_balance_exceeds,
                     priority_fee,
+                    verbose,
+                )
+                .await?;
+            }
+            ("delegate", Some(arg_matches)) => {
+                let (signer, from_address) =
+                    signer_of(arg_matches, "from_address", &mut wallet_manager)?;
+                let amount = MaybeToken::SOL().amount(value_t_or_exit!(arg_matches, "amount", f64));
+                let vote_account_address = pubkey_of(arg_matches, "vote_account_address").unwrap();
+                let description = value_t!(arg_matches, "description", String).ok();
+                let lot_numbers = lot_numbers_of(arg_matches, "lot_numbers");
+                let lot_selection_method =
+                    value_t_or_exit!(arg_matches, "lot_selection", LotSelectionMethod);
+                let stake_keypair = keypair_of(arg_matches, "stake_keypair");
+
+                process_account_delegate(
+                    &mut db,
+                    &rpc_clients,
+                    from_address.expect("from_address"),
+                    amount,
+                    vote_account_address,
+                    description,
+                    lot_selection_method,
+                    lot_numbers,
+                    vec![signer.expect("signer")],
+                    stake_keypair,
+                    priority_fee,
+                    verbose,
+                )
+                .await?;
+            }
+            ("deactivate", Some(arg_matches)) => {
+                let address = pubkey_of(arg_matches, "address").unwrap();
+
+                let (authority_signer, authority_address) = if arg_matches.is_present("by") {
+                    signer_of(arg_matches, "by", &mut wallet_manager)?
+                } else {
+                    signer_of(arg_matches, "address", &mut wallet_manager).map_err(|err| {
+                        format!("Authority not found, consider using the `--by` argument): {err}")
+                    })?
+                };
+
+                process_account_deactivate(
+                    &db,
+                    &rpc_clients,
+                    address,
+                    authority_address.expect("authority_address"),
+                    vec![authority_signer.expect("authority_signer")],
+                    priority_fee,
+                    verbose,
+                )
+                .await?;
+            }
+            ("withdraw-stake", Some(arg_matches)) => {
+                let from_address = pubkey_of(arg_matches, "from_address").unwrap();
+                let to_address = pubkey_of(arg_matches, "to_address").unwrap();
+                let amount = match arg_matches.value_of("amount").unwrap() {
+                    "ALL" => None,
+                    amount => Some(MaybeToken::SOL().amount(amount.parse::<f64>().unwrap())),
+                };
+                let lot_numbers = lot_numbers_of(arg_matches, "lot_numbers");
+                let lot_selection_method =
+                    value_t_or_exit!(arg_matches, "lot_selection", LotSelectionMethod);
+
+                let (authority_signer, authority_address) = if arg_matches.is_present("by") {
+                    signer_of(arg_matches, "by", &mut wallet_manager)?
+                } else {
+                    signer_of(arg_matches, "from_address", &mut wallet_manager).map_err(|err| {
+                        format!("Authority not found, consider using the `--by` argument): {err}")
+                    })?
+                };
+                let signature = value_t!(arg_matches, "transaction", Signature).ok();
+
+                process_account_withdraw_stake(
+                    &mut db,
+                    &rpc_clients,
+                    from_address,
+                    to_address,
+                    amount,
+                    lot_selection_method,
+                    lot_numbers,
+                    authority_address.expect("authority_address"),
+                    vec![authority_signer.expect("authority_signer")],
+                    priority_fee,
+                    signature,
+                    verbose,
                 )
                 .await?;
             }
@@ -6581,6 +16636,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     authority_address,
                     &vec![authority_signer],
                     into_keypair,
+                    verbose,
                 )
                 .await?;
             }
@@ -6593,14 +16649,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     value_t!(arg_matches, "max_epochs_to_process", u64).ok();
                 process_account_sync(
                     &mut db,
-                    &rpc_clients,
+                    rpc_clients.default(),
+                    Some(&rpc_clients),
                     address,
                     max_epochs_to_process,
                     reconcile_no_sync_account_balances,
                     force_rescan_balances,
                     &notifier,
+                    verbose,
+                )
+                .await?;
+            }
+            ("liquid-stake", Some(arg_matches)) => {
+                let (signer, address) = signer_of(arg_matches, "address", &mut wallet_manager)?;
+                let amount = match arg_matches.value_of("amount").unwrap() {
+                    "ALL" => Amount::All,
+                    "HALF" => Amount::Half,
+                    amount => {
+                        Amount::Exact(MaybeToken::SOL().amount(amount.parse::<f64>().unwrap()))
+                    }
+                };
+                let protocol = value_t_or_exit!(arg_matches, "protocol", LiquidStakeProtocol);
+                let lot_selection_method =
+                    value_t_or_exit!(arg_matches, "lot_selection", LotSelectionMethod);
+
+                process_account_liquid_stake(
+                    &mut db,
+                    &rpc_clients,
+                    address.expect("address"),
+                    amount,
+                    protocol,
+                    lot_selection_method,
+                    vec![signer.expect("signer")],
+                    priority_fee,
+                    verbose,
                 )
                 .await?;
+                process_sync_swaps(&mut db, rpc_client, &notifier, verbose).await?;
             }
             ("wrap", Some(arg_matches)) => {
                 let address = pubkey_of(arg_matches, "address").unwrap();
@@ -6641,6 +16726,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     authority_address,
                     vec![authority_signer],
                     priority_fee,
+                    verbose,
                 )
                 .await?;
             }
@@ -6675,6 +16761,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     authority_address,
                     vec![authority_signer],
                     priority_fee,
+                    verbose,
                 )
                 .await?;
             }
@@ -6709,6 +16796,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let for_no_less_than = value_t!(arg_matches, "for_no_less_than", f64).ok();
                 let max_coingecko_value_percentage_loss =
                     value_t_or_exit!(arg_matches, "max_coingecko_value_percentage_loss", f64);
+                let force_priority_fee = arg_matches.is_present("force_priority_fee");
 
                 process_jup_swap(
                     &mut db,
@@ -6725,10 +16813,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     for_no_less_than,
                     max_coingecko_value_percentage_loss,
                     priority_fee,
+                    force_priority_fee,
                     &notifier,
+                    verbose,
                 )
                 .await?;
-                process_sync_swaps(&mut db, rpc_client, &notifier).await?;
+                process_sync_swaps(&mut db, rpc_client, &notifier, verbose).await?;
+            }
+            ("history", Some(arg_matches)) => {
+                let year = value_t!(arg_matches, "year", i32).ok();
+                let csv = value_t!(arg_matches, "csv", PathBuf).ok();
+                process_jup_history(&db, year, csv.as_deref())?;
             }
             _ => unreachable!(),
         },
@@ -6771,6 +16866,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 authority_address,
                 vec![authority_signer],
                 &notifier,
+                verbose,
             )
             .await?;
         }
@@ -6783,7 +16879,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "{}",
                     tulip::get_current_lending_apr(&rpc_client, &token.into())
                         .await?
-                        .separated_string_with_fixed_place(2)
+                        .localized_string_with_fixed_place(2)
                 );
             }
             ("deposit", Some(arg_matches)) => {
@@ -6822,7 +16918,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     signature,
                 )
                 .await?;
-                process_sync_swaps(&mut db, &rpc_client, &notifier).await?;
+                process_sync_swaps(&mut db, &rpc_client, &notifier, verbose).await?;
             }
             ("withdraw", Some(arg_matches)) => {
                 let collateral_token = value_t_or_exit!(arg_matches, "collateral_token", Token);
@@ -6853,7 +16949,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     vec![signer],
                 )
                 .await?;
-                process_sync_swaps(&mut db, &rpc_client, &notifier).await?;
+                process_sync_swaps(&mut db, &rpc_client, &notifier, verbose).await?;
             }
             _ => unreachable!(),
         },
@@ -6866,12 +16962,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .ok()
                 .unwrap_or_default();
 
-            let exchange_client = || {
-                let exchange_credentials = db
-                    .get_exchange_credentials(exchange, &exchange_account)
-                    .ok_or_else(|| format!("No API key set for {exchange:?}"))?;
-                exchange_client_new(exchange, exchange_credentials)
-            };
+            let exchange_client = || exchange_client_of(&db, exchange, &exchange_account);
 
             match exchange_matches.subcommand() {
                 ("address", Some(arg_matches)) => {
@@ -6879,6 +16970,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let deposit_address = exchange_client()?.deposit_address(token).await?;
                     println!("{token} deposit address: {deposit_address}");
                 }
+                ("dust-convert", Some(arg_matches)) => {
+                    let coins = arg_matches
+                        .values_of("coin")
+                        .map(|coins| coins.map(|coin| coin.to_uppercase()).collect());
+                    let confirm = arg_matches.is_present("confirm");
+                    let exchange_client = exchange_client()?;
+
+                    process_exchange_dust_convert(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        coins,
+                        confirm,
+                        &notifier,
+                    )
+                    .await?;
+                }
                 ("pending-deposits", Some(arg_matches)) => {
                     let quiet = arg_matches.is_present("quiet");
 
@@ -6889,6 +16997,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 format!("{} deposits pending", pending_deposits.len()).into()
                             );
                         }
+                    } else if output_format == OutputFormat::Json {
+                        println_json(
+                            &pending_deposits
+                                .into_iter()
+                                .map(|pending_deposit| {
+                                    let token = pending_deposit.transfer.to_token;
+                                    PendingDepositOutput {
+                                        token,
+                                        amount: token.ui_amount(pending_deposit.amount),
+                                        signature: pending_deposit.transfer.signature.to_string(),
+                                    }
+                                })
+                                .collect::<Vec<_>>(),
+                        )?;
                     } else {
                         for pending_deposit in pending_deposits {
                             let token = pending_deposit.transfer.to_token;
@@ -6919,6 +17041,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             )
                             .into());
                         }
+                    } else if output_format == OutputFormat::Json {
+                        println_json(
+                            &pending_withdrawals
+                                .into_iter()
+                                .map(|pending_withdrawal| {
+                                    let token = pending_withdrawal.token;
+                                    PendingWithdrawalOutput {
+                                        token,
+                                        amount: token.ui_amount(pending_withdrawal.amount),
+                                        destination: pending_withdrawal.to_address,
+                                        tag: pending_withdrawal.tag,
+                                    }
+                                })
+                                .collect::<Vec<_>>(),
+                        )?;
                     } else {
                         for pending_withdrawals in pending_withdrawals {
                             let token = pending_withdrawals.token;
@@ -6937,7 +17074,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let total_only = arg_matches.is_present("total_only");
                     let integer = arg_matches.is_present("integer");
 
-                    let balances = exchange_client()?.balances().await?;
+                    let exchange_client = exchange_client()?;
+                    let balances = exchange_client.balances().await?;
+
+                    if output_format == OutputFormat::Json {
+                        return println_json(
+                            &["SOL"]
+                                .iter()
+                                .chain(exchange::USD_COINS)
+                                .filter_map(|coin| {
+                                    balances.get(*coin).map(|balance| ExchangeBalanceOutput {
+                                        coin: coin.to_string(),
+                                        total: balance.total,
+                                        available: balance.available,
+                                        earn: balance.earn,
+                                    })
+                                })
+                                .collect::<Vec<_>>(),
+                        );
+                    }
 
                     if !(available_only || total_only) {
                         println!("                   Total            Available")
@@ -6953,7 +17108,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             if integer {
                                 balance.available.floor().to_string()
                             } else {
-                                balance.available.separated_string_with_fixed_place(8)
+                                balance.available.localized_string_with_fixed_place(8)
                             }
                         );
 
@@ -6963,7 +17118,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             if integer {
                                 balance.total.floor().to_string()
                             } else {
-                                balance.total.separated_string_with_fixed_place(8)
+                                balance.total.localized_string_with_fixed_place(8)
                             }
                         );
 
@@ -6971,12 +17126,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             println!("{coin} {available_balance}");
                         } else if total_only {
                             println!("{coin} {total_balance}");
+                        } else if balance.earn > 0. {
+                            println!(
+                                "{coin} {total_balance:>20} {available_balance:>20}   (includes {}{} in earn)",
+                                symbol,
+                                balance.earn.localized_string_with_fixed_place(8)
+                            );
                         } else {
                             println!("{coin} {total_balance:>20} {available_balance:>20}");
                         }
                     };
 
-                    print_balance("SOL", "◎", &balance);
+                    print_balance("SOL", MaybeToken::SOL().symbol(), &balance);
                     for coin in exchange::USD_COINS {
                         if let Some(balance) = balances.get(*coin) {
                             if balance.total > 0. {
@@ -6984,6 +17145,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
                     }
+
+                    if verbose && !(available_only || total_only) {
+                        let tokens = std::iter::once(MaybeToken::SOL()).chain(
+                            exchange::USD_COINS
+                                .iter()
+                                .filter(|coin| {
+                                    balances
+                                        .get(**coin)
+                                        .is_some_and(|balance| balance.total > 0.)
+                                })
+                                .filter_map(|coin| {
+                                    Token::from_str(coin).ok().map(MaybeToken::from)
+                                }),
+                        );
+
+                        let mut withdrawal_allowances =
+                            WithdrawalAllowances::new(exchange_client.as_ref(), rpc_client);
+                        let mut printed_header = false;
+                        for token in tokens {
+                            let Some(limit) = withdrawal_allowances.limit(token).await? else {
+                                continue;
+                            };
+                            if !printed_header {
+                                println!();
+                                println!("Withdrawal limits (24 hour):");
+                                if let (Some(daily_limit_usd), Some(remaining_usd)) =
+                                    (limit.daily_limit_usd, limit.remaining_usd)
+                                {
+                                    println!(
+                                        "  {} of {} remaining across all assets",
+                                        currency(
+                                            remaining_usd.localized_string_with_fixed_place(2)
+                                        ),
+                                        currency(
+                                            daily_limit_usd.localized_string_with_fixed_place(2)
+                                        )
+                                    );
+                                }
+                                printed_header = true;
+                            }
+                            if let Some(remaining) = withdrawal_allowances.remaining(token).await? {
+                                println!("  {token}: {} remaining", token.format_amount(remaining));
+                            }
+                        }
+                    }
                 }
                 ("market", Some(arg_matches)) => {
                     let exchange_client = exchange_client()?;
@@ -7034,6 +17240,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                     let authority_address = authority_address.expect("authority_address");
                     let authority_signer = authority_signer.expect("authority_signer");
+                    let durable_nonce = durable_nonce_of(arg_matches, &mut wallet_manager)?;
 
                     let exchange_client = exchange_client()?;
                     let deposit_address = exchange_client.deposit_address(token).await?;
@@ -7050,17 +17257,97 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         &rpc_clients,
                         exchange,
                         exchange_client.as_ref(),
-                        token,
-                        deposit_address,
-                        amount,
+                        token,
+                        deposit_address,
+                        amount,
+                        from_address,
+                        if_source_balance_exceeds,
+                        if_exchange_balance_less_than,
+                        authority_address,
+                        vec![authority_signer],
+                        durable_nonce,
+                        lot_selection_method,
+                        lot_numbers,
+                        priority_fee,
+                        verbose,
+                    )
+                    .await?;
+                    process_sync_exchange(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        false,
+                        &notifier,
+                    )
+                    .await?;
+                }
+                ("deposit-all", Some(arg_matches)) => {
+                    let from_address =
+                        pubkey_of_signer(arg_matches, "from", &mut wallet_manager)?.expect("from");
+                    let tokens = values_t!(arg_matches, "token", Token).ok();
+
+                    let (authority_signer, authority_address) = if arg_matches.is_present("by") {
+                        signer_of(arg_matches, "by", &mut wallet_manager)?
+                    } else {
+                        signer_of(arg_matches, "from", &mut wallet_manager).map_err(|err| {
+                            format!(
+                                "Authority not found, consider using the `--by` argument): {err}"
+                            )
+                        })?
+                    };
+
+                    let authority_address = authority_address.expect("authority_address");
+                    let authority_signer = authority_signer.expect("authority_signer");
+                    let durable_nonce = durable_nonce_of(arg_matches, &mut wallet_manager)?;
+
+                    let exchange_client = exchange_client()?;
+                    let mut deposit_addresses = vec![];
+                    for account in db.get_accounts() {
+                        let Some(token) = account.token.token() else {
+                            continue;
+                        };
+                        if account.address != from_address
+                            || tokens
+                                .as_ref()
+                                .is_some_and(|tokens| !tokens.contains(&token))
+                        {
+                            continue;
+                        }
+                        let deposit_address =
+                            match exchange_client.deposit_address(token.into()).await {
+                                Ok(deposit_address) => deposit_address,
+                                Err(err) => {
+                                    println!(
+                                        "Skipping {}, no {exchange:?} deposit address: {err}",
+                                        token.name()
+                                    );
+                                    continue;
+                                }
+                            };
+                        add_exchange_deposit_address_to_db(
+                            &mut db,
+                            exchange,
+                            &exchange_account,
+                            token.into(),
+                            deposit_address,
+                            rpc_client,
+                        )?;
+                        deposit_addresses.push((token, deposit_address));
+                    }
+
+                    process_exchange_deposit_all(
+                        &mut db,
+                        &rpc_clients,
+                        exchange,
+                        exchange_client.as_ref(),
                         from_address,
-                        if_source_balance_exceeds,
-                        if_exchange_balance_less_than,
+                        deposit_addresses,
                         authority_address,
                         vec![authority_signer],
-                        lot_selection_method,
-                        lot_numbers,
+                        durable_nonce,
                         priority_fee,
+                        verbose,
                     )
                     .await?;
                     process_sync_exchange(
@@ -7068,10 +17355,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         exchange,
                         exchange_client.as_ref(),
                         rpc_client,
+                        false,
                         &notifier,
                     )
                     .await?;
                 }
+                ("withdraw-schedule", Some(arg_matches)) => {
+                    let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
+                    let total = token.amount(value_t_or_exit!(arg_matches, "total", f64));
+                    let to_address =
+                        pubkey_of_signer(arg_matches, "to", &mut wallet_manager)?.expect("to");
+                    let chunks = value_t_or_exit!(arg_matches, "chunks", u32);
+                    let interval_hours = value_t_or_exit!(arg_matches, "interval", u32);
+                    let lot_selection_method =
+                        value_t_or_exit!(arg_matches, "lot_selection", LotSelectionMethod);
+
+                    if chunks == 0 {
+                        return Err("--chunks must be at least 1".into());
+                    }
+                    if db.get_account(to_address, token).is_none() {
+                        return Err(
+                            format!("Unknown recipient account: {to_address} ({token})").into()
+                        );
+                    }
+                    let chunk_amount = total.div_ceil(u64::from(chunks));
+
+                    db.set_withdrawal_schedule(WithdrawalSchedule {
+                        exchange,
+                        exchange_account: exchange_account.clone(),
+                        token,
+                        to_address,
+                        remaining_amount: total,
+                        chunk_amount,
+                        interval_hours,
+                        next_withdrawal: Utc::now(),
+                        lot_selection_method,
+                    })?;
+
+                    if total == 0 {
+                        println!("Cancelled scheduled {token} withdrawals to {to_address}");
+                    } else {
+                        println!(
+                            "Scheduled {chunks} withdrawals of {} to {to_address} every \
+                             {interval_hours} hours, starting at the next sync",
+                            token.format_amount(chunk_amount),
+                        );
+                    }
+                }
                 ("withdraw", Some(arg_matches)) => {
                     let token = MaybeToken::from(value_t!(arg_matches, "token", Token).ok());
                     let amount = match arg_matches.value_of("amount").unwrap() {
@@ -7084,6 +17414,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let lot_selection_method =
                         value_t_or_exit!(arg_matches, "lot_selection", LotSelectionMethod);
 
+                    let split = arg_matches.is_present("split");
+
                     let withdrawal_password = None; // TODO: Support reading password from stdin
                     let withdrawal_code = value_t!(arg_matches, "code", String).ok();
 
@@ -7098,25 +17430,72 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         rpc_client,
                     )?;
 
-                    process_exchange_withdraw(
-                        &mut db,
-                        exchange,
-                        exchange_client.as_ref(),
-                        token,
-                        deposit_address,
-                        amount,
-                        to_address,
-                        lot_selection_method,
-                        lot_numbers,
-                        withdrawal_password,
-                        withdrawal_code,
-                    )
-                    .await?;
+                    let requested_amount = amount.unwrap_or_else(|| {
+                        db.get_account(deposit_address, token)
+                            .map(|deposit_account| deposit_account.last_update_balance)
+                            .unwrap_or_default()
+                    });
+                    let mut withdrawal_allowances =
+                        WithdrawalAllowances::new(exchange_client.as_ref(), rpc_client);
+                    let mut amount = amount;
+                    if let Some(remaining) = withdrawal_allowances.remaining(token).await? {
+                        if requested_amount > remaining {
+                            if !split {
+                                return Err(format!(
+                                    "Withdrawal of {} exceeds the remaining {exchange:?} \
+                                     withdrawal limit of {}, use --split to withdraw the \
+                                     remainder once the limit resets",
+                                    token.format_amount(requested_amount),
+                                    token.format_amount(remaining),
+                                )
+                                .into());
+                            }
+
+                            // `sync` withdraws as much of the remainder as the limit allows each day
+                            let remainder = requested_amount - remaining;
+                            db.set_withdrawal_schedule(WithdrawalSchedule {
+                                exchange,
+                                exchange_account: exchange_account.clone(),
+                                token,
+                                to_address,
+                                remaining_amount: remainder,
+                                chunk_amount: remainder,
+                                interval_hours: 24,
+                                next_withdrawal: Utc::now()
+                                    + chrono::Duration::try_hours(24).unwrap(),
+                                lot_selection_method,
+                            })?;
+                            println!(
+                                "Scheduled the remaining {} for withdrawal once the {exchange:?} \
+                                 withdrawal limit resets",
+                                token.format_amount(remainder)
+                            );
+                            amount = Some(remaining);
+                        }
+                    }
+
+                    if amount != Some(0) {
+                        process_exchange_withdraw(
+                            &mut db,
+                            exchange,
+                            exchange_client.as_ref(),
+                            token,
+                            deposit_address,
+                            amount,
+                            to_address,
+                            lot_selection_method,
+                            lot_numbers,
+                            withdrawal_password,
+                            withdrawal_code,
+                        )
+                        .await?;
+                    }
                     process_sync_exchange(
                         &mut db,
                         exchange,
                         exchange_client.as_ref(),
                         rpc_client,
+                        false,
                         &notifier,
                     )
                     .await?;
@@ -7155,6 +17534,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         exchange,
                         exchange_client.as_ref(),
                         rpc_client,
+                        false,
+                        &notifier,
+                    )
+                    .await?;
+                }
+                ("adopt-order", Some(arg_matches)) => {
+                    let exchange_client = exchange_client()?;
+                    let token = MaybeToken::SOL();
+                    let pair = value_t!(arg_matches, "pair", String)
+                        .unwrap_or_else(|_| exchange_client.preferred_solusd_pair().into());
+                    let order_id = value_t_or_exit!(arg_matches, "order_id", String);
+
+                    process_exchange_adopt_order(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        pair,
+                        token,
+                        order_id,
+                    )
+                    .await?;
+                    process_sync_exchange(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        false,
                         &notifier,
                     )
                     .await?;
@@ -7170,13 +17576,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     };
 
                     let if_balance_exceeds = value_t!(arg_matches, "if_balance_exceeds", f64).ok();
+                    let group = value_t!(arg_matches, "group", String).ok();
 
                     let price = if let Ok(price) = value_t!(arg_matches, "at", f64) {
-                        LimitOrderPrice::At(price)
+                        Some(LimitOrderPrice::At(price))
                     } else if let Ok(bid_minus) = value_t!(arg_matches, "bid_minus", f64) {
-                        LimitOrderPrice::AmountUnderBid(bid_minus)
+                        Some(LimitOrderPrice::AmountUnderBid(bid_minus))
+                    } else if arg_matches.is_present("market") {
+                        None
                     } else {
-                        return Err("--at or --bid-minus argument required".into());
+                        return Err("--at, --bid-minus or --market argument required".into());
                     };
 
                     process_exchange_buy(
@@ -7188,6 +17597,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         amount,
                         price,
                         if_balance_exceeds,
+                        group,
+                        order_options_of(arg_matches),
+                        max_book_fraction_of(arg_matches),
                         &notifier,
                     )
                     .await?;
@@ -7196,6 +17608,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         exchange,
                         exchange_client.as_ref(),
                         rpc_client,
+                        false,
                         &notifier,
                     )
                     .await?;
@@ -7215,13 +17628,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let lot_numbers = lot_numbers_of(arg_matches, "lot_numbers");
                     let lot_selection_method =
                         value_t_or_exit!(arg_matches, "lot_selection", LotSelectionMethod);
+                    let group = value_t!(arg_matches, "group", String).ok();
 
                     let price = if let Ok(price) = value_t!(arg_matches, "at", f64) {
-                        LimitOrderPrice::At(price)
+                        Some(LimitOrderPrice::At(price))
                     } else if let Ok(ask_plus) = value_t!(arg_matches, "ask_plus", f64) {
-                        LimitOrderPrice::AmountOverAsk(ask_plus)
+                        Some(LimitOrderPrice::AmountOverAsk(ask_plus))
+                    } else if arg_matches.is_present("market") {
+                        None
                     } else {
-                        return Err("--at or --ask-plus argument required".into());
+                        return Err("--at, --ask-plus or --market argument required".into());
                     };
                     process_exchange_sell(
                         &mut db,
@@ -7237,6 +17653,49 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         price_floor,
                         lot_selection_method,
                         lot_numbers,
+                        group,
+                        order_options_of(arg_matches),
+                        max_book_fraction_of(arg_matches),
+                        &notifier,
+                    )
+                    .await?;
+                    process_sync_exchange(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        false,
+                        &notifier,
+                    )
+                    .await?;
+                }
+                ("sell-ladder", Some(arg_matches)) => {
+                    let exchange_client = exchange_client()?;
+                    let token = MaybeToken::SOL();
+                    let pair = value_t!(arg_matches, "pair", String)
+                        .unwrap_or_else(|_| exchange_client.preferred_solusd_pair().into());
+                    let amount = value_t_or_exit!(arg_matches, "amount", f64);
+                    let from_price = value_t_or_exit!(arg_matches, "from", f64);
+                    let to_price = value_t_or_exit!(arg_matches, "to", f64);
+                    let steps = value_t_or_exit!(arg_matches, "steps", u64);
+                    let lot_selection_method =
+                        value_t_or_exit!(arg_matches, "lot_selection", LotSelectionMethod);
+                    let group = value_t!(arg_matches, "group", String).unwrap_or_else(|_| {
+                        format!("ladder-{}", Local::now().format("%Y%m%d%H%M%S"))
+                    });
+
+                    process_exchange_sell_ladder(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        token,
+                        pair,
+                        amount,
+                        from_price,
+                        to_price,
+                        steps,
+                        lot_selection_method,
+                        group,
                         &notifier,
                     )
                     .await?;
@@ -7245,6 +17704,86 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         exchange,
                         exchange_client.as_ref(),
                         rpc_client,
+                        false,
+                        &notifier,
+                    )
+                    .await?;
+                }
+                ("cancel-group", Some(arg_matches)) => {
+                    let group = value_t_or_exit!(arg_matches, "group", String);
+
+                    let exchange_client = exchange_client()?;
+                    process_exchange_orders(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        Some(group),
+                        true,
+                        None,
+                        &notifier,
+                    )
+                    .await?;
+                }
+                ("statement", Some(arg_matches)) => {
+                    let month = value_t_or_exit!(arg_matches, "month", String);
+                    let month = NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d")?;
+                    let output = value_t!(arg_matches, "output", PathBuf).unwrap_or_else(|_| {
+                        PathBuf::from(format!(
+                            "{}-{}.csv",
+                            exchange.to_string().to_lowercase(),
+                            month.format("%Y-%m")
+                        ))
+                    });
+
+                    process_exchange_statement(&db, exchange, month, &output)?;
+                }
+                ("watch", Some(arg_matches)) => {
+                    let poll_interval =
+                        Duration::from_secs(value_t_or_exit!(arg_matches, "interval", u64));
+
+                    let exchange_client = exchange_client()?;
+                    process_exchange_watch(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        poll_interval,
+                        &notifier,
+                        &healthcheck,
+                    )
+                    .await?;
+                }
+                ("orders", Some(arg_matches)) => {
+                    let group = value_t!(arg_matches, "group", String).ok();
+                    let cancel = arg_matches.is_present("cancel");
+                    let reprice_by = value_t!(arg_matches, "reprice_by", f64).ok();
+
+                    let exchange_client = exchange_client()?;
+                    process_exchange_orders(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        group,
+                        cancel,
+                        reprice_by,
+                        &notifier,
+                    )
+                    .await?;
+                }
+                ("status-group", Some(arg_matches)) => {
+                    let group = value_t_or_exit!(arg_matches, "group", String);
+
+                    let exchange_client = exchange_client()?;
+                    process_exchange_orders(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        Some(group),
+                        false,
+                        None,
                         &notifier,
                     )
                     .await?;
@@ -7280,32 +17819,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         if additional_amount.abs() > f64::EPSILON {
                             let msg = format!(
                                 "Lending offer: {} {} (change: {}) at {:.1}%",
-                                amount.separated_string_with_fixed_place(2),
+                                amount.localized_string_with_fixed_place(2),
                                 coin,
-                                additional_amount.separated_string_with_fixed_place(2),
+                                additional_amount.localized_string_with_fixed_place(2),
                                 lending_info.estimate_rate,
                             );
                             exchange_client.submit_lending_offer(&coin, amount).await?;
                             println!("{msg}");
-                            notifier.send(&format!("{exchange:?}: {msg}")).await;
+                            notifier
+                                .send(
+                                    NotificationKind::Balance,
+                                    Severity::Info,
+                                    &format!("{exchange:?}: {msg}"),
+                                )
+                                .await;
                         } else {
                             println!(
                                 "Lending offer unchanged: {}",
-                                lending_info.offered.separated_string_with_fixed_place(2)
+                                lending_info.offered.localized_string_with_fixed_place(2)
                             );
                         }
                     } else {
                         println!(
                             "Available:     {}",
-                            lending_info.lendable.separated_string_with_fixed_place(2),
+                            lending_info.lendable.localized_string_with_fixed_place(2),
                         );
                         println!(
                             "Current offer: {}",
-                            lending_info.offered.separated_string_with_fixed_place(2),
+                            lending_info.offered.localized_string_with_fixed_place(2),
                         );
                         println!(
                             "Locked:        {}",
-                            lending_info.locked.separated_string_with_fixed_place(2),
+                            lending_info.locked.localized_string_with_fixed_place(2),
                         );
                         println!(
                             "Current rate:  {:.1}% (estimated)",
@@ -7337,17 +17882,114 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                     .await?;
 
-                    for (coin, amount) in lending_history.iter() {
-                        println!("{}: {}", coin, amount.separated_string_with_fixed_place(2));
+                    let mut totals = BTreeMap::<&str, f64>::new();
+                    for interest in &lending_history {
+                        *totals.entry(&interest.coin).or_default() += interest.amount;
+                    }
+                    for (coin, amount) in totals {
+                        println!("{}: {}", coin, amount.localized_string_with_fixed_place(2));
                     }
+
+                    // Interest not yet synced becomes income, like that found by `sync`
+                    record_exchange_rewards(
+                        &mut db,
+                        exchange,
+                        exchange_client.as_ref(),
+                        rpc_client,
+                        lending_history
+                            .iter()
+                            .filter_map(LendingInterest::reward_info)
+                            .collect(),
+                        &notifier,
+                    )
+                    .await?;
                 }
-                ("sync", Some(_arg_matches)) => {
+                ("margin", Some(margin_matches)) => match margin_matches.subcommand() {
+                    ("show", Some(_arg_matches)) => {
+                        let margin_tracking = db
+                            .get_margin_tracking(exchange, &exchange_account)
+                            .ok_or_else(|| {
+                            format!("{exchange:?} margin account is not tracked")
+                        })?;
+
+                        match margin_tracking.last_update {
+                            None => println!("Not synced yet"),
+                            Some(last_update) => {
+                                println!(
+                                    "Margin level: {} | updated {}",
+                                    margin_tracking
+                                        .margin_level
+                                        .map(|margin_level| format!("{margin_level:.2}"))
+                                        .unwrap_or_else(|| "-".into()),
+                                    HumanTime::from(last_update)
+                                );
+                                for position in &margin_tracking.positions {
+                                    println!(
+                                        "  {:>7}: {} borrowed, {} interest | {}",
+                                        position.coin,
+                                        position.borrowed,
+                                        position.interest,
+                                        position
+                                            .value
+                                            .map(format_currency)
+                                            .unwrap_or_else(|| "?".into()),
+                                    );
+                                }
+                                println!(
+                                    "Borrowed: {}",
+                                    format_currency(margin_tracking.liabilities())
+                                );
+                            }
+                        }
+                        println!(
+                            "Interest paid: {}",
+                            format_currency(margin_tracking.interest_expense)
+                        );
+                    }
+                    ("track", Some(arg_matches)) => {
+                        let warn_margin_level = value_t!(arg_matches, "warn_below", f64).ok();
+                        let alert_margin_level = value_t!(arg_matches, "alert_below", f64).ok();
+
+                        let margin_tracking =
+                            match db.get_margin_tracking(exchange, &exchange_account) {
+                                Some(margin_tracking) => MarginTracking {
+                                    warn_margin_level,
+                                    alert_margin_level,
+                                    ..margin_tracking
+                                },
+                                None => {
+                                    // Fails early for exchanges without margin support
+                                    exchange_client()?.margin_account().await?;
+                                    MarginTracking {
+                                        exchange,
+                                        exchange_account: exchange_account.clone(),
+                                        warn_margin_level,
+                                        alert_margin_level,
+                                        margin_level: None,
+                                        positions: vec![],
+                                        interest_expense: Decimal::ZERO,
+                                        last_update: None,
+                                    }
+                                }
+                            };
+                        db.set_margin_tracking(margin_tracking)?;
+                        println!("Tracking margin borrows, starting at the next sync");
+                    }
+                    ("untrack", Some(_arg_matches)) => {
+                        db.remove_margin_tracking(exchange, &exchange_account)?;
+                        println!("No longer tracking margin borrows");
+                    }
+                    _ => unreachable!(),
+                },
+                ("sync", Some(arg_matches)) => {
+                    let adopt_unknown_orders = arg_matches.is_present("adopt_unknown_orders");
                     let exchange_client = exchange_client()?;
                     process_sync_exchange(
                         &mut db,
                         exchange,
                         exchange_client.as_ref(),
                         rpc_client,
+                        adopt_unknown_orders,
                         &notifier,
                     )
                     .await?;
@@ -7359,11 +18001,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 Some(ExchangeCredentials {
                                     api_key,
                                     subaccount,
+                                    passphrase,
                                     ..
                                 }) => {
                                     println!("Account name: {exchange_account}");
                                     println!("API Key: {api_key}");
                                     println!("Secret: ********");
+                                    if passphrase.is_some() {
+                                        println!("Passphrase: ********");
+                                    }
                                     if let Some(subaccount) = subaccount {
                                         println!("Subaccount: {subaccount}");
                                     }
@@ -7377,6 +18023,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             let api_key = value_t_or_exit!(arg_matches, "api_key", String);
                             let secret = value_t_or_exit!(arg_matches, "secret", String);
                             let subaccount = value_t!(arg_matches, "subaccount", String).ok();
+                            let passphrase = value_t!(arg_matches, "passphrase", String).ok();
                             db.set_exchange_credentials(
                                 exchange,
                                 &exchange_account,
@@ -7384,6 +18031,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     api_key,
                                     secret,
                                     subaccount,
+                                    passphrase,
                                 },
                             )?;
                             println!(
@@ -7404,6 +18052,287 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         _ => unreachable!(),
     };
 
+    record_fetched_historical_prices(&mut db).await?;
     metrics::send(db.get_metrics_config()).await;
     Ok(())
 }
+
+// Sync scenarios against the exchange and ledger stand-ins of `sys::testkit`, run with
+// `cargo test --features testkit`
+#[cfg(all(test, feature = "testkit"))]
+mod tests {
+    use {
+        super::*,
+        solana_transaction_status::{TransactionConfirmationStatus, TransactionStatus},
+        sys::testkit::{fixtures, LocalLedger, LocalLedgerState, MockExchangeClient},
+    };
+
+    const EXCHANGE: Exchange = Exchange::Kraken;
+    const BLOCK_TIME: i64 = 1_700_000_000;
+
+    fn new_test_db(name: &str) -> Db {
+        let db_path = std::env::temp_dir().join(format!("sys-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&db_path);
+        db::new(&db_path, None).unwrap()
+    }
+
+    fn new_test_ledger() -> LocalLedger {
+        LocalLedger::new(LocalLedgerState {
+            block_time: BLOCK_TIME,
+            ..LocalLedgerState::default()
+        })
+    }
+
+    fn add_sol_account(db: &mut Db, address: Pubkey, ui_amount: f64) {
+        let amount = MaybeToken::SOL().amount(ui_amount);
+        let lots = if amount > 0 {
+            vec![Lot {
+                lot_number: db.next_lot_number(),
+                acquisition: LotAcquistion::new(
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                    Decimal::from(10),
+                    LotAcquistionKind::NotAvailable,
+                ),
+                amount,
+            }]
+        } else {
+            vec![]
+        };
+        db.add_account(TrackedAccount {
+            address,
+            token: MaybeToken::SOL(),
+            description: String::default(),
+            last_update_epoch: 0,
+            last_update_balance: amount,
+            lots,
+            no_sync: None,
+            last_activity: None,
+        })
+        .unwrap();
+    }
+
+    fn sol_balance(db: &Db, address: Pubkey) -> f64 {
+        let token = MaybeToken::SOL();
+        token.ui_amount(db.get_account(address, token).unwrap().last_update_balance)
+    }
+
+    async fn sync_exchange(
+        db: &mut Db,
+        exchange_client: &MockExchangeClient,
+        ledger: &LocalLedger,
+    ) {
+        let notifier = Notifier::disabled();
+        process_sync_exchange(db, EXCHANGE, exchange_client, ledger, false, &notifier)
+            .await
+            .unwrap();
+    }
+
+    async fn sync_accounts(db: &mut Db, ledger: &LocalLedger) {
+        let notifier = Notifier::disabled();
+        process_account_sync(db, ledger, None, None, None, false, false, &notifier, false)
+            .await
+            .unwrap();
+    }
+
+    // Lands `signature` at the current slot of `ledger`
+    fn confirm_signature(ledger: &LocalLedger, signature: Signature) {
+        let mut state = ledger.state();
+        let slot = state.epoch_info.absolute_slot;
+        state.signature_statuses.insert(
+            signature,
+            TransactionStatus {
+                slot,
+                confirmations: None,
+                status: Ok(()),
+                err: None,
+                confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_exchange_deposit_fill_withdrawal() {
+        let mut db = new_test_db("deposit-fill-withdrawal");
+        let notifier = Notifier::disabled();
+        let ledger = new_test_ledger();
+        let sol = MaybeToken::SOL();
+        let wallet_address = Pubkey::new_unique();
+        let deposit_address = Pubkey::new_unique();
+        add_sol_account(&mut db, wallet_address, 10.);
+        add_sol_account(&mut db, deposit_address, 0.);
+        let exchange_client = MockExchangeClient::new(fixtures::sol_exchange(deposit_address, 20.));
+
+        // A landed deposit stays pending until the exchange credits it
+        let signature = Signature::new_unique();
+        db.record_deposit(
+            signature,
+            100,
+            wallet_address,
+            sol.amount(4.),
+            EXCHANGE,
+            deposit_address,
+            None,
+            sol,
+            LotSelectionMethod::default(),
+            None,
+        )
+        .unwrap();
+        confirm_signature(&ledger, signature);
+        sync_exchange(&mut db, &exchange_client, &ledger).await;
+        assert_eq!(db.pending_deposits(Some(EXCHANGE)).len(), 1);
+
+        exchange_client
+            .state()
+            .add_deposit(&signature.to_string(), sol, 4.);
+        sync_exchange(&mut db, &exchange_client, &ledger).await;
+        assert!(db.pending_deposits(Some(EXCHANGE)).is_empty());
+        assert_eq!(sol_balance(&db, deposit_address), 4.);
+        assert_eq!(sol_balance(&db, wallet_address), 6.);
+
+        // Sell half of the deposit
+        process_exchange_sell(
+            &mut db,
+            EXCHANGE,
+            &exchange_client,
+            sol,
+            "SOLUSD".into(),
+            2.,
+            Some(LimitOrderPrice::At(25.)),
+            None,
+            None,
+            false,
+            None,
+            LotSelectionMethod::default(),
+            None,
+            None,
+            OrderOptions::default(),
+            None,
+            &notifier,
+        )
+        .await
+        .unwrap();
+        let order_id = db.open_orders(Some(EXCHANGE), None)[0].order_id.clone();
+        sync_exchange(&mut db, &exchange_client, &ledger).await;
+        assert_eq!(db.open_orders(Some(EXCHANGE), None).len(), 1);
+
+        exchange_client.state().fill_order(&order_id, None);
+        sync_exchange(&mut db, &exchange_client, &ledger).await;
+        assert!(db.open_orders(Some(EXCHANGE), None).is_empty());
+        let disposed_lots = db.disposed_lots();
+        assert_eq!(disposed_lots.len(), 1);
+        assert_eq!(disposed_lots[0].lot.amount, sol.amount(2.));
+        assert_eq!(disposed_lots[0].price(), Decimal::from(25));
+        assert_eq!(sol_balance(&db, deposit_address), 2.);
+
+        // Withdraw the rest back to the wallet
+        process_exchange_withdraw(
+            &mut db,
+            EXCHANGE,
+            &exchange_client,
+            sol,
+            deposit_address,
+            None,
+            wallet_address,
+            LotSelectionMethod::default(),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let tag = db.pending_withdrawals(Some(EXCHANGE))[0].tag.clone();
+        sync_exchange(&mut db, &exchange_client, &ledger).await;
+        assert_eq!(db.pending_withdrawals(Some(EXCHANGE)).len(), 1);
+
+        exchange_client
+            .state()
+            .complete_withdrawal(&tag, Some("withdrawal-tx"));
+        sync_exchange(&mut db, &exchange_client, &ledger).await;
+        assert!(db.pending_withdrawals(Some(EXCHANGE)).is_empty());
+        assert_eq!(sol_balance(&db, deposit_address), 0.);
+        assert_eq!(sol_balance(&db, wallet_address), 8.);
+    }
+
+    #[tokio::test]
+    async fn sync_exchange_cancelled_withdrawal() {
+        let mut db = new_test_db("cancelled-withdrawal");
+        let ledger = new_test_ledger();
+        let sol = MaybeToken::SOL();
+        let wallet_address = Pubkey::new_unique();
+        let deposit_address = Pubkey::new_unique();
+        add_sol_account(&mut db, wallet_address, 0.);
+        add_sol_account(&mut db, deposit_address, 3.);
+        let exchange_client = MockExchangeClient::new(fixtures::sol_exchange(deposit_address, 20.));
+
+        process_exchange_withdraw(
+            &mut db,
+            EXCHANGE,
+            &exchange_client,
+            sol,
+            deposit_address,
+            Some(sol.amount(1.)),
+            wallet_address,
+            LotSelectionMethod::default(),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(sol_balance(&db, deposit_address), 2.);
+
+        let tag = db.pending_withdrawals(Some(EXCHANGE))[0].tag.clone();
+        exchange_client.state().complete_withdrawal(&tag, None);
+        sync_exchange(&mut db, &exchange_client, &ledger).await;
+        assert!(db.pending_withdrawals(Some(EXCHANGE)).is_empty());
+        assert_eq!(sol_balance(&db, deposit_address), 3.);
+        assert_eq!(sol_balance(&db, wallet_address), 0.);
+    }
+
+    #[tokio::test]
+    async fn sync_account_pending_transfers() {
+        let mut db = new_test_db("pending-transfers");
+        let ledger = new_test_ledger();
+        let sol = MaybeToken::SOL();
+        let from_address = Pubkey::new_unique();
+        let to_address = Pubkey::new_unique();
+        add_sol_account(&mut db, from_address, 10.);
+        add_sol_account(&mut db, to_address, 0.);
+
+        let mut record_transfer = |ui_amount| {
+            let signature = Signature::new_unique();
+            db.record_transfer(
+                signature,
+                100,
+                Some(sol.amount(ui_amount)),
+                from_address,
+                sol,
+                to_address,
+                sol,
+                LotSelectionMethod::default(),
+                None,
+            )
+            .unwrap();
+            signature
+        };
+        let landed_signature = record_transfer(2.);
+        let stuck_signature = record_transfer(1.);
+        confirm_signature(&ledger, landed_signature);
+
+        // The transfer that has not landed stays pending while its blockhash is valid
+        sync_accounts(&mut db, &ledger).await;
+        let pending_signatures = db
+            .pending_transfers()
+            .into_iter()
+            .map(|pending_transfer| pending_transfer.signature)
+            .collect::<Vec<_>>();
+        assert_eq!(pending_signatures, vec![stuck_signature]);
+        assert_eq!(sol_balance(&db, from_address), 7.);
+        assert_eq!(sol_balance(&db, to_address), 2.);
+
+        confirm_signature(&ledger, stuck_signature);
+        sync_accounts(&mut db, &ledger).await;
+        assert!(db.pending_transfers().is_empty());
+        assert_eq!(sol_balance(&db, to_address), 3.);
+    }
+}
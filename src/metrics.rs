@@ -1,14 +1,143 @@
 pub use influxdb_client::{Client, Point};
 use {
+    crate::http_client::{self, ServedResponse, TracedSend},
     chrono::Utc,
     influxdb_client::{timestamp, Precision, Timestamp, TimestampOptions},
     serde::{Deserialize, Serialize},
-    std::{env, sync::Arc},
+    std::{
+        collections::BTreeMap,
+        env, io,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    },
     tokio::sync::RwLock,
 };
 
 lazy_static::lazy_static! {
     static ref POINTS: Arc<RwLock<Vec<Point>>> = Arc::new(RwLock::new(vec![]));
+    static ref PROMETHEUS: Mutex<BTreeMap<&'static str, PrometheusFamily>> =
+        Mutex::new(BTreeMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrometheusKind {
+    Gauge,
+    Counter,
+}
+
+// The series of one Prometheus metric, by their rendered labels
+struct PrometheusFamily {
+    kind: PrometheusKind,
+    help: &'static str,
+    series: BTreeMap<String, f64>,
+}
+
+fn prometheus_record(
+    kind: PrometheusKind,
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&str, &str)],
+    value: f64,
+) {
+    let labels = labels
+        .iter()
+        .map(|(label, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{label}=\"{value}\"")
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut prometheus = PROMETHEUS.lock().unwrap();
+    let family = prometheus.entry(name).or_insert_with(|| PrometheusFamily {
+        kind,
+        help,
+        series: BTreeMap::new(),
+    });
+    let series = family.series.entry(labels).or_default();
+    match kind {
+        PrometheusKind::Gauge => *series = value,
+        PrometheusKind::Counter => *series += value,
+    }
+}
+
+// Records the current value of a Prometheus gauge
+pub fn prometheus_gauge(
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&str, &str)],
+    value: f64,
+) {
+    prometheus_record(PrometheusKind::Gauge, name, help, labels, value);
+}
+
+// Adds to a Prometheus counter, which totals over the life of the process
+pub fn prometheus_counter(
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&str, &str)],
+    increment: f64,
+) {
+    prometheus_record(PrometheusKind::Counter, name, help, labels, increment);
+}
+
+// Everything recorded so far, in the Prometheus text exposition format
+pub fn prometheus_text() -> String {
+    let mut text = String::new();
+    for (name, family) in PROMETHEUS.lock().unwrap().iter() {
+        text += &format!("# HELP {name} {}\n", family.help);
+        text += &format!(
+            "# TYPE {name} {}\n",
+            match family.kind {
+                PrometheusKind::Gauge => "gauge",
+                PrometheusKind::Counter => "counter",
+            }
+        );
+        for (labels, value) in &family.series {
+            if labels.is_empty() {
+                text += &format!("{name} {value}\n");
+            } else {
+                text += &format!("{name}{{{labels}}} {value}\n");
+            }
+        }
+    }
+    text
+}
+
+// Serves `prometheus_text()` for Prometheus to scrape, from a background thread
+pub fn serve_prometheus(listen: SocketAddr) -> io::Result<()> {
+    http_client::serve(listen, || ServedResponse {
+        status: "200 OK",
+        content_type: "text/plain; version=0.0.4",
+        body: prometheus_text(),
+    })
+}
+
+// Replaces the metrics of the `sys` job on the Prometheus Pushgateway at PROMETHEUS_PUSHGATEWAY,
+// for runs too short-lived to be scraped
+async fn push_prometheus() {
+    let Ok(pushgateway) = env::var("PROMETHEUS_PUSHGATEWAY") else {
+        return;
+    };
+    // Pushing nothing would wipe the metrics of the last run that recorded some
+    let text = prometheus_text();
+    if text.is_empty() {
+        return;
+    }
+    let url = format!("{}/metrics/job/sys", pushgateway.trim_end_matches('/'));
+    let result = http_client::client()
+        .put(&url)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(text)
+        .traced_send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        eprintln!("Failed to push metrics to the Prometheus Pushgateway: {err:?}");
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,6 +162,8 @@ pub async fn push(point: Point) {
 }
 
 pub async fn send(config: Option<MetricsConfig>) {
+    push_prometheus().await;
+
     if let Some(config) = config {
         let client = Client::new(config.url, config.token)
             .with_bucket(config.bucket)
@@ -42,21 +173,25 @@ pub async fn send(config: Option<MetricsConfig>) {
         // Write all metrics with the same timestamp to ensure multiple sys-lend APY and balance
         // values line up
         let timestamp = timestamp!(Utc::now().timestamp_millis());
+        let points = std::mem::take(&mut *POINTS.write().await);
         client
-            .insert_points(&*POINTS.write().await, timestamp)
+            .insert_points(&points, timestamp)
             .await
             .unwrap_or_else(|err| eprintln!("Failed to send metrics: {err:?}"));
     }
 }
 
+// Data points for InfluxDB. Each is also recorded in the Prometheus registry as it is built
 pub mod dp {
     use {
+        super::{prometheus_counter, prometheus_gauge},
         crate::{
             exchange::{Exchange, OrderSide},
             token::MaybeToken,
         },
         influxdb_client::{Point, Value},
-        solana_sdk::pubkey::Pubkey,
+        solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey},
+        std::time::Duration,
     };
 
     pub fn pubkey_to_value(p: &Pubkey) -> Value {
@@ -64,6 +199,15 @@ pub mod dp {
     }
 
     pub fn exchange_deposit(exchange: Exchange, maybe_token: MaybeToken, ui_amount: f64) -> Point {
+        prometheus_counter(
+            "sys_exchange_deposit_amount_total",
+            "Amount deposited into exchanges",
+            &[
+                ("exchange", &exchange.to_string()),
+                ("token", maybe_token.name()),
+            ],
+            ui_amount,
+        );
         Point::new("exchange_deposit")
             .tag("exchange", exchange.to_string().as_str())
             .tag("token", maybe_token.name())
@@ -76,6 +220,15 @@ pub mod dp {
         address: &Pubkey,
         ui_amount: f64,
     ) -> Point {
+        prometheus_counter(
+            "sys_exchange_withdrawal_amount_total",
+            "Amount withdrawn from exchanges",
+            &[
+                ("exchange", &exchange.to_string()),
+                ("token", maybe_token.name()),
+            ],
+            ui_amount,
+        );
         Point::new("exchange_withdrawal")
             .tag("exchange", exchange.to_string().as_str())
             .tag("token", maybe_token.name())
@@ -91,6 +244,17 @@ pub mod dp {
         amount: f64,
         price: f64,
     ) -> Point {
+        prometheus_counter(
+            "sys_exchange_fill_amount_total",
+            "Amount filled by exchange orders",
+            &[
+                ("exchange", &exchange.to_string()),
+                ("pair", pair),
+                ("side", &side.to_string()),
+                ("token", maybe_token.name()),
+            ],
+            amount,
+        );
         Point::new("exchange_fill")
             .tag("exchange", exchange.to_string().as_str())
             .tag("pair", pair)
@@ -99,4 +263,102 @@ pub mod dp {
             .field("price", price)
             .field("amount", amount)
     }
+
+    // Number of items awaiting confirmation, which grow without bound if a pipeline is stuck
+    pub fn pending_items(
+        pending_deposits: usize,
+        pending_withdrawals: usize,
+        pending_transfers: usize,
+        pending_swaps: usize,
+    ) -> Point {
+        for (kind, count) in [
+            ("deposits", pending_deposits),
+            ("withdrawals", pending_withdrawals),
+            ("transfers", pending_transfers),
+            ("swaps", pending_swaps),
+        ] {
+            prometheus_gauge(
+                "sys_pending_items",
+                "Items awaiting confirmation",
+                &[("kind", kind)],
+                count as f64,
+            );
+        }
+        Point::new("pending_items")
+            .field("deposits", pending_deposits as i64)
+            .field("withdrawals", pending_withdrawals as i64)
+            .field("transfers", pending_transfers as i64)
+            .field("swaps", pending_swaps as i64)
+    }
+
+    // Priority fee paid by a transaction, by the kind of operation it was for
+    pub fn priority_fee(category: &str, lamports: u64) -> Point {
+        let amount = lamports_to_sol(lamports);
+        prometheus_counter(
+            "sys_priority_fee_sol_total",
+            "Priority fees paid, by operation",
+            &[("category", category)],
+            amount,
+        );
+        Point::new("priority_fee")
+            .tag("category", category)
+            .field("amount", amount)
+    }
+
+    pub fn sync_duration(phase: &str, duration: Duration) -> Point {
+        prometheus_gauge(
+            "sys_sync_duration_seconds",
+            "Duration of the last sync, by phase",
+            &[("phase", phase)],
+            duration.as_secs_f64(),
+        );
+        Point::new("sync_duration")
+            .tag("phase", phase)
+            .field("seconds", duration.as_secs_f64())
+    }
+
+    // Requests made to an RPC endpoint by this process so far. Their Prometheus counters are
+    // recorded as the requests are made
+    pub fn rpc_requests(
+        endpoint: &str,
+        requests: u64,
+        retries: u64,
+        throttled: u64,
+        rate_limited_time: Duration,
+    ) -> Point {
+        Point::new("rpc_requests")
+            .tag("endpoint", endpoint)
+            .field("requests", requests as i64)
+            .field("retries", retries as i64)
+            .field("throttled", throttled as i64)
+            .field("rate_limited_seconds", rate_limited_time.as_secs_f64())
+    }
+
+    // Holdings of a token across all accounts, valued at the current price
+    pub fn token_holdings(
+        maybe_token: MaybeToken,
+        ui_amount: f64,
+        value: f64,
+        unrealized_gain: f64,
+    ) -> Point {
+        let labels = [("token", maybe_token.name())];
+        prometheus_gauge("sys_token_balance", "Token balance", &labels, ui_amount);
+        prometheus_gauge(
+            "sys_token_value_usd",
+            "Token balance value in USD",
+            &labels,
+            value,
+        );
+        prometheus_gauge(
+            "sys_token_unrealized_gain_usd",
+            "Unrealized gain of the token balance in USD",
+            &labels,
+            unrealized_gain,
+        );
+        Point::new("token_holdings")
+            .tag("token", maybe_token.name())
+            .field("amount", ui_amount)
+            .field("value", value)
+            .field("unrealized_gain", unrealized_gain)
+    }
 }
@@ -1,28 +1,498 @@
-use {reqwest::Client, serde_json::json, std::env};
+use {
+    crate::http_client::{self, TracedSend},
+    chrono::{Local, Timelike},
+    reqwest::Client,
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    std::{
+        collections::{HashMap, VecDeque},
+        env, fmt,
+        str::FromStr,
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+    strum::{EnumString, IntoStaticStr},
+};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, IntoStaticStr, Serialize, Deserialize,
+)]
+pub enum NotificationKind {
+    #[strum(serialize = "order")]
+    Order,
+    #[strum(serialize = "transfer")]
+    Transfer,
+    #[strum(serialize = "swap")]
+    Swap,
+    #[strum(serialize = "disposal")]
+    Disposal,
+    #[strum(serialize = "balance")]
+    Balance,
+    #[strum(serialize = "reward")]
+    Reward,
+    #[strum(serialize = "sync")]
+    Sync,
+    #[strum(serialize = "summary")]
+    Summary,
+    #[strum(serialize = "workflow")]
+    Workflow,
+    #[strum(serialize = "stake")]
+    Stake,
+}
+
+pub const POSSIBLE_NOTIFICATION_KIND_VALUES: &[&str] = &[
+    "order", "transfer", "swap", "disposal", "balance", "reward", "sync", "summary", "workflow",
+    "stake",
+];
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    EnumString,
+    IntoStaticStr,
+    Serialize,
+    Deserialize,
+)]
+pub enum Severity {
+    // Routine activity, such as a settled deposit or a filled order
+    #[strum(serialize = "info")]
+    Info,
+    // Something unexpected that doesn't need immediate attention
+    #[strum(serialize = "warn")]
+    Warn,
+    // Needs attention. Sent regardless of quiet hours, rate limits and `--quiet-notifications`
+    #[strum(serialize = "alert")]
+    Alert,
+}
+
+pub const POSSIBLE_SEVERITY_VALUES: &[&str] = &["info", "warn", "alert"];
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, IntoStaticStr, Serialize, Deserialize,
+)]
+pub enum Destination {
+    #[strum(serialize = "slack")]
+    Slack,
+    #[strum(serialize = "telegram")]
+    Telegram,
+}
+
+pub const POSSIBLE_DESTINATION_VALUES: &[&str] = &["slack", "telegram"];
+
+// Sends the notifications of `severity`, and only those of `kind` if set, to `destinations`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationRoute {
+    pub severity: Severity,
+    pub kind: Option<NotificationKind>,
+    pub destinations: Vec<Destination>,
+}
+
+impl NotificationRoute {
+    fn matches(&self, kind: NotificationKind, severity: Severity) -> bool {
+        self.severity == severity && self.kind.map_or(true, |route_kind| route_kind == kind)
+    }
+}
+
+impl fmt::Display for NotificationRoute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", <&str>::from(self.severity))?;
+        if let Some(kind) = self.kind {
+            write!(f, " {}", <&str>::from(kind))?;
+        }
+        write!(
+            f,
+            " -> {}",
+            self.destinations
+                .iter()
+                .map(|destination| <&str>::from(*destination))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+// How much of the end-of-cycle summary a destination receives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumString)]
+pub enum SummaryVerbosity {
+    #[strum(serialize = "off")]
+    Off,
+    // Only cycles that failed
+    #[strum(serialize = "errors")]
+    Errors,
+    // One line of counts for every cycle that changed something or failed
+    #[strum(serialize = "counts")]
+    Counts,
+    // The counts followed by every change
+    #[strum(serialize = "full")]
+    Full,
+}
+
+// What one sync cycle did
+#[derive(Debug, Default, Clone)]
+pub struct CycleSummary {
+    pub new_lots: Vec<String>,
+    pub confirmed_transfers: Vec<String>,
+    pub fills: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl CycleSummary {
+    pub fn is_empty(&self) -> bool {
+        self.new_lots.is_empty()
+            && self.confirmed_transfers.is_empty()
+            && self.fills.is_empty()
+            && self.errors.is_empty()
+    }
+
+    pub fn counts(&self) -> String {
+        fn count(n: usize, what: &str) -> String {
+            format!("{n} {what}{}", if n == 1 { "" } else { "s" })
+        }
+        format!(
+            "{}, {}, {}, {}",
+            count(self.new_lots.len(), "new lot"),
+            count(self.confirmed_transfers.len(), "settled transfer"),
+            count(self.fills.len(), "fill"),
+            count(self.errors.len(), "error"),
+        )
+    }
+
+    // The summary at `verbosity`, or `None` if there is nothing to report at that verbosity
+    pub fn format(&self, verbosity: SummaryVerbosity) -> Option<String> {
+        let sections = [
+            ("Errors", &self.errors),
+            ("New lots", &self.new_lots),
+            ("Settled transfers", &self.confirmed_transfers),
+            ("Fills", &self.fills),
+        ];
+
+        let mut msg = format!("Sync cycle: {}", self.counts());
+        match verbosity {
+            SummaryVerbosity::Off => return None,
+            SummaryVerbosity::Errors if self.errors.is_empty() => return None,
+            SummaryVerbosity::Counts | SummaryVerbosity::Full if self.is_empty() => return None,
+            SummaryVerbosity::Errors | SummaryVerbosity::Counts => {
+                for error in &self.errors {
+                    msg += &format!("\n{error}");
+                }
+            }
+            SummaryVerbosity::Full => {
+                for (title, lines) in sections {
+                    if !lines.is_empty() {
+                        msg += &format!("\n{title}:");
+                        for line in lines {
+                            msg += &format!("\n  {line}");
+                        }
+                    }
+                }
+            }
+        }
+        Some(msg)
+    }
+}
+
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+// Local hours of the day, from `start` up to but excluding `end`, during which routine
+// notifications are dropped. Wraps around midnight when `start` is after `end`
+#[derive(Debug, Clone, Copy)]
+struct QuietHours {
+    start: u32,
+    end: u32,
+}
+
+impl QuietHours {
+    fn contains(&self, hour: u32) -> bool {
+        if self.start <= self.end {
+            self.start <= hour && hour < self.end
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid quiet hours, expected START-END: {s}"))?;
+        let parse_hour = |hour: &str| match hour.trim().parse::<u32>() {
+            Ok(hour) if hour < 24 => Ok(hour),
+            _ => Err(format!("Invalid quiet hours hour: {hour}")),
+        };
+        Ok(Self {
+            start: parse_hour(start)?,
+            end: parse_hour(end)?,
+        })
+    }
+}
+
+// Parses `KIND=COUNT,...`, the maximum number of notifications of each kind sent per hour
+fn parse_rate_limits(s: &str) -> Result<HashMap<NotificationKind, usize>, String> {
+    s.split(',')
+        .filter(|rate_limit| !rate_limit.trim().is_empty())
+        .map(|rate_limit| {
+            let (kind, count) = rate_limit
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid rate limit, expected KIND=COUNT: {rate_limit}"))?;
+            let kind = NotificationKind::from_str(kind.trim())
+                .map_err(|_| format!("Invalid notification kind: {kind}"))?;
+            let count = count
+                .trim()
+                .parse::<usize>()
+                .map_err(|err| format!("Invalid rate limit count: {count}: {err}"))?;
+            Ok((kind, count))
+        })
+        .collect()
+}
+
+fn env_var_of<T>(name: &str, parse: impl FnOnce(&str) -> Result<T, String>) -> Option<T> {
+    let value = env::var(name).ok()?;
+    parse(&value)
+        .map_err(|err| eprintln!("Ignoring {name}: {err}"))
+        .ok()
+}
+
+#[derive(Default)]
+struct RateLimitState {
+    sent: VecDeque<Instant>,
+    suppressed: usize,
+}
+
+// A destination configured through the environment
+struct DestinationConfig {
+    destination: Destination,
+    url: String,
+    chat_id: Option<String>, // Telegram only
+    quiet_hours: Option<QuietHours>,
+    summary: SummaryVerbosity,
+}
+
+impl DestinationConfig {
+    // `{PREFIX}_QUIET_HOURS` and `{PREFIX}_SUMMARY` configure the destination's quiet hours and
+    // summary verbosity
+    fn new(destination: Destination, url: String, chat_id: Option<String>, prefix: &str) -> Self {
+        Self {
+            destination,
+            url,
+            chat_id,
+            quiet_hours: env_var_of(&format!("{prefix}_QUIET_HOURS"), QuietHours::from_str),
+            summary: env_var_of(&format!("{prefix}_SUMMARY"), |s| {
+                SummaryVerbosity::from_str(s).map_err(|_| format!("Invalid summary verbosity: {s}"))
+            })
+            .unwrap_or(SummaryVerbosity::Off),
+        }
+    }
+}
 
 pub struct Notifier {
     client: Client,
-    slack_webhook: Option<String>,
+    destinations: Vec<DestinationConfig>,
+    routes: Vec<NotificationRoute>,
+    quiet: bool,
+    rate_limits: HashMap<NotificationKind, usize>,
+    rate_limit_state: Mutex<HashMap<NotificationKind, RateLimitState>>,
 }
 
 impl Default for Notifier {
     fn default() -> Self {
-        let slack_webhook = env::var("SLACK_WEBHOOK").ok();
+        let mut destinations = vec![];
+        if let Ok(slack_webhook) = env::var("SLACK_WEBHOOK") {
+            destinations.push(DestinationConfig::new(
+                Destination::Slack,
+                slack_webhook,
+                None,
+                "SLACK",
+            ));
+        }
+        if let (Ok(bot_token), Ok(chat_id)) =
+            (env::var("TELEGRAM_BOT_TOKEN"), env::var("TELEGRAM_CHAT_ID"))
+        {
+            destinations.push(DestinationConfig::new(
+                Destination::Telegram,
+                format!("https://api.telegram.org/bot{bot_token}/sendMessage"),
+                Some(chat_id),
+                "TELEGRAM",
+            ));
+        }
+        let rate_limits = env_var_of("NOTIFY_RATE_LIMITS", parse_rate_limits).unwrap_or_default();
         Notifier {
-            client: Client::new(),
-            slack_webhook,
+            client: http_client::client(),
+            destinations,
+            routes: vec![],
+            quiet: false,
+            rate_limits,
+            rate_limit_state: Mutex::default(),
         }
     }
 }
 
 impl Notifier {
-    pub async fn send(&self, msg: &str) {
-        if let Some(ref slack_webhook) = self.slack_webhook {
-            let data = json!({ "text": msg });
+    // A notifier that drops all messages
+    pub fn disabled() -> Self {
+        Notifier {
+            client: http_client::client(),
+            destinations: vec![],
+            routes: vec![],
+            quiet: false,
+            rate_limits: HashMap::default(),
+            rate_limit_state: Mutex::default(),
+        }
+    }
 
-            if let Err(err) = self.client.post(slack_webhook).json(&data).send().await {
-                eprintln!("Failed to send Slack message: {err:?}");
-            }
+    // Without routes every notification is sent to every destination. With routes, a
+    // notification is only sent to the destinations of the routes it matches
+    pub fn with_routes(mut self, routes: Vec<NotificationRoute>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    // Only sends alerts
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    // The destinations a notification of `kind` and `severity` is sent to right now
+    fn route(&self, kind: NotificationKind, severity: Severity) -> Vec<&DestinationConfig> {
+        if self.quiet && severity != Severity::Alert {
+            return vec![];
+        }
+
+        let hour = Local::now().hour();
+        self.destinations
+            .iter()
+            .filter(|destination| {
+                self.routes.is_empty()
+                    || self.routes.iter().any(|route| {
+                        route.matches(kind, severity)
+                            && route.destinations.contains(&destination.destination)
+                    })
+            })
+            .filter(|destination| {
+                severity == Severity::Alert
+                    || !destination
+                        .quiet_hours
+                        .is_some_and(|quiet_hours| quiet_hours.contains(hour))
+            })
+            .collect()
+    }
+
+    // Applies the rate limit of `kind`, returning the number of notifications suppressed since the
+    // last one sent or `None` if this notification should be suppressed too
+    fn rate_limit(&self, kind: NotificationKind) -> Option<usize> {
+        let Some(&max_per_period) = self.rate_limits.get(&kind) else {
+            return Some(0);
+        };
+
+        let mut rate_limit_state = self.rate_limit_state.lock().unwrap();
+        let state = rate_limit_state.entry(kind).or_default();
+
+        let now = Instant::now();
+        while state
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= RATE_LIMIT_PERIOD)
+        {
+            state.sent.pop_front();
+        }
+
+        if state.sent.len() >= max_per_period {
+            state.suppressed += 1;
+            return None;
+        }
+        state.sent.push_back(now);
+        Some(std::mem::take(&mut state.suppressed))
+    }
+
+    // Alerts are never rate limited
+    fn suppressed_suffix(&self, kind: NotificationKind, severity: Severity) -> Option<String> {
+        let suppressed = if severity == Severity::Alert {
+            0
+        } else {
+            self.rate_limit(kind)?
+        };
+
+        Some(if suppressed > 0 {
+            format!(
+                "\n({suppressed} {} notifications suppressed)",
+                <&str>::from(kind)
+            )
+        } else {
+            String::new()
+        })
+    }
+
+    async fn post(&self, destination: &DestinationConfig, text: &str) {
+        let data = match destination.destination {
+            Destination::Slack => json!({ "text": text }),
+            Destination::Telegram => json!({ "chat_id": destination.chat_id, "text": text }),
+        };
+
+        if let Err(err) = self
+            .client
+            .post(&destination.url)
+            .json(&data)
+            .traced_send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            eprintln!(
+                "Failed to send {:?} message: {err:?}",
+                destination.destination
+            );
+        }
+    }
+
+    // Sends the end-of-cycle summary to each destination at that destination's verbosity. A
+    // summary that reports errors is a warning
+    pub async fn send_summary(&self, summary: &CycleSummary) {
+        let severity = if summary.errors.is_empty() {
+            Severity::Info
+        } else {
+            Severity::Warn
+        };
+        let msgs = self
+            .route(NotificationKind::Summary, severity)
+            .into_iter()
+            .filter_map(|destination| {
+                summary
+                    .format(destination.summary)
+                    .map(|msg| (destination, msg))
+            })
+            .collect::<Vec<_>>();
+        if msgs.is_empty() {
+            return;
+        }
+
+        let Some(suffix) = self.suppressed_suffix(NotificationKind::Summary, severity) else {
+            return;
+        };
+        for (destination, msg) in msgs {
+            self.post(destination, &format!("{msg}{suffix}")).await;
+        }
+    }
+
+    pub async fn send(&self, kind: NotificationKind, severity: Severity, msg: &str) {
+        let destinations = self.route(kind, severity);
+        if destinations.is_empty() {
+            return;
+        }
+
+        let Some(suffix) = self.suppressed_suffix(kind, severity) else {
+            return;
+        };
+        let text = format!("{msg}{suffix}");
+        for destination in destinations {
+            self.post(destination, &text).await;
         }
     }
 }
@@ -0,0 +1,84 @@
+use {
+    separator::FixedPlaceSeparatable,
+    serde::{Deserialize, Serialize},
+    std::{fmt, sync::OnceLock},
+};
+
+// How numbers and USD values are written in console output. Exports that are read by other
+// programs, such as CSV files and `--output json`, always use plain US-style numbers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NumberFormat {
+    pub thousands_separator: String, // may be empty to not group digits
+    pub decimal_separator: char,
+    pub currency_symbol: String, // prefixed to USD values
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            thousands_separator: ",".into(),
+            decimal_separator: '.',
+            currency_symbol: "$".into(),
+        }
+    }
+}
+
+impl fmt::Display for NumberFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (thousands separator: {:?}, decimal separator: {:?}, currency symbol: {:?})",
+            self.format_fixed_place(1234567.891, 2),
+            self.thousands_separator,
+            self.decimal_separator,
+            self.currency_symbol
+        )
+    }
+}
+
+impl NumberFormat {
+    pub fn format_fixed_place(&self, value: f64, places: usize) -> String {
+        let us_style = value.separated_string_with_fixed_place(places);
+        if self.thousands_separator == "," && self.decimal_separator == '.' {
+            return us_style;
+        }
+
+        let mut result = String::with_capacity(us_style.len());
+        for c in us_style.chars() {
+            match c {
+                ',' => result += &self.thousands_separator,
+                '.' => result.push(self.decimal_separator),
+                c => result.push(c),
+            }
+        }
+        result
+    }
+}
+
+static NUMBER_FORMAT: OnceLock<NumberFormat> = OnceLock::new();
+
+// Called once at startup with the format configured in the database. Until then, and if never
+// called, numbers are formatted US-style
+pub fn configure(number_format: NumberFormat) {
+    let _ = NUMBER_FORMAT.set(number_format);
+}
+
+pub fn number_format() -> &'static NumberFormat {
+    NUMBER_FORMAT.get_or_init(NumberFormat::default)
+}
+
+// Prefixes an already formatted USD value with the configured currency symbol
+pub fn currency<T: fmt::Display>(value: T) -> String {
+    format!("{}{value}", number_format().currency_symbol)
+}
+
+// Locale-aware replacement for `separator::FixedPlaceSeparatable`
+pub trait LocalizedFixedPlace {
+    fn localized_string_with_fixed_place(&self, places: usize) -> String;
+}
+
+impl LocalizedFixedPlace for f64 {
+    fn localized_string_with_fixed_place(&self, places: usize) -> String {
+        number_format().format_fixed_place(*self, places)
+    }
+}
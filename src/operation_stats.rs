@@ -0,0 +1,226 @@
+use {
+    chrono::prelude::*,
+    serde::{Deserialize, Serialize},
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    strum::{EnumString, IntoStaticStr},
+};
+
+const OPERATION_STATS_FILENAME: &str = "operation-stats.json";
+
+// Days of stats kept in the database directory
+const RETENTION_DAYS: i64 = 90;
+
+// Latencies are counted in buckets a quarter of a doubling wide. Bucket 0 holds those under a
+// millisecond, and bucket `i` those from 2^((i-1)/4) up to 2^(i/4) milliseconds
+const BUCKETS_PER_DOUBLING: f64 = 4.;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    EnumString,
+    IntoStaticStr,
+)]
+pub enum Operation {
+    #[strum(serialize = "send")]
+    Send, // A transaction sent to an RPC endpoint, or to Jito as a bundle
+    #[strum(serialize = "confirmation")]
+    Confirmation, // From first sending a transaction until it is confirmed, fails or expires
+    #[strum(serialize = "exchange-api")]
+    ExchangeApi,
+    #[strum(serialize = "sync")]
+    Sync,
+}
+
+// The outcomes and latencies of an operation
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperationStats {
+    pub successes: u64,
+    pub failures: u64,
+    latency_histogram: Vec<u64>,
+}
+
+impl OperationStats {
+    fn record(&mut self, duration: Duration, success: bool) {
+        if success {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+
+        let milliseconds = duration.as_secs_f64() * 1_000.;
+        let bucket = if milliseconds < 1. {
+            0
+        } else {
+            (milliseconds.log2() * BUCKETS_PER_DOUBLING) as usize + 1
+        };
+        if self.latency_histogram.len() <= bucket {
+            self.latency_histogram.resize(bucket + 1, 0);
+        }
+        self.latency_histogram[bucket] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.successes += other.successes;
+        self.failures += other.failures;
+        if self.latency_histogram.len() < other.latency_histogram.len() {
+            self.latency_histogram
+                .resize(other.latency_histogram.len(), 0);
+        }
+        for (count, other_count) in self
+            .latency_histogram
+            .iter_mut()
+            .zip(&other.latency_histogram)
+        {
+            *count += other_count;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.successes + self.failures
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        self.successes as f64 / self.count().max(1) as f64
+    }
+
+    // The median latency, accurate to within the width of a histogram bucket
+    pub fn median_latency(&self) -> Option<Duration> {
+        let median = self.latency_histogram.iter().sum::<u64>().div_ceil(2);
+        let mut count = 0;
+        let bucket = self.latency_histogram.iter().position(|bucket_count| {
+            count += bucket_count;
+            count >= median
+        })?;
+
+        let milliseconds = if bucket == 0 {
+            0.5
+        } else {
+            2_f64.powf((bucket as f64 - 0.5) / BUCKETS_PER_DOUBLING)
+        };
+        Some(Duration::from_secs_f64(milliseconds / 1_000.))
+    }
+}
+
+// The stats of an operation on one day. `name` distinguishes the RPC endpoint of sends and the
+// exchange and method of exchange API calls
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyOperationStats {
+    pub date: NaiveDate,
+    pub operation: Operation,
+    pub name: String,
+    #[serde(flatten)]
+    pub stats: OperationStats,
+}
+
+#[derive(Default)]
+struct OperationStatsFile {
+    filename: Option<PathBuf>,
+    daily_stats: Vec<DailyOperationStats>,
+    modified: bool,
+}
+
+lazy_static::lazy_static! {
+    static ref OPERATION_STATS: Mutex<OperationStatsFile> =
+        Mutex::new(OperationStatsFile::default());
+}
+
+pub fn record(operation: Operation, name: &str, duration: Duration, success: bool) {
+    let date = Local::now().date_naive();
+    let mut operation_stats = OperationStats::default();
+    operation_stats.record(duration, success);
+
+    let mut operation_stats_file = OPERATION_STATS.lock().unwrap();
+    match operation_stats_file
+        .daily_stats
+        .iter_mut()
+        .find(|daily_stats| {
+            daily_stats.date == date
+                && daily_stats.operation == operation
+                && daily_stats.name == name
+        }) {
+        Some(daily_stats) => daily_stats.stats.merge(&operation_stats),
+        None => operation_stats_file.daily_stats.push(DailyOperationStats {
+            date,
+            operation,
+            name: name.to_string(),
+            stats: operation_stats,
+        }),
+    }
+    operation_stats_file.modified = true;
+}
+
+// The stats loaded from the database directory along with those recorded since
+pub fn daily_stats() -> Vec<DailyOperationStats> {
+    OPERATION_STATS.lock().unwrap().daily_stats.clone()
+}
+
+// Saves the stats recorded since the last save into the database directory, dropping those older
+// than `RETENTION_DAYS`. Does nothing unless loaded by `load` for writing
+pub fn save() {
+    let mut operation_stats_file = OPERATION_STATS.lock().unwrap();
+    if !operation_stats_file.modified {
+        return;
+    }
+    let Some(filename) = operation_stats_file.filename.clone() else {
+        return;
+    };
+
+    let oldest_date =
+        Local::now().date_naive() - chrono::Duration::try_days(RETENTION_DAYS).unwrap();
+    operation_stats_file
+        .daily_stats
+        .retain(|daily_stats| daily_stats.date >= oldest_date);
+
+    let temp_filename = format!(
+        "{}.temp.{}",
+        filename.display(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    );
+    let result = serde_json::to_vec(&operation_stats_file.daily_stats)
+        .map_err(io::Error::from)
+        .and_then(|bytes| fs::write(&temp_filename, bytes))
+        .and_then(|()| fs::rename(&temp_filename, &filename));
+    match result {
+        Ok(()) => operation_stats_file.modified = false,
+        Err(err) => eprintln!("Unable to save {}: {err}", filename.display()),
+    }
+}
+
+// Saves the stats recorded while it was held into the database directory when dropped
+pub struct OperationStatsGuard;
+
+impl Drop for OperationStatsGuard {
+    fn drop(&mut self) {
+        save();
+    }
+}
+
+// Loads the stats kept in `db_path`. Unless `read_only`, they are saved back with the stats
+// recorded since once the returned guard is dropped
+pub fn load(db_path: &Path, read_only: bool) -> OperationStatsGuard {
+    let filename = db_path.join(OPERATION_STATS_FILENAME);
+    *OPERATION_STATS.lock().unwrap() = OperationStatsFile {
+        daily_stats: fs::read(&filename)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default(),
+        filename: (!read_only).then_some(filename),
+        modified: false,
+    };
+    OperationStatsGuard
+}
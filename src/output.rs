@@ -0,0 +1,108 @@
+use {
+    crate::field_as_string,
+    chrono::NaiveDate,
+    serde::Serialize,
+    solana_sdk::pubkey::Pubkey,
+    strum::{EnumString, IntoStaticStr},
+    sys::{exchange::OrderSide, token::MaybeToken},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, IntoStaticStr)]
+pub enum OutputFormat {
+    #[strum(serialize = "display")]
+    Display,
+    #[strum(serialize = "json")]
+    Json,
+}
+
+pub const POSSIBLE_OUTPUT_FORMAT_VALUES: &[&str] = &["display", "json"];
+
+pub fn println_json<T: Serialize>(value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+// Amounts are in UI units and prices, values and bases are in USD
+
+#[derive(Serialize)]
+pub struct LotOutput {
+    pub lot_number: usize,
+    pub acquired: NaiveDate,
+    pub acquisition: String,
+    pub amount: f64,
+    pub price: f64,
+    pub basis: f64,
+    pub income: f64,
+}
+
+#[derive(Serialize)]
+pub struct OpenOrderOutput {
+    pub pair: String,
+    pub side: OrderSide,
+    pub order_id: String,
+    pub price: f64,
+    pub amount: f64,
+    pub lots: Vec<LotOutput>,
+}
+
+#[derive(Serialize)]
+pub struct AccountOutput {
+    #[serde(with = "field_as_string")]
+    pub address: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub token: MaybeToken,
+    pub description: String,
+    pub balance: f64,
+    pub value: Option<f64>,
+    pub lots: Vec<LotOutput>,
+    pub open_orders: Vec<OpenOrderOutput>,
+}
+
+#[derive(Serialize)]
+pub struct AccountListOutput {
+    pub as_of: Option<NaiveDate>,
+    pub accounts: Vec<AccountOutput>,
+}
+
+#[derive(Serialize)]
+pub struct CostBasisOutput {
+    #[serde(with = "field_as_string")]
+    pub token: MaybeToken,
+    pub amount: f64,
+    pub basis: f64,
+    pub average_price: f64,
+}
+
+#[derive(Serialize)]
+pub struct PendingDepositOutput {
+    #[serde(with = "field_as_string")]
+    pub token: MaybeToken,
+    pub amount: f64,
+    pub signature: String,
+}
+
+#[derive(Serialize)]
+pub struct PendingWithdrawalOutput {
+    #[serde(with = "field_as_string")]
+    pub token: MaybeToken,
+    pub amount: f64,
+    #[serde(with = "field_as_string")]
+    pub destination: Pubkey,
+    pub tag: String,
+}
+
+#[derive(Serialize)]
+pub struct PriceOutput {
+    #[serde(with = "field_as_string")]
+    pub token: MaybeToken,
+    pub when: Option<NaiveDate>,
+    pub price: f64,
+}
+
+#[derive(Serialize)]
+pub struct ExchangeBalanceOutput {
+    pub coin: String,
+    pub total: f64,
+    pub available: f64,
+    pub earn: f64,
+}
@@ -0,0 +1,16 @@
+use {
+    aes_gcm_siv::{aead::NewAead, Aes256GcmSiv},
+    hmac::Hmac,
+    sha2::Sha256,
+};
+
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+
+// An AES-256-GCM-SIV cipher keyed by PBKDF2-HMAC-SHA256 of `passphrase` and `salt`
+pub fn derive_cipher(passphrase: &str, salt: &[u8], iterations: u32) -> Aes256GcmSiv {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, iterations, &mut key);
+    Aes256GcmSiv::new_from_slice(&key).expect("valid key length")
+}
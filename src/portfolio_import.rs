@@ -0,0 +1,415 @@
+use {
+    crate::db::*,
+    chrono::NaiveDate,
+    rust_decimal::prelude::*,
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::pubkey::Pubkey,
+    std::{collections::HashMap, fs, path::Path, str::FromStr},
+    strum::{EnumString, IntoStaticStr},
+    sys::token::*,
+};
+
+pub const POSSIBLE_IMPORT_FORMAT_VALUES: &[&str] = &["sys", "json", "koinly", "cointracking"];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, IntoStaticStr)]
+pub enum ImportFormat {
+    #[strum(serialize = "sys")]
+    Sys,
+    #[strum(serialize = "json")]
+    Json, // written by `sys db export --format json`
+    #[strum(serialize = "koinly")]
+    Koinly,
+    #[strum(serialize = "cointracking")]
+    CoinTracking,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum ImportedKind {
+    Acquisition(LotAcquistionKind),
+    Disposal,
+}
+
+#[derive(Debug, Clone)]
+struct ImportedTransaction {
+    when: NaiveDate,
+    kind: ImportedKind,
+    token: MaybeToken,
+    ui_amount: f64,
+    usd_value: Option<f64>,
+    description: String,
+}
+
+// Koinly labels and CoinTracking types that represent income
+const INCOME_CATEGORIES: &[&str] = &[
+    "reward",
+    "rewards",
+    "reward / bonus",
+    "staking",
+    "income",
+    "interest income",
+    "lending interest",
+    "lending income",
+    "mining",
+    "airdrop",
+    "fork",
+    "other income",
+];
+
+// Koinly labels and CoinTracking types that dispose of tokens without a trade
+const DISPOSAL_CATEGORIES: &[&str] = &["gift", "donation", "cost", "spend", "lost", "stolen"];
+
+fn parse_csv(contents: &str) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.into_iter()
+        .filter(|row| row.iter().any(|field| !field.trim().is_empty()))
+        .collect()
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    let date = value.split_whitespace().next().unwrap_or_default();
+    for format in ["%Y-%m-%d", "%d.%m.%Y", "%m/%d/%Y"] {
+        if let Ok(when) = NaiveDate::parse_from_str(date, format) {
+            return Ok(when);
+        }
+    }
+    Err(format!("Unsupported date: {value}"))
+}
+
+fn parse_amount(value: &str) -> Option<f64> {
+    let value = value.trim().replace(',', "");
+    if value.is_empty() {
+        None
+    } else {
+        value.parse::<f64>().ok().map(f64::abs)
+    }
+}
+
+fn token_of(currency: &str) -> Option<MaybeToken> {
+    match currency.trim() {
+        "" => None,
+        "SOL" => Some(MaybeToken::SOL()),
+        currency => Token::from_str(currency).ok().map(MaybeToken::from),
+    }
+}
+
+fn column(header: &[String], name: &str) -> Option<usize> {
+    header
+        .iter()
+        .position(|column| column.trim().eq_ignore_ascii_case(name))
+}
+
+fn parse_koinly(rows: &[Vec<String>]) -> Result<Vec<ImportedTransaction>, String> {
+    let (header, rows) = rows.split_first().ok_or("Empty Koinly export")?;
+    let required_column = |name: &str| {
+        column(header, name).ok_or_else(|| format!("Koinly export is missing the {name} column"))
+    };
+    let date = required_column("Date")?;
+    let sent_amount = required_column("Sent Amount")?;
+    let sent_currency = required_column("Sent Currency")?;
+    let received_amount = required_column("Received Amount")?;
+    let received_currency = required_column("Received Currency")?;
+    let net_worth_amount = column(header, "Net Worth Amount");
+    let net_worth_currency = column(header, "Net Worth Currency");
+    let label = column(header, "Label");
+    let description = column(header, "Description");
+
+    let mut transactions = vec![];
+    for row in rows {
+        let field = |index: usize| row.get(index).map(|s| s.trim()).unwrap_or_default();
+        let optional_field = |index: Option<usize>| index.map(field).unwrap_or_default();
+
+        let when = parse_date(field(date))?;
+        let label = optional_field(label).to_lowercase();
+        let usd_value = if optional_field(net_worth_currency) == "USD" {
+            parse_amount(optional_field(net_worth_amount))
+        } else {
+            None
+        };
+        let description = format!(
+            "Koinly {}{}",
+            if label.is_empty() { "transfer" } else { &label },
+            match optional_field(description) {
+                "" => String::new(),
+                description => format!(": {description}"),
+            }
+        );
+
+        let sent = parse_amount(field(sent_amount)).zip(token_of(field(sent_currency)));
+        let received = parse_amount(field(received_amount)).zip(token_of(field(received_currency)));
+        let is_trade = !field(sent_currency).is_empty() && !field(received_currency).is_empty();
+
+        if let Some((ui_amount, token)) = received {
+            let kind = if INCOME_CATEGORIES.contains(&label.as_str()) {
+                Some(LotAcquistionKind::NotAvailable)
+            } else if is_trade {
+                Some(LotAcquistionKind::Fiat)
+            } else {
+                None // Deposits are transfers between the user's own wallets
+            };
+            if let Some(kind) = kind {
+                transactions.push(ImportedTransaction {
+                    when,
+                    kind: ImportedKind::Acquisition(kind),
+                    token,
+                    ui_amount,
+                    usd_value,
+                    description: description.clone(),
+                });
+            }
+        }
+        if let Some((ui_amount, token)) = sent {
+            if is_trade || DISPOSAL_CATEGORIES.contains(&label.as_str()) {
+                transactions.push(ImportedTransaction {
+                    when,
+                    kind: ImportedKind::Disposal,
+                    token,
+                    ui_amount,
+                    usd_value,
+                    description,
+                });
+            }
+        }
+    }
+    Ok(transactions)
+}
+
+fn parse_cointracking(rows: &[Vec<String>]) -> Result<Vec<ImportedTransaction>, String> {
+    // CoinTracking exports repeat the "Cur." column name, so the leading columns are positional:
+    // Type, Buy, Cur., Sell, Cur., Fee, Cur., Exchange, Group, Comment, Date
+    const TYPE: usize = 0;
+    const BUY_AMOUNT: usize = 1;
+    const BUY_CURRENCY: usize = 2;
+    const SELL_AMOUNT: usize = 3;
+    const SELL_CURRENCY: usize = 4;
+    const EXCHANGE: usize = 7;
+    const COMMENT: usize = 9;
+    const DATE: usize = 10;
+
+    let (header, rows) = rows.split_first().ok_or("Empty CoinTracking export")?;
+    if header.len() <= DATE || !header[TYPE].trim().eq_ignore_ascii_case("Type") {
+        return Err("Unrecognized CoinTracking export header".into());
+    }
+    let buy_usd_value = column(header, "Buy Value in USD");
+    let sell_usd_value = column(header, "Sell Value in USD");
+
+    let mut transactions = vec![];
+    for row in rows {
+        let field = |index: usize| row.get(index).map(|s| s.trim()).unwrap_or_default();
+        let optional_field = |index: Option<usize>| index.map(field).unwrap_or_default();
+
+        let when = parse_date(field(DATE))?;
+        let category = field(TYPE).to_lowercase();
+        let description = format!(
+            "CoinTracking {}{}{}",
+            category,
+            match field(EXCHANGE) {
+                "" => String::new(),
+                exchange => format!(" ({exchange})"),
+            },
+            match field(COMMENT) {
+                "" => String::new(),
+                comment => format!(": {comment}"),
+            }
+        );
+
+        let is_trade = category == "trade";
+        if let Some((ui_amount, token)) =
+            parse_amount(field(BUY_AMOUNT)).zip(token_of(field(BUY_CURRENCY)))
+        {
+            let kind = if INCOME_CATEGORIES.contains(&category.as_str()) {
+                Some(LotAcquistionKind::NotAvailable)
+            } else if is_trade {
+                Some(LotAcquistionKind::Fiat)
+            } else {
+                None
+            };
+            if let Some(kind) = kind {
+                transactions.push(ImportedTransaction {
+                    when,
+                    kind: ImportedKind::Acquisition(kind),
+                    token,
+                    ui_amount,
+                    usd_value: parse_amount(optional_field(buy_usd_value)),
+                    description: description.clone(),
+                });
+            }
+        }
+        if let Some((ui_amount, token)) =
+            parse_amount(field(SELL_AMOUNT)).zip(token_of(field(SELL_CURRENCY)))
+        {
+            if is_trade || DISPOSAL_CATEGORIES.contains(&category.as_str()) {
+                transactions.push(ImportedTransaction {
+                    when,
+                    kind: ImportedKind::Disposal,
+                    token,
+                    ui_amount,
+                    usd_value: parse_amount(optional_field(sell_usd_value)),
+                    description,
+                });
+            }
+        }
+    }
+    Ok(transactions)
+}
+
+// Imports the lot history of another portfolio tool into the tracked accounts of `address`.
+// Only SOL and supported tokens are imported; transfers between wallets are skipped
+pub async fn import(
+    db: &mut Db,
+    rpc_client: &RpcClient,
+    format: ImportFormat,
+    path: &Path,
+    address: Pubkey,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Unable to read {}: {err}", path.display()))?;
+    let rows = parse_csv(&contents);
+
+    let mut transactions = match format {
+        ImportFormat::Koinly => parse_koinly(&rows)?,
+        ImportFormat::CoinTracking => parse_cointracking(&rows)?,
+        ImportFormat::Sys | ImportFormat::Json => unreachable!(),
+    };
+    // Acquisitions must be recorded before any same-day disposals of them
+    transactions
+        .sort_by_key(|transaction| (transaction.when, transaction.kind == ImportedKind::Disposal));
+
+    // Every price is resolved and every row validated before anything is recorded, so that a
+    // bad row doesn't leave the import half done
+    let mut priced_transactions = vec![];
+    for transaction in transactions {
+        let token = transaction.token;
+        let amount = token.amount(transaction.ui_amount);
+        if amount == 0 {
+            continue;
+        }
+
+        let decimal_price = match transaction.usd_value {
+            Some(usd_value) => {
+                Decimal::from_f64(usd_value / transaction.ui_amount).unwrap_or_default()
+            }
+            None => token
+                .get_historical_price(rpc_client, transaction.when)
+                .await
+                .map_err(|err| {
+                    format!(
+                        "Unable to determine the {token} price on {}: {err}",
+                        transaction.when
+                    )
+                })?,
+        };
+        priced_transactions.push((transaction, amount, decimal_price));
+    }
+    validate(db, address, &priced_transactions)?;
+
+    let epoch = rpc_client.get_epoch_info()?.epoch;
+    let (acquisitions, disposals) = db.with_single_save(|db| {
+        let (mut acquisitions, mut disposals) = (0, 0);
+        for (transaction, amount, decimal_price) in priced_transactions {
+            let token = transaction.token;
+            match transaction.kind {
+                ImportedKind::Acquisition(kind) => {
+                    if db.get_account(address, token).is_none() {
+                        db.add_account(TrackedAccount {
+                            address,
+                            token,
+                            description: format!("Imported from {}", <&str>::from(format)),
+                            last_update_epoch: epoch,
+                            last_update_balance: 0,
+                            lots: vec![],
+                            no_sync: Some(true),
+                            last_activity: None,
+                        })?;
+                    }
+                    let mut account = db.get_account(address, token).unwrap();
+                    account.lots.push(Lot {
+                        lot_number: db.next_lot_number(),
+                        acquisition: LotAcquistion::new(transaction.when, decimal_price, kind),
+                        amount,
+                    });
+                    account.last_update_balance += amount;
+                    db.update_account(account)?;
+                    acquisitions += 1;
+                }
+                ImportedKind::Disposal => {
+                    db.record_disposal(
+                        address,
+                        token,
+                        amount,
+                        transaction.description,
+                        transaction.when,
+                        decimal_price,
+                        LotSelectionMethod::FirstInFirstOut,
+                        None,
+                    )?;
+                    disposals += 1;
+                }
+            }
+        }
+        Ok((acquisitions, disposals))
+    })?;
+
+    println!("Imported {acquisitions} acquisitions and {disposals} disposals into {address}");
+    Ok(())
+}
+
+// Checks that no row falls in a closed period and that each disposal is covered by the lots held
+// at that point of the import
+fn validate(
+    db: &Db,
+    address: Pubkey,
+    priced_transactions: &[(ImportedTransaction, u64, Decimal)],
+) -> Result<(), String> {
+    let closed_periods = db.closed_periods();
+    let mut balances = HashMap::new();
+    for (transaction, amount, _) in priced_transactions {
+        let row = format!("{} ({})", transaction.when, transaction.description);
+        if closed_periods.contains(&period_start(transaction.when)) {
+            return Err(format!("Unable to import {row}: its period is closed"));
+        }
+
+        let token = transaction.token;
+        let balance = balances.entry(token).or_insert_with(|| {
+            db.get_account(address, token)
+                .map(|account| account.lots.iter().map(|lot| lot.amount).sum::<u64>())
+                .unwrap_or_default()
+        });
+        match transaction.kind {
+            ImportedKind::Acquisition(_) => *balance += amount,
+            ImportedKind::Disposal => {
+                *balance = balance.checked_sub(*amount).ok_or_else(|| {
+                    format!(
+                        "Unable to import disposal on {row}: only {} held",
+                        token.format_amount(*balance)
+                    )
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,12 @@
 use {
-    crate::{helius_rpc, RpcClients},
-    solana_client::rpc_client::RpcClient,
+    crate::{chain_client::ChainClient, helius_rpc, RpcClients},
     solana_sdk::{
         compute_budget,
-        instruction::Instruction,
+        instruction::{CompiledInstruction, Instruction},
+        message::VersionedMessage,
         native_token::lamports_to_sol,
         native_token::{sol_to_lamports, Sol},
+        pubkey::Pubkey,
     },
 };
 
@@ -63,6 +64,35 @@ impl ComputeBudget {
         }
     }
 
+    // Extracts the compute budget from the ComputeBudget instructions of a compiled transaction
+    pub fn from_compiled_instructions(
+        account_keys: &[Pubkey],
+        instructions: &[CompiledInstruction],
+    ) -> Self {
+        let mut transaction_compute_budget = Self::default();
+        for instruction in instructions {
+            if account_keys.get(instruction.program_id_index as usize)
+                != Some(&compute_budget::id())
+            {
+                continue;
+            }
+            match solana_sdk::borsh0_10::try_from_slice_unchecked(&instruction.data) {
+                Ok(compute_budget::ComputeBudgetInstruction::SetComputeUnitLimit(
+                    compute_unit_limit,
+                )) => {
+                    transaction_compute_budget.compute_unit_limit = compute_unit_limit;
+                }
+                Ok(compute_budget::ComputeBudgetInstruction::SetComputeUnitPrice(
+                    micro_lamports,
+                )) => {
+                    transaction_compute_budget.compute_unit_price_micro_lamports = micro_lamports;
+                }
+                _ => {}
+            }
+        }
+        transaction_compute_budget
+    }
+
     pub fn priority_fee_lamports(&self) -> u64 {
         self.compute_unit_limit as u64 * self.compute_unit_price_micro_lamports / (1e6 as u64)
     }
@@ -70,7 +100,7 @@ impl ComputeBudget {
 
 // Returns a sorted list of compute unit prices in micro lamports, from low to high
 fn get_recent_priority_fees_for_instructions(
-    rpc_client: &RpcClient,
+    rpc_client: &dyn ChainClient,
     instructions: &[Instruction],
 ) -> Result<Vec<u64>, String> {
     let mut account_keys: Vec<_> = instructions
@@ -99,12 +129,12 @@ fn get_recent_priority_fees_for_instructions(
     Ok(prioritization_fees)
 }
 
-pub fn apply_priority_fee(
+pub fn compute_priority_fee(
     rpc_clients: &RpcClients,
-    instructions: &mut Vec<Instruction>,
+    instructions: &[Instruction],
     compute_unit_limit: u32,
     priority_fee: PriorityFee,
-) -> Result<u64, Box<dyn std::error::Error>> {
+) -> Result<ComputeBudget, Box<dyn std::error::Error>> {
     assert_ne!(compute_unit_limit, 0);
 
     let compute_budget = match priority_fee {
@@ -216,6 +246,18 @@ pub fn apply_priority_fee(
         "Priority fee too large, Bug?"
     );
 
+    Ok(compute_budget)
+}
+
+pub fn apply_priority_fee(
+    rpc_clients: &RpcClients,
+    instructions: &mut Vec<Instruction>,
+    compute_unit_limit: u32,
+    priority_fee: PriorityFee,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let compute_budget =
+        compute_priority_fee(rpc_clients, instructions, compute_unit_limit, priority_fee)?;
+
     instructions.push(
         compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(
             compute_budget.compute_unit_limit,
@@ -232,3 +274,86 @@ pub fn apply_priority_fee(
 
     Ok(compute_budget.priority_fee_lamports())
 }
+
+// Rewrites the ComputeBudget instructions of a compiled transaction to use
+// `compute_unit_price_micro_lamports`, removing any duplicate ComputeBudget instructions. A
+// SetComputeUnitPrice instruction is added if the transaction has none
+pub fn override_compute_unit_price(
+    message: &mut VersionedMessage,
+    compute_unit_price_micro_lamports: u64,
+) {
+    let (header, account_keys, instructions) = match message {
+        VersionedMessage::Legacy(message) => (
+            &mut message.header,
+            &mut message.account_keys,
+            &mut message.instructions,
+        ),
+        VersionedMessage::V0(message) => (
+            &mut message.header,
+            &mut message.account_keys,
+            &mut message.instructions,
+        ),
+    };
+
+    let program_id_index = match account_keys
+        .iter()
+        .position(|account_key| *account_key == compute_budget::id())
+    {
+        Some(program_id_index) => program_id_index as u8,
+        None => {
+            // Append the program as a read-only non-signer static account key. Any account
+            // loaded from an address lookup table is indexed after the static account keys, so
+            // those indices move up by one
+            let program_id_index = account_keys.len() as u8;
+            account_keys.push(compute_budget::id());
+            header.num_readonly_unsigned_accounts += 1;
+            for instruction in instructions.iter_mut() {
+                for account_index in instruction.accounts.iter_mut() {
+                    if *account_index >= program_id_index {
+                        *account_index += 1;
+                    }
+                }
+            }
+            program_id_index
+        }
+    };
+
+    let compute_unit_price_data = compute_budget::ComputeBudgetInstruction::set_compute_unit_price(
+        compute_unit_price_micro_lamports,
+    )
+    .data;
+
+    let mut found_compute_unit_limit = false;
+    let mut found_compute_unit_price = false;
+    instructions.retain_mut(|instruction| {
+        if instruction.program_id_index != program_id_index {
+            return true;
+        }
+        match solana_sdk::borsh0_10::try_from_slice_unchecked(&instruction.data) {
+            Ok(compute_budget::ComputeBudgetInstruction::SetComputeUnitLimit(_)) => {
+                !std::mem::replace(&mut found_compute_unit_limit, true)
+            }
+            Ok(compute_budget::ComputeBudgetInstruction::SetComputeUnitPrice(_)) => {
+                if found_compute_unit_price {
+                    false
+                } else {
+                    instruction.data = compute_unit_price_data.clone();
+                    found_compute_unit_price = true;
+                    true
+                }
+            }
+            _ => true,
+        }
+    });
+
+    if !found_compute_unit_price {
+        instructions.insert(
+            0,
+            CompiledInstruction::new_from_raw_parts(
+                program_id_index,
+                compute_unit_price_data,
+                vec![],
+            ),
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use {
+    indicatif::{HumanDuration, ProgressBar, ProgressStyle},
+    std::{sync::Mutex, time::Instant},
+};
+
+lazy_static::lazy_static! {
+    static ref ACTIVE_PROGRESS_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+}
+
+// Prints a line of output without disturbing the active progress bar, if any
+pub fn println(msg: &str) {
+    match ACTIVE_PROGRESS_BAR.lock().unwrap().as_ref() {
+        Some(progress_bar) => progress_bar.println(msg),
+        None => println!("{msg}"),
+    }
+}
+
+// Progress of one phase of a long running command, such as the epochs processed by a sync.
+//
+// A progress bar with an ETA is drawn when stderr is a terminal. Otherwise progress is logged in
+// plain text every 10%
+pub struct Progress {
+    progress_bar: ProgressBar,
+    phase: &'static str,
+    started: Instant,
+}
+
+impl Progress {
+    pub fn new(phase: &'static str, len: u64) -> Self {
+        let progress_bar = ProgressBar::new(len);
+        progress_bar.set_style(
+            ProgressStyle::with_template(
+                "{prefix:>18} [{bar:30}] {pos}/{len} {msg} (elapsed {elapsed}, ETA {eta})",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        );
+        progress_bar.set_prefix(phase);
+
+        if progress_bar.is_hidden() {
+            println!("{phase}: 0/{len}");
+        } else {
+            *ACTIVE_PROGRESS_BAR.lock().unwrap() = Some(progress_bar.clone());
+        }
+
+        Self {
+            progress_bar,
+            phase,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn inc(&self) {
+        let before = self.progress_bar.position();
+        self.progress_bar.inc(1);
+
+        if self.progress_bar.is_hidden() {
+            let len = self.progress_bar.length().unwrap_or_default().max(1);
+            if (before * 10 / len) != ((before + 1) * 10 / len) {
+                println!(
+                    "{}: {}/{len} {}",
+                    self.phase,
+                    before + 1,
+                    self.progress_bar.message()
+                );
+            }
+        }
+    }
+
+    pub fn set_message(&self, msg: String) {
+        self.progress_bar.set_message(msg);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.progress_bar.finish_and_clear();
+        *ACTIVE_PROGRESS_BAR.lock().unwrap() = None;
+
+        println!(
+            "{}: {}/{} in {}",
+            self.phase,
+            self.progress_bar.position(),
+            self.progress_bar.length().unwrap_or_default(),
+            HumanDuration(self.started.elapsed())
+        );
+    }
+}
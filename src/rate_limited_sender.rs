@@ -0,0 +1,165 @@
+use {
+    crate::metrics::prometheus_counter,
+    async_trait::async_trait,
+    solana_client::{
+        client_error::Result as ClientResult,
+        http_sender::HttpSender,
+        rpc_request::RpcRequest,
+        rpc_sender::{RpcSender, RpcTransportStats},
+    },
+    std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+// Token bucket of an endpoint, which holds up to one second of requests
+struct TokenBucket {
+    requests_per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_second: f64) -> Self {
+        Self {
+            requests_per_second,
+            tokens: requests_per_second.max(1.),
+            last_refill: Instant::now(),
+        }
+    }
+
+    // Takes a token, returning how long to wait for it when the bucket is empty. Waiting
+    // requests take theirs in advance so that they are spaced out rather than all woken at once
+    fn take(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        self.tokens = (self.tokens
+            + now.duration_since(self.last_refill).as_secs_f64() * self.requests_per_second)
+            .min(self.requests_per_second.max(1.));
+        self.last_refill = now;
+
+        self.tokens -= 1.;
+        (self.tokens < 0.).then(|| Duration::from_secs_f64(-self.tokens / self.requests_per_second))
+    }
+}
+
+// Requests made through an endpoint so far
+#[derive(Default)]
+pub struct RpcRequestStats {
+    pub requests: AtomicU64,
+    pub retries: AtomicU64,   // requests failed over to another endpoint
+    pub throttled: AtomicU64, // requests held back by the rate limit
+    rate_limited_time: Mutex<Duration>,
+}
+
+impl RpcRequestStats {
+    // Time spent waiting to retry requests the endpoint answered with HTTP 429
+    pub fn rate_limited_time(&self) -> Duration {
+        *self.rate_limited_time.lock().unwrap()
+    }
+}
+
+// Shared between an `RpcClient`, which owns its sender, and `RpcClients`, which sets the rate
+// limit after the client is created and reads the stats
+#[derive(Default)]
+pub struct RpcEndpointState {
+    bucket: Mutex<Option<TokenBucket>>,
+    pub stats: RpcRequestStats,
+}
+
+impl RpcEndpointState {
+    pub fn set_rate_limit(&self, requests_per_second: Option<f64>) {
+        *self.bucket.lock().unwrap() = requests_per_second.map(TokenBucket::new);
+    }
+}
+
+// The endpoint label of metrics, only the host so that API keys in the URL are not exposed
+pub fn endpoint_label(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_else(|| "unknown".into())
+}
+
+// `HttpSender`, which retries HTTP 429 responses itself, behind a client-side rate limit that
+// keeps requests below the limit of public RPC endpoints to begin with
+pub struct RateLimitedSender {
+    sender: HttpSender,
+    endpoint: String,
+    state: Arc<RpcEndpointState>,
+}
+
+impl RateLimitedSender {
+    pub fn new(url: String, state: Arc<RpcEndpointState>) -> Self {
+        Self {
+            endpoint: endpoint_label(&url),
+            sender: HttpSender::new(url),
+            state,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcSender for RateLimitedSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let wait = self
+            .state
+            .bucket
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(TokenBucket::take);
+        if let Some(wait) = wait {
+            self.state.stats.throttled.fetch_add(1, Ordering::Relaxed);
+            prometheus_counter(
+                "sys_rpc_throttled_total",
+                "RPC requests held back by the client-side rate limit",
+                &[("endpoint", &self.endpoint)],
+                1.,
+            );
+            tokio::time::sleep(wait).await;
+        }
+
+        let method = request.to_string();
+        let rate_limited_time = self.sender.get_transport_stats().rate_limited_time;
+        let result = self.sender.send(request, params).await;
+        let rate_limited_time = self
+            .sender
+            .get_transport_stats()
+            .rate_limited_time
+            .saturating_sub(rate_limited_time);
+
+        self.state.stats.requests.fetch_add(1, Ordering::Relaxed);
+        prometheus_counter(
+            "sys_rpc_requests_total",
+            "RPC requests",
+            &[("endpoint", &self.endpoint), ("method", &method)],
+            1.,
+        );
+        if !rate_limited_time.is_zero() {
+            *self.state.stats.rate_limited_time.lock().unwrap() += rate_limited_time;
+            prometheus_counter(
+                "sys_rpc_rate_limited_seconds_total",
+                "Time spent waiting to retry RPC requests answered with HTTP 429",
+                &[("endpoint", &self.endpoint)],
+                rate_limited_time.as_secs_f64(),
+            );
+        }
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.sender.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.sender.url()
+    }
+}
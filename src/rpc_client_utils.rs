@@ -1,21 +1,142 @@
 use {
     chrono::prelude::*,
-    solana_client::{rpc_client::RpcClient, rpc_response::StakeActivationState},
+    solana_client::rpc_response::StakeActivationState,
     solana_sdk::{
         account::Account,
         account_utils::StateMut,
-        clock::Slot,
+        clock::{Slot, UnixTimestamp},
         pubkey::Pubkey,
         signature::Signature,
-        stake::state::{Authorized, StakeStateV2},
+        stake::{
+            self,
+            state::{Authorized, StakeStateV2},
+        },
     },
+    std::{
+        collections::BTreeMap,
+        fs, io,
+        path::{Path, PathBuf},
+        sync::RwLock,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    sys::chain_client::ChainClient,
 };
 
+const BLOCK_DATE_CACHE_FILENAME: &str = "block-dates.json";
+
+// `get_multiple_accounts` accepts at most this many addresses
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+// The time of a block never changes, so times once fetched are kept in the database directory
+// and reused by later runs. Times rather than dates are kept so that the cache remains valid if
+// the local timezone changes
+#[derive(Default)]
+struct BlockDateCache {
+    times: BTreeMap<Slot, UnixTimestamp>,
+    modified: bool,
+}
+
+lazy_static::lazy_static! {
+    static ref BLOCK_DATE_CACHE: RwLock<BlockDateCache> = RwLock::new(BlockDateCache::default());
+}
+
+fn read_block_date_cache(db_path: &Path) -> BTreeMap<Slot, UnixTimestamp> {
+    fs::read(db_path.join(BLOCK_DATE_CACHE_FILENAME))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+// Saves block times fetched while it was held back into the database directory when dropped
+pub struct BlockDateCacheGuard {
+    filename: Option<PathBuf>,
+}
+
+impl Drop for BlockDateCacheGuard {
+    fn drop(&mut self) {
+        let Some(ref filename) = self.filename else {
+            return;
+        };
+        let block_date_cache = BLOCK_DATE_CACHE.read().unwrap();
+        if !block_date_cache.modified {
+            return;
+        }
+
+        let temp_filename = format!(
+            "{}.temp.{}",
+            filename.display(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+        let result = serde_json::to_vec(&block_date_cache.times)
+            .map_err(io::Error::from)
+            .and_then(|bytes| fs::write(&temp_filename, bytes))
+            .and_then(|()| fs::rename(&temp_filename, filename));
+        if let Err(err) = result {
+            eprintln!("Unable to save {}: {err}", filename.display());
+        }
+    }
+}
+
+// Loads the block times cached in `db_path`. Unless `read_only`, the cache is saved back with any
+// times fetched since once the returned guard is dropped
+pub fn load_block_date_cache(db_path: &Path, read_only: bool) -> BlockDateCacheGuard {
+    *BLOCK_DATE_CACHE.write().unwrap() = BlockDateCache {
+        times: read_block_date_cache(db_path),
+        modified: false,
+    };
+    BlockDateCacheGuard {
+        filename: (!read_only).then(|| db_path.join(BLOCK_DATE_CACHE_FILENAME)),
+    }
+}
+
+pub struct BlockDateCacheStats {
+    pub entries: usize,
+    pub slots: Option<(Slot, Slot)>,
+    pub file_size: u64,
+}
+
+pub fn block_date_cache_stats(db_path: &Path) -> BlockDateCacheStats {
+    let times = read_block_date_cache(db_path);
+    BlockDateCacheStats {
+        entries: times.len(),
+        slots: times
+            .first_key_value()
+            .zip(times.last_key_value())
+            .map(|((first_slot, _), (last_slot, _))| (*first_slot, *last_slot)),
+        file_size: fs::metadata(db_path.join(BLOCK_DATE_CACHE_FILENAME))
+            .map(|metadata| metadata.len())
+            .unwrap_or_default(),
+    }
+}
+
+pub fn clear_block_date_cache(db_path: &Path) -> io::Result<()> {
+    *BLOCK_DATE_CACHE.write().unwrap() = BlockDateCache::default();
+    let filename = db_path.join(BLOCK_DATE_CACHE_FILENAME);
+    if filename.exists() {
+        fs::remove_file(filename)?;
+    }
+    Ok(())
+}
+
 pub async fn get_block_date(
-    rpc_client: &RpcClient,
+    rpc_client: &dyn ChainClient,
     slot: Slot,
 ) -> Result<NaiveDate, Box<dyn std::error::Error>> {
-    let block_time = rpc_client.get_block_time(slot)?;
+    let cached_block_time = BLOCK_DATE_CACHE.read().unwrap().times.get(&slot).copied();
+    let block_time = match cached_block_time {
+        Some(block_time) => block_time,
+        None => {
+            let block_time = rpc_client.get_block_time(slot)?;
+            let mut block_date_cache = BLOCK_DATE_CACHE.write().unwrap();
+            block_date_cache.times.insert(slot, block_time);
+            block_date_cache.modified = true;
+            block_time
+        }
+    };
+
     let local_timestamp = Local.timestamp_opt(block_time, 0).unwrap();
     Ok(NaiveDate::from_ymd_opt(
         local_timestamp.year(),
@@ -26,7 +147,7 @@ pub async fn get_block_date(
 }
 
 pub fn get_stake_authorized(
-    rpc_client: &RpcClient,
+    rpc_client: &dyn ChainClient,
     stake_account_address: Pubkey,
 ) -> Result<(Authorized, Pubkey), Box<dyn std::error::Error>> {
     let stake_account = rpc_client.get_account(&stake_account_address)?;
@@ -52,6 +173,58 @@ pub fn get_stake_authorized(
     }
 }
 
+// The vote account each of `addresses` is currently delegated to, `None` for those that are not
+// delegated stake accounts
+pub fn get_stake_voters(
+    rpc_client: &dyn ChainClient,
+    addresses: &[Pubkey],
+) -> Result<Vec<Option<Pubkey>>, Box<dyn std::error::Error>> {
+    let mut voters = vec![];
+    for addresses in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        for account in rpc_client.get_multiple_accounts(addresses)? {
+            voters.push(account.and_then(|account| {
+                if account.owner != stake::program::id() {
+                    return None;
+                }
+                match account.state() {
+                    Ok(StakeStateV2::Stake(_, stake, _)) => Some(stake.delegation.voter_pubkey),
+                    _ => None,
+                }
+            }));
+        }
+    }
+    Ok(voters)
+}
+
+// The withdraw authority of each of `addresses` and the lamports that can be withdrawn from it
+// without touching its delegated stake or rent exempt reserve, such as MEV rewards. `None` for
+// those that are not delegated stake accounts
+pub fn get_stake_excess_balances(
+    rpc_client: &dyn ChainClient,
+    addresses: &[Pubkey],
+) -> Result<Vec<Option<(Pubkey, u64)>>, Box<dyn std::error::Error>> {
+    let mut excess_balances = vec![];
+    for addresses in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        for account in rpc_client.get_multiple_accounts(addresses)? {
+            excess_balances.push(account.and_then(|account| {
+                if account.owner != stake::program::id() {
+                    return None;
+                }
+                match account.state() {
+                    Ok(StakeStateV2::Stake(meta, stake, _)) => Some((
+                        meta.authorized.withdrawer,
+                        account
+                            .lamports
+                            .saturating_sub(stake.delegation.stake + meta.rent_exempt_reserve),
+                    )),
+                    _ => None,
+                }
+            }));
+        }
+    }
+    Ok(excess_balances)
+}
+
 pub fn stake_accounts_have_same_credits_observed(
     stake_account1: &Account,
     stake_account2: &Account,
@@ -88,7 +261,7 @@ pub fn stake_accounts_have_same_credits_observed(
 }
 
 pub async fn get_signature_date(
-    rpc_client: &RpcClient,
+    rpc_client: &dyn ChainClient,
     signature: Signature,
 ) -> Result<NaiveDate, Box<dyn std::error::Error>> {
     let statuses = rpc_client.get_signature_statuses_with_history(&[signature])?;
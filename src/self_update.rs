@@ -0,0 +1,193 @@
+use {
+    reqwest::Client,
+    serde::Deserialize,
+    sha2::{Digest, Sha256},
+    std::{
+        env, fs,
+        path::Path,
+        time::{Duration, SystemTime},
+    },
+    sys::{
+        app_version,
+        http_client::{self, TracedSend},
+    },
+};
+
+const GITHUB_REPO: &str = "mvines/sys";
+const CHECKSUM_SUFFIX: &str = ".sha256";
+
+// How often the passive new version notice queries GitHub
+const VERSION_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+// The target triple of the release binaries built for this platform
+fn release_target() -> Result<&'static str, Box<dyn std::error::Error>> {
+    match (env::consts::ARCH, env::consts::OS) {
+        ("x86_64", "linux") => Ok("x86_64-unknown-linux-gnu"),
+        ("x86_64", "macos") => Ok("x86_64-apple-darwin"),
+        (arch, os) => Err(format!("No release binaries are published for {arch}-{os}").into()),
+    }
+}
+
+// Only binaries built by the release workflow for a tag report a release version, other builds
+// report a commit or `devbuild` and are never considered out of date
+fn is_release_build() -> bool {
+    option_env!("GITHUB_REF").is_some_and(|github_ref| github_ref.starts_with("refs/tags/"))
+}
+
+async fn get_release(
+    client: &Client,
+    tag: Option<&str>,
+) -> Result<Release, Box<dyn std::error::Error>> {
+    let url = match tag {
+        Some(tag) => format!("https://api.github.com/repos/{GITHUB_REPO}/releases/tags/{tag}"),
+        None => format!("https://api.github.com/repos/{GITHUB_REPO}/releases/latest"),
+    };
+    Ok(client
+        .get(url)
+        .header("User-Agent", "sys")
+        .header("Accept", "application/vnd.github+json")
+        .traced_send()
+        .await?
+        .error_for_status()?
+        .json::<Release>()
+        .await?)
+}
+
+async fn download(client: &Client, asset: &Asset) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(client
+        .get(&asset.browser_download_url)
+        .header("User-Agent", "sys")
+        .traced_send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec())
+}
+
+// Checksum files are in `sha256sum` format: the hex digest followed by the file name. They come
+// from the same release as the binary, so this is an integrity check only, not an authenticity one
+fn verify_checksum(bytes: &[u8], checksum_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let expected = checksum_file
+        .split_whitespace()
+        .next()
+        .ok_or("Checksum file is empty")?
+        .to_lowercase();
+    let actual = hex::encode(Sha256::digest(bytes));
+    if actual != expected {
+        return Err(format!("Checksum mismatch: expected {expected}, got {actual}").into());
+    }
+    Ok(())
+}
+
+// Replaces the running executable. The new binary is written alongside it first so that the
+// final rename is atomic and an interrupted update leaves the current executable intact
+fn replace_current_exe(bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let current_exe = env::current_exe()?.canonicalize()?;
+    let new_exe = current_exe.with_file_name(format!(
+        "{}.new.{}",
+        current_exe
+            .file_name()
+            .ok_or("Current executable has no file name")?
+            .to_string_lossy(),
+        std::process::id()
+    ));
+
+    fs::write(&new_exe, bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755))?;
+    }
+    if let Err(err) = fs::rename(&new_exe, &current_exe) {
+        let _ = fs::remove_file(&new_exe);
+        return Err(format!("Failed to replace {}: {err}", current_exe.display()).into());
+    }
+    Ok(())
+}
+
+pub async fn self_update(tag: Option<&str>, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let client = http_client::client();
+    let current_version = app_version();
+
+    let release = get_release(&client, tag).await?;
+    if release.tag_name == current_version && !force {
+        println!("Already up to date: {current_version}");
+        return Ok(());
+    }
+
+    let binary_name = format!("sys-{}", release_target()?);
+    let checksum_name = format!("{binary_name}{CHECKSUM_SUFFIX}");
+    let binary = release
+        .asset(&binary_name)
+        .ok_or_else(|| format!("Release {} has no {binary_name}", release.tag_name))?;
+    let checksum = release.asset(&checksum_name).ok_or_else(|| {
+        format!(
+            "Release {} has no {checksum_name}, unable to verify the download",
+            release.tag_name
+        )
+    })?;
+
+    println!("Downloading {} {binary_name}", release.tag_name);
+    let bytes = download(&client, binary).await?;
+    let checksum_file = String::from_utf8(download(&client, checksum).await?)?;
+    verify_checksum(&bytes, &checksum_file)?;
+
+    replace_current_exe(&bytes)?;
+    println!("Updated from {current_version} to {}", release.tag_name);
+    Ok(())
+}
+
+// Prints a notice when a newer release is available. Runs at most once per
+// `VERSION_CHECK_INTERVAL`, tracked by the modification time of a marker file in `db_path`, and
+// never fails the command it runs alongside
+pub async fn notify_if_outdated(db_path: &Path) {
+    if !is_release_build() || env::var("SYS_NO_UPDATE_CHECK").is_ok() {
+        return;
+    }
+    let current_version = app_version();
+
+    let marker = db_path.join(".version-check");
+    let checked_recently = fs::metadata(&marker)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|elapsed| elapsed < VERSION_CHECK_INTERVAL);
+    if checked_recently {
+        return;
+    }
+    let _ = fs::write(&marker, "");
+
+    let client = http_client::client();
+    let Ok(Ok(release)) =
+        tokio::time::timeout(VERSION_CHECK_TIMEOUT, get_release(&client, None)).await
+    else {
+        return;
+    };
+    if release.tag_name != current_version {
+        eprintln!(
+            "A new version of sys is available: {} (current: {current_version}), \
+             run `sys self-update` to install it",
+            release.tag_name
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use {
-    crate::{db::*, rpc_client_utils::get_signature_date},
+    crate::{
+        db::*, rpc_client_utils::get_signature_date, transaction_cost::println_transaction_cost,
+    },
     log::*,
     solana_client::{rpc_client::RpcClient, rpc_config::RpcBlockConfig, rpc_custom_error},
     solana_sdk::{
@@ -7,7 +9,7 @@ use {
         account::from_account,
         clock::{Clock, Epoch},
         epoch_info::EpochInfo,
-        message::Message,
+        message::{Message, VersionedMessage},
         pubkey::Pubkey,
         reward_type::RewardType,
         signers::Signers,
@@ -157,6 +159,7 @@ pub async fn run<T: Signers>(
     authority_address: Pubkey,
     signers: T,
     notifier: &Notifier,
+    verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let rpc_client = rpc_clients.default();
     let epoch_info = rpc_client.get_epoch_info()?;
@@ -352,7 +355,7 @@ pub async fn run<T: Signers>(
             merged_into_stake_account_adddresses.insert(into_address);
 
             let (recent_blockhash, last_valid_block_height) =
-                rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+                rpc_clients.get_latest_blockhash_with_commitment()?;
             let authority_account = rpc_client
                 .get_account_with_commitment(&authority_address, rpc_client.commitment())?
                 .value
@@ -379,6 +382,14 @@ pub async fn run<T: Signers>(
                 continue;
             }
 
+            if verbose {
+                println_transaction_cost(
+                    rpc_client,
+                    db,
+                    &VersionedMessage::Legacy(transaction.message.clone()),
+                )?;
+            }
+
             transaction.try_sign(&signers, recent_blockhash)?;
             let signature = transaction.signatures[0];
             println!("Transaction signature: {signature}");
@@ -395,10 +406,13 @@ pub async fn run<T: Signers>(
                 None,
             )?;
 
-            if !send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height)
-                .unwrap_or_default()
-            {
-                db.cancel_transfer(signature)?;
+            let status =
+                send_transaction_until_expired(rpc_clients, &transaction, last_valid_block_height);
+            db.record_sent_transaction(signature)?;
+            if !status.confirmed() {
+                if status.abandoned() {
+                    db.cancel_transfer(signature)?;
+                }
                 eprintln!("Merge failed");
             } else {
                 let when = get_signature_date(rpc_client, signature).await?;
@@ -572,7 +586,7 @@ pub async fn run<T: Signers>(
                 println!("Delegate {stake_account_address} to {vote_account_address}");
 
                 let (recent_blockhash, last_valid_block_height) =
-                    rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())?;
+                    rpc_clients.get_latest_blockhash_with_commitment()?;
 
                 let mut message = Message::new(
                     &[solana_sdk::stake::instruction::delegate_stake(
@@ -592,17 +606,25 @@ pub async fn run<T: Signers>(
                     continue;
                 }
 
+                if verbose {
+                    println_transaction_cost(
+                        rpc_client,
+                        db,
+                        &VersionedMessage::Legacy(transaction.message.clone()),
+                    )?;
+                }
+
                 transaction.try_sign(&signers, recent_blockhash)?;
                 let signature = transaction.signatures[0];
                 println!("Transaction signature: {signature}");
 
-                if !send_transaction_until_expired(
+                let status = send_transaction_until_expired(
                     rpc_clients,
                     &transaction,
                     last_valid_block_height,
-                )
-                .unwrap_or_default()
-                {
+                );
+                db.record_sent_transaction(signature)?;
+                if !status.confirmed() {
                     eprintln!("Delegation failed");
                     transaction_failures += 1;
                 }
@@ -628,7 +650,9 @@ pub async fn run<T: Signers>(
 
     if transaction_failures > 0 {
         let msg = format!("stake spreader: {transaction_failures} transactions failed");
-        notifier.send(&msg).await;
+        notifier
+            .send(NotificationKind::Stake, Severity::Alert, &msg)
+            .await;
         println!("{msg}");
     }
 
@@ -0,0 +1,181 @@
+use {
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::{Memcmp, RpcFilterType},
+    },
+    solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        pubkey,
+        pubkey::Pubkey,
+    },
+    sys::chain_client::ChainClient,
+};
+
+const STREAMFLOW_PROGRAM_ID: Pubkey = pubkey!("strmRqUCoQUgGUan5YhzUZa6KqdzwX5L6FpUxfmKg5m");
+
+// Offset of `recipient` in the stream metadata account
+const RECIPIENT_OFFSET: usize = 113;
+
+// A Streamflow token stream, which unlocks `cliff_amount` at `cliff` and then `amount_per_period`
+// every `period` seconds until `net_amount_deposited` has unlocked
+pub struct Stream {
+    pub address: Pubkey,
+    pub name: String,
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub withdrawn_amount: u64,
+    pub net_amount_deposited: u64,
+    canceled_at: u64,
+    start_time: u64,
+    period: u64,
+    amount_per_period: u64,
+    cliff: u64,
+    cliff_amount: u64,
+    recipient_tokens: Pubkey,
+    escrow_tokens: Pubkey,
+    streamflow_treasury: Pubkey,
+    streamflow_treasury_tokens: Pubkey,
+    partner: Pubkey,
+    partner_tokens: Pubkey,
+}
+
+impl Stream {
+    // The amount unlocked as of the unix timestamp `now`. A cancelled stream pays out what had
+    // unlocked when it was cancelled, so nothing more unlocks
+    pub fn unlocked_amount(&self, now: u64) -> u64 {
+        if self.canceled_at != 0 {
+            return self.withdrawn_amount;
+        }
+        let cliff = if self.cliff == 0 {
+            self.start_time
+        } else {
+            self.cliff
+        };
+        if now < cliff {
+            return 0;
+        }
+        let periods = (now - cliff).checked_div(self.period).unwrap_or_default();
+        self.cliff_amount
+            .saturating_add(periods.saturating_mul(self.amount_per_period))
+            .min(self.net_amount_deposited)
+    }
+
+    // Unlocked but not yet withdrawn by the recipient
+    pub fn claimable_amount(&self, now: u64) -> u64 {
+        self.unlocked_amount(now)
+            .saturating_sub(self.withdrawn_amount)
+    }
+
+    // Withdraws `amount` of the unlocked tokens into the token account of the recipient, which
+    // must sign
+    pub fn withdraw_instruction(&self, amount: u64, token_program_id: Pubkey) -> Instruction {
+        // Anchor discriminator of the `withdraw` instruction, the first 8 bytes of
+        // sha256("global:withdraw")
+        let mut data = vec![183, 18, 70, 156, 148, 109, 161, 34];
+        data.extend(amount.to_le_bytes());
+
+        Instruction::new_with_bytes(
+            STREAMFLOW_PROGRAM_ID,
+            &data,
+            vec![
+                AccountMeta::new(self.recipient, true),
+                AccountMeta::new(self.recipient, false),
+                AccountMeta::new(self.recipient_tokens, false),
+                AccountMeta::new(self.address, false),
+                AccountMeta::new(self.escrow_tokens, false),
+                AccountMeta::new(self.streamflow_treasury, false),
+                AccountMeta::new(self.streamflow_treasury_tokens, false),
+                AccountMeta::new(self.partner, false),
+                AccountMeta::new(self.partner_tokens, false),
+                AccountMeta::new(self.mint, false),
+                AccountMeta::new_readonly(token_program_id, false),
+            ],
+        )
+    }
+}
+
+pub fn get_stream(
+    rpc_client: &dyn ChainClient,
+    address: Pubkey,
+) -> Result<Stream, Box<dyn std::error::Error>> {
+    let account = rpc_client.get_account(&address)?;
+    if account.owner != STREAMFLOW_PROGRAM_ID {
+        return Err(format!("{address} is not a Streamflow stream").into());
+    }
+    parse_stream(address, &account.data)
+        .ok_or_else(|| format!("Invalid Streamflow stream: {address}").into())
+}
+
+// Streams paying out to `recipient`. Found with `getProgramAccounts`, which some RPC endpoints
+// do not serve
+pub fn get_incoming_streams(
+    rpc_client: &dyn ChainClient,
+    recipient: Pubkey,
+) -> Result<Vec<Stream>, Box<dyn std::error::Error>> {
+    Ok(rpc_client
+        .get_program_accounts_with_config(
+            &STREAMFLOW_PROGRAM_ID,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                    RECIPIENT_OFFSET,
+                    recipient.to_bytes().to_vec(),
+                ))]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )?
+        .into_iter()
+        .filter_map(|(address, account)| parse_stream(address, &account.data))
+        .collect())
+}
+
+// The stream metadata account is laid out as:
+//   magic: u64, version: u8, created_at: u64, amount_withdrawn: u64, canceled_at: u64,
+//   end_time: u64, last_withdrawn_at: u64, sender, sender_tokens, recipient, recipient_tokens,
+//   mint, escrow_tokens, streamflow_treasury, streamflow_treasury_tokens: Pubkey,
+//   streamflow_fee_total: u64, streamflow_fee_withdrawn: u64, streamflow_fee_percent: f32,
+//   partner, partner_tokens: Pubkey, partner_fee_total: u64, partner_fee_withdrawn: u64,
+//   partner_fee_percent: f32,
+// followed by the creation parameters:
+//   start_time: u64, net_amount_deposited: u64, period: u64, amount_per_period: u64,
+//   cliff: u64, cliff_amount: u64, six bool flags, stream_name: [u8; 64], ...
+fn parse_stream(address: Pubkey, data: &[u8]) -> Option<Stream> {
+    let u64_at = |offset: usize| {
+        data.get(offset..offset + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let pubkey_at = |offset: usize| {
+        data.get(offset..offset + 32)
+            .map(|bytes| Pubkey::try_from(bytes).unwrap())
+    };
+
+    let name = data.get(463..527)?;
+    Some(Stream {
+        address,
+        name: String::from_utf8_lossy(name)
+            .trim_end_matches('\0')
+            .to_string(),
+        withdrawn_amount: u64_at(17)?,
+        canceled_at: u64_at(25)?,
+        sender: pubkey_at(49)?,
+        recipient: pubkey_at(RECIPIENT_OFFSET)?,
+        recipient_tokens: pubkey_at(145)?,
+        mint: pubkey_at(177)?,
+        escrow_tokens: pubkey_at(209)?,
+        streamflow_treasury: pubkey_at(241)?,
+        streamflow_treasury_tokens: pubkey_at(273)?,
+        partner: pubkey_at(325)?,
+        partner_tokens: pubkey_at(357)?,
+        start_time: u64_at(409)?,
+        net_amount_deposited: u64_at(417)?,
+        period: u64_at(425)?,
+        amount_per_period: u64_at(433)?,
+        cliff: u64_at(441)?,
+        cliff_amount: u64_at(449)?,
+    })
+}
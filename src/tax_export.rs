@@ -0,0 +1,449 @@
+use {
+    crate::{all_lots, csv_field, db::*},
+    chrono::{Datelike, NaiveDate},
+    rust_decimal::prelude::*,
+    std::{
+        collections::{BTreeMap, HashMap},
+        fs,
+        path::Path,
+    },
+    strum::{EnumString, IntoStaticStr},
+    sys::token::*,
+};
+
+pub const POSSIBLE_EXPORT_FORMAT_VALUES: &[&str] = &["turbotax", "koinly", "cointracker"];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, IntoStaticStr)]
+pub enum ExportFormat {
+    #[strum(serialize = "turbotax")]
+    TurboTax,
+    #[strum(serialize = "koinly")]
+    Koinly,
+    #[strum(serialize = "cointracker")]
+    CoinTracker,
+}
+
+// Options that keep the exported rows usable without cleaning them up in a spreadsheet
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    pub precision: u32,                   // decimal places of USD values
+    pub min_value: Option<Decimal>,       // rows of a lesser USD value are left out
+    pub aggregate_below: Option<Decimal>, // income lots of a lesser USD value are merged monthly
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            precision: 2,
+            min_value: None,
+            aggregate_below: None,
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn round(&self, value: Decimal) -> Decimal {
+        value.round_dp(self.precision)
+    }
+
+    pub fn is_below_min_value(&self, value: Decimal) -> bool {
+        self.min_value
+            .is_some_and(|min_value| value.abs() < min_value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IncomeKind {
+    Staking,
+    Reward,
+    Airdrop,
+    Other,
+}
+
+// A token amount, or a USD amount when `token` is `None`
+type ExportedAmount = (Option<MaybeToken>, Decimal);
+
+// A transaction in the layout shared by Koinly and CoinTracker: something sent, something
+// received, or both for a trade
+struct ExportedTransaction {
+    when: NaiveDate,
+    sent: Option<ExportedAmount>,
+    received: Option<ExportedAmount>,
+    fee_usd: Option<Decimal>,
+    usd_value: Decimal,
+    income: Option<IncomeKind>,
+    cost: bool,
+    description: String,
+}
+
+fn currency(token: Option<MaybeToken>) -> &'static str {
+    token.map(|token| token.name()).unwrap_or("USD")
+}
+
+// USD values are exported to the cent unless another precision is chosen
+fn usd(value: Decimal, options: &ExportOptions) -> String {
+    format!("{:.*}", options.precision as usize, options.round(value))
+}
+
+fn exported_amount((token, amount): ExportedAmount, options: &ExportOptions) -> String {
+    match token {
+        Some(_) => amount.normalize().to_string(),
+        None => usd(amount, options),
+    }
+}
+
+// Merge the income transactions of a USD value below `threshold` into one per token, kind of
+// income and month, dated the last of the merged transactions
+fn aggregate_income(
+    transactions: Vec<ExportedTransaction>,
+    threshold: Decimal,
+) -> Vec<ExportedTransaction> {
+    let mut aggregated =
+        BTreeMap::<(MaybeToken, i32, u32, u8), (ExportedTransaction, usize)>::new();
+    let mut result = vec![];
+    for transaction in transactions {
+        let (Some(income), Some((Some(token), amount)), None) =
+            (transaction.income, transaction.received, transaction.sent)
+        else {
+            result.push(transaction);
+            continue;
+        };
+        if transaction.usd_value >= threshold {
+            result.push(transaction);
+            continue;
+        }
+
+        let key = (
+            token,
+            transaction.when.year(),
+            transaction.when.month(),
+            income as u8,
+        );
+        match aggregated.get_mut(&key) {
+            Some((aggregate, count)) => {
+                aggregate.when = aggregate.when.max(transaction.when);
+                aggregate.received = Some((Some(token), aggregate.received.unwrap().1 + amount));
+                aggregate.usd_value += transaction.usd_value;
+                *count += 1;
+            }
+            None => {
+                aggregated.insert(key, (transaction, 1));
+            }
+        }
+    }
+
+    for (mut aggregate, count) in aggregated.into_values() {
+        if count > 1 {
+            aggregate.description = format!("{count} lots of less than ${threshold} each");
+        }
+        result.push(aggregate);
+    }
+    result.sort_by_key(|transaction| transaction.when);
+    result
+}
+
+// Acquisitions, aggregated across the pieces each lot was since split into, and disposals.
+// Swaps are exported once, as the trade of the disposal, and the lots they acquired are skipped.
+// Transfers between tracked accounts preserve lots and are not taxable so are not exported
+fn exported_transactions(
+    db: &Db,
+    fees_usd: &HashMap<(usize, NaiveDate), Decimal>,
+) -> Vec<ExportedTransaction> {
+    let mut lots = BTreeMap::<(usize, MaybeToken), (Lot, u64)>::new();
+    for (token, lot) in all_lots(db) {
+        lots.entry((lot.lot_number, token))
+            .and_modify(|(_, amount)| *amount += lot.amount)
+            .or_insert_with(|| {
+                let amount = lot.amount;
+                (lot, amount)
+            });
+    }
+
+    let mut transactions = vec![];
+    for ((_, token), (mut lot, amount)) in lots {
+        lot.amount = amount;
+        let ui_amount = token.ui_amount_decimal(amount);
+        let basis = lot.basis(token);
+        let description = lot.acquisition.kind.to_string();
+
+        let (sent, income) = match lot.acquisition.kind {
+            LotAcquistionKind::Swap { .. } => continue,
+            LotAcquistionKind::EpochReward { .. } | LotAcquistionKind::MevReward { .. } => {
+                (None, Some(IncomeKind::Staking))
+            }
+            LotAcquistionKind::ExchangeReward { .. } => (None, Some(IncomeKind::Reward)),
+            LotAcquistionKind::Vesting { .. } => (None, Some(IncomeKind::Airdrop)),
+            LotAcquistionKind::StreamPayment { .. } | LotAcquistionKind::NotAvailable => {
+                (None, Some(IncomeKind::Other))
+            }
+            LotAcquistionKind::Exchange { .. } | LotAcquistionKind::Fiat => {
+                (Some((None, basis)), None)
+            }
+            // Deposits from untracked wallets, the basis carries over
+            LotAcquistionKind::Transaction { .. } => (None, None),
+        };
+        transactions.push(ExportedTransaction {
+            when: lot.acquisition.when,
+            sent,
+            received: Some((Some(token), ui_amount)),
+            fee_usd: None,
+            usd_value: basis,
+            income,
+            cost: false,
+            description,
+        });
+    }
+
+    for disposed_lot in db.disposed_lots() {
+        let token = disposed_lot.token;
+        let ui_amount = token.ui_amount_decimal(disposed_lot.lot.amount);
+        let proceeds = disposed_lot.price() * ui_amount;
+
+        let (received, cost) = match disposed_lot.kind {
+            LotDisposalKind::Swap {
+                token: to_token,
+                amount: Some(to_amount),
+                ..
+            } => (
+                Some((Some(to_token), to_token.ui_amount_decimal(to_amount))),
+                false,
+            ),
+            LotDisposalKind::Swap { amount: None, .. } => (None, false),
+            LotDisposalKind::WithdrawalFee { .. } => (None, true),
+            LotDisposalKind::Usd { .. } | LotDisposalKind::Fiat | LotDisposalKind::Other { .. } => {
+                (Some((None, proceeds)), false)
+            }
+        };
+        transactions.push(ExportedTransaction {
+            when: disposed_lot.when,
+            sent: Some((Some(token), ui_amount)),
+            received,
+            fee_usd: fees_usd
+                .get(&(disposed_lot.lot.lot_number, disposed_lot.when))
+                .copied(),
+            usd_value: proceeds,
+            income: None,
+            cost,
+            description: disposed_lot.kind.to_string(),
+        });
+    }
+
+    transactions.sort_by_key(|transaction| transaction.when);
+    transactions
+}
+
+fn koinly_rows(
+    transactions: Vec<ExportedTransaction>,
+    options: &ExportOptions,
+) -> Vec<Vec<String>> {
+    let mut rows = vec![[
+        "Date",
+        "Sent Amount",
+        "Sent Currency",
+        "Received Amount",
+        "Received Currency",
+        "Fee Amount",
+        "Fee Currency",
+        "Net Worth Amount",
+        "Net Worth Currency",
+        "Label",
+        "Description",
+        "TxHash",
+    ]
+    .map(String::from)
+    .to_vec()];
+
+    for transaction in transactions {
+        let label = match (transaction.income, transaction.cost) {
+            (Some(IncomeKind::Staking), _) => "staking",
+            (Some(IncomeKind::Reward), _) => "reward",
+            (Some(IncomeKind::Airdrop), _) => "airdrop",
+            (Some(IncomeKind::Other), _) => "other income",
+            (None, true) => "cost",
+            (None, false) => "",
+        };
+        rows.push(vec![
+            format!("{} 00:00 UTC", transaction.when),
+            transaction
+                .sent
+                .map(|amount| exported_amount(amount, options))
+                .unwrap_or_default(),
+            transaction
+                .sent
+                .map(|(token, _)| currency(token).to_string())
+                .unwrap_or_default(),
+            transaction
+                .received
+                .map(|amount| exported_amount(amount, options))
+                .unwrap_or_default(),
+            transaction
+                .received
+                .map(|(token, _)| currency(token).to_string())
+                .unwrap_or_default(),
+            transaction
+                .fee_usd
+                .map(|fee_usd| usd(fee_usd, options))
+                .unwrap_or_default(),
+            transaction
+                .fee_usd
+                .map(|_| "USD".to_string())
+                .unwrap_or_default(),
+            usd(transaction.usd_value, options),
+            "USD".to_string(),
+            label.to_string(),
+            transaction.description,
+            String::new(),
+        ]);
+    }
+    rows
+}
+
+fn cointracker_rows(
+    transactions: Vec<ExportedTransaction>,
+    options: &ExportOptions,
+) -> Vec<Vec<String>> {
+    let mut rows = vec![[
+        "Date",
+        "Received Quantity",
+        "Received Currency",
+        "Sent Quantity",
+        "Sent Currency",
+        "Fee Amount",
+        "Fee Currency",
+        "Tag",
+    ]
+    .map(String::from)
+    .to_vec()];
+
+    for transaction in transactions {
+        // CoinTracker records a cost as a transaction of only a fee
+        let (sent, fee) = if transaction.cost {
+            (None, transaction.sent)
+        } else {
+            (
+                transaction.sent,
+                transaction.fee_usd.map(|fee_usd| (None, fee_usd)),
+            )
+        };
+        let tag = match transaction.income {
+            Some(IncomeKind::Staking | IncomeKind::Reward) => "staked",
+            Some(IncomeKind::Airdrop) => "airdrop",
+            Some(IncomeKind::Other) => "payment",
+            None => "",
+        };
+        rows.push(vec![
+            format!(
+                "{:02}/{:02}/{} 00:00:00",
+                transaction.when.month(),
+                transaction.when.day(),
+                transaction.when.year()
+            ),
+            transaction
+                .received
+                .map(|amount| exported_amount(amount, options))
+                .unwrap_or_default(),
+            transaction
+                .received
+                .map(|(token, _)| currency(token).to_string())
+                .unwrap_or_default(),
+            sent.map(|amount| exported_amount(amount, options))
+                .unwrap_or_default(),
+            sent.map(|(token, _)| currency(token).to_string())
+                .unwrap_or_default(),
+            fee.map(|amount| exported_amount(amount, options))
+                .unwrap_or_default(),
+            fee.map(|(token, _)| currency(token).to_string())
+                .unwrap_or_default(),
+            tag.to_string(),
+        ]);
+    }
+    rows
+}
+
+// One row per disposed lot, the layout of TurboTax's gains and losses import
+fn turbotax_rows(
+    db: &Db,
+    filter_by_year: Option<i32>,
+    options: &ExportOptions,
+) -> Vec<Vec<String>> {
+    let mut rows = vec![[
+        "Amount",
+        "Currency Name",
+        "Purchase Date",
+        "Date Sold",
+        "Cost Basis",
+        "Proceeds",
+    ]
+    .map(String::from)
+    .to_vec()];
+
+    let mut disposed_lots = db.disposed_lots();
+    disposed_lots.sort_by_key(|disposed_lot| disposed_lot.when);
+    for disposed_lot in disposed_lots {
+        if filter_by_year.is_some_and(|year| disposed_lot.when.year() != year) {
+            continue;
+        }
+        let token = disposed_lot.token;
+        let ui_amount = token.ui_amount_decimal(disposed_lot.lot.amount);
+        let basis = disposed_lot.lot.basis(token);
+        let proceeds = disposed_lot.price() * ui_amount;
+        if options.is_below_min_value(basis) && options.is_below_min_value(proceeds) {
+            continue;
+        }
+        rows.push(vec![
+            ui_amount.to_string(),
+            token.name().to_string(),
+            disposed_lot
+                .lot
+                .acquisition
+                .when
+                .format("%m/%d/%Y")
+                .to_string(),
+            disposed_lot.when.format("%m/%d/%Y").to_string(),
+            usd(basis, options),
+            usd(proceeds, options),
+        ]);
+    }
+    rows
+}
+
+pub fn export(
+    db: &Db,
+    format: ExportFormat,
+    outfile: &Path,
+    filter_by_year: Option<i32>,
+    fees_usd: &HashMap<(usize, NaiveDate), Decimal>, // by disposed lot number and date
+    options: &ExportOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rows = match format {
+        ExportFormat::TurboTax => turbotax_rows(db, filter_by_year, options),
+        ExportFormat::Koinly | ExportFormat::CoinTracker => {
+            let mut transactions = exported_transactions(db, fees_usd);
+            if let Some(year) = filter_by_year {
+                transactions.retain(|transaction| transaction.when.year() == year);
+            }
+            if let Some(aggregate_below) = options.aggregate_below {
+                transactions = aggregate_income(transactions, aggregate_below);
+            }
+            transactions.retain(|transaction| !options.is_below_min_value(transaction.usd_value));
+            if format == ExportFormat::Koinly {
+                koinly_rows(transactions, options)
+            } else {
+                cointracker_rows(transactions, options)
+            }
+        }
+    };
+
+    let mut csv = String::new();
+    for row in &rows {
+        csv += &row
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv += "\n";
+    }
+    fs::write(outfile, csv)?;
+    println!("Wrote {} rows to {}", rows.len() - 1, outfile.display());
+    Ok(())
+}
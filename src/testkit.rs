@@ -0,0 +1,749 @@
+// Deterministic stand-ins for the exchanges and the Solana RPC endpoint, for exercising sync and
+// order handling without network access.
+//
+// `MockExchangeClient` serves a scripted `MockExchangeState` and records the orders and
+// withdrawals requested of it, so a scenario can be advanced step by step: fill an order,
+// complete a withdrawal, then sync again. RPC responses are scripted with `MockRpc`, built on the
+// mock sender of the Solana RPC client, or served by a `LocalLedger` for code that takes a
+// `ChainClient`
+use {
+    crate::{chain_client::ChainClient, exchange::*, token::MaybeToken},
+    async_trait::async_trait,
+    chrono::{Local, NaiveDate},
+    serde_json::{json, Value},
+    solana_account_decoder::parse_token::{token_amount_to_ui_amount, UiTokenAmount},
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+        mock_sender::Mocks,
+        rpc_client::RpcClient,
+        rpc_config::RpcProgramAccountsConfig,
+        rpc_request::RpcRequest,
+        rpc_response::{
+            Response, RpcInflationReward, RpcPrioritizationFee, RpcResponseContext, RpcResult,
+            RpcStakeActivation,
+        },
+    },
+    solana_sdk::{
+        account::{Account, AccountSharedData},
+        clock::{Epoch, Slot, UnixTimestamp},
+        commitment_config::CommitmentConfig,
+        epoch_info::EpochInfo,
+        hash::Hash,
+        message::VersionedMessage,
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::Signature,
+        transaction::VersionedTransaction,
+    },
+    solana_transaction_status::{
+        EncodedConfirmedTransactionWithStatusMeta, TransactionConfirmationStatus,
+        TransactionStatus, UiTransactionEncoding,
+    },
+    std::{
+        collections::HashMap,
+        sync::{Mutex, MutexGuard},
+    },
+    tokio::sync::mpsc::UnboundedReceiver,
+};
+
+#[derive(Debug, Default)]
+pub struct MockExchangeState {
+    pub deposit_addresses: HashMap<MaybeToken, Pubkey>,
+    pub deposit_memos: HashMap<MaybeToken, String>,
+    pub deposits: Vec<DepositInfo>,
+    pub withdrawals: Vec<WithdrawalInfo>,
+    pub rewards: Vec<RewardInfo>,
+    pub withdrawal_limits: HashMap<MaybeToken, WithdrawalLimit>,
+    pub withdrawal_fee: f64,
+    pub balances: HashMap<String, ExchangeBalance>,
+    pub bid_asks: HashMap<String, BidAsk>,
+    pub orders: HashMap<OrderId, (/* pair: */ String, OrderStatus)>,
+    pub cancelled_orders: Vec<OrderId>,
+    next_id: usize,
+}
+
+impl MockExchangeState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("mock-{prefix}-{}", self.next_id)
+    }
+
+    pub fn add_deposit(&mut self, tx_id: &str, token: MaybeToken, amount: f64) {
+        self.deposits.push(DepositInfo {
+            tx_id: tx_id.into(),
+            coin: token.to_string(),
+            amount,
+            memo: None,
+            when: None,
+        });
+    }
+
+    // A deposit to a shared deposit address, which the exchange may report without its
+    // transaction signature
+    pub fn add_deposit_with_memo(
+        &mut self,
+        tx_id: &str,
+        token: MaybeToken,
+        amount: f64,
+        memo: &str,
+    ) {
+        self.deposits.push(DepositInfo {
+            tx_id: tx_id.into(),
+            coin: token.to_string(),
+            amount,
+            memo: Some(memo.into()),
+            when: None,
+        });
+    }
+
+    pub fn set_bid_ask(&mut self, pair: &str, bid_price: f64, ask_price: f64) {
+        self.bid_asks.insert(
+            pair.into(),
+            BidAsk {
+                bid_price,
+                ask_price,
+            },
+        );
+    }
+
+    // Fills `order_id` completely at its price
+    pub fn fill_order(&mut self, order_id: &str, fee: Option<(f64, String)>) {
+        let (_, order_status) = self.orders.get_mut(order_id).expect("unknown order");
+        order_status.open = false;
+        order_status.filled_amount = order_status.amount;
+        order_status.last_update = Local::now().date_naive();
+        order_status.fee = fee;
+    }
+
+    // Completes the withdrawal tagged `tag`, a `tx_id` of `None` cancels it
+    pub fn complete_withdrawal(&mut self, tag: &str, tx_id: Option<&str>) {
+        let withdrawal = self
+            .withdrawals
+            .iter_mut()
+            .find(|withdrawal| withdrawal.tag == tag)
+            .expect("unknown withdrawal");
+        withdrawal.completed = true;
+        withdrawal.tx_id = tx_id.map(|tx_id| tx_id.into());
+    }
+}
+
+#[derive(Default)]
+pub struct MockExchangeClient {
+    state: Mutex<MockExchangeState>,
+}
+
+impl MockExchangeClient {
+    pub fn new(state: MockExchangeState) -> Self {
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    // The scripted state, to inspect what was requested or to advance the scenario
+    pub fn state(&self) -> MutexGuard<'_, MockExchangeState> {
+        self.state.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl ExchangeClient for MockExchangeClient {
+    async fn deposit_address(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Pubkey, Box<dyn std::error::Error>> {
+        self.state()
+            .deposit_addresses
+            .get(&token)
+            .copied()
+            .ok_or_else(|| format!("No mock deposit address for {token}").into())
+    }
+
+    async fn deposit_memo(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(self.state().deposit_memos.get(&token).cloned())
+    }
+
+    async fn recent_deposits(
+        &self,
+    ) -> Result<Option<Vec<DepositInfo>>, Box<dyn std::error::Error>> {
+        Ok(Some(self.state().deposits.clone()))
+    }
+
+    async fn recent_withdrawals(&self) -> Result<Vec<WithdrawalInfo>, Box<dyn std::error::Error>> {
+        Ok(self.state().withdrawals.clone())
+    }
+
+    async fn recent_rewards(&self) -> Result<Vec<RewardInfo>, Box<dyn std::error::Error>> {
+        Ok(self.state().rewards.clone())
+    }
+
+    async fn withdrawal_limit(
+        &self,
+        token: MaybeToken,
+    ) -> Result<Option<WithdrawalLimit>, Box<dyn std::error::Error>> {
+        Ok(self.state().withdrawal_limits.get(&token).copied())
+    }
+
+    async fn request_withdraw(
+        &self,
+        address: Pubkey,
+        token: MaybeToken,
+        amount: f64,
+        _withdrawal_password: Option<String>,
+        _withdrawal_code: Option<String>,
+    ) -> Result<(/* withdraw_id: */ String, /*withdraw_fee: */ f64), Box<dyn std::error::Error>>
+    {
+        let mut state = self.state();
+        let tag = state.next_id("withdrawal");
+        state.withdrawals.push(WithdrawalInfo {
+            address,
+            token,
+            amount,
+            tag: tag.clone(),
+            completed: false,
+            tx_id: None,
+        });
+        Ok((tag, state.withdrawal_fee))
+    }
+
+    async fn balances(
+        &self,
+    ) -> Result<HashMap<String, ExchangeBalance>, Box<dyn std::error::Error>> {
+        Ok(self.state().balances.clone())
+    }
+
+    async fn print_market_info(
+        &self,
+        pair: &str,
+        _format: MarketInfoFormat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let bid_ask = self.bid_ask(pair).await?;
+        println!(
+            "{pair} | Ask: ${}, Bid: ${}",
+            bid_ask.ask_price, bid_ask.bid_price
+        );
+        Ok(())
+    }
+
+    async fn bid_ask(&self, pair: &str) -> Result<BidAsk, Box<dyn std::error::Error>> {
+        self.state()
+            .bid_asks
+            .get(pair)
+            .cloned()
+            .ok_or_else(|| format!("No mock bid/ask for {pair}").into())
+    }
+
+    async fn order_book(&self, pair: &str) -> Result<OrderBook, Box<dyn std::error::Error>> {
+        // A book deep enough for any order at the scripted bid and ask
+        let bid_ask = self.bid_ask(pair).await?;
+        Ok(OrderBook {
+            bids: vec![(bid_ask.bid_price, f64::MAX)],
+            asks: vec![(bid_ask.ask_price, f64::MAX)],
+        })
+    }
+
+    async fn place_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        price: f64,
+        amount: f64,
+        _options: OrderOptions,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        let mut state = self.state();
+        let order_id = state.next_id("order");
+        state.orders.insert(
+            order_id.clone(),
+            (
+                pair.into(),
+                OrderStatus {
+                    open: true,
+                    side,
+                    price,
+                    amount,
+                    filled_amount: 0.,
+                    last_update: Local::now().date_naive(),
+                    fee: None,
+                },
+            ),
+        );
+        Ok(order_id)
+    }
+
+    async fn place_market_order(
+        &self,
+        pair: &str,
+        side: OrderSide,
+        amount: f64,
+    ) -> Result<OrderId, Box<dyn std::error::Error>> {
+        let bid_ask = self.bid_ask(pair).await?;
+        let price = match side {
+            OrderSide::Buy => bid_ask.ask_price,
+            OrderSide::Sell => bid_ask.bid_price,
+        };
+        let order_id = self
+            .place_order(pair, side, price, amount, OrderOptions::default())
+            .await?;
+        self.state().fill_order(&order_id, None);
+        Ok(order_id)
+    }
+
+    async fn cancel_order(
+        &self,
+        _pair: &str,
+        order_id: &OrderId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state();
+        let (_, order_status) = state
+            .orders
+            .get_mut(order_id)
+            .ok_or_else(|| format!("Unknown mock order: {order_id}"))?;
+        order_status.open = false;
+        state.cancelled_orders.push(order_id.clone());
+        Ok(())
+    }
+
+    async fn order_status(
+        &self,
+        _pair: &str,
+        order_id: &OrderId,
+    ) -> Result<OrderStatus, Box<dyn std::error::Error>> {
+        self.state()
+            .orders
+            .get(order_id)
+            .map(|(_, order_status)| order_status.clone())
+            .ok_or_else(|| format!("Unknown mock order: {order_id}").into())
+    }
+
+    async fn open_orders(
+        &self,
+        pair: &str,
+    ) -> Result<HashMap<OrderId, OrderStatus>, Box<dyn std::error::Error>> {
+        Ok(self
+            .state()
+            .orders
+            .iter()
+            .filter(|(_, (order_pair, order_status))| order_pair == pair && order_status.open)
+            .map(|(order_id, (_, order_status))| (order_id.clone(), order_status.clone()))
+            .collect())
+    }
+
+    async fn order_updates(
+        &self,
+    ) -> Result<Option<UnboundedReceiver<OrderId>>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    async fn get_lending_info(
+        &self,
+        _coin: &str,
+    ) -> Result<Option<LendingInfo>, Box<dyn std::error::Error>> {
+        Err("Lending not supported by the mock exchange".into())
+    }
+
+    async fn get_lending_history(
+        &self,
+        _lending_history: LendingHistory,
+    ) -> Result<HashMap<String, f64>, Box<dyn std::error::Error>> {
+        Err("Lending not supported by the mock exchange".into())
+    }
+
+    async fn submit_lending_offer(
+        &self,
+        _coin: &str,
+        _size: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Err("Lending not supported by the mock exchange".into())
+    }
+
+    async fn margin_account(&self) -> Result<MarginAccount, Box<dyn std::error::Error>> {
+        Ok(MarginAccount::default())
+    }
+
+    async fn dust_balances(&self) -> Result<Vec<DustBalance>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    async fn convert_dust(
+        &self,
+        _coins: &[String],
+    ) -> Result<Vec<DustConversion>, Box<dyn std::error::Error>> {
+        Err("Dust conversion not supported by the mock exchange".into())
+    }
+
+    fn preferred_solusd_pair(&self) -> &'static str {
+        "SOLUSD"
+    }
+}
+
+// Scripted responses of an `RpcClient`. The mock sender answers each kind of request with the
+// same response regardless of its parameters, and with a generic success for unscripted requests
+#[derive(Default)]
+pub struct MockRpc {
+    mocks: Mocks,
+}
+
+impl MockRpc {
+    pub fn response(mut self, request: RpcRequest, response: Value) -> Self {
+        self.mocks.insert(request, response);
+        self
+    }
+
+    pub fn slot(self, slot: u64) -> Self {
+        self.response(RpcRequest::GetSlot, json!(slot))
+    }
+
+    pub fn epoch(self, epoch: u64, slot: u64) -> Self {
+        self.response(
+            RpcRequest::GetEpochInfo,
+            json!({
+                "epoch": epoch,
+                "slotIndex": 0,
+                "slotsInEpoch": 432_000,
+                "absoluteSlot": slot,
+                "blockHeight": slot,
+                "transactionCount": null,
+            }),
+        )
+    }
+
+    pub fn balance(self, lamports: u64) -> Self {
+        self.response(
+            RpcRequest::GetBalance,
+            json!({ "context": { "slot": 1 }, "value": lamports }),
+        )
+    }
+
+    pub fn block_height(self, block_height: u64) -> Self {
+        self.response(RpcRequest::GetBlockHeight, json!(block_height))
+    }
+
+    // Confirms, or with `err` fails, every signature queried
+    pub fn signature_status(self, slot: u64, err: Option<Value>) -> Self {
+        self.response(
+            RpcRequest::GetSignatureStatuses,
+            json!({
+                "context": { "slot": slot },
+                "value": [{
+                    "slot": slot,
+                    "confirmations": null,
+                    "err": err,
+                    "status": match err {
+                        Some(ref err) => json!({ "Err": err }),
+                        None => json!({ "Ok": null }),
+                    },
+                    "confirmationStatus": "finalized",
+                }],
+            }),
+        )
+    }
+
+    pub fn build(self) -> RpcClient {
+        RpcClient::new_mock_with_mocks("succeeds".into(), self.mocks)
+    }
+}
+
+#[derive(Debug)]
+pub struct LocalLedgerState {
+    pub accounts: HashMap<Pubkey, Account>,
+    pub token_balances: HashMap<Pubkey, UiTokenAmount>,
+    pub stake_activations: HashMap<Pubkey, RpcStakeActivation>,
+    pub inflation_rewards: HashMap<(Pubkey, Epoch), RpcInflationReward>,
+    pub epoch_info: EpochInfo,
+    pub block_time: UnixTimestamp,
+    pub blockhash: Hash,
+    pub lamports_per_signature: u64,
+    pub prioritization_fees: Vec<RpcPrioritizationFee>,
+    pub signature_statuses: HashMap<Signature, TransactionStatus>,
+    pub sent_transactions: Vec<VersionedTransaction>,
+}
+
+impl Default for LocalLedgerState {
+    fn default() -> Self {
+        Self {
+            accounts: HashMap::default(),
+            token_balances: HashMap::default(),
+            stake_activations: HashMap::default(),
+            inflation_rewards: HashMap::default(),
+            epoch_info: EpochInfo {
+                epoch: 0,
+                slot_index: 0,
+                slots_in_epoch: 432_000,
+                absolute_slot: 0,
+                block_height: 0,
+                transaction_count: None,
+            },
+            block_time: 0,
+            blockhash: Hash::default(),
+            lamports_per_signature: 5_000,
+            prioritization_fees: vec![],
+            signature_statuses: HashMap::default(),
+            sent_transactions: vec![],
+        }
+    }
+}
+
+impl LocalLedgerState {
+    pub fn set_balance(&mut self, address: Pubkey, lamports: u64) {
+        self.accounts.entry(address).or_default().lamports = lamports;
+    }
+
+    pub fn set_token_balance(&mut self, token_account: Pubkey, amount: u64, decimals: u8) {
+        self.token_balances
+            .insert(token_account, token_amount_to_ui_amount(amount, decimals));
+    }
+
+    // Moves to the first slot of `epoch`
+    pub fn advance_to_epoch(&mut self, epoch: Epoch) {
+        let slots = (epoch - self.epoch_info.epoch) * self.epoch_info.slots_in_epoch
+            - self.epoch_info.slot_index;
+        self.epoch_info.epoch = epoch;
+        self.epoch_info.slot_index = 0;
+        self.epoch_info.absolute_slot += slots;
+        self.epoch_info.block_height += slots;
+    }
+
+    fn context(&self) -> RpcResponseContext {
+        RpcResponseContext {
+            slot: self.epoch_info.absolute_slot,
+            api_version: None,
+        }
+    }
+}
+
+fn local_ledger_error(message: String) -> ClientError {
+    ClientErrorKind::Custom(message).into()
+}
+
+// A `ChainClient` backed by in-memory accounts instead of a cluster. Sent transactions are
+// recorded, not executed, and are confirmed successfully at the current slot
+#[derive(Default)]
+pub struct LocalLedger {
+    state: Mutex<LocalLedgerState>,
+}
+
+impl LocalLedger {
+    pub fn new(state: LocalLedgerState) -> Self {
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    // The ledger state, to inspect sent transactions or to advance the scenario
+    pub fn state(&self) -> MutexGuard<'_, LocalLedgerState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl ChainClient for LocalLedger {
+    fn commitment(&self) -> CommitmentConfig {
+        CommitmentConfig::confirmed()
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        self.state()
+            .accounts
+            .get(pubkey)
+            .cloned()
+            .ok_or_else(|| local_ledger_error(format!("AccountNotFound: pubkey={pubkey}")))
+    }
+
+    fn get_account_with_commitment(
+        &self,
+        pubkey: &Pubkey,
+        _commitment_config: CommitmentConfig,
+    ) -> RpcResult<Option<Account>> {
+        let state = self.state();
+        Ok(Response {
+            context: state.context(),
+            value: state.accounts.get(pubkey).cloned(),
+        })
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> ClientResult<Vec<Option<Account>>> {
+        let state = self.state();
+        Ok(pubkeys
+            .iter()
+            .map(|pubkey| state.accounts.get(pubkey).cloned())
+            .collect())
+    }
+
+    fn get_token_account_balance(&self, pubkey: &Pubkey) -> ClientResult<UiTokenAmount> {
+        self.state()
+            .token_balances
+            .get(pubkey)
+            .cloned()
+            .ok_or_else(|| local_ledger_error(format!("Invalid token account: {pubkey}")))
+    }
+
+    fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> ClientResult<Vec<(Pubkey, Account)>> {
+        let filters = config.filters.unwrap_or_default();
+        Ok(self
+            .state()
+            .accounts
+            .iter()
+            .filter(|(_, account)| {
+                let account_shared_data = AccountSharedData::from((*account).clone());
+                account.owner == *program_id
+                    && filters
+                        .iter()
+                        .all(|filter| filter.allows(&account_shared_data))
+            })
+            .map(|(address, account)| (*address, account.clone()))
+            .collect())
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64> {
+        Ok(Rent::default().minimum_balance(data_len))
+    }
+
+    fn get_stake_activation(
+        &self,
+        stake_account: Pubkey,
+        _epoch: Option<Epoch>,
+    ) -> ClientResult<RpcStakeActivation> {
+        self.state()
+            .stake_activations
+            .get(&stake_account)
+            .cloned()
+            .ok_or_else(|| local_ledger_error(format!("Invalid stake account: {stake_account}")))
+    }
+
+    fn get_epoch_info(&self) -> ClientResult<EpochInfo> {
+        Ok(self.state().epoch_info.clone())
+    }
+
+    // Rewards of the previous epoch unless `epoch` is given, like the RPC method
+    fn get_inflation_reward(
+        &self,
+        addresses: &[Pubkey],
+        epoch: Option<Epoch>,
+    ) -> ClientResult<Vec<Option<RpcInflationReward>>> {
+        let state = self.state();
+        let epoch = epoch.unwrap_or_else(|| state.epoch_info.epoch.saturating_sub(1));
+        Ok(addresses
+            .iter()
+            .map(|address| state.inflation_rewards.get(&(*address, epoch)).cloned())
+            .collect())
+    }
+
+    fn get_block_time(&self, _slot: Slot) -> ClientResult<UnixTimestamp> {
+        Ok(self.state().block_time)
+    }
+
+    fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        Ok(self.state().blockhash)
+    }
+
+    fn get_fee_for_message(&self, message: &VersionedMessage) -> ClientResult<u64> {
+        Ok(message.header().num_required_signatures as u64 * self.state().lamports_per_signature)
+    }
+
+    fn get_recent_prioritization_fees(
+        &self,
+        _addresses: &[Pubkey],
+    ) -> ClientResult<Vec<RpcPrioritizationFee>> {
+        Ok(self.state().prioritization_fees.clone())
+    }
+
+    fn get_signature_statuses_with_history(
+        &self,
+        signatures: &[Signature],
+    ) -> RpcResult<Vec<Option<TransactionStatus>>> {
+        let state = self.state();
+        Ok(Response {
+            context: state.context(),
+            value: signatures
+                .iter()
+                .map(|signature| state.signature_statuses.get(signature).cloned())
+                .collect(),
+        })
+    }
+
+    fn get_transaction(
+        &self,
+        signature: &Signature,
+        _encoding: UiTransactionEncoding,
+    ) -> ClientResult<EncodedConfirmedTransactionWithStatusMeta> {
+        Err(local_ledger_error(format!(
+            "Transaction details are not available in the local ledger: {signature}"
+        )))
+    }
+
+    fn send_transaction(&self, transaction: &VersionedTransaction) -> ClientResult<Signature> {
+        let mut state = self.state();
+        let signature = transaction.signatures[0];
+        let slot = state.epoch_info.absolute_slot;
+        state.signature_statuses.insert(
+            signature,
+            TransactionStatus {
+                slot,
+                confirmations: None,
+                status: Ok(()),
+                err: None,
+                confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+            },
+        );
+        state.sent_transactions.push(transaction.clone());
+        Ok(signature)
+    }
+}
+
+// Exchange states of common sync scenarios
+pub mod fixtures {
+    use super::*;
+
+    // An exchange holding no funds with a SOL deposit address and a SOL/USD market
+    pub fn sol_exchange(deposit_address: Pubkey, price: f64) -> MockExchangeState {
+        let mut state = MockExchangeState::default();
+        state
+            .deposit_addresses
+            .insert(MaybeToken::SOL(), deposit_address);
+        state.set_bid_ask("SOLUSD", price, price);
+        state
+    }
+
+    // A SOL deposit of `amount` that the exchange has credited
+    pub fn credited_deposit(
+        deposit_address: Pubkey,
+        tx_id: &str,
+        amount: f64,
+        price: f64,
+    ) -> MockExchangeState {
+        let mut state = sol_exchange(deposit_address, price);
+        state.add_deposit(tx_id, MaybeToken::SOL(), amount);
+        state.balances.insert(
+            "SOL".into(),
+            ExchangeBalance {
+                available: amount,
+                total: amount,
+                earn: 0.,
+            },
+        );
+        state
+    }
+
+    // A SOL reward credited on `when`
+    pub fn credited_reward(
+        deposit_address: Pubkey,
+        id: &str,
+        amount: f64,
+        when: NaiveDate,
+        price: f64,
+    ) -> MockExchangeState {
+        let mut state = sol_exchange(deposit_address, price);
+        state.rewards.push(RewardInfo {
+            id: id.into(),
+            token: MaybeToken::SOL(),
+            amount,
+            when,
+        });
+        state
+    }
+}
@@ -1,33 +1,25 @@
 use {
-    crate::coin_gecko,
+    crate::{
+        chain_client::ChainClient, coin_gecko, field_as_string, number_format::LocalizedFixedPlace,
+    },
     chrono::prelude::*,
     rust_decimal::prelude::*,
-    separator::FixedPlaceSeparatable,
-    serde::{Deserialize, Serialize},
-    solana_client::rpc_client::RpcClient,
+    serde::{de, Deserialize, Deserializer, Serialize},
     solana_sdk::{
+        instruction::Instruction,
         native_token::{lamports_to_sol, sol_to_lamports},
+        program_pack::Pack,
         pubkey,
         pubkey::Pubkey,
     },
-    std::str::FromStr,
-    strum::{EnumString, IntoStaticStr},
+    spl_token_2022::extension::{
+        transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType,
+        StateWithExtensions,
+    },
+    std::{collections::HashMap, str::FromStr, sync::OnceLock},
 };
 
-#[derive(
-    Debug,
-    PartialEq,
-    Eq,
-    Hash,
-    Copy,
-    Clone,
-    Serialize,
-    Deserialize,
-    EnumString,
-    IntoStaticStr,
-    PartialOrd,
-    Ord,
-)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize, PartialOrd, Ord)]
 #[allow(clippy::upper_case_acronyms)]
 #[allow(non_camel_case_types)]
 pub enum Token {
@@ -52,9 +44,150 @@ pub enum Token {
     PYTH,
     WEN,
     WIF,
+    PYUSD,
+    // Registered with `sys token add`, identified by its mint
+    Custom(
+        #[serde(
+            serialize_with = "field_as_string::serialize",
+            deserialize_with = "deserialize_custom_mint"
+        )]
+        Pubkey,
+    ),
+}
+
+const BUILTIN_TOKENS: [Token; 22] = [
+    Token::USDC,
+    Token::USDT,
+    Token::UXD,
+    Token::bSOL,
+    Token::hSOL,
+    Token::mSOL,
+    Token::stSOL,
+    Token::JitoSOL,
+    Token::tuSOL,
+    Token::tuUSDC,
+    Token::tumSOL,
+    Token::tustSOL,
+    Token::wSOL,
+    Token::JLP,
+    Token::JUP,
+    Token::JTO,
+    Token::BONK,
+    Token::KMNO,
+    Token::PYTH,
+    Token::WEN,
+    Token::WIF,
+    Token::PYUSD,
+];
+
+// A token definition persisted in the database by `sys token add`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CustomToken {
+    #[serde(with = "field_as_string")]
+    pub mint: Pubkey,
+    pub name: String,
+    pub coingecko_id: String,
+    pub decimals: u8,
+    #[serde(with = "field_as_string")]
+    pub program_id: Pubkey,
+}
+
+// The strings of a `CustomToken`, leaked to be `'static` like those of the built-in tokens
+struct CustomTokenInfo {
+    name: &'static str,
+    symbol: &'static str,
+    coingecko_id: &'static str,
+    decimals: u8,
+    program_id: Pubkey,
+}
+
+static CUSTOM_TOKENS: OnceLock<HashMap<Pubkey, CustomTokenInfo>> = OnceLock::new();
+
+// Called once at startup, before the command line is parsed, with the custom tokens in the
+// database
+pub fn configure_custom_tokens(custom_tokens: Vec<CustomToken>) {
+    let _ = CUSTOM_TOKENS.set(
+        custom_tokens
+            .into_iter()
+            .map(|custom_token| {
+                let symbol = format!("{}/", custom_token.name);
+                (
+                    custom_token.mint,
+                    CustomTokenInfo {
+                        name: Box::leak(custom_token.name.into_boxed_str()),
+                        symbol: Box::leak(symbol.into_boxed_str()),
+                        coingecko_id: Box::leak(custom_token.coingecko_id.into_boxed_str()),
+                        decimals: custom_token.decimals,
+                        program_id: custom_token.program_id,
+                    },
+                )
+            })
+            .collect(),
+    );
+}
+
+fn custom_tokens() -> &'static HashMap<Pubkey, CustomTokenInfo> {
+    CUSTOM_TOKENS.get_or_init(HashMap::default)
+}
+
+// Refuses the mint of a custom token that is not registered, such as one removed from the
+// database, so that a `Token::Custom` always has its name, decimals and program to look up
+fn deserialize_custom_mint<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    let mint: Pubkey = field_as_string::deserialize(deserializer)?;
+    if Token::custom_token_info(&mint).is_none() {
+        return Err(de::Error::custom(format!("Unknown custom token: {mint}")));
+    }
+    Ok(mint)
+}
+
+static DISPLAY_SYMBOLS: OnceLock<HashMap<MaybeToken, &'static str>> = OnceLock::new();
+
+// Called once at startup with the symbols configured in the database to write amounts of a token
+// with in place of its default symbol
+pub fn configure_display_symbols(display_symbols: HashMap<MaybeToken, String>) {
+    let _ = DISPLAY_SYMBOLS.set(
+        display_symbols
+            .into_iter()
+            .map(|(token, symbol)| (token, &*Box::leak(symbol.into_boxed_str())))
+            .collect(),
+    );
+}
+
+fn display_symbol(token: MaybeToken) -> Option<&'static str> {
+    DISPLAY_SYMBOLS.get()?.get(&token).copied()
+}
+
+impl FromStr for Token {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BUILTIN_TOKENS
+            .into_iter()
+            .find(|token| token.name() == s)
+            .or_else(|| {
+                custom_tokens()
+                    .iter()
+                    .find(|(_, info)| info.name == s)
+                    .map(|(mint, _)| Token::Custom(*mint))
+            })
+            .ok_or(strum::ParseError::VariantNotFound)
+    }
 }
 
 impl Token {
+    fn custom_token_info(mint: &Pubkey) -> Option<&'static CustomTokenInfo> {
+        custom_tokens().get(mint)
+    }
+
+    // Only registered custom tokens are parsed or deserialized, so their info is always found
+    fn registered_custom_token_info(mint: &Pubkey) -> &'static CustomTokenInfo {
+        Self::custom_token_info(mint).expect("registered custom token")
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Token::Custom(_))
+    }
+
     pub fn mint(&self) -> Pubkey {
         match self {
             Token::USDC => pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
@@ -78,14 +211,134 @@ impl Token {
             Token::PYTH => pubkey!("HZ1JovNiVvGrGNiiYvEozEVgZ58xaU3RKwX8eACQBCt3"),
             Token::WEN => pubkey!("WENWENvqqNya429ubCdR81ZmD69brwQaaBYY6p3LCpk"),
             Token::WIF => pubkey!("EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm"),
+            Token::PYUSD => pubkey!("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo"),
+            Token::Custom(mint) => *mint,
         }
     }
 
+    // The token program that owns the mint, SPL Token or Token-2022 (SPL Token Extensions)
+    pub fn program_id(&self) -> Pubkey {
+        match self {
+            Token::PYUSD => spl_token_2022::id(),
+            Token::Custom(mint) => Self::registered_custom_token_info(mint).program_id,
+            _ => spl_token::id(),
+        }
+    }
+
+    pub fn is_token_2022(&self) -> bool {
+        self.program_id() == spl_token_2022::id()
+    }
+
     pub fn ata(&self, wallet_address: &Pubkey) -> Pubkey {
-        spl_associated_token_account::get_associated_token_address(wallet_address, &self.mint())
+        spl_associated_token_account::get_associated_token_address_with_program_id(
+            wallet_address,
+            &self.mint(),
+            &self.program_id(),
+        )
+    }
+
+    pub fn create_ata_instruction(
+        &self,
+        funding_address: &Pubkey,
+        wallet_address: &Pubkey,
+    ) -> Instruction {
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            funding_address,
+            wallet_address,
+            &self.mint(),
+            &self.program_id(),
+        )
+    }
+
+    // The rent-exempt balance of a new associated token account. For Token-2022 mints the account
+    // also holds the extensions that the mint's extensions require
+    pub fn ata_rent(
+        &self,
+        rpc_client: &dyn ChainClient,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let account_len = if self.is_token_2022() {
+            let mint_account = rpc_client.get_account(&self.mint())?;
+            let mint =
+                StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)?;
+            let mut account_extensions =
+                ExtensionType::get_required_init_account_extensions(&mint.get_extension_types()?);
+            account_extensions.push(ExtensionType::ImmutableOwner);
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(
+                &account_extensions,
+            )?
+        } else {
+            spl_token::state::Account::LEN
+        };
+        Ok(rpc_client.get_minimum_balance_for_rent_exemption(account_len)?)
+    }
+
+    // The fee withheld from the recipient when transferring `amount` in the current epoch, for
+    // Token-2022 mints with the transfer fee extension
+    pub fn transfer_fee(
+        &self,
+        rpc_client: &dyn ChainClient,
+        amount: u64,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        if !self.is_token_2022() {
+            return Ok(0);
+        }
+
+        let mint_account = rpc_client.get_account(&self.mint())?;
+        let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)?;
+        let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() else {
+            return Ok(0);
+        };
+        let epoch = rpc_client.get_epoch_info()?.epoch;
+        transfer_fee_config
+            .calculate_epoch_fee(epoch, amount)
+            .ok_or_else(|| format!("Unable to calculate the {} transfer fee", self.name()).into())
+    }
+
+    // A `transfer_checked` instruction against the mint's token program. `fee` must match the
+    // mint's transfer fee, or the transfer fails
+    pub fn transfer_checked_instruction(
+        &self,
+        from_address: &Pubkey,
+        to_address: &Pubkey,
+        authority_address: &Pubkey,
+        amount: u64,
+        fee: u64,
+    ) -> Instruction {
+        if self.is_token_2022() {
+            spl_token_2022::extension::transfer_fee::instruction::transfer_checked_with_fee(
+                &self.program_id(),
+                &self.ata(from_address),
+                &self.mint(),
+                &self.ata(to_address),
+                authority_address,
+                &[],
+                amount,
+                self.decimals(),
+                fee,
+            )
+            .unwrap()
+        } else {
+            assert_eq!(fee, 0);
+            spl_token::instruction::transfer_checked(
+                &self.program_id(),
+                &self.ata(from_address),
+                &self.mint(),
+                &self.ata(to_address),
+                authority_address,
+                &[],
+                amount,
+                self.decimals(),
+            )
+            .unwrap()
+        }
     }
 
     pub fn symbol(&self) -> &'static str {
+        display_symbol(MaybeToken::from(*self)).unwrap_or_else(|| self.default_symbol())
+    }
+
+    // The symbol amounts of the token are written with unless configured otherwise
+    pub fn default_symbol(&self) -> &'static str {
         match self {
             Token::USDC => "($)",
             Token::USDT => "USDT$",
@@ -108,6 +361,8 @@ impl Token {
             Token::PYTH => "PYTH/",
             Token::WEN => "WEN/",
             Token::WIF => "WIF/",
+            Token::PYUSD => "PY$",
+            Token::Custom(mint) => Self::registered_custom_token_info(mint).symbol,
         }
     }
 
@@ -122,7 +377,8 @@ impl Token {
             | Token::JUP
             | Token::KMNO
             | Token::PYTH
-            | Token::WIF => 6,
+            | Token::WIF
+            | Token::PYUSD => 6,
             Token::stSOL
             | Token::tuSOL
             | Token::bSOL
@@ -133,6 +389,7 @@ impl Token {
             | Token::tustSOL
             | Token::JTO
             | Token::wSOL => 9,
+            Token::Custom(mint) => Self::registered_custom_token_info(mint).decimals,
         }
     }
 
@@ -144,8 +401,45 @@ impl Token {
         spl_token::ui_amount_to_amount(ui_amount, self.decimals())
     }
 
+    // Exact, unlike `ui_amount`, for arithmetic on USD values
+    pub fn ui_amount_decimal(&self, amount: u64) -> Decimal {
+        Decimal::from_i128_with_scale(amount.into(), self.decimals().into()).normalize()
+    }
+
     pub fn name(&self) -> &'static str {
-        self.into()
+        match self {
+            Token::USDC => "USDC",
+            Token::USDT => "USDT",
+            Token::UXD => "UXD",
+            Token::bSOL => "bSOL",
+            Token::hSOL => "hSOL",
+            Token::mSOL => "mSOL",
+            Token::stSOL => "stSOL",
+            Token::JitoSOL => "JitoSOL",
+            Token::tuSOL => "tuSOL",
+            Token::tuUSDC => "tuUSDC",
+            Token::tumSOL => "tumSOL",
+            Token::tustSOL => "tustSOL",
+            Token::wSOL => "wSOL",
+            Token::JLP => "JLP",
+            Token::JUP => "JUP",
+            Token::JTO => "JTO",
+            Token::BONK => "BONK",
+            Token::KMNO => "KMNO",
+            Token::PYTH => "PYTH",
+            Token::WEN => "WEN",
+            Token::WIF => "WIF",
+            Token::PYUSD => "PYUSD",
+            Token::Custom(mint) => Self::registered_custom_token_info(mint).name,
+        }
+    }
+
+    // The CoinGecko id of a custom token
+    pub fn coingecko_id(&self) -> Option<&'static str> {
+        match self {
+            Token::Custom(mint) => Self::custom_token_info(mint).map(|info| info.coingecko_id),
+            _ => None,
+        }
     }
 
     pub fn fiat_fungible(&self) -> bool {
@@ -178,7 +472,7 @@ impl Token {
 
     pub async fn get_current_liquidity_token_rate(
         &self,
-        _rpc_client: &RpcClient,
+        _rpc_client: &dyn ChainClient,
     ) -> Result<Decimal, Box<dyn std::error::Error>> {
         unreachable!()
         /*
@@ -204,7 +498,7 @@ impl Token {
 
     pub fn balance(
         &self,
-        rpc_client: &RpcClient,
+        rpc_client: &dyn ChainClient,
         address: &Pubkey,
     ) -> Result<u64, Box<dyn std::error::Error>> {
         Ok(u64::from_str(
@@ -225,7 +519,7 @@ impl Token {
     #[async_recursion::async_recursion(?Send)]
     pub async fn get_current_price(
         &self,
-        _rpc_client: &RpcClient,
+        _rpc_client: &dyn ChainClient,
     ) -> Result<Decimal, Box<dyn std::error::Error>> {
         if self.fiat_fungible() {
             return Ok(Decimal::from_f64(1.).unwrap());
@@ -247,7 +541,9 @@ impl Token {
             | Token::KMNO
             | Token::PYTH
             | Token::WEN
-            | Token::WIF => coin_gecko::get_current_price(&MaybeToken(Some(*self))).await,
+            | Token::WIF
+            | Token::PYUSD
+            | Token::Custom(_) => coin_gecko::get_current_price(&MaybeToken(Some(*self))).await,
             Token::tuUSDC | Token::tuSOL | Token::tumSOL | Token::tustSOL => {
                 Err("tulip support disabled".into())
                 //crate::tulip::get_current_price(rpc_client, self).await
@@ -257,14 +553,16 @@ impl Token {
 
     pub async fn get_historical_price(
         &self,
-        _rpc_client: &RpcClient,
+        _rpc_client: &dyn ChainClient,
         when: NaiveDate,
     ) -> Result<Decimal, Box<dyn std::error::Error>> {
         if self.fiat_fungible() {
             return Ok(Decimal::from_f64(1.).unwrap());
         }
         match self {
-            Token::USDC => coin_gecko::get_historical_price(when, &MaybeToken(Some(*self))).await,
+            Token::USDC | Token::PYUSD | Token::Custom(_) => {
+                coin_gecko::get_historical_price(when, &MaybeToken(Some(*self))).await
+            }
             unsupported_token => Err(format!(
                 "Historical price data is not available for {}",
                 unsupported_token.name()
@@ -281,7 +579,7 @@ impl Token {
         format!(
             "{}{}",
             self.symbol(),
-            ui_amount.separated_string_with_fixed_place(2)
+            ui_amount.localized_string_with_fixed_place(2)
         )
     }
 }
@@ -333,6 +631,13 @@ impl MaybeToken {
         }
     }
 
+    pub fn ui_amount_decimal(&self, amount: u64) -> Decimal {
+        match self.0 {
+            None => Decimal::from_i128_with_scale(amount.into(), 9).normalize(),
+            Some(token) => token.ui_amount_decimal(amount),
+        }
+    }
+
     pub fn mint(&self) -> Pubkey {
         match self.0 {
             None => spl_token::native_mint::id(),
@@ -348,9 +653,13 @@ impl MaybeToken {
     }
 
     pub fn symbol(&self) -> &'static str {
+        display_symbol(*self).unwrap_or_else(|| self.default_symbol())
+    }
+
+    pub fn default_symbol(&self) -> &'static str {
         match self.0 {
             None => "◎",
-            Some(token) => token.symbol(),
+            Some(token) => token.default_symbol(),
         }
     }
 
@@ -363,7 +672,7 @@ impl MaybeToken {
 
     pub async fn get_current_liquidity_token_rate(
         &self,
-        rpc_client: &RpcClient,
+        rpc_client: &dyn ChainClient,
     ) -> Result<Decimal, Box<dyn std::error::Error>> {
         match self.0 {
             None => Ok(Decimal::from_usize(1).unwrap()),
@@ -374,7 +683,7 @@ impl MaybeToken {
     pub fn name(&self) -> &'static str {
         match self.0 {
             None => "SOL",
-            Some(token) => token.into(),
+            Some(token) => token.name(),
         }
     }
 
@@ -387,7 +696,7 @@ impl MaybeToken {
 
     pub fn balance(
         &self,
-        rpc_client: &RpcClient,
+        rpc_client: &dyn ChainClient,
         address: &Pubkey,
     ) -> Result<u64, Box<dyn std::error::Error>> {
         match self.0 {
@@ -402,7 +711,7 @@ impl MaybeToken {
 
     pub async fn get_current_price(
         &self,
-        rpc_client: &RpcClient,
+        rpc_client: &dyn ChainClient,
     ) -> Result<Decimal, Box<dyn std::error::Error>> {
         match self.0 {
             None => coin_gecko::get_current_price(self).await,
@@ -412,7 +721,7 @@ impl MaybeToken {
 
     pub async fn get_historical_price(
         &self,
-        rpc_client: &RpcClient,
+        rpc_client: &dyn ChainClient,
         when: NaiveDate,
     ) -> Result<Decimal, Box<dyn std::error::Error>> {
         match self.0 {
@@ -429,7 +738,7 @@ impl MaybeToken {
         format!(
             "{}{}",
             self.symbol(),
-            ui_amount.separated_string_with_fixed_place(9)
+            ui_amount.localized_string_with_fixed_place(9)
         )
     }
 }
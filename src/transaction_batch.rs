@@ -0,0 +1,151 @@
+use {
+    solana_sdk::{
+        compute_budget::ComputeBudgetInstruction, instruction::Instruction, message::Message,
+        packet::PACKET_DATA_SIZE, pubkey::Pubkey, signature::Signature, transaction::Transaction,
+    },
+    std::mem,
+};
+
+// Maximum compute units a single transaction may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+#[derive(Debug, Clone)]
+pub struct TransactionBatch<T> {
+    pub instructions: Vec<Instruction>,
+    pub compute_units: u32,
+    pub operations: Vec<T>, // the operations packed into this batch, in the order they were added
+}
+
+impl<T> Default for TransactionBatch<T> {
+    fn default() -> Self {
+        Self {
+            instructions: vec![],
+            compute_units: 0,
+            operations: vec![],
+        }
+    }
+}
+
+// Packs independent operations, each a list of instructions that must execute atomically, into as
+// few transactions as possible while staying within the transaction size and compute unit limits.
+// Each operation carries a `T` describing it, so the caller can record what a batch did once it
+// is sent.
+//
+// Room is reserved for the ComputeBudget instructions that `apply_priority_fee()` appends to each
+// batch
+pub struct TransactionBatcher<T> {
+    payer: Pubkey,
+    max_compute_units: u32,
+    reserved_instructions: Vec<Instruction>,
+    reserved_compute_units: u32,
+    current: TransactionBatch<T>,
+    batches: Vec<TransactionBatch<T>>,
+}
+
+impl<T> TransactionBatcher<T> {
+    pub fn new(payer: Pubkey) -> Self {
+        Self::new_with_max_compute_units(payer, MAX_COMPUTE_UNIT_LIMIT)
+    }
+
+    pub fn new_with_max_compute_units(payer: Pubkey, max_compute_units: u32) -> Self {
+        assert!(max_compute_units <= MAX_COMPUTE_UNIT_LIMIT);
+        Self {
+            payer,
+            max_compute_units,
+            reserved_instructions: vec![],
+            reserved_compute_units: 0,
+            current: TransactionBatch::default(),
+            batches: vec![],
+        }
+    }
+
+    // Reserves room in every batch for `instructions`, and the `compute_units` they use, that the
+    // caller adds to each batch before it is sent, such as the instruction advancing a durable
+    // nonce
+    pub fn reserve(
+        &mut self,
+        instructions: impl IntoIterator<Item = Instruction>,
+        compute_units: u32,
+    ) {
+        assert!(self.is_empty(), "reserve before adding operations");
+        self.reserved_instructions.extend(instructions);
+        self.reserved_compute_units += compute_units;
+    }
+
+    fn fits(&self, instructions: &[Instruction], compute_units: u32) -> bool {
+        if compute_units + self.reserved_compute_units > self.max_compute_units {
+            return false;
+        }
+
+        let mut instructions = self
+            .reserved_instructions
+            .iter()
+            .chain(instructions)
+            .cloned()
+            .collect::<Vec<_>>();
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(
+            compute_units + self.reserved_compute_units,
+        ));
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(u64::MAX));
+
+        let message = Message::new(&instructions, Some(&self.payer));
+        let transaction = Transaction {
+            signatures: vec![Signature::default(); message.header.num_required_signatures as usize],
+            message,
+        };
+        bincode::serialized_size(&transaction)
+            .map(|size| size as usize <= PACKET_DATA_SIZE)
+            .unwrap_or(false)
+    }
+
+    // Adds an operation to the current batch, starting a new batch if it does not fit
+    pub fn add(
+        &mut self,
+        operation: T,
+        instructions: Vec<Instruction>,
+        compute_units: u32,
+    ) -> Result<(), String> {
+        if !self.fits(&instructions, compute_units) {
+            return Err(format!(
+                "Operation with {} instructions does not fit in a single transaction",
+                instructions.len()
+            ));
+        }
+
+        let candidate_instructions = self
+            .current
+            .instructions
+            .iter()
+            .chain(instructions.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        let candidate_compute_units = self.current.compute_units + compute_units;
+
+        if self.fits(&candidate_instructions, candidate_compute_units) {
+            self.current.instructions = candidate_instructions;
+            self.current.compute_units = candidate_compute_units;
+            self.current.operations.push(operation);
+        } else {
+            self.batches.push(mem::replace(
+                &mut self.current,
+                TransactionBatch {
+                    instructions,
+                    compute_units,
+                    operations: vec![operation],
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty() && self.current.instructions.is_empty()
+    }
+
+    pub fn into_batches(mut self) -> Vec<TransactionBatch<T>> {
+        if !self.current.instructions.is_empty() {
+            self.batches.push(self.current);
+        }
+        self.batches
+    }
+}
@@ -0,0 +1,174 @@
+use {
+    crate::db::Db,
+    solana_sdk::{
+        instruction::CompiledInstruction, message::VersionedMessage, native_token::Sol,
+        program_pack::Pack, pubkey::Pubkey, stake, system_instruction::SystemInstruction,
+        system_program,
+    },
+    std::collections::{BTreeMap, HashMap},
+    sys::{chain_client::ChainClient, priority_fee::ComputeBudget},
+};
+
+struct CreatedAccount {
+    address: Pubkey,
+    kind: &'static str,
+    payer: Pubkey,
+    rent_lamports: u64,
+}
+
+fn describe_payer(db: &Db, address: &Pubkey) -> String {
+    match db.get_account_tokens(*address).first() {
+        Some(tracked_account) => format!("{address} ({})", tracked_account.description),
+        None => format!("{address} (untracked)"),
+    }
+}
+
+fn created_accounts(
+    rpc_client: &dyn ChainClient,
+    fee_payer: Pubkey,
+    account_keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+) -> Result<Vec<CreatedAccount>, Box<dyn std::error::Error>> {
+    let account_at = |instruction: &CompiledInstruction, index: usize| {
+        instruction
+            .accounts
+            .get(index)
+            .and_then(|account_index| account_keys.get(*account_index as usize))
+            .copied()
+    };
+
+    // Accounts that are allocated in place (ie, stake splits) are funded by a prior transfer
+    let mut funders = HashMap::<Pubkey, Pubkey>::new();
+    let mut created_accounts = vec![];
+
+    for instruction in instructions {
+        let program_id = match account_keys.get(instruction.program_id_index as usize) {
+            Some(program_id) => *program_id,
+            None => continue,
+        };
+
+        if program_id == system_program::id() {
+            let (address, payer, space, owner) =
+                match bincode::deserialize::<SystemInstruction>(&instruction.data) {
+                    Ok(SystemInstruction::Transfer { .. }) => {
+                        if let (Some(from), Some(to)) =
+                            (account_at(instruction, 0), account_at(instruction, 1))
+                        {
+                            funders.insert(to, from);
+                        }
+                        continue;
+                    }
+                    Ok(SystemInstruction::CreateAccount { space, owner, .. })
+                    | Ok(SystemInstruction::CreateAccountWithSeed { space, owner, .. }) => (
+                        account_at(instruction, 1),
+                        account_at(instruction, 0),
+                        space,
+                        Some(owner),
+                    ),
+                    Ok(SystemInstruction::Allocate { space }) => {
+                        let address = account_at(instruction, 0);
+                        let payer = address.and_then(|address| funders.get(&address).copied());
+                        (address, payer, space, None)
+                    }
+                    Ok(SystemInstruction::AllocateWithSeed { space, owner, .. }) => {
+                        let address = account_at(instruction, 0);
+                        let payer = address.and_then(|address| funders.get(&address).copied());
+                        (address, payer, space, Some(owner))
+                    }
+                    _ => continue,
+                };
+
+            if let Some(address) = address {
+                let kind = if space as usize == stake::state::StakeStateV2::size_of()
+                    || owner == Some(stake::program::id())
+                {
+                    "stake account"
+                } else {
+                    "account"
+                };
+                created_accounts.push(CreatedAccount {
+                    address,
+                    kind,
+                    payer: payer.unwrap_or(fee_payer),
+                    rent_lamports: rpc_client
+                        .get_minimum_balance_for_rent_exemption(space as usize)?,
+                });
+            }
+        } else if program_id == spl_associated_token_account::id() {
+            // `Create` and `CreateIdempotent` are the only instructions that take a funding account
+            if !matches!(instruction.data.first(), None | Some(0) | Some(1)) {
+                continue;
+            }
+            if let (Some(payer), Some(address)) =
+                (account_at(instruction, 0), account_at(instruction, 1))
+            {
+                if rpc_client
+                    .get_account_with_commitment(&address, rpc_client.commitment())?
+                    .value
+                    .is_some()
+                {
+                    continue;
+                }
+                created_accounts.push(CreatedAccount {
+                    address,
+                    kind: "associated token account",
+                    payer,
+                    rent_lamports: rpc_client
+                        .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?,
+                });
+            }
+        }
+    }
+
+    Ok(created_accounts)
+}
+
+// Prints the fees and rent the transaction will cost, and which accounts will pay for them
+pub fn println_transaction_cost(
+    rpc_client: &dyn ChainClient,
+    db: &Db,
+    message: &VersionedMessage,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let account_keys = message.static_account_keys();
+    let fee_payer = *account_keys
+        .first()
+        .ok_or("Transaction does not have a fee payer")?;
+
+    let fee = rpc_client.get_fee_for_message(message)?;
+
+    let compute_budget =
+        ComputeBudget::from_compiled_instructions(account_keys, message.instructions());
+    let priority_fee = compute_budget.priority_fee_lamports().min(fee);
+    let base_fee = fee - priority_fee;
+
+    let created_accounts =
+        created_accounts(rpc_client, fee_payer, account_keys, message.instructions())?;
+
+    let mut costs_by_payer = BTreeMap::<Pubkey, u64>::new();
+    *costs_by_payer.entry(fee_payer).or_default() += fee;
+
+    println!("Transaction cost:");
+    println!("  Fee payer:    {}", describe_payer(db, &fee_payer));
+    println!("  Base fee:     {}", Sol(base_fee));
+    println!("  Priority fee: {}", Sol(priority_fee));
+    for created_account in &created_accounts {
+        println!(
+            "  Rent:         {} for {} {}, paid by {}",
+            Sol(created_account.rent_lamports),
+            created_account.kind,
+            created_account.address,
+            describe_payer(db, &created_account.payer),
+        );
+        *costs_by_payer.entry(created_account.payer).or_default() += created_account.rent_lamports;
+    }
+    if costs_by_payer.len() > 1 || !created_accounts.is_empty() {
+        for (payer, lamports) in costs_by_payer {
+            println!(
+                "  Total:        {} from {}",
+                Sol(lamports),
+                describe_payer(db, &payer)
+            );
+        }
+    }
+    Ok(())
+}